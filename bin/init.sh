@@ -5,13 +5,27 @@ shift
 completers_args_=("$@")
 
 function completers_complete_ {
+    # Aliases and functions exist only in the shell, so their
+    # definitions are passed to completers in a file.
+    local definitions="${TMPDIR:-/tmp}/completers-definitions-$$.txt"
+    local result="${TMPDIR:-/tmp}/completers-result-$$.txt"
+    { alias -p; declare -F; } > "$definitions"
+    COMPLETERS_SHELL_DEFINITIONS="$definitions" \
     "${completers_dir_}/../target/$completers_target_/completers" \
 			    --point="${READLINE_POINT}" \
+			    --shell=bash \
+			    --print0 \
 			    "${READLINE_LINE}" \
-			    "${completers_args_[@]}" 2> /tmp/completers-result.txt
-    read point line <<< "$(cat /tmp/completers-result.txt)"
-    READLINE_LINE=$line
-    READLINE_POINT=$point
+			    "${completers_args_[@]}" > "$result"
+    # The line is printed when a completion is chosen (0) and when the
+    # choice is cancelled (1), but not after an error (2).
+    if [ $? -le 1 ]; then
+        local point line
+        { IFS= read -r -d '' point; IFS= read -r -d '' line; } < "$result"
+        READLINE_LINE=$line
+        READLINE_POINT=$point
+    fi
+    rm -f "$definitions" "$result"
 }
 
 bind -x '"`":"completers_complete_"'
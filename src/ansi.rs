@@ -0,0 +1,202 @@
+//! Module with the ANSI escape sequences used for styling and
+//! positioning output in the terminal.
+//!
+//! The sequences are written directly rather than through a terminal
+//! library, so that completers and the chooser do not depend on any
+//! particular terminal backend. Terminals on Windows understand them
+//! once virtual terminal processing is enabled, which the crossterm
+//! backend does.
+
+use std::fmt;
+
+use serde::Deserialize;
+use serde::Deserializer;
+
+pub const FG_RED: &str = "\x1b[38;5;1m";
+pub const FG_GREEN: &str = "\x1b[38;5;2m";
+pub const FG_YELLOW: &str = "\x1b[38;5;3m";
+pub const FG_BLUE: &str = "\x1b[38;5;4m";
+pub const FG_CYAN: &str = "\x1b[38;5;6m";
+pub const FG_LIGHT_BLACK: &str = "\x1b[38;5;8m";
+pub const FG_RESET: &str = "\x1b[39m";
+
+pub const BOLD: &str = "\x1b[1m";
+pub const NORMAL_INTENSITY: &str = "\x1b[22m";
+pub const UNDERLINE: &str = "\x1b[4m";
+pub const NO_UNDERLINE: &str = "\x1b[24m";
+pub const INVERT: &str = "\x1b[7m";
+pub const RESET: &str = "\x1b[m";
+
+/// Names of the 16 basic colors of the terminal, in the order of their
+/// numbers.
+const BASIC_COLOR_NAMES: &[&str] = &[
+    "black",
+    "red",
+    "green",
+    "yellow",
+    "blue",
+    "magenta",
+    "cyan",
+    "white",
+    "bright-black",
+    "bright-red",
+    "bright-green",
+    "bright-yellow",
+    "bright-blue",
+    "bright-magenta",
+    "bright-cyan",
+    "bright-white",
+];
+
+/// A color of text or of its background.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Color {
+    /// The default color of the terminal.
+    Default,
+
+    /// One of the 16 basic colors, which the terminal's palette
+    /// defines.
+    Basic(u8),
+
+    /// One of the 256 colors of the extended palette.
+    Indexed(u8),
+
+    /// A 24-bit color.
+    Rgb(u8, u8, u8),
+}
+
+impl Color {
+    /// Parses a color: `default`, the name of a basic color, e.g.
+    /// `bright-red`, the number of an indexed color, or `#rrggbb`.
+    pub fn parse(value: &str) -> Result<Color, String> {
+        if value == "default" {
+            return Result::Ok(Color::Default);
+        }
+        if let Some(n) = BASIC_COLOR_NAMES.iter().position(|n| *n == value) {
+            return Result::Ok(Color::Basic(n as u8));
+        }
+        if let Result::Ok(n) = value.parse::<u8>() {
+            return Result::Ok(Color::Indexed(n));
+        }
+        let hex = value
+            .strip_prefix('#')
+            .filter(|h| h.len() == 6 && h.chars().all(|c| c.is_ascii_hexdigit()));
+        match hex {
+            Some(h) => {
+                let component = |i: usize| u8::from_str_radix(&h[i..i + 2], 16).unwrap();
+                Result::Ok(Color::Rgb(component(0), component(2), component(4)))
+            }
+            None => Result::Err(format!(
+                "invalid color '{}', expected a color name, a number or '#rrggbb'",
+                value
+            )),
+        }
+    }
+
+    /// Returns the sequence setting the color of text.
+    pub fn fg(self) -> String {
+        match self {
+            Color::Default => FG_RESET.to_owned(),
+            Color::Basic(n) if n < 8 => format!("\x1b[{}m", 30 + n),
+            Color::Basic(n) => format!("\x1b[{}m", 90 + n - 8),
+            Color::Indexed(n) => format!("\x1b[38;5;{}m", n),
+            Color::Rgb(r, g, b) => format!("\x1b[38;2;{};{};{}m", r, g, b),
+        }
+    }
+
+    /// Returns the sequence setting the color of the background.
+    pub fn bg(self) -> String {
+        match self {
+            Color::Default => "\x1b[49m".to_owned(),
+            Color::Basic(n) if n < 8 => format!("\x1b[{}m", 40 + n),
+            Color::Basic(n) => format!("\x1b[{}m", 100 + n - 8),
+            Color::Indexed(n) => format!("\x1b[48;5;{}m", n),
+            Color::Rgb(r, g, b) => format!("\x1b[48;2;{};{};{}m", r, g, b),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Color, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Value {
+            Number(u8),
+            Name(String),
+        }
+        match Value::deserialize(deserializer)? {
+            Value::Number(n) => Result::Ok(Color::Indexed(n)),
+            Value::Name(name) => Color::parse(&name).map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[test]
+fn test_colors() {
+    assert_eq!(Color::parse("default"), Result::Ok(Color::Default));
+    assert_eq!(Color::parse("blue"), Result::Ok(Color::Basic(4)));
+    assert_eq!(Color::parse("bright-black"), Result::Ok(Color::Basic(8)));
+    assert_eq!(Color::parse("236"), Result::Ok(Color::Indexed(236)));
+    assert_eq!(
+        Color::parse("#268bD2"),
+        Result::Ok(Color::Rgb(38, 139, 210))
+    );
+    assert!(Color::parse("#268bd").is_err());
+    assert!(Color::parse("purple").is_err());
+
+    assert_eq!(Color::Default.fg(), FG_RESET);
+    assert_eq!(Color::Basic(1).fg(), "\x1b[31m");
+    assert_eq!(Color::Basic(9).bg(), "\x1b[101m");
+    assert_eq!(Color::Indexed(236).bg(), "\x1b[48;5;236m");
+    assert_eq!(Color::Rgb(1, 2, 3).fg(), "\x1b[38;2;1;2;3m");
+}
+
+/// Clears the line with the cursor.
+pub const CLEAR_LINE: &str = "\x1b[2K";
+
+/// Moves the cursor to the given column and row, counted from 1.
+pub struct Goto(pub u16, pub u16);
+
+impl fmt::Display for Goto {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\x1b[{};{}H", self.1, self.0)
+    }
+}
+
+/// Moves the cursor up by the given number of rows.
+pub struct Up(pub u16);
+
+impl fmt::Display for Up {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\x1b[{}A", self.0)
+    }
+}
+
+/// Moves the cursor down by the given number of rows.
+pub struct Down(pub u16);
+
+impl fmt::Display for Down {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\x1b[{}B", self.0)
+    }
+}
+
+/// Moves the cursor left by the given number of columns.
+pub struct Left(pub u16);
+
+impl fmt::Display for Left {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\x1b[{}D", self.0)
+    }
+}
+
+#[test]
+fn test_cursor_sequences() {
+    assert_eq!(Goto(3, 7).to_string(), "\x1b[7;3H");
+    assert_eq!(Up(2).to_string(), "\x1b[2A");
+    assert_eq!(Down(1).to_string(), "\x1b[1B");
+    assert_eq!(Left(100).to_string(), "\x1b[100D");
+}
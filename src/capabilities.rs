@@ -0,0 +1,312 @@
+//! Describes the completers available to integrations, e.g. editor
+//! plugins deciding which tabs to expect, as a JSON document printed by
+//! `completers --capabilities`.
+//!
+//! The built-in completers are described by a table rather than by the
+//! completers themselves, as most of them can only be created for a
+//! command line, and the rules activating them are those of
+//! `get_completers`, which the table must be kept in sync with.
+
+use crate::config;
+
+/// The description of a built-in completer.
+struct Capability {
+    /// The names of the tabs of the completer.
+    names: &'static [&'static str],
+    description: &'static str,
+
+    /// When the completer is offered.
+    activation: &'static str,
+
+    /// The section of the configuration file configuring the completer.
+    config_key: Option<&'static str>,
+}
+
+const BUILT_IN: &[Capability] = &[
+    Capability {
+        names: &["fs", "dir"],
+        description: "Files and directories, searched recursively",
+        activation: "always",
+        config_key: Some("fs"),
+    },
+    Capability {
+        names: &["br", "co"],
+        description: "Git branches, tags and commits",
+        activation: "always",
+        config_key: None,
+    },
+    Capability {
+        names: &["wt"],
+        description: "Git worktrees",
+        activation: "command: git worktree",
+        config_key: None,
+    },
+    Capability {
+        names: &["sub"],
+        description: "Git submodules",
+        activation: "command: git submodule",
+        config_key: None,
+    },
+    Capability {
+        names: &["br", "rev"],
+        description: "Mercurial branches, bookmarks and revisions",
+        activation: "in a Mercurial repository",
+        config_key: None,
+    },
+    Capability {
+        names: &["br", "rev"],
+        description: "Subversion branches, tags and revisions",
+        activation: "in a Subversion working copy",
+        config_key: None,
+    },
+    Capability {
+        names: &["clip"],
+        description: "The clipboard and its history",
+        activation: "when enabled",
+        config_key: Some("clipboard"),
+    },
+    Capability {
+        names: &["recent"],
+        description: "Recently visited directories",
+        activation: "configured commands",
+        config_key: Some("recent_dirs"),
+    },
+    Capability {
+        names: &["editor"],
+        description: "Files recently opened in editors",
+        activation: "configured commands",
+        config_key: Some("recent_files"),
+    },
+    Capability {
+        names: &["img", "tag"],
+        description: "Container images and their tags",
+        activation: "configured commands",
+        config_key: Some("containers"),
+    },
+    Capability {
+        names: &["npm"],
+        description: "Scripts of package.json",
+        activation: "configured commands",
+        config_key: Some("npm"),
+    },
+    Capability {
+        names: &["man", "sec"],
+        description: "Manual pages and their sections",
+        activation: "configured commands",
+        config_key: Some("man"),
+    },
+    Capability {
+        names: &["pkg"],
+        description: "Packages of the system package manager",
+        activation: "configured commands",
+        config_key: Some("packages"),
+    },
+    Capability {
+        names: &["nix"],
+        description: "Attributes of Nix packages and flakes",
+        activation: "configured commands",
+        config_key: Some("nix"),
+    },
+    Capability {
+        names: &["brew"],
+        description: "Homebrew formulae and casks",
+        activation: "configured commands",
+        config_key: Some("homebrew"),
+    },
+    Capability {
+        names: &["bash"],
+        description: "Words given by the compspecs of bash-completion",
+        activation: "when enabled, for commands",
+        config_key: Some("bash_completion"),
+    },
+    Capability {
+        names: &["opt"],
+        description: "Options parsed from the help or manual page of the command",
+        activation: "when enabled, for queries starting with '-'",
+        config_key: Some("options"),
+    },
+    Capability {
+        names: &["alias"],
+        description: "Aliases and functions of the shell",
+        activation: "in place of a command name",
+        config_key: None,
+    },
+    Capability {
+        names: &["date"],
+        description: "Dates and times",
+        activation: "after configured options, or queries describing a moment",
+        config_key: Some("datetime"),
+    },
+    Capability {
+        names: &["calc"],
+        description: "The value of an arithmetic expression",
+        activation: "queries which are expressions",
+        config_key: None,
+    },
+    Capability {
+        names: &["tz"],
+        description: "Time zones",
+        activation: "values of TZ",
+        config_key: None,
+    },
+    Capability {
+        names: &["locale"],
+        description: "Locales",
+        activation: "values of LANG and LC_* variables",
+        config_key: None,
+    },
+    Capability {
+        names: &["app"],
+        description: "Desktop applications",
+        activation: "configured commands",
+        config_key: Some("desktop"),
+    },
+    Capability {
+        names: &["pass"],
+        description: "Entries of the password store",
+        activation: "configured commands",
+        config_key: Some("pass"),
+    },
+    Capability {
+        names: &["tf", "ws"],
+        description: "Terraform resources and workspaces",
+        activation: "state and workspace commands of configured programs",
+        config_key: Some("terraform"),
+    },
+    Capability {
+        names: &["db"],
+        description: "Databases, schemas and tables of configured connections",
+        activation: "configured commands",
+        config_key: Some("database"),
+    },
+    Capability {
+        names: &["profile", "region", "s3", "ec2"],
+        description: "AWS profiles, regions, buckets and instances",
+        activation: "aws commands and AWS_* variables",
+        config_key: Some("aws"),
+    },
+    Capability {
+        names: &["sig"],
+        description: "Signals",
+        activation: "kill and trap commands",
+        config_key: None,
+    },
+    Capability {
+        names: &["pr", "issue", "#"],
+        description: "Pull requests and issues of the forge",
+        activation: "configured commands, or queries starting with '#'",
+        config_key: Some("forge"),
+    },
+    Capability {
+        names: &["cargo"],
+        description: "Binaries, examples, tests, benches and features of the Cargo workspace",
+        activation: "cargo commands and options",
+        config_key: None,
+    },
+    Capability {
+        names: &["journal"],
+        description: "Units and boots of the systemd journal",
+        activation: "journalctl options",
+        config_key: None,
+    },
+    Capability {
+        names: &["dev"],
+        description: "Printers and USB devices",
+        activation: "options of printing and USB commands",
+        config_key: None,
+    },
+    Capability {
+        names: &["net", "bt"],
+        description: "Wireless networks and Bluetooth devices",
+        activation: "nmcli and bluetoothctl commands",
+        config_key: None,
+    },
+    Capability {
+        names: &["test"],
+        description: "Names of the tests of the project",
+        activation: "test runners",
+        config_key: Some("tests"),
+    },
+    Capability {
+        names: &["gradle", "maven", "just"],
+        description: "Tasks of the task runner of the project",
+        activation: "task runners",
+        config_key: Some("tasks"),
+    },
+    Capability {
+        names: &["history"],
+        description: "The history of the shell",
+        activation: "when the whole line is replaced",
+        config_key: None,
+    },
+];
+
+/// Returns the JSON document describing the version of the application
+/// and the completers it offers with the given configuration.
+pub fn document(config: &config::Config) -> serde_json::Value {
+    let mut completers: Vec<serde_json::Value> = config
+        .completers
+        .iter()
+        .map(|c| {
+            let activation = if c.commands.is_empty() {
+                "always".to_owned()
+            } else {
+                format!("commands: {}", c.commands.join(", "))
+            };
+            serde_json::json!({
+                "names": [c.name],
+                "description": c.command,
+                "activation": activation,
+                "config_key": "completers",
+                "user_defined": true,
+            })
+        })
+        .collect();
+    completers.extend(BUILT_IN.iter().map(|c| {
+        serde_json::json!({
+            "names": c.names,
+            "description": c.description,
+            "activation": c.activation,
+            "config_key": c.config_key,
+            "user_defined": false,
+        })
+    }));
+    serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "completers": completers,
+    })
+}
+
+#[test]
+fn test_document() {
+    let config = config::Config::parse(
+        "[[completers]]
+name = \"hosts\"
+command = \"cat /etc/hosts\"
+commands = [\"ssh\"]
+",
+    )
+    .unwrap();
+    let document = document(&config);
+    assert_eq!(document["version"], env!("CARGO_PKG_VERSION"));
+    let completers = document["completers"].as_array().unwrap();
+    assert_eq!(completers.len(), BUILT_IN.len() + 1);
+    assert_eq!(
+        completers[0],
+        serde_json::json!({
+            "names": ["hosts"],
+            "description": "cat /etc/hosts",
+            "activation": "commands: ssh",
+            "config_key": "completers",
+            "user_defined": true,
+        })
+    );
+    assert_eq!(completers[1]["names"], serde_json::json!(["fs", "dir"]));
+    assert_eq!(completers[1]["config_key"], "fs");
+    let options = completers
+        .iter()
+        .find(|c| c["names"] == serde_json::json!(["opt"]))
+        .unwrap();
+    assert_eq!(options["config_key"], "options");
+    assert_eq!(options["user_defined"], false);
+}
@@ -0,0 +1,449 @@
+//! Defines a completer for the values of the options of the AWS command
+//! line interface: profiles, regions and, when enabled, S3 buckets and
+//! EC2 instances.
+//!
+//! Profiles are read from the configuration and credentials files of
+//! the AWS CLI. Buckets and instances are listed with `aws` itself,
+//! which queries the network, so they are listed only if enabled in the
+//! configuration, in a background thread, and cached for a while.
+
+use std::any;
+use std::env;
+use std::fs;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// The regions of the standard partition, with their names.
+const REGIONS: &[(&str, &str)] = &[
+    ("us-east-1", "US East (N. Virginia)"),
+    ("us-east-2", "US East (Ohio)"),
+    ("us-west-1", "US West (N. California)"),
+    ("us-west-2", "US West (Oregon)"),
+    ("af-south-1", "Africa (Cape Town)"),
+    ("ap-east-1", "Asia Pacific (Hong Kong)"),
+    ("ap-south-1", "Asia Pacific (Mumbai)"),
+    ("ap-south-2", "Asia Pacific (Hyderabad)"),
+    ("ap-southeast-1", "Asia Pacific (Singapore)"),
+    ("ap-southeast-2", "Asia Pacific (Sydney)"),
+    ("ap-southeast-3", "Asia Pacific (Jakarta)"),
+    ("ap-southeast-4", "Asia Pacific (Melbourne)"),
+    ("ap-northeast-1", "Asia Pacific (Tokyo)"),
+    ("ap-northeast-2", "Asia Pacific (Seoul)"),
+    ("ap-northeast-3", "Asia Pacific (Osaka)"),
+    ("ca-central-1", "Canada (Central)"),
+    ("ca-west-1", "Canada West (Calgary)"),
+    ("eu-central-1", "Europe (Frankfurt)"),
+    ("eu-central-2", "Europe (Zurich)"),
+    ("eu-west-1", "Europe (Ireland)"),
+    ("eu-west-2", "Europe (London)"),
+    ("eu-west-3", "Europe (Paris)"),
+    ("eu-south-1", "Europe (Milan)"),
+    ("eu-south-2", "Europe (Spain)"),
+    ("eu-north-1", "Europe (Stockholm)"),
+    ("il-central-1", "Israel (Tel Aviv)"),
+    ("me-south-1", "Middle East (Bahrain)"),
+    ("me-central-1", "Middle East (UAE)"),
+    ("sa-east-1", "South America (São Paulo)"),
+];
+
+/// The kind of values completed on an `aws` command line.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueKind {
+    Profile,
+    Region,
+
+    /// The names of S3 buckets, as `s3://` URLs for the `aws s3`
+    /// commands, which take them.
+    Bucket {
+        url: bool,
+    },
+
+    /// The IDs of EC2 instances.
+    Instance,
+}
+
+impl ValueKind {
+    /// Indicates if the values are listed by querying AWS.
+    pub fn is_resource(self) -> bool {
+        matches!(self, ValueKind::Bucket { .. } | ValueKind::Instance)
+    }
+}
+
+/// Returns the kind of values completed for the query after the given
+/// words of an `aws` command line, if any.
+pub fn value_kind(command_words: &[&str], query: &str) -> Option<ValueKind> {
+    if command_words.first() != Some(&"aws") {
+        return None;
+    }
+    match *command_words.last()? {
+        "--profile" => Some(ValueKind::Profile),
+        "--region" => Some(ValueKind::Region),
+        "--bucket" => Some(ValueKind::Bucket { url: false }),
+        "--instance-id" | "--instance-ids" => Some(ValueKind::Instance),
+        _ if command_words.get(1) == Some(&"s3") && "s3://".starts_with(query)
+            || query.starts_with("s3://") =>
+        {
+            Some(ValueKind::Bucket { url: true })
+        }
+        _ => None,
+    }
+}
+
+/// Returns the kind of values of an environment variable of the AWS
+/// CLI, if they are completed.
+pub fn variable_kind(variable: &str) -> Option<ValueKind> {
+    match variable {
+        "AWS_PROFILE" | "AWS_DEFAULT_PROFILE" => Some(ValueKind::Profile),
+        "AWS_REGION" | "AWS_DEFAULT_REGION" => Some(ValueKind::Region),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_value_kind() {
+    assert_eq!(
+        value_kind(&["aws", "s3", "ls", "--profile"], ""),
+        Some(ValueKind::Profile)
+    );
+    assert_eq!(
+        value_kind(&["aws", "ec2", "start-instances", "--instance-ids"], "i-"),
+        Some(ValueKind::Instance)
+    );
+    assert_eq!(
+        value_kind(&["aws", "s3", "cp", "file.txt"], "s3"),
+        Some(ValueKind::Bucket { url: true })
+    );
+    assert_eq!(value_kind(&["aws", "s3", "cp"], "file.txt"), None);
+    assert_eq!(
+        value_kind(&["aws", "s3api", "get-object", "--bucket"], ""),
+        Some(ValueKind::Bucket { url: false })
+    );
+    assert_eq!(value_kind(&["terraform", "--region"], ""), None);
+    assert_eq!(variable_kind("AWS_REGION"), Some(ValueKind::Region));
+    assert_eq!(variable_kind("AWS_SECRET_ACCESS_KEY"), None);
+}
+
+/// Returns the path of a file of the AWS CLI, which is either in the
+/// given variable or `~/.aws/<name>`.
+fn aws_file_path(variable: &str, name: &str) -> Option<path::PathBuf> {
+    env::var_os(variable)
+        .map(path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".aws").join(name)))
+}
+
+/// Parses the profiles of the configuration file of the AWS CLI, whose
+/// sections are `[default]` and `[profile <name>]`, or of the
+/// credentials file, whose sections are `[<name>]`. Returns the names
+/// of the profiles with their regions, if set.
+fn parse_profiles(contents: &str, is_credentials: bool) -> Vec<(String, Option<String>)> {
+    let mut profiles: Vec<(String, Option<String>)> = vec![];
+    let mut in_profile = false;
+    for line in contents.lines().map(str::trim) {
+        if let Some(section) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            let section = section.trim();
+            let name = if is_credentials || section == "default" {
+                Some(section)
+            } else {
+                section.strip_prefix("profile ").map(str::trim)
+            };
+            in_profile = name.is_some();
+            if let Some(name) = name {
+                profiles.push((name.to_owned(), None));
+            }
+        } else if let Some((key, value)) = line.split_once('=') {
+            if in_profile && key.trim() == "region" {
+                if let Some(profile) = profiles.last_mut() {
+                    profile.1 = Some(value.trim().to_owned());
+                }
+            }
+        }
+    }
+    profiles
+}
+
+#[test]
+fn test_parse_profiles() {
+    let config = "[default]\n\
+                  region = eu-west-1\n\
+                  \n\
+                  [profile prod]\n\
+                  sso_session = corp\n\
+                  region=us-east-1\n\
+                  [sso-session corp]\n\
+                  sso_region = us-east-2\n\
+                  [profile dev]\n";
+    assert_eq!(
+        parse_profiles(config, false),
+        vec![
+            ("default".to_owned(), Some("eu-west-1".to_owned())),
+            ("prod".to_owned(), Some("us-east-1".to_owned())),
+            ("dev".to_owned(), None),
+        ]
+    );
+    let credentials = "[default]\naws_access_key_id = AKIA\n[ci]\n";
+    assert_eq!(
+        parse_profiles(credentials, true),
+        vec![("default".to_owned(), None), ("ci".to_owned(), None)]
+    );
+}
+
+/// Returns the profiles of the configuration and credentials files,
+/// without duplicates.
+fn read_profiles() -> Vec<(String, Option<String>)> {
+    let read = |variable: &str, name: &str| {
+        aws_file_path(variable, name).and_then(|p| fs::read_to_string(p).ok())
+    };
+    let mut profiles = read("AWS_CONFIG_FILE", "config")
+        .map(|c| parse_profiles(&c, false))
+        .unwrap_or_default();
+    let credentials = read("AWS_SHARED_CREDENTIALS_FILE", "credentials")
+        .map(|c| parse_profiles(&c, true))
+        .unwrap_or_default();
+    for (name, region) in credentials {
+        if !profiles.iter().any(|(n, _)| *n == name) {
+            profiles.push((name, region));
+        }
+    }
+    profiles
+}
+
+/// Parses the output of `aws ec2 describe-instances` with the query of
+/// `list_command`: lines of the ID, name and state of an instance.
+fn parse_instances(output: &str) -> Vec<AwsCompletion> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let id = fields.first().filter(|i| !i.is_empty())?;
+            let description: Vec<&str> = fields[1..]
+                .iter()
+                .filter(|f| !f.is_empty() && **f != "None")
+                .cloned()
+                .collect();
+            Some(AwsCompletion {
+                value: (*id).to_owned(),
+                description: Some(description.join(", ")).filter(|d| !d.is_empty()),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_instances() {
+    let output = "i-0abc\tweb\trunning\ni-0def\tNone\tstopped\n";
+    assert_eq!(
+        parse_instances(output),
+        vec![
+            AwsCompletion {
+                value: "i-0abc".to_owned(),
+                description: Some("web, running".to_owned()),
+            },
+            AwsCompletion {
+                value: "i-0def".to_owned(),
+                description: Some("stopped".to_owned()),
+            },
+        ]
+    );
+}
+
+#[derive(Debug, PartialEq)]
+struct AwsCompletion {
+    value: String,
+    description: Option<String>,
+}
+
+impl core::Completion for AwsCompletion {
+    fn result_string(&self) -> String {
+        self.value.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the value of an option on a command line, e.g. of
+/// `--profile`, so that resources are listed for the same account.
+fn option_value<'a>(command_words: &[&'a str], option: &str) -> Option<&'a str> {
+    let index = command_words.iter().position(|w| *w == option)?;
+    command_words.get(index + 1).cloned()
+}
+
+/// Returns the `aws` command listing resources of the given kind, for
+/// the profile and region of the command line.
+fn list_command(kind: ValueKind, profile: Option<&str>, region: Option<&str>) -> Command {
+    let mut command = Command::new("aws");
+    if let ValueKind::Instance = kind {
+        command.args([
+            "ec2",
+            "describe-instances",
+            "--query",
+            "Reservations[].Instances[].[InstanceId, \
+             Tags[?Key=='Name'] | [0].Value, State.Name]",
+        ]);
+    } else {
+        command.args(["s3api", "list-buckets", "--query", "Buckets[].Name"]);
+    }
+    command.args(["--output", "text"]);
+    if let Some(p) = profile {
+        command.args(["--profile", p]);
+    }
+    if let Some(r) = region {
+        command.args(["--region", r]);
+    }
+    command
+}
+
+/// Lists the resources of a kind with `aws`, or gets them from the
+/// cache, returning the completions or the error.
+fn list_resources(
+    kind: ValueKind,
+    mut command: Command,
+    use_cache: bool,
+    config: &config::AwsConfig,
+) -> Result<Vec<AwsCompletion>, String> {
+    let output = subprocess::run_expiring(
+        &mut command,
+        config.timeout(),
+        "aws",
+        time::Duration::from_secs(config.cache_secs),
+        use_cache,
+    )?;
+    Result::Ok(match kind {
+        ValueKind::Bucket { url } => output
+            .split_whitespace()
+            .map(|name| AwsCompletion {
+                value: if url {
+                    format!("s3://{}/", name)
+                } else {
+                    name.to_owned()
+                },
+                description: None,
+            })
+            .collect(),
+        _ => parse_instances(&output),
+    })
+}
+
+/// A completer for the values of an option of the AWS CLI.
+pub struct AwsCompleter {
+    kind: ValueKind,
+    profile: Option<String>,
+    region: Option<String>,
+    config: config::AwsConfig,
+
+    /// Lists the resources in the background.
+    listing: subprocess::Background<Result<Vec<AwsCompletion>, String>>,
+    errors: Vec<String>,
+}
+
+impl AwsCompleter {
+    /// Creates a completer of the given kind of values, for a command
+    /// line made of `command_words`.
+    pub fn new(kind: ValueKind, command_words: &[&str], config: config::AwsConfig) -> Self {
+        let mut completer = AwsCompleter {
+            kind,
+            profile: option_value(command_words, "--profile").map(str::to_owned),
+            region: option_value(command_words, "--region").map(str::to_owned),
+            config,
+            listing: subprocess::Background::idle(),
+            errors: vec![],
+        };
+        completer.start(true);
+        completer
+    }
+
+    fn start(&mut self, use_cache: bool) {
+        if !self.kind.is_resource() {
+            return;
+        }
+        let kind = self.kind;
+        let command = list_command(kind, self.profile.as_deref(), self.region.as_deref());
+        let config = self.config.clone();
+        self.listing =
+            subprocess::Background::run(move || list_resources(kind, command, use_cache, &config));
+    }
+}
+
+impl core::Completer for AwsCompleter {
+    fn name(&self) -> String {
+        match self.kind {
+            ValueKind::Profile => "profile",
+            ValueKind::Region => "region",
+            ValueKind::Bucket { .. } => "s3",
+            ValueKind::Instance => "ec2",
+        }
+        .to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        if self.kind.is_resource() {
+            self.profile.clone()
+        } else {
+            None
+        }
+    }
+
+    /// Resources are completed only if enabled, as listing them queries
+    /// AWS, with the credentials of the user.
+    fn is_applicable(&self) -> bool {
+        !self.kind.is_resource() || self.config.resources
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let completions = match self.kind {
+            ValueKind::Profile => read_profiles()
+                .into_iter()
+                .map(|(value, region)| AwsCompletion {
+                    value,
+                    description: region,
+                })
+                .collect(),
+            ValueKind::Region => REGIONS
+                .iter()
+                .map(|(value, name)| AwsCompletion {
+                    value: (*value).to_owned(),
+                    description: Some((*name).to_owned()),
+                })
+                .collect(),
+            ValueKind::Bucket { .. } | ValueKind::Instance => {
+                let failed = || Result::Err("listing AWS resources failed".to_owned());
+                let result = match self.listing.poll(failed) {
+                    Some(result) => result,
+                    None => return vec![],
+                };
+                result.unwrap_or_else(|e| {
+                    self.errors.push(e);
+                    vec![]
+                })
+            }
+        };
+        completions
+            .into_iter()
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    /// Lists resources again, bypassing the cache.
+    fn refresh(&mut self) -> bool {
+        self.start(false);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
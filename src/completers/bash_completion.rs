@@ -0,0 +1,224 @@
+//! Defines a completer bridging the programmable completion of bash,
+//! which gives completions of the options and arguments of the many
+//! commands which bash-completion and packages define compspecs for.
+//!
+//! A bash subprocess sources bash-completion, loads the compspec of the
+//! command and calls its function, or `compgen` with its options, with
+//! the `COMP_*` variables set as bash sets them for the command line,
+//! and prints the words in `COMPREPLY`. The word being completed is
+//! only the leading dashes of the query, so that the compspec lists
+//! all the options, or all the arguments, and the chooser matches them
+//! fuzzily.
+
+use std::any;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// The script run by bash, with the path of bash-completion, the index
+/// of the completed word and the words of the command line as its
+/// arguments.
+const BRIDGE_SCRIPT: &str = r#"
+. "$1" >/dev/null 2>&1 || exit 1
+COMP_CWORD=$2
+shift 2
+COMP_WORDS=("$@")
+COMP_LINE="$*"
+COMP_POINT=${#COMP_LINE}
+COMP_TYPE=9
+COMP_KEY=9
+cmd=${COMP_WORDS[0]}
+cur=${COMP_WORDS[COMP_CWORD]}
+prev=${COMP_WORDS[COMP_CWORD-1]}
+_comp_load -- "$cmd" >/dev/null 2>&1 ||
+    __load_completion "$cmd" >/dev/null 2>&1 ||
+    _completion_loader "$cmd" >/dev/null 2>&1
+spec=$(complete -p -- "$cmd" 2>/dev/null) || exit 0
+COMPREPLY=()
+if [[ $spec =~ " -F "([^ ]+) ]]; then
+    "${BASH_REMATCH[1]}" "$cmd" "$cur" "$prev" >/dev/null 2>&1
+else
+    opts=${spec#complete }
+    eval "COMPREPLY=(\$(compgen ${opts% *} -- \"\$cur\" 2>/dev/null))"
+fi
+printf '%s\n' "${COMPREPLY[@]}"
+"#;
+
+/// Returns the word completed for the given query: its leading dashes.
+fn completed_word(query: &str) -> &str {
+    &query[..query.len() - query.trim_start_matches('-').len()]
+}
+
+#[test]
+fn test_completed_word() {
+    assert_eq!(completed_word("--col"), "--");
+    assert_eq!(completed_word("-"), "-");
+    assert_eq!(completed_word("origin"), "");
+    assert_eq!(completed_word("a-b"), "");
+}
+
+#[derive(Debug, PartialEq)]
+struct BashCompletion {
+    word: String,
+}
+
+impl core::Completion for BashCompletion {
+    fn result_string(&self) -> String {
+        self.word.clone()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the words printed by the bridge script, one per line, without
+/// duplicates. Compspecs add a space to words which end an argument,
+/// which the shell integration adds anyway.
+fn parse_words(output: &str) -> Vec<BashCompletion> {
+    let mut words: Vec<BashCompletion> = vec![];
+    for word in output.lines().map(str::trim_end).filter(|w| !w.is_empty()) {
+        if !words.iter().any(|w| w.word == word) {
+            words.push(BashCompletion {
+                word: word.to_owned(),
+            });
+        }
+    }
+    words
+}
+
+#[test]
+fn test_parse_words() {
+    assert_eq!(
+        parse_words("--color \n--all\n\n--color\n"),
+        vec![
+            BashCompletion {
+                word: "--color".to_owned(),
+            },
+            BashCompletion {
+                word: "--all".to_owned(),
+            },
+        ]
+    );
+}
+
+/// A completer of the words which the compspec of a command gives.
+pub struct BashCompletionCompleter {
+    /// The words of the command line preceding the query, and the word
+    /// completed in place of the query.
+    words: Vec<String>,
+    config: config::BashCompletionConfig,
+
+    /// The time after which bash is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl BashCompletionCompleter {
+    pub fn new(
+        command_words: &[&str],
+        query: &str,
+        config: config::BashCompletionConfig,
+        timeout: time::Duration,
+    ) -> Self {
+        BashCompletionCompleter {
+            words: command_words
+                .iter()
+                .map(|w| w.to_string())
+                .chain(Some(completed_word(query).to_owned()))
+                .collect(),
+            config,
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for BashCompletionCompleter {
+    fn name(&self) -> String {
+        "bash".to_owned()
+    }
+
+    /// The completer applies to commands, if bash-completion is
+    /// installed.
+    fn is_applicable(&self) -> bool {
+        self.words.len() > 1 && path::Path::new(&self.config.script).is_file()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let output = subprocess::run(
+            Command::new("bash")
+                .args(["--norc", "--noprofile", "-c", BRIDGE_SCRIPT, "bash"])
+                .arg(&self.config.script)
+                .arg((self.words.len() - 1).to_string())
+                .args(&self.words),
+            self.timeout,
+        );
+        match output {
+            Result::Ok(o) => parse_words(&o)
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+#[test]
+fn test_bash_completion_completer() {
+    use crate::core::Completer;
+    use std::fs;
+
+    let dir = std::env::temp_dir().join(format!("completers-bash-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let script = dir.join("bash_completion");
+    fs::write(
+        &script,
+        "_fake() { COMPREPLY=(\"cur=$2\" \"prev=$3\" --all); }
+complete -F _fake fake
+complete -W 'one two' words
+",
+    )
+    .unwrap();
+    let config = config::BashCompletionConfig {
+        enabled: true,
+        script: script.to_string_lossy().into_owned(),
+    };
+    let timeout = time::Duration::from_secs(10);
+    let words = |command_words: &[&str], query: &str| {
+        let mut completer =
+            BashCompletionCompleter::new(command_words, query, config.clone(), timeout);
+        assert!(completer.is_applicable());
+        let words: Vec<String> = completer
+            .fetch_completions()
+            .iter()
+            .map(|c| c.result_string())
+            .collect();
+        assert_eq!(completer.take_errors(), Vec::<String>::new());
+        words
+    };
+    assert_eq!(
+        words(&["fake", "-v"], "--al"),
+        vec!["cur=--", "prev=-v", "--all"]
+    );
+    assert_eq!(words(&["words"], "tw"), vec!["one", "two"]);
+    assert_eq!(words(&["unknown"], ""), Vec::<String>::new());
+    fs::remove_dir_all(&dir).unwrap();
+
+    assert!(!BashCompletionCompleter::new(&["fake"], "", config, timeout).is_applicable());
+}
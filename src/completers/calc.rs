@@ -0,0 +1,225 @@
+//! Defines a completer evaluating an arithmetic expression in the query,
+//! e.g. `(45*3)+2`, whose completion is the value of the expression.
+//!
+//! Expressions consist of numbers, parentheses and the operators `+`,
+//! `-`, `*`, `/`, `%` and `^` (power), with the usual precedence. The
+//! completer is dynamic, so that the value follows the query as it is
+//! edited in the chooser.
+
+use std::any;
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::core;
+
+/// The characters which expressions consist of.
+pub const EXPRESSION_CHARS: &str = "0123456789.+-*/%^()";
+
+/// A recursive descent parser evaluating an expression.
+struct Parser<'a> {
+    chars: Peekable<Chars<'a>>,
+
+    /// Indicates if the expression has an operator with two operands,
+    /// rather than just a number.
+    has_binary_operator: bool,
+}
+
+impl<'a> Parser<'a> {
+    fn new(expression: &'a str) -> Self {
+        Parser {
+            chars: expression.chars().peekable(),
+            has_binary_operator: false,
+        }
+    }
+
+    /// Consumes the next character if it is one of `operators`.
+    fn operator(&mut self, operators: &str) -> Option<char> {
+        let c = self.chars.next_if(|c| operators.contains(*c))?;
+        self.has_binary_operator = true;
+        Some(c)
+    }
+
+    /// Parses a sum or difference of terms.
+    fn expression(&mut self) -> Option<f64> {
+        let mut value = self.term()?;
+        while let Some(op) = self.operator("+-") {
+            let rhs = self.term()?;
+            value = if op == '+' { value + rhs } else { value - rhs };
+        }
+        Some(value)
+    }
+
+    /// Parses a product, quotient or remainder of factors.
+    fn term(&mut self) -> Option<f64> {
+        let mut value = self.factor()?;
+        while let Some(op) = self.operator("*/%") {
+            let rhs = self.factor()?;
+            value = match op {
+                '*' => value * rhs,
+                _ if rhs == 0.0 => return None,
+                '/' => value / rhs,
+                _ => value % rhs,
+            };
+        }
+        Some(value)
+    }
+
+    /// Parses a power, which is right-associative: 2^3^2 is 2^9.
+    fn factor(&mut self) -> Option<f64> {
+        let base = self.unary()?;
+        if self.operator("^").is_some() {
+            Some(base.powf(self.factor()?))
+        } else {
+            Some(base)
+        }
+    }
+
+    /// Parses a number or a parenthesized expression, possibly negated.
+    fn unary(&mut self) -> Option<f64> {
+        match self.chars.peek()? {
+            '-' => {
+                self.chars.next();
+                Some(-self.unary()?)
+            }
+            '(' => {
+                self.chars.next();
+                let value = self.expression()?;
+                self.chars.next_if_eq(&')')?;
+                Some(value)
+            }
+            _ => {
+                let mut number = String::new();
+                while let Some(c) = self.chars.next_if(|c| c.is_ascii_digit() || *c == '.') {
+                    number.push(c);
+                }
+                number.parse().ok()
+            }
+        }
+    }
+}
+
+/// Evaluates an expression, returning None if it is not a valid one or
+/// is just a number, or its value is not finite.
+pub fn evaluate(expression: &str) -> Option<f64> {
+    let mut parser = Parser::new(expression);
+    let value = parser.expression()?;
+    if parser.chars.next().is_some() || !parser.has_binary_operator || !value.is_finite() {
+        return None;
+    }
+    Some(value)
+}
+
+#[test]
+fn test_evaluate() {
+    assert_eq!(evaluate("(45*3)+2"), Some(137.0));
+    assert_eq!(evaluate("1+2*3"), Some(7.0));
+    assert_eq!(evaluate("2^3^2"), Some(512.0));
+    assert_eq!(evaluate("-(1.5-4)/2"), Some(1.25));
+    assert_eq!(evaluate("17%5"), Some(2.0));
+    assert_eq!(evaluate("42"), None);
+    assert_eq!(evaluate("1/0"), None);
+    assert_eq!(evaluate("(1+2"), None);
+    assert_eq!(evaluate("1+"), None);
+    assert_eq!(evaluate("1..2+3"), None);
+}
+
+/// Formats a value, without a fractional part if it is an integer, and
+/// without the noise of floating point arithmetic otherwise.
+fn format_value(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        let formatted = format!("{:.10}", value);
+        formatted
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_owned()
+    }
+}
+
+#[test]
+fn test_format_value() {
+    assert_eq!(format_value(137.0), "137");
+    assert_eq!(format_value(-2.0), "-2");
+    assert_eq!(format_value(0.1 + 0.2), "0.3");
+    assert_eq!(format_value(1.0 / 3.0), "0.3333333333");
+}
+
+/// Indicates if the query is an expression to evaluate. Numbers joined
+/// only with dashes are not, as they are more likely dates or ranges,
+/// like `2024-01-05`.
+pub fn is_expression(query: &str) -> bool {
+    query.chars().any(|c| "+*/%^()".contains(c)) && evaluate(query).is_some()
+}
+
+#[test]
+fn test_is_expression() {
+    assert!(is_expression("(45*3)+2"));
+    assert!(is_expression("(2-1)"));
+    assert!(!is_expression("2024-01-05"));
+    assert!(!is_expression("foo*2"));
+}
+
+struct CalcCompletion {
+    expression: String,
+    value: String,
+}
+
+impl core::Completion for CalcCompletion {
+    fn result_string(&self) -> String {
+        self.value.clone()
+    }
+
+    fn display_string(&self) -> String {
+        format!("= {}", self.value)
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(self.expression.clone())
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A completer of the value of the expression in the query.
+pub struct CalcCompleter {
+    expression: String,
+}
+
+impl CalcCompleter {
+    pub fn new<E: Into<String>>(expression: E) -> Self {
+        Self {
+            expression: expression.into(),
+        }
+    }
+}
+
+impl core::Completer for CalcCompleter {
+    fn name(&self) -> String {
+        "calc".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        match evaluate(&self.expression) {
+            Some(value) => vec![Box::new(CalcCompletion {
+                expression: self.expression.clone(),
+                value: format_value(value),
+            })],
+            None => vec![],
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn is_dynamic(&self) -> bool {
+        true
+    }
+
+    fn requery(&mut self, query: &str) {
+        self.expression = query.to_owned();
+    }
+}
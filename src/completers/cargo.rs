@@ -0,0 +1,460 @@
+//! Defines completers for Cargo, the Rust package manager: names of
+//! targets, features and dependencies of the packages in the current
+//! workspace, read from `cargo metadata`, and versions of crates, read
+//! from the local copy of the crates.io index.
+
+use std::any;
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::completers::subprocess;
+use crate::core;
+
+/// The kinds of items completed by the Cargo completer.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ItemKind {
+    Bin,
+    Example,
+    Test,
+    Bench,
+    Feature,
+    Dependency,
+}
+
+impl ItemKind {
+    fn label(self) -> &'static str {
+        match self {
+            ItemKind::Bin => "bin",
+            ItemKind::Example => "example",
+            ItemKind::Test => "test",
+            ItemKind::Bench => "bench",
+            ItemKind::Feature => "feature",
+            ItemKind::Dependency => "dep",
+        }
+    }
+}
+
+/// Returns the kinds of items to complete on a command line with the
+/// given words preceding the query, e.g. binaries for `cargo run --bin`.
+///
+/// Returns no kinds for lines which are not Cargo commands, or where
+/// none of the items is expected.
+pub fn item_kinds(command_words: &[&str]) -> Vec<ItemKind> {
+    if command_words.first() != Some(&"cargo") {
+        return vec![];
+    }
+    match command_words.last() {
+        Some(&"--bin") => return vec![ItemKind::Bin],
+        Some(&"--example") => return vec![ItemKind::Example],
+        Some(&"--test") => return vec![ItemKind::Test],
+        Some(&"--bench") => return vec![ItemKind::Bench],
+        Some(&"--features") | Some(&"-F") => return vec![ItemKind::Feature],
+        _ => {}
+    }
+    match command_words.get(1) {
+        Some(&"run") => vec![ItemKind::Bin, ItemKind::Example],
+        Some(&"test") | Some(&"t") => vec![ItemKind::Test],
+        Some(&"bench") => vec![ItemKind::Bench],
+        Some(&"add") | Some(&"remove") | Some(&"rm") | Some(&"update") => {
+            vec![ItemKind::Dependency]
+        }
+        _ => vec![],
+    }
+}
+
+#[test]
+fn test_item_kinds() {
+    assert_eq!(item_kinds(&["cargo", "run", "--bin"]), vec![ItemKind::Bin]);
+    assert_eq!(
+        item_kinds(&["cargo", "run"]),
+        vec![ItemKind::Bin, ItemKind::Example]
+    );
+    assert_eq!(item_kinds(&["cargo", "test"]), vec![ItemKind::Test]);
+    assert_eq!(
+        item_kinds(&["cargo", "build", "-F"]),
+        vec![ItemKind::Feature]
+    );
+    assert_eq!(item_kinds(&["cargo", "add"]), vec![ItemKind::Dependency]);
+    assert_eq!(item_kinds(&["cargo", "build"]), vec![]);
+    assert_eq!(item_kinds(&["cargo"]), vec![]);
+    assert_eq!(item_kinds(&["make", "test"]), vec![]);
+}
+
+/// The parts of the output of `cargo metadata` which are read.
+#[derive(Deserialize)]
+struct Metadata {
+    packages: Vec<Package>,
+}
+
+#[derive(Deserialize)]
+struct Package {
+    targets: Vec<Target>,
+    #[serde(default)]
+    features: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    dependencies: Vec<Dependency>,
+}
+
+#[derive(Deserialize)]
+struct Target {
+    name: String,
+    kind: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct Dependency {
+    name: String,
+    req: String,
+}
+
+struct CargoCompletion {
+    kind: ItemKind,
+    name: String,
+
+    /// E.g. the version requirement of a dependency.
+    description: String,
+}
+
+impl core::Completion for CargoCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        if self.description.is_empty() {
+            Some(self.kind.label().to_owned())
+        } else {
+            Some(format!("{} {}", self.kind.label(), self.description))
+        }
+    }
+
+    fn search_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        match self.kind {
+            ItemKind::Dependency => core::CompletionKind::Package,
+            _ => core::CompletionKind::Other,
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the items of the given kinds from the output of
+/// `cargo metadata`. Items of all packages of a workspace are
+/// returned, without duplicates.
+fn parse_metadata(json: &str, kinds: &[ItemKind]) -> Result<Vec<CargoCompletion>, String> {
+    let metadata: Metadata = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut completions: Vec<CargoCompletion> = vec![];
+    let mut add = |kind: ItemKind, name: &str, description: String| {
+        let known = completions.iter().any(|c| c.kind == kind && c.name == name);
+        if kinds.contains(&kind) && !known {
+            completions.push(CargoCompletion {
+                kind,
+                name: name.to_owned(),
+                description,
+            });
+        }
+    };
+    for package in &metadata.packages {
+        for target in &package.targets {
+            for target_kind in &target.kind {
+                let kind = match target_kind.as_str() {
+                    "bin" => ItemKind::Bin,
+                    "example" => ItemKind::Example,
+                    "test" => ItemKind::Test,
+                    "bench" => ItemKind::Bench,
+                    _ => continue,
+                };
+                add(kind, &target.name, String::new());
+            }
+        }
+        for (feature, enabled) in &package.features {
+            add(ItemKind::Feature, feature, enabled.join(", "));
+        }
+        for dependency in &package.dependencies {
+            add(
+                ItemKind::Dependency,
+                &dependency.name,
+                dependency.req.clone(),
+            );
+        }
+    }
+    Result::Ok(completions)
+}
+
+#[test]
+fn test_parse_metadata() {
+    let json = r#"{"packages": [
+        {"name": "a", "targets": [
+            {"name": "a", "kind": ["lib"]},
+            {"name": "a-cli", "kind": ["bin"]},
+            {"name": "demo", "kind": ["example"]}
+         ],
+         "features": {"default": ["std"], "std": []},
+         "dependencies": [{"name": "serde", "req": "^1"}]},
+        {"name": "b", "targets": [{"name": "b", "kind": ["bin"]}],
+         "features": {"std": []},
+         "dependencies": [{"name": "serde", "req": "^1.0.100"}]}
+    ], "version": 1}"#;
+    let names = |kinds: &[ItemKind]| -> Vec<String> {
+        parse_metadata(json, kinds)
+            .unwrap()
+            .iter()
+            .map(|c| c.name.clone())
+            .collect()
+    };
+    assert_eq!(
+        names(&[ItemKind::Bin, ItemKind::Example]),
+        vec!["a-cli", "demo", "b"]
+    );
+    assert_eq!(names(&[ItemKind::Feature]), vec!["default", "std"]);
+    assert_eq!(names(&[ItemKind::Dependency]), vec!["serde"]);
+    assert!(parse_metadata("{}", &[ItemKind::Bin]).is_err());
+}
+
+/// A completer of targets, features and dependencies of the packages
+/// in the current workspace.
+pub struct CargoCompleter {
+    kinds: Vec<ItemKind>,
+
+    /// The time after which `cargo metadata` is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl CargoCompleter {
+    pub fn new(kinds: Vec<ItemKind>, timeout: time::Duration) -> Self {
+        CargoCompleter {
+            kinds,
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for CargoCompleter {
+    fn name(&self) -> String {
+        "cargo".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let result = subprocess::run(
+            Command::new("cargo").args([
+                "metadata",
+                "--no-deps",
+                "--offline",
+                "--format-version",
+                "1",
+            ]),
+            self.timeout,
+        );
+        let output = match result {
+            Result::Ok(o) => o,
+            Result::Err(e) => {
+                self.errors.push(e);
+                return vec![];
+            }
+        };
+        match parse_metadata(&output, &self.kinds) {
+            Result::Ok(completions) => completions
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                warn!("Failed to parse the output of cargo metadata: {}", e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let completion_any = completion.as_any();
+        let cargo_completion = completion_any.downcast_ref::<CargoCompletion>().unwrap();
+        if cargo_completion.kind == ItemKind::Dependency {
+            Some(Box::new(CrateVersionCompleter::new(
+                cargo_completion.name.as_str(),
+            )))
+        } else {
+            None
+        }
+    }
+}
+
+struct CrateVersionCompletion {
+    crate_name: String,
+    version: String,
+    yanked: bool,
+}
+
+impl core::Completion for CrateVersionCompletion {
+    fn result_string(&self) -> String {
+        format!("{}@{}", self.crate_name, self.version)
+    }
+
+    fn display_string(&self) -> String {
+        if self.yanked {
+            format!("{} (yanked)", self.version)
+        } else {
+            self.version.clone()
+        }
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        let style = if self.yanked {
+            core::Style::colored(core::SpanColor::Error)
+        } else {
+            core::Style::default()
+        };
+        vec![core::Span::styled(self.display_string(), style)]
+    }
+
+    fn search_string(&self) -> String {
+        self.version.clone()
+    }
+
+    fn is_hidden(&self) -> bool {
+        self.yanked
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Version
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the path of the file describing a crate within the index,
+/// e.g. `se/rd/serde`.
+fn index_file_path(crate_name: &str) -> path::PathBuf {
+    let name = crate_name.to_lowercase();
+    match name.len() {
+        1 => path::Path::new("1").join(&name),
+        2 => path::Path::new("2").join(&name),
+        3 => path::Path::new("3").join(&name[..1]).join(&name),
+        _ => path::Path::new(&name[..2]).join(&name[2..4]).join(&name),
+    }
+}
+
+#[test]
+fn test_index_file_path() {
+    assert_eq!(index_file_path("a"), path::Path::new("1/a"));
+    assert_eq!(index_file_path("cc"), path::Path::new("2/cc"));
+    assert_eq!(index_file_path("Log"), path::Path::new("3/l/log"));
+    assert_eq!(index_file_path("serde"), path::Path::new("se/rd/serde"));
+}
+
+#[derive(Deserialize)]
+struct IndexEntry {
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// Returns the versions of a crate, with the yanked flags, from a file
+/// of Cargo's index cache, from the oldest one.
+///
+/// The cache file consists of a binary header followed by pairs of
+/// zero-terminated version strings and JSON entries of the index;
+/// only the JSON entries are read.
+fn parse_index_cache(data: &[u8]) -> Vec<(String, bool)> {
+    data.split(|b| *b == 0)
+        .filter(|chunk| chunk.starts_with(b"{"))
+        .filter_map(|chunk| serde_json::from_slice::<IndexEntry>(chunk).ok())
+        .map(|e| (e.vers, e.yanked))
+        .collect()
+}
+
+#[test]
+fn test_parse_index_cache() {
+    let mut data = b"\x03\x02\x00\x00\x00etag\x00".to_vec();
+    data.extend_from_slice(b"1.0.0\x00{\"name\":\"x\",\"vers\":\"1.0.0\",\"deps\":[]}\x00");
+    data.extend_from_slice(b"1.0.1\x00{\"name\":\"x\",\"vers\":\"1.0.1\",\"yanked\":true}\x00");
+    assert_eq!(
+        parse_index_cache(&data),
+        vec![("1.0.0".to_owned(), false), ("1.0.1".to_owned(), true)]
+    );
+}
+
+fn cargo_home() -> Option<path::PathBuf> {
+    env::var_os("CARGO_HOME")
+        .map(path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".cargo")))
+}
+
+/// A completer of the versions of a crate known to the local copies of
+/// registry indices.
+struct CrateVersionCompleter {
+    crate_name: String,
+}
+
+impl CrateVersionCompleter {
+    fn new<N: Into<String>>(crate_name: N) -> CrateVersionCompleter {
+        CrateVersionCompleter {
+            crate_name: crate_name.into(),
+        }
+    }
+}
+
+impl core::Completer for CrateVersionCompleter {
+    fn name(&self) -> String {
+        "ver".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.crate_name.clone())
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let index_dirs = cargo_home().map(|h| fs::read_dir(h.join("registry").join("index")));
+        let index_dirs = match index_dirs {
+            Some(Result::Ok(d)) => d,
+            _ => return vec![],
+        };
+        let file_path = index_file_path(&self.crate_name);
+        let mut versions: Vec<(String, bool)> = vec![];
+        for index_dir in index_dirs.flatten() {
+            let cache_path = index_dir.path().join(".cache").join(&file_path);
+            if let Result::Ok(data) = fs::read(cache_path) {
+                for version in parse_index_cache(&data) {
+                    if !versions.contains(&version) {
+                        versions.push(version);
+                    }
+                }
+            }
+        }
+        // The newest versions first.
+        versions
+            .into_iter()
+            .rev()
+            .map(|(version, yanked)| {
+                Box::new(CrateVersionCompletion {
+                    crate_name: self.crate_name.clone(),
+                    version,
+                    yanked,
+                }) as core::CompletionBox
+            })
+            .collect()
+    }
+}
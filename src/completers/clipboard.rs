@@ -0,0 +1,163 @@
+//! Defines a completer for recently copied strings: the contents of the
+//! clipboard and the history of the clipboard.
+//!
+//! The clipboard is read with `wl-paste` on Wayland and `xclip` on X11.
+//! The history is a file which the daemon maintains when collecting the
+//! clipboard is enabled, by polling the clipboard and appending the
+//! strings it didn't see yet.
+
+use std::any;
+use std::env;
+use std::path;
+use std::process::Command;
+use std::thread;
+use std::time;
+
+use log::warn;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+use crate::history_file;
+
+/// Returns the path of the history of the clipboard.
+pub fn history_path() -> Option<path::PathBuf> {
+    history_file::path("clipboard_history")
+}
+
+/// Returns the command printing the contents of the clipboard, for the
+/// display server of the session.
+fn paste_command() -> Option<Command> {
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        let mut command = Command::new("wl-paste");
+        command.args(["--no-newline", "--type", "text"]);
+        Some(command)
+    } else if env::var_os("DISPLAY").is_some() {
+        let mut command = Command::new("xclip");
+        command.args(["-selection", "clipboard", "-out"]);
+        Some(command)
+    } else {
+        None
+    }
+}
+
+/// Returns the contents of the clipboard, or None if it is empty or
+/// can't be read.
+fn read_clipboard(timeout: time::Duration) -> Option<String> {
+    let contents = subprocess::run(&mut paste_command()?, timeout).ok()?;
+    if contents.trim().is_empty() {
+        None
+    } else {
+        Some(contents)
+    }
+}
+
+/// Collects the strings copied to the clipboard into the history file,
+/// polling the clipboard forever. Run by the daemon.
+pub fn collect(clipboard_config: config::ClipboardConfig, timeout: time::Duration) {
+    let history_path = match history_path() {
+        Some(p) => p,
+        None => return,
+    };
+    let mut last = None;
+    loop {
+        thread::sleep(clipboard_config.poll_interval());
+        let contents = match read_clipboard(timeout) {
+            Some(c) if c.len() <= clipboard_config.max_length => c,
+            _ => continue,
+        };
+        if last.as_ref() == Some(&contents) {
+            continue;
+        }
+        if let Result::Err(e) =
+            history_file::save(&history_path, &contents, clipboard_config.max_entries)
+        {
+            warn!("Failed to save the clipboard history: {}", e);
+        }
+        last = Some(contents);
+    }
+}
+
+struct ClipboardCompletion {
+    string: String,
+
+    /// Indicates if the string is the current contents of the
+    /// clipboard, rather than an older one.
+    current: bool,
+}
+
+impl core::Completion for ClipboardCompletion {
+    fn result_string(&self) -> String {
+        self.string.clone()
+    }
+
+    /// Shows line breaks and tabs as symbols, so that each string takes
+    /// a single line.
+    fn display_string(&self) -> String {
+        self.string
+            .replace('\n', "\u{23ce}")
+            .replace('\t', "\u{21e5}")
+    }
+
+    fn description(&self) -> Option<String> {
+        if self.current {
+            Some("clipboard".to_owned())
+        } else {
+            None
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A completer for the contents and the history of the clipboard, from
+/// the most recent string.
+pub struct ClipboardCompleter {
+    /// The time after which reading the clipboard is given up.
+    timeout: time::Duration,
+}
+
+impl ClipboardCompleter {
+    pub fn new(timeout: time::Duration) -> Self {
+        Self { timeout }
+    }
+}
+
+impl core::Completer for ClipboardCompleter {
+    fn name(&self) -> String {
+        "clip".to_owned()
+    }
+
+    fn is_applicable(&self) -> bool {
+        paste_command().is_some() || history_path().is_some_and(|p| p.is_file())
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let current = read_clipboard(self.timeout);
+        let history = history_path()
+            .map(|p| history_file::read(&p))
+            .unwrap_or_default();
+        let older: Vec<String> = history
+            .into_iter()
+            .filter(|s| Some(s) != current.as_ref())
+            .collect();
+        current
+            .into_iter()
+            .map(|string| ClipboardCompletion {
+                string,
+                current: true,
+            })
+            .chain(older.into_iter().map(|string| ClipboardCompletion {
+                string,
+                current: false,
+            }))
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+}
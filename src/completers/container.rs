@@ -0,0 +1,464 @@
+//! Defines completers for container image references.
+//!
+//! Images are taken from the local container engine (`docker images`)
+//! and from the registries listed in the configuration, which are
+//! queried through the registry HTTP API (`/v2/_catalog` and
+//! `/v2/<name>/tags/list`). Registry listings are paginated; pages are
+//! fetched in a background thread and passed to the UI as they arrive.
+//! The engine and `curl` are killed when they run longer than the
+//! timeout.
+
+use std::any;
+use std::process::Command;
+use std::sync::mpsc;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+struct ImageCompletion {
+    /// The registry the image comes from, or None for local images.
+    registry: Option<String>,
+    repository: String,
+    tag: Option<String>,
+}
+
+impl ImageCompletion {
+    fn reference(&self) -> String {
+        let mut reference = match self.registry {
+            Some(ref r) => format!("{}/{}", registry_host(r), self.repository),
+            None => self.repository.clone(),
+        };
+        if let Some(ref t) = self.tag {
+            reference.push(':');
+            reference.push_str(t);
+        }
+        reference
+    }
+}
+
+impl core::Completion for ImageCompletion {
+    fn result_string(&self) -> String {
+        self.reference()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        if self.tag.is_some() {
+            core::CompletionKind::Version
+        } else {
+            core::CompletionKind::Image
+        }
+    }
+
+    /// Local images without a tag are dangling, left over by builds.
+    fn severity(&self) -> Option<core::Severity> {
+        if self.registry.is_none() && self.tag.is_none() {
+            Some(core::Severity::Inactive)
+        } else {
+            None
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A source of image references, listed page by page.
+enum ImageSource {
+    /// Images known to the local container engine.
+    Local,
+
+    /// Repositories in the catalog of the given registry.
+    Catalog(String),
+
+    /// Tags of a repository in the given registry.
+    Tags(String, String),
+}
+
+/// Returns the host part of a registry address, i.e. the address
+/// without the URL scheme.
+fn registry_host(registry: &str) -> &str {
+    match registry.find("://") {
+        Some(p) => &registry[(p + 3)..],
+        None => registry,
+    }
+}
+
+/// Returns the base URL of the registry API.
+///
+/// Registries configured without a scheme are accessed over HTTPS.
+fn registry_url(registry: &str) -> String {
+    let registry = registry.trim_end_matches('/');
+    if registry.contains("://") {
+        registry.to_owned()
+    } else {
+        format!("https://{}", registry)
+    }
+}
+
+/// Encodes a value for a URL query, leaving only unreserved characters
+/// as they are.
+fn encode_query_value(value: &str) -> String {
+    let mut encoded = String::new();
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Returns the URL of a single page of a registry listing.
+///
+/// `last` is the last entry of the previous page, as required by the
+/// pagination scheme of the registry API.
+fn page_url(registry: &str, path: &str, page_size: usize, last: Option<&str>) -> String {
+    let mut url = format!("{}/v2/{}?n={}", registry_url(registry), path, page_size);
+    if let Some(l) = last {
+        url.push_str("&last=");
+        url.push_str(&encode_query_value(l));
+    }
+    url
+}
+
+/// Returns the last entry of a page, to request the page following it,
+/// or None if the listing ends with the page.
+///
+/// Registries may return fewer entries than requested before the end,
+/// so the listing ends only with an empty page, or with a page which
+/// doesn't advance past the previous one, e.g. from a registry which
+/// ignores `last`.
+fn next_page_last(page: &[String], last: Option<&str>) -> Option<String> {
+    page.last().filter(|l| Some(l.as_str()) != last).cloned()
+}
+
+#[test]
+fn test_next_page_last() {
+    let page = vec!["a".to_owned(), "b".to_owned()];
+    assert_eq!(next_page_last(&page, None), Some("b".to_owned()));
+    assert_eq!(next_page_last(&page, Some("0")), Some("b".to_owned()));
+    assert_eq!(next_page_last(&page, Some("b")), None);
+    assert_eq!(next_page_last(&[], Some("b")), None);
+}
+
+#[test]
+fn test_page_url() {
+    assert_eq!(
+        page_url("localhost:5000", "_catalog", 100, None),
+        "https://localhost:5000/v2/_catalog?n=100"
+    );
+    assert_eq!(
+        page_url(
+            "http://mirror/",
+            "library/nginx/tags/list",
+            50,
+            Some("1.25")
+        ),
+        "http://mirror/v2/library/nginx/tags/list?n=50&last=1.25"
+    );
+    assert_eq!(
+        page_url("mirror", "_catalog", 50, Some("team/app&x=1 é")),
+        "https://mirror/v2/_catalog?n=50&last=team%2Fapp%26x%3D1%20%C3%A9"
+    );
+    assert_eq!(registry_host("http://mirror"), "mirror");
+    assert_eq!(registry_host("localhost:5000"), "localhost:5000");
+}
+
+/// Extracts the array of strings stored under `key` in a JSON object.
+///
+/// This understands just enough JSON to read the responses of the
+/// registry API, e.g. `{"repositories":["a","b"]}`.
+fn json_string_array(json: &str, key: &str) -> Option<Vec<String>> {
+    let key_pattern = format!("\"{}\"", key);
+    let after_key = &json[(json.find(&key_pattern)? + key_pattern.len())..];
+    let after_colon = after_key.trim_start().strip_prefix(':')?.trim_start();
+    if after_colon.starts_with("null") {
+        return Some(vec![]);
+    }
+    let mut chars = after_colon.strip_prefix('[')?.chars();
+    let mut result = vec![];
+    loop {
+        match chars.next()? {
+            ']' => return Some(result),
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next()? {
+                            'n' => string.push('\n'),
+                            't' => string.push('\t'),
+                            'u' => {
+                                let code: String = chars.by_ref().take(4).collect();
+                                let c = u32::from_str_radix(&code, 16).ok()?;
+                                string.push(char::from_u32(c).unwrap_or('\u{fffd}'));
+                            }
+                            c => string.push(c),
+                        },
+                        c => string.push(c),
+                    }
+                }
+                result.push(string);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[test]
+fn test_json_string_array() {
+    assert_eq!(
+        json_string_array(r#"{"repositories":["a","b/c"]}"#, "repositories"),
+        Some(vec!["a".to_owned(), "b/c".to_owned()])
+    );
+    assert_eq!(
+        json_string_array(r#"{"name": "x", "tags": [ "1.0" , "la\"test" ]}"#, "tags"),
+        Some(vec!["1.0".to_owned(), "la\"test".to_owned()])
+    );
+    assert_eq!(
+        json_string_array(r#"{"name":"x","tags":null}"#, "tags"),
+        Some(vec![])
+    );
+    assert_eq!(
+        json_string_array(r#"{"tags":["A"]}"#, "tags"),
+        Some(vec!["A".to_owned()])
+    );
+    assert_eq!(json_string_array(r#"{"tags":["unterminated"#, "tags"), None);
+    assert_eq!(json_string_array(r#"{"errors":[]}"#, "tags"), None);
+}
+
+/// Fetches a single page of a registry listing with `curl`.
+fn fetch_page(url: &str, key: &str, timeout: time::Duration) -> Result<Vec<String>, String> {
+    let output = subprocess::run(
+        Command::new("curl").args(["--silent", "--show-error", "--fail", "--location", url]),
+        timeout,
+    )?;
+    json_string_array(&output, key).ok_or_else(|| format!("unexpected response from {}", url))
+}
+
+/// Lists the images known to the local container engine.
+fn local_images(engine: &str, timeout: time::Duration) -> Result<Vec<core::CompletionBox>, String> {
+    let output = subprocess::run(
+        Command::new(engine).args(["images", "--format", "{{.Repository}}\t{{.Tag}}"]),
+        timeout,
+    )?;
+    let mut completions: Vec<core::CompletionBox> = vec![];
+    for line in output.lines() {
+        if let Some((repository, tag)) = line.split_once('\t') {
+            if repository == "<none>" {
+                continue;
+            }
+            completions.push(Box::new(ImageCompletion {
+                registry: None,
+                repository: repository.to_owned(),
+                tag: if tag == "<none>" {
+                    None
+                } else {
+                    Some(tag.to_owned())
+                },
+            }));
+        }
+    }
+    Result::Ok(completions)
+}
+
+/// Lists all pages of the given source, sending each page through
+/// `response_send` as soon as it is fetched, or the failure which
+/// stopped the listing.
+///
+/// Returns `false` if the receiving end is gone and fetching should
+/// be stopped.
+fn fetch_source(
+    source: &ImageSource,
+    containers_config: &config::ContainersConfig,
+    timeout: time::Duration,
+    response_send: &mpsc::Sender<Result<Vec<core::CompletionBox>, String>>,
+) -> bool {
+    let (registry, path, key) = match *source {
+        ImageSource::Local => {
+            return response_send
+                .send(local_images(&containers_config.engine, timeout))
+                .is_ok();
+        }
+        ImageSource::Catalog(ref registry) => (registry, "_catalog".to_owned(), "repositories"),
+        ImageSource::Tags(ref registry, ref repository) => {
+            (registry, format!("{}/tags/list", repository), "tags")
+        }
+    };
+    let page_size = containers_config.page_size.max(1);
+    let mut last: Option<String> = None;
+    loop {
+        let url = page_url(registry, &path, page_size, last.as_deref());
+        let page = match fetch_page(&url, key, timeout) {
+            Result::Ok(p) => p,
+            Result::Err(e) => return response_send.send(Result::Err(e)).is_ok(),
+        };
+        let next_last = next_page_last(&page, last.as_deref());
+        if next_last.is_none() {
+            return true;
+        }
+        last = next_last;
+        let completions = page
+            .into_iter()
+            .map(|name| -> core::CompletionBox {
+                match *source {
+                    ImageSource::Tags(_, ref repository) => Box::new(ImageCompletion {
+                        registry: Some(registry.clone()),
+                        repository: repository.clone(),
+                        tag: Some(name),
+                    }),
+                    _ => Box::new(ImageCompletion {
+                        registry: Some(registry.clone()),
+                        repository: name,
+                        tag: None,
+                    }),
+                }
+            })
+            .collect();
+        if response_send.send(Result::Ok(completions)).is_err() {
+            return false;
+        }
+    }
+}
+
+/// Lists the given sources in the background. Each page is passed on
+/// as soon as it is fetched, so that a slow registry never blocks the
+/// UI.
+fn list_sources(
+    sources: Vec<ImageSource>,
+    containers_config: config::ContainersConfig,
+    timeout: time::Duration,
+) -> subprocess::Background<Result<Vec<core::CompletionBox>, String>> {
+    subprocess::Background::stream(move |response_send| {
+        for source in &sources {
+            if !fetch_source(source, &containers_config, timeout, response_send) {
+                return;
+            }
+        }
+    })
+}
+
+/// A completer for image references: local images and repositories
+/// of the configured registries.
+pub struct ImageCompleter {
+    containers_config: config::ContainersConfig,
+
+    /// Lists the images in the background, page by page.
+    listing: subprocess::Background<Result<Vec<core::CompletionBox>, String>>,
+    errors: Vec<String>,
+
+    /// The time after which the engine and `curl` are killed.
+    timeout: time::Duration,
+}
+
+impl ImageCompleter {
+    pub fn new(
+        containers_config: config::ContainersConfig,
+        timeout: time::Duration,
+    ) -> ImageCompleter {
+        let mut sources = vec![ImageSource::Local];
+        sources.extend(
+            containers_config
+                .registries
+                .iter()
+                .map(|r| ImageSource::Catalog(r.clone())),
+        );
+        ImageCompleter {
+            listing: list_sources(sources, containers_config.clone(), timeout),
+            errors: vec![],
+            containers_config,
+            timeout,
+        }
+    }
+}
+
+impl core::Completer for ImageCompleter {
+    fn name(&self) -> String {
+        "img".to_owned()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        self.listing.poll_listing(&mut self.errors)
+    }
+
+    fn refresh(&mut self) -> bool {
+        *self = ImageCompleter::new(self.containers_config.clone(), self.timeout);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let completion_any = completion.as_any();
+        let image_completion = completion_any.downcast_ref::<ImageCompletion>().unwrap();
+        match (&image_completion.registry, &image_completion.tag) {
+            (Some(registry), None) => Some(Box::new(ImageTagCompleter::new(
+                registry.clone(),
+                image_completion.repository.clone(),
+                self.containers_config.clone(),
+                self.timeout,
+            ))),
+            _ => None,
+        }
+    }
+}
+
+/// A completer for the tags of a single repository in a registry.
+struct ImageTagCompleter {
+    /// The reference of the repository, without a tag.
+    repository_reference: String,
+
+    /// Lists the tags in the background, page by page.
+    listing: subprocess::Background<Result<Vec<core::CompletionBox>, String>>,
+    errors: Vec<String>,
+}
+
+impl ImageTagCompleter {
+    fn new(
+        registry: String,
+        repository: String,
+        containers_config: config::ContainersConfig,
+        timeout: time::Duration,
+    ) -> ImageTagCompleter {
+        let repository_reference = format!("{}/{}", registry_host(&registry), repository);
+        let sources = vec![ImageSource::Tags(registry, repository)];
+        ImageTagCompleter {
+            repository_reference,
+            listing: list_sources(sources, containers_config, timeout),
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for ImageTagCompleter {
+    fn name(&self) -> String {
+        "tag".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.repository_reference.clone())
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        self.listing.poll_listing(&mut self.errors)
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,465 @@
+//! Defines a completer for the objects of the databases of connections
+//! set up in the configuration: databases, their tables and the columns
+//! of the tables, listed with the command line clients of PostgreSQL,
+//! MySQL and SQLite.
+//!
+//! The completer lists the databases of all the connections, descends
+//! from a database to its tables and from a table to its columns. As
+//! the clients connect to servers, it is offered only when connections
+//! are configured, lists objects in a background thread with its own
+//! timeout, and caches the listings for a while.
+
+use std::any;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// Options of the clients after which databases are completed.
+pub const DATABASE_OPTIONS: &[&str] = &["-d", "--dbname", "-D", "--database"];
+
+/// Returns an SQL string literal of a name.
+fn quote_literal(name: &str) -> String {
+    format!("'{}'", name.replace('\'', "''"))
+}
+
+/// Returns the command running a query with the client of an engine,
+/// printing the rows without headers, with fields separated by tabs.
+fn query_command(
+    connection: &config::DatabaseConnection,
+    database: Option<&str>,
+    sql: &str,
+) -> Command {
+    let mut command;
+    match connection.engine {
+        config::DatabaseEngine::Postgres => {
+            command = Command::new("psql");
+            command.args(["--no-psqlrc", "--no-align", "--tuples-only"]);
+            command.args(["--field-separator", "\t"]);
+            command.args(&connection.args);
+            if let Some(d) = database {
+                command.args(["--dbname", d]);
+            }
+            command.args(["--command", sql]);
+        }
+        config::DatabaseEngine::Mysql => {
+            command = Command::new("mysql");
+            command.args(["--batch", "--skip-column-names"]);
+            command.args(&connection.args);
+            if let Some(d) = database {
+                command.args(["--database", d]);
+            }
+            command.args(["--execute", sql]);
+        }
+        config::DatabaseEngine::Sqlite => {
+            // The arguments end with the path of the database file,
+            // after which the query follows.
+            command = Command::new("sqlite3");
+            command.args(["-batch", "-separator", "\t"]);
+            command.args(&connection.args);
+            command.arg(sql);
+        }
+    }
+    command
+}
+
+/// The node of the tree of database objects which a completer lists.
+#[derive(Clone, Debug, PartialEq)]
+enum Level {
+    /// The databases of all the connections.
+    Databases,
+
+    /// The tables and views of a database of a connection, given by its
+    /// index. SQLite connections have a single, unnamed database.
+    Tables {
+        connection: usize,
+        database: Option<String>,
+    },
+
+    /// The columns of a table, with its schema in PostgreSQL.
+    Columns {
+        connection: usize,
+        database: Option<String>,
+        schema: Option<String>,
+        table: String,
+    },
+}
+
+#[derive(Debug, PartialEq)]
+struct DatabaseCompletion {
+    value: String,
+    description: Option<String>,
+
+    /// The node which the completion descends to, if it isn't a column.
+    level: Option<Level>,
+}
+
+impl core::Completion for DatabaseCompletion {
+    fn result_string(&self) -> String {
+        self.value.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        if self.level.is_some() {
+            core::CompletionKind::Directory
+        } else {
+            core::CompletionKind::Other
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the rows of the output of a query, split into fields.
+fn rows(output: &str) -> impl Iterator<Item = Vec<&str>> {
+    output
+        .lines()
+        .filter(|l| !l.is_empty())
+        .map(|l| l.split('\t').collect())
+}
+
+/// Returns the completions of the objects at a level, parsed from the
+/// output of `level_query` run for the connection of the given index.
+fn parse_level(
+    engine: config::DatabaseEngine,
+    level: &Level,
+    connection: usize,
+    connection_name: &str,
+    output: &str,
+) -> Vec<DatabaseCompletion> {
+    match *level {
+        Level::Databases => rows(output)
+            .filter_map(|row| {
+                let name = *row.first()?;
+                Some(DatabaseCompletion {
+                    value: name.to_owned(),
+                    description: Some(connection_name.to_owned()),
+                    level: Some(Level::Tables {
+                        connection,
+                        database: Some(name.to_owned()),
+                    }),
+                })
+            })
+            .collect(),
+        Level::Tables { ref database, .. } => rows(output)
+            .filter_map(|row| {
+                // PostgreSQL prints the schema first, as tables in
+                // schemas other than `public` are named with it.
+                let (schema, fields) = match engine {
+                    config::DatabaseEngine::Postgres => (Some(*row.first()?), &row[1..]),
+                    _ => (None, &row[..]),
+                };
+                let table = (*fields.first()?).to_owned();
+                let value = match schema {
+                    Some(s) if s != "public" => format!("{}.{}", s, table),
+                    _ => table.clone(),
+                };
+                // E.g. `BASE TABLE` or `VIEW`, or `table` in SQLite.
+                let table_type = fields.get(1).map(|t| t.to_lowercase().replace("base ", ""));
+                Some(DatabaseCompletion {
+                    value,
+                    description: table_type,
+                    level: Some(Level::Columns {
+                        connection,
+                        database: database.clone(),
+                        schema: schema.map(str::to_owned),
+                        table,
+                    }),
+                })
+            })
+            .collect(),
+        Level::Columns { .. } => rows(output)
+            .filter_map(|row| {
+                Some(DatabaseCompletion {
+                    value: (*row.first()?).to_owned(),
+                    description: row.get(1).map(|t| (*t).to_owned()),
+                    level: None,
+                })
+            })
+            .collect(),
+    }
+}
+
+#[test]
+fn test_parse_level() {
+    let engine = config::DatabaseEngine::Postgres;
+    let tables = Level::Tables {
+        connection: 1,
+        database: Some("shop".to_owned()),
+    };
+    let output = "public\torders\tBASE TABLE\nreports\tsales\tVIEW\n";
+    assert_eq!(
+        parse_level(engine, &tables, 1, "local", output),
+        vec![
+            DatabaseCompletion {
+                value: "orders".to_owned(),
+                description: Some("table".to_owned()),
+                level: Some(Level::Columns {
+                    connection: 1,
+                    database: Some("shop".to_owned()),
+                    schema: Some("public".to_owned()),
+                    table: "orders".to_owned(),
+                }),
+            },
+            DatabaseCompletion {
+                value: "reports.sales".to_owned(),
+                description: Some("view".to_owned()),
+                level: Some(Level::Columns {
+                    connection: 1,
+                    database: Some("shop".to_owned()),
+                    schema: Some("reports".to_owned()),
+                    table: "sales".to_owned(),
+                }),
+            },
+        ]
+    );
+    let databases = parse_level(engine, &Level::Databases, 1, "local", "shop\n");
+    assert_eq!(databases[0].description, Some("local".to_owned()));
+    assert_eq!(databases[0].level, Some(tables));
+    let columns = Level::Columns {
+        connection: 0,
+        database: None,
+        schema: None,
+        table: "t".to_owned(),
+    };
+    assert_eq!(
+        parse_level(
+            config::DatabaseEngine::Sqlite,
+            &columns,
+            0,
+            "local",
+            "id\tINTEGER\n"
+        ),
+        vec![DatabaseCompletion {
+            value: "id".to_owned(),
+            description: Some("INTEGER".to_owned()),
+            level: None,
+        }]
+    );
+}
+
+/// Returns the query listing the objects at a level, or None for the
+/// databases of SQLite, which has just the one of the connection.
+fn level_query(engine: config::DatabaseEngine, level: &Level) -> Option<String> {
+    let query = match (engine, level) {
+        (config::DatabaseEngine::Postgres, Level::Databases) => {
+            "SELECT datname FROM pg_database WHERE NOT datistemplate ORDER BY 1".to_owned()
+        }
+        (config::DatabaseEngine::Mysql, Level::Databases) => "SHOW DATABASES".to_owned(),
+        (config::DatabaseEngine::Sqlite, Level::Databases) => return None,
+        (config::DatabaseEngine::Postgres, Level::Tables { .. }) => {
+            "SELECT table_schema, table_name, table_type FROM information_schema.tables \
+             WHERE table_schema NOT IN ('pg_catalog', 'information_schema') ORDER BY 1, 2"
+                .to_owned()
+        }
+        (config::DatabaseEngine::Mysql, Level::Tables { .. }) => {
+            "SELECT table_name, table_type FROM information_schema.tables \
+             WHERE table_schema = DATABASE() ORDER BY 1"
+                .to_owned()
+        }
+        (config::DatabaseEngine::Sqlite, Level::Tables { .. }) => {
+            "SELECT name, type FROM sqlite_master \
+             WHERE type IN ('table', 'view') AND name NOT LIKE 'sqlite_%' ORDER BY 1"
+                .to_owned()
+        }
+        (config::DatabaseEngine::Postgres, Level::Columns { schema, table, .. }) => format!(
+            "SELECT column_name, data_type FROM information_schema.columns \
+             WHERE table_schema = {} AND table_name = {} ORDER BY ordinal_position",
+            quote_literal(schema.as_deref().unwrap_or("public")),
+            quote_literal(table)
+        ),
+        (config::DatabaseEngine::Mysql, Level::Columns { table, .. }) => format!(
+            "SELECT column_name, column_type FROM information_schema.columns \
+             WHERE table_schema = DATABASE() AND table_name = {} ORDER BY ordinal_position",
+            quote_literal(table)
+        ),
+        (config::DatabaseEngine::Sqlite, Level::Columns { table, .. }) => format!(
+            "SELECT name, type FROM pragma_table_info({})",
+            quote_literal(table)
+        ),
+    };
+    Some(query)
+}
+
+#[test]
+fn test_level_query() {
+    let columns = Level::Columns {
+        connection: 0,
+        database: Some("shop".to_owned()),
+        schema: None,
+        table: "o'brien".to_owned(),
+    };
+    assert_eq!(
+        level_query(config::DatabaseEngine::Sqlite, &columns),
+        Some("SELECT name, type FROM pragma_table_info('o''brien')".to_owned())
+    );
+    assert_eq!(
+        level_query(config::DatabaseEngine::Sqlite, &Level::Databases),
+        None
+    );
+}
+
+/// Lists the objects at a level, returning the completions and the
+/// errors.
+fn list_level(
+    level: &Level,
+    use_cache: bool,
+    config: &config::DatabaseConfig,
+) -> (Vec<DatabaseCompletion>, Vec<String>) {
+    let (connections, database) = match *level {
+        Level::Databases => ((0..config.connections.len()).collect(), None),
+        Level::Tables {
+            connection,
+            ref database,
+        }
+        | Level::Columns {
+            connection,
+            ref database,
+            ..
+        } => (vec![connection], database.as_deref()),
+    };
+    let mut completions = vec![];
+    let mut errors = vec![];
+    for index in connections {
+        let connection = match config.connections.get(index) {
+            Some(c) => c,
+            None => continue,
+        };
+        let query = match level_query(connection.engine, level) {
+            Some(q) => q,
+            None => {
+                completions.push(DatabaseCompletion {
+                    value: connection.name.clone(),
+                    description: connection.args.last().cloned(),
+                    level: Some(Level::Tables {
+                        connection: index,
+                        database: None,
+                    }),
+                });
+                continue;
+            }
+        };
+        let output = subprocess::run_expiring(
+            &mut query_command(connection, database, &query),
+            config.timeout(),
+            "database",
+            time::Duration::from_secs(config.cache_secs),
+            use_cache,
+        );
+        match output {
+            Result::Ok(o) => completions.extend(parse_level(
+                connection.engine,
+                level,
+                index,
+                &connection.name,
+                &o,
+            )),
+            Result::Err(e) => errors.push(format!("{}: {}", connection.name, e)),
+        }
+    }
+    (completions, errors)
+}
+
+/// A completer for the objects of the configured databases, at a node
+/// of their tree.
+pub struct DatabaseCompleter {
+    level: Level,
+    config: config::DatabaseConfig,
+
+    /// Lists the objects in the background.
+    listing: subprocess::Background<(Vec<DatabaseCompletion>, Vec<String>)>,
+    errors: Vec<String>,
+}
+
+impl DatabaseCompleter {
+    /// Creates a completer of the databases of all the connections.
+    pub fn new(config: config::DatabaseConfig) -> Self {
+        DatabaseCompleter::at_level(Level::Databases, config)
+    }
+
+    fn at_level(level: Level, config: config::DatabaseConfig) -> Self {
+        let mut completer = DatabaseCompleter {
+            level,
+            config,
+            listing: subprocess::Background::idle(),
+            errors: vec![],
+        };
+        completer.start(true);
+        completer
+    }
+
+    fn start(&mut self, use_cache: bool) {
+        let level = self.level.clone();
+        let config = self.config.clone();
+        self.listing = subprocess::Background::run(move || list_level(&level, use_cache, &config));
+    }
+}
+
+impl core::Completer for DatabaseCompleter {
+    fn name(&self) -> String {
+        "db".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        let connection_name = |c: usize| self.config.connections.get(c).map(|c| c.name.clone());
+        match self.level {
+            Level::Databases => None,
+            Level::Tables {
+                connection,
+                ref database,
+            } => database.clone().or_else(|| connection_name(connection)),
+            Level::Columns {
+                connection,
+                ref database,
+                ref table,
+                ..
+            } => {
+                let database = database.clone().or_else(|| connection_name(connection))?;
+                Some(format!("{}/{}", database, table))
+            }
+        }
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let failed = || (vec![], vec!["listing database objects failed".to_owned()]);
+        let (completions, errors) = match self.listing.poll(failed) {
+            Some(result) => result,
+            None => return vec![],
+        };
+        self.errors.extend(errors);
+        completions
+            .into_iter()
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    /// Lists the objects anew, bypassing the cache.
+    fn refresh(&mut self) -> bool {
+        self.start(false);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let completion = completion.as_any().downcast_ref::<DatabaseCompletion>()?;
+        Some(Box::new(DatabaseCompleter::at_level(
+            completion.level.clone()?,
+            self.config.clone(),
+        )))
+    }
+}
@@ -0,0 +1,324 @@
+//! Defines a completer inserting dates and times in common formats,
+//! e.g. for names of log files or options like `git log --since`.
+//!
+//! The query names the moment: `now` (the default), `today`,
+//! `tomorrow`, `yesterday`, a weekday like `friday`, optionally after
+//! `next` or `last`, or a relative time like `in 3 days` or `2 hours
+//! ago`. Named days start at midnight, in the local time zone.
+
+use std::any;
+use std::time;
+
+use crate::core;
+
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// The names of weekdays, from Sunday, as 1970-01-01 was a Thursday.
+const WEEKDAYS: [&str; 7] = [
+    "sunday",
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+];
+
+/// Returns the number of days since 1970-01-01 of a date of the
+/// proleptic Gregorian calendar.
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146097 + day_of_era - 719468
+}
+
+/// Returns the year, month and day of a number of days since
+/// 1970-01-01.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let day_of_era = days - era * 146097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+#[test]
+fn test_civil_days() {
+    assert_eq!(days_from_civil(1970, 1, 1), 0);
+    assert_eq!(days_from_civil(2000, 3, 1), 11017);
+    assert_eq!(civil_from_days(11017), (2000, 3, 1));
+    assert_eq!(civil_from_days(-1), (1969, 12, 31));
+    assert_eq!(civil_from_days(days_from_civil(2024, 2, 29)), (2024, 2, 29));
+}
+
+/// Returns the offset of the local time zone from UTC in seconds at the
+/// given Unix time.
+fn local_offset(timestamp: i64) -> i64 {
+    #[cfg(unix)]
+    {
+        let time = timestamp as libc::time_t;
+        // Safe, as `tm` is plain data, filled in by localtime_r.
+        let mut tm: libc::tm = unsafe { std::mem::zeroed() };
+        if !unsafe { libc::localtime_r(&time, &mut tm) }.is_null() {
+            return tm.tm_gmtoff as i64;
+        }
+    }
+    let _ = timestamp;
+    0
+}
+
+/// Returns the number of seconds of a unit of relative times.
+fn unit_seconds(unit: &str) -> Option<i64> {
+    match unit.trim_end_matches('s') {
+        "sec" | "second" => Some(1),
+        "min" | "minute" => Some(60),
+        "hour" => Some(60 * 60),
+        "day" => Some(SECONDS_PER_DAY),
+        "week" => Some(7 * SECONDS_PER_DAY),
+        _ => None,
+    }
+}
+
+/// Returns the index of a weekday in `WEEKDAYS`, given its name or an
+/// abbreviation of at least three letters.
+fn weekday(name: &str) -> Option<i64> {
+    if name.len() < 3 {
+        return None;
+    }
+    WEEKDAYS
+        .iter()
+        .position(|d| d.starts_with(name))
+        .map(|i| i as i64)
+}
+
+/// Returns the Unix time of the moment named by a phrase, relative to
+/// `now`, using `offset_at` for the offset of the local time zone.
+fn resolve(phrase: &str, now: i64, offset_at: &dyn Fn(i64) -> i64) -> Option<i64> {
+    let phrase = phrase.to_lowercase();
+    let words: Vec<&str> = phrase.split_whitespace().collect();
+    let today = (now + offset_at(now)).div_euclid(SECONDS_PER_DAY);
+    let today_weekday = (today + 4).rem_euclid(7);
+    // The start of a local day, with the offset on that day, which
+    // differs from today's one across a change of daylight saving time.
+    let midnight = |days: i64| {
+        let local = (today + days) * SECONDS_PER_DAY;
+        Some(local - offset_at(local - offset_at(now)))
+    };
+    match words[..] {
+        [] | ["now"] => Some(now),
+        ["today"] => midnight(0),
+        ["tomorrow"] => midnight(1),
+        ["yesterday"] => midnight(-1),
+        ["next", day] => midnight((weekday(day)? - today_weekday - 1).rem_euclid(7) + 1),
+        ["last", day] => midnight(-((today_weekday - weekday(day)? - 1).rem_euclid(7) + 1)),
+        [day] => midnight((weekday(day)? - today_weekday).rem_euclid(7)),
+        ["in", count, unit] => Some(now + count.parse::<i64>().ok()? * unit_seconds(unit)?),
+        [count, unit, "ago"] => Some(now - count.parse::<i64>().ok()? * unit_seconds(unit)?),
+        _ => None,
+    }
+}
+
+/// Indicates if the query names a moment, e.g. `tomorrow`.
+pub fn is_moment(query: &str) -> bool {
+    !query.trim().is_empty() && resolve(query, 0, &|_| 0).is_some()
+}
+
+#[test]
+fn test_resolve() {
+    // Thursday, 2024-03-14 15:30:00 in UTC+01:00.
+    let now = days_from_civil(2024, 3, 14) * SECONDS_PER_DAY + 14 * 3600 + 30 * 60;
+    let offset_at = |_| 3600;
+    let local_date = |phrase: &str| {
+        let t = resolve(phrase, now, &offset_at).unwrap() + 3600;
+        let (y, m, d) = civil_from_days(t.div_euclid(SECONDS_PER_DAY));
+        (y, m, d, t.rem_euclid(SECONDS_PER_DAY) / 3600)
+    };
+    assert_eq!(local_date("now"), (2024, 3, 14, 15));
+    assert_eq!(local_date("Today"), (2024, 3, 14, 0));
+    assert_eq!(local_date("yesterday"), (2024, 3, 13, 0));
+    assert_eq!(local_date("thursday"), (2024, 3, 14, 0));
+    assert_eq!(local_date("next thu"), (2024, 3, 21, 0));
+    assert_eq!(local_date("next friday"), (2024, 3, 15, 0));
+    assert_eq!(local_date("last friday"), (2024, 3, 8, 0));
+    assert_eq!(local_date("last wed"), (2024, 3, 13, 0));
+    assert_eq!(local_date("in 3 days"), (2024, 3, 17, 15));
+    assert_eq!(local_date("2 hours ago"), (2024, 3, 14, 13));
+    assert_eq!(resolve("fr", now, &offset_at), None);
+    assert_eq!(resolve("in 3 fortnights", now, &offset_at), None);
+    assert!(is_moment("tomorrow"));
+    assert!(!is_moment("tom"));
+    assert!(!is_moment(""));
+}
+
+/// The formats of dates and times offered, with their descriptions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Format {
+    /// The date alone, e.g. `2024-03-14`.
+    Date,
+    /// RFC 3339, a profile of ISO 8601, in local time.
+    Rfc3339,
+    /// RFC 3339 in UTC.
+    Utc,
+    /// The number of seconds since 1970-01-01 UTC.
+    Epoch,
+    /// A compact form for names of files, e.g. `20240314-153000`.
+    Compact,
+}
+
+const FORMATS: [Format; 5] = [
+    Format::Date,
+    Format::Rfc3339,
+    Format::Utc,
+    Format::Epoch,
+    Format::Compact,
+];
+
+impl Format {
+    fn description(self) -> &'static str {
+        match self {
+            Format::Date => "ISO 8601 date",
+            Format::Rfc3339 => "RFC 3339 / ISO 8601",
+            Format::Utc => "RFC 3339, UTC",
+            Format::Epoch => "Unix time",
+            Format::Compact => "compact, for file names",
+        }
+    }
+
+    /// Formats a Unix time, in a time zone with the given offset.
+    fn format(self, timestamp: i64, offset: i64) -> String {
+        let offset = if self == Format::Utc { 0 } else { offset };
+        let local = timestamp + offset;
+        let (year, month, day) = civil_from_days(local.div_euclid(SECONDS_PER_DAY));
+        let seconds = local.rem_euclid(SECONDS_PER_DAY);
+        let (hour, minute, second) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+        match self {
+            Format::Date => format!("{:04}-{:02}-{:02}", year, month, day),
+            Format::Rfc3339 => format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}{}{:02}:{:02}",
+                year,
+                month,
+                day,
+                hour,
+                minute,
+                second,
+                if offset < 0 { '-' } else { '+' },
+                offset.abs() / 3600,
+                offset.abs() / 60 % 60
+            ),
+            Format::Utc => format!(
+                "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+                year, month, day, hour, minute, second
+            ),
+            Format::Epoch => timestamp.to_string(),
+            Format::Compact => format!(
+                "{:04}{:02}{:02}-{:02}{:02}{:02}",
+                year, month, day, hour, minute, second
+            ),
+        }
+    }
+}
+
+#[test]
+fn test_format() {
+    let timestamp = days_from_civil(2024, 3, 14) * SECONDS_PER_DAY + 14 * 3600 + 30 * 60 + 5;
+    let offset = -(5 * 3600 + 30 * 60);
+    let formatted: Vec<String> = FORMATS
+        .iter()
+        .map(|f| f.format(timestamp, offset))
+        .collect();
+    assert_eq!(
+        formatted,
+        vec![
+            "2024-03-14",
+            "2024-03-14T09:00:05-05:30",
+            "2024-03-14T14:30:05Z",
+            "1710426605",
+            "20240314-090005",
+        ]
+    );
+}
+
+struct DateTimeCompletion {
+    text: String,
+    format: Format,
+}
+
+impl core::Completion for DateTimeCompletion {
+    fn result_string(&self) -> String {
+        self.text.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(self.format.description().to_owned())
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A completer of the moment named by the query in several formats.
+/// It is dynamic, as the query names the moment rather than filters
+/// the completions.
+pub struct DateTimeCompleter {
+    phrase: String,
+}
+
+impl DateTimeCompleter {
+    pub fn new<P: Into<String>>(phrase: P) -> Self {
+        Self {
+            phrase: phrase.into(),
+        }
+    }
+}
+
+impl core::Completer for DateTimeCompleter {
+    fn name(&self) -> String {
+        "date".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs() as i64);
+        let timestamp = match resolve(&self.phrase, now, &local_offset) {
+            Some(t) => t,
+            None => return vec![],
+        };
+        let offset = local_offset(timestamp);
+        FORMATS
+            .iter()
+            .map(|f| {
+                Box::new(DateTimeCompletion {
+                    text: f.format(timestamp, offset),
+                    format: *f,
+                }) as core::CompletionBox
+            })
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn is_dynamic(&self) -> bool {
+        true
+    }
+
+    fn requery(&mut self, query: &str) {
+        self.phrase = query.to_owned();
+    }
+}
@@ -0,0 +1,261 @@
+//! Defines a completer for desktop applications, read from the desktop
+//! entry files in the `applications` directories of the XDG data
+//! directories, e.g. `/usr/share/applications`.
+//!
+//! An application is identified by the path of its file relative to
+//! the `applications` directory, with slashes replaced by dashes and
+//! without the `.desktop` extension. Files in the user's directory
+//! override the system ones with the same ID.
+
+use std::any;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path;
+
+use crate::config;
+use crate::core;
+
+/// The keys of the `Desktop Entry` group used for completions.
+#[derive(Debug, Default, PartialEq)]
+struct DesktopEntry {
+    name: String,
+    generic_name: Option<String>,
+    comment: Option<String>,
+    exec: Option<String>,
+    categories: Vec<String>,
+
+    /// Indicates if the application is not to be shown in menus, or was
+    /// deleted by the user, with `NoDisplay` or `Hidden`.
+    hidden: bool,
+}
+
+/// Parses the `Desktop Entry` group of a desktop entry file, returning
+/// None for files which don't describe an application. Localized keys,
+/// like `Name[de]`, are ignored.
+fn parse_desktop_entry(contents: &str) -> Option<DesktopEntry> {
+    let mut entry = DesktopEntry::default();
+    let mut in_group = false;
+    let mut is_application = false;
+    for line in contents.lines().map(str::trim) {
+        if line.starts_with('[') {
+            in_group = line == "[Desktop Entry]";
+            continue;
+        }
+        let (key, value) = match line.split_once('=') {
+            Some((k, v)) if in_group && !line.starts_with('#') => (k.trim(), v.trim()),
+            _ => continue,
+        };
+        match key {
+            "Type" => is_application = value == "Application",
+            "Name" => entry.name = value.to_owned(),
+            "GenericName" => entry.generic_name = Some(value.to_owned()),
+            "Comment" => entry.comment = Some(value.to_owned()),
+            "Exec" => entry.exec = Some(strip_field_codes(value)),
+            "Categories" => {
+                entry.categories = value
+                    .split(';')
+                    .filter(|c| !c.is_empty())
+                    .map(str::to_owned)
+                    .collect()
+            }
+            "NoDisplay" | "Hidden" if value == "true" => entry.hidden = true,
+            _ => {}
+        }
+    }
+    if is_application && !entry.name.is_empty() {
+        Some(entry)
+    } else {
+        None
+    }
+}
+
+/// Removes the field codes, like `%U`, from the value of an `Exec` key,
+/// which the launcher replaces with the files or URLs opened.
+fn strip_field_codes(exec: &str) -> String {
+    let mut stripped = String::new();
+    let mut chars = exec.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            stripped.push(c);
+        } else if let Some('%') = chars.next() {
+            stripped.push('%');
+        }
+    }
+    stripped.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[test]
+fn test_parse_desktop_entry() {
+    let contents = "# A comment\n\
+                    [Desktop Entry]\n\
+                    Type=Application\n\
+                    Name=Firefox\n\
+                    Name[pl]=Firefox\n\
+                    GenericName=Web Browser\n\
+                    Exec=firefox --name=%c %u\n\
+                    Icon=firefox\n\
+                    Categories=Network;WebBrowser;\n\
+                    \n\
+                    [Desktop Action new-window]\n\
+                    Name=New Window\n\
+                    Exec=firefox --new-window %u\n";
+    assert_eq!(
+        parse_desktop_entry(contents),
+        Some(DesktopEntry {
+            name: "Firefox".to_owned(),
+            generic_name: Some("Web Browser".to_owned()),
+            comment: None,
+            exec: Some("firefox --name=".to_owned()),
+            categories: vec!["Network".to_owned(), "WebBrowser".to_owned()],
+            hidden: false,
+        })
+    );
+    assert_eq!(
+        parse_desktop_entry("[Desktop Entry]\nType=Link\nName=Docs\nURL=https://docs.rs\n"),
+        None
+    );
+    assert_eq!(strip_field_codes("printf 100%% %F"), "printf 100%");
+}
+
+#[derive(Debug, PartialEq)]
+struct DesktopCompletion {
+    id: String,
+    entry: DesktopEntry,
+    result: config::DesktopResult,
+}
+
+impl core::Completion for DesktopCompletion {
+    fn result_string(&self) -> String {
+        match (self.result, self.entry.exec.as_ref()) {
+            (config::DesktopResult::Exec, Some(e)) => e.clone(),
+            _ => self.id.clone(),
+        }
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        let mut spans = vec![core::Span::plain(self.entry.name.as_str())];
+        if self.entry.name != self.id {
+            spans.push(core::Span::styled(
+                format!(" {}", self.id),
+                core::Style::colored(core::SpanColor::Dimmed),
+            ));
+        }
+        spans
+    }
+
+    fn search_string(&self) -> String {
+        format!("{} {}", self.entry.name, self.id)
+    }
+
+    /// Describes the application and lists its categories.
+    fn description(&self) -> Option<String> {
+        let summary = self
+            .entry
+            .generic_name
+            .as_ref()
+            .or(self.entry.comment.as_ref());
+        let categories = self.entry.categories.join(", ");
+        match (summary, categories.is_empty()) {
+            (Some(s), false) => Some(format!("{} ({})", s, categories)),
+            (Some(s), true) => Some(s.clone()),
+            (None, false) => Some(categories),
+            (None, true) => None,
+        }
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Application
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the `applications` directories, the user's one first.
+fn application_dirs() -> Vec<path::PathBuf> {
+    let data_home = env::var_os("XDG_DATA_HOME")
+        .filter(|d| !d.is_empty())
+        .map(path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".local/share")));
+    let data_dirs = env::var_os("XDG_DATA_DIRS")
+        .filter(|d| !d.is_empty())
+        .unwrap_or_else(|| "/usr/local/share:/usr/share".into());
+    data_home
+        .into_iter()
+        .chain(env::split_paths(&data_dirs))
+        .map(|d| d.join("applications"))
+        .collect()
+}
+
+/// Adds the desktop entry files in a directory and its subdirectories,
+/// with their IDs, skipping IDs which were seen already.
+fn find_desktop_files(
+    dir: &path::Path,
+    id_prefix: &str,
+    seen: &mut HashSet<String>,
+    files: &mut Vec<(String, path::PathBuf)>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Result::Ok(e) => e,
+        Result::Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if path.is_dir() {
+            let prefix = format!("{}{}-", id_prefix, file_name);
+            find_desktop_files(&path, &prefix, seen, files);
+        } else if let Some(stem) = file_name.strip_suffix(".desktop") {
+            let id = format!("{}{}", id_prefix, stem);
+            if seen.insert(id.clone()) {
+                files.push((id, path));
+            }
+        }
+    }
+}
+
+/// A completer for the desktop applications.
+pub struct DesktopCompleter {
+    config: config::DesktopConfig,
+}
+
+impl DesktopCompleter {
+    pub fn new(config: config::DesktopConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl core::Completer for DesktopCompleter {
+    fn name(&self) -> String {
+        "app".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let mut seen = HashSet::new();
+        let mut files = vec![];
+        for dir in application_dirs() {
+            find_desktop_files(&dir, "", &mut seen, &mut files);
+        }
+        files
+            .into_iter()
+            .filter_map(|(id, path)| {
+                let entry = parse_desktop_entry(&fs::read_to_string(path).ok()?)?;
+                // A hidden entry of the user still hides the system one.
+                if entry.hidden {
+                    return None;
+                }
+                Some(Box::new(DesktopCompletion {
+                    id,
+                    entry,
+                    result: self.config.result,
+                }) as core::CompletionBox)
+            })
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,240 @@
+//! Defines a completer for hardware devices, with a source for each
+//! kind of device: the printer queues of CUPS, listed with `lpstat`,
+//! and the USB devices, listed with `lsusb`.
+//!
+//! The sources completed depend on the command line, e.g. printers for
+//! `lp -d`, and the completions of all of them are listed together.
+
+use std::any;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::core;
+
+/// The kinds of devices completed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DeviceSource {
+    /// The name of a printer queue.
+    Printer,
+    /// The vendor and product IDs of a USB device, e.g. `046d:c52b`.
+    UsbId,
+    /// The bus and device numbers of a USB device, e.g. `001:003`.
+    UsbBusDevice,
+}
+
+/// Returns the sources of the devices completed on a command line with
+/// the given words preceding the query.
+pub fn device_sources(command_words: &[&str]) -> Vec<DeviceSource> {
+    let command = match command_words.first() {
+        Some(c) => *c,
+        None => return vec![],
+    };
+    let last = command_words.last().cloned().unwrap_or_default();
+    match (command, last) {
+        ("lp", "-d") | ("lpoptions", "-d") | ("lpoptions", "-p") => vec![DeviceSource::Printer],
+        ("lpr", "-P") | ("lpq", "-P") | ("lprm", "-P") => vec![DeviceSource::Printer],
+        ("lpstat", "-p") | ("cupsenable", _) | ("cupsdisable", _) => vec![DeviceSource::Printer],
+        ("lsusb", "-d") => vec![DeviceSource::UsbId],
+        ("lsusb", "-s") => vec![DeviceSource::UsbBusDevice],
+        ("usbreset", "usbreset") => vec![DeviceSource::UsbId, DeviceSource::UsbBusDevice],
+        _ => vec![],
+    }
+}
+
+#[test]
+fn test_device_sources() {
+    assert_eq!(
+        device_sources(&["lp", "-n", "2", "-d"]),
+        vec![DeviceSource::Printer]
+    );
+    assert_eq!(device_sources(&["lpr", "-P"]), vec![DeviceSource::Printer]);
+    assert_eq!(
+        device_sources(&["lsusb", "-v", "-d"]),
+        vec![DeviceSource::UsbId]
+    );
+    assert_eq!(
+        device_sources(&["usbreset"]),
+        vec![DeviceSource::UsbId, DeviceSource::UsbBusDevice]
+    );
+    assert_eq!(device_sources(&["lp"]), vec![]);
+    assert_eq!(device_sources(&[]), vec![]);
+}
+
+#[derive(Debug, PartialEq)]
+struct DeviceCompletion {
+    id: String,
+    description: String,
+}
+
+impl core::Completion for DeviceCompletion {
+    fn result_string(&self) -> String {
+        self.id.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(self.description.clone())
+    }
+
+    /// Devices are searched by their descriptions too, e.g. USB devices
+    /// by the names of their vendors.
+    fn search_string(&self) -> String {
+        format!("{} {}", self.id, self.description)
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of `lpstat -a` into the printer queues, with
+/// whether they accept jobs. Reasons for rejecting jobs are on indented
+/// lines, which are skipped.
+fn parse_printers(output: &str) -> Vec<DeviceCompletion> {
+    output
+        .lines()
+        .filter(|l| !l.starts_with(char::is_whitespace))
+        .filter_map(|l| {
+            let (name, state) = l.split_once(' ')?;
+            let description = if state.starts_with("not accepting") {
+                "printer, not accepting jobs"
+            } else {
+                "printer"
+            };
+            Some(DeviceCompletion {
+                id: name.to_owned(),
+                description: description.to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_printers() {
+    let output = "HP_LaserJet accepting requests since Mon 11 Mar 2024 09:00:00 AM CET
+Office not accepting requests since Tue 12 Mar 2024 10:00:00 AM CET -
+\tPaused for maintenance
+";
+    assert_eq!(
+        parse_printers(output),
+        vec![
+            DeviceCompletion {
+                id: "HP_LaserJet".to_owned(),
+                description: "printer".to_owned(),
+            },
+            DeviceCompletion {
+                id: "Office".to_owned(),
+                description: "printer, not accepting jobs".to_owned(),
+            },
+        ]
+    );
+}
+
+/// Parses the output of `lsusb` into the identifiers of the devices of
+/// the given source, described with their names.
+fn parse_usb_devices(output: &str, source: DeviceSource) -> Vec<DeviceCompletion> {
+    output
+        .lines()
+        .filter_map(|l| {
+            // E.g. `Bus 001 Device 003: ID 046d:c52b Logitech, Inc.`
+            let rest = l.strip_prefix("Bus ")?;
+            let (bus, rest) = rest.split_once(" Device ")?;
+            let (device, rest) = rest.split_once(": ID ")?;
+            let (id, name) = rest.split_once(' ').unwrap_or((rest, ""));
+            let (id, description) = match source {
+                DeviceSource::UsbBusDevice => {
+                    (format!("{}:{}", bus, device), format!("{} {}", id, name))
+                }
+                _ => (id.to_owned(), name.to_owned()),
+            };
+            Some(DeviceCompletion {
+                id,
+                description: description.trim().to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_usb_devices() {
+    let output = "Bus 002 Device 001: ID 1d6b:0003 Linux Foundation 3.0 root hub
+Bus 001 Device 003: ID 046d:c52b Logitech, Inc. Unifying Receiver
+";
+    assert_eq!(
+        parse_usb_devices(output, DeviceSource::UsbId)[1],
+        DeviceCompletion {
+            id: "046d:c52b".to_owned(),
+            description: "Logitech, Inc. Unifying Receiver".to_owned(),
+        }
+    );
+    assert_eq!(
+        parse_usb_devices(output, DeviceSource::UsbBusDevice)[0],
+        DeviceCompletion {
+            id: "002:001".to_owned(),
+            description: "1d6b:0003 Linux Foundation 3.0 root hub".to_owned(),
+        }
+    );
+}
+
+/// A completer of the devices of the given sources.
+pub struct DeviceCompleter {
+    sources: Vec<DeviceSource>,
+
+    /// The time after which the commands listing devices are killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl DeviceCompleter {
+    pub fn new(sources: Vec<DeviceSource>, timeout: time::Duration) -> Self {
+        DeviceCompleter {
+            sources,
+            timeout,
+            errors: vec![],
+        }
+    }
+
+    /// Lists the devices of a source, returning the completions or the
+    /// error.
+    fn list(&self, source: DeviceSource) -> Result<Vec<DeviceCompletion>, String> {
+        match source {
+            DeviceSource::Printer => {
+                subprocess::run(Command::new("lpstat").arg("-a"), self.timeout)
+                    .map(|o| parse_printers(&o))
+            }
+            DeviceSource::UsbId | DeviceSource::UsbBusDevice => {
+                subprocess::run(&mut Command::new("lsusb"), self.timeout)
+                    .map(|o| parse_usb_devices(&o, source))
+            }
+        }
+    }
+}
+
+impl core::Completer for DeviceCompleter {
+    fn name(&self) -> String {
+        "dev".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let mut completions: Vec<core::CompletionBox> = vec![];
+        for source in self.sources.clone() {
+            match self.list(source) {
+                Result::Ok(devices) => completions.extend(
+                    devices
+                        .into_iter()
+                        .map(|c| Box::new(c) as core::CompletionBox),
+                ),
+                Result::Err(e) => self.errors.push(e),
+            }
+        }
+        completions
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
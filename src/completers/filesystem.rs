@@ -3,27 +3,68 @@
 
 use std::any;
 use std::collections::vec_deque::VecDeque;
+use std::collections::HashSet;
+use std::ffi;
 use std::fs;
 use std::path;
 use std::sync::mpsc;
+use std::sync::OnceLock;
 use std::thread;
 
-use termion::color;
+use log::warn;
 
+use crate::config;
 use crate::core;
 
-const DIRECTORY_DEPTH_LIMIT: usize = 7;
+use super::fs_cache;
+use super::viewer;
 
 #[derive(PartialEq)]
 enum FsEntryType {
     Directory,
     File,
+    /// A symbolic link, whose target is not resolved yet.
+    Symlink,
     Error,
 }
 
 struct FsCompletion {
     relative_path: path::PathBuf,
+
+    /// The type of the entry; for symbolic links which are followed,
+    /// this is the type of the target.
     entry_type: FsEntryType,
+
+    /// Indicates if the entry is a symbolic link.
+    symlink: bool,
+
+    /// Indicates if the entry or any of its parent directories is a
+    /// dotfile.
+    hidden: bool,
+
+    /// The metadata of the entry, read when it is first needed.
+    metadata: OnceLock<Option<core::FileMetadata>>,
+}
+
+/// Reads the metadata of a file; for symbolic links, this is the
+/// metadata of the target, or of the link itself if it is dangling.
+fn read_file_metadata(file_path: &path::Path) -> Option<core::FileMetadata> {
+    let metadata = fs::metadata(file_path)
+        .or_else(|_| fs::symlink_metadata(file_path))
+        .ok()?;
+    #[cfg(unix)]
+    let mode = {
+        use std::os::unix::fs::PermissionsExt;
+        Some(metadata.permissions().mode())
+    };
+    #[cfg(not(unix))]
+    let mode = None;
+    Some(core::FileMetadata {
+        is_dir: metadata.is_dir(),
+        size: metadata.len(),
+        modified: metadata.modified().ok(),
+        mode,
+    })
 }
 
 impl core::Completion for FsCompletion {
@@ -31,19 +72,70 @@ impl core::Completion for FsCompletion {
         self.relative_path.to_string_lossy().into_owned()
     }
 
-    fn display_string(&self) -> String {
-        if self.entry_type == FsEntryType::Directory {
-            format!(
-                "{}{}{}",
-                color::Fg(color::Blue),
-                self.result_string(),
-                color::Fg(color::Reset)
-            )
+    fn display_spans(&self) -> Vec<core::Span> {
+        let path = self.relative_path.to_string_lossy();
+        // The directories leading to the entry are dimmed, so that its
+        // name stands out.
+        let name_start = path.rfind(path::MAIN_SEPARATOR).map_or(0, |i| i + 1);
+        let name_color = match self.entry_type {
+            FsEntryType::Error => Some(core::SpanColor::Error),
+            FsEntryType::File if !self.symlink => None,
+            _ => Some(core::SpanColor::Kind(self.kind())),
+        };
+        let mut spans = vec![];
+        if name_start > 0 {
+            spans.push(core::Span::styled(
+                &path[..name_start],
+                core::Style::colored(core::SpanColor::Dimmed),
+            ));
+        }
+        spans.push(core::Span::styled(
+            &path[name_start..],
+            core::Style {
+                color: name_color,
+                ..core::Style::default()
+            },
+        ));
+        spans
+    }
+
+    fn description(&self) -> Option<String> {
+        if self.symlink {
+            let target = fs::read_link(&self.relative_path).ok()?;
+            Some(format!("-> {}", target.to_string_lossy()))
         } else {
-            self.result_string()
+            None
+        }
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        if self.symlink {
+            core::CompletionKind::Symlink
+        } else if self.entry_type == FsEntryType::Directory {
+            core::CompletionKind::Directory
+        } else {
+            core::CompletionKind::File
         }
     }
 
+    fn is_hidden(&self) -> bool {
+        self.hidden
+    }
+
+    fn group(&self) -> Option<core::CompletionGroup> {
+        Some(if self.entry_type == FsEntryType::Directory {
+            core::CompletionGroup::new(0, "directories")
+        } else {
+            core::CompletionGroup::new(1, "files")
+        })
+    }
+
+    fn file_metadata(&self) -> Option<core::FileMetadata> {
+        *self
+            .metadata
+            .get_or_init(|| read_file_metadata(&self.relative_path))
+    }
+
     fn as_any(&self) -> &dyn any::Any {
         self
     }
@@ -51,9 +143,29 @@ impl core::Completion for FsCompletion {
 
 /// Type representing an entry in the BFS queue of directory enumeration.
 ///
-/// The first element is a directory path, and the second element signifies
-/// the depth of the directory in the search.
-struct DirectoryQueueEntry(path::PathBuf, usize);
+/// The first element is a directory path, the second element signifies
+/// the depth of the directory in the search, and the third one indicates
+/// whether the directory is hidden.
+struct DirectoryQueueEntry(path::PathBuf, usize, bool);
+
+/// A value identifying a directory regardless of the path used to
+/// reach it, used to detect cycles when following symbolic links.
+#[cfg(unix)]
+type DirectoryId = (u64, u64);
+#[cfg(not(unix))]
+type DirectoryId = path::PathBuf;
+
+#[cfg(unix)]
+fn directory_id(dir_path: &path::Path) -> Option<DirectoryId> {
+    use std::os::unix::fs::MetadataExt;
+    let metadata = fs::metadata(dir_path).ok()?;
+    Some((metadata.dev(), metadata.ino()))
+}
+
+#[cfg(not(unix))]
+fn directory_id(dir_path: &path::Path) -> Option<DirectoryId> {
+    fs::canonicalize(dir_path).ok()
+}
 
 /// A structure representing the background fetching thread.
 struct BgThread {
@@ -62,63 +174,273 @@ struct BgThread {
     pub response_recv: mpsc::Receiver<Option<Vec<core::CompletionBox>>>,
 }
 
-fn directory_bfs(queue: &mut VecDeque<DirectoryQueueEntry>) -> Vec<core::CompletionBox> {
-    let queue_entry = queue.pop_front();
-    if let None = queue_entry {
-        return vec![];
+/// Lists the names and types of the entries of a directory, taking
+/// them from the cache if the directory did not change since it was
+/// cached.
+fn read_directory(
+    dir_path: &path::Path,
+    cache: Option<&mut fs_cache::FsCache>,
+) -> Option<Vec<(ffi::OsString, FsEntryType)>> {
+    let cache = match cache {
+        Some(c) => c,
+        None => return read_directory_uncached(dir_path).map(|(entries, _)| entries),
+    };
+    if let Some(cached_entries) = cache.get(dir_path) {
+        return Some(
+            cached_entries
+                .into_iter()
+                .map(|e| {
+                    let entry_type = if e.is_symlink {
+                        FsEntryType::Symlink
+                    } else if e.is_dir {
+                        FsEntryType::Directory
+                    } else {
+                        FsEntryType::File
+                    };
+                    (ffi::OsString::from(e.name), entry_type)
+                })
+                .collect(),
+        );
     }
-    let DirectoryQueueEntry(dir_path, depth) = queue_entry.unwrap();
-    let mut completions: Vec<core::CompletionBox> = vec![];
-    let read_dir_result = fs::read_dir(&dir_path);
-    if let Err(_) = read_dir_result {
-        return vec![];
+    let (entries, complete) = read_directory_uncached(dir_path)?;
+    let cached_entries: Option<Vec<fs_cache::Entry>> = entries
+        .iter()
+        .map(|(name, entry_type)| {
+            Some(fs_cache::Entry {
+                name: name.to_str()?.to_owned(),
+                is_dir: *entry_type == FsEntryType::Directory,
+                is_symlink: *entry_type == FsEntryType::Symlink,
+            })
+        })
+        .collect();
+    // Listings with errors or names which are not valid UTF-8 are not
+    // cached.
+    if let (true, Some(c)) = (complete, cached_entries) {
+        cache.insert(dir_path, c);
     }
-    let mut entries = read_dir_result.unwrap();
-    while let Some(Ok(entry)) = entries.next() {
+    Some(entries)
+}
+
+/// Lists the entries of a directory, also returning an indication if
+/// all of them were read without errors.
+fn read_directory_uncached(
+    dir_path: &path::Path,
+) -> Option<(Vec<(ffi::OsString, FsEntryType)>, bool)> {
+    let mut entries = vec![];
+    let mut complete = true;
+    let mut dir_entries = fs::read_dir(dir_path).ok()?;
+    loop {
+        let entry = match dir_entries.next() {
+            Some(Ok(e)) => e,
+            Some(Err(_)) => {
+                complete = false;
+                break;
+            }
+            None => break,
+        };
         let entry_type = match entry.file_type() {
             Ok(md) => {
-                if md.is_dir() {
+                if md.is_symlink() {
+                    FsEntryType::Symlink
+                } else if md.is_dir() {
                     FsEntryType::Directory
                 } else {
                     FsEntryType::File
                 }
             }
-            _ => FsEntryType::Error,
+            _ => {
+                complete = false;
+                FsEntryType::Error
+            }
         };
+        entries.push((entry.file_name(), entry_type));
+    }
+    Some((entries, complete))
+}
 
+/// Returns the type of the target of a symbolic link.
+fn symlink_target_type(link_path: &path::Path) -> FsEntryType {
+    match fs::metadata(link_path) {
+        Ok(md) if md.is_dir() => FsEntryType::Directory,
+        Ok(_) => FsEntryType::File,
+        // The link is dangling.
+        Err(_) => FsEntryType::Error,
+    }
+}
+
+/// Lists the next directory in the queue, adding its subdirectories
+/// to the queue.
+///
+/// If `visited` is given, it holds the directories searched so far,
+/// and symbolic links to directories are followed unless they lead to
+/// one of them.
+fn directory_bfs(
+    queue: &mut VecDeque<DirectoryQueueEntry>,
+    fs_config: &config::FsConfig,
+    cache: Option<&mut fs_cache::FsCache>,
+    mut visited: Option<&mut HashSet<DirectoryId>>,
+) -> Vec<core::CompletionBox> {
+    let queue_entry = queue.pop_front();
+    if queue_entry.is_none() {
+        return vec![];
+    }
+    let DirectoryQueueEntry(dir_path, depth, dir_hidden) = queue_entry.unwrap();
+    let mut completions: Vec<core::CompletionBox> = vec![];
+    let entries = match read_directory(&dir_path, cache) {
+        Some(e) => e,
+        None => return vec![],
+    };
+    for (file_name, entry_type) in entries {
         let here_prefix = path::Path::new("./");
-        let mut path = dir_path.join(entry.file_name());
+        let mut path = dir_path.join(&file_name);
         if path.starts_with(here_prefix) {
             path = path.strip_prefix(here_prefix).unwrap().to_path_buf();
         }
-        if let Some(s) = path.file_name().and_then(|f| f.to_str()) {
-            if s.starts_with(".") {
-                continue;
+        let hidden = dir_hidden || file_name.to_string_lossy().starts_with('.');
+
+        let symlink = entry_type == FsEntryType::Symlink;
+        let entry_type = match entry_type {
+            FsEntryType::Symlink if visited.is_some() => symlink_target_type(&path),
+            FsEntryType::Symlink => FsEntryType::File,
+            t => t,
+        };
+
+        // Hidden entries are always reported, so that the user can
+        // toggle their visibility without rescanning, but we only
+        // traverse hidden directories once they are shown.
+        if entry_type == FsEntryType::Directory
+            && fs_config.max_depth.is_none_or(|d| depth < d)
+            && (!hidden || fs_config.show_hidden)
+        {
+            let not_visited = match visited.as_deref_mut() {
+                Some(v) => directory_id(&path).is_some_and(|id| v.insert(id)),
+                None => true,
+            };
+            if not_visited {
+                queue.push_back(DirectoryQueueEntry(path.clone(), depth + 1, hidden));
             }
         }
 
-        if entry_type == FsEntryType::Directory && depth < DIRECTORY_DEPTH_LIMIT {
-            queue.push_back(DirectoryQueueEntry(path.clone(), depth + 1));
+        // In directory-only mode, we still descend into all directories
+        // above, but files are never offered as completions.
+        if fs_config.dirs_only && entry_type != FsEntryType::Directory {
+            continue;
         }
 
         completions.push(Box::new(FsCompletion {
             relative_path: path,
-            entry_type: entry_type,
+            entry_type,
+            symlink,
+            hidden,
+            metadata: OnceLock::new(),
         }));
     }
     completions
 }
 
+#[cfg(unix)]
+#[test]
+fn test_display_spans() {
+    use crate::core::Completion;
+
+    let completion = |relative_path: &str, entry_type: FsEntryType| FsCompletion {
+        relative_path: path::PathBuf::from(relative_path),
+        entry_type,
+        symlink: false,
+        hidden: false,
+        metadata: OnceLock::new(),
+    };
+    let dimmed = core::Style::colored(core::SpanColor::Dimmed);
+    let directory = core::Style::colored(core::SpanColor::Kind(core::CompletionKind::Directory));
+    let separator = path::MAIN_SEPARATOR.to_string();
+    let nested = ["src", "ui", "mod.rs"].join(&separator);
+    assert_eq!(
+        completion(&nested, FsEntryType::File).display_spans(),
+        vec![
+            core::Span::styled(format!("src{}ui{}", separator, separator), dimmed),
+            core::Span::plain("mod.rs"),
+        ]
+    );
+    assert_eq!(
+        completion("src", FsEntryType::Directory).display_spans(),
+        vec![core::Span::styled("src", directory)]
+    );
+}
+
+#[test]
+#[cfg(unix)]
+fn test_follow_symlinks() {
+    let dir = std::env::temp_dir().join(format!("completers-symlinks-{}", std::process::id()));
+    fs::create_dir_all(dir.join("a")).unwrap();
+    std::os::unix::fs::symlink("..", dir.join("a").join("up")).unwrap();
+    let fs_config = config::FsConfig {
+        max_depth: None,
+        cache: false,
+        follow_symlinks: true,
+        ..Default::default()
+    };
+
+    let mut queue = VecDeque::new();
+    queue.push_back(DirectoryQueueEntry(dir.clone(), 0, false));
+    let mut visited: HashSet<DirectoryId> = directory_id(&dir).into_iter().collect();
+    let mut completions = vec![];
+    while !queue.is_empty() {
+        completions.extend(directory_bfs(
+            &mut queue,
+            &fs_config,
+            None,
+            Some(&mut visited),
+        ));
+    }
+    let mut entries: Vec<(path::PathBuf, bool)> = completions
+        .iter()
+        .map(|c| {
+            let c = c.as_any().downcast_ref::<FsCompletion>().unwrap();
+            (c.relative_path.clone(), c.symlink)
+        })
+        .collect();
+    entries.sort();
+    // The link leads back to the searched directory, so it is listed
+    // but not searched.
+    assert_eq!(
+        entries,
+        vec![(dir.join("a"), false), (dir.join("a").join("up"), true)]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
 fn fetching_thread_routine(
     dir_path: path::PathBuf,
+    fs_config: config::FsConfig,
     request_recv: mpsc::Receiver<()>,
     response_send: mpsc::Sender<Option<Vec<core::CompletionBox>>>,
 ) {
+    let mut visited: Option<HashSet<DirectoryId>> = if fs_config.follow_symlinks {
+        Some(directory_id(&dir_path).into_iter().collect())
+    } else {
+        None
+    };
     let mut dir_queue: VecDeque<DirectoryQueueEntry> = VecDeque::new();
-    dir_queue.push_back(DirectoryQueueEntry(dir_path, 0));
+    dir_queue.push_back(DirectoryQueueEntry(dir_path, 0, false));
+    let mut cache = if fs_config.cache {
+        Some(fs_cache::FsCache::open())
+    } else {
+        None
+    };
     let mut completions = Vec::new();
+    let mut candidate_count = 0;
     while !dir_queue.is_empty() {
-        completions.extend(directory_bfs(&mut dir_queue));
+        let new_completions =
+            directory_bfs(&mut dir_queue, &fs_config, cache.as_mut(), visited.as_mut());
+        candidate_count += new_completions.len();
+        completions.extend(new_completions);
+        if candidate_count >= fs_config.max_candidates {
+            warn!(
+                "Stopped searching subdirectories after {} entries",
+                candidate_count
+            );
+            dir_queue.clear();
+        }
         match request_recv.try_recv() {
             Result::Ok(_) => {
                 response_send.send(Some(completions)).unwrap();
@@ -126,10 +448,16 @@ fn fetching_thread_routine(
             }
             Result::Err(mpsc::TryRecvError::Empty) => {}
             Result::Err(mpsc::TryRecvError::Disconnected) => {
-                return;
+                break;
             }
         }
     }
+    if let Some(ref mut c) = cache {
+        c.save();
+    }
+    if !dir_queue.is_empty() {
+        return;
+    }
     match request_recv.recv() {
         Result::Ok(_) => {
             response_send.send(Some(completions)).unwrap();
@@ -138,13 +466,8 @@ fn fetching_thread_routine(
             return;
         }
     }
-    match request_recv.recv() {
-        Result::Ok(_) => {
-            response_send.send(None).unwrap();
-        }
-        Result::Err(_) => {
-            return;
-        }
+    if request_recv.recv().is_ok() {
+        response_send.send(None).unwrap();
     }
 }
 
@@ -168,25 +491,28 @@ fn fetching_thread_routine(
 /// return to this level.
 pub struct FsCompleter {
     dir_path: path::PathBuf,
+    fs_config: config::FsConfig,
     fetching_thread: Option<BgThread>,
 }
 
 impl FsCompleter {
-    pub fn new(dir_path: path::PathBuf) -> FsCompleter {
+    pub fn new(dir_path: path::PathBuf, fs_config: config::FsConfig) -> FsCompleter {
         let (request_send, request_recv) = mpsc::channel::<()>();
         let (response_send, response_recv) = mpsc::channel::<Option<Vec<core::CompletionBox>>>();
         let dir_path_clone = dir_path.clone();
+        let fs_config_clone = fs_config.clone();
         let thread = thread::spawn(move || {
-            fetching_thread_routine(dir_path_clone, request_recv, response_send)
+            fetching_thread_routine(dir_path_clone, fs_config_clone, request_recv, response_send)
         });
         let bg_thread = BgThread {
-            thread: thread,
-            request_send: request_send,
-            response_recv: response_recv,
+            thread,
+            request_send,
+            response_recv,
         };
 
         FsCompleter {
-            dir_path: dir_path,
+            dir_path,
+            fs_config,
             fetching_thread: Some(bg_thread),
         }
     }
@@ -194,14 +520,23 @@ impl FsCompleter {
 
 impl core::Completer for FsCompleter {
     fn name(&self) -> String {
-        "fs".to_owned()
+        if self.fs_config.dirs_only {
+            "dir".to_owned()
+        } else {
+            "fs".to_owned()
+        }
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.dir_path.display().to_string())
+    }
+
+    fn path_separator(&self) -> Option<char> {
+        Some(path::MAIN_SEPARATOR)
     }
 
     fn fetching_completions_finished(&self) -> bool {
-        match self.fetching_thread {
-            Some(_) => false,
-            None => true,
-        }
+        self.fetching_thread.is_none()
     }
 
     fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
@@ -225,13 +560,45 @@ impl core::Completer for FsCompleter {
         fetched_completions
     }
 
+    fn stop(&mut self) {
+        // Dropping the thread's channels makes it stop.
+        self.fetching_thread = None;
+    }
+
+    fn refresh(&mut self) -> bool {
+        // Dropping the old thread's channels makes it stop.
+        *self = FsCompleter::new(self.dir_path.clone(), self.fs_config.clone());
+        true
+    }
+
+    fn show_hidden(&mut self, shown: bool) -> bool {
+        // Hidden directories keep being searched once they were shown,
+        // as their entries are reported as hidden anyway.
+        if !shown || self.fs_config.show_hidden {
+            return false;
+        }
+        self.fs_config.show_hidden = true;
+        true
+    }
+
     fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
         let completion_any = completion.as_any();
         let fs_completion = completion_any.downcast_ref::<FsCompletion>().unwrap();
         match fs_completion.entry_type {
             FsEntryType::Directory => Some(Box::new(FsCompleter::new(
                 fs_completion.relative_path.clone(),
+                self.fs_config.clone(),
             ))),
+            // Files, including the targets of symbolic links, are viewed.
+            FsEntryType::File | FsEntryType::Symlink
+                if self.fs_config.view_lines > 0 && fs_completion.relative_path.is_file() =>
+            {
+                Some(Box::new(viewer::FileViewer::new(
+                    fs_completion.relative_path.clone(),
+                    self.fs_config.view_lines,
+                    self.fs_config.highlight,
+                )))
+            }
             _ => None,
         }
     }
@@ -239,13 +606,16 @@ impl core::Completer for FsCompleter {
     fn ascend(&self) -> Option<Box<dyn core::Completer>> {
         let current_path = self.dir_path.clone();
         if current_path.ends_with(path::Path::new(".")) {
-            Some(Box::new(FsCompleter::new(path::PathBuf::from(".."))))
+            Some(Box::new(FsCompleter::new(
+                path::PathBuf::from(".."),
+                self.fs_config.clone(),
+            )))
         } else if current_path.ends_with(path::Path::new("..")) {
             let mut new_path = current_path.join(path::Path::new(".."));
             if new_path.canonicalize().unwrap() == path::Path::new("/") {
                 new_path = path::PathBuf::from("/");
             }
-            Some(Box::new(FsCompleter::new(new_path)))
+            Some(Box::new(FsCompleter::new(new_path, self.fs_config.clone())))
         } else {
             None
         }
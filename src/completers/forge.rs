@@ -0,0 +1,369 @@
+//! Defines a completer for the pull requests and issues of the GitHub
+//! or GitLab project of the current repository.
+//!
+//! They are listed with the command line clients of the forges, `gh`
+//! and `glab`, which query the network. The listings are run in a
+//! background thread with a short timeout, and cached for a while, so
+//! that completing several numbers in a row doesn't query the forge
+//! each time.
+
+use std::any;
+use std::env;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use serde::Deserialize;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Forge {
+    GitHub,
+    GitLab,
+}
+
+impl Forge {
+    /// Returns the forge whose client is the given command, if any.
+    fn from_command(command: &str) -> Option<Forge> {
+        match command {
+            "gh" => Some(Forge::GitHub),
+            "glab" => Some(Forge::GitLab),
+            _ => None,
+        }
+    }
+
+    /// Returns the forge hosting a remote repository. Repositories not
+    /// on a GitLab host are assumed to be on GitHub.
+    fn from_remote_url(url: &str) -> Forge {
+        if url.contains("gitlab") {
+            Forge::GitLab
+        } else {
+            Forge::GitHub
+        }
+    }
+
+    fn client(self) -> &'static str {
+        match self {
+            Forge::GitHub => "gh",
+            Forge::GitLab => "glab",
+        }
+    }
+
+    /// Returns the command of the client listing items of a type.
+    fn list_command(self, item_type: ItemType, limit: usize) -> Command {
+        let mut command = Command::new(self.client());
+        match (self, item_type) {
+            (Forge::GitHub, ItemType::PullRequest) => command.arg("pr"),
+            (Forge::GitLab, ItemType::PullRequest) => command.arg("mr"),
+            (_, ItemType::Issue) => command.arg("issue"),
+        };
+        match self {
+            Forge::GitHub => command.args(["list", "--json", "number,title,author", "--limit"]),
+            Forge::GitLab => command.args(["list", "--output", "json", "--per-page"]),
+        };
+        command.arg(limit.to_string());
+        command
+    }
+}
+
+#[test]
+fn test_forge() {
+    assert_eq!(
+        Forge::from_remote_url("git@gitlab.example.com:group/project.git"),
+        Forge::GitLab
+    );
+    assert_eq!(
+        Forge::from_remote_url("https://github.com/saf/completers"),
+        Forge::GitHub
+    );
+    assert_eq!(Forge::from_command("glab"), Some(Forge::GitLab));
+    assert_eq!(Forge::from_command("git"), None);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ItemType {
+    /// A pull request, or a merge request of GitLab.
+    PullRequest,
+    Issue,
+}
+
+/// The author of an item in the output of `gh` (`login`) or `glab`
+/// (`username`).
+#[derive(Deserialize)]
+struct Author {
+    #[serde(alias = "username")]
+    login: String,
+}
+
+/// An item in the output of `gh` (`number`) or `glab` (`iid`, the
+/// number within the project).
+#[derive(Deserialize)]
+struct Item {
+    #[serde(alias = "iid")]
+    number: u64,
+    title: String,
+    author: Option<Author>,
+}
+
+#[derive(Debug, PartialEq)]
+struct ForgeCompletion {
+    item_type: ItemType,
+    number: u64,
+    title: String,
+    author: Option<String>,
+
+    /// Indicates if the number is inserted with a `#` prefix, as in
+    /// commit messages.
+    with_hash: bool,
+}
+
+impl core::Completion for ForgeCompletion {
+    fn result_string(&self) -> String {
+        if self.with_hash {
+            format!("#{}", self.number)
+        } else {
+            self.number.to_string()
+        }
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        vec![
+            core::Span::styled(
+                format!("#{} ", self.number),
+                core::Style::colored(core::SpanColor::Kind(self.kind())),
+            ),
+            core::Span::plain(self.title.as_str()),
+        ]
+    }
+
+    fn search_string(&self) -> String {
+        format!("#{} {}", self.number, self.title)
+    }
+
+    fn description(&self) -> Option<String> {
+        self.author.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        match self.item_type {
+            ItemType::PullRequest => core::CompletionKind::PullRequest,
+            ItemType::Issue => core::CompletionKind::Issue,
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the JSON list of items printed by `gh` or `glab`.
+fn parse_items(
+    json: &str,
+    item_type: ItemType,
+    with_hash: bool,
+) -> Result<Vec<ForgeCompletion>, String> {
+    let items: Vec<Item> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    Result::Ok(
+        items
+            .into_iter()
+            .map(|i| ForgeCompletion {
+                item_type,
+                number: i.number,
+                title: i.title,
+                author: i.author.map(|a| a.login),
+                with_hash,
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn test_parse_items() {
+    let gh = r#"[{"author":{"id":"1","is_bot":false,"login":"octocat","name":"Mona"},
+                  "number":42,"title":"Fix the build"}]"#;
+    assert_eq!(
+        parse_items(gh, ItemType::PullRequest, false),
+        Result::Ok(vec![ForgeCompletion {
+            item_type: ItemType::PullRequest,
+            number: 42,
+            title: "Fix the build".to_owned(),
+            author: Some("octocat".to_owned()),
+            with_hash: false,
+        }])
+    );
+    let glab = r#"[{"id":1234,"iid":7,"title":"Crash on start","state":"opened",
+                    "author":{"id":5,"username":"jdoe","name":"J. Doe"}}]"#;
+    assert_eq!(
+        parse_items(glab, ItemType::Issue, true),
+        Result::Ok(vec![ForgeCompletion {
+            item_type: ItemType::Issue,
+            number: 7,
+            title: "Crash on start".to_owned(),
+            author: Some("jdoe".to_owned()),
+            with_hash: true,
+        }])
+    );
+    assert!(parse_items("not json", ItemType::Issue, false).is_err());
+}
+
+/// The settings of a listing, passed to the background thread.
+struct Listing {
+    forge: Option<Forge>,
+    item_types: Vec<ItemType>,
+    with_hash: bool,
+    use_cache: bool,
+    config: config::ForgeConfig,
+}
+
+impl Listing {
+    /// Lists the items, returning the completions and the errors.
+    fn run(&self) -> (Vec<ForgeCompletion>, Vec<String>) {
+        let mut errors = vec![];
+        let forge = match self.forge {
+            Some(f) => f,
+            None => match subprocess::run(
+                Command::new("git").args(["remote", "get-url", "origin"]),
+                self.config.timeout(),
+            ) {
+                Result::Ok(url) => Forge::from_remote_url(&url),
+                Result::Err(e) => return (vec![], vec![e]),
+            },
+        };
+        let mut completions = vec![];
+        for item_type in self.item_types.iter() {
+            let mut command = forge.list_command(*item_type, self.config.limit);
+            let cache_path = subprocess::cache_path("forge", &command);
+            let max_age = time::Duration::from_secs(self.config.cache_secs);
+            let cached = match cache_path {
+                Some(ref p) if self.use_cache => subprocess::read_cache(p, max_age),
+                _ => None,
+            };
+            let output = match cached {
+                Some(o) => Result::Ok(o),
+                None => subprocess::run(&mut command, self.config.timeout()),
+            };
+            let parsed = output.and_then(|o| {
+                let items = parse_items(&o, *item_type, self.with_hash)?;
+                if let Some(ref p) = cache_path {
+                    subprocess::write_cache(p, &o);
+                }
+                Result::Ok(items)
+            });
+            match parsed {
+                Result::Ok(items) => completions.extend(items),
+                Result::Err(e) => errors.push(e),
+            }
+        }
+        (completions, errors)
+    }
+}
+
+/// A completer for the pull requests and issues of the project of the
+/// current repository.
+pub struct ForgeCompleter {
+    forge: Option<Forge>,
+    item_types: Vec<ItemType>,
+    with_hash: bool,
+    config: config::ForgeConfig,
+
+    /// Lists the items in the background.
+    listing: subprocess::Background<(Vec<ForgeCompletion>, Vec<String>)>,
+    errors: Vec<String>,
+}
+
+impl ForgeCompleter {
+    /// Creates a completer of the given types of items, for a command
+    /// line starting with `command`, which is the client of a forge,
+    /// or of the forge of the `origin` remote otherwise. The numbers
+    /// are inserted with a `#` prefix if `with_hash` is set.
+    pub fn new(
+        command: Option<&str>,
+        item_types: Vec<ItemType>,
+        with_hash: bool,
+        config: config::ForgeConfig,
+    ) -> ForgeCompleter {
+        let mut completer = ForgeCompleter {
+            forge: command.and_then(Forge::from_command),
+            item_types,
+            with_hash,
+            config,
+            listing: subprocess::Background::idle(),
+            errors: vec![],
+        };
+        completer.start(true);
+        completer
+    }
+
+    fn start(&mut self, use_cache: bool) {
+        let listing = Listing {
+            forge: self.forge,
+            item_types: self.item_types.clone(),
+            with_hash: self.with_hash,
+            use_cache,
+            config: self.config.clone(),
+        };
+        self.listing = subprocess::Background::run(move || listing.run());
+    }
+}
+
+impl core::Completer for ForgeCompleter {
+    fn name(&self) -> String {
+        match self.item_types.as_slice() {
+            [ItemType::PullRequest] => "pr",
+            [ItemType::Issue] => "issue",
+            _ => "#",
+        }
+        .to_owned()
+    }
+
+    /// Indicates if the client of the forge, or of any forge if it is
+    /// not known yet, is installed.
+    fn is_applicable(&self) -> bool {
+        let dirs: Vec<path::PathBuf> = match env::var_os("PATH") {
+            Some(p) => env::split_paths(&p).collect(),
+            None => return false,
+        };
+        let forges = match self.forge {
+            Some(f) => vec![f],
+            None => vec![Forge::GitHub, Forge::GitLab],
+        };
+        forges
+            .iter()
+            .any(|f| dirs.iter().any(|d| d.join(f.client()).is_file()))
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let failed = || {
+            (
+                vec![],
+                vec!["listing pull requests and issues failed".to_owned()],
+            )
+        };
+        let (completions, errors) = match self.listing.poll(failed) {
+            Some(result) => result,
+            None => return vec![],
+        };
+        self.errors.extend(errors);
+        completions
+            .into_iter()
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    /// Lists the items anew, bypassing the cache.
+    fn refresh(&mut self) -> bool {
+        self.start(false);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
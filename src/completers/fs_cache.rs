@@ -0,0 +1,441 @@
+//! Defines the on-disk cache of directory listings used by the file
+//! system completer.
+//!
+//! The listing of a directory is cached along with the modification
+//! time of the directory, and reused only as long as the directory
+//! still has the same modification time. Adding, removing or renaming
+//! an entry changes the modification time of its parent directory, so
+//! a directory tree is rescanned only where it changed.
+//!
+//! The cache is stored in a text file with one record per line:
+//! a directory record `D<TAB><seconds>.<nanoseconds><TAB><path>` is
+//! followed by records `d<TAB><name>`, `l<TAB><name>` or `f<TAB><name>`
+//! for its subdirectories, symbolic links and other entries. Tabs,
+//! newlines and backslashes in paths and names are escaped with
+//! backslashes.
+//!
+//! When the daemon is running, the cache is kept in its memory instead,
+//! and listings are got from it and stored in it.
+
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path;
+use std::sync::atomic;
+use std::time;
+
+use log::warn;
+
+#[cfg(unix)]
+use crate::daemon;
+
+/// The first line of a cache file, identifying its format.
+const CACHE_HEADER: &str = "completers-fs-cache 2";
+
+/// The maximum number of directories in the cache file. Directories
+/// listed in the current session are kept first.
+const MAX_CACHED_DIRECTORIES: usize = 100_000;
+
+/// Directories modified more recently than this are not cached, since
+/// they could be modified again without a change of the modification
+/// time, if its resolution is low.
+const MIN_CACHED_AGE: time::Duration = time::Duration::from_secs(2);
+
+/// A counter making the names of temporary files unique within the
+/// process.
+static TEMP_FILE_COUNTER: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+
+/// An entry of a cached directory listing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Entry {
+    pub name: String,
+    pub is_dir: bool,
+
+    /// Indicates if the entry is a symbolic link. The type of its
+    /// target is not cached, since it may change without a change of
+    /// the directory.
+    pub is_symlink: bool,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct CachedDirectory {
+    modified: time::Duration,
+    entries: Vec<Entry>,
+
+    /// Indicates if the directory was listed in this session.
+    used: bool,
+}
+
+/// The cache of directory listings, keyed by absolute paths.
+pub struct FsCache {
+    cache_path: Option<path::PathBuf>,
+    directories: HashMap<path::PathBuf, CachedDirectory>,
+    base_dir: path::PathBuf,
+    changed: bool,
+
+    /// The connection to the daemon keeping the cache, if it is running.
+    #[cfg(unix)]
+    daemon: Option<daemon::Client>,
+}
+
+/// Returns the path of the cache file.
+fn cache_path() -> Option<path::PathBuf> {
+    let cache_dir = env::var_os("XDG_CACHE_HOME")
+        .map(path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".cache")))?;
+    Some(cache_dir.join("completers").join("fs-cache"))
+}
+
+/// Returns the modification time of a directory as a duration since
+/// the Unix epoch, if the directory is old enough to be cached.
+fn cacheable_modification_time(dir_path: &path::Path) -> Option<time::Duration> {
+    let modified = fs::metadata(dir_path).and_then(|m| m.modified()).ok()?;
+    let age = time::SystemTime::now().duration_since(modified).ok()?;
+    if age < MIN_CACHED_AGE {
+        return None;
+    }
+    modified.duration_since(time::UNIX_EPOCH).ok()
+}
+
+/// Escapes tabs, newlines and backslashes, so that the text can be a
+/// field of a record.
+pub fn escape(s: &str) -> String {
+    s.replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+}
+
+/// Reverses `escape`, keeping the characters following other
+/// backslashes as they are.
+pub fn unescape(s: &str) -> String {
+    let mut result = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('t') => result.push('\t'),
+                Some('n') => result.push('\n'),
+                Some(c) => result.push(c),
+                None => {}
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+#[test]
+fn test_escape() {
+    for s in &["", "a b", "tab\there", "new\nline", "back\\slash\\t"] {
+        assert_eq!(unescape(&escape(s)), *s);
+        assert!(!escape(s).contains(['\t', '\n']));
+    }
+}
+
+/// Parses the record of an entry of a directory, e.g. `d<TAB><name>`.
+pub fn parse_entry(record: &str) -> Option<Entry> {
+    let (kind, name) = record.split_once('\t')?;
+    match kind {
+        "d" | "f" | "l" => Some(Entry {
+            name: unescape(name),
+            is_dir: kind == "d",
+            is_symlink: kind == "l",
+        }),
+        _ => None,
+    }
+}
+
+/// Writes the records of entries of a directory.
+pub fn write_entries<W: Write>(out: &mut W, entries: &[Entry]) -> io::Result<()> {
+    for entry in entries {
+        let kind = if entry.is_symlink {
+            "l"
+        } else if entry.is_dir {
+            "d"
+        } else {
+            "f"
+        };
+        writeln!(out, "{}\t{}", kind, escape(&entry.name))?;
+    }
+    Result::Ok(())
+}
+
+/// Parses the records of a cache file, following the header.
+fn parse<R: BufRead>(reader: R) -> HashMap<path::PathBuf, CachedDirectory> {
+    let mut directories = HashMap::new();
+    let mut current: Option<(path::PathBuf, CachedDirectory)> = None;
+    for line in reader.lines() {
+        let line = match line {
+            Result::Ok(l) => l,
+            Result::Err(_) => break,
+        };
+        let (kind, rest) = match line.split_once('\t') {
+            Some(r) => r,
+            None => continue,
+        };
+        match kind {
+            "D" => {
+                if let Some((p, d)) = current.take() {
+                    directories.insert(p, d);
+                }
+                let (modified, dir_path) = match rest.split_once('\t') {
+                    Some(r) => r,
+                    None => continue,
+                };
+                // A directory with an invalid modification time, e.g.
+                // in a corrupt file, is dropped, so that it is rescanned.
+                let modified = match modified.split_once('.') {
+                    Some((secs, nanos)) => match (secs.parse(), nanos.parse::<u32>()) {
+                        (Result::Ok(s), Result::Ok(n)) if n < 1_000_000_000 => {
+                            time::Duration::new(s, n)
+                        }
+                        _ => continue,
+                    },
+                    None => continue,
+                };
+                current = Some((
+                    path::PathBuf::from(unescape(dir_path)),
+                    CachedDirectory {
+                        modified,
+                        entries: vec![],
+                        used: false,
+                    },
+                ));
+            }
+            _ => {
+                if let (Some((_, d)), Some(entry)) = (current.as_mut(), parse_entry(&line)) {
+                    d.entries.push(entry);
+                }
+            }
+        }
+    }
+    if let Some((p, d)) = current {
+        directories.insert(p, d);
+    }
+    directories
+}
+
+impl FsCache {
+    /// Creates an empty cache, which is not stored on disk.
+    pub fn new() -> FsCache {
+        FsCache {
+            cache_path: None,
+            directories: HashMap::new(),
+            base_dir: env::current_dir().unwrap_or_default(),
+            changed: false,
+            #[cfg(unix)]
+            daemon: None,
+        }
+    }
+
+    /// Connects to the daemon if it is running, or loads the cache from
+    /// the cache file otherwise.
+    pub fn open() -> FsCache {
+        #[cfg(unix)]
+        {
+            if let Some(client) = daemon::Client::connect() {
+                let mut cache = FsCache::new();
+                cache.daemon = Some(client);
+                return cache;
+            }
+        }
+        FsCache::load()
+    }
+
+    /// Loads the cache from the cache file.
+    ///
+    /// A missing or invalid cache file results in an empty cache.
+    pub fn load() -> FsCache {
+        let mut cache = FsCache::new();
+        cache.cache_path = cache_path();
+        let file = match cache.cache_path.as_ref().map(fs::File::open) {
+            Some(Result::Ok(f)) => f,
+            _ => return cache,
+        };
+        let mut reader = io::BufReader::new(file);
+        let mut header = String::new();
+        if reader.read_line(&mut header).is_ok() && header.trim_end() == CACHE_HEADER {
+            cache.directories = parse(reader);
+        }
+        cache
+    }
+
+    fn key(&self, dir_path: &path::Path) -> path::PathBuf {
+        self.base_dir.join(dir_path)
+    }
+
+    /// Returns the cached listing of the directory, if it is still up
+    /// to date.
+    pub fn get(&mut self, dir_path: &path::Path) -> Option<Vec<Entry>> {
+        let key = self.key(dir_path);
+        #[cfg(unix)]
+        {
+            if let Some(ref mut client) = self.daemon {
+                match client.get(&key) {
+                    Result::Ok(entries) => return entries,
+                    Result::Err(e) => {
+                        warn!("Lost the connection to the daemon: {}", e);
+                        self.daemon = None;
+                    }
+                }
+            }
+        }
+        let cached = self.directories.get_mut(&key)?;
+        if cacheable_modification_time(dir_path) != Some(cached.modified) {
+            return None;
+        }
+        cached.used = true;
+        Some(cached.entries.clone())
+    }
+
+    /// Stores the listing of the directory.
+    pub fn insert(&mut self, dir_path: &path::Path, entries: Vec<Entry>) {
+        #[cfg(unix)]
+        {
+            let key = self.key(dir_path);
+            if let Some(ref mut client) = self.daemon {
+                match client.put(&key, &entries) {
+                    Result::Ok(()) => return,
+                    Result::Err(e) => {
+                        warn!("Lost the connection to the daemon: {}", e);
+                        self.daemon = None;
+                    }
+                }
+            }
+        }
+        if let Some(modified) = cacheable_modification_time(dir_path) {
+            let key = self.key(dir_path);
+            self.directories.insert(
+                key,
+                CachedDirectory {
+                    modified,
+                    entries,
+                    used: true,
+                },
+            );
+            self.changed = true;
+        }
+    }
+
+    fn write<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        writeln!(out, "{}", CACHE_HEADER)?;
+        let mut directories: Vec<(&path::PathBuf, &CachedDirectory)> =
+            self.directories.iter().collect();
+        if directories.len() > MAX_CACHED_DIRECTORIES {
+            directories.sort_by_key(|(_, d)| !d.used);
+            directories.truncate(MAX_CACHED_DIRECTORIES);
+        }
+        for (dir_path, directory) in directories {
+            let dir_path = match dir_path.to_str() {
+                Some(p) => p,
+                None => continue,
+            };
+            writeln!(
+                out,
+                "D\t{}.{}\t{}",
+                directory.modified.as_secs(),
+                directory.modified.subsec_nanos(),
+                escape(dir_path)
+            )?;
+            write_entries(out, &directory.entries)?;
+        }
+        Result::Ok(())
+    }
+
+    /// Writes the cache to the cache file, if anything changed.
+    ///
+    /// The file is replaced atomically, so that concurrent sessions
+    /// never read a partially written cache.
+    pub fn save(&mut self) {
+        let cache_path = match self.cache_path {
+            Some(ref p) if self.changed => p,
+            _ => return,
+        };
+        let temp_path = cache_path.with_extension(format!(
+            "{}.{}.tmp",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, atomic::Ordering::Relaxed)
+        ));
+        let result = cache_path
+            .parent()
+            .map_or(Result::Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::File::create(&temp_path))
+            .and_then(|f| {
+                let mut out = io::BufWriter::new(f);
+                self.write(&mut out)?;
+                out.flush()
+            })
+            .and_then(|_| fs::rename(&temp_path, cache_path));
+        match result {
+            Result::Ok(()) => self.changed = false,
+            Result::Err(e) => {
+                warn!("Failed to save the file system cache: {}", e);
+                let _ = fs::remove_file(&temp_path);
+            }
+        }
+    }
+}
+
+impl Default for FsCache {
+    fn default() -> Self {
+        FsCache::new()
+    }
+}
+
+#[test]
+fn test_fs_cache() {
+    let dir = env::temp_dir().join(format!("completers-fs-cache-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let entries = vec![
+        Entry {
+            name: "sub\tdir".to_owned(),
+            is_dir: true,
+            is_symlink: false,
+        },
+        Entry {
+            name: "file".to_owned(),
+            is_dir: false,
+            is_symlink: false,
+        },
+        Entry {
+            name: "link".to_owned(),
+            is_dir: false,
+            is_symlink: true,
+        },
+    ];
+
+    let mut cache = FsCache::new();
+    // The directory was just created, so it is too new to be cached.
+    cache.insert(&dir, entries.clone());
+    assert_eq!(cache.get(&dir), None);
+
+    let modified = time::Duration::from_secs(1_000_000_000);
+    cache.directories.insert(
+        cache.key(&dir),
+        CachedDirectory {
+            modified,
+            entries: entries.clone(),
+            used: true,
+        },
+    );
+    // The modification time does not match.
+    assert_eq!(cache.get(&dir), None);
+
+    let mut serialized = vec![];
+    cache.write(&mut serialized).unwrap();
+    let mut lines = serialized.splitn(2, |b| *b == b'\n');
+    assert_eq!(lines.next().unwrap(), CACHE_HEADER.as_bytes());
+    let parsed = parse(lines.next().unwrap());
+    let cached = &parsed[&cache.key(&dir)];
+    assert_eq!(cached.modified, modified);
+    assert_eq!(cached.entries, entries);
+
+    let corrupt = format!("D\t{}.1000000000\t/dir\nd\tsub\n", u64::MAX);
+    assert!(parse(corrupt.as_bytes()).is_empty());
+
+    fs::remove_dir(&dir).unwrap();
+}
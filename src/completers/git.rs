@@ -1,12 +1,19 @@
-//! Defines a completer for Git branches and commits.
+//! Defines completers for Git branches and commits, and for the
+//! worktrees and submodules of a repository.
 
 use std::any;
+use std::env;
+use std::fs;
+use std::path;
 use std::process::Command;
+use std::time;
 
 use itertools::Itertools;
-use termion::color;
 
+use crate::completers::subprocess;
+use crate::config;
 use crate::core;
+use crate::version;
 
 #[derive(Debug, PartialEq)]
 enum GitBranchCompletionType {
@@ -19,6 +26,12 @@ enum GitBranchCompletionType {
 struct GitBranchCompletion {
     kind: GitBranchCompletionType,
     branch_name: String,
+
+    /// The date of the last commit, or of the creation of a tag.
+    date: String,
+
+    /// The author of the last commit, or the tagger of a tag.
+    author: String,
 }
 
 impl core::Completion for GitBranchCompletion {
@@ -26,21 +39,56 @@ impl core::Completion for GitBranchCompletion {
         self.branch_name.clone()
     }
 
-    fn display_string(&self) -> String {
-        let mut color_string = "".to_owned();
-        if self.kind == GitBranchCompletionType::Tag {
-            color_string = format!("{}", color::Fg(color::Yellow));
-        } else if self.kind == GitBranchCompletionType::Head {
-            color_string = format!("{}", color::Fg(color::Red));
-        } else if self.kind == GitBranchCompletionType::RemoteBranch {
-            color_string = format!("{}", color::Fg(color::LightBlack));
+    fn display_spans(&self) -> Vec<core::Span> {
+        let name_style = match self.kind {
+            GitBranchCompletionType::Head => core::Style {
+                bold: true,
+                ..core::Style::default()
+            },
+            GitBranchCompletionType::RemoteBranch => core::Style::default(),
+            _ => core::Style::colored(core::SpanColor::Kind(self.kind())),
+        };
+        match self.branch_name.find('/') {
+            // The name of the remote is dimmed, so that the name of the
+            // branch stands out.
+            Some(i) if self.kind == GitBranchCompletionType::RemoteBranch => vec![
+                core::Span::styled(
+                    &self.branch_name[..=i],
+                    core::Style::colored(core::SpanColor::Dimmed),
+                ),
+                core::Span::styled(&self.branch_name[i + 1..], name_style),
+            ],
+            _ => vec![core::Span::styled(self.branch_name.as_str(), name_style)],
+        }
+    }
+
+    fn description(&self) -> Option<String> {
+        if self.date.is_empty() && self.author.is_empty() {
+            None
+        } else {
+            Some(format!("{} {}", self.date, self.author))
+        }
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        match self.kind {
+            GitBranchCompletionType::Head => core::CompletionKind::Commit,
+            GitBranchCompletionType::Branch => core::CompletionKind::Branch,
+            GitBranchCompletionType::RemoteBranch => core::CompletionKind::RemoteBranch,
+            GitBranchCompletionType::Tag => core::CompletionKind::Tag,
         }
-        format!(
-            "{}{}{}",
-            color_string,
-            self.branch_name,
-            color::Fg(color::Reset)
-        )
+    }
+
+    fn group(&self) -> Option<core::CompletionGroup> {
+        Some(match self.kind {
+            GitBranchCompletionType::Head | GitBranchCompletionType::Branch => {
+                core::CompletionGroup::new(0, "branches")
+            }
+            GitBranchCompletionType::RemoteBranch => {
+                core::CompletionGroup::new(1, "remote branches")
+            }
+            GitBranchCompletionType::Tag => core::CompletionGroup::new(2, "tags"),
+        })
     }
 
     fn as_any(&self) -> &dyn any::Any {
@@ -48,11 +96,118 @@ impl core::Completion for GitBranchCompletion {
     }
 }
 
-pub struct GitBranchCompleter {}
+/// Returns the git directory of the repository containing the given
+/// directory, or the current one, and the common directory shared by
+/// its worktrees, which holds the refs.
+fn git_dirs(repo_dir: Option<&path::Path>) -> Option<(path::PathBuf, path::PathBuf)> {
+    let current_dir = env::current_dir().ok()?;
+    let start_dir = match repo_dir {
+        Some(d) => current_dir.join(d),
+        None => current_dir,
+    };
+    let dot_git = start_dir
+        .ancestors()
+        .map(|d| d.join(".git"))
+        .find(|p| p.exists())?;
+    let git_dir = if dot_git.is_dir() {
+        dot_git
+    } else {
+        // The `.git` file of a worktree points to its git directory.
+        let contents = fs::read_to_string(&dot_git).ok()?;
+        let git_dir = contents.strip_prefix("gitdir:")?.trim();
+        dot_git.parent()?.join(git_dir)
+    };
+    let common_dir = match fs::read_to_string(git_dir.join("commondir")) {
+        Result::Ok(c) => git_dir.join(c.trim()),
+        Result::Err(_) => git_dir.clone(),
+    };
+    Some((git_dir, common_dir))
+}
+
+/// Returns the files and directories whose modification times change
+/// whenever a ref changes: the HEAD, the packed refs and the directories
+/// of the loose refs, as git replaces ref files by renaming.
+fn ref_paths(repo_dir: Option<&path::Path>) -> Vec<path::PathBuf> {
+    let (git_dir, common_dir) = match git_dirs(repo_dir) {
+        Some(d) => d,
+        None => return vec![],
+    };
+    let mut paths = vec![git_dir.join("HEAD"), common_dir.join("packed-refs")];
+    let mut dirs = vec![common_dir.join("refs")];
+    while let Some(dir) = dirs.pop() {
+        if let Result::Ok(entries) = fs::read_dir(&dir) {
+            dirs.extend(
+                entries
+                    .filter_map(Result::ok)
+                    .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                    .map(|e| e.path()),
+            );
+        }
+        paths.push(dir);
+    }
+    paths
+}
+
+/// Runs git with the given arguments in the given repository, or in
+/// the current directory, returning its output, or None if it failed,
+/// in which case the error is added to `errors`.
+///
+/// The daemon reuses the output until any of the `watched` files
+/// changes, e.g. the refs a listing of branches depends on.
+fn run_git(
+    repo_dir: Option<&path::Path>,
+    args: &[&str],
+    watched: &[path::PathBuf],
+    timeout: time::Duration,
+    errors: &mut Vec<String>,
+) -> Option<String> {
+    let mut command = Command::new("git");
+    command.args(args);
+    if let Some(d) = repo_dir {
+        command.current_dir(d);
+    }
+    match subprocess::run_cached(&mut command, timeout, watched) {
+        Result::Ok(output) => Some(output),
+        Result::Err(e) => {
+            errors.push(e);
+            None
+        }
+    }
+}
+
+pub struct GitBranchCompleter {
+    /// The repository whose branches are listed, e.g. a submodule, or
+    /// None for the one of the current directory.
+    repo_dir: Option<path::PathBuf>,
+
+    /// The time after which git commands are killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl Default for GitBranchCompleter {
+    fn default() -> Self {
+        Self::new(config::SubprocessConfig::default().timeout())
+    }
+}
 
 impl GitBranchCompleter {
-    pub fn new() -> Self {
-        Self {}
+    pub fn new(timeout: time::Duration) -> Self {
+        Self {
+            repo_dir: None,
+            timeout,
+            errors: vec![],
+        }
+    }
+
+    /// Creates a completer of the branches of the repository in the
+    /// given directory.
+    fn in_repo(repo_dir: path::PathBuf, timeout: time::Duration) -> Self {
+        Self {
+            repo_dir: Some(repo_dir),
+            timeout,
+            errors: vec![],
+        }
     }
 }
 
@@ -61,25 +216,49 @@ impl core::Completer for GitBranchCompleter {
         "br".to_owned()
     }
 
+    fn location(&self) -> Option<String> {
+        self.repo_dir.as_ref().map(|d| d.display().to_string())
+    }
+
+    fn is_applicable(&self) -> bool {
+        let mut command = Command::new("git");
+        command.args(["rev-parse", "--is-inside-work-tree"]);
+        if let Some(ref d) = self.repo_dir {
+            command.current_dir(d);
+        }
+        subprocess::run(&mut command, self.timeout).is_ok()
+    }
+
     fn fetching_completions_finished(&self) -> bool {
         true
     }
 
     fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
         let mut fetched_completions: Vec<core::CompletionBox> = Vec::new();
-        let result = Command::new("git")
-            .args(&["for-each-ref", "--format=%(objecttype) %(refname:strip=2)"])
-            .output()
-            .expect("failed to run git-for-each-ref");
+        let repo_dir = self.repo_dir.as_deref();
+        let output = run_git(
+            repo_dir,
+            &[
+                "for-each-ref",
+                "--format=%(objecttype)%09%(refname:strip=2)%09%(creatordate:short)\
+                 %09%(authorname)%(taggername)",
+            ],
+            &ref_paths(repo_dir),
+            self.timeout,
+            &mut self.errors,
+        );
 
-        if result.status.success() {
+        if let Some(output) = output {
             fetched_completions.push(Box::new(GitBranchCompletion {
                 kind: GitBranchCompletionType::Head,
                 branch_name: "HEAD".to_owned(),
+                date: String::new(),
+                author: String::new(),
             }));
-            for line in String::from_utf8_lossy(&result.stdout).lines() {
-                let tuple = line.split_whitespace().next_tuple();
-                if let Some((ref_type, ref_name)) = tuple {
+            let mut tags = vec![];
+            for line in output.lines() {
+                let tuple = line.split('\t').next_tuple();
+                if let Some((ref_type, ref_name, date, author)) = tuple {
                     let compl_type = if ref_type == "commit" {
                         if ref_name.contains('/') {
                             GitBranchCompletionType::RemoteBranch
@@ -89,23 +268,52 @@ impl core::Completer for GitBranchCompleter {
                     } else {
                         GitBranchCompletionType::Tag
                     };
-                    fetched_completions.push(Box::new(GitBranchCompletion {
+                    let completion = GitBranchCompletion {
                         kind: compl_type,
                         branch_name: ref_name.to_owned(),
-                    }));
+                        date: date.to_owned(),
+                        author: author.to_owned(),
+                    };
+                    if completion.kind == GitBranchCompletionType::Tag {
+                        tags.push(completion);
+                    } else {
+                        fetched_completions.push(Box::new(completion));
+                    }
                 }
             }
+            // Tags come last, like in the output of git, but in the
+            // order of their versions rather than of their names.
+            tags.sort_by(|a, b| version::compare(&a.branch_name, &b.branch_name));
+            fetched_completions
+                .extend(tags.into_iter().map(|t| Box::new(t) as core::CompletionBox));
         }
         fetched_completions
     }
 
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
     fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
         let completion_any = completion.as_any();
         let branch_completion = completion_any
             .downcast_ref::<GitBranchCompletion>()
             .unwrap();
+        // The prefix keeps a tag from being mistaken for a branch with
+        // the same name.
+        let revision = if branch_completion.kind == GitBranchCompletionType::Tag {
+            format!("tags/{}", branch_completion.branch_name)
+        } else {
+            branch_completion.branch_name.clone()
+        };
         Some(Box::new(GitCommitCompleter::new(
-            branch_completion.branch_name.as_str(),
+            self.repo_dir.clone(),
+            revision,
+            self.timeout,
         )))
     }
 }
@@ -123,29 +331,44 @@ impl core::Completion for GitCommitCompletion {
     }
 
     fn display_string(&self) -> String {
-        format!(
-            "{:10} {:12} {:25} {}",
-            &self.hash, &self.date, &self.author, &self.subject
-        )
+        format!("{:10} {}", &self.hash, &self.subject)
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("{} {}", self.date, self.author))
     }
 
     fn search_string(&self) -> String {
         self.subject.clone()
     }
 
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Commit
+    }
+
     fn as_any(&self) -> &dyn any::Any {
         self
     }
 }
 
 struct GitCommitCompleter {
+    repo_dir: Option<path::PathBuf>,
     branch_name: String,
+    timeout: time::Duration,
+    errors: Vec<String>,
 }
 
 impl GitCommitCompleter {
-    fn new<B: Into<String>>(branch_name: B) -> GitCommitCompleter {
+    fn new<B: Into<String>>(
+        repo_dir: Option<path::PathBuf>,
+        branch_name: B,
+        timeout: time::Duration,
+    ) -> GitCommitCompleter {
         GitCommitCompleter {
+            repo_dir,
             branch_name: branch_name.into(),
+            timeout,
+            errors: vec![],
         }
     }
 }
@@ -155,24 +378,32 @@ impl core::Completer for GitCommitCompleter {
         "co".to_owned()
     }
 
+    fn location(&self) -> Option<String> {
+        Some(self.branch_name.clone())
+    }
+
     fn fetching_completions_finished(&self) -> bool {
         true
     }
 
     fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
         let mut fetched_completions: Vec<core::CompletionBox> = Vec::new();
-        let result = Command::new("git")
-            .args(&[
+        let repo_dir = self.repo_dir.as_deref();
+        let output = run_git(
+            repo_dir,
+            &[
                 "log",
                 "--format=%h%x09%ad%x09%an%x09%s",
                 "--date=short",
                 &self.branch_name,
-            ])
-            .output()
-            .expect("failed to run git-log");
+            ],
+            &ref_paths(repo_dir),
+            self.timeout,
+            &mut self.errors,
+        );
 
-        if result.status.success() {
-            for line in String::from_utf8_lossy(&result.stdout).lines() {
+        if let Some(output) = output {
+            for line in output.lines() {
                 let tuple = line.split('\t').next_tuple();
                 if let Some((hash, date, author, subject)) = tuple {
                     fetched_completions.push(Box::new(GitCommitCompletion {
@@ -186,4 +417,399 @@ impl core::Completer for GitCommitCompleter {
         }
         fetched_completions
     }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+#[test]
+fn test_branch_display_spans() {
+    use crate::core::Completion;
+
+    let completion = |kind: GitBranchCompletionType, branch_name: &str| GitBranchCompletion {
+        kind,
+        branch_name: branch_name.to_owned(),
+        date: String::new(),
+        author: String::new(),
+    };
+    assert_eq!(
+        completion(GitBranchCompletionType::RemoteBranch, "origin/fix/typo").display_spans(),
+        vec![
+            core::Span::styled("origin/", core::Style::colored(core::SpanColor::Dimmed)),
+            core::Span::plain("fix/typo"),
+        ]
+    );
+    assert_eq!(
+        completion(GitBranchCompletionType::Tag, "v1.0").display_spans(),
+        vec![core::Span::styled(
+            "v1.0",
+            core::Style::colored(core::SpanColor::Kind(core::CompletionKind::Tag))
+        )]
+    );
+}
+
+/// The state of the checkout of a worktree.
+#[derive(Debug, PartialEq)]
+enum WorktreeHead {
+    Branch(String),
+    Detached,
+    Bare,
+}
+
+#[derive(Debug, PartialEq)]
+struct GitWorktreeCompletion {
+    path: String,
+    head: WorktreeHead,
+    locked: bool,
+    prunable: bool,
+}
+
+impl core::Completion for GitWorktreeCompletion {
+    fn result_string(&self) -> String {
+        self.path.clone()
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        let style = if self.prunable {
+            core::Style::colored(core::SpanColor::Error)
+        } else {
+            core::Style::colored(core::SpanColor::Kind(self.kind()))
+        };
+        vec![core::Span::styled(self.path.as_str(), style)]
+    }
+
+    fn description(&self) -> Option<String> {
+        let head = match self.head {
+            WorktreeHead::Branch(ref b) => b.as_str(),
+            WorktreeHead::Detached => "detached",
+            WorktreeHead::Bare => "bare",
+        };
+        Some(if self.locked {
+            format!("{} (locked)", head)
+        } else {
+            head.to_owned()
+        })
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Directory
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of `git worktree list --porcelain`, in which each
+/// worktree is a block of lines separated by an empty line.
+fn parse_worktrees(output: &str) -> Vec<GitWorktreeCompletion> {
+    output
+        .split("\n\n")
+        .filter_map(|block| {
+            let mut completion = GitWorktreeCompletion {
+                path: String::new(),
+                head: WorktreeHead::Detached,
+                locked: false,
+                prunable: false,
+            };
+            for line in block.lines() {
+                let (key, value) = match line.find(' ') {
+                    Some(i) => (&line[..i], &line[i + 1..]),
+                    None => (line, ""),
+                };
+                match key {
+                    "worktree" => completion.path = value.to_owned(),
+                    "branch" => {
+                        let branch = value.strip_prefix("refs/heads/").unwrap_or(value);
+                        completion.head = WorktreeHead::Branch(branch.to_owned());
+                    }
+                    "bare" => completion.head = WorktreeHead::Bare,
+                    "locked" => completion.locked = true,
+                    "prunable" => completion.prunable = true,
+                    _ => {}
+                }
+            }
+            if completion.path.is_empty() {
+                None
+            } else {
+                Some(completion)
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_worktrees() {
+    let output = "worktree /src/app\n\
+                  HEAD 1234567890abcdef1234567890abcdef12345678\n\
+                  branch refs/heads/main\n\
+                  \n\
+                  worktree /src/app-fix\n\
+                  HEAD abcdef1234567890abcdef1234567890abcdef12\n\
+                  detached\n\
+                  locked on a removable disk\n\
+                  \n\
+                  worktree /tmp/gone\n\
+                  HEAD abcdef1234567890abcdef1234567890abcdef12\n\
+                  branch refs/heads/feature/x\n\
+                  prunable gitdir file points to non-existent location\n\
+                  \n";
+    assert_eq!(
+        parse_worktrees(output),
+        vec![
+            GitWorktreeCompletion {
+                path: "/src/app".to_owned(),
+                head: WorktreeHead::Branch("main".to_owned()),
+                locked: false,
+                prunable: false,
+            },
+            GitWorktreeCompletion {
+                path: "/src/app-fix".to_owned(),
+                head: WorktreeHead::Detached,
+                locked: true,
+                prunable: false,
+            },
+            GitWorktreeCompletion {
+                path: "/tmp/gone".to_owned(),
+                head: WorktreeHead::Branch("feature/x".to_owned()),
+                locked: false,
+                prunable: true,
+            },
+        ]
+    );
+}
+
+/// A completer for the worktrees of the repository of the current
+/// directory.
+pub struct GitWorktreeCompleter {
+    /// The time after which git commands are killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl GitWorktreeCompleter {
+    pub fn new(timeout: time::Duration) -> Self {
+        Self {
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for GitWorktreeCompleter {
+    fn name(&self) -> String {
+        "wt".to_owned()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        true
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let output = run_git(
+            None,
+            &["worktree", "list", "--porcelain"],
+            &[],
+            self.timeout,
+            &mut self.errors,
+        );
+        parse_worktrees(&output.unwrap_or_default())
+            .into_iter()
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+/// The state of a submodule, from the first character of its line in
+/// the output of `git submodule status`.
+#[derive(Debug, PartialEq)]
+enum SubmoduleState {
+    UpToDate,
+    NotInitialized,
+    /// The checked out commit differs from the recorded one.
+    Modified,
+    MergeConflicts,
+}
+
+#[derive(Debug, PartialEq)]
+struct GitSubmoduleCompletion {
+    path: String,
+    state: SubmoduleState,
+
+    /// The output of `git describe` for the checked out commit.
+    describe: Option<String>,
+}
+
+impl core::Completion for GitSubmoduleCompletion {
+    fn result_string(&self) -> String {
+        self.path.clone()
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        let color = match self.state {
+            SubmoduleState::NotInitialized => core::SpanColor::Dimmed,
+            SubmoduleState::MergeConflicts => core::SpanColor::Error,
+            _ => core::SpanColor::Kind(self.kind()),
+        };
+        vec![core::Span::styled(
+            self.path.as_str(),
+            core::Style::colored(color),
+        )]
+    }
+
+    fn description(&self) -> Option<String> {
+        let state = match self.state {
+            SubmoduleState::UpToDate => None,
+            SubmoduleState::NotInitialized => Some("not initialized"),
+            SubmoduleState::Modified => Some("modified"),
+            SubmoduleState::MergeConflicts => Some("merge conflicts"),
+        };
+        match (self.describe.as_deref(), state) {
+            (Some(d), Some(s)) => Some(format!("{} ({})", d, s)),
+            (Some(d), None) => Some(d.to_owned()),
+            (None, s) => s.map(str::to_owned),
+        }
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Directory
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of `git submodule status`, whose lines are a state
+/// character, the checked out commit, the path and, for initialized
+/// submodules, the output of `git describe` in parentheses.
+fn parse_submodules(output: &str) -> Vec<GitSubmoduleCompletion> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let state = match line.chars().next()? {
+                ' ' => SubmoduleState::UpToDate,
+                '-' => SubmoduleState::NotInitialized,
+                '+' => SubmoduleState::Modified,
+                'U' => SubmoduleState::MergeConflicts,
+                _ => return None,
+            };
+            let mut fields = line[1..].splitn(3, ' ');
+            let _commit = fields.next()?;
+            let path = fields.next()?;
+            let describe = fields
+                .next()
+                .and_then(|d| d.strip_prefix('('))
+                .and_then(|d| d.strip_suffix(')'))
+                .map(str::to_owned);
+            Some(GitSubmoduleCompletion {
+                path: path.to_owned(),
+                state,
+                describe,
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_submodules() {
+    let output = " 1234567890abcdef1234567890abcdef12345678 vendor/lib (v1.2.0)\n\
+                  -abcdef1234567890abcdef1234567890abcdef12 docs/theme\n\
+                  +abcdef1234567890abcdef1234567890abcdef12 tools (heads/main)\n";
+    assert_eq!(
+        parse_submodules(output),
+        vec![
+            GitSubmoduleCompletion {
+                path: "vendor/lib".to_owned(),
+                state: SubmoduleState::UpToDate,
+                describe: Some("v1.2.0".to_owned()),
+            },
+            GitSubmoduleCompletion {
+                path: "docs/theme".to_owned(),
+                state: SubmoduleState::NotInitialized,
+                describe: None,
+            },
+            GitSubmoduleCompletion {
+                path: "tools".to_owned(),
+                state: SubmoduleState::Modified,
+                describe: Some("heads/main".to_owned()),
+            },
+        ]
+    );
+}
+
+/// A completer for the submodules of the repository of the current
+/// directory, descending into the branches of a submodule.
+pub struct GitSubmoduleCompleter {
+    /// The time after which git commands are killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl GitSubmoduleCompleter {
+    pub fn new(timeout: time::Duration) -> Self {
+        Self {
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for GitSubmoduleCompleter {
+    fn name(&self) -> String {
+        "sub".to_owned()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        true
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let output = run_git(
+            None,
+            &["submodule", "status"],
+            &[],
+            self.timeout,
+            &mut self.errors,
+        );
+        parse_submodules(&output.unwrap_or_default())
+            .into_iter()
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let submodule = completion
+            .as_any()
+            .downcast_ref::<GitSubmoduleCompletion>()?;
+        // An uninitialized submodule has no repository to list.
+        if submodule.state == SubmoduleState::NotInitialized {
+            return None;
+        }
+        Some(Box::new(GitBranchCompleter::in_repo(
+            path::PathBuf::from(&submodule.path),
+            self.timeout,
+        )))
+    }
 }
@@ -0,0 +1,331 @@
+//! Defines a completer for Mercurial branches, bookmarks and tags, and
+//! for the revisions of each of them.
+
+use std::any;
+use std::env;
+use std::process::Command;
+use std::time;
+
+use itertools::Itertools;
+
+use crate::completers::subprocess;
+use crate::core;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum HgNameType {
+    /// A named branch, whose open heads are listed.
+    Branch,
+    Bookmark,
+    Tag,
+}
+
+impl HgNameType {
+    /// Returns the revset selecting the revisions with names of this
+    /// type, and the template printing a line for each name.
+    fn log_arguments(self) -> (&'static str, &'static str) {
+        match self {
+            HgNameType::Branch => (
+                "head() and not closed()",
+                "{branch}\t{date|shortdate}\t{author|person}\n",
+            ),
+            HgNameType::Bookmark => (
+                "bookmark()",
+                "{bookmarks % '{bookmark}\t{date|shortdate}\t{author|person}\n'}",
+            ),
+            HgNameType::Tag => (
+                "tag()",
+                "{tags % '{tag}\t{date|shortdate}\t{author|person}\n'}",
+            ),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct HgNameCompletion {
+    name_type: HgNameType,
+    name: String,
+
+    /// The date of the named revision.
+    date: String,
+    author: String,
+}
+
+impl core::Completion for HgNameCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        vec![core::Span::styled(
+            self.name.as_str(),
+            core::Style::colored(core::SpanColor::Kind(self.kind())),
+        )]
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("{} {}", self.date, self.author))
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        match self.name_type {
+            HgNameType::Branch | HgNameType::Bookmark => core::CompletionKind::Branch,
+            HgNameType::Tag => core::CompletionKind::Tag,
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the names printed by `hg log` with the template of a type of
+/// names. A branch with several heads is listed once, and the `tip`
+/// pseudo-tag is skipped.
+fn parse_names(output: &str, name_type: HgNameType) -> Vec<HgNameCompletion> {
+    output
+        .lines()
+        .filter_map(|line| line.split('\t').next_tuple())
+        .filter(|(name, _, _)| !(name_type == HgNameType::Tag && *name == "tip"))
+        .unique_by(|(name, _, _)| *name)
+        .map(|(name, date, author)| HgNameCompletion {
+            name_type,
+            name: name.to_owned(),
+            date: date.to_owned(),
+            author: author.to_owned(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_names() {
+    let completion = |name_type: HgNameType, name: &str, date: &str| HgNameCompletion {
+        name_type,
+        name: name.to_owned(),
+        date: date.to_owned(),
+        author: "alice".to_owned(),
+    };
+    assert_eq!(
+        parse_names(
+            "default\t2024-03-02\talice\nstable\t2024-02-01\talice\n\
+             default\t2024-01-05\talice\n",
+            HgNameType::Branch
+        ),
+        vec![
+            completion(HgNameType::Branch, "default", "2024-03-02"),
+            completion(HgNameType::Branch, "stable", "2024-02-01"),
+        ]
+    );
+    assert_eq!(
+        parse_names(
+            "tip\t2024-03-02\talice\n1.0\t2023-12-24\talice\n",
+            HgNameType::Tag
+        ),
+        vec![completion(HgNameType::Tag, "1.0", "2023-12-24")]
+    );
+}
+
+/// Indicates if the current directory is in a Mercurial repository.
+pub fn is_repository() -> bool {
+    env::current_dir()
+        .map(|d| d.ancestors().any(|a| a.join(".hg").is_dir()))
+        .unwrap_or(false)
+}
+
+/// Runs hg with the given arguments, returning its output, or None if
+/// it failed, in which case the error is added to `errors`.
+fn run_hg(args: &[&str], timeout: time::Duration, errors: &mut Vec<String>) -> Option<String> {
+    // The plain mode keeps the output free of the user's aliases,
+    // colors and localization.
+    let mut command = Command::new("hg");
+    command.args(args).env("HGPLAIN", "1");
+    match subprocess::run(&mut command, timeout) {
+        Result::Ok(output) => Some(output),
+        Result::Err(e) => {
+            errors.push(e);
+            None
+        }
+    }
+}
+
+/// A completer for the branches, bookmarks and tags of the Mercurial
+/// repository of the current directory.
+pub struct HgBranchCompleter {
+    /// The time after which hg commands are killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl HgBranchCompleter {
+    pub fn new(timeout: time::Duration) -> Self {
+        Self {
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for HgBranchCompleter {
+    fn name(&self) -> String {
+        "br".to_owned()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        true
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let mut fetched_completions: Vec<core::CompletionBox> = Vec::new();
+        for name_type in [HgNameType::Branch, HgNameType::Bookmark, HgNameType::Tag] {
+            let (revset, template) = name_type.log_arguments();
+            let output = run_hg(
+                &["log", "--rev", revset, "--template", template],
+                self.timeout,
+                &mut self.errors,
+            );
+            if let Some(output) = output {
+                fetched_completions.extend(
+                    parse_names(&output, name_type)
+                        .into_iter()
+                        .map(|c| Box::new(c) as core::CompletionBox),
+                );
+            } else {
+                // The other names would fail in the same way.
+                break;
+            }
+        }
+        fetched_completions
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let name_completion = completion.as_any().downcast_ref::<HgNameCompletion>()?;
+        Some(Box::new(HgRevisionCompleter::new(
+            name_completion.name.as_str(),
+            self.timeout,
+        )))
+    }
+}
+
+struct HgRevisionCompletion {
+    node: String,
+    date: String,
+    author: String,
+    summary: String,
+}
+
+impl core::Completion for HgRevisionCompletion {
+    fn result_string(&self) -> String {
+        self.node.clone()
+    }
+
+    fn display_string(&self) -> String {
+        format!("{:12} {}", &self.node, &self.summary)
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("{} {}", self.date, self.author))
+    }
+
+    fn search_string(&self) -> String {
+        self.summary.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Commit
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the revset of the ancestors of a name, newest first. The name
+/// is quoted, so that names which look like revset expressions, e.g.
+/// `1.0-rc`, are taken literally.
+fn ancestors_revset(name: &str) -> String {
+    format!(
+        "reverse(ancestors('{}'))",
+        name.replace('\\', "\\\\").replace('\'', "\\'")
+    )
+}
+
+#[test]
+fn test_ancestors_revset() {
+    assert_eq!(ancestors_revset("stable"), "reverse(ancestors('stable'))");
+    assert_eq!(ancestors_revset("it's"), "reverse(ancestors('it\\'s'))");
+}
+
+struct HgRevisionCompleter {
+    name: String,
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl HgRevisionCompleter {
+    fn new<N: Into<String>>(name: N, timeout: time::Duration) -> HgRevisionCompleter {
+        HgRevisionCompleter {
+            name: name.into(),
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for HgRevisionCompleter {
+    fn name(&self) -> String {
+        "rev".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        true
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let mut fetched_completions: Vec<core::CompletionBox> = Vec::new();
+        let output = run_hg(
+            &[
+                "log",
+                "--rev",
+                &ancestors_revset(&self.name),
+                "--template",
+                "{node|short}\t{date|shortdate}\t{author|person}\t{desc|firstline}\n",
+            ],
+            self.timeout,
+            &mut self.errors,
+        );
+
+        if let Some(output) = output {
+            for line in output.lines() {
+                let tuple = line.split('\t').next_tuple();
+                if let Some((node, date, author, summary)) = tuple {
+                    fetched_completions.push(Box::new(HgRevisionCompletion {
+                        node: node.to_owned(),
+                        date: date.to_owned(),
+                        author: author.to_owned(),
+                        summary: summary.to_owned(),
+                    }));
+                }
+            }
+        }
+        fetched_completions
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,111 @@
+//! Defines a completer for the command history of the shell.
+//!
+//! Like aliases and functions, the history lives in the shell process,
+//! so the shell integration writes it to a file before running
+//! completers, most recent commands first and each followed by a NUL,
+//! as commands may span several lines. It passes the path of the file
+//! in the `HISTORY_VARIABLE` environment variable.
+
+use std::any;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+
+use crate::core;
+
+/// The environment variable with the path of the file with the history.
+pub const HISTORY_VARIABLE: &str = "COMPLETERS_SHELL_HISTORY";
+
+/// The symbol shown in place of newlines in commands, which would break
+/// the rows of the chooser.
+const NEWLINE_SYMBOL: &str = "\u{21b5}";
+
+#[derive(Debug, PartialEq)]
+struct HistoryCompletion {
+    command: String,
+}
+
+impl core::Completion for HistoryCompletion {
+    fn result_string(&self) -> String {
+        self.command.clone()
+    }
+
+    fn display_string(&self) -> String {
+        self.command.replace('\n', NEWLINE_SYMBOL)
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Command
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the history written by the shell, keeping only the most
+/// recent occurrence of each command.
+fn parse_history(dump: &str) -> Vec<HistoryCompletion> {
+    let mut seen = HashSet::new();
+    dump.split('\0')
+        .filter(|c| !c.trim().is_empty() && seen.insert(*c))
+        .map(|c| HistoryCompletion {
+            command: c.to_owned(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_history() {
+    let command = |command: &str| HistoryCompletion {
+        command: command.to_owned(),
+    };
+    assert_eq!(
+        parse_history("git status\0for f in *; do\n  echo $f\ndone\0\0 \0git status\0ls\0"),
+        vec![
+            command("git status"),
+            command("for f in *; do\n  echo $f\ndone"),
+            command("ls"),
+        ]
+    );
+}
+
+/// A completer for the commands in the history of the shell.
+pub struct HistoryCompleter {}
+
+impl Default for HistoryCompleter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl HistoryCompleter {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Indicates if the shell integration passed the history.
+    pub fn is_available() -> bool {
+        env::var_os(HISTORY_VARIABLE).is_some()
+    }
+}
+
+impl core::Completer for HistoryCompleter {
+    fn name(&self) -> String {
+        "history".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let dump = env::var_os(HISTORY_VARIABLE)
+            .and_then(|p| fs::read_to_string(p).ok())
+            .unwrap_or_default();
+        parse_history(&dump)
+            .into_iter()
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,294 @@
+//! Defines a completer for the formulae and casks of Homebrew, with
+//! their descriptions and versions.
+//!
+//! The catalog is listed with `brew info --json=v2 --eval-all`, which
+//! evaluates every formula and cask and prints tens of megabytes, so
+//! its output is cached on disk for a day by default. The installed
+//! formulae and casks, which change more often, are listed each time
+//! with `brew list`.
+
+use std::any;
+use std::collections::HashSet;
+use std::process::Command;
+use std::time;
+
+use serde::Deserialize;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// The kinds of packages of Homebrew.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PackageKind {
+    Formula,
+    Cask,
+}
+
+/// Returns the kinds of packages completed on a `brew` command line,
+/// which may name one with `--formula` or `--cask`.
+pub fn package_kinds(command_words: &[&str]) -> Vec<PackageKind> {
+    let has = |options: &[&str]| command_words.iter().any(|w| options.contains(w));
+    if has(&["--cask", "--casks"]) {
+        vec![PackageKind::Cask]
+    } else if has(&["--formula", "--formulae"]) {
+        vec![PackageKind::Formula]
+    } else {
+        vec![PackageKind::Formula, PackageKind::Cask]
+    }
+}
+
+#[test]
+fn test_package_kinds() {
+    assert_eq!(
+        package_kinds(&["brew", "install", "--cask"]),
+        vec![PackageKind::Cask]
+    );
+    assert_eq!(
+        package_kinds(&["brew", "install"]),
+        vec![PackageKind::Formula, PackageKind::Cask]
+    );
+}
+
+#[derive(Deserialize)]
+struct Catalog {
+    #[serde(default)]
+    formulae: Vec<Formula>,
+    #[serde(default)]
+    casks: Vec<Cask>,
+}
+
+#[derive(Deserialize)]
+struct Formula {
+    name: String,
+    #[serde(default)]
+    desc: Option<String>,
+    versions: FormulaVersions,
+}
+
+#[derive(Deserialize)]
+struct FormulaVersions {
+    stable: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Cask {
+    token: String,
+    #[serde(default)]
+    desc: Option<String>,
+    #[serde(default)]
+    version: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+struct HomebrewCompletion {
+    name: String,
+    description: Option<String>,
+    installed: bool,
+}
+
+impl core::Completion for HomebrewCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    /// Shows installed packages highlighted, like the package completer.
+    fn display_spans(&self) -> Vec<core::Span> {
+        let style = if self.installed {
+            core::Style::colored(core::SpanColor::Kind(core::CompletionKind::Package))
+        } else {
+            core::Style::default()
+        };
+        vec![core::Span::styled(self.name.as_str(), style)]
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Package
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the description of a package: its version and description,
+/// marked for casks, which share the names of formulae at times.
+fn describe(kind: PackageKind, version: Option<&str>, desc: Option<&str>) -> String {
+    let mut parts = vec![];
+    if kind == PackageKind::Cask {
+        parts.push("cask");
+    }
+    parts.extend(version);
+    parts.extend(desc);
+    parts.join(" ")
+}
+
+/// Parses the catalog printed by `brew info --json=v2` into the
+/// packages of the given kinds, marking those in `installed`.
+fn parse_catalog(
+    json: &str,
+    kinds: &[PackageKind],
+    installed: &HashSet<String>,
+) -> Result<Vec<HomebrewCompletion>, String> {
+    let catalog: Catalog = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut completions = vec![];
+    if kinds.contains(&PackageKind::Formula) {
+        completions.extend(catalog.formulae.into_iter().map(|f| HomebrewCompletion {
+            description: Some(describe(
+                PackageKind::Formula,
+                f.versions.stable.as_deref(),
+                f.desc.as_deref(),
+            )),
+            installed: installed.contains(&f.name),
+            name: f.name,
+        }));
+    }
+    if kinds.contains(&PackageKind::Cask) {
+        completions.extend(catalog.casks.into_iter().map(|c| HomebrewCompletion {
+            description: Some(describe(
+                PackageKind::Cask,
+                c.version.as_deref(),
+                c.desc.as_deref(),
+            )),
+            installed: installed.contains(&c.token),
+            name: c.token,
+        }));
+    }
+    // Installed packages come first.
+    completions.sort_by_key(|c| !c.installed);
+    Result::Ok(completions)
+}
+
+#[test]
+fn test_parse_catalog() {
+    let json = r#"{
+        "formulae": [
+            {"name": "wget", "desc": "Internet file retriever", "versions": {"stable": "1.24.5"}},
+            {"name": "jq", "desc": null, "versions": {"stable": "1.7.1", "head": "HEAD"}}
+        ],
+        "casks": [{"token": "firefox", "name": ["Mozilla Firefox"], "desc": "Web browser", "version": "128.0"}]
+    }"#;
+    let installed: HashSet<String> = ["jq".to_owned()].iter().cloned().collect();
+    let completions = parse_catalog(json, &[PackageKind::Formula, PackageKind::Cask], &installed)
+        .unwrap()
+        .into_iter()
+        .map(|c| (c.name, c.description.unwrap(), c.installed))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        completions,
+        vec![
+            ("jq".to_owned(), "1.7.1".to_owned(), true),
+            (
+                "wget".to_owned(),
+                "1.24.5 Internet file retriever".to_owned(),
+                false
+            ),
+            (
+                "firefox".to_owned(),
+                "cask 128.0 Web browser".to_owned(),
+                false
+            ),
+        ]
+    );
+    assert_eq!(
+        parse_catalog(json, &[PackageKind::Cask], &installed)
+            .unwrap()
+            .len(),
+        1
+    );
+}
+
+/// Lists the packages of the given kinds, returning the completions or
+/// the error.
+fn list_packages(
+    kinds: &[PackageKind],
+    use_cache: bool,
+    config: &config::HomebrewConfig,
+) -> Result<Vec<HomebrewCompletion>, String> {
+    let catalog = subprocess::run_expiring(
+        Command::new("brew").args(["info", "--json=v2", "--eval-all"]),
+        config.timeout(),
+        "homebrew",
+        time::Duration::from_secs(config.cache_secs),
+        use_cache,
+    )?;
+    // A failure to list the installed packages only loses the marks.
+    let installed = subprocess::run(Command::new("brew").args(["list", "-1"]), config.timeout())
+        .unwrap_or_default()
+        .lines()
+        .map(str::to_owned)
+        .collect();
+    parse_catalog(&catalog, kinds, &installed)
+}
+
+/// A completer for the formulae and casks of Homebrew.
+pub struct HomebrewCompleter {
+    kinds: Vec<PackageKind>,
+    config: config::HomebrewConfig,
+
+    /// Lists the packages in the background.
+    listing: subprocess::Background<Result<Vec<HomebrewCompletion>, String>>,
+    errors: Vec<String>,
+}
+
+impl HomebrewCompleter {
+    pub fn new(kinds: Vec<PackageKind>, config: config::HomebrewConfig) -> Self {
+        let mut completer = HomebrewCompleter {
+            kinds,
+            config,
+            listing: subprocess::Background::idle(),
+            errors: vec![],
+        };
+        completer.start(true);
+        completer
+    }
+
+    fn start(&mut self, use_cache: bool) {
+        let kinds = self.kinds.clone();
+        let config = self.config.clone();
+        self.listing =
+            subprocess::Background::run(move || list_packages(&kinds, use_cache, &config));
+    }
+}
+
+impl core::Completer for HomebrewCompleter {
+    fn name(&self) -> String {
+        "brew".to_owned()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let failed = || Result::Err("listing Homebrew packages failed".to_owned());
+        let result = match self.listing.poll(failed) {
+            Some(result) => result,
+            None => return vec![],
+        };
+        match result {
+            Result::Ok(completions) => completions
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    /// Lists the packages anew, bypassing the cache of the catalog.
+    fn refresh(&mut self) -> bool {
+        self.start(false);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
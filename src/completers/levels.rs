@@ -0,0 +1,170 @@
+//! Defines a pseudo-completer listing the completions of all the levels
+//! of a stack of completers at once, e.g. the commits of all the
+//! branches descended into, so that the query searches the whole
+//! hierarchy.
+//!
+//! The completions fetched so far at each level are copied when the
+//! search starts, and each of them is prefixed with the location or
+//! the name of the level it comes from.
+
+use std::any;
+
+use crate::core;
+
+/// A copy of a completion of one of the levels.
+struct LevelCompletion {
+    level: String,
+    result_string: String,
+    display_spans: Vec<core::Span>,
+    search_string: String,
+    description: Option<String>,
+    is_hidden: bool,
+    kind: core::CompletionKind,
+    file_metadata: Option<core::FileMetadata>,
+    group: Option<core::CompletionGroup>,
+    severity: Option<core::Severity>,
+}
+
+impl LevelCompletion {
+    fn new(level: String, completion: &dyn core::Completion) -> LevelCompletion {
+        LevelCompletion {
+            level,
+            result_string: completion.result_string(),
+            display_spans: completion.display_spans(),
+            search_string: completion.search_string(),
+            description: completion.description(),
+            is_hidden: completion.is_hidden(),
+            kind: completion.kind(),
+            file_metadata: completion.file_metadata(),
+            group: completion.group(),
+            severity: completion.severity(),
+        }
+    }
+}
+
+impl core::Completion for LevelCompletion {
+    fn result_string(&self) -> String {
+        self.result_string.clone()
+    }
+
+    fn display_string(&self) -> String {
+        let text: String = self.display_spans.iter().map(|s| s.text.as_str()).collect();
+        format!("{} {}", self.level, text)
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        let mut spans = vec![
+            core::Span::styled(
+                self.level.clone(),
+                core::Style::colored(core::SpanColor::Dimmed),
+            ),
+            core::Span::plain(" "),
+        ];
+        spans.extend(self.display_spans.iter().cloned());
+        spans
+    }
+
+    fn search_string(&self) -> String {
+        self.search_string.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn is_hidden(&self) -> bool {
+        self.is_hidden
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        self.kind
+    }
+
+    fn file_metadata(&self) -> Option<core::FileMetadata> {
+        self.file_metadata
+    }
+
+    fn group(&self) -> Option<core::CompletionGroup> {
+        self.group.clone()
+    }
+
+    fn severity(&self) -> Option<core::Severity> {
+        self.severity
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A pseudo-completer listing the completions of the levels of a stack.
+pub struct LevelsCompleter {
+    completions: Vec<core::CompletionBox>,
+}
+
+impl LevelsCompleter {
+    /// Creates the completer from the names of the levels, from the
+    /// bottom one, and the completions fetched at each of them. The
+    /// names are aligned, so that the completions are.
+    pub fn new(levels: Vec<(String, &[core::CompletionBox])>) -> LevelsCompleter {
+        let width = levels
+            .iter()
+            .map(|(name, _)| name.chars().count())
+            .max()
+            .unwrap_or(0);
+        let mut completions: Vec<core::CompletionBox> = vec![];
+        for (name, level_completions) in levels {
+            let level = format!("{:width$}", name, width = width);
+            completions.extend(level_completions.iter().map(|c| {
+                Box::new(LevelCompletion::new(level.clone(), &**c)) as core::CompletionBox
+            }));
+        }
+        LevelsCompleter { completions }
+    }
+}
+
+impl core::Completer for LevelsCompleter {
+    fn name(&self) -> String {
+        "levels".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        std::mem::take(&mut self.completions)
+    }
+}
+
+#[test]
+fn test_levels_completer() {
+    use crate::core::Completer;
+
+    struct Line(&'static str);
+    impl core::Completion for Line {
+        fn result_string(&self) -> String {
+            self.0.to_owned()
+        }
+        fn as_any(&self) -> &dyn any::Any {
+            self
+        }
+    }
+
+    let branches: Vec<core::CompletionBox> = vec![Box::new(Line("main"))];
+    let commits: Vec<core::CompletionBox> =
+        vec![Box::new(Line("abc123")), Box::new(Line("def456"))];
+    let mut completer = LevelsCompleter::new(vec![
+        ("br".to_owned(), &branches[..]),
+        ("main".to_owned(), &commits[..]),
+    ]);
+    let completions = completer.fetch_completions();
+    assert_eq!(
+        completions
+            .iter()
+            .map(|c| (c.display_string(), c.result_string()))
+            .collect::<Vec<_>>(),
+        vec![
+            ("br   main".to_owned(), "main".to_owned()),
+            ("main abc123".to_owned(), "abc123".to_owned()),
+            ("main def456".to_owned(), "def456".to_owned()),
+        ]
+    );
+    assert!(completer.fetch_completions().is_empty());
+}
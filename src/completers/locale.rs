@@ -0,0 +1,231 @@
+//! Defines completers for the values of the variables of time zones
+//! and locales, like `TZ=Europe/Warsaw` and `LANG=pl_PL.UTF-8`.
+//!
+//! Time zones are the files of the time zone database, usually in
+//! `/usr/share/zoneinfo`, and locales are listed by `locale -a`.
+
+use std::any;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::io::Read;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::core;
+
+/// The default directory of the time zone database.
+const ZONEINFO_DIR: &str = "/usr/share/zoneinfo";
+
+/// The files and directories of the database which are not time zones
+/// to choose, or duplicate the others: `posix` and `right` hold the
+/// zones again, with different handling of leap seconds.
+const SKIPPED_ZONE_FILES: &[&str] = &["posix", "right", "posixrules", "localtime", "Factory"];
+
+/// The kind of values completed for a variable.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ValueKind {
+    TimeZone,
+    Locale,
+}
+
+/// Returns the kind of values of a variable, if they are completed.
+pub fn value_kind(variable: &str) -> Option<ValueKind> {
+    match variable {
+        "TZ" => Some(ValueKind::TimeZone),
+        "LANG" => Some(ValueKind::Locale),
+        v if v.starts_with("LC_") => Some(ValueKind::Locale),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_value_kind() {
+    assert_eq!(value_kind("TZ"), Some(ValueKind::TimeZone));
+    assert_eq!(value_kind("LC_TIME"), Some(ValueKind::Locale));
+    assert_eq!(value_kind("PATH"), None);
+}
+
+#[derive(Debug, PartialEq)]
+struct LocaleCompletion {
+    value: String,
+    description: Option<String>,
+}
+
+impl core::Completion for LocaleCompletion {
+    fn result_string(&self) -> String {
+        self.value.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses `zone1970.tab` or `zone.tab` of the time zone database into
+/// descriptions of zones: the codes of the countries using a zone and
+/// the comment, if any, e.g. `PL` or `US Eastern (most areas)`.
+fn parse_zone_table(table: &str) -> HashMap<String, String> {
+    table
+        .lines()
+        .filter(|l| !l.starts_with('#'))
+        .filter_map(|line| {
+            let fields: Vec<&str> = line.split('\t').collect();
+            let codes = fields.first()?.replace(',', " ");
+            let zone = fields.get(2)?;
+            let description = match fields.get(3) {
+                Some(comment) => format!("{} {}", codes, comment),
+                None => codes,
+            };
+            Some(((*zone).to_owned(), description))
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_zone_table() {
+    let table = "# comment\n\
+                 PL\t+5215+02100\tEurope/Warsaw\n\
+                 CA,US\t+404251-0740023\tAmerica/New_York\tEastern (most areas)\n";
+    let descriptions = parse_zone_table(table);
+    assert_eq!(descriptions["Europe/Warsaw"], "PL");
+    assert_eq!(
+        descriptions["America/New_York"],
+        "CA US Eastern (most areas)"
+    );
+    assert_eq!(descriptions.len(), 2);
+}
+
+/// Indicates if a file is in the format of compiled time zones.
+fn is_zone_file(path: &path::Path) -> bool {
+    let mut magic = [0; 4];
+    fs::File::open(path)
+        .and_then(|mut f| f.read_exact(&mut magic))
+        .is_ok()
+        && &magic == b"TZif"
+}
+
+/// Adds the names of the time zones in a directory of the database and
+/// its subdirectories, e.g. `Europe/Warsaw` for `prefix` `Europe/`.
+fn find_zones(dir: &path::Path, prefix: &str, zones: &mut Vec<String>) {
+    let entries = match fs::read_dir(dir) {
+        Result::Ok(e) => e,
+        Result::Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        if prefix.is_empty() && SKIPPED_ZONE_FILES.contains(&file_name.as_str()) {
+            continue;
+        }
+        let path = entry.path();
+        let name = format!("{}{}", prefix, file_name);
+        if path.is_dir() {
+            find_zones(&path, &format!("{}/", name), zones);
+        } else if is_zone_file(&path) {
+            zones.push(name);
+        }
+    }
+}
+
+/// A completer for the names of time zones.
+pub struct TimeZoneCompleter {}
+
+impl Default for TimeZoneCompleter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TimeZoneCompleter {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl core::Completer for TimeZoneCompleter {
+    fn name(&self) -> String {
+        "tz".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        // The database may be elsewhere, as in the TZ variable itself.
+        let dir = env::var_os("TZDIR")
+            .map(path::PathBuf::from)
+            .unwrap_or_else(|| path::PathBuf::from(ZONEINFO_DIR));
+        let table = fs::read_to_string(dir.join("zone1970.tab"))
+            .or_else(|_| fs::read_to_string(dir.join("zone.tab")))
+            .unwrap_or_default();
+        let mut descriptions = parse_zone_table(&table);
+        let mut zones = vec![];
+        find_zones(&dir, "", &mut zones);
+        zones.sort();
+        zones
+            .into_iter()
+            .map(|zone| {
+                Box::new(LocaleCompletion {
+                    description: descriptions.remove(&zone),
+                    value: zone,
+                }) as core::CompletionBox
+            })
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+}
+
+/// A completer for the names of the locales available on the system.
+pub struct LocaleCompleter {
+    /// The time after which `locale` is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl LocaleCompleter {
+    pub fn new(timeout: time::Duration) -> Self {
+        Self {
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for LocaleCompleter {
+    fn name(&self) -> String {
+        "locale".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        match subprocess::run(Command::new("locale").arg("-a"), self.timeout) {
+            Result::Ok(output) => output
+                .lines()
+                .filter(|l| !l.is_empty())
+                .map(|l| {
+                    Box::new(LocaleCompletion {
+                        value: l.to_owned(),
+                        description: None,
+                    }) as core::CompletionBox
+                })
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,449 @@
+//! Defines completers for manual pages and their sections.
+//!
+//! Pages are listed with `man -k .`, which reads the index of the
+//! manual page database, or found in the directories of the manual
+//! path if that fails. The index of a system can list tens of
+//! thousands of pages, so it is read in a background thread and passed
+//! to the UI in chunks. Like the other commands run by completers,
+//! `man` and `manpath` are killed when they run longer than the
+//! timeout.
+
+use std::any;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::process::Command;
+use std::sync::mpsc;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// The number of pages sent from the fetching thread at once.
+const CHUNK_SIZE: usize = 1000;
+
+/// The manual path used when `manpath` is not available.
+const DEFAULT_MAN_PATH: &str = "/usr/local/share/man:/usr/share/man";
+
+#[derive(Debug, PartialEq)]
+struct ManPageCompletion {
+    name: String,
+    section: String,
+    description: String,
+}
+
+impl core::Completion for ManPageCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn display_string(&self) -> String {
+        format!("{} ({})", self.name, self.section)
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(self.description.clone())
+    }
+
+    fn search_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Manual
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses a line of the output of `man -k`, e.g.
+/// `ls (1)               - list directory contents`. A line may
+/// describe several pages, e.g. `gzip, gunzip(1) - ...` on BSD.
+fn parse_apropos_line(line: &str) -> Vec<ManPageCompletion> {
+    let (pages, description) = match line.split_once(" - ") {
+        Some(p) => p,
+        None => return vec![],
+    };
+    let pages = pages.trim_end();
+    let section_start = match pages.rfind('(') {
+        Some(i) if pages.ends_with(')') => i,
+        _ => return vec![],
+    };
+    let section = &pages[(section_start + 1)..(pages.len() - 1)];
+    pages[..section_start]
+        .split(',')
+        .map(|n| n.trim())
+        .filter(|n| !n.is_empty())
+        .map(|name| ManPageCompletion {
+            name: name.to_owned(),
+            section: section.to_owned(),
+            description: description.trim().to_owned(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_apropos_line() {
+    let page = |name: &str, section: &str, description: &str| ManPageCompletion {
+        name: name.to_owned(),
+        section: section.to_owned(),
+        description: description.to_owned(),
+    };
+    assert_eq!(
+        parse_apropos_line("ls (1)               - list directory contents"),
+        vec![page("ls", "1", "list directory contents")]
+    );
+    assert_eq!(
+        parse_apropos_line("gzip, gunzip(1) - compression - and more"),
+        vec![
+            page("gzip", "1", "compression - and more"),
+            page("gunzip", "1", "compression - and more"),
+        ]
+    );
+    assert_eq!(
+        parse_apropos_line("SSL_read (3ssl) - read bytes"),
+        vec![page("SSL_read", "3ssl", "read bytes")]
+    );
+    assert_eq!(parse_apropos_line(".: nothing appropriate."), vec![]);
+}
+
+/// Returns the name and section of a page from the name of its file,
+/// e.g. `ls.1.gz`.
+fn parse_page_file_name(file_name: &str) -> Option<(&str, &str)> {
+    let mut name = file_name;
+    for extension in &[".gz", ".bz2", ".xz", ".lzma", ".zst", ".Z"] {
+        if let Some(n) = name.strip_suffix(extension) {
+            name = n;
+            break;
+        }
+    }
+    name.rsplit_once('.')
+        .filter(|(n, s)| !n.is_empty() && !s.is_empty())
+}
+
+#[test]
+fn test_parse_page_file_name() {
+    assert_eq!(parse_page_file_name("ls.1.gz"), Some(("ls", "1")));
+    assert_eq!(parse_page_file_name("git-log.1"), Some(("git-log", "1")));
+    assert_eq!(
+        parse_page_file_name("Foo::Bar.3pm.xz"),
+        Some(("Foo::Bar", "3pm"))
+    );
+    assert_eq!(parse_page_file_name("README"), None);
+}
+
+/// Lists the pages found in the `man*` subdirectories of the manual
+/// path, for systems without a manual page index.
+fn pages_in_man_path(timeout: time::Duration) -> Vec<ManPageCompletion> {
+    let man_path = subprocess::run(&mut Command::new("manpath"), timeout)
+        .ok()
+        .map(|o| o.trim().to_owned())
+        .or_else(|| env::var("MANPATH").ok())
+        .unwrap_or_else(|| DEFAULT_MAN_PATH.to_owned());
+    let mut seen = HashSet::new();
+    let mut pages = vec![];
+    for dir in man_path.split(':').filter(|d| !d.is_empty()) {
+        let section_dirs = match fs::read_dir(dir) {
+            Result::Ok(d) => d,
+            Result::Err(_) => continue,
+        };
+        for section_dir in section_dirs.flatten() {
+            if !section_dir.file_name().to_string_lossy().starts_with("man") {
+                continue;
+            }
+            let files = match fs::read_dir(section_dir.path()) {
+                Result::Ok(f) => f,
+                Result::Err(_) => continue,
+            };
+            for file in files.flatten() {
+                let file_name = file.file_name().to_string_lossy().into_owned();
+                if let Some((name, section)) = parse_page_file_name(&file_name) {
+                    if seen.insert((name.to_owned(), section.to_owned())) {
+                        pages.push(ManPageCompletion {
+                            name: name.to_owned(),
+                            section: section.to_owned(),
+                            description: String::new(),
+                        });
+                    }
+                }
+            }
+        }
+    }
+    pages
+}
+
+/// Sends the completions in chunks, returning `false` if the receiving
+/// end is gone.
+fn send_in_chunks(
+    completions: &mut Vec<core::CompletionBox>,
+    response_send: &mpsc::Sender<Result<Vec<core::CompletionBox>, String>>,
+    force: bool,
+) -> bool {
+    if completions.len() < CHUNK_SIZE && !force {
+        return true;
+    }
+    response_send
+        .send(Result::Ok(std::mem::take(completions)))
+        .is_ok()
+}
+
+/// Lists all pages, sending them through `response_send` in chunks.
+///
+/// The failure of `man -k` is reported only if no pages are found in
+/// the manual path either, as it fails on systems without an index.
+fn list_pages(
+    timeout: time::Duration,
+    response_send: &mpsc::Sender<Result<Vec<core::CompletionBox>, String>>,
+) {
+    let index = subprocess::run(Command::new("man").args(["-k", "."]), timeout);
+    let mut chunk: Vec<core::CompletionBox> = vec![];
+    let mut listed_any = false;
+    if let Result::Ok(ref output) = index {
+        for line in output.lines() {
+            for page in parse_apropos_line(line) {
+                chunk.push(Box::new(page));
+                listed_any = true;
+            }
+            if !send_in_chunks(&mut chunk, response_send, false) {
+                return;
+            }
+        }
+    }
+    if !listed_any {
+        for page in pages_in_man_path(timeout) {
+            chunk.push(Box::new(page));
+            listed_any = true;
+        }
+    }
+    if let (false, Result::Err(e)) = (listed_any, index) {
+        let _ = response_send.send(Result::Err(e));
+        return;
+    }
+    send_in_chunks(&mut chunk, response_send, true);
+}
+
+/// A completer for the names of manual pages.
+pub struct ManPageCompleter {
+    /// Lists the pages in the background, in chunks.
+    listing: subprocess::Background<Result<Vec<core::CompletionBox>, String>>,
+    errors: Vec<String>,
+
+    /// The time after which `man` is killed when listing pages or
+    /// sections.
+    timeout: time::Duration,
+}
+
+impl Default for ManPageCompleter {
+    fn default() -> Self {
+        Self::new(config::SubprocessConfig::default().timeout())
+    }
+}
+
+impl ManPageCompleter {
+    pub fn new(timeout: time::Duration) -> ManPageCompleter {
+        ManPageCompleter {
+            listing: subprocess::Background::stream(move |response_send| {
+                list_pages(timeout, response_send)
+            }),
+            errors: vec![],
+            timeout,
+        }
+    }
+}
+
+impl core::Completer for ManPageCompleter {
+    fn name(&self) -> String {
+        "man".to_owned()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        self.listing.poll_listing(&mut self.errors)
+    }
+
+    fn refresh(&mut self) -> bool {
+        *self = ManPageCompleter::new(self.timeout);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let completion_any = completion.as_any();
+        let page_completion = completion_any.downcast_ref::<ManPageCompletion>().unwrap();
+        Some(Box::new(ManSectionCompleter::new(
+            page_completion.name.as_str(),
+            page_completion.section.as_str(),
+            self.timeout,
+        )))
+    }
+}
+
+struct ManSectionCompletion {
+    page: String,
+    heading: String,
+
+    /// Indicates if the heading is a subsection heading.
+    subsection: bool,
+}
+
+impl core::Completion for ManSectionCompletion {
+    fn result_string(&self) -> String {
+        self.page.clone()
+    }
+
+    fn display_string(&self) -> String {
+        if self.subsection {
+            format!("  {}", self.heading)
+        } else {
+            self.heading.clone()
+        }
+    }
+
+    fn search_string(&self) -> String {
+        self.heading.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Section
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Removes the overstriking used for bold and underlined text from
+/// formatted manual pages, e.g. `N\x08NA\x08AM\x08ME\x08E`.
+fn remove_overstrike(line: &str) -> String {
+    let mut result = String::with_capacity(line.len());
+    for c in line.chars() {
+        if c == '\x08' {
+            result.pop();
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+/// Returns the section and subsection headings of a formatted manual
+/// page, along with a flag indicating subsection headings.
+///
+/// Section headings start at the first column, and subsection
+/// headings are indented by three spaces; the first and the last line
+/// are the header and the footer of the page.
+fn parse_headings(page: &str) -> Vec<(String, bool)> {
+    let lines: Vec<String> = page
+        .lines()
+        .map(remove_overstrike)
+        .filter(|l| !l.trim().is_empty())
+        .collect();
+    if lines.len() < 2 {
+        return vec![];
+    }
+    lines[1..(lines.len() - 1)]
+        .iter()
+        .filter_map(|line| {
+            let indentation = line.len() - line.trim_start().len();
+            match indentation {
+                0 => Some((line.trim_end().to_owned(), false)),
+                3 => Some((line.trim().to_owned(), true)),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_headings() {
+    let page = "LS(1)          User Commands          LS(1)\n\n\
+                N\x08NA\x08AM\x08ME\x08E\n       ls - list directory contents\n\n\
+                DESCRIPTION\n       List information.\n   Exit status:\n       0 if OK\n\n\
+                GNU coreutils 9.4       2024-01-01       LS(1)\n";
+    assert_eq!(
+        parse_headings(page),
+        vec![
+            ("NAME".to_owned(), false),
+            ("DESCRIPTION".to_owned(), false),
+            ("Exit status:".to_owned(), true),
+        ]
+    );
+    assert_eq!(parse_headings(""), vec![]);
+}
+
+/// A completer for the section headings of a manual page.
+struct ManSectionCompleter {
+    page: String,
+    section: String,
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl ManSectionCompleter {
+    fn new<P: Into<String>, S: Into<String>>(
+        page: P,
+        section: S,
+        timeout: time::Duration,
+    ) -> ManSectionCompleter {
+        ManSectionCompleter {
+            page: page.into(),
+            section: section.into(),
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for ManSectionCompleter {
+    fn name(&self) -> String {
+        "sec".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(format!("{}({})", self.page, self.section))
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let result = subprocess::run(
+            Command::new("man")
+                .args([&self.section, &self.page])
+                .env("MANPAGER", "cat")
+                .env("MANWIDTH", "80"),
+            self.timeout,
+        );
+        let output = match result {
+            Result::Ok(o) => o,
+            Result::Err(e) => {
+                self.errors.push(e);
+                return vec![];
+            }
+        };
+        parse_headings(&output)
+            .into_iter()
+            .map(|(heading, subsection)| {
+                Box::new(ManSectionCompletion {
+                    page: self.page.clone(),
+                    heading,
+                    subsection,
+                }) as core::CompletionBox
+            })
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,261 @@
+//! Defines a completer merging the completions of several completers
+//! into one list, so that all of them can be searched at once.
+//!
+//! Completions are fetched from all the underlying completers in turn,
+//! and each of them is tagged with the name of the completer it comes
+//! from.
+
+use std::any;
+
+use crate::core;
+
+/// The width of the column with the name of the source completer.
+const SOURCE_WIDTH: usize = 6;
+
+/// A completion of one of the underlying completers.
+struct MergedCompletion {
+    /// The index of the completer the completion comes from.
+    source: usize,
+    source_name: String,
+    completion: core::CompletionBox,
+}
+
+impl core::Completion for MergedCompletion {
+    fn result_string(&self) -> String {
+        self.completion.result_string()
+    }
+
+    fn display_string(&self) -> String {
+        format!(
+            "{:width$} {}",
+            self.source_name,
+            self.completion.display_string(),
+            width = SOURCE_WIDTH
+        )
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        let mut spans = vec![
+            core::Span::styled(
+                format!("{:width$}", self.source_name, width = SOURCE_WIDTH),
+                core::Style::colored(core::SpanColor::Dimmed),
+            ),
+            core::Span::plain(" "),
+        ];
+        spans.extend(self.completion.display_spans());
+        spans
+    }
+
+    fn search_string(&self) -> String {
+        self.completion.search_string()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.completion.description()
+    }
+
+    fn is_hidden(&self) -> bool {
+        self.completion.is_hidden()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        self.completion.kind()
+    }
+
+    fn file_metadata(&self) -> Option<core::FileMetadata> {
+        self.completion.file_metadata()
+    }
+
+    fn severity(&self) -> Option<core::Severity> {
+        self.completion.severity()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A completer listing the completions of all the given completers.
+pub struct MergedCompleter {
+    completers: Vec<Box<dyn core::Completer>>,
+
+    /// Indicates if completions were fetched from all the completers
+    /// at least once.
+    started: bool,
+}
+
+impl MergedCompleter {
+    /// Creates the completer, merging the completions of those of the
+    /// given completers which are applicable.
+    pub fn new(completers: Vec<Box<dyn core::Completer>>) -> MergedCompleter {
+        MergedCompleter {
+            completers: completers
+                .into_iter()
+                .filter(|c| c.is_applicable())
+                .collect(),
+            started: false,
+        }
+    }
+}
+
+impl core::Completer for MergedCompleter {
+    fn name(&self) -> String {
+        "all".to_owned()
+    }
+
+    fn is_applicable(&self) -> bool {
+        !self.completers.is_empty()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.started
+            && self
+                .completers
+                .iter()
+                .all(|c| c.fetching_completions_finished())
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let mut fetched_completions: Vec<core::CompletionBox> = Vec::new();
+        for (source, completer) in self.completers.iter_mut().enumerate() {
+            // Completers which fetch synchronously report being finished
+            // before the first fetch, so every completer is asked once.
+            if self.started && completer.fetching_completions_finished() {
+                continue;
+            }
+            let source_name = completer.name();
+            fetched_completions.extend(completer.fetch_completions().into_iter().map(|c| {
+                Box::new(MergedCompletion {
+                    source,
+                    source_name: source_name.clone(),
+                    completion: c,
+                }) as core::CompletionBox
+            }));
+        }
+        self.started = true;
+        fetched_completions
+    }
+
+    fn refresh(&mut self) -> bool {
+        self.started = false;
+        // All the completers are refreshed, even if some of them fail.
+        let mut refreshed = true;
+        for c in &mut self.completers {
+            refreshed &= c.refresh();
+        }
+        refreshed
+    }
+
+    fn show_hidden(&mut self, shown: bool) -> bool {
+        // All the completers are told, even if one of them already
+        // needs to be refreshed.
+        let mut refresh = false;
+        for c in &mut self.completers {
+            refresh |= c.show_hidden(shown);
+        }
+        refresh
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        self.completers
+            .iter_mut()
+            .flat_map(|c| {
+                let name = c.name();
+                c.take_errors()
+                    .into_iter()
+                    .map(move |e| format!("{}: {}", name, e))
+            })
+            .collect()
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let completion_any = completion.as_any();
+        let merged_completion = completion_any.downcast_ref::<MergedCompletion>().unwrap();
+        self.completers[merged_completion.source].descend(merged_completion.completion.as_ref())
+    }
+}
+
+#[test]
+fn test_merged_completer() {
+    use crate::core::Completer;
+
+    struct Word(&'static str);
+
+    impl core::Completion for Word {
+        fn result_string(&self) -> String {
+            self.0.to_owned()
+        }
+
+        fn as_any(&self) -> &dyn any::Any {
+            self
+        }
+    }
+
+    /// A completer returning one chunk of words per fetch, which can
+    /// descend into a completer named after the word.
+    struct ChunkCompleter {
+        name: &'static str,
+        chunks: Vec<Vec<&'static str>>,
+    }
+
+    impl core::Completer for ChunkCompleter {
+        fn name(&self) -> String {
+            self.name.to_owned()
+        }
+
+        fn fetching_completions_finished(&self) -> bool {
+            self.chunks.is_empty()
+        }
+
+        fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+            if self.chunks.is_empty() {
+                return vec![];
+            }
+            self.chunks
+                .remove(0)
+                .into_iter()
+                .map(|w| Box::new(Word(w)) as core::CompletionBox)
+                .collect()
+        }
+
+        fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+            let word = completion.as_any().downcast_ref::<Word>().unwrap();
+            Some(Box::new(ChunkCompleter {
+                name: word.0,
+                chunks: vec![],
+            }))
+        }
+    }
+
+    let mut completer = MergedCompleter::new(vec![
+        Box::new(ChunkCompleter {
+            name: "sync",
+            chunks: vec![],
+        }),
+        Box::new(ChunkCompleter {
+            name: "bg",
+            chunks: vec![vec!["a", "b"], vec!["c"]],
+        }),
+    ]);
+    assert!(!completer.fetching_completions_finished());
+    let first = completer.fetch_completions();
+    assert_eq!(
+        first.iter().map(|c| c.result_string()).collect::<Vec<_>>(),
+        vec!["a", "b"]
+    );
+    assert_eq!(first[0].display_string(), "bg     a");
+    assert_eq!(
+        first[0].display_spans(),
+        vec![
+            core::Span::styled("bg    ", core::Style::colored(core::SpanColor::Dimmed)),
+            core::Span::plain(" "),
+            core::Span::plain("a"),
+        ]
+    );
+    assert!(!completer.fetching_completions_finished());
+    let second = completer.fetch_completions();
+    assert_eq!(second.len(), 1);
+    assert!(completer.fetching_completions_finished());
+    let descended = completer.descend(second[0].as_ref()).unwrap();
+    assert_eq!(descended.name(), "c");
+}
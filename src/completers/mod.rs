@@ -1,2 +1,41 @@
+pub mod aws;
+pub mod bash_completion;
+pub mod calc;
+pub mod cargo;
+pub mod clipboard;
+pub mod container;
+pub mod database;
+pub mod datetime;
+pub mod desktop;
+pub mod devices;
 pub mod filesystem;
+pub mod forge;
+pub mod fs_cache;
 pub mod git;
+pub mod hg;
+pub mod history;
+pub mod homebrew;
+pub mod levels;
+pub mod locale;
+pub mod man;
+pub mod merged;
+pub mod nix;
+pub mod npm;
+pub mod options;
+pub mod packages;
+pub mod pass;
+pub mod recent_dirs;
+pub mod recent_files;
+pub mod shared;
+pub mod shell;
+#[cfg(unix)]
+pub mod signal;
+pub mod subprocess;
+pub mod svn;
+pub mod systemd;
+pub mod tasks;
+pub mod terraform;
+pub mod test_names;
+pub mod user;
+pub mod viewer;
+pub mod wireless;
@@ -0,0 +1,311 @@
+//! Defines a completer for Nix installables: the outputs of the flake
+//! in the current directory, e.g. `.#hello`, and the attributes of
+//! nixpkgs, e.g. `nixpkgs#ripgrep`.
+//!
+//! Both are listed with `nix` as JSON. Listing nixpkgs evaluates tens
+//! of thousands of packages, which takes long and prints a lot, so its
+//! output is cached on disk for a day by default. The outputs of the
+//! flake are cached by the daemon until `flake.nix` or `flake.lock`
+//! changes.
+
+use std::any;
+use std::collections::BTreeMap;
+use std::env;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use serde::Deserialize;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// The experimental features which the commands used need.
+const FEATURES: &str = "nix-command flakes";
+
+/// The groups of outputs of flakes completed, which are keyed by the
+/// system first.
+const OUTPUT_GROUPS: &[&str] = &["packages", "apps", "devShells", "checks"];
+
+/// Returns the Nix name of the system the completer runs on, e.g.
+/// `x86_64-linux` or `aarch64-darwin`.
+fn current_system() -> String {
+    let os = match env::consts::OS {
+        "macos" => "darwin",
+        os => os,
+    };
+    format!("{}-{}", env::consts::ARCH, os)
+}
+
+#[derive(Debug, PartialEq)]
+struct NixCompletion {
+    installable: String,
+    description: Option<String>,
+}
+
+impl core::Completion for NixCompletion {
+    fn result_string(&self) -> String {
+        self.installable.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Package
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// An output of a flake in the output of `nix flake show --json`.
+#[derive(Deserialize)]
+struct FlakeOutput {
+    #[serde(default)]
+    description: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+/// Parses the output of `nix flake show --json` into the outputs of
+/// the flake for a system. Outputs named like others of another group,
+/// e.g. a package and an app both named `default`, are listed once.
+fn parse_flake_outputs(json: &str, system: &str) -> Result<Vec<NixCompletion>, String> {
+    type Outputs = BTreeMap<String, BTreeMap<String, serde_json::Value>>;
+    let groups: BTreeMap<String, serde_json::Value> =
+        serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let mut completions: Vec<NixCompletion> = vec![];
+    for group in OUTPUT_GROUPS {
+        let outputs = groups
+            .get(*group)
+            .and_then(|g| serde_json::from_value::<Outputs>(g.clone()).ok())
+            .and_then(|mut g| g.remove(system))
+            .unwrap_or_default();
+        for (name, output) in outputs {
+            let installable = format!(".#{}", name);
+            if completions.iter().any(|c| c.installable == installable) {
+                continue;
+            }
+            let output: Option<FlakeOutput> = serde_json::from_value(output).ok();
+            completions.push(NixCompletion {
+                installable,
+                description: output.and_then(|o| o.description.or(o.name)),
+            });
+        }
+    }
+    Result::Ok(completions)
+}
+
+#[test]
+fn test_parse_flake_outputs() {
+    let json = r#"{
+        "packages": {
+            "x86_64-linux": {
+                "default": {"name": "hello-2.12", "type": "derivation"},
+                "tool": {"description": "A tool", "name": "tool-1.0", "type": "derivation"}
+            },
+            "aarch64-darwin": {"other": {"type": "derivation"}}
+        },
+        "apps": {"x86_64-linux": {"default": {"type": "app"}, "serve": {"type": "app"}}},
+        "lib": {"type": "unknown"}
+    }"#;
+    assert_eq!(
+        parse_flake_outputs(json, "x86_64-linux"),
+        Result::Ok(vec![
+            NixCompletion {
+                installable: ".#default".to_owned(),
+                description: Some("hello-2.12".to_owned()),
+            },
+            NixCompletion {
+                installable: ".#tool".to_owned(),
+                description: Some("A tool".to_owned()),
+            },
+            NixCompletion {
+                installable: ".#serve".to_owned(),
+                description: None,
+            },
+        ])
+    );
+    assert!(parse_flake_outputs("[]", "x86_64-linux").is_err());
+}
+
+/// A package in the output of `nix search --json`.
+#[derive(Deserialize)]
+struct SearchResult {
+    #[serde(default)]
+    version: String,
+    #[serde(default)]
+    description: String,
+}
+
+/// Parses the output of `nix search nixpkgs --json`, keyed by the paths
+/// of packages, like `legacyPackages.x86_64-linux.python3Packages.pip`,
+/// into installables like `nixpkgs#python3Packages.pip`.
+fn parse_search(json: &str) -> Result<Vec<NixCompletion>, String> {
+    let results: BTreeMap<String, SearchResult> =
+        serde_json::from_str(json).map_err(|e| e.to_string())?;
+    Result::Ok(
+        results
+            .into_iter()
+            .filter_map(|(path, result)| {
+                // The path starts with the group and the system.
+                let attribute = path.splitn(3, '.').nth(2)?;
+                let description = match (result.version.is_empty(), result.description.is_empty()) {
+                    (false, false) => Some(format!("{} {}", result.version, result.description)),
+                    (false, true) => Some(result.version),
+                    (true, false) => Some(result.description),
+                    (true, true) => None,
+                };
+                Some(NixCompletion {
+                    installable: format!("nixpkgs#{}", attribute),
+                    description,
+                })
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn test_parse_search() {
+    let json = r#"{
+        "legacyPackages.x86_64-linux.hello": {
+            "pname": "hello", "version": "2.12.1", "description": "A program that produces a familiar, friendly greeting"
+        },
+        "legacyPackages.x86_64-linux.python3Packages.pip": {"pname": "pip", "version": "", "description": ""}
+    }"#;
+    assert_eq!(
+        parse_search(json),
+        Result::Ok(vec![
+            NixCompletion {
+                installable: "nixpkgs#hello".to_owned(),
+                description: Some(
+                    "2.12.1 A program that produces a familiar, friendly greeting".to_owned()
+                ),
+            },
+            NixCompletion {
+                installable: "nixpkgs#python3Packages.pip".to_owned(),
+                description: None,
+            },
+        ])
+    );
+}
+
+/// Returns a `nix` command with the experimental features it needs.
+fn nix_command(args: &[&str]) -> Command {
+    let mut command = Command::new("nix");
+    command.args(["--extra-experimental-features", FEATURES]);
+    command.args(args);
+    command
+}
+
+/// Lists the outputs of the flake in the current directory, if any,
+/// then the packages of nixpkgs, sending the completions or the error
+/// of each listing.
+fn list_installables(
+    use_cache: bool,
+    config: &config::NixConfig,
+    sender: &mpsc::Sender<Result<Vec<NixCompletion>, String>>,
+) {
+    if let Result::Ok(dir) = env::current_dir() {
+        if dir.join("flake.nix").is_file() {
+            let watched = [dir.join("flake.nix"), dir.join("flake.lock")];
+            let outputs = subprocess::run_cached(
+                &mut nix_command(&["flake", "show", "--json"]),
+                config.timeout(),
+                &watched,
+            )
+            .and_then(|o| parse_flake_outputs(&o, &current_system()));
+            if sender.send(outputs).is_err() {
+                return;
+            }
+        }
+    }
+    let packages = subprocess::run_expiring(
+        &mut nix_command(&["search", "nixpkgs", "^", "--json"]),
+        config.timeout(),
+        "nix",
+        time::Duration::from_secs(config.cache_secs),
+        use_cache,
+    )
+    .and_then(|o| parse_search(&o));
+    let _ = sender.send(packages);
+}
+
+/// A completer for the outputs of the flake in the current directory
+/// and the packages of nixpkgs.
+pub struct NixCompleter {
+    config: config::NixConfig,
+
+    /// Receives the results of the listings running in the background,
+    /// until they are all received.
+    receiver: Option<mpsc::Receiver<Result<Vec<NixCompletion>, String>>>,
+    errors: Vec<String>,
+}
+
+impl NixCompleter {
+    pub fn new(config: config::NixConfig) -> Self {
+        let mut completer = NixCompleter {
+            config,
+            receiver: None,
+            errors: vec![],
+        };
+        completer.start(true);
+        completer
+    }
+
+    fn start(&mut self, use_cache: bool) {
+        let config = self.config.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || list_installables(use_cache, &config, &sender));
+        self.receiver = Some(receiver);
+    }
+}
+
+impl core::Completer for NixCompleter {
+    fn name(&self) -> String {
+        "nix".to_owned()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.receiver.is_none()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let mut completions: Vec<core::CompletionBox> = vec![];
+        let receiver = match self.receiver.take() {
+            Some(r) => r,
+            None => return completions,
+        };
+        loop {
+            match receiver.try_recv() {
+                Result::Ok(Result::Ok(listed)) => completions.extend(
+                    listed
+                        .into_iter()
+                        .map(|c| Box::new(c) as core::CompletionBox),
+                ),
+                Result::Ok(Result::Err(e)) => self.errors.push(e),
+                Result::Err(mpsc::TryRecvError::Empty) => {
+                    self.receiver = Some(receiver);
+                    break;
+                }
+                Result::Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+        }
+        completions
+    }
+
+    /// Lists the installables anew, bypassing the cache of nixpkgs.
+    fn refresh(&mut self) -> bool {
+        self.start(false);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,122 @@
+//! Defines a completer for the scripts of a Node.js package, listed
+//! under `scripts` in the `package.json` file of the current directory.
+
+use std::any;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path;
+
+use log::warn;
+use serde::Deserialize;
+
+use crate::core;
+
+/// The part of `package.json` which the completer reads.
+#[derive(Deserialize)]
+struct Package {
+    #[serde(default)]
+    scripts: BTreeMap<String, String>,
+}
+
+struct NpmScriptCompletion {
+    name: String,
+
+    /// The command run by the script.
+    command: String,
+}
+
+impl core::Completion for NpmScriptCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(self.command.clone())
+    }
+
+    fn search_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Script
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the names and commands of the scripts in a `package.json`
+/// file, ordered by name.
+fn parse_scripts(json: &str) -> Result<Vec<(String, String)>, String> {
+    serde_json::from_str::<Package>(json)
+        .map(|p| p.scripts.into_iter().collect())
+        .map_err(|e| e.to_string())
+}
+
+#[test]
+fn test_parse_scripts() {
+    let json = r#"{
+        "name": "app",
+        "dependencies": { "scripts": "1.0.0" },
+        "scripts": { "test": "jest", "build": "tsc -p \"src\"" }
+    }"#;
+    assert_eq!(
+        parse_scripts(json),
+        Result::Ok(vec![
+            ("build".to_owned(), "tsc -p \"src\"".to_owned()),
+            ("test".to_owned(), "jest".to_owned()),
+        ])
+    );
+    assert_eq!(parse_scripts(r#"{"name": "app"}"#), Result::Ok(vec![]));
+    assert!(parse_scripts(r#"{"scripts": ["test"]}"#).is_err());
+    assert!(parse_scripts("{").is_err());
+}
+
+pub struct NpmScriptCompleter {
+    package_path: path::PathBuf,
+}
+
+impl NpmScriptCompleter {
+    /// Creates a completer of the scripts of the package in the given
+    /// directory.
+    pub fn new<P: AsRef<path::Path>>(dir_path: P) -> Self {
+        NpmScriptCompleter {
+            package_path: dir_path.as_ref().join("package.json"),
+        }
+    }
+}
+
+impl core::Completer for NpmScriptCompleter {
+    fn name(&self) -> String {
+        "npm".to_owned()
+    }
+
+    fn is_applicable(&self) -> bool {
+        self.package_path.is_file()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let json = match fs::read_to_string(&self.package_path) {
+            Result::Ok(j) => j,
+            Result::Err(_) => return vec![],
+        };
+        match parse_scripts(&json) {
+            Result::Ok(scripts) => scripts
+                .into_iter()
+                .map(|(name, command)| {
+                    Box::new(NpmScriptCompletion { name, command }) as core::CompletionBox
+                })
+                .collect(),
+            Result::Err(e) => {
+                warn!("Failed to parse {}: {}", self.package_path.display(), e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+}
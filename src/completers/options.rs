@@ -0,0 +1,355 @@
+//! Defines a completer for the options of commands, parsed from their
+//! help, the output of `<command> --help`, or from their manual pages
+//! if the help lists no options.
+//!
+//! The help is cached by the daemon as long as the executable of the
+//! command keeps its modification time, i.e. for each version of the
+//! command. Manual pages are formatted by the session, since their
+//! width is set in the environment of `man`, which the daemon doesn't
+//! get.
+
+use std::any;
+use std::env;
+use std::ffi;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::core;
+
+/// The width of the manual pages, wide enough for descriptions not to
+/// be wrapped.
+const MAN_WIDTH: &str = "400";
+
+/// Returns the words of the command whose options are completed, e.g.
+/// `git commit`: the command and its subcommand, if the word following
+/// the command looks like one rather than like an argument.
+fn option_command_words<'a>(command_words: &[&'a str]) -> Vec<&'a str> {
+    let is_subcommand = |w: &&str| {
+        w.chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            && !w.starts_with('-')
+    };
+    match command_words {
+        [] => vec![],
+        [command, subcommand, ..] if is_subcommand(subcommand) => vec![command, subcommand],
+        [command, ..] => vec![command],
+    }
+}
+
+#[test]
+fn test_option_command_words() {
+    assert_eq!(
+        option_command_words(&["git", "commit", "-a"]),
+        vec!["git", "commit"]
+    );
+    assert_eq!(option_command_words(&["ls", "-l"]), vec!["ls"]);
+    assert_eq!(option_command_words(&["cat", "notes.txt"]), vec!["cat"]);
+    assert_eq!(option_command_words(&[]), Vec::<&str>::new());
+}
+
+/// Removes the overstrikes which make text bold or underlined in manual
+/// pages formatted for terminals, e.g. `a\x08a`.
+fn strip_overstrikes(text: &str) -> String {
+    let mut stripped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if c == '\x08' {
+            stripped.pop();
+        } else {
+            stripped.push(c);
+        }
+    }
+    stripped
+}
+
+#[test]
+fn test_strip_overstrikes() {
+    assert_eq!(strip_overstrikes("-\x08--\x08-a\x08al\x08ll\x08l"), "--all");
+    assert_eq!(strip_overstrikes("_\x08file"), "file");
+}
+
+#[derive(Debug, PartialEq)]
+struct OptionCompletion {
+    option: String,
+    description: Option<String>,
+}
+
+impl core::Completion for OptionCompletion {
+    fn result_string(&self) -> String {
+        self.option.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the options named by a word of the list of options on a
+/// line of help, e.g. `--color` for `--color[=WHEN]`, and both
+/// `--quiet` and `--no-quiet` for `--[no-]quiet`.
+fn option_names(word: &str) -> Vec<String> {
+    let word = word.trim_end_matches([',', ';', ':']);
+    let (word, negated) = match word.strip_prefix("--[no-]") {
+        Some(rest) => (format!("--{}", rest), true),
+        None => (word.to_owned(), false),
+    };
+    let end = word.find(['=', '[', '<']).unwrap_or(word.len());
+    let option = &word[..end];
+    let name = option.trim_start_matches('-');
+    let is_valid = !name.is_empty()
+        && option.len() - name.len() <= 2
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+    if !is_valid {
+        return vec![];
+    }
+    if negated {
+        vec![option.to_owned(), format!("--no-{}", name)]
+    } else {
+        vec![option.to_owned()]
+    }
+}
+
+/// Parses the options listed in help or a manual page: lines starting
+/// with options, followed by their description after at least two
+/// spaces, or on the next, more indented, line.
+fn parse_options(help: &str) -> Vec<OptionCompletion> {
+    let lines: Vec<&str> = help.lines().collect();
+    let indent = |l: &str| l.len() - l.trim_start().len();
+    let mut options: Vec<OptionCompletion> = vec![];
+    for (i, line) in lines.iter().enumerate() {
+        let trimmed = line.trim();
+        if !trimmed.starts_with('-') || trimmed.starts_with("---") {
+            continue;
+        }
+        let (list, description) = match trimmed.find("  ").or_else(|| trimmed.find('\t')) {
+            Some(p) => (&trimmed[..p], trimmed[p..].trim()),
+            None => (trimmed, ""),
+        };
+        let description = if !description.is_empty() {
+            Some(description.to_owned())
+        } else {
+            lines
+                .get(i + 1)
+                .filter(|l| indent(l) > indent(line) && !l.trim().starts_with('-'))
+                .map(|l| l.trim().to_owned())
+                .filter(|d| !d.is_empty())
+        };
+        let names = list
+            .split([',', ' '])
+            .filter(|w| w.starts_with('-'))
+            .flat_map(option_names);
+        for option in names {
+            if !options.iter().any(|o| o.option == option) {
+                options.push(OptionCompletion {
+                    option,
+                    description: description.clone(),
+                });
+            }
+        }
+    }
+    options
+}
+
+#[test]
+fn test_parse_options() {
+    let option = |option: &str, description: Option<&str>| OptionCompletion {
+        option: option.to_owned(),
+        description: description.map(String::from),
+    };
+    let help = "Usage: ls [OPTION]... [FILE]...
+  -a, --all                  do not ignore entries starting with .
+      --color[=WHEN]         color the output WHEN
+  -I, --ignore=PATTERN       do not list implied entries matching PATTERN
+---
+";
+    assert_eq!(
+        parse_options(help),
+        vec![
+            option("-a", Some("do not ignore entries starting with .")),
+            option("--all", Some("do not ignore entries starting with .")),
+            option("--color", Some("color the output WHEN")),
+            option("-I", Some("do not list implied entries matching PATTERN")),
+            option(
+                "--ignore",
+                Some("do not list implied entries matching PATTERN")
+            ),
+        ]
+    );
+
+    let help = "    -q, --[no-]quiet      suppress summary after successful commit
+    --no-verify           bypass pre-commit and commit-msg hooks
+";
+    assert_eq!(
+        parse_options(help)
+            .iter()
+            .map(|o| o.option.as_str())
+            .collect::<Vec<_>>(),
+        vec!["-q", "--quiet", "--no-quiet", "--no-verify"]
+    );
+
+    let man_page = "OPTIONS
+       -n, --dry-run
+              Don't actually add the file(s).
+
+       --ignore-errors
+";
+    assert_eq!(
+        parse_options(man_page),
+        vec![
+            option("-n", Some("Don't actually add the file(s).")),
+            option("--dry-run", Some("Don't actually add the file(s).")),
+            option("--ignore-errors", None),
+        ]
+    );
+}
+
+/// Returns the path of the executable of a program, found in the
+/// directories of `path_var`. Programs given as paths, e.g. scripts of
+/// the current directory, are not found.
+fn find_executable(program: &str, path_var: Option<&ffi::OsStr>) -> Option<path::PathBuf> {
+    if program.contains(path::MAIN_SEPARATOR) {
+        return None;
+    }
+    env::split_paths(path_var?)
+        .map(|d| d.join(program))
+        .find(|p| p.is_file())
+}
+
+#[test]
+fn test_find_executable() {
+    use std::fs;
+    let dir = env::temp_dir().join(format!("completers-options-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("tool"), "").unwrap();
+    let path_var = env::join_paths([path::Path::new("/nonexistent"), dir.as_path()]).unwrap();
+    assert_eq!(
+        find_executable("tool", Some(&path_var)),
+        Some(dir.join("tool"))
+    );
+    assert_eq!(find_executable("missing", Some(&path_var)), None);
+    assert_eq!(find_executable("./tool", Some(&path_var)), None);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A completer of the options of a command.
+pub struct OptionCompleter {
+    /// The command and its subcommand, if any.
+    command_words: Vec<String>,
+
+    /// The time after which the command or `man` is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl OptionCompleter {
+    pub fn new(command_words: &[&str], timeout: time::Duration) -> Self {
+        OptionCompleter {
+            command_words: option_command_words(command_words)
+                .iter()
+                .map(|w| w.to_string())
+                .collect(),
+            timeout,
+            errors: vec![],
+        }
+    }
+
+    /// Returns the options listed in the help of the command, or in its
+    /// manual page, named like the command and its subcommand joined
+    /// with a dash, e.g. `git-commit`.
+    fn list(&self) -> Result<Vec<OptionCompletion>, String> {
+        let program = &self.command_words[0];
+        let watched: Vec<path::PathBuf> = find_executable(program, env::var_os("PATH").as_deref())
+            .into_iter()
+            .collect();
+        let help = subprocess::run_cached(
+            Command::new(program)
+                .args(&self.command_words[1..])
+                .arg("--help"),
+            self.timeout,
+            &watched,
+        );
+        if let Result::Ok(ref h) = help {
+            let options = parse_options(h);
+            if !options.is_empty() {
+                return Result::Ok(options);
+            }
+        }
+        subprocess::run_cached(
+            &mut man_command(&self.command_words.join("-")),
+            self.timeout,
+            &watched,
+        )
+        .map(|m| parse_options(&strip_overstrikes(&m)))
+    }
+}
+
+/// Returns the command printing the given manual page, without paging
+/// and without wrapping its lines.
+fn man_command(page: &str) -> Command {
+    let mut command = Command::new("man");
+    command
+        .env("MANWIDTH", MAN_WIDTH)
+        .args(["-P", "cat"])
+        .arg(page);
+    command
+}
+
+#[test]
+#[cfg(unix)]
+fn test_man_command() {
+    use crate::daemon;
+
+    // The width of the page would be lost in the daemon.
+    let timeout = time::Duration::from_secs(10);
+    let page = "wrapped at the width of the daemon";
+    let result = subprocess::run_in_daemon(
+        daemon::fake_client(page),
+        &mut man_command("ls"),
+        timeout,
+        &[env::temp_dir()],
+    );
+    assert_ne!(result, Result::Ok(page.to_owned()));
+}
+
+impl core::Completer for OptionCompleter {
+    fn name(&self) -> String {
+        "opt".to_owned()
+    }
+
+    /// Applies to the programs installed in `PATH`, so that typing the
+    /// options of other ones, e.g. scripts, doesn't run them.
+    fn is_applicable(&self) -> bool {
+        self.command_words
+            .first()
+            .and_then(|p| find_executable(p, env::var_os("PATH").as_deref()))
+            .is_some()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        match self.list() {
+            Result::Ok(options) => options
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
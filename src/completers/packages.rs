@@ -0,0 +1,243 @@
+//! Defines a completer for the packages of the system package manager:
+//! APT, DNF, pacman or Homebrew, whichever is installed.
+//!
+//! Installed packages are listed first, followed by the packages
+//! available from the repositories. The repositories of a system can
+//! hold tens of thousands of packages, so they are listed in a
+//! background thread and passed to the UI in chunks.
+
+use std::any;
+use std::collections::HashSet;
+use std::env;
+use std::ffi;
+use std::path;
+use std::process::Command;
+use std::sync::mpsc;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// The number of packages sent from the fetching thread at once.
+const CHUNK_SIZE: usize = 1000;
+
+/// A package manager, along with the commands listing the names of
+/// packages, one per line.
+#[derive(Debug, PartialEq)]
+struct PackageManager {
+    name: &'static str,
+    installed_command: &'static [&'static str],
+    available_command: &'static [&'static str],
+}
+
+/// The supported package managers, in the order of detection.
+const PACKAGE_MANAGERS: &[PackageManager] = &[
+    PackageManager {
+        name: "apt",
+        installed_command: &["dpkg-query", "--show", "--showformat=${Package}\\n"],
+        available_command: &["apt-cache", "pkgnames"],
+    },
+    PackageManager {
+        name: "dnf",
+        installed_command: &["rpm", "--query", "--all", "--queryformat=%{NAME}\\n"],
+        available_command: &["dnf", "repoquery", "--quiet", "--queryformat=%{name}"],
+    },
+    PackageManager {
+        name: "pacman",
+        installed_command: &["pacman", "-Qq"],
+        available_command: &["pacman", "-Slq"],
+    },
+    PackageManager {
+        name: "brew",
+        installed_command: &["brew", "list", "-1"],
+        available_command: &["brew", "formulae"],
+    },
+];
+
+/// Returns the package manager with the given name, or the first one
+/// whose command is found in the directories of `path_var`.
+fn detect_package_manager(
+    name: Option<&str>,
+    path_var: Option<&ffi::OsStr>,
+) -> Option<&'static PackageManager> {
+    if let Some(n) = name {
+        return PACKAGE_MANAGERS.iter().find(|m| m.name == n);
+    }
+    let dirs: Vec<path::PathBuf> = path_var.map(|p| env::split_paths(p).collect())?;
+    PACKAGE_MANAGERS
+        .iter()
+        .find(|m| dirs.iter().any(|d| d.join(m.name).is_file()))
+}
+
+#[test]
+fn test_detect_package_manager() {
+    use std::fs;
+    let dir = env::temp_dir().join(format!("completers-packages-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join("pacman"), "").unwrap();
+    let path_var = env::join_paths([path::Path::new("/nonexistent"), dir.as_path()]).unwrap();
+    assert_eq!(
+        detect_package_manager(None, Some(&path_var)).map(|m| m.name),
+        Some("pacman")
+    );
+    assert_eq!(
+        detect_package_manager(Some("brew"), Some(&path_var)).map(|m| m.name),
+        Some("brew")
+    );
+    assert_eq!(detect_package_manager(Some("zypper"), None), None);
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(detect_package_manager(None, Some(&path_var)), None);
+}
+
+struct PackageCompletion {
+    name: String,
+    installed: bool,
+}
+
+impl core::Completion for PackageCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        let style = if self.installed {
+            core::Style::colored(core::SpanColor::Kind(core::CompletionKind::Package))
+        } else {
+            core::Style::default()
+        };
+        vec![core::Span::styled(self.name.as_str(), style)]
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Package
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Runs a command listing package names, sending the packages which
+/// were not listed yet through `response_send` in chunks, or the
+/// failure of the command.
+///
+/// Returns `false` if the receiving end is gone and fetching should
+/// be stopped.
+fn list_packages(
+    command: &[&str],
+    installed: bool,
+    timeout: time::Duration,
+    listed: &mut HashSet<String>,
+    response_send: &mpsc::Sender<Result<Vec<core::CompletionBox>, String>>,
+) -> bool {
+    let output = match subprocess::run(Command::new(command[0]).args(&command[1..]), timeout) {
+        Result::Ok(o) => o,
+        Result::Err(e) => return response_send.send(Result::Err(e)).is_ok(),
+    };
+    let mut chunk: Vec<core::CompletionBox> = vec![];
+    for line in output.lines() {
+        let name = line.trim();
+        if name.is_empty() || !listed.insert(name.to_owned()) {
+            continue;
+        }
+        chunk.push(Box::new(PackageCompletion {
+            name: name.to_owned(),
+            installed,
+        }));
+        if chunk.len() < CHUNK_SIZE {
+            continue;
+        }
+        if response_send
+            .send(Result::Ok(std::mem::take(&mut chunk)))
+            .is_err()
+        {
+            return false;
+        }
+    }
+    response_send.send(Result::Ok(chunk)).is_ok()
+}
+
+/// A completer for installed and available packages.
+pub struct PackageCompleter {
+    packages_config: config::PackagesConfig,
+
+    /// Lists the packages in the background, in chunks.
+    listing: subprocess::Background<Result<Vec<core::CompletionBox>, String>>,
+    errors: Vec<String>,
+
+    /// The time after which the commands listing packages are killed.
+    timeout: time::Duration,
+
+    /// Indicates if a package manager was found.
+    has_manager: bool,
+}
+
+impl PackageCompleter {
+    pub fn new(
+        packages_config: &config::PackagesConfig,
+        timeout: time::Duration,
+    ) -> PackageCompleter {
+        let manager = detect_package_manager(
+            packages_config.manager.as_deref(),
+            env::var_os("PATH").as_deref(),
+        );
+        let has_manager = manager.is_some();
+        let listing = subprocess::Background::stream(move |response_send| {
+            let manager = match manager {
+                Some(m) => m,
+                None => return,
+            };
+            let mut listed = HashSet::new();
+            if list_packages(
+                manager.installed_command,
+                true,
+                timeout,
+                &mut listed,
+                response_send,
+            ) {
+                list_packages(
+                    manager.available_command,
+                    false,
+                    timeout,
+                    &mut listed,
+                    response_send,
+                );
+            }
+        });
+        PackageCompleter {
+            packages_config: packages_config.clone(),
+            listing,
+            errors: vec![],
+            timeout,
+            has_manager,
+        }
+    }
+}
+
+impl core::Completer for PackageCompleter {
+    fn name(&self) -> String {
+        "pkg".to_owned()
+    }
+
+    fn is_applicable(&self) -> bool {
+        self.has_manager
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        self.listing.poll_listing(&mut self.errors)
+    }
+
+    fn refresh(&mut self) -> bool {
+        *self = PackageCompleter::new(&self.packages_config, self.timeout);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,243 @@
+//! Defines a completer for the entries of the password store of `pass`,
+//! e.g. `email/github` for `~/.password-store/email/github.gpg`.
+//!
+//! The store is searched like any directory, by the file system
+//! completer, whose completions are turned into entry names. Only names
+//! are listed; the encrypted files are never read.
+
+use std::any;
+use std::env;
+use std::path;
+
+use crate::completers::filesystem;
+use crate::config;
+use crate::core;
+
+/// The extension of the files of entries.
+const ENTRY_EXTENSION: &str = ".gpg";
+
+/// Returns the directory of the password store.
+pub fn store_dir() -> Option<path::PathBuf> {
+    env::var_os("PASSWORD_STORE_DIR")
+        .map(path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".password-store")))
+}
+
+struct PassCompletion {
+    /// The path of the entry relative to the store, without the
+    /// extension for files.
+    name: String,
+    is_dir: bool,
+}
+
+impl core::Completion for PassCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        // The directories leading to the entry are dimmed, as for files.
+        let name_start = self.name.rfind('/').map_or(0, |i| i + 1);
+        let mut spans = vec![];
+        if name_start > 0 {
+            spans.push(core::Span::styled(
+                &self.name[..name_start],
+                core::Style::colored(core::SpanColor::Dimmed),
+            ));
+        }
+        let style = if self.is_dir {
+            core::Style::colored(core::SpanColor::Kind(self.kind()))
+        } else {
+            core::Style::default()
+        };
+        spans.push(core::Span::styled(&self.name[name_start..], style));
+        spans
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        if self.is_dir {
+            core::CompletionKind::Directory
+        } else {
+            core::CompletionKind::File
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the completion of an entry from the completion of its path,
+/// or None for other files, e.g. `.gpg-id` or the ones of git.
+fn entry_completion(
+    store_dir: &path::Path,
+    completion: &dyn core::Completion,
+) -> Option<PassCompletion> {
+    if completion.is_hidden() {
+        return None;
+    }
+    let file_path = path::PathBuf::from(completion.result_string());
+    let relative_path = file_path.strip_prefix(store_dir).ok()?.to_str()?;
+    let is_dir = completion.kind() == core::CompletionKind::Directory;
+    let name = if is_dir {
+        relative_path
+    } else {
+        relative_path.strip_suffix(ENTRY_EXTENSION)?
+    };
+    Some(PassCompletion {
+        name: name.replace(path::MAIN_SEPARATOR, "/"),
+        is_dir,
+    })
+}
+
+#[test]
+fn test_entry_completion() {
+    struct Completion {
+        path: &'static str,
+        kind: core::CompletionKind,
+        hidden: bool,
+    }
+
+    impl core::Completion for Completion {
+        fn result_string(&self) -> String {
+            self.path.to_owned()
+        }
+
+        fn kind(&self) -> core::CompletionKind {
+            self.kind
+        }
+
+        fn is_hidden(&self) -> bool {
+            self.hidden
+        }
+
+        fn as_any(&self) -> &dyn any::Any {
+            self
+        }
+    }
+
+    let store_dir = path::Path::new("/home/u/.password-store");
+    let name = |path: &'static str, kind: core::CompletionKind, hidden: bool| {
+        entry_completion(store_dir, &Completion { path, kind, hidden }).map(|c| (c.name, c.is_dir))
+    };
+    assert_eq!(
+        name(
+            "/home/u/.password-store/email/github.gpg",
+            core::CompletionKind::File,
+            false
+        ),
+        Some(("email/github".to_owned(), false))
+    );
+    assert_eq!(
+        name(
+            "/home/u/.password-store/email",
+            core::CompletionKind::Directory,
+            false
+        ),
+        Some(("email".to_owned(), true))
+    );
+    assert_eq!(
+        name(
+            "/home/u/.password-store/.gpg-id",
+            core::CompletionKind::File,
+            true
+        ),
+        None
+    );
+    assert_eq!(
+        name(
+            "/home/u/.password-store/notes.txt",
+            core::CompletionKind::File,
+            false
+        ),
+        None
+    );
+}
+
+/// A completer for the entries of the password store under a directory
+/// of the store.
+pub struct PassCompleter {
+    store_dir: path::PathBuf,
+    dir_path: path::PathBuf,
+    fs_completer: filesystem::FsCompleter,
+}
+
+impl PassCompleter {
+    pub fn new(store_dir: path::PathBuf) -> Self {
+        PassCompleter::in_dir(store_dir.clone(), store_dir)
+    }
+
+    fn in_dir(store_dir: path::PathBuf, dir_path: path::PathBuf) -> Self {
+        let fs_config = config::FsConfig {
+            // The names of entries are secrets of a kind too, so they
+            // are not written to the cache of directory listings.
+            cache: false,
+            dirs_only: false,
+            show_hidden: false,
+            ..config::FsConfig::default()
+        };
+        PassCompleter {
+            fs_completer: filesystem::FsCompleter::new(dir_path.clone(), fs_config),
+            store_dir,
+            dir_path,
+        }
+    }
+}
+
+impl core::Completer for PassCompleter {
+    fn name(&self) -> String {
+        "pass".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        let relative_dir = self.dir_path.strip_prefix(&self.store_dir).ok()?;
+        if relative_dir.as_os_str().is_empty() {
+            None
+        } else {
+            Some(relative_dir.display().to_string())
+        }
+    }
+
+    fn is_applicable(&self) -> bool {
+        self.store_dir.is_dir()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.fs_completer.fetching_completions_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        self.fs_completer
+            .fetch_completions()
+            .iter()
+            .filter_map(|c| entry_completion(&self.store_dir, c.as_ref()))
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        self.fs_completer.refresh()
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let pass_completion = completion.as_any().downcast_ref::<PassCompletion>()?;
+        if !pass_completion.is_dir {
+            return None;
+        }
+        Some(Box::new(PassCompleter::in_dir(
+            self.store_dir.clone(),
+            self.store_dir.join(&pass_completion.name),
+        )))
+    }
+
+    fn ascend(&self) -> Option<Box<dyn core::Completer>> {
+        if self.dir_path == self.store_dir {
+            return None;
+        }
+        let parent = self.dir_path.parent()?.to_path_buf();
+        Some(Box::new(PassCompleter::in_dir(
+            self.store_dir.clone(),
+            parent,
+        )))
+    }
+}
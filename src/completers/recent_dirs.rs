@@ -0,0 +1,287 @@
+//! Defines a completer for recently visited directories.
+//!
+//! The directories are read from the databases of directory jumping
+//! tools: z (`~/.z`), autojump (`autojump.txt`) and zoxide (through
+//! `zoxide query`), and ranked by frecency, i.e. by how often and how
+//! recently they were visited. A directory known to several tools is
+//! ranked by the sum of its frecencies.
+
+use std::any;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use log::warn;
+
+use crate::completers::filesystem;
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+struct RecentDirCompletion {
+    path: String,
+    frecency: f64,
+}
+
+impl core::Completion for RecentDirCompletion {
+    fn result_string(&self) -> String {
+        self.path.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("{:.1}", self.frecency))
+    }
+
+    fn search_string(&self) -> String {
+        self.path.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Directory
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Computes the frecency of a directory in the z database from its
+/// rank and the time of the last visit, the way z does.
+fn z_frecency(rank: f64, last_visit: u64, now: u64) -> f64 {
+    let age = now.saturating_sub(last_visit);
+    if age < 3600 {
+        rank * 4.0
+    } else if age < 86400 {
+        rank * 2.0
+    } else if age < 604_800 {
+        rank / 2.0
+    } else {
+        rank / 4.0
+    }
+}
+
+/// Parses the z database, with lines `<path>|<rank>|<time>`.
+fn parse_z(data: &str, now: u64) -> Vec<(String, f64)> {
+    data.lines()
+        .filter_map(|line| {
+            let mut fields = line.rsplitn(3, '|');
+            let last_visit = fields.next()?.parse().ok()?;
+            let rank = fields.next()?.parse().ok()?;
+            let path = fields.next()?;
+            Some((path.to_owned(), z_frecency(rank, last_visit, now)))
+        })
+        .collect()
+}
+
+/// Parses the autojump database, with lines `<weight>\t<path>`.
+fn parse_autojump(data: &str) -> Vec<(String, f64)> {
+    data.lines()
+        .filter_map(|line| {
+            let (weight, path) = line.split_once('\t')?;
+            Some((path.to_owned(), weight.parse().ok()?))
+        })
+        .collect()
+}
+
+/// Parses the output of `zoxide query --list --score`, with lines
+/// `<score> <path>` and the score padded with spaces.
+fn parse_zoxide(output: &str) -> Vec<(String, f64)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let (score, path) = line.trim_start().split_once(' ')?;
+            Some((path.to_owned(), score.parse().ok()?))
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_databases() {
+    let now = 1_000_000;
+    assert_eq!(
+        parse_z(
+            "/home/u/src|10|999000\n/tmp|3.5|100\ninvalid\n/a|b|c|2|999999\n",
+            now
+        ),
+        vec![
+            ("/home/u/src".to_owned(), 40.0),
+            ("/tmp".to_owned(), 0.875),
+            ("/a|b|c".to_owned(), 8.0),
+        ]
+    );
+    assert_eq!(
+        parse_autojump("22.4\t/home/u/src\n10.0\t/with\ttab\nx\t/y\n"),
+        vec![
+            ("/home/u/src".to_owned(), 22.4),
+            ("/with\ttab".to_owned(), 10.0),
+        ]
+    );
+    assert_eq!(
+        parse_zoxide("  12.5 /home/u/my dir\n 100.0 /tmp\n"),
+        vec![
+            ("/home/u/my dir".to_owned(), 12.5),
+            ("/tmp".to_owned(), 100.0),
+        ]
+    );
+}
+
+fn home_dir() -> Option<path::PathBuf> {
+    env::var_os("HOME").map(path::PathBuf::from)
+}
+
+fn z_database_path() -> Option<path::PathBuf> {
+    env::var_os("_Z_DATA")
+        .map(path::PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(".z")))
+}
+
+fn autojump_database_path() -> Option<path::PathBuf> {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .map(path::PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(".local").join("share")))?;
+    Some(data_dir.join("autojump").join("autojump.txt"))
+}
+
+/// Returns the path of the database of zoxide, which the output of
+/// `zoxide query` is cached by.
+fn zoxide_database_path() -> Option<path::PathBuf> {
+    let data_dir = env::var_os("_ZO_DATA_DIR")
+        .map(path::PathBuf::from)
+        .or_else(|| {
+            env::var_os("XDG_DATA_HOME")
+                .map(path::PathBuf::from)
+                .or_else(|| home_dir().map(|h| h.join(".local").join("share")))
+                .map(|d| d.join("zoxide"))
+        })?;
+    Some(data_dir.join("db.zo"))
+}
+
+/// Reads the database of the given directory jumping tool. Missing
+/// databases are ignored, since most users have just one of the tools.
+fn read_source(source: &str, now: u64, timeout: time::Duration) -> Vec<(String, f64)> {
+    let read = |database_path: Option<path::PathBuf>| {
+        database_path.and_then(|p| fs::read_to_string(p).ok())
+    };
+    match source {
+        "z" => read(z_database_path())
+            .map(|d| parse_z(&d, now))
+            .unwrap_or_default(),
+        "autojump" => read(autojump_database_path())
+            .map(|d| parse_autojump(&d))
+            .unwrap_or_default(),
+        "zoxide" => subprocess::run_cached(
+            Command::new("zoxide").args(["query", "--list", "--score"]),
+            timeout,
+            &zoxide_database_path().into_iter().collect::<Vec<_>>(),
+        )
+        .map(|o| parse_zoxide(&o))
+        .unwrap_or_default(),
+        _ => {
+            warn!("Unknown source of recently visited directories: {}", source);
+            vec![]
+        }
+    }
+}
+
+/// Sums up the frecencies of the directories from all sources, and
+/// orders the directories from the highest frecency.
+fn rank(entries: Vec<(String, f64)>) -> Vec<(String, f64)> {
+    let mut frecencies: HashMap<String, f64> = HashMap::new();
+    for (path, frecency) in entries {
+        *frecencies.entry(path).or_insert(0.0) += frecency;
+    }
+    let mut ranked: Vec<(String, f64)> = frecencies.into_iter().collect();
+    ranked.sort_by(|(p1, f1), (p2, f2)| f2.total_cmp(f1).then_with(|| p1.cmp(p2)));
+    ranked
+}
+
+#[test]
+fn test_rank() {
+    let entries = vec![
+        ("/a".to_owned(), 1.0),
+        ("/b".to_owned(), 2.5),
+        ("/c".to_owned(), 2.0),
+        ("/a".to_owned(), 1.5),
+    ];
+    assert_eq!(
+        rank(entries),
+        vec![
+            ("/a".to_owned(), 2.5),
+            ("/b".to_owned(), 2.5),
+            ("/c".to_owned(), 2.0),
+        ]
+    );
+}
+
+/// A completer of recently visited directories anywhere in the system.
+///
+/// Descending into a directory lists its subdirectories with the file
+/// system completer.
+pub struct RecentDirsCompleter {
+    config: config::RecentDirsConfig,
+    fs_config: config::FsConfig,
+
+    /// The time after which `zoxide` is killed.
+    timeout: time::Duration,
+}
+
+impl RecentDirsCompleter {
+    pub fn new(
+        config: config::RecentDirsConfig,
+        fs_config: config::FsConfig,
+        timeout: time::Duration,
+    ) -> Self {
+        RecentDirsCompleter {
+            config,
+            fs_config,
+            timeout,
+        }
+    }
+}
+
+impl core::Completer for RecentDirsCompleter {
+    fn name(&self) -> String {
+        "recent".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let now = time::SystemTime::now()
+            .duration_since(time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let entries = self
+            .config
+            .sources
+            .iter()
+            .flat_map(|s| read_source(s, now, self.timeout))
+            .collect();
+        rank(entries)
+            .into_iter()
+            // The databases keep directories which no longer exist.
+            .filter(|(p, _)| path::Path::new(p).is_dir())
+            .map(|(path, frecency)| {
+                Box::new(RecentDirCompletion { path, frecency }) as core::CompletionBox
+            })
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let completion_any = completion.as_any();
+        let dir_completion = completion_any
+            .downcast_ref::<RecentDirCompletion>()
+            .unwrap();
+        let mut fs_config = self.fs_config.clone();
+        fs_config.dirs_only = true;
+        Some(Box::new(filesystem::FsCompleter::new(
+            path::PathBuf::from(&dir_completion.path),
+            fs_config,
+        )))
+    }
+}
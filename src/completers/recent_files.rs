@@ -0,0 +1,623 @@
+//! Defines a completer for the files recently opened in editors.
+//!
+//! The files are read from the state which editors keep: the marks of
+//! files in the viminfo file of Vim and in the ShaDa file of Neovim,
+//! the recently opened paths of VS Code, and the list of recently used
+//! files of desktop applications (`recently-used.xbel`). Each file is
+//! listed once, if it still exists, from the most recently used one.
+//! Sources which don't record when files were used are ordered by the
+//! modification times of the files.
+
+use std::any;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use log::warn;
+
+use crate::completers::datetime;
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// The types of the entries of ShaDa files which name files: global
+/// marks, jumps, local marks and changes.
+const SHADA_FILE_ENTRY_TYPES: &[u64] = &[7, 8, 10, 11];
+
+/// The directories of the settings of the flavors of VS Code.
+const VSCODE_PRODUCTS: &[&str] = &["Code", "Code - OSS", "VSCodium"];
+
+struct RecentFileCompletion {
+    path: String,
+
+    /// The editors which used the file.
+    sources: Vec<String>,
+}
+
+impl core::Completion for RecentFileCompletion {
+    fn result_string(&self) -> String {
+        self.path.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(self.sources.join(" "))
+    }
+
+    fn search_string(&self) -> String {
+        self.path.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::File
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+fn home_dir() -> Option<path::PathBuf> {
+    env::var_os("HOME").map(path::PathBuf::from)
+}
+
+/// Returns the directory named by an XDG variable, or else the given
+/// directory under the home directory.
+fn xdg_dir(variable: &str, default: &str) -> Option<path::PathBuf> {
+    env::var_os(variable)
+        .map(path::PathBuf::from)
+        .or_else(|| home_dir().map(|h| h.join(default)))
+}
+
+/// Expands a leading `~/` of a path to the home directory.
+fn expand_home(path: &str, home: &str) -> String {
+    match path.strip_prefix("~/") {
+        Some(rest) => format!("{}/{}", home, rest),
+        None => path.to_owned(),
+    }
+}
+
+/// Decodes the path of a `file://` URI, with its escaped bytes.
+fn file_uri_path(uri: &str) -> Option<String> {
+    let escaped = uri.strip_prefix("file://")?.as_bytes();
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut i = 0;
+    while i < escaped.len() {
+        let decoded = if escaped[i] == b'%' {
+            escaped
+                .get(i + 1..i + 3)
+                .and_then(|h| std::str::from_utf8(h).ok())
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+        } else {
+            None
+        };
+        match decoded {
+            Some(b) => {
+                bytes.push(b);
+                i += 3;
+            }
+            None => {
+                bytes.push(escaped[i]);
+                i += 1;
+            }
+        }
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+#[test]
+fn test_file_uri_path() {
+    assert_eq!(
+        file_uri_path("file:///home/u/my%20notes%2B.md"),
+        Some("/home/u/my notes+.md".to_owned())
+    );
+    assert_eq!(
+        file_uri_path("file:///tmp/%C5%BC%zz"),
+        Some("/tmp/ż%zz".to_owned())
+    );
+    assert_eq!(file_uri_path("vscode-remote://ssh/tmp"), None);
+}
+
+/// Parses a viminfo file into the files with marks, from the most
+/// recently used one.
+fn parse_viminfo(data: &str, home: &str) -> Vec<String> {
+    data.lines()
+        .filter_map(|l| l.strip_prefix("> "))
+        .map(|p| expand_home(p.trim(), home))
+        .collect()
+}
+
+#[test]
+fn test_parse_viminfo() {
+    let data = "# History of marks within files (newest to oldest):
+
+> ~/src/main.rs
+\t\"\t12\t0
+> /etc/hosts
+\t\"\t1\t0
+";
+    assert_eq!(
+        parse_viminfo(data, "/home/u"),
+        vec!["/home/u/src/main.rs", "/etc/hosts"]
+    );
+}
+
+/// Reads the values of the MessagePack format, in which ShaDa files
+/// are written, as far as they are needed to find the names of files.
+struct MessagePackReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> MessagePackReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        MessagePackReader { data, position: 0 }
+    }
+
+    fn is_at_end(&self) -> bool {
+        self.position >= self.data.len()
+    }
+
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let bytes = self.data.get(self.position..self.position + length)?;
+        self.position += length;
+        Some(bytes)
+    }
+
+    /// Reads a big-endian unsigned number of `length` bytes.
+    fn number(&mut self, length: usize) -> Option<u64> {
+        Some(
+            self.take(length)?
+                .iter()
+                .fold(0, |n, b| (n << 8) | u64::from(*b)),
+        )
+    }
+
+    fn unsigned(&mut self) -> Option<u64> {
+        match self.take(1)?[0] {
+            m @ 0x00..=0x7f => Some(u64::from(m)),
+            0xcc => self.number(1),
+            0xcd => self.number(2),
+            0xce => self.number(4),
+            0xcf => self.number(8),
+            _ => None,
+        }
+    }
+
+    /// Reads a string, or binary data, which Neovim writes file names
+    /// as.
+    fn bytes(&mut self) -> Option<&'a [u8]> {
+        let length = match self.take(1)?[0] {
+            m @ 0xa0..=0xbf => u64::from(m & 0x1f),
+            0xc4 | 0xd9 => self.number(1)?,
+            0xc5 | 0xda => self.number(2)?,
+            0xc6 | 0xdb => self.number(4)?,
+            _ => return None,
+        };
+        self.take(length as usize)
+    }
+
+    fn map_length(&mut self) -> Option<u64> {
+        match self.take(1)?[0] {
+            m @ 0x80..=0x8f => Some(u64::from(m & 0x0f)),
+            0xde => self.number(2),
+            0xdf => self.number(4),
+            _ => None,
+        }
+    }
+
+    /// Skips a value of any type.
+    fn skip(&mut self) -> Option<()> {
+        let (length, values) = match self.take(1)?[0] {
+            0x00..=0x7f | 0xe0..=0xff | 0xc0 | 0xc2 | 0xc3 => (0, 0),
+            m @ 0x80..=0x8f => (0, 2 * u64::from(m & 0x0f)),
+            m @ 0x90..=0x9f => (0, u64::from(m & 0x0f)),
+            m @ 0xa0..=0xbf => (u64::from(m & 0x1f), 0),
+            0xc4 | 0xd9 => (self.number(1)?, 0),
+            0xc5 | 0xda => (self.number(2)?, 0),
+            0xc6 | 0xdb => (self.number(4)?, 0),
+            0xc7 => (self.number(1)? + 1, 0),
+            0xc8 => (self.number(2)? + 1, 0),
+            0xc9 => (self.number(4)? + 1, 0),
+            0xcc | 0xd0 => (1, 0),
+            0xcd | 0xd1 => (2, 0),
+            0xca | 0xce | 0xd2 => (4, 0),
+            0xcb | 0xcf | 0xd3 => (8, 0),
+            0xd4 => (2, 0),
+            0xd5 => (3, 0),
+            0xd6 => (5, 0),
+            0xd7 => (9, 0),
+            0xd8 => (17, 0),
+            0xdc => (0, self.number(2)?),
+            0xdd => (0, self.number(4)?),
+            0xde => (0, 2 * self.number(2)?),
+            0xdf => (0, 2 * self.number(4)?),
+            _ => return None,
+        };
+        self.take(length as usize)?;
+        for _ in 0..values {
+            self.skip()?;
+        }
+        Some(())
+    }
+}
+
+/// Returns the file named by the `f` key of the map of an entry of a
+/// ShaDa file.
+fn shada_entry_file(data: &[u8]) -> Option<String> {
+    let mut reader = MessagePackReader::new(data);
+    for _ in 0..reader.map_length()? {
+        if reader.bytes()? == b"f" {
+            return Some(String::from_utf8_lossy(reader.bytes()?).into_owned());
+        }
+        reader.skip()?;
+    }
+    None
+}
+
+/// Parses a ShaDa file into the files named by its entries, with the
+/// times of the entries.
+///
+/// Each entry is its type, its time and the length of its data, which
+/// is a map for the entries naming files.
+fn parse_shada(data: &[u8], home: &str) -> Vec<(String, u64)> {
+    let mut files = vec![];
+    let mut reader = MessagePackReader::new(data);
+    while !reader.is_at_end() {
+        let entry = (|| {
+            let entry_type = reader.unsigned()?;
+            let timestamp = reader.unsigned()?;
+            let length = reader.unsigned()?;
+            Some((entry_type, timestamp, reader.take(length as usize)?))
+        })();
+        let (entry_type, timestamp, entry_data) = match entry {
+            Some(e) => e,
+            None => break,
+        };
+        if SHADA_FILE_ENTRY_TYPES.contains(&entry_type) {
+            if let Some(file) = shada_entry_file(entry_data) {
+                files.push((expand_home(&file, home), timestamp));
+            }
+        }
+    }
+    files
+}
+
+#[test]
+fn test_parse_shada() {
+    let mut data = vec![];
+    // A header, which is skipped.
+    data.extend([
+        0x01, 0xce, 0x65, 0x00, 0x00, 0x00, 0x04, 0x81, 0xa1, b'v', 0x01,
+    ]);
+    // A local mark of ~/a.txt, with the file named with binary data.
+    data.extend([0x0a, 0xce, 0x65, 0x00, 0x00, 0x10, 0x14, 0x83]);
+    data.extend([0xa1, b'l', 0xcd, 0x01, 0x00]);
+    data.extend([0xa1, b'c', 0x00]);
+    data.extend([0xa1, b'f', 0xc4, 0x07]);
+    data.extend(b"~/a.txt");
+    // A jump to /tmp/b, with a negative column and the file named with
+    // a string.
+    data.extend([0x08, 0x7f, 0x0d, 0x82, 0xa1, b'c', 0xff, 0xa1, b'f', 0xa6]);
+    data.extend(b"/tmp/b");
+    // A truncated entry.
+    data.extend([0x0a, 0x01, 0x09, 0x81]);
+    assert_eq!(
+        parse_shada(&data, "/home/u"),
+        vec![
+            ("/home/u/a.txt".to_owned(), 0x6500_0010),
+            ("/tmp/b".to_owned(), 0x7f),
+        ]
+    );
+}
+
+/// Parses the list of recently opened paths of VS Code, stored under
+/// `history.recentlyOpenedPathsList` in its state database, or under
+/// `openedPathsList` in its `storage.json` file in older versions, into
+/// the files, from the most recently opened one. Folders are skipped.
+fn parse_vscode_paths(json: &str) -> Result<Vec<String>, String> {
+    let value: serde_json::Value = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let list = value.get("openedPathsList").unwrap_or(&value);
+    Result::Ok(
+        list.get("entries")
+            .and_then(|e| e.as_array())
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|e| e.get("fileUri")?.as_str())
+                    .filter_map(file_uri_path)
+                    .collect()
+            })
+            .unwrap_or_default(),
+    )
+}
+
+#[test]
+fn test_parse_vscode_paths() {
+    let json = r#"{"entries": [
+        {"folderUri": "file:///home/u/project"},
+        {"fileUri": "file:///home/u/project/notes%20old.md", "label": "notes"},
+        {"fileUri": "vscode-remote://ssh-remote%2Bhost/etc/hosts"}
+    ]}"#;
+    assert_eq!(
+        parse_vscode_paths(json),
+        Result::Ok(vec!["/home/u/project/notes old.md".to_owned()])
+    );
+    let storage = format!(r#"{{"theme": "dark", "openedPathsList": {}}}"#, json);
+    assert_eq!(parse_vscode_paths(&storage).map(|p| p.len()), Result::Ok(1));
+    assert!(parse_vscode_paths("{").is_err());
+}
+
+/// Returns the value of an attribute of the attributes of an XML
+/// element, with the entities which can occur in it decoded.
+fn xml_attribute(attributes: &str, name: &str) -> Option<String> {
+    let pattern = format!("{}=\"", name);
+    let start = attributes
+        .match_indices(&pattern)
+        .map(|(i, _)| i)
+        .find(|i| {
+            attributes[..*i]
+                .chars()
+                .last()
+                .is_none_or(char::is_whitespace)
+        })?
+        + pattern.len();
+    let end = start + attributes[start..].find('"')?;
+    Some(
+        attributes[start..end]
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&apos;", "'")
+            .replace("&amp;", "&"),
+    )
+}
+
+/// Parses a time of the form `2024-03-14T15:09:26Z` into a Unix time.
+fn parse_utc_time(time: &str) -> Option<u64> {
+    let number = |range: std::ops::Range<usize>| time.get(range)?.parse::<i64>().ok();
+    let days = datetime::days_from_civil(number(0..4)?, number(5..7)?, number(8..10)?);
+    let seconds = days * 86400 + number(11..13)? * 3600 + number(14..16)? * 60 + number(17..19)?;
+    if seconds < 0 {
+        None
+    } else {
+        Some(seconds as u64)
+    }
+}
+
+/// Parses a `recently-used.xbel` file into the files, with the times
+/// they were last modified or visited.
+fn parse_xbel(data: &str) -> Vec<(String, u64)> {
+    data.split("<bookmark ")
+        .skip(1)
+        .filter_map(|b| {
+            let element = &b[..b.find('>')?];
+            let path = file_uri_path(&xml_attribute(element, "href")?)?;
+            let time = ["modified", "visited"]
+                .iter()
+                .filter_map(|a| parse_utc_time(&xml_attribute(element, a)?))
+                .max()
+                .unwrap_or(0);
+            Some((path, time))
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_xbel() {
+    let data = r#"<?xml version="1.0" encoding="UTF-8"?>
+<xbel version="1.0">
+  <bookmark href="file:///home/u/R&amp;D%20plan.odt" added="2024-03-01T10:00:00.123456Z" modified="2024-03-02T10:00:00.5Z" visited="2024-03-14T15:09:26.000001Z">
+    <info/>
+  </bookmark>
+  <bookmark href="https://example.com/" modified="2024-03-02T10:00:00Z"/>
+</xbel>"#;
+    assert_eq!(
+        parse_xbel(data),
+        vec![("/home/u/R&D plan.odt".to_owned(), 1_710_428_966)]
+    );
+}
+
+/// Returns the paths of the ShaDa file of Neovim, which recent versions
+/// keep in the state directory, and older ones in the data directory.
+fn shada_paths() -> Vec<path::PathBuf> {
+    vec![
+        xdg_dir("XDG_STATE_HOME", ".local/state"),
+        xdg_dir("XDG_DATA_HOME", ".local/share"),
+    ]
+    .into_iter()
+    .flatten()
+    .map(|d| d.join("nvim").join("shada").join("main.shada"))
+    .collect()
+}
+
+/// Returns the paths of the directories of the global state of the
+/// flavors of VS Code.
+fn vscode_storage_dirs() -> Vec<path::PathBuf> {
+    let config_dir = if cfg!(target_os = "macos") {
+        home_dir().map(|h| h.join("Library").join("Application Support"))
+    } else {
+        xdg_dir("XDG_CONFIG_HOME", ".config")
+    };
+    config_dir
+        .map(|c| {
+            VSCODE_PRODUCTS
+                .iter()
+                .map(|p| c.join(p).join("User").join("globalStorage"))
+                .filter(|d| d.is_dir())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads the recently opened files of VS Code from its state database
+/// with `sqlite3`, or else from its `storage.json` file.
+fn read_vscode(timeout: time::Duration) -> Vec<String> {
+    let mut files = vec![];
+    for dir in vscode_storage_dirs() {
+        let database = dir.join("state.vscdb");
+        let json = if database.is_file() {
+            subprocess::run(
+                Command::new("sqlite3").arg(&database).arg(
+                    "SELECT value FROM ItemTable WHERE key = 'history.recentlyOpenedPathsList'",
+                ),
+                timeout,
+            )
+            .ok()
+        } else {
+            None
+        };
+        let json = json.or_else(|| fs::read_to_string(dir.join("storage.json")).ok());
+        match json.as_deref().map(parse_vscode_paths) {
+            Some(Result::Ok(paths)) => files.extend(paths),
+            Some(Result::Err(e)) => warn!("Failed to parse the state of VS Code: {}", e),
+            None => {}
+        }
+    }
+    files
+}
+
+/// Reads the recently used files of the given source, with the times
+/// they were used if the source records them. Missing files are
+/// ignored, since most users have a few of the editors only.
+fn read_source(source: &str, timeout: time::Duration) -> Vec<(String, Option<u64>)> {
+    let home = home_dir()
+        .map(|h| h.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let untimed = |files: Vec<String>| files.into_iter().map(|f| (f, None)).collect();
+    let timed = |files: Vec<(String, u64)>| files.into_iter().map(|(f, t)| (f, Some(t))).collect();
+    match source {
+        "vim" => home_dir()
+            .and_then(|h| fs::read_to_string(h.join(".viminfo")).ok())
+            .map(|d| untimed(parse_viminfo(&d, &home)))
+            .unwrap_or_default(),
+        "neovim" => shada_paths()
+            .into_iter()
+            .find_map(|p| fs::read(p).ok())
+            .map(|d| timed(parse_shada(&d, &home)))
+            .unwrap_or_default(),
+        "vscode" => untimed(read_vscode(timeout)),
+        "xbel" => xdg_dir("XDG_DATA_HOME", ".local/share")
+            .and_then(|d| fs::read_to_string(d.join("recently-used.xbel")).ok())
+            .map(|d| timed(parse_xbel(&d)))
+            .unwrap_or_default(),
+        _ => {
+            warn!("Unknown source of recently used files: {}", source);
+            vec![]
+        }
+    }
+}
+
+/// Merges the files of all sources which still exist, with the sources
+/// which used each, and orders them from the most recently used one.
+/// Files used at unknown times are taken to be used when they were last
+/// modified.
+fn rank(entries: Vec<(String, Option<u64>, String)>) -> Vec<(String, Vec<String>)> {
+    let mut files: HashMap<String, (u64, Vec<String>)> = HashMap::new();
+    for (path, time, source) in entries {
+        let time = match time {
+            Some(t) => t,
+            None => match fs::metadata(&path).and_then(|m| m.modified()) {
+                Result::Ok(modified) => modified
+                    .duration_since(time::UNIX_EPOCH)
+                    .map(|d| d.as_secs())
+                    .unwrap_or(0),
+                Result::Err(_) => continue,
+            },
+        };
+        let file = files.entry(path).or_insert((0, vec![]));
+        file.0 = file.0.max(time);
+        if !file.1.contains(&source) {
+            file.1.push(source);
+        }
+    }
+    let mut ranked: Vec<(String, (u64, Vec<String>))> = files
+        .into_iter()
+        .filter(|(p, _)| path::Path::new(p).is_file())
+        .collect();
+    ranked.sort_by(|(p1, (t1, _)), (p2, (t2, _))| t2.cmp(t1).then_with(|| p1.cmp(p2)));
+    ranked
+        .into_iter()
+        .map(|(path, (_, sources))| (path, sources))
+        .collect()
+}
+
+#[test]
+fn test_rank() {
+    let dir = env::temp_dir().join(format!(
+        "completers-recent-files-test-{}",
+        std::process::id()
+    ));
+    fs::create_dir_all(&dir).unwrap();
+    let file = |name: &str| {
+        let p = dir.join(name);
+        fs::write(&p, "").unwrap();
+        p.to_string_lossy().into_owned()
+    };
+    let (a, b) = (file("a"), file("b"));
+    let entries = vec![
+        (a.clone(), Some(10), "neovim".to_owned()),
+        (b.clone(), Some(20), "xbel".to_owned()),
+        (a.clone(), Some(30), "xbel".to_owned()),
+        (a.clone(), Some(5), "neovim".to_owned()),
+        (
+            dir.join("gone").to_string_lossy().into_owned(),
+            Some(40),
+            "xbel".to_owned(),
+        ),
+        (dir.to_string_lossy().into_owned(), None, "vim".to_owned()),
+    ];
+    assert_eq!(
+        rank(entries),
+        vec![
+            (a, vec!["neovim".to_owned(), "xbel".to_owned()]),
+            (b, vec!["xbel".to_owned()]),
+        ]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+/// A completer of the files recently opened in editors.
+pub struct RecentFilesCompleter {
+    config: config::RecentFilesConfig,
+
+    /// The time after which `sqlite3` is killed.
+    timeout: time::Duration,
+}
+
+impl RecentFilesCompleter {
+    pub fn new(config: config::RecentFilesConfig, timeout: time::Duration) -> Self {
+        RecentFilesCompleter { config, timeout }
+    }
+}
+
+impl core::Completer for RecentFilesCompleter {
+    fn name(&self) -> String {
+        "editor".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let entries = self
+            .config
+            .sources
+            .iter()
+            .flat_map(|s| {
+                read_source(s, self.timeout)
+                    .into_iter()
+                    .map(move |(path, time)| (path, time, s.clone()))
+            })
+            .collect();
+        rank(entries)
+            .into_iter()
+            .map(|(path, sources)| {
+                Box::new(RecentFileCompletion { path, sources }) as core::CompletionBox
+            })
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,355 @@
+//! Defines views of a completer which are listed separately, e.g. in
+//! its own tab and in the tab with the completions of all completers,
+//! while its completions are fetched only once.
+//!
+//! Whichever view fetches first fetches from the completer, and the
+//! completions are kept for the other views until they fetch too.
+//! Refreshing or requerying through a view fetches anew only for that
+//! view; the others keep the completions they had until they are
+//! refreshed too. Views refreshed before the completer fetches again
+//! take the same completions.
+
+use std::any;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use crate::core;
+
+/// A completion listed by several views.
+struct SharedCompletion(Arc<core::CompletionBox>);
+
+impl core::Completion for SharedCompletion {
+    fn result_string(&self) -> String {
+        self.0.result_string()
+    }
+
+    fn display_string(&self) -> String {
+        self.0.display_string()
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        self.0.display_spans()
+    }
+
+    fn search_string(&self) -> String {
+        self.0.search_string()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.0.description()
+    }
+
+    fn is_hidden(&self) -> bool {
+        self.0.is_hidden()
+    }
+
+    fn severity(&self) -> Option<core::Severity> {
+        self.0.severity()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        self.0.kind()
+    }
+
+    fn file_metadata(&self) -> Option<core::FileMetadata> {
+        self.0.file_metadata()
+    }
+
+    fn group(&self) -> Option<core::CompletionGroup> {
+        self.0.group()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// What a view has not taken from the completer yet.
+#[derive(Default)]
+struct ViewState {
+    completions: Vec<Arc<core::CompletionBox>>,
+    errors: Vec<String>,
+
+    /// The generation of the completions which the view lists; the
+    /// view gets no new completions once it falls behind.
+    generation: usize,
+    stopped: bool,
+
+    /// Indicates if the view is to be refreshed, as the completer needs
+    /// to fetch anew for hidden completions.
+    lacks_hidden: bool,
+}
+
+/// The completer shared by the views.
+struct Source {
+    completer: Box<dyn core::Completer>,
+    views: Vec<ViewState>,
+
+    /// Incremented whenever the completer fetches anew.
+    generation: usize,
+
+    /// Indicates if completions were fetched since the completer was
+    /// created or fetched anew.
+    started: bool,
+
+    /// Indicates if the completer was refreshed and didn't fetch since.
+    refreshed: bool,
+}
+
+impl Source {
+    fn is_current(&self, view: usize) -> bool {
+        self.views[view].generation == self.generation
+    }
+
+    /// Fetches from the completer for all the current views.
+    fn fetch(&mut self) {
+        // Completers which fetch synchronously report being finished
+        // before the first fetch, so they are asked once.
+        if self.started && self.completer.fetching_completions_finished() {
+            return;
+        }
+        let completions: Vec<_> = self
+            .completer
+            .fetch_completions()
+            .into_iter()
+            .map(Arc::new)
+            .collect();
+        let errors = self.completer.take_errors();
+        self.started = true;
+        self.refreshed = false;
+        let generation = self.generation;
+        for view in &mut self.views {
+            if view.generation == generation && !view.stopped {
+                view.completions.extend(completions.iter().cloned());
+                view.errors.extend(errors.iter().cloned());
+            }
+        }
+    }
+
+    /// Makes the completer fetch anew for the given view only.
+    fn restart(&mut self, view: usize) {
+        self.generation += 1;
+        self.started = false;
+        self.refreshed = false;
+        self.join(view);
+    }
+
+    /// Makes the given view take the completions which the completer
+    /// fetches next.
+    fn join(&mut self, view: usize) {
+        let generation = self.generation;
+        let state = &mut self.views[view];
+        *state = ViewState::default();
+        state.generation = generation;
+    }
+
+    /// Stops the completer once no view takes its completions any more.
+    fn stop_if_unused(&mut self) {
+        let generation = self.generation;
+        if self
+            .views
+            .iter()
+            .all(|v| v.stopped || v.generation != generation)
+        {
+            self.completer.stop();
+        }
+    }
+}
+
+/// One of the views of a shared completer.
+pub struct SharedCompleter {
+    source: Rc<RefCell<Source>>,
+    view: usize,
+}
+
+/// Returns the given number of views of the completer.
+pub fn share(completer: Box<dyn core::Completer>, count: usize) -> Vec<SharedCompleter> {
+    let source = Rc::new(RefCell::new(Source {
+        completer,
+        views: (0..count).map(|_| ViewState::default()).collect(),
+        generation: 0,
+        started: false,
+        refreshed: false,
+    }));
+    (0..count)
+        .map(|view| SharedCompleter {
+            source: source.clone(),
+            view,
+        })
+        .collect()
+}
+
+impl core::Completer for SharedCompleter {
+    fn name(&self) -> String {
+        self.source.borrow().completer.name()
+    }
+
+    fn location(&self) -> Option<String> {
+        self.source.borrow().completer.location()
+    }
+
+    fn path_separator(&self) -> Option<char> {
+        self.source.borrow().completer.path_separator()
+    }
+
+    fn is_applicable(&self) -> bool {
+        self.source.borrow().completer.is_applicable()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        let source = self.source.borrow();
+        !source.is_current(self.view)
+            || (source.started
+                && source.views[self.view].completions.is_empty()
+                && source.completer.fetching_completions_finished())
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let mut source = self.source.borrow_mut();
+        if !source.is_current(self.view) {
+            return vec![];
+        }
+        if source.views[self.view].completions.is_empty() {
+            source.fetch();
+        }
+        source.views[self.view]
+            .completions
+            .drain(..)
+            .map(|c| Box::new(SharedCompletion(c)) as core::CompletionBox)
+            .collect()
+    }
+
+    fn stop(&mut self) {
+        let mut source = self.source.borrow_mut();
+        let state = &mut source.views[self.view];
+        state.stopped = true;
+        state.completions.clear();
+        source.stop_if_unused();
+    }
+
+    fn refresh(&mut self) -> bool {
+        let mut source = self.source.borrow_mut();
+        if source.refreshed {
+            source.join(self.view);
+            return true;
+        }
+        if !source.completer.refresh() {
+            return false;
+        }
+        source.restart(self.view);
+        source.refreshed = true;
+        true
+    }
+
+    fn show_hidden(&mut self, shown: bool) -> bool {
+        let mut source = self.source.borrow_mut();
+        // The completer is told once, so the other views are marked to
+        // be refreshed when they are told.
+        if source.completer.show_hidden(shown) {
+            for view in &mut source.views {
+                view.lacks_hidden = true;
+            }
+        }
+        std::mem::take(&mut source.views[self.view].lacks_hidden)
+    }
+
+    fn is_dynamic(&self) -> bool {
+        self.source.borrow().completer.is_dynamic()
+    }
+
+    fn requery(&mut self, query: &str) {
+        let mut source = self.source.borrow_mut();
+        source.completer.requery(query);
+        source.restart(self.view);
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        let mut source = self.source.borrow_mut();
+        source.views[self.view].errors.drain(..).collect()
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let completion_any = completion.as_any();
+        let shared_completion = completion_any.downcast_ref::<SharedCompletion>().unwrap();
+        self.source
+            .borrow()
+            .completer
+            .descend(shared_completion.0.as_ref().as_ref())
+    }
+
+    fn ascend(&self) -> Option<Box<dyn core::Completer>> {
+        self.source.borrow().completer.ascend()
+    }
+}
+
+#[test]
+fn test_shared_completer() {
+    use crate::core::Completer;
+    use crate::ui::testing::words;
+    use crate::ui::testing::Word;
+    use crate::ui::testing::WordCompleter;
+
+    fn refreshed(refreshes: usize) -> Vec<Word> {
+        words(&[format!("refreshed{}", refreshes)])
+    }
+
+    let completer = WordCompleter::batches("words", vec![words(&["a", "b"]), words(&["c"])])
+        .descending(|w| Some(WordCompleter::new(w, vec![])))
+        .refreshing(refreshed);
+    let mut views = share(Box::new(completer), 2);
+    let result_strings = |completions: &[core::CompletionBox]| {
+        completions
+            .iter()
+            .map(|c| c.result_string())
+            .collect::<Vec<_>>()
+    };
+    assert!(!views[0].fetching_completions_finished());
+    assert_eq!(
+        result_strings(&views[0].fetch_completions()),
+        vec!["a", "b"]
+    );
+    assert_eq!(result_strings(&views[0].fetch_completions()), vec!["c"]);
+    assert!(views[0].fetching_completions_finished());
+    // The other view gets the same completions, without fetching again.
+    assert!(!views[1].fetching_completions_finished());
+    let fetched = views[1].fetch_completions();
+    assert_eq!(result_strings(&fetched), vec!["a", "b", "c"]);
+    assert!(views[1].fetching_completions_finished());
+    assert!(views[1].fetch_completions().is_empty());
+    let descended = views[1].descend(fetched[2].as_ref()).unwrap();
+    assert_eq!(descended.name(), "c");
+    // A refresh fetches anew only for the view refreshed.
+    assert!(views[1].refresh());
+    assert!(!views[1].fetching_completions_finished());
+    let fetched = views[1].fetch_completions();
+    assert_eq!(result_strings(&fetched), vec!["refreshed1"]);
+    assert!(views[0].fetching_completions_finished());
+    assert!(views[0].fetch_completions().is_empty());
+}
+
+#[test]
+fn test_shared_completer_hidden() {
+    use crate::core::Completer;
+    use crate::ui::testing::words;
+    use crate::ui::testing::WordCompleter;
+
+    let completer = WordCompleter::new("words", words(&["a"]))
+        .refreshing(|refreshes| words(&[format!("refreshed{}", refreshes)]))
+        .hiding();
+    let mut views = share(Box::new(completer), 2);
+    assert_eq!(views[0].fetch_completions().len(), 1);
+    assert!(!views[0].show_hidden(false));
+    // Both views are refreshed, but the completer only once.
+    assert!(views[0].show_hidden(true));
+    assert!(views[1].show_hidden(true));
+    assert!(!views[1].show_hidden(true));
+    assert!(views[0].refresh());
+    assert!(views[1].refresh());
+    for view in &mut views {
+        let fetched = view.fetch_completions();
+        assert_eq!(fetched.len(), 1);
+        assert_eq!(fetched[0].result_string(), "refreshed1");
+        assert!(view.fetching_completions_finished());
+    }
+}
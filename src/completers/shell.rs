@@ -0,0 +1,181 @@
+//! Defines a completer for the aliases and functions of the shell.
+//!
+//! Aliases and functions live in the shell process, so the shell
+//! integration dumps their definitions (the output of `alias -p` and
+//! `declare -F` in bash) to a file before running completers, and
+//! passes its path in the `DEFINITIONS_VARIABLE` environment variable.
+
+use std::any;
+use std::env;
+use std::fs;
+
+use crate::core;
+
+/// The environment variable with the path of the file with the
+/// definitions of aliases and functions.
+pub const DEFINITIONS_VARIABLE: &str = "COMPLETERS_SHELL_DEFINITIONS";
+
+#[derive(Debug, PartialEq)]
+enum Definition {
+    /// An alias, with the text it expands to.
+    Alias(String),
+    Function,
+}
+
+#[derive(Debug, PartialEq)]
+struct ShellCompletion {
+    name: String,
+    definition: Definition,
+}
+
+impl core::Completion for ShellCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        match self.definition {
+            Definition::Alias(ref expansion) => Some(expansion.clone()),
+            Definition::Function => Some("function".to_owned()),
+        }
+    }
+
+    fn search_string(&self) -> String {
+        match self.definition {
+            Definition::Alias(ref expansion) => format!("{} {}", self.name, expansion),
+            Definition::Function => self.name.clone(),
+        }
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        match self.definition {
+            Definition::Alias(_) => core::CompletionKind::Alias,
+            Definition::Function => core::CompletionKind::Function,
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Removes the quoting of a single-quoted value of an alias, e.g.
+/// `'echo '\''hi'\'''`.
+fn unquote(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut quoted = false;
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => quoted = !quoted,
+            '\\' if !quoted => result.extend(chars.next()),
+            c => result.push(c),
+        }
+    }
+    result
+}
+
+/// Parses the definitions dumped by the shell: lines of `alias -p`
+/// (`alias name='value'`, or `name='value'` in zsh) and of
+/// `declare -F` (`declare -f name`). Other lines are skipped, including
+/// continuation lines of aliases with newlines.
+fn parse_definitions(dump: &str) -> Vec<ShellCompletion> {
+    dump.lines()
+        .filter_map(|line| {
+            if line.starts_with("declare ") {
+                // `declare -fx name` for exported functions.
+                let mut words = line.split_whitespace().skip(1);
+                let flags = words.next()?;
+                let name = words.next()?;
+                return if flags.starts_with("-f") {
+                    Some(ShellCompletion {
+                        name: name.to_owned(),
+                        definition: Definition::Function,
+                    })
+                } else {
+                    None
+                };
+            }
+            let (name, value) = line
+                .strip_prefix("alias ")
+                .unwrap_or(line)
+                .split_once('=')?;
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return None;
+            }
+            Some(ShellCompletion {
+                name: name.to_owned(),
+                definition: Definition::Alias(unquote(value)),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_definitions() {
+    let alias = |name: &str, expansion: &str| ShellCompletion {
+        name: name.to_owned(),
+        definition: Definition::Alias(expansion.to_owned()),
+    };
+    let function = |name: &str| ShellCompletion {
+        name: name.to_owned(),
+        definition: Definition::Function,
+    };
+    let dump = "alias ll='ls -alF'\n\
+                alias hi='echo '\\''hi there'\\'''\n\
+                gs='git status'\n\
+                declare -f mkcd\n\
+                declare -fx nvm\n\
+                some other line\n";
+    assert_eq!(
+        parse_definitions(dump),
+        vec![
+            alias("ll", "ls -alF"),
+            alias("hi", "echo 'hi there'"),
+            alias("gs", "git status"),
+            function("mkcd"),
+            function("nvm"),
+        ]
+    );
+}
+
+/// A completer for the aliases and functions defined in the shell.
+pub struct ShellCompleter {}
+
+impl Default for ShellCompleter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ShellCompleter {
+    pub fn new() -> Self {
+        Self {}
+    }
+
+    /// Indicates if the shell integration passed the definitions of
+    /// aliases and functions.
+    pub fn is_available() -> bool {
+        env::var_os(DEFINITIONS_VARIABLE).is_some()
+    }
+}
+
+impl core::Completer for ShellCompleter {
+    fn name(&self) -> String {
+        "alias".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let dump = env::var_os(DEFINITIONS_VARIABLE)
+            .and_then(|p| fs::read_to_string(p).ok())
+            .unwrap_or_default();
+        parse_definitions(&dump)
+            .into_iter()
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+}
@@ -0,0 +1,201 @@
+//! Defines a completer for the names of signals, for `kill -SIGNAL` and
+//! `trap`.
+//!
+//! The numbers of signals differ between systems, so they are taken
+//! from libc rather than listed here.
+
+use std::any;
+
+use crate::core;
+
+/// The signals defined by POSIX, with their descriptions.
+const SIGNALS: &[(&str, libc::c_int, &str)] = &[
+    ("HUP", libc::SIGHUP, "hangup"),
+    ("INT", libc::SIGINT, "interrupt"),
+    ("QUIT", libc::SIGQUIT, "quit and dump core"),
+    ("ILL", libc::SIGILL, "illegal instruction"),
+    ("TRAP", libc::SIGTRAP, "trace trap"),
+    ("ABRT", libc::SIGABRT, "abort"),
+    ("BUS", libc::SIGBUS, "bus error"),
+    ("FPE", libc::SIGFPE, "arithmetic error"),
+    ("KILL", libc::SIGKILL, "kill, unblockable"),
+    ("USR1", libc::SIGUSR1, "user-defined signal 1"),
+    ("SEGV", libc::SIGSEGV, "segmentation fault"),
+    ("USR2", libc::SIGUSR2, "user-defined signal 2"),
+    ("PIPE", libc::SIGPIPE, "broken pipe"),
+    ("ALRM", libc::SIGALRM, "alarm clock"),
+    ("TERM", libc::SIGTERM, "termination"),
+    ("CHLD", libc::SIGCHLD, "child stopped or exited"),
+    ("CONT", libc::SIGCONT, "continue if stopped"),
+    ("STOP", libc::SIGSTOP, "stop, unblockable"),
+    ("TSTP", libc::SIGTSTP, "stop from the terminal"),
+    ("TTIN", libc::SIGTTIN, "background read from the terminal"),
+    ("TTOU", libc::SIGTTOU, "background write to the terminal"),
+    ("URG", libc::SIGURG, "urgent data on a socket"),
+    ("XCPU", libc::SIGXCPU, "CPU time limit exceeded"),
+    ("XFSZ", libc::SIGXFSZ, "file size limit exceeded"),
+    ("VTALRM", libc::SIGVTALRM, "virtual alarm clock"),
+    ("PROF", libc::SIGPROF, "profiling timer expired"),
+    ("WINCH", libc::SIGWINCH, "window size change"),
+    ("IO", libc::SIGIO, "I/O possible"),
+    ("SYS", libc::SIGSYS, "bad system call"),
+];
+
+/// The conditions which `trap` handles like signals, with their
+/// descriptions.
+const TRAP_CONDITIONS: &[(&str, &str)] = &[
+    ("EXIT", "exit of the shell"),
+    ("ERR", "failure of a command"),
+    ("DEBUG", "before each command"),
+    ("RETURN", "return from a function or a sourced script"),
+];
+
+/// The form in which signal names are completed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SignalSyntax {
+    /// An option of kill, e.g. `-TERM`.
+    KillOption,
+
+    /// A plain name, e.g. after `kill -s`.
+    Name,
+
+    /// An argument of trap, which also handles conditions like `EXIT`.
+    Trap,
+}
+
+#[derive(Debug, PartialEq)]
+struct SignalCompletion {
+    name: &'static str,
+
+    /// The number of the signal, or None for conditions of trap.
+    number: Option<libc::c_int>,
+    description: &'static str,
+
+    /// Indicates if the name is inserted as an option, with a `-`.
+    as_option: bool,
+}
+
+impl core::Completion for SignalCompletion {
+    fn result_string(&self) -> String {
+        if self.as_option {
+            format!("-{}", self.name)
+        } else {
+            self.name.to_owned()
+        }
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        let mut spans = vec![core::Span::plain(self.result_string())];
+        if let Some(n) = self.number {
+            spans.push(core::Span::styled(
+                format!(" {}", n),
+                core::Style::colored(core::SpanColor::Dimmed),
+            ));
+        }
+        spans
+    }
+
+    /// The number is searched too, so that e.g. `-9` finds `-KILL`.
+    fn search_string(&self) -> String {
+        match self.number {
+            Some(n) => format!("{} {}", self.result_string(), n),
+            None => self.result_string(),
+        }
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(self.description.to_owned())
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the syntax of signals at the query on a command line with
+/// the given words before the query, if a signal is expected there.
+pub fn signal_syntax(command_words: &[&str], query: &str) -> Option<SignalSyntax> {
+    match command_words {
+        ["trap", ..] => Some(SignalSyntax::Trap),
+        ["kill", .., "-s"] => Some(SignalSyntax::Name),
+        ["kill", ..] if query.starts_with('-') => Some(SignalSyntax::KillOption),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_signal_syntax() {
+    assert_eq!(
+        signal_syntax(&["kill"], "-TE"),
+        Some(SignalSyntax::KillOption)
+    );
+    assert_eq!(signal_syntax(&["kill", "-s"], ""), Some(SignalSyntax::Name));
+    assert_eq!(signal_syntax(&["kill"], "12"), None);
+    assert_eq!(
+        signal_syntax(&["trap", "'rm -f $tmp'"], ""),
+        Some(SignalSyntax::Trap)
+    );
+    assert_eq!(signal_syntax(&["killall"], "-"), None);
+}
+
+/// A completer for the names of signals.
+pub struct SignalCompleter {
+    syntax: SignalSyntax,
+}
+
+impl SignalCompleter {
+    pub fn new(syntax: SignalSyntax) -> Self {
+        Self { syntax }
+    }
+}
+
+impl core::Completer for SignalCompleter {
+    fn name(&self) -> String {
+        "sig".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let as_option = self.syntax == SignalSyntax::KillOption;
+        let signals = SIGNALS
+            .iter()
+            .map(|(name, number, description)| SignalCompletion {
+                name,
+                number: Some(*number),
+                description,
+                as_option,
+            });
+        let conditions = TRAP_CONDITIONS
+            .iter()
+            .filter(|_| self.syntax == SignalSyntax::Trap)
+            .map(|(name, description)| SignalCompletion {
+                name,
+                number: None,
+                description,
+                as_option,
+            });
+        signals
+            .chain(conditions)
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+}
+
+#[test]
+fn test_signal_completer() {
+    use crate::core::Completer;
+
+    let results = |syntax: SignalSyntax| -> Vec<String> {
+        SignalCompleter::new(syntax)
+            .fetch_completions()
+            .iter()
+            .map(|c| c.result_string())
+            .collect()
+    };
+    let kill_options = results(SignalSyntax::KillOption);
+    assert!(kill_options.contains(&"-TERM".to_owned()));
+    assert!(!kill_options.contains(&"-EXIT".to_owned()));
+    let trap_names = results(SignalSyntax::Trap);
+    assert!(trap_names.contains(&"HUP".to_owned()));
+    assert!(trap_names.contains(&"EXIT".to_owned()));
+    assert_eq!(results(SignalSyntax::Name).len(), SIGNALS.len());
+}
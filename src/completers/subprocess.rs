@@ -0,0 +1,405 @@
+//! Runs the external commands which completers get completions from,
+//! e.g. git, so that a command which hangs can't freeze the chooser.
+//!
+//! Commands are killed when they run longer than the timeout, and
+//! their failures are described with the first line of their error
+//! output, to be reported by completers in the status line. Outputs of
+//! slow commands can be cached in files for a while, and listings which
+//! run them can run in the background while the chooser is shown.
+
+use std::collections::hash_map::DefaultHasher;
+use std::env;
+use std::fs;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::io::Read;
+use std::path;
+use std::process::Command;
+use std::process::Stdio;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use log::warn;
+
+#[cfg(unix)]
+use crate::daemon;
+
+/// How often a running command is checked for completion.
+const POLL_INTERVAL: time::Duration = time::Duration::from_millis(5);
+
+/// Reads the whole output of a pipe of a child process in a separate
+/// thread, so that a child filling one pipe doesn't block on it while
+/// the other one is read.
+fn read_in_background<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<Vec<u8>> {
+    thread::spawn(move || {
+        let mut output = vec![];
+        if let Some(mut p) = pipe {
+            let _ = p.read_to_end(&mut output);
+        }
+        output
+    })
+}
+
+/// Runs a command with no input, returning its standard output if it
+/// succeeds within `timeout`.
+///
+/// Otherwise, returns the description of the failure: the first line
+/// of the error output of the command if there is any.
+pub fn run(command: &mut Command, timeout: time::Duration) -> Result<String, String> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("failed to run {}: {}", program, e))?;
+    let stdout = read_in_background(child.stdout.take());
+    let stderr = read_in_background(child.stderr.take());
+
+    let deadline = time::Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Result::Ok(Some(status)) => break status,
+            Result::Ok(None) if time::Instant::now() < deadline => thread::sleep(POLL_INTERVAL),
+            Result::Ok(None) => {
+                // The reading threads are left behind, as the pipes
+                // may be held open by children of the command.
+                let _ = child.kill();
+                let _ = child.wait();
+                return Result::Err(format!(
+                    "{} timed out after {} ms",
+                    program,
+                    timeout.as_millis()
+                ));
+            }
+            Result::Err(e) => return Result::Err(format!("failed to wait for {}: {}", program, e)),
+        }
+    };
+
+    let stdout = stdout.join().unwrap_or_default();
+    if status.success() {
+        return Result::Ok(String::from_utf8_lossy(&stdout).into_owned());
+    }
+    let stderr = stderr.join().unwrap_or_default();
+    let stderr = String::from_utf8_lossy(&stderr);
+    Result::Err(match stderr.lines().find(|l| !l.trim().is_empty()) {
+        Some(line) => format!("{}: {}", program, line.trim()),
+        None => format!("{} failed: {}", program, status),
+    })
+}
+
+/// Runs a command like `run`, but in the daemon if it is running and
+/// runs the program, which reuses the output of the command as long as
+/// the given files keep their modification times.
+pub fn run_cached(
+    command: &mut Command,
+    timeout: time::Duration,
+    paths: &[path::PathBuf],
+) -> Result<String, String> {
+    // Outputs which don't depend on any files are never cached.
+    #[cfg(unix)]
+    {
+        let client = if paths.is_empty() {
+            None
+        } else {
+            daemon::Client::connect()
+        };
+        if let Some(client) = client {
+            return run_in_daemon(client, command, timeout, paths);
+        }
+    }
+    run(command, timeout)
+}
+
+/// Runs a command like `run_cached`, with the daemon connected to by
+/// the given client.
+///
+/// Commands setting environment variables are run by the session, as
+/// the daemon runs commands in its own environment.
+#[cfg(unix)]
+pub fn run_in_daemon(
+    mut client: daemon::Client,
+    command: &mut Command,
+    timeout: time::Duration,
+    paths: &[path::PathBuf],
+) -> Result<String, String> {
+    if command.get_envs().next().is_none() {
+        match client.run(command, timeout, paths) {
+            Result::Ok(Some(result)) => return result,
+            Result::Ok(None) => {}
+            Result::Err(e) => warn!("Failed to run {:?} in the daemon: {}", command, e),
+        }
+    }
+    run(command, timeout)
+}
+
+#[test]
+#[cfg(unix)]
+fn test_run_in_daemon() {
+    let timeout = time::Duration::from_secs(10);
+    let paths = [env::temp_dir()];
+    let mut command = Command::new("sh");
+    command.args(["-c", "printf %s \"$COMPLETERS_TEST\""]);
+    let result = run_in_daemon(daemon::fake_client("daemon"), &mut command, timeout, &paths);
+    assert_eq!(result, Result::Ok("daemon".to_owned()));
+    command.env("COMPLETERS_TEST", "session");
+    let result = run_in_daemon(daemon::fake_client("daemon"), &mut command, timeout, &paths);
+    assert_eq!(result, Result::Ok("session".to_owned()));
+}
+
+#[test]
+fn test_run() {
+    let timeout = time::Duration::from_secs(10);
+    let sh = |script: &str, timeout: time::Duration| {
+        run(Command::new("sh").args(["-c", script]), timeout)
+    };
+    assert_eq!(sh("echo out", timeout), Result::Ok("out\n".to_owned()));
+    assert_eq!(
+        sh("echo out; echo >&2; echo error >&2; exit 1", timeout),
+        Result::Err("sh: error".to_owned())
+    );
+    assert_eq!(
+        sh("exit 3", timeout),
+        Result::Err("sh failed: exit status: 3".to_owned())
+    );
+    assert_eq!(
+        sh("sleep 10", time::Duration::from_millis(50)),
+        Result::Err("sh timed out after 50 ms".to_owned())
+    );
+    assert!(run(&mut Command::new("/nonexistent/command"), timeout)
+        .unwrap_err()
+        .starts_with("failed to run /nonexistent/command: "));
+}
+
+/// Returns the path of the file caching the output of a command run in
+/// the current directory, in the `namespace` directory of the cache,
+/// e.g. `forge`. Outputs cached this way are reused for a while, for
+/// commands which are slow, e.g. as they query the network.
+pub fn cache_path(namespace: &str, command: &Command) -> Option<path::PathBuf> {
+    let cache_dir = env::var_os("XDG_CACHE_HOME")
+        .map(path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".cache")))?;
+    let mut hasher = DefaultHasher::new();
+    env::current_dir().ok()?.hash(&mut hasher);
+    command.get_program().hash(&mut hasher);
+    command.get_args().for_each(|a| a.hash(&mut hasher));
+    Some(
+        cache_dir
+            .join("completers")
+            .join(namespace)
+            .join(format!("{:016x}", hasher.finish())),
+    )
+}
+
+/// Returns the output cached in a file if it is younger than `max_age`.
+/// The first line of the file is the time the output was cached at, in
+/// seconds since the Unix epoch.
+pub fn read_cache(cache_path: &path::Path, max_age: time::Duration) -> Option<String> {
+    let contents = fs::read_to_string(cache_path).ok()?;
+    let (cached_at, output) = contents.split_once('\n')?;
+    let cached_at = time::UNIX_EPOCH + time::Duration::from_secs(cached_at.parse().ok()?);
+    let age = time::SystemTime::now().duration_since(cached_at).ok()?;
+    if age < max_age {
+        Some(output.to_owned())
+    } else {
+        None
+    }
+}
+
+pub fn write_cache(cache_path: &path::Path, output: &str) {
+    let now = time::SystemTime::now()
+        .duration_since(time::UNIX_EPOCH)
+        .unwrap_or_default();
+    let result = cache_path
+        .parent()
+        .map_or(Result::Ok(()), fs::create_dir_all)
+        .and_then(|_| fs::write(cache_path, format!("{}\n{}", now.as_secs(), output)));
+    if let Result::Err(e) = result {
+        warn!("Failed to cache the output in {:?}: {}", cache_path, e);
+    }
+}
+
+/// Runs a command like `run`, but reuses its output cached in the
+/// `namespace` directory of the cache if it is younger than `max_age`,
+/// unless `use_cache` is false, e.g. when the user refreshes.
+pub fn run_expiring(
+    command: &mut Command,
+    timeout: time::Duration,
+    namespace: &str,
+    max_age: time::Duration,
+    use_cache: bool,
+) -> Result<String, String> {
+    let cache_path = cache_path(namespace, command);
+    let cached = match cache_path {
+        Some(ref p) if use_cache => read_cache(p, max_age),
+        _ => None,
+    };
+    if let Some(output) = cached {
+        return Result::Ok(output);
+    }
+    let output = run(command, timeout)?;
+    if let Some(ref p) = cache_path {
+        write_cache(p, &output);
+    }
+    Result::Ok(output)
+}
+
+/// A function running in a separate thread, e.g. a listing running
+/// slow commands, whose result a completer polls on every fetch. A
+/// function may also stream its results, e.g. chunks of a long listing.
+pub struct Background<T> {
+    /// Receives the result of the function, until it is received.
+    receiver: Option<mpsc::Receiver<T>>,
+}
+
+impl<T: Send + 'static> Background<T> {
+    /// Returns no function running, e.g. before one is started.
+    pub fn idle() -> Background<T> {
+        Background { receiver: None }
+    }
+
+    /// Starts running `function` in a separate thread.
+    pub fn run<F: FnOnce() -> T + Send + 'static>(function: F) -> Background<T> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The result is not needed any more if the chooser exited.
+            let _ = sender.send(function());
+        });
+        Background {
+            receiver: Some(receiver),
+        }
+    }
+
+    /// Starts running `function` in a separate thread, which sends its
+    /// results through the given sender as they come. It should stop
+    /// once sending fails, as its results are not needed any more.
+    pub fn stream<F: FnOnce(&mpsc::Sender<T>) + Send + 'static>(function: F) -> Background<T> {
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || function(&sender));
+        Background {
+            receiver: Some(receiver),
+        }
+    }
+
+    /// Indicates if the result was received, or no function runs.
+    pub fn is_finished(&self) -> bool {
+        self.receiver.is_none()
+    }
+
+    /// Returns the result of the function once it returned, or the
+    /// result of `failed` if it panicked. Returns None while it runs
+    /// and after the result was received.
+    pub fn poll<F: FnOnce() -> T>(&mut self, failed: F) -> Option<T> {
+        let result = match self.receiver.as_ref()?.try_recv() {
+            Result::Ok(result) => result,
+            Result::Err(mpsc::TryRecvError::Empty) => return None,
+            Result::Err(mpsc::TryRecvError::Disconnected) => failed(),
+        };
+        self.receiver = None;
+        Some(result)
+    }
+
+    /// Returns the results which a streaming function sent since the
+    /// last call. It is finished once it returned, or panicked.
+    pub fn poll_stream(&mut self) -> Vec<T> {
+        let mut results = vec![];
+        let receiver = match self.receiver {
+            Some(ref r) => r,
+            None => return results,
+        };
+        loop {
+            match receiver.try_recv() {
+                Result::Ok(result) => results.push(result),
+                Result::Err(mpsc::TryRecvError::Empty) => return results,
+                Result::Err(mpsc::TryRecvError::Disconnected) => break,
+            }
+        }
+        self.receiver = None;
+        results
+    }
+}
+
+impl<T: Send + 'static> Background<Result<Vec<T>, String>> {
+    /// Returns the items which a streaming listing sent since the last
+    /// call, adding the failures it reported to `errors`.
+    pub fn poll_listing(&mut self, errors: &mut Vec<String>) -> Vec<T> {
+        let mut items = vec![];
+        for result in self.poll_stream() {
+            match result {
+                Result::Ok(i) => items.extend(i),
+                Result::Err(e) => errors.push(e),
+            }
+        }
+        items
+    }
+}
+
+#[test]
+fn test_background() {
+    let mut background = Background::run(|| {
+        thread::sleep(time::Duration::from_millis(20));
+        1
+    });
+    assert!(!background.is_finished());
+    let result = loop {
+        if let Some(r) = background.poll(|| 0) {
+            break r;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    assert_eq!(result, 1);
+    assert!(background.is_finished());
+    assert_eq!(background.poll(|| 0), None);
+
+    let mut background = Background::run(|| -> i32 { panic!("listing failed") });
+    let result = loop {
+        if let Some(r) = background.poll(|| 0) {
+            break r;
+        }
+        thread::sleep(POLL_INTERVAL);
+    };
+    assert_eq!(result, 0);
+    assert!(Background::<i32>::idle().is_finished());
+
+    let mut background = Background::stream(|sender| {
+        for i in 0..3 {
+            if sender.send(i).is_err() {
+                return;
+            }
+        }
+        panic!("listing failed");
+    });
+    let mut results = vec![];
+    while !background.is_finished() {
+        results.extend(background.poll_stream());
+        thread::sleep(POLL_INTERVAL);
+    }
+    assert_eq!(results, vec![0, 1, 2]);
+
+    let mut background = Background::stream(|sender| {
+        let _ = sender.send(Result::Ok(vec![1, 2]));
+        let _ = sender.send(Result::Err("failed".to_owned()));
+        let _ = sender.send(Result::Ok(vec![3]));
+    });
+    let mut items = vec![];
+    let mut errors = vec![];
+    while !background.is_finished() {
+        items.extend(background.poll_listing(&mut errors));
+        thread::sleep(POLL_INTERVAL);
+    }
+    assert_eq!(items, vec![1, 2, 3]);
+    assert_eq!(errors, vec!["failed"]);
+}
+
+#[test]
+fn test_cache() {
+    let dir = env::temp_dir().join(format!("completers-cache-test-{}", std::process::id()));
+    let cache_path = dir.join("forge").join("entry");
+    let max_age = time::Duration::from_secs(60);
+    assert_eq!(read_cache(&cache_path, max_age), None);
+    write_cache(&cache_path, "[]\n");
+    assert_eq!(read_cache(&cache_path, max_age), Some("[]\n".to_owned()));
+    assert_eq!(read_cache(&cache_path, time::Duration::ZERO), None);
+    fs::remove_dir_all(&dir).unwrap();
+}
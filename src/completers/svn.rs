@@ -0,0 +1,339 @@
+//! Defines a completer for the trunk, branches and tags of a Subversion
+//! repository, and for the revisions of each of them.
+//!
+//! The repository is assumed to have the standard layout, with the
+//! `trunk`, `branches` and `tags` directories at its root. They are
+//! completed as URLs relative to the root (`^/branches/name`), which
+//! commands like `svn switch` and `svn merge` accept.
+
+use std::any;
+use std::env;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::core;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum SvnPathType {
+    Trunk,
+    Branch,
+    Tag,
+}
+
+#[derive(Debug, PartialEq)]
+struct SvnPathCompletion {
+    path_type: SvnPathType,
+    name: String,
+}
+
+impl SvnPathCompletion {
+    /// Returns the URL of the path relative to the root of the
+    /// repository.
+    fn url(&self) -> String {
+        match self.path_type {
+            SvnPathType::Trunk => "^/trunk".to_owned(),
+            SvnPathType::Branch => format!("^/branches/{}", self.name),
+            SvnPathType::Tag => format!("^/tags/{}", self.name),
+        }
+    }
+}
+
+impl core::Completion for SvnPathCompletion {
+    fn result_string(&self) -> String {
+        self.url()
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        vec![core::Span::styled(
+            self.name.as_str(),
+            core::Style::colored(core::SpanColor::Kind(self.kind())),
+        )]
+    }
+
+    fn search_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        match self.path_type {
+            SvnPathType::Trunk | SvnPathType::Branch => core::CompletionKind::Branch,
+            SvnPathType::Tag => core::CompletionKind::Tag,
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of `svn ls` of the branches or tags directory,
+/// where directories end with a slash.
+fn parse_listing(output: &str, path_type: SvnPathType) -> Vec<SvnPathCompletion> {
+    output
+        .lines()
+        .filter_map(|line| line.strip_suffix('/'))
+        .map(|name| SvnPathCompletion {
+            path_type,
+            name: name.to_owned(),
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_listing() {
+    assert_eq!(
+        parse_listing("1.0/\n1.1/\nREADME.txt\n", SvnPathType::Tag),
+        vec![
+            SvnPathCompletion {
+                path_type: SvnPathType::Tag,
+                name: "1.0".to_owned(),
+            },
+            SvnPathCompletion {
+                path_type: SvnPathType::Tag,
+                name: "1.1".to_owned(),
+            },
+        ]
+    );
+    assert_eq!(
+        SvnPathCompletion {
+            path_type: SvnPathType::Branch,
+            name: "fix".to_owned(),
+        }
+        .url(),
+        "^/branches/fix"
+    );
+}
+
+/// Indicates if the current directory is in a Subversion working copy.
+pub fn is_working_copy() -> bool {
+    env::current_dir()
+        .map(|d| d.ancestors().any(|a| a.join(".svn").is_dir()))
+        .unwrap_or(false)
+}
+
+/// Runs svn with the given arguments, returning its output, or None if
+/// it failed, in which case the error is added to `errors`.
+fn run_svn(args: &[&str], timeout: time::Duration, errors: &mut Vec<String>) -> Option<String> {
+    // Prompting for credentials would hang until the timeout.
+    match subprocess::run(
+        Command::new("svn").arg("--non-interactive").args(args),
+        timeout,
+    ) {
+        Result::Ok(output) => Some(output),
+        Result::Err(e) => {
+            errors.push(e);
+            None
+        }
+    }
+}
+
+/// A completer for the trunk, branches and tags of the Subversion
+/// repository of the working copy in the current directory.
+pub struct SvnBranchCompleter {
+    /// The time after which svn commands are killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl SvnBranchCompleter {
+    pub fn new(timeout: time::Duration) -> Self {
+        Self {
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for SvnBranchCompleter {
+    fn name(&self) -> String {
+        "br".to_owned()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        true
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let mut fetched_completions: Vec<core::CompletionBox> = vec![Box::new(SvnPathCompletion {
+            path_type: SvnPathType::Trunk,
+            name: "trunk".to_owned(),
+        })];
+        for (dir, path_type) in [
+            ("^/branches", SvnPathType::Branch),
+            ("^/tags", SvnPathType::Tag),
+        ] {
+            if let Some(output) = run_svn(&["ls", dir], self.timeout, &mut self.errors) {
+                fetched_completions.extend(
+                    parse_listing(&output, path_type)
+                        .into_iter()
+                        .map(|c| Box::new(c) as core::CompletionBox),
+                );
+            }
+        }
+        fetched_completions
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let path_completion = completion.as_any().downcast_ref::<SvnPathCompletion>()?;
+        Some(Box::new(SvnRevisionCompleter::new(
+            path_completion.name.as_str(),
+            path_completion.url(),
+            self.timeout,
+        )))
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct SvnRevisionCompletion {
+    revision: String,
+    date: String,
+    author: String,
+    message: String,
+}
+
+impl core::Completion for SvnRevisionCompletion {
+    fn result_string(&self) -> String {
+        self.revision.clone()
+    }
+
+    fn display_string(&self) -> String {
+        format!("r{:8} {}", &self.revision, &self.message)
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("{} {}", self.date, self.author))
+    }
+
+    fn search_string(&self) -> String {
+        self.message.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Commit
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of `svn log`, in which each revision starts with a
+/// header line, e.g. `r5 | alice | 2024-03-02 10:00:00 +0100 (...) |
+/// 1 line`, followed by an empty line and the message.
+fn parse_log(output: &str) -> Vec<SvnRevisionCompletion> {
+    let mut revisions: Vec<SvnRevisionCompletion> = vec![];
+    let mut expecting_message = false;
+    for line in output.lines() {
+        let fields: Vec<&str> = line.split(" | ").collect();
+        if let [revision, author, date, _] = fields[..] {
+            if let Some(revision) = revision.strip_prefix('r') {
+                if revision.chars().all(|c| c.is_ascii_digit()) {
+                    revisions.push(SvnRevisionCompletion {
+                        revision: revision.to_owned(),
+                        date: date.chars().take(10).collect(),
+                        author: author.to_owned(),
+                        message: String::new(),
+                    });
+                    expecting_message = true;
+                    continue;
+                }
+            }
+        }
+        if expecting_message && !line.trim().is_empty() {
+            if let Some(r) = revisions.last_mut() {
+                r.message = line.trim().to_owned();
+            }
+            expecting_message = false;
+        }
+    }
+    revisions
+}
+
+#[test]
+fn test_parse_log() {
+    let output = "------------------------------------------------------------------------\n\
+                  r12 | alice | 2024-03-02 10:00:00 +0100 (Sat, 02 Mar 2024) | 2 lines\n\
+                  \n\
+                  Fix the build\n\
+                  on Windows\n\
+                  ------------------------------------------------------------------------\n\
+                  r7 | bob | 2024-01-05 09:30:00 +0100 (Fri, 05 Jan 2024) | 1 line\n\
+                  \n\
+                  Add a README\n\
+                  ------------------------------------------------------------------------\n";
+    assert_eq!(
+        parse_log(output),
+        vec![
+            SvnRevisionCompletion {
+                revision: "12".to_owned(),
+                date: "2024-03-02".to_owned(),
+                author: "alice".to_owned(),
+                message: "Fix the build".to_owned(),
+            },
+            SvnRevisionCompletion {
+                revision: "7".to_owned(),
+                date: "2024-01-05".to_owned(),
+                author: "bob".to_owned(),
+                message: "Add a README".to_owned(),
+            },
+        ]
+    );
+}
+
+struct SvnRevisionCompleter {
+    name: String,
+    url: String,
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl SvnRevisionCompleter {
+    fn new<N: Into<String>>(name: N, url: String, timeout: time::Duration) -> SvnRevisionCompleter {
+        SvnRevisionCompleter {
+            name: name.into(),
+            url,
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for SvnRevisionCompleter {
+    fn name(&self) -> String {
+        "rev".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        true
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        run_svn(&["log", &self.url], self.timeout, &mut self.errors)
+            .map(|output| parse_log(&output))
+            .unwrap_or_default()
+            .into_iter()
+            .map(|c| Box::new(c) as core::CompletionBox)
+            .collect()
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,432 @@
+//! Defines completers for systemd: the units and boots recorded in the
+//! journal, for `journalctl -u` and `journalctl -b`.
+//!
+//! The units are the values of the `_SYSTEMD_UNIT` field of the journal,
+//! so units which no longer exist but logged are listed too. Failed
+//! units stand out. Descending into a unit lists its most recent log
+//! lines, to tell units apart; choosing a line completes the unit.
+
+use std::any;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::core;
+
+/// The number of log lines listed when descending into a unit.
+const LOG_LINES: usize = 100;
+
+/// The items of the journal completed after an option of `journalctl`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum JournalItem {
+    /// A unit of the system, or of the user's service manager.
+    Unit {
+        user: bool,
+    },
+    Boot,
+}
+
+/// Returns the item of the journal completed on a command line with the
+/// given words preceding the query, if any.
+pub fn journal_item(command_words: &[&str]) -> Option<JournalItem> {
+    if command_words.first() != Some(&"journalctl") {
+        return None;
+    }
+    match *command_words.last()? {
+        "-u" | "--unit" => Some(JournalItem::Unit {
+            user: command_words.contains(&"--user"),
+        }),
+        "--user-unit" => Some(JournalItem::Unit { user: true }),
+        "-b" | "--boot" => Some(JournalItem::Boot),
+        _ => None,
+    }
+}
+
+#[test]
+fn test_journal_item() {
+    assert_eq!(
+        journal_item(&["journalctl", "-f", "-u"]),
+        Some(JournalItem::Unit { user: false })
+    );
+    assert_eq!(
+        journal_item(&["journalctl", "--user", "-u"]),
+        Some(JournalItem::Unit { user: true })
+    );
+    assert_eq!(
+        journal_item(&["journalctl", "--user-unit"]),
+        Some(JournalItem::Unit { user: true })
+    );
+    assert_eq!(journal_item(&["journalctl", "-b"]), Some(JournalItem::Boot));
+    assert_eq!(journal_item(&["journalctl", "-n"]), None);
+    assert_eq!(journal_item(&["systemctl", "-u"]), None);
+}
+
+/// Returns a `journalctl` command reading the journal of the system, or
+/// of the user if `user`.
+fn journalctl(user: bool) -> Command {
+    let mut command = Command::new("journalctl");
+    if user {
+        command.arg("--user");
+    }
+    command.args(["--no-pager", "--quiet"]);
+    command
+}
+
+/// Returns a `systemctl` command managing the system, or the user's
+/// service manager if `user`.
+fn systemctl(user: bool) -> Command {
+    let mut command = Command::new("systemctl");
+    if user {
+        command.arg("--user");
+    }
+    command.args(["--no-pager", "--no-legend", "--plain"]);
+    command
+}
+
+/// Parses the output of `systemctl list-units --failed` into the names
+/// of the failed units. Older versions mark the units with a bullet
+/// even with `--plain`.
+fn parse_failed_units(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .map(|l| l.trim_start().trim_start_matches(['\u{25CF}', '*']))
+        .filter_map(|l| l.split_whitespace().next())
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn test_parse_failed_units() {
+    let output = "\u{25CF} backup.service loaded failed failed Nightly backup
+mnt-data.mount loaded failed failed /mnt/data
+";
+    assert_eq!(
+        parse_failed_units(output),
+        vec!["backup.service", "mnt-data.mount"]
+    );
+    assert!(parse_failed_units("").is_empty());
+}
+
+#[derive(Debug, PartialEq)]
+struct UnitCompletion {
+    name: String,
+    user: bool,
+    failed: bool,
+}
+
+impl core::Completion for UnitCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        let unit_type = self.name.rsplit_once('.').map(|(_, t)| t)?;
+        if self.failed {
+            Some(format!("{}, failed", unit_type))
+        } else {
+            Some(unit_type.to_owned())
+        }
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Process
+    }
+
+    fn severity(&self) -> Option<core::Severity> {
+        if self.failed {
+            Some(core::Severity::Error)
+        } else {
+            None
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the values of a field of the journal, one per line, into the
+/// units, ordered by name, marking the given failed ones.
+fn parse_units(output: &str, user: bool, failed: &[String]) -> Vec<UnitCompletion> {
+    let mut names: Vec<&str> = output
+        .lines()
+        .map(str::trim)
+        .filter(|l| !l.is_empty())
+        .collect();
+    names.sort_unstable();
+    names.dedup();
+    names
+        .into_iter()
+        .map(|name| UnitCompletion {
+            name: name.to_owned(),
+            user,
+            failed: failed.iter().any(|f| f == name),
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_units() {
+    let failed = vec!["sshd.service".to_owned()];
+    assert_eq!(
+        parse_units(
+            "sshd.service\ncron.service\n\nsshd.service\n",
+            false,
+            &failed
+        )
+        .into_iter()
+        .map(|u| (u.name, u.failed))
+        .collect::<Vec<_>>(),
+        vec![
+            ("cron.service".to_owned(), false),
+            ("sshd.service".to_owned(), true)
+        ]
+    );
+}
+
+#[derive(Debug, PartialEq)]
+struct BootCompletion {
+    id: String,
+
+    /// The offset of the boot from the current one, e.g. `-1` for the
+    /// previous boot.
+    offset: i64,
+
+    /// The times of the first and the last entries of the boot.
+    times: String,
+}
+
+impl core::Completion for BootCompletion {
+    fn result_string(&self) -> String {
+        self.id.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(format!("{} {}", self.offset, self.times))
+    }
+
+    /// Boots can be searched by their offsets and times too.
+    fn search_string(&self) -> String {
+        format!("{} {} {}", self.id, self.offset, self.times)
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Other
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of `journalctl --list-boots` into the boots, from
+/// the current one. Lines which don't start with an offset and an ID,
+/// like the heading of recent versions, are skipped.
+fn parse_boots(output: &str) -> Vec<BootCompletion> {
+    let mut boots: Vec<BootCompletion> = output
+        .lines()
+        .filter_map(|l| {
+            let mut words = l.split_whitespace();
+            let offset = words.next()?.parse().ok()?;
+            let id = words.next()?;
+            if id.len() != 32 || !id.chars().all(|c| c.is_ascii_hexdigit()) {
+                return None;
+            }
+            Some(BootCompletion {
+                id: id.to_owned(),
+                offset,
+                times: words.collect::<Vec<_>>().join(" "),
+            })
+        })
+        .collect();
+    boots.reverse();
+    boots
+}
+
+#[test]
+fn test_parse_boots() {
+    let output = "IDX BOOT ID                          FIRST ENTRY                 LAST ENTRY
+ -1 0d4f2a0c6e2f4b1c9a7e3d5b8f6a1c2e Mon 2024-03-11 09:00:01 CET Mon 2024-03-11 18:00:00 CET
+  0 5a1b2c3d4e5f60718293a4b5c6d7e8f9 Tue 2024-03-12 08:00:00 CET Tue 2024-03-12 12:00:00 CET
+";
+    assert_eq!(
+        parse_boots(output),
+        vec![
+            BootCompletion {
+                id: "5a1b2c3d4e5f60718293a4b5c6d7e8f9".to_owned(),
+                offset: 0,
+                times: "Tue 2024-03-12 08:00:00 CET Tue 2024-03-12 12:00:00 CET".to_owned(),
+            },
+            BootCompletion {
+                id: "0d4f2a0c6e2f4b1c9a7e3d5b8f6a1c2e".to_owned(),
+                offset: -1,
+                times: "Mon 2024-03-11 09:00:01 CET Mon 2024-03-11 18:00:00 CET".to_owned(),
+            },
+        ]
+    );
+}
+
+/// A completer of the units or the boots recorded in the journal.
+pub struct JournalCompleter {
+    item: JournalItem,
+
+    /// The time after which `journalctl` is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl JournalCompleter {
+    pub fn new(item: JournalItem, timeout: time::Duration) -> Self {
+        JournalCompleter {
+            item,
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for JournalCompleter {
+    fn name(&self) -> String {
+        "journal".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let completions: Result<Vec<core::CompletionBox>, String> = match self.item {
+            JournalItem::Unit { user } => {
+                let field = if user {
+                    "_SYSTEMD_USER_UNIT"
+                } else {
+                    "_SYSTEMD_UNIT"
+                };
+                // Units aren't marked if systemctl isn't available, e.g.
+                // when reading the journal of another machine.
+                let failed = subprocess::run(
+                    systemctl(user).args(["list-units", "--failed"]),
+                    self.timeout,
+                )
+                .map(|o| parse_failed_units(&o))
+                .unwrap_or_default();
+                subprocess::run(journalctl(user).args(["--field", field]), self.timeout).map(|o| {
+                    parse_units(&o, user, &failed)
+                        .into_iter()
+                        .map(|c| Box::new(c) as core::CompletionBox)
+                        .collect()
+                })
+            }
+            JournalItem::Boot => {
+                subprocess::run(journalctl(false).arg("--list-boots"), self.timeout).map(|o| {
+                    parse_boots(&o)
+                        .into_iter()
+                        .map(|c| Box::new(c) as core::CompletionBox)
+                        .collect()
+                })
+            }
+        };
+        completions.unwrap_or_else(|e| {
+            self.errors.push(e);
+            vec![]
+        })
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let unit = completion.as_any().downcast_ref::<UnitCompletion>()?;
+        Some(Box::new(UnitLogCompleter {
+            unit: unit.name.clone(),
+            user: unit.user,
+            timeout: self.timeout,
+            errors: vec![],
+        }))
+    }
+}
+
+/// A recent log line of a unit, which completes the unit.
+struct LogLineCompletion {
+    unit: String,
+    line: String,
+}
+
+impl core::Completion for LogLineCompletion {
+    fn result_string(&self) -> String {
+        self.unit.clone()
+    }
+
+    fn display_string(&self) -> String {
+        self.line.clone()
+    }
+
+    fn search_string(&self) -> String {
+        self.line.clone()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A completer of the most recent log lines of a unit, from the newest.
+struct UnitLogCompleter {
+    unit: String,
+    user: bool,
+
+    /// The time after which `journalctl` is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl core::Completer for UnitLogCompleter {
+    fn name(&self) -> String {
+        "journal".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.unit.clone())
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let unit_option = if self.user { "--user-unit" } else { "--unit" };
+        let lines = LOG_LINES.to_string();
+        let output = subprocess::run(
+            journalctl(self.user).args([
+                unit_option,
+                &self.unit,
+                "--lines",
+                &lines,
+                "--reverse",
+                "--output",
+                "short-iso",
+            ]),
+            self.timeout,
+        );
+        match output {
+            Result::Ok(o) => o
+                .lines()
+                .map(|line| {
+                    Box::new(LogLineCompletion {
+                        unit: self.unit.clone(),
+                        line: line.to_owned(),
+                    }) as core::CompletionBox
+                })
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,438 @@
+//! Defines a completer for the tasks of build systems: the tasks of
+//! Gradle, the lifecycle phases and profiles of Maven and the recipes
+//! of just, with their descriptions.
+//!
+//! Each build system is a backend in `TASK_RUNNERS`, naming the
+//! commands which run it, the files of its projects, and the function
+//! listing its tasks. Gradle configures the whole project to list its
+//! tasks, which takes seconds, so the tasks are listed in a background
+//! thread, and through the daemon if it is running, which lists them
+//! again only when the build files change.
+
+use std::any;
+use std::env;
+use std::fs;
+use std::path;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+#[derive(Debug, PartialEq)]
+struct TaskCompletion {
+    name: String,
+    description: Option<String>,
+}
+
+impl core::Completion for TaskCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn search_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Script
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Lists the tasks of a build system, given the words of the command
+/// line preceding the query, returning the completions or the error.
+type ListTasks = fn(&[&str], time::Duration) -> Result<Vec<TaskCompletion>, String>;
+
+/// A build system whose tasks are completed.
+pub struct TaskRunner {
+    name: &'static str,
+
+    /// The commands running the build system.
+    commands: &'static [&'static str],
+
+    /// The files one of which the root directory of a project has.
+    project_files: &'static [&'static str],
+
+    /// Whether the root directory of a project may be above the
+    /// current one.
+    search_ancestors: bool,
+
+    list: ListTasks,
+}
+
+/// The build files of Gradle, which change its tasks.
+const GRADLE_FILES: &[&str] = &[
+    "build.gradle",
+    "build.gradle.kts",
+    "settings.gradle",
+    "settings.gradle.kts",
+];
+
+/// The supported build systems.
+const TASK_RUNNERS: &[TaskRunner] = &[
+    TaskRunner {
+        name: "gradle",
+        commands: &["gradle", "gradlew", "./gradlew"],
+        project_files: GRADLE_FILES,
+        search_ancestors: false,
+        list: list_gradle_tasks,
+    },
+    TaskRunner {
+        name: "maven",
+        commands: &["mvn", "mvnw", "./mvnw"],
+        project_files: &["pom.xml"],
+        search_ancestors: false,
+        list: list_maven_items,
+    },
+    TaskRunner {
+        name: "just",
+        commands: &["just"],
+        project_files: &["justfile", "Justfile", ".justfile"],
+        search_ancestors: true,
+        list: list_just_recipes,
+    },
+];
+
+/// Returns the build system run by a command line with the given words
+/// preceding the query, if any.
+pub fn task_runner(command_words: &[&str]) -> Option<&'static TaskRunner> {
+    let command = command_words.first()?;
+    TASK_RUNNERS.iter().find(|r| r.commands.contains(command))
+}
+
+#[test]
+fn test_task_runner() {
+    assert_eq!(
+        task_runner(&["./gradlew", "-q"]).map(|r| r.name),
+        Some("gradle")
+    );
+    assert_eq!(task_runner(&["mvn", "-P"]).map(|r| r.name), Some("maven"));
+    assert_eq!(task_runner(&["just"]).map(|r| r.name), Some("just"));
+    assert_eq!(task_runner(&["make"]).map(|r| r.name), None);
+    assert_eq!(task_runner(&[]).map(|r| r.name), None);
+}
+
+/// Parses the output of `gradle tasks --all` into the names and
+/// descriptions of the tasks, skipping the headings of the groups of
+/// tasks and the notes, which are underlined or contain spaces.
+fn parse_gradle_tasks(output: &str) -> Vec<TaskCompletion> {
+    let lines: Vec<&str> = output.lines().collect();
+    let is_rule = |l: &str| !l.is_empty() && l.chars().all(|c| c == '-');
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(i, l)| !is_rule(l) && !lines.get(i + 1).is_some_and(|n| is_rule(n)))
+        .filter_map(|(_, l)| {
+            let mut parts = l.splitn(2, " - ");
+            let name = parts.next()?;
+            if name.is_empty() || name.contains(char::is_whitespace) {
+                return None;
+            }
+            Some(TaskCompletion {
+                name: name.to_owned(),
+                description: parts.next().map(str::to_owned),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_gradle_tasks() {
+    let output = "
+------------------------------------------------------------
+Tasks runnable from root project 'app'
+------------------------------------------------------------
+
+Build tasks
+-----------
+assemble - Assembles the outputs of this project.
+app:compileJava - Compiles main Java source.
+
+Other tasks
+-----------
+prepareKotlinBuildScriptModel
+
+Rules
+-----
+Pattern: clean<TaskName>: Cleans the output files of a task.
+
+To see all tasks and more detail, run gradlew tasks --all
+";
+    assert_eq!(
+        parse_gradle_tasks(output),
+        vec![
+            TaskCompletion {
+                name: "assemble".to_owned(),
+                description: Some("Assembles the outputs of this project.".to_owned()),
+            },
+            TaskCompletion {
+                name: "app:compileJava".to_owned(),
+                description: Some("Compiles main Java source.".to_owned()),
+            },
+            TaskCompletion {
+                name: "prepareKotlinBuildScriptModel".to_owned(),
+                description: None,
+            },
+        ]
+    );
+}
+
+fn list_gradle_tasks(
+    _command_words: &[&str],
+    timeout: time::Duration,
+) -> Result<Vec<TaskCompletion>, String> {
+    let dir = env::current_dir().map_err(|e| e.to_string())?;
+    // The wrapper of the project runs the version of Gradle it needs.
+    let program = if dir.join("gradlew").is_file() {
+        dir.join("gradlew")
+    } else {
+        path::PathBuf::from("gradle")
+    };
+    let watched: Vec<path::PathBuf> = GRADLE_FILES.iter().map(|f| dir.join(f)).collect();
+    subprocess::run_cached(
+        Command::new(program).args(["tasks", "--all", "--quiet", "--console=plain"]),
+        timeout,
+        &watched,
+    )
+    .map(|o| parse_gradle_tasks(&o))
+}
+
+/// The phases of the lifecycles of Maven, with their descriptions.
+const MAVEN_PHASES: &[(&str, &str)] = &[
+    ("clean", "Remove the files of the previous build"),
+    ("validate", "Validate the project"),
+    ("initialize", "Initialize the build state"),
+    ("generate-sources", "Generate the source code"),
+    ("process-resources", "Copy the resources"),
+    ("compile", "Compile the source code"),
+    ("test-compile", "Compile the source code of the tests"),
+    ("test", "Run the unit tests"),
+    ("package", "Package the compiled code, e.g. as a JAR"),
+    ("integration-test", "Run the integration tests"),
+    ("verify", "Check that the package is valid"),
+    ("install", "Install the package into the local repository"),
+    ("deploy", "Copy the package to the remote repository"),
+    ("site", "Generate the documentation of the project"),
+];
+
+/// Returns the IDs of the profiles defined in a `pom.xml` file, which
+/// are the first `id` element of each `profile` element.
+fn parse_maven_profiles(pom: &str) -> Vec<String> {
+    pom.split("<profile>")
+        .skip(1)
+        .filter_map(|p| {
+            let start = p.find("<id>")? + "<id>".len();
+            let end = start + p[start..].find("</id>")?;
+            Some(p[start..end].trim().to_owned())
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_maven_profiles() {
+    let pom = "<project>
+  <profiles>
+    <profile>
+      <id>release</id>
+      <activation><property><name>ci</name></property></activation>
+    </profile>
+    <profile><id> native </id></profile>
+  </profiles>
+</project>";
+    assert_eq!(parse_maven_profiles(pom), vec!["release", "native"]);
+}
+
+/// Lists the profiles of the project after `-P`, or else the phases.
+fn list_maven_items(
+    command_words: &[&str],
+    _timeout: time::Duration,
+) -> Result<Vec<TaskCompletion>, String> {
+    if let Some(&"-P") | Some(&"--activate-profiles") = command_words.last() {
+        let pom = fs::read_to_string("pom.xml").map_err(|e| format!("pom.xml: {}", e))?;
+        return Result::Ok(
+            parse_maven_profiles(&pom)
+                .into_iter()
+                .map(|name| TaskCompletion {
+                    name,
+                    description: Some("profile".to_owned()),
+                })
+                .collect(),
+        );
+    }
+    Result::Ok(
+        MAVEN_PHASES
+            .iter()
+            .map(|(name, description)| TaskCompletion {
+                name: (*name).to_owned(),
+                description: Some((*description).to_owned()),
+            })
+            .collect(),
+    )
+}
+
+/// Returns the descriptions of the recipes in the output of
+/// `just --list`, i.e. their doc comments, or else their parameters.
+fn parse_just_list(output: &str) -> Vec<(String, String)> {
+    output
+        .lines()
+        .filter(|l| l.starts_with(char::is_whitespace))
+        .filter_map(|l| {
+            let l = l.trim();
+            let (signature, doc) = match l.find(" # ") {
+                Some(i) => (&l[..i], Some(l[i + " # ".len()..].trim())),
+                None => (l, None),
+            };
+            let mut words = signature.splitn(2, ' ');
+            let name = words.next()?;
+            let description = doc.or_else(|| words.next()).unwrap_or_default();
+            Some((name.to_owned(), description.trim().to_owned()))
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_just_list() {
+    let output = "Available recipes:
+    build        # Build the project
+    deploy env='staging'
+    lint
+";
+    assert_eq!(
+        parse_just_list(output),
+        vec![
+            ("build".to_owned(), "Build the project".to_owned()),
+            ("deploy".to_owned(), "env='staging'".to_owned()),
+            ("lint".to_owned(), "".to_owned()),
+        ]
+    );
+}
+
+/// Lists the recipes named by `just --summary`, described from the
+/// output of `just --list`.
+fn list_just_recipes(
+    _command_words: &[&str],
+    timeout: time::Duration,
+) -> Result<Vec<TaskCompletion>, String> {
+    let summary = subprocess::run(Command::new("just").arg("--summary"), timeout)?;
+    let descriptions = subprocess::run(Command::new("just").arg("--list"), timeout)
+        .map_or(vec![], |o| parse_just_list(&o));
+    Result::Ok(
+        summary
+            .split_whitespace()
+            .map(|name| TaskCompletion {
+                name: name.to_owned(),
+                description: descriptions
+                    .iter()
+                    .find(|(n, _)| n == name)
+                    .map(|(_, d)| d.clone())
+                    .filter(|d| !d.is_empty()),
+            })
+            .collect(),
+    )
+}
+
+/// A completer for the tasks of a build system.
+pub struct TaskCompleter {
+    runner: &'static TaskRunner,
+    command_words: Vec<String>,
+    timeout: time::Duration,
+
+    /// Lists the tasks in the background.
+    listing: subprocess::Background<Result<Vec<TaskCompletion>, String>>,
+    errors: Vec<String>,
+}
+
+impl TaskCompleter {
+    pub fn new(
+        runner: &'static TaskRunner,
+        command_words: &[&str],
+        config: &config::TasksConfig,
+    ) -> Self {
+        let mut completer = TaskCompleter {
+            runner,
+            command_words: command_words.iter().map(|w| (*w).to_owned()).collect(),
+            timeout: config.timeout(),
+            listing: subprocess::Background::idle(),
+            errors: vec![],
+        };
+        if core::Completer::is_applicable(&completer) {
+            completer.start();
+        }
+        completer
+    }
+
+    fn start(&mut self) {
+        let list = self.runner.list;
+        let command_words = self.command_words.clone();
+        let timeout = self.timeout;
+        self.listing = subprocess::Background::run(move || {
+            let words: Vec<&str> = command_words.iter().map(String::as_str).collect();
+            list(&words, timeout)
+        });
+    }
+}
+
+impl core::Completer for TaskCompleter {
+    fn name(&self) -> String {
+        self.runner.name.to_owned()
+    }
+
+    fn is_applicable(&self) -> bool {
+        let dir = match env::current_dir() {
+            Result::Ok(d) => d,
+            Result::Err(_) => return false,
+        };
+        let has_project_file = |d: &path::Path| {
+            self.runner
+                .project_files
+                .iter()
+                .any(|f| d.join(f).is_file())
+        };
+        if self.runner.search_ancestors {
+            dir.ancestors().any(has_project_file)
+        } else {
+            has_project_file(&dir)
+        }
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let name = self.runner.name;
+        let failed = || Result::Err(format!("listing the tasks of {} failed", name));
+        let result = match self.listing.poll(failed) {
+            Some(result) => result,
+            None => return vec![],
+        };
+        match result {
+            Result::Ok(completions) => completions
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        self.start();
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
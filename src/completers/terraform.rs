@@ -0,0 +1,569 @@
+//! Defines completers for the workspaces of a Terraform configuration
+//! and the resources in its state, which also work with OpenTofu.
+//!
+//! Resources are shown as a tree: a module holds its child modules and
+//! the types of its resources, e.g. `aws_instance`, and descending into
+//! a type lists the addresses of its instances, e.g.
+//! `aws_instance.web[0]`. The addresses come from `terraform state
+//! list`, which may query a remote backend, so its output is cached
+//! for a while. Modules without resources in the state are read from
+//! `.terraform/modules/modules.json`, written by `terraform init`.
+
+use std::any;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path;
+use std::process::Command;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread;
+use std::time;
+
+use serde::Deserialize;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// Splits a resource address into its steps, separated by dots outside
+/// of instance keys, which may be quoted strings with dots, e.g.
+/// `module.vpc["a.b"]` into `module` and `vpc["a.b"]`.
+fn split_steps(address: &str) -> Vec<&str> {
+    let mut steps = vec![];
+    let mut start = 0;
+    let mut in_brackets = false;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in address.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_quotes => escaped = true,
+            '"' if in_brackets => in_quotes = !in_quotes,
+            '[' if !in_quotes => in_brackets = true,
+            ']' if !in_quotes => in_brackets = false,
+            '.' if !in_brackets => {
+                steps.push(&address[start..i]);
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    steps.push(&address[start..]);
+    steps
+}
+
+/// A resource address split into the path of its module, e.g.
+/// `["module.vpc"]`, and its type, e.g. `aws_subnet` or `data.aws_ami`.
+#[derive(Debug, PartialEq)]
+struct Resource {
+    module: Vec<String>,
+    type_name: String,
+    address: String,
+}
+
+/// Parses a resource address, returning None if it is not one.
+fn parse_address(address: &str) -> Option<Resource> {
+    let steps = split_steps(address);
+    let mut module = vec![];
+    let mut i = 0;
+    while steps.get(i) == Some(&"module") {
+        module.push(format!("module.{}", steps.get(i + 1)?));
+        i += 2;
+    }
+    let type_name = match *steps.get(i)? {
+        "data" => {
+            i += 1;
+            format!("data.{}", steps.get(i)?)
+        }
+        t => t.to_owned(),
+    };
+    // The name of the resource must follow its type.
+    steps.get(i + 1)?;
+    Some(Resource {
+        module,
+        type_name,
+        address: address.to_owned(),
+    })
+}
+
+#[test]
+fn test_parse_address() {
+    assert_eq!(
+        parse_address("module.vpc[\"a.b\"].data.aws_ami.ubuntu"),
+        Some(Resource {
+            module: vec!["module.vpc[\"a.b\"]".to_owned()],
+            type_name: "data.aws_ami".to_owned(),
+            address: "module.vpc[\"a.b\"].data.aws_ami.ubuntu".to_owned(),
+        })
+    );
+    assert_eq!(
+        parse_address("aws_instance.web[0]").map(|r| (r.module, r.type_name)),
+        Some((vec![], "aws_instance".to_owned()))
+    );
+    assert_eq!(parse_address("module.vpc"), None);
+    assert_eq!(parse_address("aws_instance"), None);
+}
+
+/// The part of `.terraform/modules/modules.json` which the completer
+/// reads.
+#[derive(Deserialize)]
+struct ModulesManifest {
+    #[serde(rename = "Modules")]
+    modules: Vec<ModuleRecord>,
+}
+
+#[derive(Deserialize)]
+struct ModuleRecord {
+    /// The names of the module and its ancestors joined with dots, e.g.
+    /// `vpc.subnets`, or empty for the root module.
+    #[serde(rename = "Key")]
+    key: String,
+}
+
+/// Parses the manifest of the installed modules into the paths of the
+/// modules, e.g. `["module.vpc", "module.subnets"]`.
+fn parse_modules(json: &str) -> Result<Vec<Vec<String>>, String> {
+    let manifest: ModulesManifest = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    Result::Ok(
+        manifest
+            .modules
+            .iter()
+            .filter(|m| !m.key.is_empty())
+            .map(|m| m.key.split('.').map(|n| format!("module.{}", n)).collect())
+            .collect(),
+    )
+}
+
+#[test]
+fn test_parse_modules() {
+    let json = r#"{"Modules":[
+        {"Key":"","Source":"","Dir":"."},
+        {"Key":"vpc","Source":"./vpc","Dir":"vpc"},
+        {"Key":"vpc.subnets","Source":"./subnets","Dir":"vpc/subnets"}
+    ]}"#;
+    assert_eq!(
+        parse_modules(json),
+        Result::Ok(vec![
+            vec!["module.vpc".to_owned()],
+            vec!["module.vpc".to_owned(), "module.subnets".to_owned()],
+        ])
+    );
+    assert!(parse_modules("[]").is_err());
+}
+
+/// The resources in the state and the known modules.
+#[derive(Default)]
+struct State {
+    resources: Vec<Resource>,
+    modules: Vec<Vec<String>>,
+}
+
+/// The node of the tree of resources which a completer lists.
+#[derive(Clone, Debug, PartialEq)]
+enum Scope {
+    /// The child modules and the types of resources of a module.
+    Module(Vec<String>),
+
+    /// The instances of a type of resources in a module.
+    Type(Vec<String>, String),
+}
+
+#[derive(Debug, PartialEq)]
+struct TerraformCompletion {
+    /// The address, or its prefix for modules and types, e.g.
+    /// `module.vpc.aws_subnet`.
+    address: String,
+
+    /// The node which the completion descends to, if it isn't an
+    /// instance.
+    scope: Option<Scope>,
+
+    description: Option<String>,
+}
+
+impl core::Completion for TerraformCompletion {
+    fn result_string(&self) -> String {
+        self.address.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        if self.scope.is_some() {
+            core::CompletionKind::Directory
+        } else {
+            core::CompletionKind::Other
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns the completions under a node of the tree of resources. Like
+/// the file system completer searching subdirectories, a module lists
+/// all the modules, types of resources and instances nested in it, so
+/// that an instance can be found without descending to it. Types come
+/// with the numbers of their instances.
+fn descendants(state: &State, scope: &Scope) -> Vec<TerraformCompletion> {
+    let (module, type_name) = match scope {
+        Scope::Module(m) => (m, None),
+        Scope::Type(m, t) => (m, Some(t)),
+    };
+    let instances = state
+        .resources
+        .iter()
+        .filter(|r| match type_name {
+            Some(t) => r.module == *module && r.type_name == *t,
+            None => r.module.starts_with(module),
+        })
+        .map(|r| TerraformCompletion {
+            address: r.address.clone(),
+            scope: None,
+            description: None,
+        });
+    if type_name.is_some() {
+        return instances.collect();
+    }
+
+    let modules: BTreeSet<&Vec<String>> = state
+        .modules
+        .iter()
+        .chain(state.resources.iter().map(|r| &r.module))
+        .filter(|m| m.len() > module.len() && m.starts_with(module))
+        .collect();
+    let mut types: BTreeMap<(&Vec<String>, &str), usize> = BTreeMap::new();
+    for resource in state.resources.iter() {
+        if resource.module.starts_with(module) {
+            *types
+                .entry((&resource.module, &resource.type_name))
+                .or_default() += 1;
+        }
+    }
+    let modules = modules.into_iter().map(|m| TerraformCompletion {
+        address: m.join("."),
+        scope: Some(Scope::Module(m.clone())),
+        description: None,
+    });
+    let types = types.into_iter().map(|((module, type_name), count)| {
+        let mut address = module.clone();
+        address.push(type_name.to_owned());
+        TerraformCompletion {
+            address: address.join("."),
+            scope: Some(Scope::Type(module.clone(), type_name.to_owned())),
+            description: Some(if count == 1 {
+                "1 instance".to_owned()
+            } else {
+                format!("{} instances", count)
+            }),
+        }
+    });
+    modules.chain(types).chain(instances).collect()
+}
+
+#[test]
+fn test_descendants() {
+    let state = State {
+        resources: [
+            "aws_instance.web[0]",
+            "aws_instance.web[1]",
+            "data.aws_ami.ubuntu",
+            "module.vpc.aws_subnet.private",
+        ]
+        .iter()
+        .filter_map(|a| parse_address(a))
+        .collect(),
+        modules: vec![vec!["module.dns".to_owned()]],
+    };
+    let addresses = |scope: Scope| -> Vec<(String, Option<String>)> {
+        descendants(&state, &scope)
+            .into_iter()
+            .map(|c| (c.address, c.description))
+            .collect()
+    };
+    assert_eq!(
+        addresses(Scope::Module(vec![])),
+        vec![
+            ("module.dns".to_owned(), None),
+            ("module.vpc".to_owned(), None),
+            ("aws_instance".to_owned(), Some("2 instances".to_owned())),
+            ("data.aws_ami".to_owned(), Some("1 instance".to_owned())),
+            (
+                "module.vpc.aws_subnet".to_owned(),
+                Some("1 instance".to_owned())
+            ),
+            ("aws_instance.web[0]".to_owned(), None),
+            ("aws_instance.web[1]".to_owned(), None),
+            ("data.aws_ami.ubuntu".to_owned(), None),
+            ("module.vpc.aws_subnet.private".to_owned(), None),
+        ]
+    );
+    assert_eq!(
+        addresses(Scope::Type(vec![], "aws_instance".to_owned())),
+        vec![
+            ("aws_instance.web[0]".to_owned(), None),
+            ("aws_instance.web[1]".to_owned(), None),
+        ]
+    );
+    assert_eq!(
+        addresses(Scope::Module(vec!["module.vpc".to_owned()])),
+        vec![
+            (
+                "module.vpc.aws_subnet".to_owned(),
+                Some("1 instance".to_owned())
+            ),
+            ("module.vpc.aws_subnet.private".to_owned(), None),
+        ]
+    );
+}
+
+/// Reads the state with `program state list`, or from the cache,
+/// along with the installed modules.
+fn read_state(
+    program: &str,
+    use_cache: bool,
+    config: &config::TerraformConfig,
+) -> Result<State, String> {
+    let mut command = Command::new(program);
+    command.args(["state", "list"]);
+    let output = subprocess::run_expiring(
+        &mut command,
+        config.timeout(),
+        "terraform",
+        time::Duration::from_secs(config.cache_secs),
+        use_cache,
+    )?;
+    let modules = fs::read_to_string(path::Path::new(".terraform/modules/modules.json"))
+        .ok()
+        .and_then(|json| parse_modules(&json).ok())
+        .unwrap_or_default();
+    Result::Ok(State {
+        resources: output.lines().filter_map(parse_address).collect(),
+        modules,
+    })
+}
+
+/// A completer for the modules and resources in the state of the
+/// configuration in the current directory, at a node of their tree.
+pub struct TerraformResourceCompleter {
+    program: String,
+    scope: Scope,
+    config: config::TerraformConfig,
+
+    /// The state, once it is read.
+    state: Option<Arc<State>>,
+
+    /// Receives the state read in the background, until it is received.
+    receiver: Option<mpsc::Receiver<Result<State, String>>>,
+    errors: Vec<String>,
+}
+
+impl TerraformResourceCompleter {
+    /// Creates a completer of the root module, with `program` being
+    /// `terraform` or a compatible tool, like `tofu`.
+    pub fn new(program: &str, config: config::TerraformConfig) -> Self {
+        let mut completer = TerraformResourceCompleter {
+            program: program.to_owned(),
+            scope: Scope::Module(vec![]),
+            config,
+            state: None,
+            receiver: None,
+            errors: vec![],
+        };
+        completer.start(true);
+        completer
+    }
+
+    fn start(&mut self, use_cache: bool) {
+        let program = self.program.clone();
+        let config = self.config.clone();
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            // The result is not needed any more if the chooser exited.
+            let _ = sender.send(read_state(&program, use_cache, &config));
+        });
+        self.receiver = Some(receiver);
+    }
+}
+
+impl core::Completer for TerraformResourceCompleter {
+    fn name(&self) -> String {
+        "tf".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        let location = match self.scope {
+            Scope::Module(ref m) => m.join("."),
+            Scope::Type(ref m, ref t) => {
+                let mut steps = m.clone();
+                steps.push(t.clone());
+                steps.join(".")
+            }
+        };
+        Some(location).filter(|l| !l.is_empty())
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.receiver.is_none()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        if let Some(ref r) = self.receiver {
+            let result = match r.try_recv() {
+                Result::Ok(result) => result,
+                Result::Err(mpsc::TryRecvError::Empty) => return vec![],
+                Result::Err(mpsc::TryRecvError::Disconnected) => {
+                    Result::Err("listing Terraform resources failed".to_owned())
+                }
+            };
+            self.receiver = None;
+            match result {
+                Result::Ok(state) => self.state = Some(Arc::new(state)),
+                Result::Err(e) => self.errors.push(e),
+            }
+        }
+        match self.state {
+            Some(ref state) => descendants(state, &self.scope)
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            None => vec![],
+        }
+    }
+
+    /// Lists the resources anew, bypassing the cache.
+    fn refresh(&mut self) -> bool {
+        self.state = None;
+        self.start(false);
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let completion = completion.as_any().downcast_ref::<TerraformCompletion>()?;
+        Some(Box::new(TerraformResourceCompleter {
+            program: self.program.clone(),
+            scope: completion.scope.clone()?,
+            config: self.config.clone(),
+            state: self.state.clone(),
+            receiver: None,
+            errors: vec![],
+        }))
+    }
+}
+
+/// Parses the output of `terraform workspace list`, in which the
+/// current workspace is marked with `*`.
+fn parse_workspaces(output: &str) -> Vec<(String, bool)> {
+    output
+        .lines()
+        .filter_map(|line| {
+            let current = line.starts_with('*');
+            let name = line.trim_start_matches('*').trim();
+            if name.is_empty() {
+                None
+            } else {
+                Some((name.to_owned(), current))
+            }
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_workspaces() {
+    assert_eq!(
+        parse_workspaces("  default\n* staging\n\n"),
+        vec![("default".to_owned(), false), ("staging".to_owned(), true)]
+    );
+}
+
+struct WorkspaceCompletion {
+    name: String,
+    current: bool,
+}
+
+impl core::Completion for WorkspaceCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        if self.current {
+            Some("current".to_owned())
+        } else {
+            None
+        }
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A completer for the workspaces of the configuration in the current
+/// directory.
+pub struct TerraformWorkspaceCompleter {
+    program: String,
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl TerraformWorkspaceCompleter {
+    pub fn new(program: &str, timeout: time::Duration) -> Self {
+        Self {
+            program: program.to_owned(),
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for TerraformWorkspaceCompleter {
+    fn name(&self) -> String {
+        "ws".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        // Workspaces of the local backend are directories in
+        // `terraform.tfstate.d`, and the current one is recorded in
+        // `.terraform/environment`, so the listing changes with them.
+        // The daemon needs their absolute paths.
+        let watched: Vec<path::PathBuf> = match env::current_dir() {
+            Result::Ok(dir) => vec![
+                dir.join("terraform.tfstate.d"),
+                dir.join(".terraform").join("environment"),
+            ],
+            Result::Err(_) => vec![],
+        };
+        let mut command = Command::new(&self.program);
+        command.args(["workspace", "list"]);
+        match subprocess::run_cached(&mut command, self.timeout, &watched) {
+            Result::Ok(output) => parse_workspaces(&output)
+                .into_iter()
+                .map(|(name, current)| {
+                    Box::new(WorkspaceCompletion { name, current }) as core::CompletionBox
+                })
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,493 @@
+//! Defines a completer for the names of the tests of the project in the
+//! current directory, to be run alone, e.g. with `cargo test <name>`.
+//!
+//! The tests are listed by the runner the command line invokes: Cargo
+//! with `cargo test -- --list`, pytest with `pytest --collect-only`,
+//! while the tests of Go are read from the `_test.go` files directly.
+//! Listing tests with Cargo builds them first, so it runs in the
+//! background, with a long timeout.
+
+use std::any;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path;
+use std::process::Command;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+
+/// The options of `cargo test` which select the tests built, passed on
+/// when listing them, followed by whether they take a value.
+const CARGO_OPTIONS: &[(&str, bool)] = &[
+    ("-p", true),
+    ("--package", true),
+    ("--test", true),
+    ("--features", true),
+    ("-F", true),
+    ("--manifest-path", true),
+    ("--lib", false),
+    ("--bins", false),
+    ("--tests", false),
+    ("--workspace", false),
+    ("--all-features", false),
+    ("--no-default-features", false),
+];
+
+/// The other options of `cargo test` which take a value.
+const CARGO_VALUE_OPTIONS: &[&str] = &[
+    "--bin",
+    "--example",
+    "--bench",
+    "--exclude",
+    "-j",
+    "--jobs",
+    "--target",
+    "--target-dir",
+    "--profile",
+    "--color",
+    "--message-format",
+];
+
+/// The runners of tests whose tests are completed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Runner {
+    /// `cargo test`, with the options selecting the tests built.
+    Cargo { options: Vec<String> },
+    /// pytest, invoked with the given words, e.g. `python -m pytest`.
+    Pytest { command: Vec<String> },
+    /// `go test`, with the directories of the packages tested, which
+    /// are searched recursively if `recursive`, and the prefixes of the
+    /// functions matched by the option completed, e.g. `Benchmark`.
+    Go {
+        dirs: Vec<(String, bool)>,
+        prefixes: &'static [&'static str],
+    },
+}
+
+impl Runner {
+    /// Returns the file which a project of the runner has in its root
+    /// directory, if it needs one.
+    fn project_file(&self) -> Option<&'static str> {
+        match *self {
+            Runner::Cargo { .. } => Some("Cargo.toml"),
+            Runner::Pytest { .. } => None,
+            Runner::Go { .. } => Some("go.mod"),
+        }
+    }
+}
+
+/// Returns the options of `cargo test` among `words` which select the
+/// tests built.
+fn cargo_options(words: &[&str]) -> Vec<String> {
+    let mut options = vec![];
+    let mut words = words.iter();
+    while let Some(word) = words.next() {
+        if *word == "--" {
+            break;
+        }
+        let name = word.split('=').next().unwrap_or(word);
+        match CARGO_OPTIONS.iter().find(|(o, _)| *o == name) {
+            Some((_, true)) if !word.contains('=') => {
+                options.push((*word).to_owned());
+                options.extend(words.next().map(|w| (*w).to_owned()));
+            }
+            Some(_) => options.push((*word).to_owned()),
+            None => {}
+        }
+    }
+    options
+}
+
+/// Returns the directories of the packages named by the words of a
+/// `go test` command line, e.g. `./...`, with whether each is to be
+/// searched recursively. The current directory is tested by default.
+fn go_dirs(words: &[&str]) -> Vec<(String, bool)> {
+    let dirs: Vec<(String, bool)> = words
+        .iter()
+        .filter(|w| w.starts_with('.'))
+        .map(|w| match w.strip_suffix("...") {
+            Some(dir) => (dir.trim_end_matches('/').to_owned(), true),
+            None => ((*w).to_owned(), false),
+        })
+        .map(|(dir, recursive)| {
+            if dir.is_empty() {
+                (".".to_owned(), recursive)
+            } else {
+                (dir, recursive)
+            }
+        })
+        .collect();
+    if dirs.is_empty() {
+        vec![(".".to_owned(), false)]
+    } else {
+        dirs
+    }
+}
+
+/// Returns the runner whose tests are completed on a command line with
+/// the given words preceding the query, if any.
+///
+/// Options expecting other values, e.g. `cargo test --test`, are left
+/// to other completers.
+pub fn runner(command_words: &[&str]) -> Option<Runner> {
+    let last = command_words.last()?;
+    match command_words {
+        ["cargo", "test", rest @ ..] | ["cargo", "t", rest @ ..] => {
+            let expects_value = CARGO_OPTIONS.iter().any(|(o, v)| *v && o == last)
+                || CARGO_VALUE_OPTIONS.contains(last);
+            if expects_value {
+                return None;
+            }
+            Some(Runner::Cargo {
+                options: cargo_options(rest),
+            })
+        }
+        ["pytest", ..] | ["py.test", ..] => Some(Runner::Pytest {
+            command: vec![command_words[0].to_owned()],
+        }),
+        [python, "-m", "pytest", ..] if python.starts_with("python") => Some(Runner::Pytest {
+            command: command_words[..3].iter().map(|w| (*w).to_owned()).collect(),
+        }),
+        ["go", "test", rest @ ..] => {
+            let prefixes: &'static [&'static str] = match *last {
+                "-run" | "-skip" => &["Test", "Example", "Fuzz"],
+                "-bench" => &["Benchmark"],
+                "-fuzz" => &["Fuzz"],
+                _ => return None,
+            };
+            Some(Runner::Go {
+                dirs: go_dirs(rest),
+                prefixes,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test_runner() {
+    assert_eq!(
+        runner(&["cargo", "test", "-p", "core", "--lib"]),
+        Some(Runner::Cargo {
+            options: vec!["-p".to_owned(), "core".to_owned(), "--lib".to_owned()],
+        })
+    );
+    assert_eq!(
+        runner(&["cargo", "t", "--release"]),
+        Some(Runner::Cargo { options: vec![] })
+    );
+    assert_eq!(runner(&["cargo", "test", "--test"]), None);
+    assert_eq!(runner(&["cargo", "test", "--jobs"]), None);
+    assert_eq!(
+        runner(&["python3", "-m", "pytest", "-x"]),
+        Some(Runner::Pytest {
+            command: vec!["python3".to_owned(), "-m".to_owned(), "pytest".to_owned()],
+        })
+    );
+    assert_eq!(
+        runner(&["go", "test", "./pkg/...", "-bench"]),
+        Some(Runner::Go {
+            dirs: vec![("./pkg".to_owned(), true)],
+            prefixes: &["Benchmark"],
+        })
+    );
+    assert_eq!(
+        runner(&["go", "test", "-run"]),
+        Some(Runner::Go {
+            dirs: vec![(".".to_owned(), false)],
+            prefixes: &["Test", "Example", "Fuzz"],
+        })
+    );
+    assert_eq!(runner(&["go", "test"]), None);
+    assert_eq!(runner(&["cargo", "build"]), None);
+}
+
+#[derive(Debug, PartialEq)]
+struct TestCompletion {
+    name: String,
+
+    /// E.g. the file defining the test.
+    description: Option<String>,
+}
+
+impl core::Completion for TestCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Function
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of `cargo test -- --list` into the names of the
+/// tests, listed once even when several targets share them. Doctests,
+/// which can't be run alone by name, are skipped.
+fn parse_cargo_list(output: &str) -> Vec<TestCompletion> {
+    let mut seen = HashSet::new();
+    output
+        .lines()
+        .filter_map(|l| l.strip_suffix(": test"))
+        .filter(|n| !n.contains(' ') && seen.insert(n.to_owned()))
+        .map(|n| TestCompletion {
+            name: n.to_owned(),
+            description: None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_cargo_list() {
+    let output = "config::test_default: test
+core::tests::test_score: test
+bench_sort: benchmark
+
+2 tests, 1 benchmark
+config::test_default: test
+src/lib.rs - core::score (line 12): test
+";
+    assert_eq!(
+        parse_cargo_list(output)
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>(),
+        vec!["config::test_default", "core::tests::test_score"]
+    );
+}
+
+/// Parses the output of `pytest --collect-only -q` into the node IDs of
+/// the tests, e.g. `tests/test_app.py::TestApp::test_run`.
+fn parse_pytest_collection(output: &str) -> Vec<TestCompletion> {
+    output
+        .lines()
+        .filter(|l| {
+            l.split("::")
+                .next()
+                .is_some_and(|f| f.ends_with(".py") && !f.contains(' ') && f != *l)
+        })
+        .map(|l| TestCompletion {
+            name: l.to_owned(),
+            description: None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_pytest_collection() {
+    let output = "tests/test_app.py::test_start
+tests/test_app.py::TestApp::test_run[fast mode]
+
+2 tests collected in 0.02s
+";
+    assert_eq!(
+        parse_pytest_collection(output)
+            .into_iter()
+            .map(|c| c.name)
+            .collect::<Vec<_>>(),
+        vec![
+            "tests/test_app.py::test_start",
+            "tests/test_app.py::TestApp::test_run[fast mode]",
+        ]
+    );
+}
+
+/// Returns the names of the functions of a Go test file starting with
+/// one of `prefixes`. As for `go test`, the prefix must not be followed
+/// by a lowercase letter, so that e.g. `Testify` isn't a test.
+fn parse_go_tests(source: &str, prefixes: &[&str]) -> Vec<String> {
+    source
+        .lines()
+        .filter_map(|l| l.strip_prefix("func "))
+        .filter_map(|l| l.split('(').next())
+        .filter(|name| {
+            prefixes.iter().any(|p| {
+                name.strip_prefix(p)
+                    .is_some_and(|r| !r.starts_with(|c: char| c.is_lowercase()))
+            })
+        })
+        .map(|name| name.trim().to_owned())
+        .collect()
+}
+
+#[test]
+fn test_parse_go_tests() {
+    let source = "package app
+
+func TestStart(t *testing.T) {}
+func Testify() {}
+func (s *suite) TestMethod() {}
+func ExampleApp() {}
+func BenchmarkRun(b *testing.B) {}
+";
+    assert_eq!(
+        parse_go_tests(source, &["Test", "Example", "Fuzz"]),
+        vec!["TestStart", "ExampleApp"]
+    );
+    assert_eq!(parse_go_tests(source, &["Benchmark"]), vec!["BenchmarkRun"]);
+}
+
+/// Appends the tests of the `_test.go` files in a directory, and in its
+/// subdirectories if `recursive`, skipping those ignored by Go.
+fn find_go_tests(
+    dir: &path::Path,
+    recursive: bool,
+    prefixes: &[&str],
+    completions: &mut Vec<TestCompletion>,
+) {
+    let entries = match fs::read_dir(dir) {
+        Result::Ok(e) => e,
+        Result::Err(_) => return,
+    };
+    let mut paths: Vec<path::PathBuf> = entries.filter_map(|e| e.ok()).map(|e| e.path()).collect();
+    paths.sort();
+    for path in paths {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        if name.starts_with('.') || name.starts_with('_') {
+            continue;
+        }
+        if path.is_dir() {
+            if recursive && name != "testdata" && name != "vendor" {
+                find_go_tests(&path, recursive, prefixes, completions);
+            }
+        } else if name.ends_with("_test.go") {
+            let source = fs::read_to_string(&path).unwrap_or_default();
+            let file = path
+                .strip_prefix(".")
+                .unwrap_or(&path)
+                .display()
+                .to_string();
+            completions.extend(parse_go_tests(&source, prefixes).into_iter().map(|name| {
+                TestCompletion {
+                    name,
+                    description: Some(file.clone()),
+                }
+            }));
+        }
+    }
+}
+
+/// Lists the tests of a runner, returning the completions or the error.
+fn list_tests(
+    runner: &Runner,
+    config: &config::TestsConfig,
+) -> Result<Vec<TestCompletion>, String> {
+    match *runner {
+        Runner::Cargo { ref options } => subprocess::run(
+            Command::new("cargo")
+                .args(["test", "--quiet"])
+                .args(options)
+                .args(["--", "--list"]),
+            config.timeout(),
+        )
+        .map(|o| parse_cargo_list(&o)),
+        Runner::Pytest { ref command } => subprocess::run(
+            Command::new(&command[0])
+                .args(&command[1..])
+                .args(["--collect-only", "-q"]),
+            config.timeout(),
+        )
+        .map(|o| parse_pytest_collection(&o)),
+        Runner::Go { ref dirs, prefixes } => {
+            let mut completions = vec![];
+            for (dir, recursive) in dirs {
+                find_go_tests(path::Path::new(dir), *recursive, prefixes, &mut completions);
+            }
+            Result::Ok(completions)
+        }
+    }
+}
+
+/// A completer for the names of the tests of the project in the current
+/// directory.
+pub struct TestCompleter {
+    runner: Runner,
+    config: config::TestsConfig,
+
+    /// Lists the tests in the background.
+    listing: subprocess::Background<Result<Vec<TestCompletion>, String>>,
+    errors: Vec<String>,
+}
+
+impl TestCompleter {
+    pub fn new(runner: Runner, config: config::TestsConfig) -> Self {
+        let mut completer = TestCompleter {
+            runner,
+            config,
+            listing: subprocess::Background::idle(),
+            errors: vec![],
+        };
+        if core::Completer::is_applicable(&completer) {
+            completer.start();
+        }
+        completer
+    }
+
+    fn start(&mut self) {
+        let runner = self.runner.clone();
+        let config = self.config.clone();
+        self.listing = subprocess::Background::run(move || list_tests(&runner, &config));
+    }
+}
+
+impl core::Completer for TestCompleter {
+    fn name(&self) -> String {
+        "test".to_owned()
+    }
+
+    /// Applies in projects of the runner, whose root directory may be
+    /// above the current one.
+    fn is_applicable(&self) -> bool {
+        let file = match self.runner.project_file() {
+            Some(f) => f,
+            None => return true,
+        };
+        match env::current_dir() {
+            Result::Ok(dir) => dir.ancestors().any(|d| d.join(file).is_file()),
+            Result::Err(_) => false,
+        }
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.listing.is_finished()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let failed = || Result::Err("listing tests failed".to_owned());
+        let result = match self.listing.poll(failed) {
+            Some(result) => result,
+            None => return vec![],
+        };
+        match result {
+            Result::Ok(completions) => completions
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        self.start();
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
@@ -0,0 +1,269 @@
+//! Defines the completers declared by the user in the configuration,
+//! which list the completions printed by shell commands.
+//!
+//! Each line printed by the command is a completion. Lines may be split
+//! into columns, so that the inserted result, the displayed text and
+//! the description are taken from different columns, e.g. a host name
+//! and its address.
+//!
+//! A completer with a query command is dynamic: the command is run
+//! anew whenever the query changes, in a background thread, so that
+//! a slow search doesn't hold up typing.
+
+use std::any;
+use std::process::Command;
+use std::sync::mpsc;
+use std::thread;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::config;
+use crate::core;
+use crate::quoting;
+
+/// The placeholder replaced with the query in the query command.
+const QUERY_PLACEHOLDER: &str = "{query}";
+
+#[derive(Debug, PartialEq)]
+struct UserCompletion {
+    result: String,
+    display: Option<String>,
+    description: Option<String>,
+    kind: core::CompletionKind,
+}
+
+impl core::Completion for UserCompletion {
+    fn result_string(&self) -> String {
+        self.result.clone()
+    }
+
+    fn display_string(&self) -> String {
+        self.display.clone().unwrap_or_else(|| self.result.clone())
+    }
+
+    fn search_string(&self) -> String {
+        self.display_string()
+    }
+
+    fn description(&self) -> Option<String> {
+        self.description.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        self.kind
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of the command of a completer into completions.
+/// Lines without the result column, or with an empty one, are skipped.
+fn parse_output(output: &str, config: &config::UserCompleterConfig) -> Vec<UserCompletion> {
+    let column = |columns: &[&str], number: Option<usize>| {
+        number
+            .and_then(|n| columns.get(n.wrapping_sub(1)))
+            .map(|c| c.trim().to_owned())
+            .filter(|c| !c.is_empty())
+    };
+    output
+        .lines()
+        .filter_map(|line| {
+            let columns: Vec<&str> = match config.separator {
+                Some(ref s) if !s.is_empty() => line.split(s.as_str()).collect(),
+                _ => vec![line],
+            };
+            Some(UserCompletion {
+                result: column(&columns, Some(config.result_column.unwrap_or(1)))?,
+                display: column(&columns, config.display_column),
+                description: column(&columns, config.description_column),
+                kind: config.kind.unwrap_or(core::CompletionKind::Other),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_output() {
+    let config = config::Config::parse(
+        "[[completers]]\n\
+         name = \"hosts\"\n\
+         command = \"cat hosts\"\n\
+         separator = \"\\t\"\n\
+         result_column = 2\n\
+         display_column = 1\n\
+         description_column = 3\n\
+         kind = \"host\"\n",
+    )
+    .unwrap();
+    let completion = |result: &str, display: &str, description: Option<&str>| UserCompletion {
+        result: result.to_owned(),
+        display: Some(display.to_owned()),
+        description: description.map(str::to_owned),
+        kind: core::CompletionKind::Host,
+    };
+    assert_eq!(
+        parse_output(
+            "web\t10.0.0.1\tfront end\ndb\t10.0.0.2\nbroken\n",
+            &config.completers[0]
+        ),
+        vec![
+            completion("10.0.0.1", "web", Some("front end")),
+            completion("10.0.0.2", "db", None),
+        ]
+    );
+
+    let config = config::Config::parse(
+        "[[completers]]\n\
+         name = \"lines\"\n\
+         command = \"cat lines\"\n",
+    )
+    .unwrap();
+    assert_eq!(
+        parse_output("a b\n\nc\n", &config.completers[0]),
+        vec![
+            UserCompletion {
+                result: "a b".to_owned(),
+                display: None,
+                description: None,
+                kind: core::CompletionKind::Other,
+            },
+            UserCompletion {
+                result: "c".to_owned(),
+                display: None,
+                description: None,
+                kind: core::CompletionKind::Other,
+            },
+        ]
+    );
+}
+
+/// Returns the command to run for the query: the query command with the
+/// query in place of the placeholders, if there is a query and a query
+/// command, or the command listing all completions otherwise.
+fn command_for_query(config: &config::UserCompleterConfig, query: &str) -> String {
+    match config.query_command {
+        Some(ref c) if !query.is_empty() => c.replace(
+            QUERY_PLACEHOLDER,
+            &quoting::quote(query, quoting::QuotingStyle::SingleQuote),
+        ),
+        _ => config.command.clone(),
+    }
+}
+
+#[test]
+fn test_command_for_query() {
+    let config = config::Config::parse(
+        "[[completers]]\n\
+         name = \"grep\"\n\
+         command = \"rg --files\"\n\
+         query_command = \"rg -l {query}\"\n",
+    )
+    .unwrap();
+    let config = &config.completers[0];
+    assert_eq!(command_for_query(config, ""), "rg --files");
+    assert_eq!(command_for_query(config, "fn main"), "rg -l 'fn main'");
+}
+
+/// The state of the command of a completer.
+enum Run {
+    NotStarted,
+
+    /// The command is running, and its output will be received.
+    Running(mpsc::Receiver<Result<String, String>>),
+
+    Finished,
+}
+
+/// A completer listing the completions printed by a command declared
+/// in the configuration.
+pub struct UserCompleter {
+    config: config::UserCompleterConfig,
+    query: String,
+    run: Run,
+
+    /// The time after which the command is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl UserCompleter {
+    pub fn new(config: config::UserCompleterConfig, timeout: time::Duration) -> Self {
+        UserCompleter {
+            config,
+            query: String::new(),
+            run: Run::NotStarted,
+            timeout,
+            errors: vec![],
+        }
+    }
+
+    /// Starts the command for the current query in the background.
+    fn start(&self) -> mpsc::Receiver<Result<String, String>> {
+        let command = command_for_query(&self.config, &self.query);
+        let timeout = self.timeout;
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let output = subprocess::run(Command::new("sh").args(["-c", &command]), timeout);
+            // The output is not needed any more if the query changed.
+            let _ = sender.send(output);
+        });
+        receiver
+    }
+}
+
+impl core::Completer for UserCompleter {
+    fn name(&self) -> String {
+        self.config.name.clone()
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        matches!(self.run, Run::Finished)
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        if let Run::NotStarted = self.run {
+            self.run = Run::Running(self.start());
+        }
+        let output = match self.run {
+            Run::Running(ref receiver) => match receiver.try_recv() {
+                Result::Ok(output) => output,
+                Result::Err(mpsc::TryRecvError::Empty) => return vec![],
+                Result::Err(mpsc::TryRecvError::Disconnected) => {
+                    Result::Err(format!("{} failed", self.config.name))
+                }
+            },
+            _ => return vec![],
+        };
+        self.run = Run::Finished;
+        match output {
+            Result::Ok(output) => parse_output(&output, &self.config)
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        self.run = Run::NotStarted;
+        true
+    }
+
+    fn is_dynamic(&self) -> bool {
+        self.config.query_command.is_some()
+    }
+
+    fn requery(&mut self, query: &str) {
+        self.query = query.to_owned();
+        self.run = Run::NotStarted;
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
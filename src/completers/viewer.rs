@@ -0,0 +1,292 @@
+//! Defines a pseudo-completer showing the contents of a file, which the
+//! file system completer descends into for files.
+//!
+//! The first lines of the file are listed as completions, so that they
+//! can be scrolled and searched like any others, and each of them
+//! completes the file itself. The syntax of source code is highlighted
+//! and binary files are shown as a hexdump. Ascending returns to the
+//! directory the file is in.
+
+use std::any;
+use std::fs;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::path;
+
+use crate::core;
+use crate::highlight;
+
+/// The number of bytes shown on a line of a hexdump.
+const HEXDUMP_WIDTH: usize = 16;
+
+/// The number of bytes at the start of a file which are checked to
+/// tell binary files, which have null bytes, from text.
+const BINARY_CHECK_LENGTH: usize = 8000;
+
+/// The number of columns a tab is expanded to.
+const TAB_WIDTH: usize = 4;
+
+struct LineCompletion {
+    path: String,
+
+    /// The number of the line, or the offset of the bytes of a line of
+    /// a hexdump.
+    position: String,
+    spans: Vec<core::Span>,
+}
+
+impl core::Completion for LineCompletion {
+    fn result_string(&self) -> String {
+        self.path.clone()
+    }
+
+    fn display_spans(&self) -> Vec<core::Span> {
+        vec![core::Span::styled(
+            format!("{} ", self.position),
+            core::Style::colored(core::SpanColor::Dimmed),
+        )]
+        .into_iter()
+        .chain(self.spans.iter().cloned())
+        .collect()
+    }
+
+    fn search_string(&self) -> String {
+        self.spans.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Makes a line of a file displayable: tabs are expanded and other
+/// control characters, which could be escape sequences, are replaced.
+fn displayable(line: &str) -> String {
+    line.trim_end_matches(['\n', '\r'])
+        .replace('\t', &" ".repeat(TAB_WIDTH))
+        .chars()
+        .map(|c| if c.is_control() { '\u{fffd}' } else { c })
+        .collect()
+}
+
+#[test]
+fn test_displayable() {
+    assert_eq!(displayable("a\tb\x1b[31m\r\n"), "a    b\u{fffd}[31m");
+}
+
+/// Formats a line of a hexdump: the bytes in hexadecimal, padded to
+/// the full width, followed by their printable ASCII characters.
+fn hexdump_line(bytes: &[u8]) -> String {
+    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+    let ascii: String = bytes
+        .iter()
+        .map(|b| {
+            if b.is_ascii_graphic() || *b == b' ' {
+                *b as char
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!(
+        "{:width$}  |{}|",
+        hex.join(" "),
+        ascii,
+        width = HEXDUMP_WIDTH * 3 - 1
+    )
+}
+
+#[test]
+fn test_hexdump_line() {
+    assert_eq!(
+        hexdump_line(b"\x7fELF\x02\x01\x01\x00abcdefgh"),
+        "7f 45 4c 46 02 01 01 00 61 62 63 64 65 66 67 68  |.ELF....abcdefgh|"
+    );
+    assert_eq!(
+        hexdump_line(b"ab"),
+        format!("61 62{}  |ab|", " ".repeat(42))
+    );
+}
+
+/// Reads the first `max_lines` lines of a file, as pairs of positions
+/// and spans, highlighted if `highlight`, or of its hexdump if it is
+/// binary.
+fn read_lines(
+    file_path: &path::Path,
+    max_lines: usize,
+    highlight: bool,
+) -> Result<Vec<(String, Vec<core::Span>)>, String> {
+    let file = fs::File::open(file_path).map_err(|e| e.to_string())?;
+    let mut reader = BufReader::new(file);
+    let is_binary = reader
+        .fill_buf()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .take(BINARY_CHECK_LENGTH)
+        .any(|b| *b == 0);
+    if is_binary {
+        let mut bytes = vec![];
+        reader
+            .take((max_lines * HEXDUMP_WIDTH) as u64)
+            .read_to_end(&mut bytes)
+            .map_err(|e| e.to_string())?;
+        return Result::Ok(
+            bytes
+                .chunks(HEXDUMP_WIDTH)
+                .enumerate()
+                .map(|(i, c)| {
+                    (
+                        format!("{:08x}", i * HEXDUMP_WIDTH),
+                        vec![core::Span::plain(hexdump_line(c))],
+                    )
+                })
+                .collect(),
+        );
+    }
+    let mut lines = vec![];
+    let mut line = vec![];
+    let mut highlighter = None;
+    while lines.len() < max_lines {
+        line.clear();
+        match reader.read_until(b'\n', &mut line) {
+            Result::Ok(0) => break,
+            Result::Ok(_) => {
+                let text = String::from_utf8_lossy(&line);
+                if highlight && lines.is_empty() {
+                    highlighter = highlight::Highlighter::for_file(file_path, &text);
+                }
+                // Lines after one which can't be parsed are plain.
+                let spans = match highlighter.as_mut().and_then(|h| h.highlight_line(&text)) {
+                    Some(spans) => spans,
+                    None => {
+                        highlighter = None;
+                        vec![core::Span::plain(text.as_ref())]
+                    }
+                };
+                let spans = spans
+                    .into_iter()
+                    .map(|s| core::Span::styled(displayable(&s.text), s.style))
+                    .filter(|s| !s.text.is_empty())
+                    .collect();
+                lines.push(((lines.len() + 1).to_string(), spans));
+            }
+            Result::Err(e) => return Result::Err(e.to_string()),
+        }
+    }
+    // The numbers of lines are aligned.
+    let width = lines.last().map_or(0, |(n, _)| n.len());
+    Result::Ok(
+        lines
+            .into_iter()
+            .map(|(n, spans)| (format!("{:>width$}", n, width = width), spans))
+            .collect(),
+    )
+}
+
+#[test]
+fn test_read_lines() {
+    let dir = std::env::temp_dir().join(format!("completers-viewer-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let text_path = dir.join("text");
+    fs::write(
+        &text_path,
+        (1..=12)
+            .map(|i| format!("line {}\n", i))
+            .collect::<String>(),
+    )
+    .unwrap();
+    let lines = read_lines(&text_path, 10, true).unwrap();
+    assert_eq!(lines.len(), 10);
+    assert_eq!(
+        lines[0],
+        (" 1".to_owned(), vec![core::Span::plain("line 1")])
+    );
+    assert_eq!(
+        lines[9],
+        ("10".to_owned(), vec![core::Span::plain("line 10")])
+    );
+
+    let source_path = dir.join("script.py");
+    fs::write(&source_path, "# a\n").unwrap();
+    let lines = read_lines(&source_path, 10, true).unwrap();
+    assert_eq!(
+        lines[0].1,
+        vec![core::Span::styled(
+            "# a",
+            core::Style::colored(core::SpanColor::Syntax(core::SyntaxClass::Comment))
+        )]
+    );
+    let lines = read_lines(&source_path, 10, false).unwrap();
+    assert_eq!(lines[0].1, vec![core::Span::plain("# a")]);
+
+    let binary_path = dir.join("binary");
+    fs::write(&binary_path, [0u8; 40]).unwrap();
+    let lines = read_lines(&binary_path, 2, true).unwrap();
+    assert_eq!(
+        lines.iter().map(|(p, _)| p.as_str()).collect::<Vec<_>>(),
+        vec!["00000000", "00000010"]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(read_lines(&text_path, 10, true).is_err());
+}
+
+/// A pseudo-completer listing the first lines of a file.
+pub struct FileViewer {
+    file_path: path::PathBuf,
+    max_lines: usize,
+
+    /// Whether the syntax of source code is highlighted.
+    highlight: bool,
+    errors: Vec<String>,
+}
+
+impl FileViewer {
+    pub fn new(file_path: path::PathBuf, max_lines: usize, highlight: bool) -> Self {
+        FileViewer {
+            file_path,
+            max_lines,
+            highlight,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for FileViewer {
+    fn name(&self) -> String {
+        "view".to_owned()
+    }
+
+    fn location(&self) -> Option<String> {
+        Some(self.file_path.display().to_string())
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let path = self.file_path.to_string_lossy().into_owned();
+        match read_lines(&self.file_path, self.max_lines, self.highlight) {
+            Result::Ok(lines) => lines
+                .into_iter()
+                .map(|(position, spans)| {
+                    Box::new(LineCompletion {
+                        path: path.clone(),
+                        position,
+                        spans,
+                    }) as core::CompletionBox
+                })
+                .collect(),
+            Result::Err(e) => {
+                self.errors
+                    .push(format!("{}: {}", self.file_path.display(), e));
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
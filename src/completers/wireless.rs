@@ -0,0 +1,431 @@
+//! Defines completers for wireless networks and devices: the Wi-Fi
+//! networks in range and the connections of NetworkManager, listed with
+//! `nmcli`, and the paired Bluetooth devices, listed with
+//! `bluetoothctl`.
+
+use std::any;
+use std::process::Command;
+use std::time;
+
+use crate::completers::subprocess;
+use crate::core;
+
+/// The subcommands of `nmcli connection` taking the name of a
+/// connection.
+const CONNECTION_ACTIONS: &[&str] = &["up", "down", "show", "modify", "edit", "delete"];
+
+/// The commands of `bluetoothctl` taking the address of a device.
+const BLUETOOTH_ACTIONS: &[&str] = &[
+    "connect",
+    "disconnect",
+    "info",
+    "pair",
+    "remove",
+    "trust",
+    "untrust",
+    "block",
+    "unblock",
+];
+
+/// The items of NetworkManager completed on `nmcli` command lines.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NetworkItem {
+    /// A saved connection, e.g. for `nmcli connection up`.
+    Connection,
+    /// A Wi-Fi network in range, for `nmcli device wifi connect`.
+    WifiNetwork,
+}
+
+/// Indicates if `word` abbreviates `object`, as `nmcli` accepts, e.g.
+/// `con` for `connection`.
+fn abbreviates(word: &str, object: &str) -> bool {
+    !word.is_empty() && object.starts_with(word)
+}
+
+/// Returns the item of NetworkManager completed on a command line with
+/// the given words preceding the query, if any.
+pub fn network_item(command_words: &[&str]) -> Option<NetworkItem> {
+    match *command_words {
+        ["nmcli", object, action] if abbreviates(object, "connection") => {
+            if CONNECTION_ACTIONS.contains(&action) {
+                Some(NetworkItem::Connection)
+            } else {
+                None
+            }
+        }
+        ["nmcli", object, "wifi", "connect"] if abbreviates(object, "device") => {
+            Some(NetworkItem::WifiNetwork)
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test_network_item() {
+    assert_eq!(
+        network_item(&["nmcli", "connection", "up"]),
+        Some(NetworkItem::Connection)
+    );
+    assert_eq!(
+        network_item(&["nmcli", "c", "down"]),
+        Some(NetworkItem::Connection)
+    );
+    assert_eq!(
+        network_item(&["nmcli", "dev", "wifi", "connect"]),
+        Some(NetworkItem::WifiNetwork)
+    );
+    assert_eq!(network_item(&["nmcli", "connection", "up", "home"]), None);
+    assert_eq!(network_item(&["nmcli", "general", "up"]), None);
+    assert_eq!(network_item(&["nmcli", "connection", "add"]), None);
+}
+
+/// Indicates if the address of a Bluetooth device is completed on a
+/// command line with the given words preceding the query.
+pub fn completes_bluetooth_device(command_words: &[&str]) -> bool {
+    match *command_words {
+        ["bluetoothctl", action] => BLUETOOTH_ACTIONS.contains(&action),
+        _ => false,
+    }
+}
+
+#[test]
+fn test_completes_bluetooth_device() {
+    assert!(completes_bluetooth_device(&["bluetoothctl", "connect"]));
+    assert!(!completes_bluetooth_device(&["bluetoothctl", "scan"]));
+    assert!(!completes_bluetooth_device(&["bluetoothctl"]));
+}
+
+/// Splits a line of the terse output of `nmcli` into its fields, which
+/// are separated by colons, with colons and backslashes in them
+/// escaped.
+fn split_terse(line: &str) -> Vec<String> {
+    let mut fields = vec![String::new()];
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => fields.last_mut().unwrap().extend(chars.next()),
+            ':' => fields.push(String::new()),
+            c => fields.last_mut().unwrap().push(c),
+        }
+    }
+    fields
+}
+
+#[test]
+fn test_split_terse() {
+    assert_eq!(
+        split_terse(r"Caf\\e\: guests:78:WPA2"),
+        vec![r"Caf\e: guests", "78", "WPA2"]
+    );
+    assert_eq!(split_terse(":0:"), vec!["", "0", ""]);
+}
+
+#[derive(Debug, PartialEq)]
+struct NetworkCompletion {
+    name: String,
+
+    /// E.g. the type of a connection, or the security of a network.
+    kind: String,
+
+    /// The strength of the signal of the network, in percents, if it
+    /// is in range.
+    signal: Option<u8>,
+}
+
+impl core::Completion for NetworkCompletion {
+    fn result_string(&self) -> String {
+        self.name.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        match self.signal {
+            Some(s) => Some(format!("{}% {}", s, self.kind).trim_end().to_owned()),
+            None => Some(self.kind.clone()),
+        }
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Host
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the output of `nmcli -t -f SSID,SIGNAL,SECURITY device wifi
+/// list` into the networks, listed once with the strongest signal of
+/// their access points, from the strongest one. Hidden networks are
+/// skipped.
+fn parse_wifi_networks(output: &str) -> Vec<NetworkCompletion> {
+    let mut networks: Vec<NetworkCompletion> = vec![];
+    for line in output.lines() {
+        let fields = split_terse(line);
+        let (name, signal, security) = match fields.as_slice() {
+            [name, signal, security] if !name.is_empty() => (name, signal, security),
+            _ => continue,
+        };
+        let signal = signal.parse().ok();
+        match networks.iter_mut().find(|n| &n.name == name) {
+            Some(n) => n.signal = n.signal.max(signal),
+            None => networks.push(NetworkCompletion {
+                name: name.clone(),
+                kind: security.clone(),
+                signal,
+            }),
+        }
+    }
+    networks.sort_by_key(|n| std::cmp::Reverse(n.signal));
+    networks
+}
+
+#[test]
+fn test_parse_wifi_networks() {
+    let output = "home:54:WPA2
+:40:WPA2
+Caf\\: guests:78:
+home:81:WPA2
+";
+    assert_eq!(
+        parse_wifi_networks(output),
+        vec![
+            NetworkCompletion {
+                name: "home".to_owned(),
+                kind: "WPA2".to_owned(),
+                signal: Some(81),
+            },
+            NetworkCompletion {
+                name: "Caf: guests".to_owned(),
+                kind: "".to_owned(),
+                signal: Some(78),
+            },
+        ]
+    );
+}
+
+/// Parses the output of `nmcli -t -f NAME,TYPE connection show` into the
+/// connections, with the signals of the Wi-Fi networks in range named
+/// like them.
+fn parse_connections(output: &str, networks: &[NetworkCompletion]) -> Vec<NetworkCompletion> {
+    output
+        .lines()
+        .filter_map(|line| match split_terse(line).as_slice() {
+            [name, connection_type] if !name.is_empty() => {
+                let kind = match connection_type.as_str() {
+                    "802-11-wireless" => "wifi",
+                    "802-3-ethernet" => "ethernet",
+                    t => t,
+                };
+                let signal = if kind == "wifi" {
+                    networks
+                        .iter()
+                        .find(|n| &n.name == name)
+                        .and_then(|n| n.signal)
+                } else {
+                    None
+                };
+                Some(NetworkCompletion {
+                    name: name.clone(),
+                    kind: kind.to_owned(),
+                    signal,
+                })
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_connections() {
+    let networks = parse_wifi_networks("home:81:WPA2\n");
+    assert_eq!(
+        parse_connections(
+            "home:802-11-wireless\nWired connection 1:802-3-ethernet\nwg0:wireguard\n",
+            &networks
+        )
+        .iter()
+        .map(|c| core::Completion::description(c).unwrap())
+        .collect::<Vec<_>>(),
+        vec!["81% wifi", "ethernet", "wireguard"]
+    );
+}
+
+/// A completer of the connections of NetworkManager or of the Wi-Fi
+/// networks in range.
+pub struct NetworkCompleter {
+    item: NetworkItem,
+
+    /// The time after which `nmcli` is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl NetworkCompleter {
+    pub fn new(item: NetworkItem, timeout: time::Duration) -> Self {
+        NetworkCompleter {
+            item,
+            timeout,
+            errors: vec![],
+        }
+    }
+
+    /// Runs `nmcli` with terse output of the given fields.
+    fn nmcli(&self, fields: &str, args: &[&str]) -> Result<String, String> {
+        subprocess::run(
+            Command::new("nmcli")
+                .args(["--terse", "--fields", fields])
+                .args(args),
+            self.timeout,
+        )
+    }
+}
+
+impl core::Completer for NetworkCompleter {
+    fn name(&self) -> String {
+        "net".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        let networks = self
+            .nmcli("SSID,SIGNAL,SECURITY", &["device", "wifi", "list"])
+            .map(|o| parse_wifi_networks(&o));
+        let completions = match self.item {
+            NetworkItem::WifiNetwork => networks,
+            // Connections are listed without signals on systems without
+            // Wi-Fi.
+            NetworkItem::Connection => self
+                .nmcli("NAME,TYPE", &["connection", "show"])
+                .map(|o| parse_connections(&o, &networks.unwrap_or_default())),
+        };
+        match completions {
+            Result::Ok(c) => c
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct BluetoothCompletion {
+    address: String,
+    name: String,
+}
+
+impl core::Completion for BluetoothCompletion {
+    fn result_string(&self) -> String {
+        self.address.clone()
+    }
+
+    fn description(&self) -> Option<String> {
+        Some(self.name.clone())
+    }
+
+    /// Devices are searched by their names too.
+    fn search_string(&self) -> String {
+        format!("{} {}", self.address, self.name)
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        core::CompletionKind::Host
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Parses the lines listing devices in the output of `bluetoothctl`,
+/// e.g. `Device 00:11:22:33:44:55 Headphones`.
+fn parse_bluetooth_devices(output: &str) -> Vec<BluetoothCompletion> {
+    output
+        .lines()
+        .filter_map(|l| {
+            let mut words = l.trim().splitn(3, ' ');
+            if words.next()? != "Device" {
+                return None;
+            }
+            Some(BluetoothCompletion {
+                address: words.next()?.to_owned(),
+                name: words.next().unwrap_or_default().to_owned(),
+            })
+        })
+        .collect()
+}
+
+#[test]
+fn test_parse_bluetooth_devices() {
+    assert_eq!(
+        parse_bluetooth_devices("Device 00:11:22:33:44:55 WH-1000XM4\nAgent registered\n"),
+        vec![BluetoothCompletion {
+            address: "00:11:22:33:44:55".to_owned(),
+            name: "WH-1000XM4".to_owned(),
+        }]
+    );
+}
+
+/// A completer of the paired Bluetooth devices.
+pub struct BluetoothCompleter {
+    /// The time after which `bluetoothctl` is killed.
+    timeout: time::Duration,
+    errors: Vec<String>,
+}
+
+impl BluetoothCompleter {
+    pub fn new(timeout: time::Duration) -> Self {
+        BluetoothCompleter {
+            timeout,
+            errors: vec![],
+        }
+    }
+}
+
+impl core::Completer for BluetoothCompleter {
+    fn name(&self) -> String {
+        "bt".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        // Versions of BlueZ before 5.65 only have `paired-devices`.
+        let output = subprocess::run(
+            Command::new("bluetoothctl").args(["devices", "Paired"]),
+            self.timeout,
+        )
+        .or_else(|_| {
+            subprocess::run(
+                Command::new("bluetoothctl").arg("paired-devices"),
+                self.timeout,
+            )
+        });
+        match output {
+            Result::Ok(o) => parse_bluetooth_devices(&o)
+                .into_iter()
+                .map(|c| Box::new(c) as core::CompletionBox)
+                .collect(),
+            Result::Err(e) => {
+                self.errors.push(e);
+                vec![]
+            }
+        }
+    }
+
+    fn refresh(&mut self) -> bool {
+        true
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
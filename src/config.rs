@@ -1,4 +1,1464 @@
+//! Module for the configuration of the completers application.
+//!
+//! The configuration is read from a TOML file, by default
+//! `$XDG_CONFIG_HOME/completers/config.toml`. Every key in the file is
+//! optional; missing keys take their default values.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::io;
+use std::path;
+use std::time;
+
+use serde::Deserialize;
+use serde::Deserializer;
+
+use crate::ansi;
+use crate::core;
+use crate::quoting;
+use crate::scoring;
+
 // TODO: make the values here truly configurable.
 
+/// The default number of completions displayed at once.
 pub const CHOOSER_HEIGHT: usize = 10;
-pub const WORD_BOUNDARIES: &'static [char] = &[' ', '(', ')', ':', '`'];
+pub const WORD_BOUNDARIES: &[char] = &[' ', '\t', '\n', '(', ')', ':', '`'];
+
+/// The name of the environment variable which may be used to point
+/// to a configuration file in a non-standard location.
+const CONFIG_PATH_VARIABLE: &str = "COMPLETERS_CONFIG";
+
+/// The value of a directory depth meaning that the depth is not
+/// limited.
+const UNLIMITED_DEPTH: &str = "unlimited";
+
+/// Parses a directory depth: a number, or `unlimited`, for which None
+/// is returned.
+pub fn parse_depth(depth: &str) -> Result<Option<usize>, String> {
+    if depth == UNLIMITED_DEPTH {
+        return Result::Ok(None);
+    }
+    depth.parse::<usize>().map(Some).map_err(|_| {
+        format!(
+            "invalid depth '{}', expected a number or '{}'",
+            depth, UNLIMITED_DEPTH
+        )
+    })
+}
+
+fn deserialize_depth<'de, D>(deserializer: D) -> Result<Option<usize>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Depth {
+        Number(usize),
+        Name(String),
+    }
+    match Depth::deserialize(deserializer)? {
+        Depth::Number(n) => Result::Ok(Some(n)),
+        Depth::Name(name) => parse_depth(&name).map_err(serde::de::Error::custom),
+    }
+}
+
+/// The height of the chooser.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Height {
+    /// A number of completions displayed at once.
+    Rows(usize),
+
+    /// A percentage of the height of the terminal, including the query
+    /// and the tab bar.
+    Percent(usize),
+}
+
+/// Parses the height of the chooser: a number of completions, or a
+/// percentage of the height of the terminal, e.g. `40%`.
+pub fn parse_height(height: &str) -> Result<Height, String> {
+    let invalid = || {
+        format!(
+            "invalid height '{}', expected a positive number or a percentage",
+            height
+        )
+    };
+    match height.strip_suffix('%') {
+        Some(percent) => match percent.parse::<usize>() {
+            Result::Ok(p) if p > 0 && p <= 100 => Result::Ok(Height::Percent(p)),
+            _ => Result::Err(invalid()),
+        },
+        None => match height.parse::<usize>() {
+            Result::Ok(rows) if rows > 0 => Result::Ok(Height::Rows(rows)),
+            _ => Result::Err(invalid()),
+        },
+    }
+}
+
+fn deserialize_height<'de, D>(deserializer: D) -> Result<Height, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Value {
+        Number(usize),
+        Text(String),
+    }
+    let height = match Value::deserialize(deserializer)? {
+        Value::Number(n) => n.to_string(),
+        Value::Text(text) => text,
+    };
+    parse_height(&height).map_err(serde::de::Error::custom)
+}
+
+/// Settings of the file system completer.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct FsConfig {
+    /// Offer only directories as completions.
+    pub dirs_only: bool,
+
+    /// Show hidden files and directories from the start.
+    pub show_hidden: bool,
+
+    /// Commands for which the file system completer offers only
+    /// directories, e.g. `cd`.
+    pub dirs_only_commands: Vec<String>,
+
+    /// Cache directory listings on disk, so that unchanged directories
+    /// are not read again.
+    pub cache: bool,
+
+    /// The depth up to which subdirectories are searched, or
+    /// `"unlimited"`.
+    #[serde(deserialize_with = "deserialize_depth")]
+    pub max_depth: Option<usize>,
+
+    /// The number of entries after which searching subdirectories
+    /// stops, which bounds the search when the depth is unlimited. The
+    /// search also stops at `ui.max_candidates`, as the tab wouldn't
+    /// keep more entries, so only a lower number has an effect.
+    pub max_candidates: usize,
+
+    /// Search the directories which symbolic links point to.
+    pub follow_symlinks: bool,
+
+    /// The number of lines of a file shown when descending into it, or
+    /// 0 not to descend into files.
+    pub view_lines: usize,
+
+    /// Highlight the syntax of the files shown when descending into
+    /// them, which may be slow for long lines.
+    pub highlight: bool,
+}
+
+impl Default for FsConfig {
+    fn default() -> Self {
+        FsConfig {
+            dirs_only: false,
+            show_hidden: false,
+            dirs_only_commands: vec!["cd".to_owned(), "pushd".to_owned(), "rmdir".to_owned()],
+            cache: true,
+            max_depth: Some(7),
+            max_candidates: 200_000,
+            follow_symlinks: false,
+            view_lines: 500,
+            highlight: true,
+        }
+    }
+}
+
+/// Settings of the container image completer.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ContainersConfig {
+    /// The container engine used to list local images.
+    pub engine: String,
+
+    /// Registries queried for repositories and tags, e.g.
+    /// `localhost:5000`. Registries without a URL scheme are accessed
+    /// over HTTPS.
+    pub registries: Vec<String>,
+
+    /// The number of entries requested in a single page of a registry
+    /// listing.
+    pub page_size: usize,
+
+    /// Commands for which image references are completed.
+    pub commands: Vec<String>,
+}
+
+impl Default for ContainersConfig {
+    fn default() -> Self {
+        ContainersConfig {
+            engine: "docker".to_owned(),
+            registries: vec![],
+            page_size: 100,
+            commands: ["docker", "podman", "nerdctl", "kubectl", "crane", "skopeo"]
+                .iter()
+                .map(|c| (*c).to_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Settings of the completer of recently visited directories.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RecentDirsConfig {
+    /// The directory jumping tools whose databases are read: `z`,
+    /// `autojump` and `zoxide`.
+    pub sources: Vec<String>,
+
+    /// Commands for which recently visited directories are completed.
+    pub commands: Vec<String>,
+}
+
+impl Default for RecentDirsConfig {
+    fn default() -> Self {
+        RecentDirsConfig {
+            sources: vec!["z".to_owned(), "autojump".to_owned(), "zoxide".to_owned()],
+            commands: vec!["cd".to_owned(), "pushd".to_owned()],
+        }
+    }
+}
+
+/// Settings of the completer of files recently opened in editors.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct RecentFilesConfig {
+    /// The editors whose state is read: `vim`, `neovim`, `vscode` and
+    /// `xbel`, the recently used files of desktop applications.
+    pub sources: Vec<String>,
+
+    /// Commands for which recently opened files are completed.
+    pub commands: Vec<String>,
+}
+
+impl Default for RecentFilesConfig {
+    fn default() -> Self {
+        RecentFilesConfig {
+            sources: ["vim", "neovim", "vscode", "xbel"]
+                .iter()
+                .map(|s| (*s).to_owned())
+                .collect(),
+            commands: ["vi", "vim", "nvim", "code", "codium", "emacs", "nano", "hx"]
+                .iter()
+                .map(|c| (*c).to_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Settings of the completer of package.json scripts.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct NpmConfig {
+    /// Commands, possibly with subcommands, after which script names
+    /// are completed, e.g. `npm run`.
+    pub commands: Vec<String>,
+}
+
+impl Default for NpmConfig {
+    fn default() -> Self {
+        NpmConfig {
+            commands: ["npm run", "npm run-script", "yarn", "pnpm", "bun run"]
+                .iter()
+                .map(|c| (*c).to_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Settings of the completer of manual pages.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ManConfig {
+    /// Commands for which names of manual pages are completed.
+    pub commands: Vec<String>,
+}
+
+impl Default for ManConfig {
+    fn default() -> Self {
+        ManConfig {
+            commands: vec!["man".to_owned(), "whatis".to_owned()],
+        }
+    }
+}
+
+/// Settings of the completer bridging the programmable completion of
+/// bash.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct BashCompletionConfig {
+    /// Complete the options and arguments of commands with their
+    /// compspecs, if bash-completion is installed.
+    pub enabled: bool,
+
+    /// The path of the main script of bash-completion.
+    pub script: String,
+}
+
+impl Default for BashCompletionConfig {
+    fn default() -> Self {
+        BashCompletionConfig {
+            enabled: true,
+            script: "/usr/share/bash-completion/bash_completion".to_owned(),
+        }
+    }
+}
+
+/// Settings of the completer of the options of commands.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct OptionsConfig {
+    /// Complete the options of commands, parsed from their help or
+    /// manual pages, when the query starts with a dash. Disabled by
+    /// default, since the command is run with `--help`, along with the
+    /// word following it if it looks like a subcommand.
+    pub enabled: bool,
+}
+
+/// What is inserted for a desktop application.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DesktopResult {
+    /// The desktop file ID, e.g. `firefox` for `gtk-launch`.
+    Id,
+
+    /// The command line of the `Exec` key, without field codes.
+    Exec,
+}
+
+/// Settings of the completer of desktop applications.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DesktopConfig {
+    pub result: DesktopResult,
+
+    /// Commands for which desktop applications are completed.
+    pub commands: Vec<String>,
+}
+
+impl Default for DesktopConfig {
+    fn default() -> Self {
+        DesktopConfig {
+            result: DesktopResult::Id,
+            commands: ["gtk-launch", "xdg-open", "dex", "kioclient", "swaymsg exec"]
+                .iter()
+                .map(|c| (*c).to_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Settings of the completer of entries of the password store.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PassConfig {
+    /// Commands for which entries of the password store are completed.
+    pub commands: Vec<String>,
+}
+
+impl Default for PassConfig {
+    fn default() -> Self {
+        PassConfig {
+            commands: [
+                "pass show",
+                "pass edit",
+                "pass rm",
+                "pass mv",
+                "pass cp",
+                "pass generate",
+                "pass insert",
+                "pass otp",
+            ]
+            .iter()
+            .map(|c| (*c).to_owned())
+            .collect(),
+        }
+    }
+}
+
+/// Settings of the completer of the clipboard and its history.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ClipboardConfig {
+    /// Offer the contents and the history of the clipboard.
+    pub enabled: bool,
+
+    /// Make the daemon collect the strings copied to the clipboard into
+    /// the history.
+    pub collect: bool,
+
+    /// The interval in milliseconds at which the daemon reads the
+    /// clipboard.
+    pub poll_interval_ms: u64,
+
+    /// The number of strings kept in the history.
+    pub max_entries: usize,
+
+    /// The length in bytes above which strings aren't kept in the
+    /// history.
+    pub max_length: usize,
+}
+
+impl ClipboardConfig {
+    pub fn poll_interval(&self) -> time::Duration {
+        time::Duration::from_millis(self.poll_interval_ms)
+    }
+}
+
+impl Default for ClipboardConfig {
+    fn default() -> Self {
+        ClipboardConfig {
+            enabled: true,
+            collect: false,
+            poll_interval_ms: 1000,
+            max_entries: 100,
+            max_length: 4096,
+        }
+    }
+}
+
+/// Settings of the completer of values of the options of the AWS CLI.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AwsConfig {
+    /// List S3 buckets and EC2 instances with `aws`, which queries AWS
+    /// with the credentials of the user. Profiles and regions are
+    /// always completed.
+    pub resources: bool,
+
+    /// The time in milliseconds after which `aws` is killed.
+    pub timeout_ms: u64,
+
+    /// The time in seconds during which listings of resources are
+    /// reused instead of being fetched again.
+    pub cache_secs: u64,
+}
+
+impl AwsConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for AwsConfig {
+    fn default() -> Self {
+        AwsConfig {
+            resources: false,
+            timeout_ms: 5000,
+            cache_secs: 300,
+        }
+    }
+}
+
+/// Settings of the completers of Terraform workspaces and resources.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TerraformConfig {
+    /// Programs whose command lines are completed, e.g. `tofu`, which
+    /// is compatible with `terraform`.
+    pub programs: Vec<String>,
+
+    /// The time in milliseconds after which listing the state is given
+    /// up. It is long, as the state may be kept in a remote backend.
+    pub timeout_ms: u64,
+
+    /// The time in seconds during which the listing of the state is
+    /// reused instead of being fetched again.
+    pub cache_secs: u64,
+}
+
+impl TerraformConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for TerraformConfig {
+    fn default() -> Self {
+        TerraformConfig {
+            programs: vec!["terraform".to_owned(), "tofu".to_owned()],
+            timeout_ms: 10000,
+            cache_secs: 60,
+        }
+    }
+}
+
+/// The database systems whose objects are completed.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum DatabaseEngine {
+    Postgres,
+    Mysql,
+    Sqlite,
+}
+
+/// A connection to a database server, or a database file of SQLite.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct DatabaseConnection {
+    /// The name of the connection, shown next to its databases.
+    pub name: String,
+
+    pub engine: DatabaseEngine,
+
+    /// The arguments of the client (`psql`, `mysql` or `sqlite3`)
+    /// connecting to the server, e.g. `["--host", "db", "--user",
+    /// "app"]`, or the path of the database file of SQLite. Passwords
+    /// are best kept in the files the clients read, like `~/.pgpass`.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+/// Settings of the completer of database objects. It is offered only
+/// when connections are configured.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DatabaseConfig {
+    pub connections: Vec<DatabaseConnection>,
+
+    /// Commands for which database objects are completed.
+    pub commands: Vec<String>,
+
+    /// The time in milliseconds after which a client is killed.
+    pub timeout_ms: u64,
+
+    /// The time in seconds during which listings are reused instead of
+    /// being fetched again.
+    pub cache_secs: u64,
+}
+
+impl DatabaseConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            connections: vec![],
+            commands: ["psql", "pg_dump", "mysql", "mysqldump", "sqlite3"]
+                .iter()
+                .map(|c| (*c).to_owned())
+                .collect(),
+            timeout_ms: 3000,
+            cache_secs: 120,
+        }
+    }
+}
+
+/// Settings of the completer of Nix installables.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct NixConfig {
+    /// Commands for which installables are completed.
+    pub commands: Vec<String>,
+
+    /// The time in milliseconds after which `nix` is killed. It is long,
+    /// as listing nixpkgs evaluates it.
+    pub timeout_ms: u64,
+
+    /// The time in seconds during which the listing of nixpkgs is
+    /// reused instead of being fetched again.
+    pub cache_secs: u64,
+}
+
+impl NixConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for NixConfig {
+    fn default() -> Self {
+        NixConfig {
+            commands: [
+                "nix run",
+                "nix shell",
+                "nix build",
+                "nix develop",
+                "nix profile install",
+            ]
+            .iter()
+            .map(|c| (*c).to_owned())
+            .collect(),
+            timeout_ms: 120_000,
+            cache_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Settings of the completer of Homebrew formulae and casks.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct HomebrewConfig {
+    /// Commands for which formulae and casks are completed.
+    pub commands: Vec<String>,
+
+    /// The time in milliseconds after which `brew` is killed.
+    pub timeout_ms: u64,
+
+    /// The time in seconds during which the catalog of formulae and
+    /// casks is reused instead of being fetched again.
+    pub cache_secs: u64,
+}
+
+impl HomebrewConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for HomebrewConfig {
+    fn default() -> Self {
+        HomebrewConfig {
+            commands: [
+                "brew install",
+                "brew info",
+                "brew home",
+                "brew uninstall",
+                "brew upgrade",
+                "brew reinstall",
+            ]
+            .iter()
+            .map(|c| (*c).to_owned())
+            .collect(),
+            timeout_ms: 60_000,
+            cache_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Settings of the completer of the names of tests.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TestsConfig {
+    /// The time in milliseconds after which listing the tests is given
+    /// up. It is long, as Cargo builds the tests to list them.
+    pub timeout_ms: u64,
+}
+
+impl TestsConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for TestsConfig {
+    fn default() -> Self {
+        TestsConfig {
+            timeout_ms: 5 * 60 * 1000,
+        }
+    }
+}
+
+/// Settings of the completer of the tasks of build systems.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct TasksConfig {
+    /// The time in milliseconds after which listing the tasks is given
+    /// up. It is long, as Gradle configures the project to list them.
+    pub timeout_ms: u64,
+}
+
+impl TasksConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for TasksConfig {
+    fn default() -> Self {
+        TasksConfig { timeout_ms: 60_000 }
+    }
+}
+
+/// Settings of the completer of dates and times.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct DateTimeConfig {
+    /// Options after which dates and times are completed, besides
+    /// queries naming a moment, like `tomorrow`.
+    pub options: Vec<String>,
+}
+
+impl Default for DateTimeConfig {
+    fn default() -> Self {
+        DateTimeConfig {
+            options: ["--since", "--until", "--after", "--before", "--date", "-d"]
+                .iter()
+                .map(|c| (*c).to_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Settings of the completer of system packages.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct PackagesConfig {
+    /// The package manager, `apt`, `dnf`, `pacman` or `brew`. By
+    /// default the first one found in `PATH` is used.
+    pub manager: Option<String>,
+
+    /// Commands for which package names are completed. Homebrew has a
+    /// completer of its own, see `HomebrewConfig`.
+    pub commands: Vec<String>,
+}
+
+impl Default for PackagesConfig {
+    fn default() -> Self {
+        PackagesConfig {
+            manager: None,
+            commands: ["apt", "apt-get", "apt-cache", "dnf", "yum", "pacman"]
+                .iter()
+                .map(|c| (*c).to_owned())
+                .collect(),
+        }
+    }
+}
+
+/// Settings of the completer of pull requests and issues of GitHub and
+/// GitLab, which lists them with `gh` and `glab`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ForgeConfig {
+    /// The time in milliseconds after which `gh` or `glab` is killed.
+    /// It is shorter than for other commands, as they query the
+    /// network.
+    pub timeout_ms: u64,
+
+    /// The time in seconds during which listings are reused instead of
+    /// being fetched again.
+    pub cache_secs: u64,
+
+    /// The number of pull requests and issues listed.
+    pub limit: usize,
+
+    /// Commands for which pull request numbers are completed.
+    pub pull_request_commands: Vec<String>,
+
+    /// Commands for which issue numbers are completed.
+    pub issue_commands: Vec<String>,
+}
+
+impl ForgeConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for ForgeConfig {
+    fn default() -> Self {
+        let commands = |tool: &str, noun: &str, verbs: &[&str]| -> Vec<String> {
+            verbs
+                .iter()
+                .map(|v| format!("{} {} {}", tool, noun, v))
+                .collect()
+        };
+        let pull_request_verbs = ["checkout", "view", "diff", "merge", "close", "reopen"];
+        let issue_verbs = ["view", "close", "reopen", "comment", "edit"];
+        ForgeConfig {
+            timeout_ms: 2000,
+            cache_secs: 300,
+            limit: 100,
+            pull_request_commands: [
+                commands("gh", "pr", &pull_request_verbs),
+                commands("glab", "mr", &pull_request_verbs),
+            ]
+            .concat(),
+            issue_commands: [
+                commands("gh", "issue", &issue_verbs),
+                commands("glab", "issue", &issue_verbs),
+            ]
+            .concat(),
+        }
+    }
+}
+
+/// A completer defined by the user, listing the completions printed by
+/// a shell command, one per line.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct UserCompleterConfig {
+    /// The name of the tab of the completer.
+    pub name: String,
+
+    /// The command listing the completions, run with `sh -c`.
+    pub command: String,
+
+    /// The command run instead of `command` when the query isn't empty,
+    /// anew whenever the query changes, e.g. a search in a large source.
+    /// Occurrences of `{query}` are replaced with the query, quoted. The
+    /// completions it prints are not filtered with the query.
+    #[serde(default)]
+    pub query_command: Option<String>,
+
+    /// The separator of the columns of the printed lines. Without it,
+    /// each line is a single column.
+    #[serde(default)]
+    pub separator: Option<String>,
+
+    /// The number of the column inserted as the result, counted from 1.
+    /// The first column by default.
+    #[serde(default)]
+    pub result_column: Option<usize>,
+
+    /// The number of the column shown in place of the result.
+    #[serde(default)]
+    pub display_column: Option<usize>,
+
+    /// The number of the column shown dimmed at the right edge.
+    #[serde(default)]
+    pub description_column: Option<usize>,
+
+    /// The kind of the completions, which their icon shows.
+    #[serde(default)]
+    pub kind: Option<core::CompletionKind>,
+
+    /// Commands, possibly with subcommands, after which the completer
+    /// is offered, e.g. `ssh` or `git checkout`. An empty list means
+    /// all commands.
+    #[serde(default)]
+    pub commands: Vec<String>,
+}
+
+/// Settings of the external commands run by completers, e.g. git.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct SubprocessConfig {
+    /// The time in milliseconds after which a command is killed.
+    pub timeout_ms: u64,
+}
+
+impl SubprocessConfig {
+    pub fn timeout(&self) -> time::Duration {
+        time::Duration::from_millis(self.timeout_ms)
+    }
+}
+
+impl Default for SubprocessConfig {
+    fn default() -> Self {
+        SubprocessConfig { timeout_ms: 5000 }
+    }
+}
+
+/// The least severe messages written to the log.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LogLevel {
+    /// Nothing is logged.
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    pub fn filter(self) -> log::LevelFilter {
+        match self {
+            LogLevel::Off => log::LevelFilter::Off,
+            LogLevel::Error => log::LevelFilter::Error,
+            LogLevel::Warn => log::LevelFilter::Warn,
+            LogLevel::Info => log::LevelFilter::Info,
+            LogLevel::Debug => log::LevelFilter::Debug,
+            LogLevel::Trace => log::LevelFilter::Trace,
+        }
+    }
+}
+
+/// Settings of the log and of the session trace.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// The file the log is written to, replaced in each session.
+    pub path: String,
+
+    /// The least severe messages logged; `--debug` logs debug messages
+    /// regardless.
+    pub level: LogLevel,
+
+    /// The file the trace of the session is written to as JSON when the
+    /// chooser closes: the keys pressed and how long fetching, scoring
+    /// and drawing the completions took. Sessions aren't traced without
+    /// it.
+    pub trace_path: Option<String>,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            path: "/tmp/completers.log".to_owned(),
+            level: LogLevel::Warn,
+            trace_path: None,
+        }
+    }
+}
+
+/// Settings of the chooser user interface.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// Report the progress of fetching completions in the terminal
+    /// window title and progress indicator (OSC 0 and OSC 9;4).
+    pub terminal_title: bool,
+
+    /// The time in milliseconds since the last edit of the query after
+    /// which the completions are filtered, so that typing several
+    /// characters quickly results in filtering just once.
+    pub debounce_ms: u64,
+
+    /// The terminal backend, `termion` or `crossterm`. The default
+    /// depends on the platform.
+    pub backend: Option<String>,
+
+    /// Show the permissions, size and modification time of files
+    /// next to them.
+    pub metadata_columns: bool,
+
+    /// The initial order of the completions.
+    pub sort_order: SortOrder,
+
+    /// The placement of the query relative to the completions.
+    pub layout: Layout,
+
+    /// The number of completions displayed at once, or a percentage of
+    /// the height of the terminal, e.g. `"40%"`.
+    #[serde(deserialize_with = "deserialize_height")]
+    pub height: Height,
+
+    /// Add a first tab listing the completions of all the other tabs,
+    /// each tagged with the name of its tab.
+    pub all_tab: bool,
+
+    /// Keep a separate query for each tab, so that switching tabs
+    /// restores the query last typed in the tab instead of filtering
+    /// it with the current one. Every tab starts with the initial
+    /// query.
+    pub per_tab_queries: bool,
+
+    /// The number of accepted queries kept in a history, from which
+    /// they can be recalled in the chooser, or 0 not to keep any.
+    pub query_history: usize,
+
+    /// Show the kinds of completions with glyphs of a Nerd Font
+    /// instead of letters.
+    pub nerd_font: bool,
+
+    /// Number the first displayed completions, which are then accepted
+    /// with alt and their numbers instead of switching tabs.
+    pub row_numbers: bool,
+
+    /// What cancelling the choice does to the command line.
+    pub cancel: CancelBehavior,
+
+    /// Accept the only completion matching the initial query without
+    /// showing the chooser.
+    pub select_one: bool,
+
+    /// Cancel the choice without showing the chooser if no completion
+    /// matches the initial query.
+    pub exit_zero: bool,
+
+    /// The command the selected completion is piped into to copy it to
+    /// the clipboard, e.g. `wl-copy` or `xclip -selection clipboard`,
+    /// for terminals which don't support OSC 52 sequences.
+    pub clipboard_command: Option<String>,
+
+    /// The number of completions a tab keeps at most. Fetching stops
+    /// once it is reached, e.g. when searching files from `/`, and the
+    /// status line shows that the completions are truncated. The file
+    /// system search stops there too, unless `fs.max_candidates` is
+    /// lower.
+    pub max_candidates: usize,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        UiConfig {
+            terminal_title: true,
+            debounce_ms: 30,
+            backend: None,
+            metadata_columns: false,
+            sort_order: SortOrder::Score,
+            layout: Layout::Default,
+            height: Height::Rows(CHOOSER_HEIGHT),
+            all_tab: false,
+            per_tab_queries: false,
+            query_history: 100,
+            nerd_font: false,
+            row_numbers: false,
+            cancel: CancelBehavior::RestoreQuery,
+            select_one: false,
+            exit_zero: false,
+            clipboard_command: None,
+            max_candidates: 200_000,
+        }
+    }
+}
+
+/// The order in which the completions matching the query are listed.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// The best matches first.
+    Score,
+
+    /// In alphabetical order.
+    Alphabetical,
+
+    /// The most recently modified files first.
+    Modified,
+
+    /// The largest files first.
+    Size,
+
+    /// In the order in which the completer returned them.
+    Native,
+
+    /// Under the headers of the groups of the completer, e.g. branches
+    /// and tags, and the best matches first in each group.
+    Group,
+}
+
+impl SortOrder {
+    /// Returns the name of the sort order, as used in the
+    /// configuration file.
+    pub fn name(self) -> &'static str {
+        match self {
+            SortOrder::Score => "score",
+            SortOrder::Alphabetical => "alphabetical",
+            SortOrder::Modified => "modified",
+            SortOrder::Size => "size",
+            SortOrder::Native => "native",
+            SortOrder::Group => "group",
+        }
+    }
+
+    /// Returns the sort order following this one when cycling through
+    /// all of them.
+    pub fn next(self) -> SortOrder {
+        match self {
+            SortOrder::Score => SortOrder::Alphabetical,
+            SortOrder::Alphabetical => SortOrder::Modified,
+            SortOrder::Modified => SortOrder::Size,
+            SortOrder::Size => SortOrder::Native,
+            SortOrder::Native => SortOrder::Group,
+            SortOrder::Group => SortOrder::Score,
+        }
+    }
+}
+
+/// The placement of the query relative to the completions.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// The query at the top, with the completions listed below it.
+    Default,
+
+    /// The query at the bottom, with the completions listed upwards
+    /// from it.
+    Reverse,
+}
+
+impl Layout {
+    /// Names of all layouts, as used in the configuration file.
+    pub const NAMES: &'static [&'static str] = &["default", "reverse"];
+
+    /// Returns the layout with the given name.
+    pub fn from_name(name: &str) -> Option<Layout> {
+        match name {
+            "default" => Some(Layout::Default),
+            "reverse" => Some(Layout::Reverse),
+            _ => None,
+        }
+    }
+}
+
+/// What cancelling the choice does to the command line.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CancelBehavior {
+    /// Replace the query with itself, quoted like a completion, with
+    /// the cursor after it.
+    RestoreQuery,
+
+    /// Leave the command line and the cursor as they were.
+    KeepLine,
+}
+
+/// Settings of the integration with the shell.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    /// The quoting style of results with special characters.
+    pub quoting: quoting::QuotingStyle,
+
+    /// Quoting styles for particular shells, overriding `quoting`,
+    /// e.g. `{ fish = "single-quote" }`.
+    pub quoting_by_shell: BTreeMap<String, quoting::QuotingStyle>,
+}
+
+impl ShellConfig {
+    /// Returns the quoting style for the given shell, e.g. `bash`.
+    pub fn quoting_style(&self, shell: Option<&str>) -> quoting::QuotingStyle {
+        shell
+            .and_then(|s| self.quoting_by_shell.get(s))
+            .cloned()
+            .unwrap_or(self.quoting)
+    }
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        ShellConfig {
+            quoting: quoting::QuotingStyle::Backslash,
+            quoting_by_shell: BTreeMap::new(),
+        }
+    }
+}
+
+/// A built-in set of colors of the user interface.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum BaseTheme {
+    /// Basic colors readable on a dark background.
+    Dark,
+
+    /// Basic colors readable on a light background.
+    Light,
+
+    /// The Solarized palette, in truecolor.
+    Solarized,
+}
+
+/// Settings of the colors of the user interface. Colors are names of
+/// the 16 basic colors, e.g. `bright-blue`, numbers of the 256 indexed
+/// colors, or truecolor values, e.g. `#268bd2`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct ThemeConfig {
+    /// The built-in theme which the other settings override.
+    pub base: BaseTheme,
+
+    /// The colors of the selected completion and the current tab. The
+    /// selection is shown in reverse video if neither is set.
+    pub selection_fg: Option<ansi::Color>,
+    pub selection_bg: Option<ansi::Color>,
+
+    /// The color of secondary text, e.g. descriptions of completions.
+    pub dimmed: Option<ansi::Color>,
+
+    /// The color of messages in the status line.
+    pub message: Option<ansi::Color>,
+
+    /// The color of errors in the status line.
+    pub error: Option<ansi::Color>,
+
+    /// The colors of the icons of kinds of completions, e.g.
+    /// `{ directory = "blue" }`.
+    pub kinds: BTreeMap<core::CompletionKind, ansi::Color>,
+
+    /// The colors of completions with a severity, e.g.
+    /// `{ warning = "yellow" }`.
+    pub severities: BTreeMap<core::Severity, ansi::Color>,
+}
+
+impl Default for ThemeConfig {
+    fn default() -> Self {
+        ThemeConfig {
+            base: BaseTheme::Dark,
+            selection_fg: None,
+            selection_bg: None,
+            dimmed: None,
+            message: None,
+            error: None,
+            kinds: BTreeMap::new(),
+            severities: BTreeMap::new(),
+        }
+    }
+}
+
+/// The complete configuration of the application.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub fs: FsConfig,
+
+    pub containers: ContainersConfig,
+
+    pub recent_dirs: RecentDirsConfig,
+
+    pub recent_files: RecentFilesConfig,
+
+    pub npm: NpmConfig,
+
+    pub man: ManConfig,
+
+    pub bash_completion: BashCompletionConfig,
+
+    pub options: OptionsConfig,
+
+    pub packages: PackagesConfig,
+
+    pub nix: NixConfig,
+
+    pub homebrew: HomebrewConfig,
+
+    pub tests: TestsConfig,
+
+    pub tasks: TasksConfig,
+
+    pub forge: ForgeConfig,
+
+    pub desktop: DesktopConfig,
+
+    pub datetime: DateTimeConfig,
+
+    pub pass: PassConfig,
+
+    pub clipboard: ClipboardConfig,
+
+    pub aws: AwsConfig,
+
+    pub terraform: TerraformConfig,
+
+    pub database: DatabaseConfig,
+
+    /// Completers defined by the user, offered before the built-in ones.
+    pub completers: Vec<UserCompleterConfig>,
+
+    pub subprocess: SubprocessConfig,
+
+    pub log: LogConfig,
+
+    pub ui: UiConfig,
+
+    pub theme: ThemeConfig,
+
+    pub shell: ShellConfig,
+
+    /// Weights used when scoring completions against the query.
+    pub scoring: scoring::ScoringSettings,
+
+    /// Key bindings, mapping key names (e.g. `ctrl-n`) to action
+    /// names (e.g. `select-next`).
+    pub keys: BTreeMap<String, String>,
+
+    /// Commands run on the selected completion, e.g. opening it in an
+    /// editor. They take precedence over `keys` for the completions
+    /// they apply to.
+    pub actions: Vec<core::CompletionAction>,
+}
+
+impl Config {
+    /// Returns the path of the configuration file.
+    ///
+    /// The file does not need to exist.
+    pub fn path() -> Option<path::PathBuf> {
+        if let Some(p) = env::var_os(CONFIG_PATH_VARIABLE) {
+            return Some(path::PathBuf::from(p));
+        }
+        let config_dir = env::var_os("XDG_CONFIG_HOME")
+            .map(path::PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".config")))?;
+        Some(config_dir.join("completers").join("config.toml"))
+    }
+
+    /// Parses the configuration from the contents of a config file.
+    pub fn parse(contents: &str) -> io::Result<Config> {
+        toml::from_str(contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Loads the configuration from the configuration file.
+    ///
+    /// A missing configuration file is not an error; the default
+    /// configuration is returned in that case.
+    pub fn load() -> io::Result<Config> {
+        match Config::path() {
+            Some(p) => Config::load_from(&p),
+            None => Result::Ok(Config::default()),
+        }
+    }
+
+    /// Loads the configuration from the given file.
+    pub fn load_from(config_path: &path::Path) -> io::Result<Config> {
+        match fs::read_to_string(config_path) {
+            Result::Ok(contents) => Config::parse(&contents),
+            Result::Err(ref e) if e.kind() == io::ErrorKind::NotFound => {
+                Result::Ok(Config::default())
+            }
+            Result::Err(e) => Result::Err(e),
+        }
+    }
+}
+
+/// A structure watching the configuration file for changes, so that
+/// they can be applied in a running session.
+pub struct ConfigWatcher {
+    config_path: Option<path::PathBuf>,
+    modified: Option<time::SystemTime>,
+}
+
+impl ConfigWatcher {
+    pub fn new() -> ConfigWatcher {
+        let config_path = Config::path();
+        let modified = config_path.as_ref().and_then(|p| modification_time(p));
+        ConfigWatcher {
+            config_path,
+            modified,
+        }
+    }
+
+    /// Checks if the configuration file changed since the last call,
+    /// returning the newly loaded configuration if it did.
+    pub fn poll(&mut self) -> Option<io::Result<Config>> {
+        let config_path = self.config_path.as_ref()?;
+        let modified = modification_time(config_path);
+        if modified == self.modified {
+            return None;
+        }
+        self.modified = modified;
+        Some(Config::load_from(config_path))
+    }
+}
+
+impl Default for ConfigWatcher {
+    fn default() -> Self {
+        ConfigWatcher::new()
+    }
+}
+
+fn modification_time(file_path: &path::Path) -> Option<time::SystemTime> {
+    fs::metadata(file_path).and_then(|m| m.modified()).ok()
+}
+
+#[test]
+fn test_config_parse() {
+    let config = Config::parse("").unwrap();
+    assert!(!config.fs.dirs_only);
+    assert_eq!(config.fs.dirs_only_commands, vec!["cd", "pushd", "rmdir"]);
+
+    let config = Config::parse("[fs]\ndirs_only = true\n").unwrap();
+    assert!(config.fs.dirs_only);
+    assert!(config.fs.cache);
+    assert_eq!(config.fs.dirs_only_commands, vec!["cd", "pushd", "rmdir"]);
+
+    assert!(Config::parse("[fs]\ndirs_only = 1\n").is_err());
+
+    let config = Config::parse("[fs]\nmax_depth = 12\n").unwrap();
+    assert_eq!(config.fs.max_depth, Some(12));
+    let config = Config::parse("[fs]\nmax_depth = \"unlimited\"\n").unwrap();
+    assert_eq!(config.fs.max_depth, None);
+    assert!(Config::parse("[fs]\nmax_depth = \"deep\"\n").is_err());
+    assert_eq!(Config::default().fs.max_depth, Some(7));
+
+    let config = Config::parse("[containers]\nregistries = [\"localhost:5000\"]\n").unwrap();
+    assert_eq!(config.containers.registries, vec!["localhost:5000"]);
+    assert_eq!(config.containers.page_size, 100);
+    assert!(config.containers.commands.iter().any(|c| c == "docker"));
+
+    let config = Config::parse("[forge]\ncache_secs = 60\n").unwrap();
+    assert_eq!(config.forge.cache_secs, 60);
+    assert_eq!(config.forge.timeout(), time::Duration::from_secs(2));
+    assert!(config
+        .forge
+        .pull_request_commands
+        .iter()
+        .any(|c| c == "gh pr checkout"));
+    assert!(config
+        .forge
+        .issue_commands
+        .iter()
+        .any(|c| c == "glab issue view"));
+
+    let config = Config::parse("[desktop]\nresult = \"exec\"\n").unwrap();
+    assert_eq!(config.desktop.result, DesktopResult::Exec);
+    assert!(config.desktop.commands.iter().any(|c| c == "gtk-launch"));
+    assert_eq!(Config::default().desktop.result, DesktopResult::Id);
+
+    let config = Config::parse("[datetime]\noptions = [\"--newer\"]\n").unwrap();
+    assert_eq!(config.datetime.options, vec!["--newer"]);
+    assert!(Config::default()
+        .datetime
+        .options
+        .iter()
+        .any(|o| o == "--since"));
+
+    let config = Config::parse("[ui]\nterminal_title = false\n").unwrap();
+    assert!(!config.ui.terminal_title);
+    assert_eq!(config.ui.debounce_ms, 30);
+    assert_eq!(config.ui.sort_order, SortOrder::Score);
+    assert_eq!(config.ui.layout, Layout::Default);
+
+    let config = Config::parse("[ui]\nsort_order = \"modified\"\n").unwrap();
+    assert_eq!(config.ui.sort_order, SortOrder::Modified);
+    let config = Config::parse("[ui]\nsort_order = \"native\"\n").unwrap();
+    assert_eq!(config.ui.sort_order.next(), SortOrder::Group);
+    let config = Config::parse("[ui]\nsort_order = \"group\"\n").unwrap();
+    assert_eq!(config.ui.sort_order.next(), SortOrder::Score);
+    let config = Config::parse("[ui]\nlayout = \"reverse\"\n").unwrap();
+    assert_eq!(config.ui.layout, Layout::Reverse);
+    assert_eq!(Layout::from_name("reverse"), Some(Layout::Reverse));
+
+    assert_eq!(config.ui.height, Height::Rows(CHOOSER_HEIGHT));
+    let config = Config::parse("[ui]\nheight = 15\n").unwrap();
+    assert_eq!(config.ui.height, Height::Rows(15));
+    let config = Config::parse("[ui]\nheight = \"40%\"\n").unwrap();
+    assert_eq!(config.ui.height, Height::Percent(40));
+    assert!(Config::parse("[ui]\nheight = 0\n").is_err());
+    assert!(parse_height("120%").is_err());
+    assert!(parse_height("tall").is_err());
+
+    assert_eq!(config.ui.cancel, CancelBehavior::RestoreQuery);
+    assert_eq!(config.ui.max_candidates, 200_000);
+    let config = Config::parse("[ui]\ncancel = \"keep-line\"\n").unwrap();
+    assert_eq!(config.ui.cancel, CancelBehavior::KeepLine);
+
+    assert_eq!(config.log.level, LogLevel::Warn);
+    assert_eq!(config.log.trace_path, None);
+    let config =
+        Config::parse("[log]\nlevel = \"off\"\ntrace_path = \"/tmp/trace.json\"\n").unwrap();
+    assert_eq!(config.log.level.filter(), log::LevelFilter::Off);
+    assert_eq!(config.log.path, "/tmp/completers.log");
+    assert_eq!(config.log.trace_path.as_deref(), Some("/tmp/trace.json"));
+
+    let config = Config::parse("[shell.quoting_by_shell]\nfish = \"single-quote\"\n").unwrap();
+    assert_eq!(
+        config.shell.quoting_style(Some("fish")),
+        quoting::QuotingStyle::SingleQuote
+    );
+    assert_eq!(
+        config.shell.quoting_style(Some("bash")),
+        quoting::QuotingStyle::Backslash
+    );
+    assert!(Config::parse("[shell]\nquoting = \"double-quote\"\n").is_err());
+
+    let config =
+        Config::parse("[scoring]\nletter_match = 5\n[keys]\nctrl-n = \"select-next\"\n").unwrap();
+    assert_eq!(config.scoring.letter_match, 5);
+    assert_eq!(config.scoring.word_start_bonus, 2);
+    assert_eq!(config.keys["ctrl-n"], "select-next");
+
+    let config = Config::parse(
+        "[[completers]]\n\
+         name = \"hosts\"\n\
+         command = \"cat hosts\"\n\
+         separator = \" \"\n\
+         result_column = 2\n\
+         kind = \"host\"\n",
+    )
+    .unwrap();
+    let completer = &config.completers[0];
+    assert_eq!(completer.name, "hosts");
+    assert_eq!(completer.result_column, Some(2));
+    assert_eq!(completer.display_column, None);
+    assert_eq!(completer.kind, Some(core::CompletionKind::Host));
+    assert!(completer.commands.is_empty());
+    assert!(Config::parse("[[completers]]\nname = \"hosts\"\n").is_err());
+}
@@ -2,6 +2,277 @@
 //! completions and completion providers (aka Completers).
 
 use std::any;
+use std::time;
+
+use serde::Deserialize;
+
+/// The marker which completions may embed in their result strings to
+/// indicate where the cursor should be placed after inserting them,
+/// e.g. inside the quotes of a `--format="{cursor}"` template.
+pub const CURSOR_MARKER: &str = "{cursor}";
+
+/// The text inserted as the result of the completion, along with the
+/// position of the cursor after inserting it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompletionResult {
+    pub text: String,
+
+    /// The byte offset of the cursor within `text`.
+    pub cursor_offset: usize,
+
+    /// Indicates if `text` is to be taken literally, and so has to be
+    /// quoted before it is inserted into the command line. Templates
+    /// with a `CURSOR_MARKER` are shell syntax already.
+    pub literal: bool,
+
+    /// Indicates if no completion was chosen, e.g. because the user
+    /// cancelled the choice; `text` is the initial query then.
+    pub cancelled: bool,
+}
+
+impl CompletionResult {
+    /// Creates the result from a result string, placing the cursor at
+    /// the first `CURSOR_MARKER`, or at the end if there is none.
+    ///
+    /// All markers are removed from the text. A result string without
+    /// markers is taken literally.
+    pub fn from_result_string(result_string: &str) -> CompletionResult {
+        let cursor_offset = result_string.find(CURSOR_MARKER);
+        let text = result_string.replace(CURSOR_MARKER, "");
+        CompletionResult {
+            cursor_offset: cursor_offset.unwrap_or(text.len()),
+            text,
+            literal: cursor_offset.is_none(),
+            cancelled: false,
+        }
+    }
+
+    /// Creates the result of a cancelled choice, which leaves the query
+    /// as it is.
+    pub fn cancelled(query: &str) -> CompletionResult {
+        CompletionResult {
+            text: query.to_owned(),
+            cursor_offset: query.len(),
+            literal: false,
+            cancelled: true,
+        }
+    }
+}
+
+#[test]
+fn test_completion_result() {
+    let check = |s: &str, text: &str, cursor_offset: usize, literal: bool| {
+        assert_eq!(
+            CompletionResult::from_result_string(s),
+            CompletionResult {
+                text: text.to_owned(),
+                cursor_offset,
+                literal,
+                cancelled: false,
+            }
+        )
+    };
+    check("", "", 0, true);
+    check("foo", "foo", 3, true);
+    check("{cursor}foo", "foo", 0, false);
+    check("--format=\"{cursor}\"", "--format=\"\"", 10, false);
+    check("a{cursor}b{cursor}c", "abc", 1, false);
+}
+
+/// How much attention the state of the thing which a completion refers
+/// to calls for, e.g. a failed unit, which the theme maps to the color
+/// of the completion.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum Severity {
+    /// A thing which isn't in use, e.g. a stopped service.
+    Inactive,
+    Warning,
+    /// A thing which failed or is broken.
+    Error,
+}
+
+/// A group of completions of a completer, listed under a header when
+/// the completions are sorted by group, e.g. the tags among the
+/// branches of a Git repository.
+#[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub struct CompletionGroup {
+    /// The position of the group among those of the completer; groups
+    /// are listed from the lowest one.
+    pub order: u32,
+
+    /// The name of the group, shown in its header.
+    pub name: String,
+}
+
+impl CompletionGroup {
+    pub fn new<S: Into<String>>(order: u32, name: S) -> CompletionGroup {
+        CompletionGroup {
+            order,
+            name: name.into(),
+        }
+    }
+}
+
+/// Metadata of a completion which refers to a file.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FileMetadata {
+    pub is_dir: bool,
+
+    /// The size of the file in bytes.
+    pub size: u64,
+
+    pub modified: Option<time::SystemTime>,
+
+    /// The Unix permission bits of the file, if known.
+    pub mode: Option<u32>,
+}
+
+/// The kind of the thing which a completion refers to, shown as an
+/// icon next to the completion.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompletionKind {
+    Directory,
+    File,
+    Symlink,
+    Branch,
+    RemoteBranch,
+    Tag,
+    Commit,
+    Host,
+    Process,
+    /// A container image.
+    Image,
+    Package,
+    /// A version of a package.
+    Version,
+    Script,
+    Alias,
+    Function,
+    /// A manual page.
+    Manual,
+    /// A section of a document.
+    Section,
+    /// A command line, e.g. from the history of the shell.
+    Command,
+    /// A pull request or a merge request of a forge, e.g. GitHub.
+    PullRequest,
+    /// An issue of a forge, e.g. GitHub.
+    Issue,
+    /// A desktop application.
+    Application,
+    Other,
+}
+
+/// The classes of the tokens of highlighted source code, e.g. in the
+/// contents of a file, which the theme maps to colors like kinds.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum SyntaxClass {
+    Comment,
+    String,
+    /// A number, or a constant of the language, e.g. `true`.
+    Constant,
+    /// A keyword, or a storage modifier, e.g. `static`.
+    Keyword,
+    Function,
+    Type,
+    /// A line added by a diff.
+    Inserted,
+    /// A line removed by a diff.
+    Deleted,
+}
+
+/// The color of a span of displayed text, which the theme maps to an
+/// actual color.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SpanColor {
+    /// The color of the icons of a kind of completions.
+    Kind(CompletionKind),
+
+    /// The color of secondary text.
+    Dimmed,
+
+    /// The color of errors, e.g. for broken things.
+    Error,
+
+    /// The color of a class of tokens of source code.
+    Syntax(SyntaxClass),
+
+    /// The color of completions with a severity.
+    Severity(Severity),
+}
+
+/// The style of a span of displayed text.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Style {
+    /// The color of the text, or None for the default one.
+    pub color: Option<SpanColor>,
+
+    pub bold: bool,
+    pub underline: bool,
+}
+
+impl Style {
+    /// Returns the style of text in the given color.
+    pub fn colored(color: SpanColor) -> Style {
+        Style {
+            color: Some(color),
+            ..Style::default()
+        }
+    }
+}
+
+/// A part of the displayed text of a completion, in a single style.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Span {
+    /// Creates a span of text in the default style.
+    pub fn plain<S: Into<String>>(text: S) -> Span {
+        Span::styled(text, Style::default())
+    }
+
+    /// Creates a span of text in the given style.
+    pub fn styled<S: Into<String>>(text: S, style: Style) -> Span {
+        Span {
+            text: text.into(),
+            style,
+        }
+    }
+}
+
+/// A shell command which the user may run on the selected completion
+/// with a key, e.g. opening a file in an editor.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct CompletionAction {
+    /// The name of the key running the command, e.g. `ctrl-e`.
+    pub key: String,
+
+    /// The command, run with `sh -c`. Occurrences of `{}` are
+    /// replaced with the result of the completion, quoted.
+    pub command: String,
+
+    /// The kinds of completions the command applies to. An empty list
+    /// means all kinds.
+    #[serde(default)]
+    pub kinds: Vec<CompletionKind>,
+
+    /// Ask the user for confirmation before running the command.
+    #[serde(default)]
+    pub confirm: bool,
+}
+
+impl CompletionAction {
+    /// Indicates if the command applies to completions of the given
+    /// kind.
+    pub fn applies_to(&self, kind: CompletionKind) -> bool {
+        self.kinds.is_empty() || self.kinds.contains(&kind)
+    }
+}
 
 /// A trait representing a single completion.
 ///
@@ -22,6 +293,16 @@ pub trait Completion: any::Any {
         self.result_string()
     }
 
+    /// Returns the text to be shown in the selection UI, in styled
+    /// spans. The text must not contain escape sequences; the UI
+    /// renders the styles with the colors of the theme.
+    ///
+    /// The default implementation is to show `display_string` in the
+    /// default style.
+    fn display_spans(&self) -> Vec<Span> {
+        vec![Span::plain(self.display_string())]
+    }
+
     /// Returns the string to be analyzed during the search.
     ///
     /// The default implementation is to search in the same
@@ -30,6 +311,57 @@ pub trait Completion: any::Any {
         self.result_string()
     }
 
+    /// Returns secondary information about the completion, e.g. the
+    /// command run by a script, shown dimmed at the right edge.
+    ///
+    /// The default implementation returns None.
+    fn description(&self) -> Option<String> {
+        None
+    }
+
+    /// Indicates if the completion is hidden, e.g. a dotfile.
+    ///
+    /// Hidden completions are only shown when the user asks for them.
+    /// The default implementation returns `false`.
+    fn is_hidden(&self) -> bool {
+        false
+    }
+
+    /// Returns the severity of the state of the thing which the
+    /// completion refers to, which makes the completion stand out.
+    ///
+    /// The default implementation returns None, for completions of
+    /// things without a state or in a normal one.
+    fn severity(&self) -> Option<Severity> {
+        None
+    }
+
+    /// Returns the kind of the thing which the completion refers to.
+    ///
+    /// The default implementation returns `CompletionKind::Other`.
+    fn kind(&self) -> CompletionKind {
+        CompletionKind::Other
+    }
+
+    /// Returns the metadata of the file which the completion refers
+    /// to, shown in columns next to the completion and used for
+    /// sorting completions by size or modification time.
+    ///
+    /// The default implementation returns None, for completions which
+    /// are not files.
+    fn file_metadata(&self) -> Option<FileMetadata> {
+        None
+    }
+
+    /// Returns the group of the completion, under whose header it is
+    /// listed when sorting completions by group.
+    ///
+    /// The default implementation returns None; completions without a
+    /// group are listed after the groups.
+    fn group(&self) -> Option<CompletionGroup> {
+        None
+    }
+
     /// Converts a completion to an `Any` reference.
     ///
     /// This is needed for technical reasons because concrete
@@ -59,6 +391,37 @@ pub trait Completer {
     /// Returns the name of the completer.
     fn name(&self) -> String;
 
+    /// Returns the location in the hierarchy which the completer
+    /// lists, e.g. a directory or a branch, shown in the breadcrumb
+    /// trail of the chooser.
+    ///
+    /// The default implementation returns None, for completers which
+    /// are not part of a hierarchy.
+    fn location(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the character separating the components of the paths
+    /// which the completer lists, e.g. `/` for files. Typing it after
+    /// a query matching a single directory descends into it, as shells
+    /// complete unambiguous components of paths.
+    ///
+    /// The default implementation returns None, so that the character
+    /// is always added to the query.
+    fn path_separator(&self) -> Option<char> {
+        None
+    }
+
+    /// Indicates if the completer applies in the current environment,
+    /// e.g. if the current directory is in a Git repository for a
+    /// completer of branches. Completers which don't apply are not
+    /// shown.
+    ///
+    /// The default implementation returns `true`.
+    fn is_applicable(&self) -> bool {
+        true
+    }
+
     /// Indicates if fetching completions is finished.
     ///
     /// A completer may return `false` from this method to indicate
@@ -74,6 +437,60 @@ pub trait Completer {
     /// from `fetching_completions_finished`.
     fn fetch_completions(&mut self) -> Vec<CompletionBox>;
 
+    /// Stops fetching completions, e.g. in the background, as no more
+    /// of them are kept. `fetch_completions` isn't called afterwards.
+    ///
+    /// The default implementation does nothing.
+    fn stop(&mut self) {}
+
+    /// Discards the state of fetching completions, so that they are
+    /// fetched anew, e.g. after files changed. Returns `false` if
+    /// refreshing is not supported, in which case the framework keeps
+    /// the completions fetched so far.
+    ///
+    /// The default implementation returns `false`.
+    fn refresh(&mut self) -> bool {
+        false
+    }
+
+    /// Indicates if the completions depend on the query, e.g. those of
+    /// a search run for it. The completions of a dynamic completer are
+    /// fetched anew whenever the query changes, and not filtered with
+    /// the query, since the completer has done that already.
+    ///
+    /// The default implementation returns `false`.
+    fn is_dynamic(&self) -> bool {
+        false
+    }
+
+    /// Tells the completer whether hidden completions are shown.
+    /// Returns `true` if it needs to be refreshed for them, e.g. as it
+    /// didn't search hidden directories so far.
+    ///
+    /// The default implementation returns `false`.
+    fn show_hidden(&mut self, _: bool) -> bool {
+        false
+    }
+
+    /// Prepares a dynamic completer to fetch the completions for a new
+    /// query. The framework discards the completions fetched so far and
+    /// calls `fetch_completions` until fetching is finished again, so
+    /// results for older queries must not be returned any more.
+    ///
+    /// The default implementation does nothing.
+    fn requery(&mut self, _: &str) {}
+
+    /// Returns the errors which occurred since the last call, e.g.
+    /// failures to run external commands, to be shown in the status
+    /// line. Errors are not fatal; the completer may still return
+    /// completions.
+    ///
+    /// The framework calls this after each `fetch_completions`. The
+    /// default implementation returns no errors.
+    fn take_errors(&mut self) -> Vec<String> {
+        vec![]
+    }
+
     /// Descends into the given completion if possible, yielding a new
     /// completer. Returns None if descending is not possible for the
     /// completion.
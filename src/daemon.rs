@@ -0,0 +1,554 @@
+//! Defines the daemon, a long-lived process keeping the data which is
+//! slow to get warm between sessions, and the client which sessions
+//! use to get the data from it.
+//!
+//! The daemon keeps the file system cache in memory, so that listings
+//! of directories don't have to be loaded from the cache file, and it
+//! caches the outputs of commands, e.g. git listing branches or zoxide
+//! listing recently visited directories. The output of a command is
+//! reused as long as the files it depends on, which the client names,
+//! keep their modification times; commands without such files are
+//! never cached.
+//!
+//! The daemon listens on a Unix socket, `completers.sock` in
+//! `$XDG_RUNTIME_DIR`, or in a private directory in the temporary
+//! directory, or the path in `$COMPLETERS_SOCKET`. Only processes of
+//! the user can connect to it. When it isn't running, sessions get the
+//! data themselves. A client sends requests, one record per line, with
+//! tab-separated fields escaped like those of the file system cache:
+//!
+//! - `get<TAB><dir>` asks for the cached listing of a directory, which
+//!   the daemon sends as entry records followed by `.`, or `-` if it
+//!   has no up to date listing,
+//! - `put<TAB><dir>` followed by entry records and `.` stores the
+//!   listing of a directory,
+//! - `run<TAB><timeout ms><TAB><dir><TAB><program><TAB><arg>...`
+//!   followed by `w<TAB><path>` records of the files the command
+//!   depends on and `.` asks for the output of a command run in the
+//!   directory, which the daemon sends as `+<TAB><output>`, or
+//!   `!<TAB><error>` if the command failed, or `-` if the daemon
+//!   doesn't run the program, e.g. because no completer runs it in
+//!   the daemon.
+//!
+//! When collecting the clipboard is enabled, the daemon also maintains
+//! the history of the clipboard.
+//!
+//! Commands are run in the environment of the daemon, so it should be
+//! started from the shell whose sessions use it. Commands which set
+//! environment variables of their own are not sent to the daemon.
+
+use std::collections::HashMap;
+use std::env;
+use std::ffi;
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::os::unix::net::UnixStream;
+use std::path;
+use std::process::Command;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::time;
+
+use log::debug;
+use log::warn;
+
+use crate::completers::clipboard;
+use crate::completers::fs_cache;
+use crate::completers::subprocess;
+use crate::config;
+use crate::socket;
+
+/// The time the client waits for the daemon to answer a request, in
+/// addition to the timeout of a command.
+const CLIENT_TIMEOUT: time::Duration = time::Duration::from_millis(500);
+
+/// How often the daemon writes the file system cache to the cache
+/// file, so that sessions started without it still benefit from it.
+const SAVE_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+/// Files modified more recently than this don't make outputs cached,
+/// since they could be modified again without a change of the
+/// modification time, if its resolution is low.
+const MIN_CACHED_AGE: time::Duration = time::Duration::from_secs(2);
+
+/// The programs which the built-in completers run in the daemon, found
+/// in its `PATH`. The daemon also runs the programs of Terraform of the
+/// configuration, and the Gradle wrapper of the directory of a command.
+const PROGRAMS: &[&str] = &["git", "gradle", "man", "nix", "zoxide"];
+
+/// Returns the directory of the socket the daemon listens on, private
+/// to the user, or None if the user chose the path of the socket.
+fn socket_dir() -> Option<path::PathBuf> {
+    if env::var_os("COMPLETERS_SOCKET").is_some() {
+        return None;
+    }
+    Some(match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) => path::PathBuf::from(dir),
+        None => {
+            let uid = unsafe { libc::getuid() };
+            env::temp_dir().join(format!("completers-{}", uid))
+        }
+    })
+}
+
+/// Returns the path of the socket the daemon listens on.
+pub fn socket_path() -> path::PathBuf {
+    match socket_dir() {
+        Some(dir) => dir.join("completers.sock"),
+        None => path::PathBuf::from(env::var_os("COMPLETERS_SOCKET").unwrap_or_default()),
+    }
+}
+
+/// Returns a field of a record: the escaped path, or None if the path
+/// isn't valid Unicode, in which case nothing is sent.
+fn path_field(p: &path::Path) -> Option<String> {
+    p.to_str().map(fs_cache::escape)
+}
+
+/// Returns the modification times of the files an output depends on,
+/// with None for the missing ones, or None if some file was modified
+/// too recently for the output to be cached.
+fn modification_times(paths: &[path::PathBuf]) -> Option<Vec<Option<time::SystemTime>>> {
+    let now = time::SystemTime::now();
+    let mut times = Vec::with_capacity(paths.len());
+    for p in paths {
+        match fs::metadata(p).and_then(|m| m.modified()) {
+            Result::Ok(modified) => match now.duration_since(modified) {
+                Result::Ok(age) if age >= MIN_CACHED_AGE => times.push(Some(modified)),
+                _ => return None,
+            },
+            Result::Err(_) => times.push(None),
+        }
+    }
+    Some(times)
+}
+
+/// The output of a command, along with the modification times of the
+/// files it depends on at the time the command was run.
+struct CachedOutput {
+    paths: Vec<path::PathBuf>,
+    times: Vec<Option<time::SystemTime>>,
+    output: String,
+}
+
+/// The data the daemon keeps warm.
+struct State {
+    fs_cache: fs_cache::FsCache,
+
+    /// The outputs of commands, keyed by the directory they were run in
+    /// and their arguments, including the program.
+    outputs: HashMap<(String, Vec<String>), CachedOutput>,
+
+    /// The programs the daemon runs for clients.
+    programs: Vec<String>,
+}
+
+impl State {
+    /// Indicates if the daemon runs the program for clients, in the
+    /// given directory.
+    fn runs(&self, dir: &str, program: &str) -> bool {
+        self.programs.iter().any(|p| p == program)
+            || path::Path::new(program) == path::Path::new(dir).join("gradlew")
+    }
+}
+
+/// Runs a command on behalf of a client, or returns its cached output.
+/// The program must be one which the daemon runs.
+fn run(
+    state: &Mutex<State>,
+    timeout: time::Duration,
+    dir: String,
+    args: Vec<String>,
+    paths: Vec<path::PathBuf>,
+) -> Result<String, String> {
+    let key = (dir, args);
+    let times = if paths.is_empty() {
+        None
+    } else {
+        modification_times(&paths)
+    };
+    if let Some(ref times) = times {
+        let state = state.lock().unwrap();
+        if let Some(cached) = state.outputs.get(&key) {
+            if cached.paths == paths && cached.times == *times {
+                return Result::Ok(cached.output.clone());
+            }
+        }
+    }
+
+    // The lock isn't held while the command runs, so that it doesn't
+    // hold up other clients.
+    let (ref dir, ref args) = key;
+    let output = match args.split_first() {
+        Some((program, args)) => {
+            subprocess::run(Command::new(program).args(args).current_dir(dir), timeout)
+        }
+        None => Result::Err("no command to run".to_owned()),
+    };
+    if let (Result::Ok(ref output), Some(times)) = (&output, times) {
+        state.lock().unwrap().outputs.insert(
+            key,
+            CachedOutput {
+                paths,
+                times,
+                output: output.clone(),
+            },
+        );
+    }
+    output
+}
+
+/// Answers the requests of a client until it disconnects.
+fn handle<R: BufRead, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    state: &Mutex<State>,
+) -> io::Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Result::Ok(());
+        }
+        let fields: Vec<String> = line
+            .trim_end_matches('\n')
+            .split('\t')
+            .map(fs_cache::unescape)
+            .collect();
+        match fields.first().map(String::as_str) {
+            Some("get") if fields.len() == 2 => {
+                let dir = path::Path::new(&fields[1]);
+                let entries = state.lock().unwrap().fs_cache.get(dir);
+                match entries {
+                    Some(entries) => {
+                        fs_cache::write_entries(&mut writer, &entries)?;
+                        writeln!(writer, ".")?;
+                    }
+                    None => writeln!(writer, "-")?,
+                }
+            }
+            Some("put") if fields.len() == 2 => {
+                let entries = socket::read_records(&mut reader)?
+                    .iter()
+                    .filter_map(|r| fs_cache::parse_entry(r))
+                    .collect();
+                let dir = path::Path::new(&fields[1]);
+                state.lock().unwrap().fs_cache.insert(dir, entries);
+                // Nothing is sent back, so that clients don't wait.
+                continue;
+            }
+            Some("run") if fields.len() >= 4 => {
+                let timeout = time::Duration::from_millis(fields[1].parse().unwrap_or(0));
+                let paths = socket::read_records(&mut reader)?
+                    .iter()
+                    .filter_map(|r| r.strip_prefix("w\t"))
+                    .map(|p| path::PathBuf::from(fs_cache::unescape(p)))
+                    .collect();
+                let args = fields[3..].to_vec();
+                if !state.lock().unwrap().runs(&fields[2], &args[0]) {
+                    writeln!(writer, "-")?;
+                } else {
+                    match run(state, timeout, fields[2].clone(), args, paths) {
+                        Result::Ok(output) => writeln!(writer, "+\t{}", fs_cache::escape(&output))?,
+                        Result::Err(e) => writeln!(writer, "!\t{}", fs_cache::escape(&e))?,
+                    }
+                }
+            }
+            _ => {
+                return Result::Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("invalid request: {:?}", line),
+                ))
+            }
+        }
+        writer.flush()?;
+    }
+}
+
+/// Runs the daemon until it fails to listen on the socket.
+pub fn serve(config: &config::Config) -> io::Result<()> {
+    let socket_path = socket_path();
+    if UnixStream::connect(&socket_path).is_ok() {
+        return Result::Err(io::Error::new(
+            io::ErrorKind::AddrInUse,
+            format!("a daemon is already listening on {}", socket_path.display()),
+        ));
+    }
+    if let Some(dir) = socket_dir() {
+        socket::create_private_dir(&dir)?;
+    }
+    let listener = socket::bind(&socket_path)?;
+
+    let programs = PROGRAMS
+        .iter()
+        .map(|p| (*p).to_owned())
+        .chain(config.terraform.programs.iter().cloned())
+        .collect();
+    let state = Arc::new(Mutex::new(State {
+        fs_cache: fs_cache::FsCache::load(),
+        outputs: HashMap::new(),
+        programs,
+    }));
+    let saved_state = Arc::clone(&state);
+    thread::spawn(move || loop {
+        thread::sleep(SAVE_INTERVAL);
+        saved_state.lock().unwrap().fs_cache.save();
+    });
+    if config.clipboard.collect {
+        let clipboard_config = config.clipboard.clone();
+        let timeout = config.subprocess.timeout();
+        thread::spawn(move || clipboard::collect(clipboard_config, timeout));
+    }
+
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Result::Ok(s) => s,
+            Result::Err(e) => {
+                warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        if let Result::Err(e) = socket::check_peer(&stream) {
+            warn!("{}", e);
+            continue;
+        }
+        let state = Arc::clone(&state);
+        thread::spawn(move || {
+            let result = stream
+                .try_clone()
+                .and_then(|writer| handle(io::BufReader::new(stream), writer, &state));
+            if let Result::Err(e) = result {
+                debug!("Client disconnected: {}", e);
+            }
+        });
+    }
+    Result::Ok(())
+}
+
+/// A connection to the daemon.
+pub struct Client {
+    reader: io::BufReader<UnixStream>,
+    writer: io::BufWriter<UnixStream>,
+}
+
+impl Client {
+    /// Connects to the daemon, or returns None if it isn't running.
+    pub fn connect() -> Option<Client> {
+        let stream = UnixStream::connect(socket_path()).ok()?;
+        Client::from_stream(stream).ok()
+    }
+
+    fn from_stream(stream: UnixStream) -> io::Result<Client> {
+        stream.set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        Result::Ok(Client {
+            writer: io::BufWriter::new(stream.try_clone()?),
+            reader: io::BufReader::new(stream),
+        })
+    }
+
+    fn read_line(&mut self) -> io::Result<String> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            return Result::Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        line.truncate(line.trim_end_matches('\n').len());
+        Result::Ok(line)
+    }
+
+    /// Returns the listing of a directory cached by the daemon, if it
+    /// is still up to date. The path must be absolute.
+    pub fn get(&mut self, dir: &path::Path) -> io::Result<Option<Vec<fs_cache::Entry>>> {
+        let dir = match path_field(dir) {
+            Some(d) => d,
+            None => return Result::Ok(None),
+        };
+        writeln!(self.writer, "get\t{}", dir)?;
+        self.writer.flush()?;
+        let mut entries = vec![];
+        loop {
+            let line = self.read_line()?;
+            match line.as_str() {
+                "-" => return Result::Ok(None),
+                "." => return Result::Ok(Some(entries)),
+                _ => entries.extend(fs_cache::parse_entry(&line)),
+            }
+        }
+    }
+
+    /// Stores the listing of a directory in the daemon. The path must
+    /// be absolute.
+    pub fn put(&mut self, dir: &path::Path, entries: &[fs_cache::Entry]) -> io::Result<()> {
+        let dir = match path_field(dir) {
+            Some(d) => d,
+            None => return Result::Ok(()),
+        };
+        writeln!(self.writer, "put\t{}", dir)?;
+        fs_cache::write_entries(&mut self.writer, entries)?;
+        writeln!(self.writer, ".")?;
+        self.writer.flush()
+    }
+
+    /// Runs a command in the daemon, which reuses its output as long
+    /// as the given files keep their modification times.
+    ///
+    /// Returns the result of the command like `subprocess::run`, None if
+    /// the daemon doesn't run the program, or an error if the daemon
+    /// didn't answer.
+    pub fn run(
+        &mut self,
+        command: &Command,
+        timeout: time::Duration,
+        paths: &[path::PathBuf],
+    ) -> io::Result<Option<Result<String, String>>> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidInput, "not valid Unicode");
+        let dir = match command.get_current_dir() {
+            Some(d) => d.to_owned(),
+            None => env::current_dir()?,
+        };
+        let mut request = format!(
+            "run\t{}\t{}",
+            timeout.as_millis(),
+            path_field(&dir).ok_or_else(invalid)?
+        );
+        let args = std::iter::once(command.get_program()).chain(command.get_args());
+        for arg in args.map(ffi::OsStr::to_str) {
+            request.push('\t');
+            request.push_str(&fs_cache::escape(arg.ok_or_else(invalid)?));
+        }
+        writeln!(self.writer, "{}", request)?;
+        for p in paths.iter().filter_map(|p| path_field(p)) {
+            writeln!(self.writer, "w\t{}", p)?;
+        }
+        writeln!(self.writer, ".")?;
+        self.writer.flush()?;
+
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(timeout + CLIENT_TIMEOUT))?;
+        let line = self.read_line();
+        self.reader
+            .get_ref()
+            .set_read_timeout(Some(CLIENT_TIMEOUT))?;
+        let line = line?;
+        if line == "-" {
+            return Result::Ok(None);
+        }
+        match line.split_once('\t') {
+            Some(("+", output)) => Result::Ok(Some(Result::Ok(fs_cache::unescape(output)))),
+            Some(("!", error)) => Result::Ok(Some(Result::Err(fs_cache::unescape(error)))),
+            _ => Result::Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "invalid answer of the daemon",
+            )),
+        }
+    }
+}
+
+/// Returns a client of a daemon which answers every request to run a
+/// command with the given output, without running it.
+#[cfg(test)]
+pub fn fake_client(output: &str) -> Client {
+    let (client_stream, daemon_stream) = UnixStream::pair().unwrap();
+    let answer = format!("+\t{}\n", fs_cache::escape(output));
+    thread::spawn(move || {
+        let mut writer = daemon_stream.try_clone().unwrap();
+        let mut reader = io::BufReader::new(daemon_stream);
+        let mut request = String::new();
+        while reader.read_line(&mut request).unwrap_or(0) > 0 {
+            if socket::read_records(&mut reader).is_err()
+                || writer.write_all(answer.as_bytes()).is_err()
+            {
+                return;
+            }
+            request.clear();
+        }
+    });
+    Client::from_stream(client_stream).unwrap()
+}
+
+#[test]
+fn test_daemon() {
+    let dir = env::temp_dir().join(format!("completers-daemon-test-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let old = time::UNIX_EPOCH + time::Duration::from_secs(1_000_000_000);
+    let set_modified = |p: &path::Path, t: time::SystemTime| {
+        fs::File::open(p).unwrap().set_modified(t).unwrap();
+    };
+    let output_path = dir.join("output");
+    let watched_path = dir.join("watched");
+    fs::write(&output_path, "first").unwrap();
+    fs::write(&watched_path, "").unwrap();
+    set_modified(&watched_path, old);
+    set_modified(&dir, old);
+
+    let state = Arc::new(Mutex::new(State {
+        fs_cache: fs_cache::FsCache::new(),
+        outputs: HashMap::new(),
+        programs: vec!["cat".to_owned()],
+    }));
+    let (client_stream, daemon_stream) = UnixStream::pair().unwrap();
+    let daemon_state = Arc::clone(&state);
+    let daemon = thread::spawn(move || {
+        let writer = daemon_stream.try_clone().unwrap();
+        handle(io::BufReader::new(daemon_stream), writer, &daemon_state).unwrap();
+    });
+    let mut client = Client::from_stream(client_stream).unwrap();
+
+    let entries = vec![
+        fs_cache::Entry {
+            name: "output".to_owned(),
+            is_dir: false,
+            is_symlink: false,
+        },
+        fs_cache::Entry {
+            name: "sub\tdir".to_owned(),
+            is_dir: true,
+            is_symlink: false,
+        },
+    ];
+    assert_eq!(client.get(&dir).unwrap(), None);
+    client.put(&dir, &entries).unwrap();
+    assert_eq!(client.get(&dir).unwrap(), Some(entries));
+
+    let timeout = time::Duration::from_secs(10);
+    let mut cat = Command::new("cat");
+    cat.arg("output").current_dir(&dir);
+    let watched = [watched_path.clone()];
+    assert_eq!(
+        client.run(&cat, timeout, &watched).unwrap(),
+        Some(Result::Ok("first".to_owned()))
+    );
+    fs::write(&output_path, "second").unwrap();
+    // The watched file didn't change, so the output is reused.
+    assert_eq!(
+        client.run(&cat, timeout, &watched).unwrap(),
+        Some(Result::Ok("first".to_owned()))
+    );
+    // Commands without watched files are always run.
+    assert_eq!(
+        client.run(&cat, timeout, &[]).unwrap(),
+        Some(Result::Ok("second".to_owned()))
+    );
+    set_modified(&watched_path, old + time::Duration::from_secs(1));
+    assert_eq!(
+        client.run(&cat, timeout, &watched).unwrap(),
+        Some(Result::Ok("second".to_owned()))
+    );
+    let mut missing = Command::new("cat");
+    missing.arg("missing").current_dir(&dir);
+    assert!(client
+        .run(&missing, timeout, &watched)
+        .unwrap()
+        .unwrap()
+        .is_err());
+    // Other programs are left to the client.
+    let mut echo = Command::new("echo");
+    echo.current_dir(&dir);
+    assert_eq!(client.run(&echo, timeout, &watched).unwrap(), None);
+
+    drop(client);
+    daemon.join().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,366 @@
+//! Module checking the environment of the application, to help with
+//! setting up the shell integration.
+//!
+//! Each check reports its status along with a hint on how to fix the
+//! problem, if there is one.
+
+use std::io;
+#[cfg(unix)]
+use std::path;
+use std::process::Command;
+use std::time;
+
+use crate::ansi;
+use crate::completers::subprocess;
+use crate::config;
+#[cfg(unix)]
+use crate::daemon;
+use crate::ui::backend;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Ok,
+    /// A problem which only affects some of the features.
+    Warning,
+    /// A problem which prevents the application from working.
+    Error,
+}
+
+/// The result of a single check.
+pub struct Check {
+    pub name: &'static str,
+    pub status: Status,
+    pub details: String,
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn ok<D: Into<String>>(name: &'static str, details: D) -> Check {
+        Check {
+            name,
+            status: Status::Ok,
+            details: details.into(),
+            hint: None,
+        }
+    }
+
+    fn failed<D: Into<String>, H: Into<String>>(
+        name: &'static str,
+        status: Status,
+        details: D,
+        hint: H,
+    ) -> Check {
+        Check {
+            name,
+            status,
+            details: details.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl Check {
+    /// Writes the result of the check, with the status of failed
+    /// checks colored if `colored` is set.
+    fn write<W: io::Write>(&self, out: &mut W, colored: bool) -> io::Result<()> {
+        let (color, label) = match self.status {
+            Status::Ok => (None, "ok"),
+            Status::Warning => (Some(ansi::FG_YELLOW), "warning"),
+            Status::Error => (Some(ansi::FG_RED), "error"),
+        };
+        match color.filter(|_| colored) {
+            Some(c) => write!(out, "{}[{}]{}", c, label, ansi::FG_RESET)?,
+            None => write!(out, "[{}]", label)?,
+        }
+        writeln!(out, " {}: {}", self.name, self.details)?;
+        if let Some(ref hint) = self.hint {
+            writeln!(out, "    {}", hint)?;
+        }
+        Result::Ok(())
+    }
+}
+
+fn check_config() -> Check {
+    let name = "configuration";
+    let config_path = match config::Config::path() {
+        Some(p) => p,
+        None => {
+            return Check::failed(
+                name,
+                Status::Warning,
+                "cannot determine the configuration file path",
+                "set HOME, XDG_CONFIG_HOME or COMPLETERS_CONFIG",
+            )
+        }
+    };
+    match config::Config::load_from(&config_path) {
+        Result::Ok(_) if config_path.exists() => {
+            Check::ok(name, format!("{} is valid", config_path.display()))
+        }
+        Result::Ok(_) => Check::ok(
+            name,
+            format!("{} does not exist, using defaults", config_path.display()),
+        ),
+        Result::Err(e) => Check::failed(
+            name,
+            Status::Error,
+            format!("{} is invalid: {}", config_path.display(), e),
+            "fix the file; until then the default configuration is used",
+        ),
+    }
+}
+
+fn check_terminal(config: &config::Config) -> Vec<Check> {
+    let name = "terminal";
+    let mut backend = match backend::new(config.ui.backend.as_deref()) {
+        Result::Ok(b) => b,
+        Result::Err(e) => {
+            return vec![Check::failed(
+                name,
+                Status::Error,
+                e.to_string(),
+                "set ui.backend in the configuration to a supported backend",
+            )]
+        }
+    };
+    let mut checks = vec![];
+    let check_io = |name: &'static str, result: io::Result<String>, hint: &str| match result {
+        Result::Ok(details) => Check::ok(name, details),
+        Result::Err(e) => Check::failed(name, Status::Error, e.to_string(), hint),
+    };
+    checks.push(check_io(
+        "tty",
+        backend
+            .output()
+            .map(|_| "the terminal can be opened".to_owned()),
+        "run completers from an interactive terminal",
+    ));
+    checks.push(check_io(
+        "terminal size",
+        backend
+            .dimensions()
+            .map(|(cols, rows)| format!("{} columns, {} rows", cols, rows)),
+        "make sure that standard output is a terminal",
+    ));
+    checks.push(check_io(
+        "terminal settings",
+        backend
+            .prepare(false)
+            .and_then(|_| backend.restore())
+            .map(|_| "terminal settings can be changed".to_owned()),
+        "make sure that standard input is a terminal",
+    ));
+    checks
+}
+
+/// Checks that the given command can be run.
+fn check_command(
+    name: &'static str,
+    command: &str,
+    args: &[&str],
+    status_if_missing: Status,
+    hint: &str,
+) -> Check {
+    match Command::new(command).args(args).output() {
+        Result::Ok(output) if output.status.success() => {
+            let version = String::from_utf8_lossy(&output.stdout);
+            Check::ok(name, version.lines().next().unwrap_or(command).to_owned())
+        }
+        Result::Ok(output) => Check::failed(
+            name,
+            status_if_missing,
+            format!("{} failed: {}", command, output.status),
+            hint,
+        ),
+        Result::Err(e) => Check::failed(
+            name,
+            status_if_missing,
+            format!("cannot run {}: {}", command, e),
+            hint,
+        ),
+    }
+}
+
+/// Checks that the daemon answers if its socket exists. Sessions get
+/// the data themselves when the daemon isn't running, so that is fine.
+#[cfg(unix)]
+fn check_daemon() -> Check {
+    let name = "daemon";
+    let socket_path = daemon::socket_path();
+    if !socket_path.exists() {
+        return Check::ok(name, "not running, sessions get the data themselves");
+    }
+    let mut client = match daemon::Client::connect() {
+        Some(c) => c,
+        None => {
+            return Check::failed(
+                name,
+                Status::Warning,
+                format!("cannot connect to {}", socket_path.display()),
+                "start the daemon with `completers daemon`, or remove the stale socket",
+            )
+        }
+    };
+    match client.get(path::Path::new("/")) {
+        Result::Ok(_) => Check::ok(
+            name,
+            format!("running, listening on {}", socket_path.display()),
+        ),
+        Result::Err(e) => Check::failed(
+            name,
+            Status::Warning,
+            format!("{} does not answer: {}", socket_path.display(), e),
+            "restart the daemon with `completers daemon`",
+        ),
+    }
+}
+
+/// Checks that the commands of a completer declared by the user are
+/// valid shell commands, and that the programs they start with can be
+/// run.
+fn check_user_completer(completer: &config::UserCompleterConfig, timeout: time::Duration) -> Check {
+    let name = "user completer";
+    let hint = "fix the command of the completer in the configuration";
+    let commands = Some(&completer.command)
+        .into_iter()
+        .chain(completer.query_command.as_ref());
+    for command in commands {
+        let syntax = subprocess::run(Command::new("sh").args(["-n", "-c", command]), timeout);
+        if let Result::Err(e) = syntax {
+            return Check::failed(
+                name,
+                Status::Warning,
+                format!("{}: `{}` is invalid: {}", completer.name, command, e),
+                hint,
+            );
+        }
+        // Leading assignments of variables are skipped; `command -v`
+        // finds builtins and programs in PATH alike.
+        let program = match command.split_whitespace().find(|w| !w.contains('=')) {
+            Some(p) => p,
+            None => continue,
+        };
+        let found = subprocess::run(
+            Command::new("sh").args(["-c", "command -v \"$1\"", "sh", program]),
+            timeout,
+        );
+        if found.is_err() {
+            return Check::failed(
+                name,
+                Status::Warning,
+                format!("{}: cannot run {}", completer.name, program),
+                hint,
+            );
+        }
+    }
+    Check::ok(name, format!("{} can be run", completer.name))
+}
+
+/// Runs all checks.
+pub fn checks(config: &config::Config) -> Vec<Check> {
+    let mut checks = vec![check_config()];
+    checks.extend(check_terminal(config));
+    checks.push(check_command(
+        "git",
+        "git",
+        &["--version"],
+        Status::Warning,
+        "install git to complete branches and commits",
+    ));
+    checks.push(check_command(
+        "container engine",
+        &config.containers.engine,
+        &["--version"],
+        Status::Warning,
+        "set containers.engine to complete local container images",
+    ));
+    if !config.containers.registries.is_empty() {
+        checks.push(check_command(
+            "curl",
+            "curl",
+            &["--version"],
+            Status::Warning,
+            "install curl to complete images from registries",
+        ));
+    }
+    #[cfg(unix)]
+    checks.push(check_daemon());
+    for completer in &config.completers {
+        checks.push(check_user_completer(completer, config.subprocess.timeout()));
+    }
+    checks
+}
+
+/// Runs all checks and prints their results, coloring them if
+/// `colored` is set, e.g. when the output is a terminal.
+///
+/// Returns `false` if any of the checks reported an error.
+pub fn run<W: io::Write>(config: &config::Config, out: &mut W, colored: bool) -> io::Result<bool> {
+    let mut passed = true;
+    for check in checks(config) {
+        check.write(out, colored)?;
+        passed &= check.status != Status::Error;
+    }
+    Result::Ok(passed)
+}
+
+#[test]
+fn test_check_write() {
+    let written = |check: &Check, colored: bool| {
+        let mut out = vec![];
+        check.write(&mut out, colored).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    let check = Check::ok("git", "git version 2.40.0");
+    assert_eq!(written(&check, true), "[ok] git: git version 2.40.0\n");
+    let check = Check::failed("curl", Status::Warning, "not found", "install curl");
+    assert_eq!(
+        written(&check, true),
+        "\x1b[38;5;3m[warning]\x1b[39m curl: not found\n    install curl\n"
+    );
+    assert_eq!(
+        written(&check, false),
+        "[warning] curl: not found\n    install curl\n"
+    );
+}
+
+#[test]
+fn test_check_user_completer() {
+    let timeout = config::SubprocessConfig::default().timeout();
+    let completer = |command: &str| config::UserCompleterConfig {
+        name: "test".to_owned(),
+        command: command.to_owned(),
+        query_command: None,
+        separator: None,
+        result_column: None,
+        display_column: None,
+        description_column: None,
+        kind: None,
+        commands: vec![],
+    };
+    for command in &["cat /etc/hosts | cut -f 1", "LC_ALL=C sort file", "echo a"] {
+        let check = check_user_completer(&completer(command), timeout);
+        assert_eq!(check.status, Status::Ok, "{}", check.details);
+    }
+    for command in &[
+        "completers-no-such-command",
+        "cat (",
+        "./completers-missing",
+    ] {
+        let check = check_user_completer(&completer(command), timeout);
+        assert_eq!(check.status, Status::Warning, "{}", command);
+    }
+}
+
+#[test]
+fn test_check_command() {
+    let check = check_command(
+        "missing",
+        "completers-no-such-command",
+        &[],
+        Status::Warning,
+        "",
+    );
+    assert_eq!(check.status, Status::Warning);
+    assert!(check.hint.is_some());
+}
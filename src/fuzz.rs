@@ -0,0 +1,81 @@
+//! Module with fuzz targets for the matching and scoring routines.
+//!
+//! Each target takes arbitrary bytes, e.g. from `cargo fuzz` or
+//! another fuzzing harness, turns them into the arguments of the
+//! routine under test and checks invariants which must hold for
+//! any input. A target panics only if an invariant is broken.
+
+use crate::scoring;
+
+/// Splits the fuzzer input into a query and a candidate.
+///
+/// The input is decoded as UTF-8, replacing invalid sequences. The
+/// first byte selects the number of characters which go to the query;
+/// the remaining characters form the candidate.
+pub fn split_input(data: &[u8]) -> (String, String) {
+    let (first, rest) = match data.split_first() {
+        Some((first, rest)) => (*first as usize, rest),
+        None => return (String::new(), String::new()),
+    };
+    let text = String::from_utf8_lossy(rest);
+    let char_count = text.chars().count();
+    let query_len = if char_count > 0 {
+        first % (char_count + 1)
+    } else {
+        0
+    };
+    let query = text.chars().take(query_len).collect();
+    let candidate = text.chars().skip(query_len).collect();
+    (query, candidate)
+}
+
+/// Fuzz target for `scoring::subsequence_match` and `scoring::score`.
+///
+/// Checks that a non-empty query which matches the candidate gets
+/// a positive score, and that an empty query gets none.
+pub fn scoring(data: &[u8]) {
+    let (query, candidate) = split_input(data);
+    let settings = scoring::ScoringSettings::default();
+    let matches = scoring::subsequence_match(&query, &candidate);
+    let score = scoring::score(&candidate, &query, &settings);
+    if query.chars().any(|c| !c.is_whitespace()) {
+        assert!(
+            !matches || score > 0,
+            "query {:?}, candidate {:?}",
+            query,
+            candidate
+        );
+    } else {
+        assert!(matches);
+        assert_eq!(score, 0);
+    }
+}
+
+#[test]
+fn test_split_input() {
+    assert_eq!(split_input(b""), (String::new(), String::new()));
+    assert_eq!(split_input(b"\x02abc"), ("ab".to_owned(), "c".to_owned()));
+    assert_eq!(split_input(b"\x05abc"), ("a".to_owned(), "bc".to_owned()));
+    assert_eq!(
+        split_input(b"\x01\xffa"),
+        ("\u{fffd}".to_owned(), "a".to_owned())
+    );
+}
+
+#[test]
+fn test_scoring_target() {
+    let inputs: &[&[u8]] = &[
+        b"",
+        b"\x00",
+        b"\x01\x00\x00",
+        b"\x02ab",
+        b"\x01 a",
+        b"\x03\xc5\xbc\xc3\xb3 \xc5\x82\xc5\xbc\xc3\xb3\xc5\x82",
+        b"\x02e\xcc\x81cafe\xcc\x81",
+        b"\x02\xff\xfe\xff",
+        b"\xffABCDEFabcdef",
+    ];
+    for input in inputs {
+        scoring(input);
+    }
+}
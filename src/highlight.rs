@@ -0,0 +1,164 @@
+//! Module highlighting the syntax of source code shown in the list,
+//! e.g. the lines of a file which the file system completer descends
+//! into.
+//!
+//! The code is parsed with the syntax definitions bundled with syntect,
+//! chosen by the name of the file or its first line, and the scopes of
+//! the tokens are reduced to a few classes, which the theme colors.
+//! Themes of syntect aren't used, so that highlighted code matches the
+//! rest of the interface.
+
+use std::path;
+
+use syntect::parsing::ParseState;
+use syntect::parsing::Scope;
+use syntect::parsing::ScopeStack;
+use syntect::parsing::SyntaxSet;
+
+use crate::core;
+use crate::core::SyntaxClass;
+
+/// The prefixes of the scopes of each class of tokens, e.g.
+/// `comment.line.double-slash.rust` for comments.
+const CLASS_SCOPES: &[(&str, SyntaxClass)] = &[
+    ("comment", SyntaxClass::Comment),
+    ("string", SyntaxClass::String),
+    ("constant.numeric", SyntaxClass::Constant),
+    ("constant.language", SyntaxClass::Constant),
+    ("constant.character", SyntaxClass::Constant),
+    ("keyword", SyntaxClass::Keyword),
+    ("storage", SyntaxClass::Keyword),
+    ("entity.name.function", SyntaxClass::Function),
+    ("support.function", SyntaxClass::Function),
+    ("entity.name.type", SyntaxClass::Type),
+    ("entity.name.class", SyntaxClass::Type),
+    ("entity.name.struct", SyntaxClass::Type),
+    ("support.type", SyntaxClass::Type),
+    ("support.class", SyntaxClass::Type),
+    ("markup.inserted", SyntaxClass::Inserted),
+    ("markup.deleted", SyntaxClass::Deleted),
+];
+
+/// A highlighter of the successive lines of a file.
+pub struct Highlighter {
+    syntax_set: SyntaxSet,
+    state: ParseState,
+    scopes: ScopeStack,
+    class_scopes: Vec<(Scope, SyntaxClass)>,
+}
+
+impl Highlighter {
+    /// Returns a highlighter for a file with the given path and first
+    /// line, or None if its syntax is unknown.
+    pub fn for_file(file_path: &path::Path, first_line: &str) -> Option<Highlighter> {
+        let syntax_set = SyntaxSet::load_defaults_newlines();
+        // Syntaxes list the names of files without extensions, e.g.
+        // `Makefile`, among their extensions.
+        let file_name = file_path.file_name()?.to_string_lossy();
+        let extension = file_path
+            .extension()
+            .map(|e| e.to_string_lossy())
+            .unwrap_or_default();
+        let state = {
+            let syntax = syntax_set
+                .find_syntax_by_extension(&file_name)
+                .or_else(|| syntax_set.find_syntax_by_extension(&extension))
+                .or_else(|| syntax_set.find_syntax_by_first_line(first_line))?;
+            ParseState::new(syntax)
+        };
+        Some(Highlighter {
+            syntax_set,
+            state,
+            scopes: ScopeStack::new(),
+            class_scopes: CLASS_SCOPES
+                .iter()
+                .filter_map(|(s, c)| Scope::new(s).ok().map(|s| (s, *c)))
+                .collect(),
+        })
+    }
+
+    /// Returns the class of the innermost scope with one.
+    fn class(&self) -> Option<SyntaxClass> {
+        self.scopes.as_slice().iter().rev().find_map(|scope| {
+            self.class_scopes
+                .iter()
+                .find(|(prefix, _)| prefix.is_prefix_of(*scope))
+                .map(|(_, class)| *class)
+        })
+    }
+
+    /// Splits the next line of the file, with its line ending, into
+    /// spans colored by the classes of their tokens. Returns None if
+    /// the line can't be parsed, in which case the following lines
+    /// can't be either.
+    pub fn highlight_line(&mut self, line: &str) -> Option<Vec<core::Span>> {
+        let operations = self.state.parse_line(line, &self.syntax_set).ok()?;
+        let mut spans = vec![];
+        let mut start = 0;
+        for (end, operation) in operations
+            .iter()
+            .map(|(i, o)| (*i, Some(o)))
+            .chain(Some((line.len(), None)))
+        {
+            if end > start {
+                let style = core::Style {
+                    color: self.class().map(core::SpanColor::Syntax),
+                    ..core::Style::default()
+                };
+                // Tokens of the same class, e.g. the quotes and the
+                // contents of a string, make up a single span.
+                match spans.last_mut() {
+                    Some(core::Span { text, style: s }) if *s == style => {
+                        text.push_str(&line[start..end])
+                    }
+                    _ => spans.push(core::Span::styled(&line[start..end], style)),
+                }
+                start = end;
+            }
+            if let Some(o) = operation {
+                self.scopes.apply(o).ok()?;
+            }
+        }
+        Some(spans)
+    }
+}
+
+#[test]
+fn test_highlighter() {
+    let mut highlighter = Highlighter::for_file(path::Path::new("src/main.rs"), "").unwrap();
+    let spans = highlighter.highlight_line("let s = \"a\"; // b\n").unwrap();
+    let class_of = |text: &str| {
+        spans
+            .iter()
+            .find(|s| s.text.contains(text))
+            .and_then(|s| s.style.color)
+    };
+    assert_eq!(
+        class_of("let"),
+        Some(core::SpanColor::Syntax(SyntaxClass::Keyword))
+    );
+    assert_eq!(
+        class_of("\"a\""),
+        Some(core::SpanColor::Syntax(SyntaxClass::String))
+    );
+    assert_eq!(
+        class_of("b"),
+        Some(core::SpanColor::Syntax(SyntaxClass::Comment))
+    );
+    assert_eq!(
+        spans.iter().map(|s| s.text.as_str()).collect::<String>(),
+        "let s = \"a\"; // b\n"
+    );
+
+    let mut highlighter = Highlighter::for_file(path::Path::new("deploy"), "#!/bin/sh\n").unwrap();
+    let spans = highlighter.highlight_line("#!/bin/sh\n").unwrap();
+    assert_eq!(
+        spans,
+        vec![core::Span::styled(
+            "#!/bin/sh\n",
+            core::Style::colored(core::SpanColor::Syntax(SyntaxClass::Comment))
+        )]
+    );
+
+    assert!(Highlighter::for_file(path::Path::new("notes"), "hello\n").is_none());
+}
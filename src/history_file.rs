@@ -0,0 +1,97 @@
+//! Module keeping histories of strings in files, e.g. of the strings
+//! copied to the clipboard and of the queries accepted in the chooser.
+//!
+//! Each line of a history file is a string, escaped like the records of
+//! the file system cache, the most recent string last. The files are in
+//! the data directory of the user and readable only by the user, as the
+//! strings may be secrets.
+
+use std::env;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path;
+
+use crate::completers::fs_cache;
+
+/// Returns the path of the history file with the given name.
+pub fn path(name: &str) -> Option<path::PathBuf> {
+    let data_dir = env::var_os("XDG_DATA_HOME")
+        .filter(|d| !d.is_empty())
+        .map(path::PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| path::Path::new(&h).join(".local/share")))?;
+    Some(data_dir.join("completers").join(name))
+}
+
+/// Parses a history, returning the strings from the most recent one,
+/// without duplicates.
+pub fn parse(contents: &str) -> Vec<String> {
+    let mut strings: Vec<String> = vec![];
+    for line in contents.lines().rev().filter(|l| !l.is_empty()) {
+        let string = fs_cache::unescape(line);
+        if !strings.contains(&string) {
+            strings.push(string);
+        }
+    }
+    strings
+}
+
+/// Adds a string to a history, moving it to the end if it is there
+/// already, and keeping at most `max_entries` strings.
+fn add(history: &str, string: &str, max_entries: usize) -> String {
+    let escaped = fs_cache::escape(string);
+    let mut lines: Vec<&str> = history
+        .lines()
+        .filter(|l| !l.is_empty() && *l != escaped)
+        .collect();
+    lines.push(&escaped);
+    let skipped = lines.len().saturating_sub(max_entries);
+    lines[skipped..]
+        .iter()
+        .map(|l| format!("{}\n", l))
+        .collect()
+}
+
+#[test]
+fn test_history() {
+    let history = add("", "ls -l", 3);
+    let history = add(&history, "two\nlines", 3);
+    let history = add(&history, "ls -l", 3);
+    assert_eq!(history, "two\\nlines\nls -l\n");
+    assert_eq!(parse(&history), vec!["ls -l", "two\nlines"]);
+    let history = add(&history, "a", 3);
+    let history = add(&history, "b", 3);
+    assert_eq!(parse(&history), vec!["b", "a", "ls -l"]);
+}
+
+/// Reads a history file, returning the strings from the most recent
+/// one, or none if the file doesn't exist or can't be read.
+pub fn read(history_path: &path::Path) -> Vec<String> {
+    fs::read_to_string(history_path)
+        .map(|h| parse(&h))
+        .unwrap_or_default()
+}
+
+/// Adds a string to a history file, which is replaced atomically, so
+/// that readers never read a partly written one.
+pub fn save(history_path: &path::Path, string: &str, max_entries: usize) -> io::Result<()> {
+    let history = match fs::read_to_string(history_path) {
+        Result::Ok(h) => h,
+        Result::Err(ref e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Result::Err(e) => return Result::Err(e),
+    };
+    if let Some(dir) = history_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temp_path = history_path.with_extension("tmp");
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let mut file = options.open(&temp_path)?;
+    file.write_all(add(&history, string, max_entries).as_bytes())?;
+    fs::rename(&temp_path, history_path)
+}
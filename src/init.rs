@@ -0,0 +1,37 @@
+//! Module with the scripts integrating completers with shells, which
+//! `completers init` prints for the shell to evaluate, e.g. with
+//! `eval "$(completers init zsh)"` in `~/.zshrc`.
+//!
+//! The scripts define key bindings running completers on the command
+//! line being edited, and replace the line with the result.
+
+use crate::quoting;
+
+/// The names of the shells with an integration script.
+pub const SHELLS: &[&str] = &["bash", "zsh"];
+
+const BASH: &str = include_str!("init/init.bash");
+const ZSH: &str = include_str!("init/init.zsh");
+
+/// Returns the script integrating completers with the shell, which runs
+/// the given program, or None if the shell isn't supported.
+pub fn script(shell: &str, program: &str) -> Option<String> {
+    let script = match shell {
+        "bash" => BASH,
+        "zsh" => ZSH,
+        _ => return None,
+    };
+    let program = quoting::quote(program, quoting::QuotingStyle::SingleQuote);
+    Some(format!("completers_program_={}\n\n{}", program, script))
+}
+
+#[test]
+fn test_script() {
+    for shell in SHELLS {
+        assert!(script(shell, "completers").is_some());
+    }
+    let zsh = script("zsh", "/opt/my tools/completers").unwrap();
+    assert!(zsh.starts_with("completers_program_='/opt/my tools/completers'\n"));
+    assert!(zsh.contains("zle -N completers-complete"));
+    assert_eq!(script("fish", "completers"), None);
+}
@@ -0,0 +1,30 @@
+# Integrates completers with bash. Loaded with:
+#
+#     eval "$(completers init bash)"
+#
+# The word at the cursor is completed with the backtick key.
+
+function completers_complete_ {
+    # Aliases and functions exist only in the shell, so their
+    # definitions are passed to completers in a file.
+    local definitions="${TMPDIR:-/tmp}/completers-definitions-$$.txt"
+    local result="${TMPDIR:-/tmp}/completers-result-$$.txt"
+    { alias -p; declare -F; } > "$definitions"
+    COMPLETERS_SHELL_DEFINITIONS="$definitions" \
+        "$completers_program_" \
+        --point="${READLINE_POINT}" \
+        --shell=bash \
+        --print0 \
+        -- "${READLINE_LINE}" > "$result"
+    # The line is printed when a completion is chosen (0) and when the
+    # choice is cancelled (1), but not after an error (2).
+    if [ $? -le 1 ]; then
+        local point line
+        { IFS= read -r -d '' point; IFS= read -r -d '' line; } < "$result"
+        READLINE_LINE=$line
+        READLINE_POINT=$point
+    fi
+    rm -f "$definitions" "$result"
+}
+
+bind -x '"`":"completers_complete_"'
@@ -1,14 +1,42 @@
 extern crate array2d;
+#[cfg(any(feature = "crossterm", windows))]
+extern crate crossterm;
 extern crate itertools;
+#[cfg(unix)]
 extern crate libc;
 extern crate log;
+extern crate serde;
+extern crate serde_json;
+extern crate syntect;
+#[cfg(unix)]
 extern crate term_cursor;
+#[cfg(unix)]
 extern crate term_size;
+#[cfg(unix)]
 extern crate termion;
+#[cfg(unix)]
 extern crate termios;
+extern crate toml;
+extern crate unicode_width;
 
+pub mod ansi;
+pub mod capabilities;
 pub mod completers;
 pub mod config;
 pub mod core;
+#[cfg(unix)]
+pub mod daemon;
+pub mod doctor;
+pub mod fuzz;
+pub mod highlight;
+pub mod history_file;
+pub mod init;
+pub mod picker;
+pub mod quoting;
 pub mod scoring;
+#[cfg(unix)]
+pub mod server;
+#[cfg(unix)]
+pub mod socket;
 pub mod ui;
+pub mod version;
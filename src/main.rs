@@ -4,41 +4,120 @@ extern crate completers;
 extern crate log;
 extern crate simplelog;
 
-extern crate termion;
-
 use std::fs;
 use std::io;
+use std::io::IsTerminal;
 use std::io::Write;
-use std::path;
 
+use completers::capabilities;
+use completers::completers::aws;
+use completers::completers::bash_completion;
+use completers::completers::calc;
+use completers::completers::cargo;
+use completers::completers::clipboard;
+use completers::completers::container;
+use completers::completers::database;
+use completers::completers::datetime;
+use completers::completers::desktop;
+use completers::completers::devices;
 use completers::completers::filesystem;
+use completers::completers::forge;
 use completers::completers::git;
+use completers::completers::hg;
+use completers::completers::history;
+use completers::completers::homebrew;
+use completers::completers::locale;
+use completers::completers::man;
+use completers::completers::merged;
+use completers::completers::nix;
+use completers::completers::npm;
+use completers::completers::options;
+use completers::completers::packages;
+use completers::completers::pass;
+use completers::completers::recent_dirs;
+use completers::completers::recent_files;
+use completers::completers::shared;
+use completers::completers::shell;
+#[cfg(unix)]
+use completers::completers::signal;
+use completers::completers::svn;
+use completers::completers::systemd;
+use completers::completers::tasks;
+use completers::completers::terraform;
+use completers::completers::test_names;
+use completers::completers::user;
+use completers::completers::wireless;
+use completers::config;
 use completers::config::WORD_BOUNDARIES;
 use completers::core;
+#[cfg(unix)]
+use completers::daemon;
+use completers::doctor;
+use completers::init;
+use completers::quoting;
+#[cfg(unix)]
+use completers::server;
 use completers::ui;
 
-/// Returns a pair of character indices within `line`
-/// which delimit the initial query, i.e., the string
-/// which will be substituted by completions.
-///
-/// This returns a pair representing the range [start, end).
-fn get_initial_query_range(line: &str, point: usize) -> (usize, usize) {
-    let words = line.split(WORD_BOUNDARIES);
-    let mut start: usize = 0;
-    for w in words {
-        let end = start + w.len();
-        if point >= start && point <= end {
-            return (start, end);
-        }
-        // Moving forward, we have to add 1 for the delimiter itself.
-        start = end + 1;
+/// Returns the byte index `point` from the shell as an index which the
+/// line can be sliced at: not past the end of the line, and at the
+/// start of a character rather than within one.
+fn clamp_point(line: &str, point: usize) -> usize {
+    let mut point = std::cmp::min(point, line.len());
+    while !line.is_char_boundary(point) {
+        point -= 1;
     }
-    // If we get here, it means that there were no words.
-    (0, 0)
+    point
+}
+
+/// Returns the byte index of the character with the given index, e.g.
+/// of the cursor of zsh, which counts characters.
+fn byte_point(line: &str, char_point: usize) -> usize {
+    line.char_indices()
+        .nth(char_point)
+        .map_or(line.len(), |(i, _)| i)
+}
+
+/// Returns the index of the character at the byte index `point`.
+fn char_point(line: &str, point: usize) -> usize {
+    line[..point].chars().count()
+}
+
+#[test]
+fn test_point_units() {
+    assert_eq!(0, byte_point("", 0));
+    assert_eq!(3, byte_point("cd żółw", 3));
+    assert_eq!(5, byte_point("cd żółw", 4));
+    assert_eq!(10, byte_point("cd żółw", 10));
+    assert_eq!(4, char_point("cd żółw", 5));
+    assert_eq!(7, char_point("cd żółw", 10));
+}
+
+#[test]
+fn test_clamp_point() {
+    assert_eq!(0, clamp_point("", 3));
+    assert_eq!(3, clamp_point("foo", 3));
+    assert_eq!(3, clamp_point("foo", 10));
+    // 'ż' takes two bytes.
+    assert_eq!(3, clamp_point("cd żółw", 3));
+    assert_eq!(3, clamp_point("cd żółw", 4));
+    assert_eq!(5, clamp_point("cd żółw", 5));
+}
+
+/// Returns the word of `line` at `point` which is the initial query,
+/// i.e., the word which will be substituted by completions. Quotes and
+/// escapes are removed from the query, so that the query for
+/// `cp "My Documents/fi` is the whole path.
+fn get_initial_query(line: &str, point: usize) -> quoting::Word {
+    quoting::word_at(line, point, WORD_BOUNDARIES)
 }
 
 #[test]
 fn test_initial_query_range() {
+    let get_initial_query_range = |line, point| {
+        let word = get_initial_query(line, point);
+        (word.start, word.end)
+    };
     assert_eq!((0, 0), get_initial_query_range("", 0));
     assert_eq!((0, 3), get_initial_query_range("foo", 0));
     assert_eq!((0, 3), get_initial_query_range("foo", 2));
@@ -48,46 +127,844 @@ fn test_initial_query_range() {
     assert_eq!((4, 7), get_initial_query_range("foo bar", 4));
     assert_eq!((4, 7), get_initial_query_range("foo bar", 6));
     assert_eq!((4, 7), get_initial_query_range("foo bar", 7));
+    // Indices are in bytes; 'ó' and 'ł' take two bytes each.
+    assert_eq!((0, 7), get_initial_query_range("żółw ćma", 3));
+    assert_eq!((8, 12), get_initial_query_range("żółw ćma", 8));
+    assert_eq!("ćma", get_initial_query("żółw ćma", 12).text);
+}
+
+/// Returns a pair of byte indices within `line` delimiting the
+/// component of a URL under `point`, if the word at `point` is a URL.
+///
+/// A URL has the form `scheme://[user@]host[:port][/path][?query]`.
+/// The range covers the scheme, the host or the path, so that only
+/// that component is replaced by the completion. The path range
+/// does not include the leading slash.
+fn get_url_query_range(line: &str, point: usize) -> Option<(usize, usize)> {
+    let is_boundary = |c: char| c != ':' && WORD_BOUNDARIES.contains(&c);
+    let start = line.get(..point)?.rfind(is_boundary).map_or(0, |p| p + 1);
+    let end = line[point..]
+        .find(is_boundary)
+        .map_or(line.len(), |p| point + p);
+    let word = &line[start..end];
+
+    let scheme_end = word.find("://")?;
+    let scheme = &word[..scheme_end];
+    if !scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        || !scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+    {
+        return None;
+    }
+
+    let authority_start = scheme_end + 3;
+    let authority_end = word[authority_start..]
+        .find(['/', '?', '#'])
+        .map_or(word.len(), |p| authority_start + p);
+    let authority = &word[authority_start..authority_end];
+    let host_start = authority_start + authority.rfind('@').map_or(0, |p| p + 1);
+    let host = &word[host_start..authority_end];
+    let host_end = if host.starts_with('[') {
+        // An IPv6 address may contain colons.
+        host.find(']').map_or(authority_end, |p| host_start + p + 1)
+    } else {
+        host.find(':').map_or(authority_end, |p| host_start + p)
+    };
+
+    let path_start = if word[authority_end..].starts_with('/') {
+        authority_end + 1
+    } else {
+        authority_end
+    };
+    let path_end = word[path_start..]
+        .find(['?', '#'])
+        .map_or(word.len(), |p| path_start + p);
+
+    let point = point - start;
+    let range = if point <= scheme_end {
+        (0, scheme_end)
+    } else if point <= authority_end {
+        (host_start, host_end)
+    } else if point <= path_end {
+        (path_start, path_end)
+    } else {
+        return None;
+    };
+    Some((start + range.0, start + range.1))
+}
+
+#[test]
+fn test_url_query_range() {
+    let url = "curl https://user@example.com:8080/foo/bar?x=1";
+    assert_eq!(
+        Some((24, 33)),
+        get_url_query_range("curl https://żółw.pl/ścieżka", 28)
+    );
+    assert_eq!(None, get_url_query_range("foo bar", 2));
+    assert_eq!(None, get_url_query_range("ls a:b", 5));
+    assert_eq!(None, get_url_query_range("ls 1a://b", 6));
+    assert_eq!(None, get_url_query_range("ls", 10));
+    assert_eq!(None, get_url_query_range(url, 2));
+    assert_eq!(None, get_url_query_range(url, 45));
+    assert_eq!(Some((5, 10)), get_url_query_range(url, 7));
+    assert_eq!(Some((18, 29)), get_url_query_range(url, 13));
+    assert_eq!(Some((18, 29)), get_url_query_range(url, 20));
+    assert_eq!(Some((18, 29)), get_url_query_range(url, 34));
+    assert_eq!(Some((35, 42)), get_url_query_range(url, 35));
+    assert_eq!(Some((35, 42)), get_url_query_range(url, 42));
+    assert_eq!(
+        Some((21, 21)),
+        get_url_query_range("git clone ssh://host/", 21)
+    );
+    assert_eq!(
+        Some((12, 17)),
+        get_url_query_range("echo (ftp://[::1]:21/a)", 12)
+    );
+    assert_eq!(
+        Some((21, 22)),
+        get_url_query_range("echo (ftp://[::1]:21/a)", 22)
+    );
+}
+
+/// Returns a pair of byte indices within `line` delimiting the
+/// arithmetic expression at `point`, if there is one. Unlike words,
+/// expressions may contain parentheses, e.g. `(45*3)+2`.
+fn get_expression_query_range(line: &str, point: usize) -> Option<(usize, usize)> {
+    let is_expression_char = |c: char| calc::EXPRESSION_CHARS.contains(c);
+    let start = line
+        .get(..point)?
+        .trim_end_matches(is_expression_char)
+        .len();
+    let after = &line[point..];
+    let end = point + after.len() - after.trim_start_matches(is_expression_char).len();
+    if calc::is_expression(&line[start..end]) {
+        Some((start, end))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_expression_query_range() {
+    assert_eq!(
+        Some((5, 13)),
+        get_expression_query_range("echo (45*3)+2", 13)
+    );
+    assert_eq!(Some((5, 9)), get_expression_query_range("head -1+2 x", 7));
+    assert_eq!(None, get_expression_query_range("ls 2024-01-05", 13));
+    assert_eq!(None, get_expression_query_range("ls *.rs", 7));
+    assert_eq!(None, get_expression_query_range("ls", 10));
+}
+
+/// Returns a pair of byte indices within `line` delimiting a query
+/// given explicitly rather than found in the line: the query itself if
+/// it precedes `point`, or an empty range at `point` otherwise, where
+/// the completion is inserted then.
+fn get_explicit_query_range(line: &str, point: usize, query: &str) -> (usize, usize) {
+    if line.get(..point).is_some_and(|l| l.ends_with(query)) {
+        (point - query.len(), point)
+    } else {
+        (point, point)
+    }
+}
+
+#[test]
+fn test_explicit_query_range() {
+    assert_eq!((3, 8), get_explicit_query_range("ls foo.b", 8, "foo.b"));
+    assert_eq!((6, 8), get_explicit_query_range("ls foo.b", 8, ".b"));
+    assert_eq!((4, 4), get_explicit_query_range("ls foo.b", 4, "bar"));
+    assert_eq!((2, 2), get_explicit_query_range("ls", 2, "x"));
+}
+
+/// Returns the name of the variable assigned in `word`, e.g. `TZ` in
+/// `TZ=Europe/Wa`, and the byte index within `line` where the assigned
+/// value starts, as only the value is to be completed.
+fn get_assignment(line: &str, word: &quoting::Word) -> Option<(String, usize)> {
+    let (name, _) = line[word.start..word.end].split_once('=')?;
+    let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if is_identifier {
+        Some((name.to_owned(), word.start + name.len() + 1))
+    } else {
+        None
+    }
+}
+
+#[test]
+fn test_assignment() {
+    let get_assignment = |line: &str, point| get_assignment(line, &get_initial_query(line, point));
+    assert_eq!(
+        Some(("TZ".to_owned(), 3)),
+        get_assignment("TZ=Europe/Wa date", 12)
+    );
+    assert_eq!(
+        Some(("LC_ALL".to_owned(), 11)),
+        get_assignment("env LC_ALL='pl_P", 16)
+    );
+    assert_eq!(None, get_assignment("ls --color=au", 13));
+    assert_eq!(None, get_assignment("ls foo", 6));
+}
+
+/// Commands which run the command given in their arguments, such as
+/// `sudo kill`; the name of the command run is used instead.
+const COMMAND_PREFIXES: &[&str] = &[
+    "sudo", "xargs", "env", "time", "nohup", "exec", "nice", "command", "watch",
+];
+
+/// Returns the byte index within `line` at which the command
+/// containing `point` starts.
+///
+/// In pipelines and lists of commands, such as `foo | bar` or
+/// `foo && bar; baz`, this is the start of the command after the last
+/// separator preceding `point`. Separators within quotes and in
+/// redirections such as `2>&1` are ignored.
+fn get_command_start(line: &str, point: usize) -> usize {
+    let mut start = 0;
+    let mut quote: Option<char> = None;
+    let mut previous_char = None;
+    for (i, c) in line[..point].char_indices() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '\'' | '"' => quote = Some(c),
+                '&' if previous_char == Some('>') || previous_char == Some('<') => {}
+                '&' if line[(i + 1)..].starts_with('>') => {}
+                // A backslash before a newline continues the command.
+                '\n' if previous_char == Some('\\') => {}
+                '|' | '&' | ';' | '\n' => start = i + 1,
+                _ => {}
+            },
+        }
+        previous_char = Some(c);
+    }
+    start
+}
+
+#[test]
+fn test_command_start() {
+    assert_eq!(0, get_command_start("", 0));
+    assert_eq!(0, get_command_start("foo bar", 7));
+    assert_eq!(5, get_command_start("foo | bar", 9));
+    assert_eq!(0, get_command_start("foo | bar", 3));
+    assert_eq!(6, get_command_start("foo && bar", 10));
+    assert_eq!(4, get_command_start("foo;bar", 7));
+    assert_eq!(0, get_command_start("grep 'a|b' x", 12));
+    assert_eq!(0, get_command_start("make 2>&1 x", 11));
+    assert_eq!(0, get_command_start("make &>log x", 12));
+    assert_eq!(4, get_command_start("foo\nbar", 7));
+    assert_eq!(0, get_command_start("foo \\\nbar", 9));
+}
+
+/// Returns the words of the command invoked on `line` which precede
+/// the query starting at `query_start`, e.g. `["npm", "run"]` for
+/// `npm run b`. The first word is the name of the command. Returns no
+/// words if the query is the command name itself.
+///
+/// Environment variable assignments and commands from
+/// `COMMAND_PREFIXES`, along with their options, are skipped, so that
+/// the command name is `kill` in `sudo -n kill` or `xargs kill`.
+fn get_command_words(line: &str, query_start: usize) -> Vec<&str> {
+    let command_start = get_command_start(line, query_start);
+    let mut words = line[command_start..query_start]
+        .split(WORD_BOUNDARIES)
+        .filter(|w| !w.is_empty());
+    let mut after_prefix = false;
+    while let Some(word) = words.next() {
+        if word.contains('=') {
+            continue;
+        }
+        if COMMAND_PREFIXES.contains(&word) {
+            after_prefix = true;
+        } else if !(after_prefix && word.starts_with('-')) {
+            return std::iter::once(word).chain(words).collect();
+        }
+    }
+    vec![]
+}
+
+#[test]
+fn test_command_name() {
+    let get_command_name =
+        |line, query_start| get_command_words(line, query_start).first().cloned();
+    assert_eq!(None, get_command_name("", 0));
+    assert_eq!(None, get_command_name("cd", 0));
+    assert_eq!(None, get_command_name("  cd", 2));
+    assert_eq!(Some("cd"), get_command_name("cd ", 3));
+    assert_eq!(Some("cd"), get_command_name("cd foo", 3));
+    assert_eq!(Some("cd"), get_command_name("  cd foo", 5));
+    assert_eq!(Some("git"), get_command_name("git checkout foo", 13));
+    assert_eq!(Some("kill"), get_command_name("grep foo | xargs kill ", 22));
+    assert_eq!(Some("cd"), get_command_name("make && cd ", 11));
+    assert_eq!(None, get_command_name("make && ", 8));
+    assert_eq!(Some("kill"), get_command_name("sudo -n kill ", 13));
+    assert_eq!(Some("make"), get_command_name("CC=clang make ", 14));
+    assert_eq!(None, get_command_name("sudo ", 5));
+}
+
+#[test]
+fn test_command_words() {
+    let empty: Vec<&str> = vec![];
+    assert_eq!(empty, get_command_words("npm", 0));
+    assert_eq!(vec!["npm"], get_command_words("npm ", 4));
+    assert_eq!(vec!["npm", "run"], get_command_words("npm run b", 8));
+    assert_eq!(vec!["yarn"], get_command_words("sudo -E yarn  b", 14));
+    assert_eq!(vec!["cd"], get_command_words("git pull && cd ", 15));
+    assert_eq!(vec!["żółw", "ćma"], get_command_words("sudo żółw ćma ", 18));
+}
+
+/// Indicates if the command words start with the words of `command`,
+/// e.g. `npm run`.
+fn command_matches(command_words: &[&str], command: &str) -> bool {
+    let pattern: Vec<&str> = command.split_whitespace().collect();
+    !pattern.is_empty() && command_words.starts_with(&pattern)
+}
+
+#[test]
+fn test_command_matches() {
+    assert!(command_matches(&["npm", "run"], "npm run"));
+    assert!(command_matches(&["yarn", "run"], "yarn"));
+    assert!(!command_matches(&["npm", "install"], "npm run"));
+    assert!(!command_matches(&["npm"], "npm run"));
+    assert!(!command_matches(&[], " "));
 }
 
 /// Returns the collection of completers to be used for the completion.
 ///
 /// This routine makes it possible to return different sets of completers
-/// depending on the query.
-fn get_completers(original_query: &str) -> Vec<Box<dyn core::Completer>> {
+/// depending on the query and the command being completed.
+fn get_completers(
+    original_query: &str,
+    command_words: &[&str],
+    assigned_variable: Option<&str>,
+    config: &config::Config,
+) -> Vec<Box<dyn core::Completer>> {
+    let command = command_words.first().cloned();
     let query_path = std::path::PathBuf::from(original_query);
-    let fs_completer_path;
-    if query_path.is_absolute() {
+    let fs_completer_path = if query_path.is_absolute() {
         // If we start from an absolute path in the query, we interpret
         // that as the user trying to search that directory, not to
         // search for the query as a substring in the current directory.
-        fs_completer_path = query_path;
+        query_path
     } else {
-        fs_completer_path = std::path::PathBuf::from(".");
+        std::path::PathBuf::from(".")
+    };
+
+    let mut fs_config = config.fs.clone();
+    // Entries beyond the limit of the tab would be dropped anyway.
+    fs_config.max_candidates = fs_config.max_candidates.min(config.ui.max_candidates);
+    if let Some(c) = command {
+        if fs_config.dirs_only_commands.iter().any(|d| d == c) {
+            fs_config.dirs_only = true;
+        }
     }
 
-    vec![
-        Box::new(filesystem::FsCompleter::new(path::PathBuf::from(
-            fs_completer_path,
-        ))),
-        Box::new(git::GitBranchCompleter::new()),
+    let mut completers: Vec<Box<dyn core::Completer>> = vec![
+        Box::new(filesystem::FsCompleter::new(fs_completer_path, fs_config)),
+        Box::new(git::GitBranchCompleter::new(config.subprocess.timeout())),
+    ];
+    if hg::is_repository() {
+        completers.push(Box::new(hg::HgBranchCompleter::new(
+            config.subprocess.timeout(),
+        )));
+    }
+    if svn::is_working_copy() {
+        completers.push(Box::new(svn::SvnBranchCompleter::new(
+            config.subprocess.timeout(),
+        )));
+    }
+    if config.clipboard.enabled {
+        completers.push(Box::new(clipboard::ClipboardCompleter::new(
+            config.subprocess.timeout(),
+        )));
+    }
+    if let Some(c) = command {
+        if config.recent_dirs.commands.iter().any(|d| d == c) {
+            completers.insert(
+                1,
+                Box::new(recent_dirs::RecentDirsCompleter::new(
+                    config.recent_dirs.clone(),
+                    config.fs.clone(),
+                    config.subprocess.timeout(),
+                )),
+            );
+        }
+        if config.recent_files.commands.iter().any(|d| d == c) {
+            completers.insert(
+                0,
+                Box::new(recent_files::RecentFilesCompleter::new(
+                    config.recent_files.clone(),
+                    config.subprocess.timeout(),
+                )),
+            );
+        }
+        if config.containers.commands.iter().any(|d| d == c) {
+            completers.insert(
+                0,
+                Box::new(container::ImageCompleter::new(
+                    config.containers.clone(),
+                    config.subprocess.timeout(),
+                )),
+            );
+        }
+    }
+    if config
+        .npm
+        .commands
+        .iter()
+        .any(|c| command_matches(command_words, c))
+    {
+        completers.insert(0, Box::new(npm::NpmScriptCompleter::new(".")));
+    }
+    if let Some(c) = command {
+        if config.man.commands.iter().any(|d| d == c) {
+            completers.insert(
+                0,
+                Box::new(man::ManPageCompleter::new(config.subprocess.timeout())),
+            );
+        }
+        if config.packages.commands.iter().any(|d| d == c) {
+            completers.insert(
+                0,
+                Box::new(packages::PackageCompleter::new(
+                    &config.packages,
+                    config.subprocess.timeout(),
+                )),
+            );
+        }
+    }
+    if config
+        .nix
+        .commands
+        .iter()
+        .any(|c| command_matches(command_words, c))
+    {
+        completers.insert(0, Box::new(nix::NixCompleter::new(config.nix.clone())));
+    }
+    if config
+        .homebrew
+        .commands
+        .iter()
+        .any(|c| command_matches(command_words, c))
+    {
+        completers.insert(
+            0,
+            Box::new(homebrew::HomebrewCompleter::new(
+                homebrew::package_kinds(command_words),
+                config.homebrew.clone(),
+            )),
+        );
+    }
+    // Compspecs complete options best, and arguments after the other
+    // completers.
+    if config.bash_completion.enabled && command.is_some() {
+        let completer = Box::new(bash_completion::BashCompletionCompleter::new(
+            command_words,
+            original_query,
+            config.bash_completion.clone(),
+            config.subprocess.timeout(),
+        ));
+        if original_query.starts_with('-') {
+            completers.insert(0, completer);
+        } else {
+            completers.push(completer);
+        }
+    }
+    if config.options.enabled && command.is_some() && original_query.starts_with('-') {
+        completers.insert(
+            0,
+            Box::new(options::OptionCompleter::new(
+                command_words,
+                config.subprocess.timeout(),
+            )),
+        );
+    }
+    // Aliases and functions are completed in place of a command name.
+    if command.is_none() && shell::ShellCompleter::is_available() {
+        completers.insert(0, Box::new(shell::ShellCompleter::new()));
+    }
+    let after_date_option = command_words
+        .last()
+        .is_some_and(|w| config.datetime.options.iter().any(|o| o == w));
+    if after_date_option || datetime::is_moment(original_query) {
+        completers.insert(
+            0,
+            Box::new(datetime::DateTimeCompleter::new(original_query)),
+        );
+    }
+    if calc::is_expression(original_query) {
+        completers.insert(0, Box::new(calc::CalcCompleter::new(original_query)));
+    }
+    match assigned_variable.and_then(locale::value_kind) {
+        Some(locale::ValueKind::TimeZone) => {
+            completers.insert(0, Box::new(locale::TimeZoneCompleter::new()));
+        }
+        Some(locale::ValueKind::Locale) => {
+            completers.insert(
+                0,
+                Box::new(locale::LocaleCompleter::new(config.subprocess.timeout())),
+            );
+        }
+        None => {}
+    }
+    if config
+        .desktop
+        .commands
+        .iter()
+        .any(|c| command_matches(command_words, c))
+    {
+        completers.insert(
+            0,
+            Box::new(desktop::DesktopCompleter::new(config.desktop.clone())),
+        );
+    }
+    if config
+        .pass
+        .commands
+        .iter()
+        .any(|c| command_matches(command_words, c))
+    {
+        if let Some(store_dir) = pass::store_dir() {
+            completers.insert(0, Box::new(pass::PassCompleter::new(store_dir)));
+        }
+    }
+    let terraform_program = command_words
+        .first()
+        .filter(|p| config.terraform.programs.iter().any(|t| t == *p));
+    if let Some(program) = terraform_program {
+        match command_words[1..] {
+            ["workspace", "select" | "delete"] => completers.insert(
+                0,
+                Box::new(terraform::TerraformWorkspaceCompleter::new(
+                    program,
+                    config.subprocess.timeout(),
+                )),
+            ),
+            ["state", "show" | "mv" | "rm" | "list", ..] | ["taint" | "untaint", ..] => completers
+                .insert(
+                    0,
+                    Box::new(terraform::TerraformResourceCompleter::new(
+                        program,
+                        config.terraform.clone(),
+                    )),
+                ),
+            _ => {}
+        }
+    }
+    // Database objects are offered only for configured connections, as
+    // listing them connects to the servers.
+    let database_command = config
+        .database
+        .commands
+        .iter()
+        .any(|c| command_matches(command_words, c));
+    if database_command && !config.database.connections.is_empty() {
+        let completer = Box::new(database::DatabaseCompleter::new(config.database.clone()));
+        let after_database_option = command_words
+            .last()
+            .is_some_and(|w| database::DATABASE_OPTIONS.contains(w));
+        if after_database_option {
+            completers.insert(0, completer);
+        } else {
+            completers.push(completer);
+        }
+    }
+    let aws_kind = aws::value_kind(command_words, original_query)
+        .or_else(|| assigned_variable.and_then(aws::variable_kind));
+    if let Some(kind) = aws_kind {
+        completers.insert(
+            0,
+            Box::new(aws::AwsCompleter::new(
+                kind,
+                command_words,
+                config.aws.clone(),
+            )),
+        );
+    }
+    #[cfg(unix)]
+    if let Some(syntax) = signal::signal_syntax(command_words, original_query) {
+        completers.insert(0, Box::new(signal::SignalCompleter::new(syntax)));
+    }
+    // Numbers are completed for the commands of the forge clients, and
+    // after a `#` anywhere, e.g. in a commit message.
+    let with_hash = original_query.starts_with('#');
+    let item_types: Vec<forge::ItemType> = [
+        (
+            forge::ItemType::PullRequest,
+            &config.forge.pull_request_commands,
+        ),
+        (forge::ItemType::Issue, &config.forge.issue_commands),
     ]
+    .iter()
+    .filter(|(_, commands)| with_hash || commands.iter().any(|c| command_matches(command_words, c)))
+    .map(|(t, _)| *t)
+    .collect();
+    if with_hash || !item_types.is_empty() {
+        completers.insert(
+            0,
+            Box::new(forge::ForgeCompleter::new(
+                command,
+                item_types,
+                with_hash,
+                config.forge.clone(),
+            )),
+        );
+    }
+    if command_matches(command_words, "git worktree") {
+        completers.insert(
+            0,
+            Box::new(git::GitWorktreeCompleter::new(config.subprocess.timeout())),
+        );
+    }
+    if command_matches(command_words, "git submodule") {
+        completers.insert(
+            0,
+            Box::new(git::GitSubmoduleCompleter::new(config.subprocess.timeout())),
+        );
+    }
+    let cargo_item_kinds = cargo::item_kinds(command_words);
+    if !cargo_item_kinds.is_empty() {
+        completers.insert(
+            0,
+            Box::new(cargo::CargoCompleter::new(
+                cargo_item_kinds,
+                config.subprocess.timeout(),
+            )),
+        );
+    }
+    if let Some(item) = systemd::journal_item(command_words) {
+        completers.insert(
+            0,
+            Box::new(systemd::JournalCompleter::new(
+                item,
+                config.subprocess.timeout(),
+            )),
+        );
+    }
+    let device_sources = devices::device_sources(command_words);
+    if !device_sources.is_empty() {
+        completers.insert(
+            0,
+            Box::new(devices::DeviceCompleter::new(
+                device_sources,
+                config.subprocess.timeout(),
+            )),
+        );
+    }
+    if let Some(item) = wireless::network_item(command_words) {
+        completers.insert(
+            0,
+            Box::new(wireless::NetworkCompleter::new(
+                item,
+                config.subprocess.timeout(),
+            )),
+        );
+    }
+    if wireless::completes_bluetooth_device(command_words) {
+        completers.insert(
+            0,
+            Box::new(wireless::BluetoothCompleter::new(
+                config.subprocess.timeout(),
+            )),
+        );
+    }
+    if let Some(runner) = test_names::runner(command_words) {
+        completers.insert(
+            0,
+            Box::new(test_names::TestCompleter::new(runner, config.tests.clone())),
+        );
+    }
+    if let Some(runner) = tasks::task_runner(command_words) {
+        completers.insert(
+            0,
+            Box::new(tasks::TaskCompleter::new(
+                runner,
+                command_words,
+                &config.tasks,
+            )),
+        );
+    }
+    // Completers of the user come before the built-in ones, in the
+    // order of declaration.
+    for (i, c) in config
+        .completers
+        .iter()
+        .filter(|c| {
+            c.commands.is_empty() || c.commands.iter().any(|d| command_matches(command_words, d))
+        })
+        .enumerate()
+    {
+        completers.insert(
+            i,
+            Box::new(user::UserCompleter::new(
+                c.clone(),
+                config.subprocess.timeout(),
+            )),
+        );
+    }
+    // The history is passed only when the whole line is to be replaced.
+    if history::HistoryCompleter::is_available() {
+        completers.insert(0, Box::new(history::HistoryCompleter::new()));
+    }
+    completers
+}
+
+/// Returns the name of the shell completions are inserted into, from
+/// the `--shell` argument or the `SHELL` environment variable.
+fn get_shell_name(argument: Option<&str>) -> Option<String> {
+    if let Some(a) = argument {
+        return Some(a.to_owned());
+    }
+    let shell_path = std::env::var("SHELL").ok()?;
+    std::path::Path::new(&shell_path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+}
+
+/// The exit status when a completion was chosen.
+const EXIT_CHOSEN: i32 = 0;
+
+/// The exit status when the choice was cancelled; the command line is
+/// printed nevertheless, as cancelling may change it.
+const EXIT_CANCELLED: i32 = 1;
+
+/// The exit status when an error occurred; nothing is printed to the
+/// standard output then.
+const EXIT_ERROR: i32 = 2;
+
+/// The command line with the completion inserted.
+struct CompletedLine {
+    line: String,
+
+    /// The byte index of the cursor within `line`.
+    point: usize,
+
+    /// Indicates if the user cancelled the choice.
+    cancelled: bool,
+}
+
+impl CompletedLine {
+    /// Formats the line for the shell: the point and the line, each
+    /// followed by the delimiter, which is a newline or a NUL.
+    ///
+    /// The line may contain newlines itself, so with a newline delimiter
+    /// it spans everything after the first newline but the last one.
+    fn format(&self, delimiter: char) -> String {
+        format!("{}{}{}{}", self.point, delimiter, self.line, delimiter)
+    }
+}
+
+#[test]
+fn test_completed_line_format() {
+    let completed = CompletedLine {
+        line: "ls src/\necho".to_owned(),
+        point: 7,
+        cancelled: false,
+    };
+    assert_eq!(completed.format('\n'), "7\nls src/\necho\n");
+    assert_eq!(completed.format('\0'), "7\0ls src/\necho\0");
 }
 
-fn get_completion_result(line: String, point: usize) -> io::Result<(String, usize)> {
-    let (query_start, query_end) = get_initial_query_range(&line, point);
-    let original_query = (&line[query_start..query_end]).to_string();
+/// Lets the user choose a completion for the query in `line` at
+/// `point`, or for the given query if there is one, and returns the
+/// line with the completion in place of the query.
+///
+/// With `replace_line`, the query is the whole line, e.g. to choose
+/// a command from the history, and the completion replaces the whole
+/// line without quoting.
+fn get_completion_result(
+    line: String,
+    point: usize,
+    query: Option<&str>,
+    replace_line: bool,
+    config: &config::Config,
+    quoting_style: quoting::QuotingStyle,
+) -> io::Result<CompletedLine> {
+    let mut quoting_style = quoting_style;
+    let mut assigned_variable = None;
+    let (query_start, query_end, original_query) = match query {
+        _ if replace_line => {
+            quoting_style = quoting::QuotingStyle::None;
+            (0, line.len(), query.unwrap_or(&line).to_owned())
+        }
+        Some(q) => {
+            let (start, end) = get_explicit_query_range(&line, point, q);
+            (start, end, q.to_owned())
+        }
+        None => match get_url_query_range(&line, point)
+            .or_else(|| get_expression_query_range(&line, point))
+        {
+            Some((start, end)) => (start, end, line[start..end].to_string()),
+            None => {
+                let word = get_initial_query(&line, point);
+                // The completion is quoted the way the user started to.
+                if word.opening_quote == Some('\'') && quoting_style != quoting::QuotingStyle::None
+                {
+                    quoting_style = quoting::QuotingStyle::SingleQuote;
+                }
+                match get_assignment(&line, &word) {
+                    Some((name, value_start)) => {
+                        let value = word.text[name.len() + 1..].to_owned();
+                        assigned_variable = Some(name);
+                        (value_start, word.end, value)
+                    }
+                    None => (word.start, word.end, word.text),
+                }
+            }
+        },
+    };
 
-    let completers = get_completers(&original_query);
-    let completion = ui::get_completion(&original_query, completers)?;
+    let command_words = get_command_words(&line, query_start);
+    let mut completers = get_completers(
+        &original_query,
+        &command_words,
+        assigned_variable.as_deref(),
+        config,
+    );
+    if config.ui.all_tab && completers.len() > 1 {
+        // Each completer fetches once, for both its own tab and the tab
+        // with all completions.
+        let mut sources: Vec<Box<dyn core::Completer>> = Vec::new();
+        completers = completers
+            .into_iter()
+            .map(|c| {
+                let mut views = shared::share(c, 2);
+                sources.push(Box::new(views.pop().unwrap()));
+                Box::new(views.pop().unwrap()) as Box<dyn core::Completer>
+            })
+            .collect();
+        completers.insert(0, Box::new(merged::MergedCompleter::new(sources)));
+    }
+    // Without a terminal, e.g. in scripts, the best match is taken.
+    let completion = if ui::backend::is_interactive() {
+        ui::get_completion(&original_query, completers, config)?
+    } else {
+        ui::best_match(&original_query, completers, config)?
+    };
+    let cancelled = completion.cancelled;
+    // A query which isn't in the line can't be restored in it.
+    let query_in_line = line[query_start..query_end] == original_query;
+    if cancelled && (config.ui.cancel == config::CancelBehavior::KeepLine || !query_in_line) {
+        return Result::Ok(CompletedLine {
+            line,
+            point,
+            cancelled,
+        });
+    }
+    let completion = quoting::quote_result(completion, quoting_style);
 
     let result_line = format!(
         "{}{}{}",
         &line[..query_start],
-        &completion,
+        &completion.text,
         &line[query_end..]
     );
-    return Result::Ok((result_line, query_start + completion.len()));
+    Result::Ok(CompletedLine {
+        line: result_line,
+        point: query_start + completion.cursor_offset,
+        cancelled,
+    })
 }
 
 fn main() {
@@ -95,48 +972,297 @@ fn main() {
         .version("0.1.0")
         .author("Sławek Rudnicki <slawek.rudnicki@gmail.com>")
         .about("Extensible interactive completion for *nix shells")
+        .setting(clap::AppSettings::SubcommandsNegateReqs)
+        .subcommand(
+            clap::SubCommand::with_name("keys")
+                .about("Print the names of pressed keys, for use in key bindings"),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("init")
+                .about("Print the script integrating completers with SHELL")
+                .arg(
+                    clap::Arg::with_name("SHELL")
+                        .required(true)
+                        .possible_values(init::SHELLS),
+                ),
+        )
+        .subcommand(clap::SubCommand::with_name("daemon").about(
+            "Keep directory listings and outputs of git and zoxide cached for faster sessions",
+        ))
+        .subcommand(
+            clap::SubCommand::with_name("server")
+                .about("Let an editor drive the chooser through the Unix socket at SOCKET")
+                .arg(clap::Arg::with_name("SOCKET").required(true)),
+        )
+        .subcommand(
+            clap::SubCommand::with_name("doctor")
+                .about("Check the environment and print diagnostics of any problems"),
+        )
         .arg(
             clap::Arg::with_name("point")
                 .short("p")
                 .long("point")
                 .value_name("X") // TODO
-                .help("Current position of input point within CURRENT_LINE")
-                .required(true)
+                .help("Current position of input point within CURRENT_LINE, in bytes")
+                .required_unless("capabilities")
                 .takes_value(true),
         )
+        .arg(
+            clap::Arg::with_name("point-unit")
+                .long("point-unit")
+                .value_name("UNIT")
+                .help("Count the point in 'bytes' (the default) or in 'chars', as zsh does")
+                .takes_value(true)
+                .possible_values(&["bytes", "chars"]),
+        )
         .arg(
             clap::Arg::with_name("CURRENT_LINE")
                 .help("The current input line")
-                .required(true)
+                .required_unless("capabilities")
                 .index(1),
         )
+        .arg(
+            clap::Arg::with_name("query")
+                .long("query")
+                .value_name("STRING")
+                .help("Search for STRING instead of the word at the point in CURRENT_LINE")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("replace-line")
+                .long("replace-line")
+                .help("Search for the whole CURRENT_LINE and replace it with the completion"),
+        )
+        .arg(
+            clap::Arg::with_name("shell")
+                .long("shell")
+                .value_name("NAME")
+                .help("The shell the completion is inserted into, for quoting the result")
+                .takes_value(true),
+        )
+        .arg(
+            clap::Arg::with_name("dirs-only")
+                .long("dirs-only")
+                .help("Complete only directories in the file system completer"),
+        )
+        .arg(
+            clap::Arg::with_name("fs-depth")
+                .long("fs-depth")
+                .value_name("DEPTH")
+                .help("Search subdirectories up to DEPTH levels deep, or 'unlimited'")
+                .takes_value(true)
+                .validator(|d| config::parse_depth(&d).map(|_| ())),
+        )
+        .arg(
+            clap::Arg::with_name("height")
+                .long("height")
+                .value_name("HEIGHT")
+                .help("Show HEIGHT completions, or use HEIGHT% of the terminal, e.g. 40%")
+                .takes_value(true)
+                .validator(|h| config::parse_height(&h).map(|_| ())),
+        )
+        .arg(
+            clap::Arg::with_name("layout")
+                .long("layout")
+                .value_name("LAYOUT")
+                .help("Show the query at the top ('default') or at the bottom ('reverse')")
+                .takes_value(true)
+                .possible_values(config::Layout::NAMES),
+        )
+        .arg(
+            clap::Arg::with_name("select-1")
+                .long("select-1")
+                .help("Accept the only completion matching the query without showing the chooser"),
+        )
+        .arg(
+            clap::Arg::with_name("exit-0")
+                .long("exit-0")
+                .help("Exit without showing the chooser if no completion matches the query"),
+        )
+        .arg(
+            clap::Arg::with_name("print0")
+                .long("print0")
+                .help("Separate the point and the line in the output with NUL instead of newline"),
+        )
+        .arg(
+            clap::Arg::with_name("capabilities")
+                .long("capabilities")
+                .help("Print a JSON description of the version and the available completers"),
+        )
         .arg(
             clap::Arg::with_name("debug")
                 .long("debug")
-                .help("print debug information to /tmp/completers.txt"),
+                .help("Print debug information to the log file, /tmp/completers.log by default"),
         )
         .get_matches();
 
-    let log_level: log::LevelFilter;
-    if arguments.is_present("debug") {
-        log_level = log::LevelFilter::Debug;
+    // The configuration sets up the log, so failing to load it is
+    // logged afterwards.
+    let loaded_config = config::Config::load();
+    let log_config = match &loaded_config {
+        Result::Ok(c) => c.log.clone(),
+        Result::Err(_) => config::LogConfig::default(),
+    };
+    let log_level = if arguments.is_present("debug") {
+        std::cmp::max(log_config.level.filter(), log::LevelFilter::Debug)
     } else {
-        log_level = log::LevelFilter::Warn;
+        log_config.level.filter()
+    };
+    if log_level != log::LevelFilter::Off {
+        match fs::File::create(&log_config.path) {
+            Result::Ok(file) => {
+                let _ = simplelog::WriteLogger::init(log_level, simplelog::Config::default(), file);
+            }
+            Result::Err(e) => {
+                writeln!(
+                    &mut std::io::stderr(),
+                    "Failed to create the log file {}: {}",
+                    log_config.path,
+                    e
+                )
+                .expect("Failed to write error description");
+            }
+        }
     }
-    simplelog::WriteLogger::init(
-        log_level,
-        simplelog::Config::default(),
-        fs::File::create("/tmp/completers.log").unwrap(),
-    )
-    .unwrap();
 
-    let point: usize = arguments.value_of("point").unwrap().parse().unwrap();
+    let mut config = loaded_config.unwrap_or_else(|e| {
+        log::error!("Failed to load configuration: {}", e);
+        config::Config::default()
+    });
+    if arguments.is_present("dirs-only") {
+        config.fs.dirs_only = true;
+    }
+    if let Some(depth) = arguments.value_of("fs-depth") {
+        config.fs.max_depth = config::parse_depth(depth).unwrap();
+    }
+    if let Some(height) = arguments.value_of("height") {
+        config.ui.height = config::parse_height(height).unwrap();
+    }
+    if let Some(layout) = arguments.value_of("layout") {
+        config.ui.layout = config::Layout::from_name(layout).unwrap();
+    }
+    if arguments.is_present("select-1") {
+        config.ui.select_one = true;
+    }
+    if arguments.is_present("exit-0") {
+        config.ui.exit_zero = true;
+    }
+
+    if arguments.is_present("capabilities") {
+        println!("{:#}", capabilities::document(&config));
+        return;
+    }
+
+    if arguments.subcommand_matches("keys").is_some() {
+        let result = ui::backend::new(config.ui.backend.as_deref())
+            .and_then(|mut backend| ui::recorder::record_keys(&mut *backend));
+        if let Err(error) = result {
+            writeln!(&mut std::io::stderr(), "{}", error)
+                .expect("Failed to write error description");
+        }
+        return;
+    }
+
+    if let Some(init_arguments) = arguments.subcommand_matches("init") {
+        let program = std::env::current_exe()
+            .map(|p| p.to_string_lossy().into_owned())
+            .unwrap_or_else(|_| "completers".to_owned());
+        let shell = init_arguments.value_of("SHELL").unwrap();
+        print!("{}", init::script(shell, &program).unwrap());
+        return;
+    }
+
+    if arguments.subcommand_matches("daemon").is_some() {
+        #[cfg(unix)]
+        let result = daemon::serve(&config);
+        #[cfg(not(unix))]
+        let result: io::Result<()> = Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "the daemon is supported only on Unix",
+        ));
+        if let Err(error) = result {
+            writeln!(&mut std::io::stderr(), "{}", error)
+                .expect("Failed to write error description");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if let Some(server_arguments) = arguments.subcommand_matches("server") {
+        let socket_path = server_arguments.value_of("SOCKET").unwrap();
+        #[cfg(unix)]
+        let result = server::serve(std::path::Path::new(socket_path), &config);
+        #[cfg(not(unix))]
+        let result: io::Result<()> = Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("the server at {} is supported only on Unix", socket_path),
+        ));
+        if let Err(error) = result {
+            writeln!(&mut std::io::stderr(), "{}", error)
+                .expect("Failed to write error description");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    if arguments.subcommand_matches("doctor").is_some() {
+        match doctor::run(&config, &mut io::stdout(), io::stdout().is_terminal()) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(error) => {
+                writeln!(&mut std::io::stderr(), "{}", error)
+                    .expect("Failed to write error description");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    // The point is a byte index unless the shell counts characters, and
+    // the result point is counted the same way.
     let line = arguments.value_of("CURRENT_LINE").unwrap().to_string();
+    let point: usize = arguments.value_of("point").unwrap().parse().unwrap();
+    let in_chars = arguments.value_of("point-unit") == Some("chars");
+    let point = if in_chars {
+        byte_point(&line, point)
+    } else {
+        clamp_point(&line, point)
+    };
 
-    match get_completion_result(line, point) {
-        Ok((completion, point)) => writeln!(&mut std::io::stderr(), "{} {}", point, completion)
-            .expect("Failed to write result"),
-        Err(error) => writeln!(&mut std::io::stderr(), "{}", error)
-            .expect("Failed to write error description"),
+    let shell = get_shell_name(arguments.value_of("shell"));
+    let quoting_style = config.shell.quoting_style(shell.as_deref());
+
+    let delimiter = if arguments.is_present("print0") {
+        '\0'
+    } else {
+        '\n'
     };
+    let status = ui::backend::result_output().and_then(|mut output| {
+        let mut completed = get_completion_result(
+            line,
+            point,
+            arguments.value_of("query"),
+            arguments.is_present("replace-line"),
+            &config,
+            quoting_style,
+        )?;
+        if in_chars {
+            completed.point = char_point(&completed.line, completed.point);
+        }
+        write!(output, "{}", completed.format(delimiter))?;
+        output.flush()?;
+        Result::Ok(if completed.cancelled {
+            EXIT_CANCELLED
+        } else {
+            EXIT_CHOSEN
+        })
+    });
+    match status {
+        Ok(status) => std::process::exit(status),
+        Err(error) => {
+            writeln!(&mut std::io::stderr(), "{}", error)
+                .expect("Failed to write error description");
+            std::process::exit(EXIT_ERROR);
+        }
+    }
 }
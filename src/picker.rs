@@ -0,0 +1,118 @@
+//! Module with the public interface for embedding the chooser in
+//! other programs.
+//!
+//! ```no_run
+//! use completers::completers::git;
+//! use completers::picker::Picker;
+//!
+//! let result = Picker::new()
+//!     .completer(git::GitBranchCompleter::default())
+//!     .height(5)
+//!     .run()
+//!     .unwrap();
+//! println!("{}", result.text);
+//! ```
+
+use std::io;
+
+use crate::config;
+use crate::core;
+use crate::ui;
+
+/// A builder configuring and running the chooser.
+///
+/// Each completer is shown in a separate tab, in the order in which
+/// the completers are added.
+pub struct Picker {
+    completers: Vec<Box<dyn core::Completer>>,
+    query: String,
+    height: Option<usize>,
+    config: config::Config,
+}
+
+impl Picker {
+    /// Creates a picker with no completers, an empty initial query
+    /// and the default configuration.
+    pub fn new() -> Picker {
+        Picker {
+            completers: vec![],
+            query: String::new(),
+            height: None,
+            config: config::Config::default(),
+        }
+    }
+
+    /// Adds a completer.
+    pub fn completer<C: core::Completer + 'static>(mut self, completer: C) -> Picker {
+        self.completers.push(Box::new(completer));
+        self
+    }
+
+    /// Adds a collection of boxed completers.
+    pub fn completers<I>(mut self, completers: I) -> Picker
+    where
+        I: IntoIterator<Item = Box<dyn core::Completer>>,
+    {
+        self.completers.extend(completers);
+        self
+    }
+
+    /// Sets the query the chooser starts with.
+    pub fn query(mut self, query: &str) -> Picker {
+        self.query = query.to_owned();
+        self
+    }
+
+    /// Sets the number of completions displayed at once, overriding the
+    /// height set in the configuration.
+    pub fn height(mut self, height: usize) -> Picker {
+        self.height = Some(height);
+        self
+    }
+
+    /// Sets the configuration, e.g. one loaded with
+    /// `config::Config::load`.
+    pub fn config(mut self, config: config::Config) -> Picker {
+        self.config = config;
+        self
+    }
+
+    /// Runs the chooser in the terminal and returns the completion
+    /// chosen by the user.
+    ///
+    /// If the user cancels the choice, the initial query is returned.
+    pub fn run(self) -> io::Result<core::CompletionResult> {
+        if self.completers.is_empty() {
+            return Result::Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "no completers were added to the picker",
+            ));
+        }
+        if self.height == Some(0) {
+            return Result::Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the height of the picker must be positive",
+            ));
+        }
+        ui::choose(&self.query, self.completers, &self.config, self.height)
+    }
+}
+
+impl Default for Picker {
+    fn default() -> Self {
+        Picker::new()
+    }
+}
+
+#[test]
+fn test_picker_validation() {
+    let error = Picker::new().run().unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    let error = Picker::new()
+        .completer(crate::completers::git::GitBranchCompleter::default())
+        .query("foo")
+        .height(0)
+        .run()
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+}
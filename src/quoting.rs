@@ -0,0 +1,214 @@
+//! Module quoting completion results, so that characters special to
+//! the shell, such as spaces or globs, are taken literally when the
+//! result is inserted into the command line.
+
+use serde::Deserialize;
+
+use crate::core;
+
+/// The way of quoting special characters in results.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(rename_all = "kebab-case")]
+pub enum QuotingStyle {
+    /// Special characters are preceded by a backslash, e.g.
+    /// `My\ File\ \(1\).txt`. Newlines are written as `$'\n'`.
+    Backslash,
+
+    /// Results with special characters are enclosed in single quotes,
+    /// e.g. `'My File (1).txt'`.
+    SingleQuote,
+
+    /// Results are inserted as they are.
+    None,
+}
+
+/// Characters which have a special meaning to the shell anywhere
+/// within a word.
+const SPECIAL_CHARS: &[char] = &[
+    ' ', '\t', '\n', '\'', '"', '\\', '$', '`', '!', '&', '|', ';', '(', ')', '<', '>', '*', '?',
+    '[', ']', '{', '}',
+];
+
+/// Characters which have a special meaning to the shell at the start
+/// of a word.
+const SPECIAL_START_CHARS: &[char] = &['~', '#'];
+
+fn needs_quoting(text: &str) -> bool {
+    text.contains(SPECIAL_CHARS) || text.starts_with(SPECIAL_START_CHARS)
+}
+
+/// Quotes `text` with the given style, if it contains any special
+/// characters.
+pub fn quote(text: &str, style: QuotingStyle) -> String {
+    if style == QuotingStyle::None || !needs_quoting(text) {
+        return text.to_owned();
+    }
+    let mut quoted = String::with_capacity(text.len() + 2);
+    match style {
+        QuotingStyle::Backslash => {
+            if text.starts_with(SPECIAL_START_CHARS) {
+                quoted.push('\\');
+            }
+            for c in text.chars() {
+                if c == '\n' {
+                    quoted.push_str("$'\\n'");
+                    continue;
+                }
+                if SPECIAL_CHARS.contains(&c) {
+                    quoted.push('\\');
+                }
+                quoted.push(c);
+            }
+        }
+        QuotingStyle::SingleQuote => {
+            quoted.push('\'');
+            quoted.push_str(&text.replace('\'', "'\\''"));
+            quoted.push('\'');
+        }
+        QuotingStyle::None => unreachable!(),
+    }
+    quoted
+}
+
+/// A word of a command line, as split by the shell.
+#[derive(Debug, PartialEq)]
+pub struct Word {
+    /// The byte index of the start of the word within the line,
+    /// including any quotes.
+    pub start: usize,
+
+    /// The byte index of the end of the word within the line.
+    pub end: usize,
+
+    /// The text of the word without quotes and escapes.
+    pub text: String,
+
+    /// The quote the word starts with, e.g. `'` in `'My Fi`, if any.
+    pub opening_quote: Option<char>,
+}
+
+/// Returns the word of `line` at the byte index `point`, splitting the
+/// line at the given boundaries outside quotes. Quotes may be left
+/// open, as the word is usually still being typed.
+pub fn word_at(line: &str, point: usize, boundaries: &[char]) -> Word {
+    let mut start = 0;
+    let mut text = String::new();
+    let mut opening_quote = None;
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match quote {
+            Some(q) if c == q => quote = None,
+            // Within double quotes, backslashes escape only characters
+            // which are special there.
+            Some('"') if c == '\\' && chars.peek().is_some_and(|(_, n)| "\"\\$`".contains(*n)) => {
+                text.extend(chars.next().map(|(_, n)| n));
+            }
+            Some(_) => text.push(c),
+            // A backslash before a newline continues the line.
+            None if c == '\\' => text.extend(chars.next().map(|(_, n)| n).filter(|n| *n != '\n')),
+            None if c == '\'' || c == '"' => {
+                if i == start {
+                    opening_quote = Some(c);
+                }
+                quote = Some(c);
+            }
+            None if boundaries.contains(&c) => {
+                if i >= point {
+                    return Word {
+                        start,
+                        end: i,
+                        text,
+                        opening_quote,
+                    };
+                }
+                start = i + c.len_utf8();
+                text.clear();
+                opening_quote = None;
+            }
+            None => text.push(c),
+        }
+    }
+    Word {
+        start,
+        end: line.len(),
+        text,
+        opening_quote,
+    }
+}
+
+#[test]
+fn test_word_at() {
+    let boundaries = &[' ', '(', ')'];
+    let word = |line, point| {
+        let w = word_at(line, point, boundaries);
+        (w.start, w.end, w.text, w.opening_quote)
+    };
+    assert_eq!(word("", 0), (0, 0, "".to_owned(), None));
+    assert_eq!(word("ls foo", 4), (3, 6, "foo".to_owned(), None));
+    assert_eq!(word("ls foo", 2), (0, 2, "ls".to_owned(), None));
+    assert_eq!(
+        word("cp \"My Documents/fi", 19),
+        (3, 19, "My Documents/fi".to_owned(), Some('"'))
+    );
+    assert_eq!(
+        word("cp 'My Documents'/fi x", 20),
+        (3, 20, "My Documents/fi".to_owned(), Some('\''))
+    );
+    assert_eq!(word("cp My\\ Doc", 10), (3, 10, "My Doc".to_owned(), None));
+    assert_eq!(
+        word("echo \"a\\\"b\\c\"", 12),
+        (5, 13, "a\"b\\c".to_owned(), Some('"'))
+    );
+    assert_eq!(word("echo $(ls x", 11), (10, 11, "x".to_owned(), None));
+    assert_eq!(
+        word("ls \\\nsrc/ma", 11),
+        (3, 11, "src/ma".to_owned(), None)
+    );
+}
+
+/// Quotes the text of a completion result, if it is to be taken
+/// literally. The cursor is placed after the quoted text.
+pub fn quote_result(result: core::CompletionResult, style: QuotingStyle) -> core::CompletionResult {
+    if !result.literal {
+        return result;
+    }
+    let text = quote(&result.text, style);
+    core::CompletionResult {
+        cursor_offset: text.len(),
+        text,
+        literal: false,
+        cancelled: result.cancelled,
+    }
+}
+
+#[test]
+fn test_quote() {
+    use self::QuotingStyle::*;
+    assert_eq!(quote("src/main.rs", Backslash), "src/main.rs");
+    assert_eq!(quote("My File (1).txt", None), "My File (1).txt");
+    assert_eq!(
+        quote("My File (1).txt", Backslash),
+        "My\\ File\\ \\(1\\).txt"
+    );
+    assert_eq!(quote("My File (1).txt", SingleQuote), "'My File (1).txt'");
+    assert_eq!(quote("it's", SingleQuote), "'it'\\''s'");
+    assert_eq!(quote("it's", Backslash), "it\\'s");
+    assert_eq!(quote("a\nb", Backslash), "a$'\\n'b");
+    assert_eq!(quote("~x#", Backslash), "\\~x#");
+    assert_eq!(quote("*.rs", Backslash), "\\*.rs");
+}
+
+#[test]
+fn test_quote_result() {
+    let result = core::CompletionResult::from_result_string("a b");
+    let quoted = quote_result(result, QuotingStyle::Backslash);
+    assert_eq!(quoted.text, "a\\ b");
+    assert_eq!(quoted.cursor_offset, 4);
+
+    let result = core::CompletionResult::from_result_string("f \"{cursor}\"");
+    assert_eq!(
+        quote_result(result.clone(), QuotingStyle::SingleQuote),
+        result
+    );
+}
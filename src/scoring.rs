@@ -3,11 +3,13 @@
 use std::borrow::Borrow;
 
 use array2d::Array2D;
+use serde::Deserialize;
 
 /// Indicate if the given string matches the query.
 ///
 /// A match occurs when the query is a subsequence
-/// of the string, case-insensitive.
+/// of the string, case-insensitive. Only the string is
+/// lowercased, so the query is expected to be lowercase.
 pub fn subsequence_match(query: &str, string: &str) -> bool {
     let string = string.to_ascii_lowercase();
     let mut s: &str = string.as_ref();
@@ -15,10 +17,10 @@ pub fn subsequence_match(query: &str, string: &str) -> bool {
     for c in chars {
         match s.find(c) {
             None => return false,
-            Some(p) => s = &s[(p + 1)..],
+            Some(p) => s = &s[(p + c.len_utf8())..],
         };
     }
-    return true;
+    true
 }
 
 #[test]
@@ -33,10 +35,23 @@ fn test_subsequence_match() {
     assert!(!subsequence_match("foo", "fo"));
     assert!(!subsequence_match("bar", "bra"));
     assert!(!subsequence_match("baaaar", "bar"));
+    assert!(!subsequence_match("BAR", "bar"));
+    assert!(subsequence_match("żó", "łżół"));
+    assert!(subsequence_match("e\u{301}", "cafe\u{301}"));
+    assert!(subsequence_match("\0", "a\0b"));
+    assert!(!subsequence_match("żż", "łżół"));
 }
 
 pub type Score = u64;
 
+/// The maximum number of entries in the scoring table.
+///
+/// Scoring a query against a candidate needs a table with a cell for
+/// every pair of their characters. Pairs which would need a bigger table
+/// are given a flat score instead, so that a huge query or candidate
+/// cannot exhaust memory.
+const MAX_SCORING_CELLS: usize = 1 << 22;
+
 /// A single entry in the scoring table.
 ///
 /// See the description of the score() routine for details
@@ -84,11 +99,23 @@ fn test_word_start_indices() {
 ///
 /// This aims to represent the configuration of assigning scores
 /// which may favor word starts or consecutive characters.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+#[serde(default)]
 pub struct ScoringSettings {
     pub letter_match: Score,
     pub subsequent_bonus: Score,
     pub word_start_bonus: Score,
 }
+
+impl Default for ScoringSettings {
+    fn default() -> Self {
+        ScoringSettings {
+            letter_match: 1,
+            word_start_bonus: 2,
+            subsequent_bonus: 3,
+        }
+    }
+}
 /// An array to store the scores for prefixes of the
 /// query and the candidate string.
 ///
@@ -152,13 +179,13 @@ impl ScoringArray<'_> {
         query_chars: Vec<char>,
         word_start_indices: Vec<usize>,
         scoring_settings: &ScoringSettings,
-    ) -> ScoringArray {
+    ) -> ScoringArray<'_> {
         let query_len = query_chars.len();
         let candidate_len = candidate_chars.len();
         ScoringArray {
-            candidate_chars: candidate_chars,
-            query_chars: query_chars,
-            word_start_indices: word_start_indices,
+            candidate_chars,
+            query_chars,
+            word_start_indices,
             settings: scoring_settings,
             array: Array2D::filled_with(Default::default(), query_len, candidate_len),
         }
@@ -186,7 +213,7 @@ impl ScoringArray<'_> {
                 .get(query_index - 1, candidate_index - 1)
                 .unwrap();
             let take_prev_score = if prev.take > 0 {
-                prev.take + self.settings.subsequent_bonus
+                prev.take.saturating_add(self.settings.subsequent_bonus)
             } else {
                 0
             };
@@ -194,7 +221,9 @@ impl ScoringArray<'_> {
         } else {
             0
         };
-        score_from_prev + self.settings.letter_match + self.word_start_bonus(candidate_index)
+        score_from_prev
+            .saturating_add(self.settings.letter_match)
+            .saturating_add(self.word_start_bonus(candidate_index))
     }
 
     /// Compute the score if we do not take the current character
@@ -230,7 +259,7 @@ impl ScoringArray<'_> {
     /// Because the array entries represent scores for prefixes, the overall
     /// score is the score from the last array cell in the last row.
     pub fn score(&self) -> Score {
-        if self.query_chars.len() > 0 && self.candidate_chars.len() > 0 {
+        if !self.query_chars.is_empty() && !self.candidate_chars.is_empty() {
             let last_entry = self
                 .array
                 .get(self.array.num_rows() - 1, self.array.num_columns() - 1)
@@ -250,17 +279,18 @@ impl std::fmt::Display for ScoringArray<'_> {
             for j in 0..self.array.num_columns() {
                 write!(f, "{} ", self.array.get(i, j).unwrap())?;
             }
-            writeln!(f, "")?;
+            writeln!(f)?;
         }
         std::fmt::Result::Ok(())
     }
 }
 
 /// Return the score for the given query and candidate.
+///
+/// Queries and candidates too long to be scored with the full table
+/// (see `MAX_SCORING_CELLS`) get `letter_match` points for every
+/// character of the query, provided that the query matches.
 pub fn score(candidate: &str, query: &str, settings: &ScoringSettings) -> Score {
-    if query.len() > candidate.len() {
-        return 0;
-    }
     let mut candidate_chars: Vec<char> = Vec::with_capacity(candidate.len());
     candidate_chars.extend(candidate.chars().map(|c| c.to_ascii_lowercase()));
     let mut query_chars: Vec<char> = Vec::with_capacity(query.len());
@@ -270,6 +300,21 @@ pub fn score(candidate: &str, query: &str, settings: &ScoringSettings) -> Score
             .filter(|c| !c.is_whitespace())
             .map(|c| c.to_ascii_lowercase()),
     );
+    if query_chars.len() > candidate_chars.len() {
+        return 0;
+    }
+
+    let cells = query_chars.len().checked_mul(candidate_chars.len());
+    if cells.is_none_or(|c| c > MAX_SCORING_CELLS) {
+        let query: String = query_chars.iter().collect();
+        return if subsequence_match(&query, candidate) {
+            settings
+                .letter_match
+                .saturating_mul(query_chars.len() as Score)
+        } else {
+            0
+        };
+    }
 
     let word_starts = word_start_indices(candidate_chars.iter());
 
@@ -278,6 +323,53 @@ pub fn score(candidate: &str, query: &str, settings: &ScoringSettings) -> Score
     scoring_array.score()
 }
 
+/// The minimum number of candidates handled by a single thread in
+/// `score_many`. Smaller batches are scored on the calling thread.
+const SCORE_MANY_CHUNK_SIZE: usize = 2048;
+
+/// Match and score many candidates against the same query.
+///
+/// Returns a vector with an entry for each candidate, in the same
+/// order: the score if the candidate matches the query, None
+/// otherwise. Large batches are split into chunks which are scored
+/// in parallel.
+pub fn score_many<S: AsRef<str> + Sync>(
+    candidates: &[S],
+    query: &str,
+    settings: &ScoringSettings,
+) -> Vec<Option<Score>> {
+    let match_and_score = |candidates: &[S]| -> Vec<Option<Score>> {
+        candidates
+            .iter()
+            .map(|c| {
+                let c = c.as_ref();
+                if subsequence_match(query, c) {
+                    Some(score(c, query, settings))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    };
+
+    let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+    if threads < 2 || candidates.len() < 2 * SCORE_MANY_CHUNK_SIZE {
+        return match_and_score(candidates);
+    }
+    let chunk_size = std::cmp::max(SCORE_MANY_CHUNK_SIZE, candidates.len().div_ceil(threads));
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = candidates
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(move || match_and_score(chunk)))
+            .collect();
+        let mut result = Vec::with_capacity(candidates.len());
+        for handle in handles {
+            result.extend(handle.join().unwrap());
+        }
+        result
+    })
+}
+
 #[test]
 fn test_scoring_plain() {
     let settings = ScoringSettings {
@@ -335,3 +427,50 @@ fn test_scoring_subsequent_bonus() {
     assert_eq!(score("bar", "bar", &settings), 9);
     assert_eq!(score("foo/bar", "ob", &settings), 2);
 }
+
+#[test]
+fn test_scoring_adversarial() {
+    let settings = ScoringSettings::default();
+    assert_eq!(score("łżół", "żó", &settings), 5);
+    assert_eq!(score("ż", "żż", &settings), 0);
+    assert_eq!(score("ab", "a b c", &settings), 0);
+    assert!(score("a\0b", "\0", &settings) > 0);
+    assert!(score("cafe\u{301}", "e\u{301}", &settings) > 0);
+
+    let huge = "x".repeat(1 << 12);
+    assert_eq!(score(&huge, &huge, &settings), 1 << 12);
+    // Like the full table, the flat score ignores the case of the query.
+    assert_eq!(score(&huge, &huge.to_uppercase(), &settings), 1 << 12);
+    assert_eq!(score(&huge, "y", &settings), 0);
+
+    let saturating = ScoringSettings {
+        letter_match: Score::MAX,
+        subsequent_bonus: Score::MAX,
+        word_start_bonus: Score::MAX,
+    };
+    assert_eq!(score("foo bar", "fb", &saturating), Score::MAX);
+}
+
+#[test]
+fn test_score_many() {
+    let settings = ScoringSettings::default();
+    let candidates = ["foo", "bar", "foo/bar", ""];
+    assert_eq!(
+        score_many(&candidates, "fb", &settings),
+        vec![None, None, Some(score("foo/bar", "fb", &settings)), None]
+    );
+
+    let many: Vec<String> = (0..5 * SCORE_MANY_CHUNK_SIZE)
+        .map(|i| format!("file{}", i))
+        .collect();
+    let scores = score_many(&many, "f99", &settings);
+    assert_eq!(scores.len(), many.len());
+    for (c, s) in many.iter().zip(scores) {
+        let expected = if subsequence_match("f99", c) {
+            Some(score(c, "f99", &settings))
+        } else {
+            None
+        };
+        assert_eq!(s, expected);
+    }
+}
@@ -0,0 +1,192 @@
+//! Defines the server mode, in which an editor drives the chooser, e.g.
+//! a plugin of Neovim or Kakoune running it in a terminal split.
+//!
+//! The server shows the chooser in its terminal, reading keys from its
+//! standard input, so the editor talks to it over a Unix socket, whose
+//! path it chooses. An editor connects to the socket and sends
+//! requests, one record per line, with tab-separated fields escaped
+//! like those of the file system cache:
+//!
+//! - `candidates` followed by a record for each candidate and `.`
+//!   replaces the candidates,
+//! - `query<TAB><query>` sets the query the chooser starts with,
+//! - `choose` shows the chooser, and answers `selected<TAB><text>` with
+//!   the chosen candidate, or `cancelled<TAB><query>` if the user
+//!   cancelled the choice.
+//!
+//! The candidates and the query are kept between choices until the
+//! editor disconnects, and editors are served one at a time, since
+//! they share the terminal. Only editors run by the user can connect.
+
+use std::any;
+use std::io;
+use std::io::BufRead;
+use std::io::Write;
+use std::path;
+
+use log::debug;
+use log::warn;
+
+use crate::completers::fs_cache;
+use crate::config;
+use crate::core;
+use crate::socket;
+use crate::ui;
+
+/// A candidate sent by the editor.
+struct Candidate(String);
+
+impl core::Completion for Candidate {
+    fn result_string(&self) -> String {
+        self.0.clone()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// A completer of the candidates sent by the editor.
+struct CandidateCompleter {
+    candidates: Vec<String>,
+}
+
+impl core::Completer for CandidateCompleter {
+    fn name(&self) -> String {
+        "editor".to_owned()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        self.candidates
+            .drain(..)
+            .map(|c| Box::new(Candidate(c)) as core::CompletionBox)
+            .collect()
+    }
+}
+
+/// The state of the choices of an editor.
+#[derive(Default)]
+pub struct Session {
+    candidates: Vec<String>,
+    query: String,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    /// Answers the requests of an editor until it disconnects, letting
+    /// the user choose with the given function, which gets the query
+    /// and the completer of the candidates.
+    pub fn handle<R, W, F>(&mut self, mut reader: R, mut writer: W, mut choose: F) -> io::Result<()>
+    where
+        R: BufRead,
+        W: Write,
+        F: FnMut(&str, Box<dyn core::Completer>) -> io::Result<core::CompletionResult>,
+    {
+        loop {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                return Result::Ok(());
+            }
+            let fields: Vec<String> = line
+                .trim_end_matches('\n')
+                .split('\t')
+                .map(fs_cache::unescape)
+                .collect();
+            match fields.first().map(String::as_str) {
+                Some("candidates") if fields.len() == 1 => {
+                    self.candidates = socket::read_records(&mut reader)?
+                        .iter()
+                        .map(|r| fs_cache::unescape(r))
+                        .collect();
+                    continue;
+                }
+                Some("query") if fields.len() == 2 => {
+                    self.query = fields[1].clone();
+                    continue;
+                }
+                Some("choose") if fields.len() == 1 => {
+                    let completer = CandidateCompleter {
+                        candidates: self.candidates.clone(),
+                    };
+                    let result = choose(&self.query, Box::new(completer))?;
+                    let answer = if result.cancelled {
+                        "cancelled"
+                    } else {
+                        "selected"
+                    };
+                    writeln!(writer, "{}\t{}", answer, fs_cache::escape(&result.text))?;
+                }
+                _ => {
+                    return Result::Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("invalid request: {:?}", line),
+                    ))
+                }
+            }
+            writer.flush()?;
+        }
+    }
+}
+
+#[test]
+fn test_session() {
+    let requests = "candidates\nsrc/main.rs\nsrc/lib.rs\nwith\\ttab\n.\n\
+                    query\tlib\nchoose\nquery\t\nchoose\n";
+    let mut output = vec![];
+    let mut choices = vec![];
+    Session::new()
+        .handle(requests.as_bytes(), &mut output, |query, mut completer| {
+            let candidates: Vec<String> = completer
+                .fetch_completions()
+                .iter()
+                .map(|c| c.result_string())
+                .collect();
+            choices.push((query.to_owned(), candidates.len()));
+            Result::Ok(match candidates.iter().find(|c| c.contains(query)) {
+                Some(c) if !query.is_empty() => core::CompletionResult::from_result_string(c),
+                _ => core::CompletionResult::cancelled(query),
+            })
+        })
+        .unwrap();
+    assert_eq!(choices, vec![("lib".to_owned(), 3), ("".to_owned(), 3)]);
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "selected\tsrc/lib.rs\ncancelled\t\n"
+    );
+
+    let error = Session::new()
+        .handle("list\n".as_bytes(), io::sink(), |_, _| unreachable!())
+        .unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+/// Serves editors connecting to the socket at the given path until
+/// listening on it fails.
+pub fn serve(socket_path: &path::Path, config: &config::Config) -> io::Result<()> {
+    let listener = socket::bind(socket_path)?;
+    for stream in listener.incoming() {
+        let stream = match stream {
+            Result::Ok(s) => s,
+            Result::Err(e) => {
+                warn!("Failed to accept a connection: {}", e);
+                continue;
+            }
+        };
+        if let Result::Err(e) = socket::check_peer(&stream) {
+            warn!("{}", e);
+            continue;
+        }
+        let result = stream.try_clone().and_then(|writer| {
+            Session::new().handle(io::BufReader::new(stream), writer, |query, completer| {
+                ui::get_completion(query, vec![completer], config)
+            })
+        });
+        if let Result::Err(e) = result {
+            debug!("Editor disconnected: {}", e);
+        }
+    }
+    Result::Ok(())
+}
@@ -0,0 +1,165 @@
+//! Defines what the daemon and the server share about the Unix sockets
+//! they listen on: the socket is created accessible only to the user,
+//! and connections from processes of other users are refused, since
+//! the requests they answer run commands or read what the user types.
+//! Both read requests made of records, one per line.
+
+use std::fs;
+use std::io;
+use std::io::BufRead;
+use std::mem;
+use std::os::unix::fs::DirBuilderExt;
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixListener;
+use std::os::unix::net::UnixStream;
+use std::path;
+
+/// Makes sure that the directory exists and is private to the user,
+/// creating it if needed, so that the sockets in it can't be replaced
+/// by other users.
+pub fn create_private_dir(dir: &path::Path) -> io::Result<()> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Result::Ok(()) => return Result::Ok(()),
+        Result::Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+        Result::Err(e) => return Result::Err(e),
+    }
+    let metadata = fs::symlink_metadata(dir)?;
+    let uid = unsafe { libc::getuid() };
+    if !metadata.is_dir() || metadata.uid() != uid || metadata.mode() & 0o077 != 0 {
+        return Result::Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} is not a private directory of the user", dir.display()),
+        ));
+    }
+    Result::Ok(())
+}
+
+/// Removes the socket at the given path if nothing listens on it, e.g.
+/// when it is left behind by a process which was killed. Other files,
+/// and sockets in use, are kept, so that binding to them fails.
+fn remove_stale_socket(socket_path: &path::Path) {
+    let is_socket = fs::symlink_metadata(socket_path)
+        .map(|m| m.file_type().is_socket())
+        .unwrap_or(false);
+    if is_socket && UnixStream::connect(socket_path).is_err() {
+        let _ = fs::remove_file(socket_path);
+    }
+}
+
+/// Listens on a socket at the given path, which is accessible only to
+/// the user from the start. A stale socket at the path is replaced.
+pub fn bind(socket_path: &path::Path) -> io::Result<UnixListener> {
+    remove_stale_socket(socket_path);
+    // The permissions of the socket are set by the mask when it is
+    // created; changing them afterwards would leave a window in which
+    // other users could connect.
+    let mask = unsafe { libc::umask(0o077) };
+    let listener = UnixListener::bind(socket_path);
+    unsafe { libc::umask(mask) };
+    listener
+}
+
+/// Returns the id of the user of the process at the other end of the
+/// connection.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut credentials = libc::ucred {
+        pid: 0,
+        uid: 0,
+        gid: 0,
+    };
+    let mut length = mem::size_of::<libc::ucred>() as libc::socklen_t;
+    let result = unsafe {
+        libc::getsockopt(
+            stream.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_PEERCRED,
+            &mut credentials as *mut libc::ucred as *mut libc::c_void,
+            &mut length,
+        )
+    };
+    if result != 0 || length as usize != mem::size_of::<libc::ucred>() {
+        return Result::Err(io::Error::last_os_error());
+    }
+    Result::Ok(credentials.uid)
+}
+
+/// Returns the id of the user of the process at the other end of the
+/// connection.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+fn peer_uid(stream: &UnixStream) -> io::Result<libc::uid_t> {
+    let mut uid = 0;
+    let mut gid = 0;
+    if unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) } != 0 {
+        return Result::Err(io::Error::last_os_error());
+    }
+    Result::Ok(uid)
+}
+
+/// Fails unless the process at the other end of the connection belongs
+/// to the user.
+pub fn check_peer(stream: &UnixStream) -> io::Result<()> {
+    let uid = peer_uid(stream)?;
+    if uid != unsafe { libc::getuid() } {
+        return Result::Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("refused a connection of the user {}", uid),
+        ));
+    }
+    Result::Ok(())
+}
+
+/// Reads the records following a request, until the `.` record.
+pub fn read_records<R: BufRead>(reader: &mut R) -> io::Result<Vec<String>> {
+    let mut records = vec![];
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line)? == 0 {
+            return Result::Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let line = line.trim_end_matches('\n');
+        if line == "." {
+            return Result::Ok(records);
+        }
+        records.push(line.to_owned());
+    }
+}
+
+#[test]
+fn test_bind() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = std::env::temp_dir()
+        .join(format!("completers-socket-test-{}", std::process::id()))
+        .join("private");
+    fs::create_dir_all(dir.parent().unwrap()).unwrap();
+    create_private_dir(&dir).unwrap();
+    create_private_dir(&dir).unwrap();
+    assert_eq!(fs::metadata(&dir).unwrap().mode() & 0o777, 0o700);
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+    assert!(create_private_dir(&dir).is_err());
+    fs::set_permissions(&dir, fs::Permissions::from_mode(0o700)).unwrap();
+
+    let socket_path = dir.join("test.sock");
+    let listener = bind(&socket_path).unwrap();
+    assert_eq!(fs::metadata(&socket_path).unwrap().mode() & 0o077, 0);
+    let client = UnixStream::connect(&socket_path).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    check_peer(&server).unwrap();
+    check_peer(&client).unwrap();
+
+    // Sockets in use and other files are not replaced.
+    assert_eq!(
+        bind(&socket_path).unwrap_err().kind(),
+        io::ErrorKind::AddrInUse
+    );
+    drop(listener);
+    bind(&socket_path).unwrap();
+    let file_path = dir.join("file");
+    fs::write(&file_path, "").unwrap();
+    assert!(bind(&file_path).is_err());
+    assert!(file_path.is_file());
+    fs::remove_dir_all(dir.parent().unwrap()).unwrap();
+}
@@ -0,0 +1,81 @@
+//! Module dispatching the commands which the user runs on the selected
+//! completion, as configured with `core::CompletionAction`s.
+
+use std::io;
+use std::process;
+
+use crate::core;
+use crate::quoting;
+
+use super::keys;
+
+/// Returns the action bound to the given key which applies to the
+/// completion, if any. Actions with invalid key names never match.
+pub fn find<'a>(
+    actions: &'a [core::CompletionAction],
+    key: &keys::Key,
+    completion: &dyn core::Completion,
+) -> Option<&'a core::CompletionAction> {
+    actions
+        .iter()
+        .find(|a| keys::parse_key(&a.key).as_ref() == Some(key) && a.applies_to(completion.kind()))
+}
+
+/// Returns the command line of an action for the completion, with the
+/// result of the completion in place of `{}`.
+pub fn command_line(action: &core::CompletionAction, completion: &dyn core::Completion) -> String {
+    let result = core::CompletionResult::from_result_string(&completion.result_string());
+    action.command.replace(
+        "{}",
+        &quoting::quote(&result.text, quoting::QuotingStyle::SingleQuote),
+    )
+}
+
+/// Runs a command line with the shell, connected to the terminal.
+pub fn run(command_line: &str) -> io::Result<process::ExitStatus> {
+    process::Command::new("sh")
+        .args(["-c", command_line])
+        .status()
+}
+
+#[test]
+fn test_actions() {
+    use std::any;
+
+    struct FileCompletion(&'static str);
+    impl core::Completion for FileCompletion {
+        fn result_string(&self) -> String {
+            self.0.to_owned()
+        }
+        fn kind(&self) -> core::CompletionKind {
+            core::CompletionKind::File
+        }
+        fn as_any(&self) -> &dyn any::Any {
+            self
+        }
+    }
+
+    let config = crate::config::Config::parse(
+        "[[actions]]\n\
+         key = \"ctrl-d\"\n\
+         command = \"rm -- {}\"\n\
+         kinds = [\"file\"]\n\
+         confirm = true\n\
+         [[actions]]\n\
+         key = \"ctrl-d\"\n\
+         command = \"rmdir -- {}\"\n\
+         kinds = [\"directory\"]\n\
+         [[actions]]\n\
+         key = \"f4\"\n\
+         command = \"$EDITOR {}\"\n",
+    )
+    .unwrap();
+    let actions = &config.actions;
+    let completion = FileCompletion("My File.txt");
+    let action = find(actions, &keys::Key::Ctrl('d'), &completion).unwrap();
+    assert!(action.confirm);
+    assert_eq!(command_line(action, &completion), "rm -- 'My File.txt'");
+    let action = find(actions, &keys::Key::F(4), &completion).unwrap();
+    assert_eq!(command_line(action, &completion), "$EDITOR 'My File.txt'");
+    assert!(find(actions, &keys::Key::Ctrl('e'), &completion).is_none());
+}
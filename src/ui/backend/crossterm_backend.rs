@@ -0,0 +1,132 @@
+//! The terminal backend based on crossterm, which also works on
+//! Windows.
+
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::iter;
+
+use crossterm::cursor;
+use crossterm::event;
+use crossterm::event::KeyCode;
+use crossterm::event::KeyModifiers;
+use crossterm::terminal;
+
+use super::Backend;
+use super::Keys;
+use crate::ui::keys::Key;
+
+/// The path of the terminal device.
+#[cfg(windows)]
+const TERMINAL_PATH: &str = "CONOUT$";
+#[cfg(not(windows))]
+const TERMINAL_PATH: &str = "/dev/tty";
+
+pub struct CrosstermBackend {
+    raw_mode_enabled: bool,
+}
+
+impl CrosstermBackend {
+    pub fn new() -> CrosstermBackend {
+        CrosstermBackend {
+            raw_mode_enabled: false,
+        }
+    }
+}
+
+impl Default for CrosstermBackend {
+    fn default() -> Self {
+        CrosstermBackend::new()
+    }
+}
+
+fn convert_key(key: event::KeyEvent) -> Key {
+    let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+    let alt = key.modifiers.contains(KeyModifiers::ALT);
+    match key.code {
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Enter => Key::Char('\n'),
+        KeyCode::Tab => Key::Char('\t'),
+        KeyCode::BackTab => Key::BackTab,
+        KeyCode::Left if alt => Key::AltLeft,
+        KeyCode::Right if alt => Key::AltRight,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Up => Key::Up,
+        KeyCode::Down => Key::Down,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::PageUp => Key::PageUp,
+        KeyCode::PageDown => Key::PageDown,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Insert => Key::Insert,
+        KeyCode::F(n) => Key::F(n),
+        KeyCode::Char(c) if ctrl => Key::Ctrl(c.to_ascii_lowercase()),
+        KeyCode::Char(c) if alt => Key::Alt(c),
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Esc => Key::Esc,
+        _ => Key::Null,
+    }
+}
+
+#[test]
+fn test_convert_key() {
+    let key = |code, modifiers| convert_key(event::KeyEvent::new(code, modifiers));
+    assert_eq!(key(KeyCode::Enter, KeyModifiers::NONE), Key::Char('\n'));
+    assert_eq!(
+        key(KeyCode::Char('N'), KeyModifiers::CONTROL),
+        Key::Ctrl('n')
+    );
+    assert_eq!(key(KeyCode::Char('x'), KeyModifiers::ALT), Key::Alt('x'));
+    assert_eq!(key(KeyCode::Left, KeyModifiers::ALT), Key::AltLeft);
+    assert_eq!(key(KeyCode::Char('x'), KeyModifiers::NONE), Key::Char('x'));
+    assert_eq!(key(KeyCode::F(5), KeyModifiers::NONE), Key::F(5));
+    assert_eq!(key(KeyCode::BackTab, KeyModifiers::SHIFT), Key::BackTab);
+}
+
+impl Backend for CrosstermBackend {
+    /// Crossterm has no mode which only disables signals, so the
+    /// terminal is always put in raw mode.
+    fn prepare(&mut self, _raw: bool) -> io::Result<()> {
+        #[cfg(windows)]
+        crossterm::ansi_support::supports_ansi();
+        terminal::enable_raw_mode()?;
+        self.raw_mode_enabled = true;
+        Result::Ok(())
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        if self.raw_mode_enabled {
+            terminal::disable_raw_mode()?;
+            self.raw_mode_enabled = false;
+        }
+        Result::Ok(())
+    }
+
+    fn dimensions(&self) -> io::Result<(usize, usize)> {
+        let (cols, rows) = terminal::size()?;
+        Result::Ok((cols as usize, rows as usize))
+    }
+
+    fn cursor_position(&mut self) -> io::Result<(usize, usize)> {
+        let (col, row) = cursor::position()?;
+        Result::Ok((col as usize + 1, row as usize + 1))
+    }
+
+    fn output(&self) -> io::Result<Box<dyn Write + Send>> {
+        let term = fs::OpenOptions::new().write(true).open(TERMINAL_PATH)?;
+        Result::Ok(Box::new(term))
+    }
+
+    fn keys(&self) -> io::Result<Keys> {
+        Result::Ok(Box::new(iter::from_fn(|| loop {
+            match event::read() {
+                Result::Ok(event::Event::Key(key)) if key.kind != event::KeyEventKind::Release => {
+                    return Some(Result::Ok(convert_key(key)));
+                }
+                Result::Ok(_) => {}
+                Result::Err(e) => return Some(Result::Err(e)),
+            }
+        })))
+    }
+}
@@ -0,0 +1,123 @@
+//! Module abstracting the terminal used by the chooser.
+//!
+//! A backend prepares the terminal for the chooser, reports its size
+//! and the cursor position, and provides the output stream and the
+//! keys pressed by the user. The termion backend is the default on
+//! Unix; the crossterm backend is available with the `crossterm`
+//! feature, and is the only backend on Windows.
+
+#[cfg(any(feature = "crossterm", windows))]
+pub mod crossterm_backend;
+#[cfg(unix)]
+pub mod termion_backend;
+
+#[cfg(unix)]
+use std::fs;
+use std::io;
+use std::io::IsTerminal;
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::io::AsRawFd;
+#[cfg(unix)]
+use std::os::unix::io::FromRawFd;
+
+#[cfg(unix)]
+use libc;
+
+use super::keys::Key;
+
+/// An iterator over the keys pressed by the user.
+pub type Keys = Box<dyn Iterator<Item = io::Result<Key>> + Send>;
+
+/// A trait for terminal backends.
+pub trait Backend {
+    /// Prepares the terminal for reading keys.
+    ///
+    /// In raw mode, all processing of input is disabled; otherwise the
+    /// backend only makes sure that keys such as ctrl-c are delivered
+    /// as keys rather than signals.
+    fn prepare(&mut self, raw: bool) -> io::Result<()>;
+
+    /// Restores the state of the terminal from before `prepare`.
+    fn restore(&mut self) -> io::Result<()>;
+
+    /// Returns the size of the terminal, in the form of a tuple of
+    /// (columns, rows).
+    fn dimensions(&self) -> io::Result<(usize, usize)>;
+
+    /// Returns the cursor position within the terminal, in the form
+    /// of a tuple of (column, row), counted from 1.
+    fn cursor_position(&mut self) -> io::Result<(usize, usize)>;
+
+    /// Opens a stream writing to the terminal.
+    fn output(&self) -> io::Result<Box<dyn Write + Send>>;
+
+    /// Returns an iterator over the keys pressed by the user, which
+    /// may be moved to another thread.
+    fn keys(&self) -> io::Result<Keys>;
+}
+
+/// Creates the backend with the given name, or the default backend
+/// for the platform if no name is given.
+pub fn new(name: Option<&str>) -> io::Result<Box<dyn Backend>> {
+    match name {
+        #[cfg(unix)]
+        None | Some("termion") => Result::Ok(Box::new(termion_backend::TermionBackend::new())),
+        #[cfg(not(unix))]
+        None => Result::Ok(Box::new(crossterm_backend::CrosstermBackend::new())),
+        #[cfg(any(feature = "crossterm", windows))]
+        Some("crossterm") => Result::Ok(Box::new(crossterm_backend::CrosstermBackend::new())),
+        Some(n) => Result::Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unsupported terminal backend: {}", n),
+        )),
+    }
+}
+
+/// Indicates if the chooser can be shown: keys are read from the
+/// standard input, which must be a terminal, and the chooser is drawn
+/// on the controlling terminal. Neither is the case e.g. in scripts.
+pub fn is_interactive() -> bool {
+    io::stdin().is_terminal() && has_controlling_terminal()
+}
+
+#[cfg(unix)]
+fn has_controlling_terminal() -> bool {
+    termion::get_tty().is_ok()
+}
+
+#[cfg(not(unix))]
+fn has_controlling_terminal() -> bool {
+    true
+}
+
+/// Returns the stream which the result of the completion is written to:
+/// the standard output the program was started with.
+///
+/// The backends ask the terminal for the cursor position through the
+/// standard output, so if it is redirected, e.g. captured by the shell,
+/// it is replaced with the terminal, if there is one.
+#[cfg(unix)]
+pub fn result_output() -> io::Result<Box<dyn Write>> {
+    if io::stdout().is_terminal() {
+        return Result::Ok(Box::new(io::stdout()));
+    }
+    let tty = match termion::get_tty() {
+        Result::Ok(t) => t,
+        Result::Err(_) => return Result::Ok(Box::new(io::stdout())),
+    };
+    unsafe {
+        let output = libc::dup(libc::STDOUT_FILENO);
+        if output < 0 || libc::dup2(tty.as_raw_fd(), libc::STDOUT_FILENO) < 0 {
+            return Result::Err(io::Error::last_os_error());
+        }
+        Result::Ok(Box::new(fs::File::from_raw_fd(output)))
+    }
+}
+
+/// Returns the stream which the result of the completion is written to:
+/// the standard output.
+#[cfg(not(unix))]
+pub fn result_output() -> io::Result<Box<dyn Write>> {
+    Result::Ok(Box::new(io::stdout()))
+}
@@ -0,0 +1,157 @@
+//! The terminal backend based on termion and termios, used by default
+//! on Unix.
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::os;
+
+use libc;
+use termion;
+use termion::input::TermRead;
+use termios;
+
+use term_cursor;
+use term_size;
+
+use super::Backend;
+use super::Keys;
+use crate::ui::keys::Key;
+
+const INPUT_FD: os::unix::io::RawFd = 0;
+
+/// The time in milliseconds within which the rest of an escape sequence
+/// is expected after the escape character.
+const ESCAPE_TIMEOUT_MS: libc::c_int = 25;
+
+pub struct TermionBackend {
+    /// The terminal settings saved by `prepare`.
+    original_term_settings: Option<termios::Termios>,
+}
+
+impl TermionBackend {
+    pub fn new() -> TermionBackend {
+        TermionBackend {
+            original_term_settings: None,
+        }
+    }
+}
+
+impl Default for TermionBackend {
+    fn default() -> Self {
+        TermionBackend::new()
+    }
+}
+
+fn convert_key(key: termion::event::Key) -> Key {
+    use termion::event::Key as K;
+    match key {
+        K::Backspace => Key::Backspace,
+        K::Left => Key::Left,
+        K::Right => Key::Right,
+        K::Up => Key::Up,
+        K::Down => Key::Down,
+        K::Home => Key::Home,
+        K::End => Key::End,
+        K::PageUp => Key::PageUp,
+        K::PageDown => Key::PageDown,
+        K::Delete => Key::Delete,
+        K::Insert => Key::Insert,
+        K::F(n) => Key::F(n),
+        K::Char(c) => Key::Char(c),
+        K::Alt(c) => Key::Alt(c),
+        K::Ctrl(c) => Key::Ctrl(c),
+        K::Esc => Key::Esc,
+        K::BackTab => Key::BackTab,
+        _ => Key::Null,
+    }
+}
+
+/// Converts an event of the terminal, including sequences of keys which
+/// termion doesn't recognize, e.g. arrows typed with alt.
+fn convert_event(event: termion::event::Event) -> Key {
+    use termion::event::Event;
+    match event {
+        Event::Key(key) => convert_key(key),
+        Event::Unsupported(ref sequence) if sequence == b"\x1b[1;3D" => Key::AltLeft,
+        Event::Unsupported(ref sequence) if sequence == b"\x1b[1;3C" => Key::AltRight,
+        _ => Key::Null,
+    }
+}
+
+/// A reader of the standard input which, after reading a lone escape
+/// character, waits briefly for the rest of an escape sequence, e.g.
+/// of alt-x, so that termion parses it as a whole. Otherwise a sequence
+/// split between reads would be taken as esc followed by other keys.
+struct EscapeReader(io::Stdin);
+
+impl Read for EscapeReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.0.read(buf)?;
+        if n == 1 && buf[0] == b'\x1b' && buf.len() > 1 && input_pending(ESCAPE_TIMEOUT_MS) {
+            return Result::Ok(1 + self.0.read(&mut buf[1..])?);
+        }
+        Result::Ok(n)
+    }
+}
+
+/// Waits up to `timeout_ms` milliseconds for input on the terminal, and
+/// returns `true` if there is any.
+fn input_pending(timeout_ms: libc::c_int) -> bool {
+    let mut poll_fd = libc::pollfd {
+        fd: INPUT_FD,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    unsafe { libc::poll(&mut poll_fd, 1, timeout_ms) > 0 }
+}
+
+impl Backend for TermionBackend {
+    fn prepare(&mut self, raw: bool) -> io::Result<()> {
+        use termios::*;
+        let original_term_settings = Termios::from_fd(INPUT_FD)?;
+
+        let mut term_settings = original_term_settings;
+        if raw {
+            cfmakeraw(&mut term_settings);
+        } else {
+            term_settings.c_lflag &= !(ISIG);
+        }
+        tcsetattr(INPUT_FD, TCSANOW, &term_settings)?;
+        self.original_term_settings = Some(original_term_settings);
+        Result::Ok(())
+    }
+
+    fn restore(&mut self) -> io::Result<()> {
+        use termios::*;
+        if let Some(settings) = self.original_term_settings.take() {
+            tcdrain(INPUT_FD)?;
+            tcsetattr(INPUT_FD, TCSADRAIN, &settings)?;
+        }
+        Result::Ok(())
+    }
+
+    /// If STDOUT is not a tty, returns `io::Error`.
+    fn dimensions(&self) -> io::Result<(usize, usize)> {
+        term_size::dimensions().ok_or(io::Error::other("failed to fetch terminal dimensions"))
+    }
+
+    fn cursor_position(&mut self) -> io::Result<(usize, usize)> {
+        let (col, row) = term_cursor::get_pos().or(Result::Err(io::Error::other(
+            "failed to fetch cursor position",
+        )))?;
+        Result::Ok((col as usize, row as usize))
+    }
+
+    fn output(&self) -> io::Result<Box<dyn Write + Send>> {
+        Result::Ok(Box::new(termion::get_tty()?))
+    }
+
+    fn keys(&self) -> io::Result<Keys> {
+        Result::Ok(Box::new(
+            EscapeReader(io::stdin())
+                .events()
+                .map(|event| event.map(convert_event)),
+        ))
+    }
+}
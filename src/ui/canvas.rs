@@ -1,61 +1,321 @@
-///! Module representing a canvas for writing to and drawing on the terminal.
-///
-/// Using a canvas from this module is different from using ncurses in that
-/// a canvas does not fill the entire terminal screen (does not use the
-/// alternate screen feature), but allows modifying a portion of the terminal
-/// screen within the current window below the current command line.
-use std::fs;
+//! Module representing a canvas for writing to and drawing on the terminal.
+//!
+//! Using a canvas from this module is different from using ncurses in that
+//! a canvas does not fill the entire terminal screen (does not use the
+//! alternate screen feature), but allows modifying a portion of the terminal
+//! screen within the current window below the current command line.
+use std::cmp;
 use std::io;
 use std::io::Write;
 
-use termion;
+use crate::ansi;
+
+use super::backend;
+use super::text;
+
+/// The attributes of text set with SGR sequences, e.g. colors.
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Style {
+    /// The parameters of the SGR sequences setting the colors of text
+    /// and of the background, e.g. `38;5;8`, or None for the defaults.
+    fg: Option<String>,
+    bg: Option<String>,
+
+    bold: bool,
+    underline: bool,
+    invert: bool,
+}
+
+impl Style {
+    /// Applies the parameters of an SGR sequence, e.g. `1;31`.
+    fn apply(&mut self, parameters: &str) {
+        let parameters: Vec<&str> = parameters.split(';').collect();
+        let mut i = 0;
+        while i < parameters.len() {
+            match parameters[i] {
+                "" | "0" => *self = Style::default(),
+                "1" => self.bold = true,
+                "22" => self.bold = false,
+                "4" => self.underline = true,
+                "24" => self.underline = false,
+                "7" => self.invert = true,
+                "27" => self.invert = false,
+                "39" => self.fg = None,
+                "49" => self.bg = None,
+                p @ ("38" | "48") => {
+                    // Extended colors take the parameters which follow:
+                    // `5;n` for indexed ones and `2;r;g;b` for RGB ones.
+                    let length = match parameters.get(i + 1) {
+                        Some(&"5") => 3,
+                        Some(&"2") => 5,
+                        _ => 1,
+                    };
+                    let end = cmp::min(i + length, parameters.len());
+                    let color = Some(parameters[i..end].join(";"));
+                    if p == "38" {
+                        self.fg = color;
+                    } else {
+                        self.bg = color;
+                    }
+                    i = end - 1;
+                }
+                p => match p.parse::<u8>() {
+                    Result::Ok(30..=37) | Result::Ok(90..=97) => self.fg = Some(p.to_owned()),
+                    Result::Ok(40..=47) | Result::Ok(100..=107) => self.bg = Some(p.to_owned()),
+                    _ => {}
+                },
+            }
+            i += 1;
+        }
+    }
+
+    /// Returns the sequence switching from any style to this one.
+    fn sequence(&self) -> String {
+        let mut parameters = vec!["0"];
+        if self.bold {
+            parameters.push("1");
+        }
+        if self.underline {
+            parameters.push("4");
+        }
+        if self.invert {
+            parameters.push("7");
+        }
+        parameters.extend(self.fg.as_deref());
+        parameters.extend(self.bg.as_deref());
+        format!("\x1b[{}m", parameters.join(";"))
+    }
+}
+
+/// A cell of the terminal, with a character and its style.
+#[derive(Clone, Debug, PartialEq)]
+struct Cell {
+    ch: char,
+
+    /// The number of columns the character takes: 2 for wide
+    /// characters, whose second column is a cell with a width of 0.
+    width: usize,
+
+    style: Style,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            ch: ' ',
+            width: 1,
+            style: Style::default(),
+        }
+    }
+}
+
+/// The contents of the cells of the canvas.
+#[derive(Clone, Debug, PartialEq)]
+struct Buffer {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Buffer {
+    fn new(width: usize, height: usize) -> Buffer {
+        Buffer {
+            width,
+            height,
+            cells: vec![Cell::default(); width * height],
+        }
+    }
+
+    fn clear(&mut self) {
+        for c in &mut self.cells {
+            *c = Cell::default();
+        }
+    }
 
-use super::terminal;
+    /// Puts text in the cells of a row, starting at `col`, in the style
+    /// which SGR sequences in the text modify. Text beyond the right
+    /// edge is cut off, and other escape sequences are dropped. Returns
+    /// the column following the text.
+    fn print(&mut self, row: usize, mut col: usize, text: &str, style: &mut Style) -> usize {
+        for part in text::parts(text) {
+            let (c, width) = match part {
+                text::Part::Escape(e) => {
+                    if let Some(parameters) =
+                        e.strip_prefix("\x1b[").and_then(|e| e.strip_suffix('m'))
+                    {
+                        style.apply(parameters);
+                    }
+                    continue;
+                }
+                text::Part::Char(_, 0) => continue,
+                text::Part::Char(c, w) => (c, w),
+            };
+            if row >= self.height || col + width > self.width {
+                col += width;
+                continue;
+            }
+            let index = row * self.width + col;
+            // Wide characters which are partly covered are erased.
+            if self.cells[index].width == 0 {
+                self.cells[index - 1] = Cell::default();
+            }
+            let end = index + width;
+            if end < (row + 1) * self.width && self.cells[end].width == 0 {
+                self.cells[end] = Cell::default();
+            }
+            self.cells[index] = Cell {
+                ch: c,
+                width,
+                style: style.clone(),
+            };
+            if width == 2 {
+                self.cells[index + 1] = Cell {
+                    ch: ' ',
+                    width: 0,
+                    style: style.clone(),
+                };
+            }
+            col += width;
+        }
+        col
+    }
 
+    /// Returns the sequences drawing the cells which differ from those
+    /// of the previous buffer, with the cells of the buffer at `origin`
+    /// of the terminal (the row and the column, counted from 0).
+    fn changes(&self, previous: &Buffer, origin: (usize, usize)) -> String {
+        let mut output = String::new();
+        let mut cursor = None;
+        let mut style = Style::default();
+        for row in 0..self.height {
+            let cells = &self.cells[row * self.width..(row + 1) * self.width];
+            let previous_cells = &previous.cells[row * self.width..(row + 1) * self.width];
+            let mut changed: Vec<bool> = cells
+                .iter()
+                .zip(previous_cells)
+                .map(|(c, p)| c != p)
+                .collect();
+            // Both columns of wide characters are drawn together, as
+            // drawing over a half of one erases the other half.
+            for col in 0..self.width {
+                let wide = |c: &Cell| c.width == 2;
+                if wide(&cells[col]) || wide(&previous_cells[col]) {
+                    let pair_changed = changed[col] || changed.get(col + 1) == Some(&true);
+                    changed[col] = pair_changed;
+                    if let Some(c) = changed.get_mut(col + 1) {
+                        *c = pair_changed;
+                    }
+                }
+            }
+            for col in 0..self.width {
+                let cell = &cells[col];
+                if !changed[col] || cell.width == 0 {
+                    continue;
+                }
+                if cursor != Some((row, col)) {
+                    output.push_str(
+                        &ansi::Goto((origin.1 + col + 1) as u16, (origin.0 + row + 1) as u16)
+                            .to_string(),
+                    );
+                }
+                if cell.style != style {
+                    output.push_str(&cell.style.sequence());
+                    style = cell.style.clone();
+                }
+                output.push(cell.ch);
+                cursor = Some((row, col + cell.width));
+            }
+        }
+        if style != Style::default() {
+            output.push_str(ansi::RESET);
+        }
+        output
+    }
+}
+
+/// A part of the terminal which the chooser is drawn on.
+///
+/// Text is drawn with `print` in a buffer, and `present` writes to the
+/// terminal only the cells which changed since it was called last, so
+/// that redrawing after each key doesn't flicker. Writing to the canvas
+/// writes to the terminal directly, e.g. sequences setting the title.
 pub struct TermCanvas {
-    term: fs::File,
+    term: Box<dyn Write + Send>,
     start_row: usize,
     start_col: usize,
     width: usize,
     height: usize,
+
+    /// The contents being drawn, and those shown in the terminal.
+    buffer: Buffer,
+    shown: Buffer,
+
+    /// The position where text is printed, and the style of the text.
+    cursor: (usize, usize),
+    style: Style,
 }
 
 impl TermCanvas {
-    pub fn new(mut term: fs::File, height: usize) -> io::Result<TermCanvas> {
-        let (term_cols, _term_rows) = terminal::get_dimensions()?;
+    pub fn new(backend: &mut dyn backend::Backend, height: usize) -> io::Result<TermCanvas> {
+        let mut term = backend.output()?;
+        let (term_cols, _term_rows) = backend.dimensions()?;
         for _ in 0..height {
-            term.write(b"\n")?;
+            term.write_all(b"\n")?;
         }
-        write!(term, "{}", termion::cursor::Up(height as u16))?;
-        let (_, start_row) = terminal::get_cursor_position()?;
+        write!(term, "{}", ansi::Up(height as u16))?;
+        term.flush()?;
+        let (_, start_row) = backend.cursor_position()?;
         Result::Ok(TermCanvas {
-            term: term,
-            start_row: start_row as usize - 1,
+            term,
+            start_row: start_row - 1,
             start_col: 0,
             width: term_cols,
-            height: height,
+            height,
+            buffer: Buffer::new(term_cols, height),
+            shown: Buffer::new(term_cols, height),
+            cursor: (0, 0),
+            style: Style::default(),
         })
     }
 
+    /// Moves the position where text is printed, and where the cursor
+    /// is placed once the canvas is presented.
     pub fn move_to(&mut self, row: usize, col: usize) -> io::Result<()> {
-        // TODO Add bounds checking.
-        write!(
-            self.term,
-            "{}",
-            termion::cursor::Goto(
-                (col + self.start_col + 1) as u16,
-                (row + self.start_row + 1) as u16
-            )
-        )?;
+        self.cursor = (row, col);
+        Result::Ok(())
+    }
+
+    /// Prints text at the current position, which may contain SGR
+    /// sequences styling it.
+    pub fn print(&mut self, text: &str) -> io::Result<()> {
+        let (row, col) = self.cursor;
+        let col = self.buffer.print(row, col, text, &mut self.style);
+        self.cursor = (row, col);
         Result::Ok(())
     }
 
+    /// Clears the contents being drawn.
     pub fn clear(&mut self) -> io::Result<()> {
-        for i in 0..self.height {
-            self.move_to(i, 0)?;
-            write!(self.term, "{}", termion::clear::CurrentLine)?;
-        }
-        self.move_to(0, 0)?;
+        self.buffer.clear();
+        self.style = Style::default();
+        self.move_to(0, 0)
+    }
+
+    /// Shows the contents drawn since the last call in the terminal,
+    /// placing the cursor at the current position.
+    pub fn present(&mut self) -> io::Result<()> {
+        let origin = (self.start_row, self.start_col);
+        let mut output = self.buffer.changes(&self.shown, origin);
+        output.push_str(
+            &ansi::Goto(
+                (self.cursor.1 + self.start_col + 1) as u16,
+                (self.cursor.0 + self.start_row + 1) as u16,
+            )
+            .to_string(),
+        );
+        self.term.write_all(output.as_bytes())?;
+        self.term.flush()?;
+        self.shown.clone_from(&self.buffer);
         Result::Ok(())
     }
 
@@ -75,7 +335,7 @@ impl TermCanvas {
     ) -> io::Result<()> {
         for i in 0..length {
             self.move_to(row, start_col + i)?;
-            write!(self, "\u{2500}")?;
+            self.print("\u{2500}")?;
         }
         Result::Ok(())
     }
@@ -83,7 +343,7 @@ impl TermCanvas {
     pub fn vertical_line(&mut self, start_row: usize, col: usize, length: usize) -> io::Result<()> {
         for i in 0..length {
             self.move_to(start_row + i, col)?;
-            write!(self, "\u{2502}")?;
+            self.print("\u{2502}")?;
         }
         Result::Ok(())
     }
@@ -96,13 +356,13 @@ impl TermCanvas {
         end_col: usize,
     ) -> io::Result<()> {
         self.move_to(start_row, start_col)?;
-        write!(self, "\u{250C}")?;
+        self.print("\u{250C}")?;
         self.move_to(start_row, end_col)?;
-        write!(self, "\u{2510}")?;
+        self.print("\u{2510}")?;
         self.move_to(end_row, start_col)?;
-        write!(self, "\u{2514}")?;
+        self.print("\u{2514}")?;
         self.move_to(end_row, end_col)?;
-        write!(self, "\u{2518}")?;
+        self.print("\u{2518}")?;
         self.horizontal_line(start_row, start_col + 1, end_col - start_col - 1)?;
         self.horizontal_line(end_row, start_col + 1, end_col - start_col - 1)?;
         self.vertical_line(start_row + 1, start_col, end_row - start_row - 1)?;
@@ -120,3 +380,49 @@ impl Write for TermCanvas {
         self.term.flush()
     }
 }
+
+#[test]
+fn test_style() {
+    let mut style = Style::default();
+    style.apply("1;38;5;8;44");
+    assert_eq!(
+        style,
+        Style {
+            fg: Some("38;5;8".to_owned()),
+            bg: Some("44".to_owned()),
+            bold: true,
+            ..Style::default()
+        }
+    );
+    assert_eq!(style.sequence(), "\x1b[0;1;38;5;8;44m");
+    style.apply("22;39");
+    style.apply("38;2;1;2;3");
+    assert_eq!(style.sequence(), "\x1b[0;38;2;1;2;3;44m");
+    style.apply("");
+    assert_eq!(style, Style::default());
+}
+
+#[test]
+fn test_buffer_changes() {
+    let mut previous = Buffer::new(6, 2);
+    let mut style = Style::default();
+    previous.print(0, 0, "abc", &mut style);
+    previous.print(1, 0, "\x1b[31mx\x1b[39my", &mut style);
+
+    let mut buffer = previous.clone();
+    assert_eq!(buffer.changes(&previous, (0, 0)), "");
+
+    // Only the changed cells are drawn.
+    buffer.print(0, 1, "B", &mut style);
+    assert_eq!(buffer.changes(&previous, (4, 0)), "\x1b[5;2HB");
+
+    buffer.clear();
+    assert_eq!(buffer.print(0, 4, "日本", &mut style), 8);
+    assert_eq!(buffer.print(1, 0, "\x1b[31mx\x1b[39my", &mut style), 2);
+    assert_eq!(buffer.changes(&previous, (0, 0)), "\x1b[1;1H   \x1b[1;5H日");
+
+    // Wide characters are redrawn whole, and erased when covered.
+    previous = buffer.clone();
+    buffer.print(0, 5, "x", &mut style);
+    assert_eq!(buffer.changes(&previous, (0, 0)), "\x1b[1;5H x");
+}
@@ -0,0 +1,90 @@
+//! Module copying text to the system clipboard.
+//!
+//! By default the text is sent to the terminal in an OSC 52 sequence,
+//! which the terminal puts in the clipboard; this works over SSH as
+//! well. Terminals which don't support OSC 52 can be worked around
+//! with a command reading the text from its input, e.g. `wl-copy` or
+//! `xclip -selection clipboard`.
+
+use std::io;
+use std::io::Write;
+use std::process::Command;
+use std::process::Stdio;
+
+const BASE64_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encodes bytes in Base64, with padding.
+fn base64(data: &[u8]) -> String {
+    let mut result = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).cloned().unwrap_or(0),
+            chunk.get(2).cloned().unwrap_or(0),
+        ];
+        let bits = (u32::from(bytes[0]) << 16) | (u32::from(bytes[1]) << 8) | u32::from(bytes[2]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                let index = (bits >> (18 - 6 * i)) & 0x3f;
+                result.push(BASE64_ALPHABET[index as usize] as char);
+            } else {
+                result.push('=');
+            }
+        }
+    }
+    result
+}
+
+#[test]
+fn test_base64() {
+    assert_eq!(base64(b""), "");
+    assert_eq!(base64(b"f"), "Zg==");
+    assert_eq!(base64(b"fo"), "Zm8=");
+    assert_eq!(base64(b"foo"), "Zm9v");
+    assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    assert_eq!(base64("żółw".as_bytes()), "xbzDs8WCdw==");
+}
+
+/// Returns the OSC 52 sequence setting the clipboard to the text.
+fn osc52(text: &str) -> String {
+    format!("\x1b]52;c;{}\x07", base64(text.as_bytes()))
+}
+
+/// Copies the text to the clipboard, with the given command run by
+/// the shell if there is one, or through the terminal otherwise.
+pub fn copy<W: Write>(term: &mut W, text: &str, command: Option<&str>) -> io::Result<()> {
+    let command = match command {
+        Some(c) => c,
+        None => {
+            write!(term, "{}", osc52(text))?;
+            return term.flush();
+        }
+    };
+    let mut child = Command::new("sh")
+        .args(["-c", command])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(text.as_bytes())?;
+    }
+    let status = child.wait()?;
+    if status.success() {
+        Result::Ok(())
+    } else {
+        Result::Err(io::Error::other(format!("{} failed: {}", command, status)))
+    }
+}
+
+#[test]
+fn test_copy() {
+    let mut term = vec![];
+    copy(&mut term, "foo", None).unwrap();
+    assert_eq!(term, b"\x1b]52;c;Zm9v\x07");
+
+    let mut term = vec![];
+    copy(&mut term, "foo", Some("cat")).unwrap();
+    assert!(term.is_empty());
+    assert!(copy(&mut term, "foo", Some("exit 1")).is_err());
+}
@@ -0,0 +1,197 @@
+//! Module formatting the columns of completions: the metadata of
+//! files (permissions, size and modification time) shown before
+//! completions, and the descriptions shown at the right edge.
+
+use std::cmp;
+use std::time;
+
+use crate::ansi;
+use crate::core;
+
+use super::text;
+
+/// The width of the columns, including the separating space after
+/// them.
+pub const COLUMNS_WIDTH: usize = 10 + 1 + 5 + 1 + 16 + 1;
+
+/// Formats Unix permission bits like `ls -l`, e.g. `drwxr-xr-x`.
+fn format_mode(mode: Option<u32>, is_dir: bool) -> String {
+    let mode = match mode {
+        Some(m) => m,
+        None => return if is_dir { "d" } else { "-" }.to_owned(),
+    };
+    let mut result = String::with_capacity(10);
+    result.push(if is_dir { 'd' } else { '-' });
+    for shift in &[6, 3, 0] {
+        let bits = (mode >> shift) & 0o7;
+        result.push(if bits & 0o4 != 0 { 'r' } else { '-' });
+        result.push(if bits & 0o2 != 0 { 'w' } else { '-' });
+        result.push(if bits & 0o1 != 0 { 'x' } else { '-' });
+    }
+    result
+}
+
+/// Formats a size in bytes in at most 5 characters, e.g. `512`,
+/// `4.0K` or `120M`.
+fn format_size(size: u64) -> String {
+    const UNITS: &[char] = &['K', 'M', 'G', 'T', 'P', 'E'];
+    if size < 1024 {
+        return size.to_string();
+    }
+    let mut value = size as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if value < 10.0 {
+        format!("{:.1}{}", value, UNITS[unit])
+    } else {
+        format!("{:.0}{}", value, UNITS[unit])
+    }
+}
+
+/// Formats a point in time as `YYYY-MM-DD HH:MM`, in UTC.
+fn format_time(time: time::SystemTime) -> String {
+    let seconds = match time.duration_since(time::UNIX_EPOCH) {
+        Result::Ok(d) => d.as_secs() as i64,
+        Result::Err(e) => -(e.duration().as_secs() as i64),
+    };
+    let days = seconds.div_euclid(86400);
+    let seconds_of_day = seconds.rem_euclid(86400);
+
+    // Converts days since the epoch to a civil date, see
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60
+    )
+}
+
+/// Formats the metadata columns, padded to `COLUMNS_WIDTH`.
+pub fn format_columns(metadata: Option<core::FileMetadata>) -> String {
+    let metadata = match metadata {
+        Some(m) => m,
+        None => return " ".repeat(COLUMNS_WIDTH),
+    };
+    let modified = metadata.modified.map(format_time).unwrap_or_default();
+    format!(
+        "{:10} {:>5} {:16} ",
+        format_mode(metadata.mode, metadata.is_dir),
+        format_size(metadata.size),
+        modified
+    )
+}
+
+#[test]
+fn test_format_columns() {
+    assert_eq!(format_mode(Some(0o755), true), "drwxr-xr-x");
+    assert_eq!(format_mode(Some(0o100640), false), "-rw-r-----");
+    assert_eq!(format_size(512), "512");
+    assert_eq!(format_size(4096), "4.0K");
+    assert_eq!(format_size(125 * 1024 * 1024), "125M");
+    let day = time::Duration::from_secs(86400);
+    assert_eq!(format_time(time::UNIX_EPOCH), "1970-01-01 00:00");
+    assert_eq!(
+        format_time(time::UNIX_EPOCH + day * 19_844 + time::Duration::from_secs(3723)),
+        "2024-05-01 01:02"
+    );
+    assert_eq!(format_time(time::UNIX_EPOCH - day), "1969-12-31 00:00");
+
+    let metadata = core::FileMetadata {
+        is_dir: false,
+        size: 2048,
+        modified: Some(time::UNIX_EPOCH),
+        mode: Some(0o644),
+    };
+    assert_eq!(
+        format_columns(Some(metadata)),
+        "-rw-r--r--  2.0K 1970-01-01 00:00 "
+    );
+    assert_eq!(format_columns(None).len(), COLUMNS_WIDTH);
+}
+
+/// The minimal number of spaces between a completion and its
+/// description.
+const DESCRIPTION_GAP: usize = 2;
+
+/// Formats the text of a completion, along with its description
+/// aligned to the right edge and in the `dimmed` color, in `width`
+/// characters.
+///
+/// The description takes the space left by the text, but at least a
+/// third of the width, so that long texts don't push it out entirely.
+pub fn format_text_and_description(
+    text: &str,
+    description: Option<&str>,
+    width: usize,
+    dimmed: ansi::Color,
+) -> String {
+    let description = match description {
+        Some(d) if !d.is_empty() => d,
+        _ => return text::truncate(text, width).0,
+    };
+    let full_text_width = text::width(text);
+    let description_width = cmp::min(
+        text::width(description),
+        cmp::max(
+            width / 3,
+            width.saturating_sub(full_text_width + DESCRIPTION_GAP),
+        ),
+    );
+    let (text, text_width) = text::truncate(
+        text,
+        width.saturating_sub(description_width + DESCRIPTION_GAP),
+    );
+    let (description, description_width) = text::truncate(description, description_width);
+    format!(
+        "{}{}{}{}{}",
+        text,
+        " ".repeat(width.saturating_sub(text_width + description_width)),
+        dimmed.fg(),
+        description,
+        ansi::FG_RESET
+    )
+}
+
+#[test]
+fn test_format_text_and_description() {
+    let format = |t: &str, d: Option<&str>, width: usize| {
+        format_text_and_description(t, d, width, ansi::Color::Basic(8))
+    };
+    let dimmed = |d: &str| format!("\x1b[90m{}{}", d, ansi::FG_RESET);
+    assert_eq!(format("abcdef", None, 4), "abcd");
+    assert_eq!(
+        format("ab", Some("xyz"), 10),
+        format!("ab     {}", dimmed("xyz"))
+    );
+    // The description keeps a third of the width.
+    assert_eq!(
+        format("abcdefghijkl", Some("uvwxyz"), 12),
+        format!("abcdef  {}", dimmed("uvwx"))
+    );
+    assert_eq!(
+        format("\x1b[1mabc\x1b[m", Some("x"), 6),
+        format!("\x1b[1mabc\x1b[m  {}", dimmed("x"))
+    );
+    assert_eq!(format("abc", Some(""), 2), "ab");
+}
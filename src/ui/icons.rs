@@ -0,0 +1,67 @@
+//! Module formatting the icons shown next to completions, which
+//! indicate the kinds of completions: a letter, or a glyph of a Nerd
+//! Font if the terminal uses one, colored per kind by the theme.
+
+use crate::ansi;
+use crate::core::CompletionKind;
+
+use super::theme;
+
+/// The width of the icon, including the separating space after it.
+pub const ICON_WIDTH: usize = 2;
+
+/// Returns the letter and the Nerd Font glyph for a kind.
+fn symbols(kind: CompletionKind) -> (char, char) {
+    match kind {
+        CompletionKind::Directory => ('d', '\u{f07b}'),
+        CompletionKind::File => ('f', '\u{f15b}'),
+        CompletionKind::Symlink => ('l', '\u{f0c1}'),
+        CompletionKind::Branch => ('b', '\u{e725}'),
+        CompletionKind::RemoteBranch => ('r', '\u{e725}'),
+        CompletionKind::Tag => ('t', '\u{f02b}'),
+        CompletionKind::Commit => ('c', '\u{e729}'),
+        CompletionKind::Host => ('h', '\u{f233}'),
+        CompletionKind::Process => ('p', '\u{f085}'),
+        CompletionKind::Image => ('i', '\u{f308}'),
+        CompletionKind::Package => ('k', '\u{f487}'),
+        CompletionKind::Version => ('v', '\u{f412}'),
+        CompletionKind::Script => ('s', '\u{f120}'),
+        CompletionKind::Alias => ('a', '\u{f0c5}'),
+        CompletionKind::Function => ('F', '\u{f794}'),
+        CompletionKind::Manual => ('m', '\u{f02d}'),
+        CompletionKind::Section => ('#', '\u{f0ca}'),
+        CompletionKind::Command => ('$', '\u{f1da}'),
+        CompletionKind::PullRequest => ('P', '\u{f407}'),
+        CompletionKind::Issue => ('I', '\u{f41b}'),
+        CompletionKind::Application => ('A', '\u{f2d0}'),
+        CompletionKind::Other => (' ', ' '),
+    }
+}
+
+/// Formats the icon of a kind, taking `ICON_WIDTH` columns.
+pub fn format_icon(kind: CompletionKind, nerd_font: bool, theme: &theme::Theme) -> String {
+    let (letter, glyph) = symbols(kind);
+    format!(
+        "{}{}{} ",
+        theme.kind_color(kind).fg(),
+        if nerd_font { glyph } else { letter },
+        ansi::FG_RESET
+    )
+}
+
+#[test]
+fn test_format_icon() {
+    let theme = theme::Theme::default();
+    assert_eq!(
+        format_icon(CompletionKind::Directory, false, &theme),
+        format!("\x1b[34md{} ", ansi::FG_RESET)
+    );
+    assert_eq!(
+        format_icon(CompletionKind::Branch, true, &theme),
+        format!("\x1b[32m\u{e725}{} ", ansi::FG_RESET)
+    );
+    assert_eq!(
+        format_icon(CompletionKind::Other, true, &theme),
+        format!("\x1b[90m {} ", ansi::FG_RESET)
+    );
+}
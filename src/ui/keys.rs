@@ -0,0 +1,430 @@
+//! Module mapping keys pressed by the user to actions in the chooser.
+//!
+//! Keys are referred to by canonical names, such as `up`, `ctrl-n`
+//! or `alt-x`, both in the configuration file and in diagnostics.
+
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use log::warn;
+
+/// A key pressed by the user, as reported by the terminal backend.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Key {
+    Backspace,
+    Left,
+    Right,
+    /// The left arrow typed with alt.
+    AltLeft,
+    /// The right arrow typed with alt.
+    AltRight,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Delete,
+    Insert,
+    /// Tab typed with shift.
+    BackTab,
+    /// A function key; `F(1)` is F1.
+    F(u8),
+    /// A character, including enter (`'\n'`) and tab (`'\t'`).
+    Char(char),
+    /// A character typed with alt.
+    Alt(char),
+    /// A character typed with ctrl.
+    Ctrl(char),
+    Null,
+    Esc,
+}
+
+/// An action which can be bound to a key.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    SelectPrevious,
+    SelectNext,
+    PreviousPage,
+    NextPage,
+    SelectFirst,
+    SelectLast,
+    Ascend,
+    Descend,
+    /// Lists the completions of all the levels descended into at once,
+    /// or returns to the current level.
+    ToggleLevelSearch,
+    Accept,
+    /// Accepts the completion displayed in the row with the given
+    /// index, counted from 0.
+    AcceptRow(usize),
+    Cancel,
+    NextTab,
+    PreviousTab,
+    /// Switches to the tab with the given index, counted from 0.
+    SelectTab(usize),
+    DeleteBackward,
+    DeleteForward,
+    DeleteWordBackward,
+    ClearQuery,
+    RecallPreviousQuery,
+    RecallNextQuery,
+    CursorBackward,
+    CursorForward,
+    CursorStart,
+    CursorEnd,
+    ToggleHidden,
+    ToggleMetadata,
+    SortByModified,
+    SortBySize,
+    SortByGroup,
+    CycleSortOrder,
+    Refresh,
+    CopyToClipboard,
+    ScrollLeft,
+    ScrollRight,
+    /// Shows labels next to the displayed completions, accepting the
+    /// one whose label is typed next.
+    JumpToLabel,
+    /// Shows or hides live measurements of fetching, scoring and
+    /// drawing the completions.
+    ToggleProfiler,
+}
+
+/// Names of all actions, as used in the configuration file.
+const ACTION_NAMES: &[(&str, Action)] = &[
+    ("select-previous", Action::SelectPrevious),
+    ("select-next", Action::SelectNext),
+    ("previous-page", Action::PreviousPage),
+    ("next-page", Action::NextPage),
+    ("select-first", Action::SelectFirst),
+    ("select-last", Action::SelectLast),
+    ("ascend", Action::Ascend),
+    ("descend", Action::Descend),
+    ("toggle-level-search", Action::ToggleLevelSearch),
+    ("accept", Action::Accept),
+    ("accept-1", Action::AcceptRow(0)),
+    ("accept-2", Action::AcceptRow(1)),
+    ("accept-3", Action::AcceptRow(2)),
+    ("accept-4", Action::AcceptRow(3)),
+    ("accept-5", Action::AcceptRow(4)),
+    ("accept-6", Action::AcceptRow(5)),
+    ("accept-7", Action::AcceptRow(6)),
+    ("accept-8", Action::AcceptRow(7)),
+    ("accept-9", Action::AcceptRow(8)),
+    ("cancel", Action::Cancel),
+    ("next-tab", Action::NextTab),
+    ("previous-tab", Action::PreviousTab),
+    ("select-tab-1", Action::SelectTab(0)),
+    ("select-tab-2", Action::SelectTab(1)),
+    ("select-tab-3", Action::SelectTab(2)),
+    ("select-tab-4", Action::SelectTab(3)),
+    ("select-tab-5", Action::SelectTab(4)),
+    ("select-tab-6", Action::SelectTab(5)),
+    ("select-tab-7", Action::SelectTab(6)),
+    ("select-tab-8", Action::SelectTab(7)),
+    ("select-tab-9", Action::SelectTab(8)),
+    ("delete-backward", Action::DeleteBackward),
+    ("delete-forward", Action::DeleteForward),
+    ("delete-word-backward", Action::DeleteWordBackward),
+    ("clear-query", Action::ClearQuery),
+    ("recall-previous-query", Action::RecallPreviousQuery),
+    ("recall-next-query", Action::RecallNextQuery),
+    ("cursor-backward", Action::CursorBackward),
+    ("cursor-forward", Action::CursorForward),
+    ("cursor-start", Action::CursorStart),
+    ("cursor-end", Action::CursorEnd),
+    ("toggle-hidden", Action::ToggleHidden),
+    ("toggle-metadata", Action::ToggleMetadata),
+    ("sort-by-modified", Action::SortByModified),
+    ("sort-by-size", Action::SortBySize),
+    ("sort-by-group", Action::SortByGroup),
+    ("cycle-sort-order", Action::CycleSortOrder),
+    ("refresh", Action::Refresh),
+    ("copy-to-clipboard", Action::CopyToClipboard),
+    ("scroll-left", Action::ScrollLeft),
+    ("scroll-right", Action::ScrollRight),
+    ("jump-to-label", Action::JumpToLabel),
+    ("toggle-profiler", Action::ToggleProfiler),
+];
+
+/// The name which may be used in the configuration file to remove
+/// the default binding of a key.
+const UNBOUND_ACTION_NAME: &str = "none";
+
+/// Names of keys which do not correspond to a single character.
+const SPECIAL_KEY_NAMES: &[(&str, Key)] = &[
+    ("backspace", Key::Backspace),
+    ("left", Key::Left),
+    ("right", Key::Right),
+    ("alt-left", Key::AltLeft),
+    ("alt-right", Key::AltRight),
+    ("up", Key::Up),
+    ("down", Key::Down),
+    ("home", Key::Home),
+    ("end", Key::End),
+    ("pageup", Key::PageUp),
+    ("pagedown", Key::PageDown),
+    ("delete", Key::Delete),
+    ("insert", Key::Insert),
+    ("esc", Key::Esc),
+    ("enter", Key::Char('\n')),
+    ("tab", Key::Char('\t')),
+    ("shift-tab", Key::BackTab),
+    ("space", Key::Char(' ')),
+];
+
+const DEFAULT_BINDINGS: &[(Key, Action)] = &[
+    (Key::Up, Action::SelectPrevious),
+    (Key::Down, Action::SelectNext),
+    (Key::PageUp, Action::PreviousPage),
+    (Key::PageDown, Action::NextPage),
+    (Key::Home, Action::SelectFirst),
+    (Key::End, Action::SelectLast),
+    (Key::Left, Action::Ascend),
+    (Key::Right, Action::Descend),
+    (Key::Alt('l'), Action::ToggleLevelSearch),
+    (Key::Char('\n'), Action::Accept),
+    (Key::Ctrl('c'), Action::Cancel),
+    (Key::Ctrl('g'), Action::Cancel),
+    (Key::Esc, Action::Cancel),
+    (Key::Char('\t'), Action::NextTab),
+    (Key::BackTab, Action::PreviousTab),
+    (Key::Alt('1'), Action::SelectTab(0)),
+    (Key::Alt('2'), Action::SelectTab(1)),
+    (Key::Alt('3'), Action::SelectTab(2)),
+    (Key::Alt('4'), Action::SelectTab(3)),
+    (Key::Alt('5'), Action::SelectTab(4)),
+    (Key::Alt('6'), Action::SelectTab(5)),
+    (Key::Alt('7'), Action::SelectTab(6)),
+    (Key::Alt('8'), Action::SelectTab(7)),
+    (Key::Alt('9'), Action::SelectTab(8)),
+    (Key::Backspace, Action::DeleteBackward),
+    (Key::Delete, Action::DeleteForward),
+    (Key::Ctrl('w'), Action::DeleteWordBackward),
+    (Key::Ctrl('u'), Action::ClearQuery),
+    (Key::Alt('p'), Action::RecallPreviousQuery),
+    (Key::Alt('n'), Action::RecallNextQuery),
+    (Key::Ctrl('b'), Action::CursorBackward),
+    (Key::Ctrl('f'), Action::CursorForward),
+    (Key::Ctrl('a'), Action::CursorStart),
+    (Key::Ctrl('e'), Action::CursorEnd),
+    (Key::Alt('h'), Action::ToggleHidden),
+    (Key::Ctrl('t'), Action::ToggleMetadata),
+    (Key::Alt('m'), Action::SortByModified),
+    (Key::Alt('s'), Action::SortBySize),
+    (Key::Alt('g'), Action::SortByGroup),
+    (Key::Ctrl('o'), Action::CycleSortOrder),
+    (Key::Ctrl('r'), Action::Refresh),
+    (Key::F(5), Action::Refresh),
+    (Key::Ctrl('y'), Action::CopyToClipboard),
+    (Key::AltLeft, Action::ScrollLeft),
+    (Key::AltRight, Action::ScrollRight),
+    // Terminals send ctrl-j as enter.
+    (Key::Ctrl('l'), Action::JumpToLabel),
+    (Key::F(12), Action::ToggleProfiler),
+];
+
+/// The bindings replacing the default ones when the displayed
+/// completions are numbered.
+const ROW_NUMBER_BINDINGS: &[(Key, Action)] = &[
+    (Key::Alt('1'), Action::AcceptRow(0)),
+    (Key::Alt('2'), Action::AcceptRow(1)),
+    (Key::Alt('3'), Action::AcceptRow(2)),
+    (Key::Alt('4'), Action::AcceptRow(3)),
+    (Key::Alt('5'), Action::AcceptRow(4)),
+    (Key::Alt('6'), Action::AcceptRow(5)),
+    (Key::Alt('7'), Action::AcceptRow(6)),
+    (Key::Alt('8'), Action::AcceptRow(7)),
+    (Key::Alt('9'), Action::AcceptRow(8)),
+];
+
+impl Action {
+    /// Returns the action with the given configuration name.
+    pub fn from_name(name: &str) -> Option<Action> {
+        ACTION_NAMES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, a)| *a)
+    }
+
+    /// Returns the action which moves the selection in the opposite
+    /// direction, or the action itself if it doesn't move it.
+    pub fn reversed(self) -> Action {
+        match self {
+            Action::SelectPrevious => Action::SelectNext,
+            Action::SelectNext => Action::SelectPrevious,
+            Action::PreviousPage => Action::NextPage,
+            Action::NextPage => Action::PreviousPage,
+            a => a,
+        }
+    }
+
+    /// Returns the configuration name of the action.
+    pub fn name(self) -> &'static str {
+        ACTION_NAMES
+            .iter()
+            .find(|(_, a)| *a == self)
+            .map(|(n, _)| *n)
+            .unwrap()
+    }
+}
+
+/// Returns the canonical name of the given key.
+pub fn key_name(key: &Key) -> Option<String> {
+    if let Some((name, _)) = SPECIAL_KEY_NAMES.iter().find(|(_, k)| k == key) {
+        return Some((*name).to_owned());
+    }
+    match *key {
+        Key::F(n) => Some(format!("f{}", n)),
+        Key::Char(c) => Some(c.to_string()),
+        Key::Alt(c) => Some(format!("alt-{}", c)),
+        Key::Ctrl(c) => Some(format!("ctrl-{}", c)),
+        _ => None,
+    }
+}
+
+/// Parses a canonical key name into a key.
+pub fn parse_key(name: &str) -> Option<Key> {
+    if let Some((_, key)) = SPECIAL_KEY_NAMES.iter().find(|(n, _)| *n == name) {
+        return Some(*key);
+    }
+    let single_char = |s: &str| {
+        let mut chars = s.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    };
+    if let Some(rest) = name.strip_prefix("ctrl-") {
+        return single_char(rest).map(Key::Ctrl);
+    }
+    if let Some(rest) = name.strip_prefix("alt-") {
+        return single_char(rest).map(Key::Alt);
+    }
+    if let Some(n) = name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+        if (1..=12).contains(&n) {
+            return Some(Key::F(n));
+        }
+    }
+    single_char(name).map(Key::Char)
+}
+
+#[test]
+fn test_key_names() {
+    for name in &[
+        "up",
+        "pageup",
+        "enter",
+        "tab",
+        "shift-tab",
+        "f5",
+        "ctrl-c",
+        "alt-p",
+        "x",
+        "/",
+        "é",
+    ] {
+        assert_eq!(key_name(&parse_key(name).unwrap()).unwrap(), *name);
+    }
+    assert_eq!(parse_key("ctrl-"), None);
+    assert_eq!(parse_key("f13"), None);
+    assert_eq!(parse_key("foo"), None);
+}
+
+/// Returns a warning if the given key may not work reliably in all
+/// terminals, e.g. because it is intercepted by the terminal or
+/// indistinguishable from another key.
+pub fn key_warning(key: &Key) -> Option<&'static str> {
+    match *key {
+        Key::Ctrl('h') => Some("many terminals send ctrl-h for backspace"),
+        Key::Ctrl('s') | Key::Ctrl('q') => {
+            Some("this is often captured by the terminal for flow control")
+        }
+        Key::Alt(_) => Some("some terminals do not send alt as a key prefix by default"),
+        Key::Esc => Some("esc is also the prefix of other keys and may be delayed"),
+        Key::Home | Key::End => Some("terminals send different sequences for this key"),
+        Key::F(1) | Key::F(10) | Key::F(11) => {
+            Some("this is often captured by the terminal or the desktop")
+        }
+        _ => None,
+    }
+}
+
+#[test]
+fn test_key_warning() {
+    assert!(key_warning(&Key::Ctrl('h')).is_some());
+    assert!(key_warning(&Key::Alt('x')).is_some());
+    assert!(key_warning(&Key::Ctrl('n')).is_none());
+    assert!(key_warning(&Key::Char('a')).is_none());
+}
+
+/// A mapping of keys to actions.
+pub struct Keymap {
+    bindings: HashMap<Key, Action>,
+}
+
+impl Keymap {
+    /// Creates the keymap from the default bindings and the bindings
+    /// specified in the configuration, mapping key names to action
+    /// names. If `row_numbers` is set, alt and numbers accept the
+    /// numbered completions by default.
+    ///
+    /// Invalid entries in the configuration are logged and ignored.
+    pub fn new(configured_bindings: &BTreeMap<String, String>, row_numbers: bool) -> Keymap {
+        let mut bindings: HashMap<Key, Action> = DEFAULT_BINDINGS.iter().cloned().collect();
+        if row_numbers {
+            bindings.extend(ROW_NUMBER_BINDINGS.iter().cloned());
+        }
+        for (key_name, action_name) in configured_bindings {
+            let key = match parse_key(key_name) {
+                Some(k) => k,
+                None => {
+                    warn!("Unknown key name in configuration: {}", key_name);
+                    continue;
+                }
+            };
+            if action_name == UNBOUND_ACTION_NAME {
+                bindings.remove(&key);
+            } else if let Some(action) = Action::from_name(action_name) {
+                bindings.insert(key, action);
+            } else {
+                warn!("Unknown action name in configuration: {}", action_name);
+            }
+        }
+        Keymap { bindings }
+    }
+
+    /// Returns the action bound to the given key, if any.
+    pub fn action(&self, key: &Key) -> Option<Action> {
+        self.bindings.get(key).cloned()
+    }
+}
+
+#[test]
+fn test_keymap() {
+    let mut configured = BTreeMap::new();
+    configured.insert("ctrl-n".to_owned(), "select-next".to_owned());
+    configured.insert("up".to_owned(), "none".to_owned());
+    configured.insert("ctrl-x".to_owned(), "no-such-action".to_owned());
+    let keymap = Keymap::new(&configured, false);
+    assert_eq!(keymap.action(&Key::Ctrl('n')), Some(Action::SelectNext));
+    assert_eq!(keymap.action(&Key::Down), Some(Action::SelectNext));
+    assert_eq!(keymap.action(&Key::Up), None);
+    assert_eq!(keymap.action(&Key::Ctrl('x')), None);
+    assert_eq!(keymap.action(&Key::Char('a')), None);
+    assert_eq!(keymap.action(&Key::Esc), Some(Action::Cancel));
+    assert_eq!(
+        keymap.action(&Key::Ctrl('w')),
+        Some(Action::DeleteWordBackward)
+    );
+    assert_eq!(keymap.action(&Key::Alt('3')), Some(Action::SelectTab(2)));
+    assert_eq!(
+        Action::from_name("select-tab-3"),
+        Some(Action::SelectTab(2))
+    );
+
+    let keymap = Keymap::new(&configured, true);
+    assert_eq!(keymap.action(&Key::Alt('3')), Some(Action::AcceptRow(2)));
+    assert_eq!(Action::from_name("accept-9"), Some(Action::AcceptRow(8)));
+}
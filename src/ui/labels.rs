@@ -0,0 +1,106 @@
+//! Module generating the labels of jump mode, in which the user accepts
+//! any of the displayed completions by typing the label shown next to
+//! it instead of moving the selection there.
+//!
+//! Labels are made of letters which are easy to reach, and none of them
+//! is a prefix of another, so a label is chosen as soon as it is typed.
+
+/// The letters labels are made of, easiest to reach first.
+const ALPHABET: &str = "asdfghjklqwertyuiopzxcvbnm";
+
+/// Returns `count` labels: single letters if there are enough of them,
+/// or pairs of letters otherwise.
+pub fn generate(count: usize) -> Vec<String> {
+    let letters: Vec<char> = ALPHABET.chars().collect();
+    if count <= letters.len() {
+        return letters[..count].iter().map(|c| c.to_string()).collect();
+    }
+    letters
+        .iter()
+        .flat_map(|a| letters.iter().map(move |b| format!("{}{}", a, b)))
+        .take(count)
+        .collect()
+}
+
+#[test]
+fn test_generate() {
+    assert!(generate(0).is_empty());
+    assert_eq!(generate(3), vec!["a", "s", "d"]);
+    let labels = generate(30);
+    assert_eq!(labels.len(), 30);
+    assert_eq!(&labels[..3], &["aa", "as", "ad"]);
+    assert_eq!(labels[26], "sa");
+}
+
+/// The outcome of a letter typed in jump mode.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// The letters typed so far begin some labels.
+    Pending,
+    /// The label of the row with the given index was typed.
+    Chosen(usize),
+    /// The letters typed so far don't begin any label.
+    Cancelled,
+}
+
+/// The state of jump mode: the labels of the displayed rows and the
+/// letters typed so far.
+pub struct Jump {
+    labels: Vec<String>,
+    typed: String,
+}
+
+impl Jump {
+    /// Starts jump mode for `count` displayed rows.
+    pub fn new(count: usize) -> Jump {
+        Jump {
+            labels: generate(count),
+            typed: String::new(),
+        }
+    }
+
+    /// Returns the part of the label of the row with the given index
+    /// which remains to be typed, or None if the letters typed so far
+    /// don't begin it.
+    pub fn remaining(&self, row: usize) -> Option<&str> {
+        self.labels
+            .get(row)
+            .and_then(|l| l.strip_prefix(self.typed.as_str()))
+    }
+
+    /// Returns the number of columns the labels take.
+    pub fn width(&self) -> usize {
+        self.labels.first().map_or(0, |l| l.len())
+    }
+
+    /// Handles a letter typed by the user.
+    pub fn press(&mut self, c: char) -> Outcome {
+        self.typed.push(c);
+        if let Some(i) = self.labels.iter().position(|l| *l == self.typed) {
+            return Outcome::Chosen(i);
+        }
+        if self.labels.iter().any(|l| l.starts_with(&self.typed)) {
+            Outcome::Pending
+        } else {
+            Outcome::Cancelled
+        }
+    }
+}
+
+#[test]
+fn test_jump() {
+    let mut jump = Jump::new(3);
+    assert_eq!(jump.width(), 1);
+    assert_eq!(jump.remaining(1), Some("s"));
+    assert_eq!(jump.press('s'), Outcome::Chosen(1));
+
+    let mut jump = Jump::new(30);
+    assert_eq!(jump.width(), 2);
+    assert_eq!(jump.press('s'), Outcome::Pending);
+    assert_eq!(jump.remaining(0), None);
+    assert_eq!(jump.remaining(27), Some("s"));
+    assert_eq!(jump.press('s'), Outcome::Chosen(27));
+
+    let mut jump = Jump::new(30);
+    assert_eq!(jump.press('x'), Outcome::Cancelled);
+}
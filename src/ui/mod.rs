@@ -1,6 +1,21 @@
+pub mod actions;
+pub mod backend;
 pub mod canvas;
+pub mod clipboard;
+pub mod columns;
+pub mod icons;
+pub mod keys;
+pub mod labels;
 pub mod model;
-pub mod terminal;
+pub mod osc;
+pub mod profiler;
+pub mod recorder;
+pub mod scorer;
+#[cfg(test)]
+pub mod testing;
+pub mod text;
+pub mod theme;
+pub mod trace;
 
 use std::cmp;
 use std::io;
@@ -9,66 +24,348 @@ use std::sync::mpsc;
 use std::thread;
 use std::time;
 
-use termion;
-use termion::clear;
-use termion::event::Key::*;
-use termion::input::TermRead;
+use log::warn;
 
-use crate::config::CHOOSER_HEIGHT;
+use crate::ansi;
+use crate::config;
 
 use crate::core;
+use crate::history_file;
+use crate::picker;
 
-fn print_state(term_canvas: &mut canvas::TermCanvas, model: &model::Model) -> io::Result<()> {
+use self::keys::Action;
+use self::keys::Key::*;
+
+/// The number of lines next to the completions: the query and the tab
+/// bar.
+const HEADER_HEIGHT: usize = 2;
+
+/// The rows of the canvas which the parts of the chooser are drawn in,
+/// depending on the layout.
+struct Rows {
+    layout: config::Layout,
+
+    /// The number of completions shown.
+    height: usize,
+}
+
+impl Rows {
+    fn query(&self) -> usize {
+        match self.layout {
+            config::Layout::Default => 0,
+            config::Layout::Reverse => self.height + HEADER_HEIGHT - 1,
+        }
+    }
+
+    fn tabs(&self) -> usize {
+        match self.layout {
+            config::Layout::Default => 1,
+            config::Layout::Reverse => self.height,
+        }
+    }
+
+    /// Returns the row of the completion shown at the given position,
+    /// counted from the one next to the tab bar.
+    fn completion(&self, position: usize) -> usize {
+        match self.layout {
+            config::Layout::Default => HEADER_HEIGHT + position,
+            config::Layout::Reverse => self.height - 1 - position,
+        }
+    }
+
+    /// Returns the topmost row of the completions.
+    fn first_completion(&self) -> usize {
+        cmp::min(self.completion(0), self.completion(self.height - 1))
+    }
+}
+
+/// Returns the number of completions displayed at once in a terminal
+/// with the given number of rows. The line with the command being
+/// completed stays visible.
+fn completion_rows(height: config::Height, terminal_rows: usize) -> usize {
+    let rows = match height {
+        config::Height::Rows(rows) => rows,
+        config::Height::Percent(p) => (terminal_rows * p / 100).saturating_sub(HEADER_HEIGHT),
+    };
+    cmp::max(
+        cmp::min(rows, terminal_rows.saturating_sub(HEADER_HEIGHT + 1)),
+        1,
+    )
+}
+
+#[test]
+fn test_completion_rows() {
+    assert_eq!(completion_rows(config::Height::Rows(10), 50), 10);
+    assert_eq!(completion_rows(config::Height::Rows(10), 8), 5);
+    assert_eq!(completion_rows(config::Height::Percent(40), 50), 18);
+    assert_eq!(completion_rows(config::Height::Percent(100), 50), 47);
+    assert_eq!(completion_rows(config::Height::Percent(1), 50), 1);
+}
+
+#[test]
+fn test_rows() {
+    let rows = Rows {
+        layout: config::Layout::Default,
+        height: 3,
+    };
+    assert_eq!((rows.query(), rows.tabs()), (0, 1));
+    assert_eq!((rows.completion(0), rows.completion(2)), (2, 4));
+    assert_eq!(rows.first_completion(), 2);
+    let rows = Rows {
+        layout: config::Layout::Reverse,
+        height: 3,
+    };
+    assert_eq!((rows.query(), rows.tabs()), (4, 3));
+    assert_eq!((rows.completion(0), rows.completion(2)), (2, 0));
+    assert_eq!(rows.first_completion(), 0);
+}
+
+/// The width of the gutter numbering the displayed completions, which
+/// can be accepted with alt and their numbers.
+const GUTTER_WIDTH: usize = 2;
+
+/// The number of displayed completions which are numbered.
+const NUMBERED_ROWS: usize = 9;
+
+/// The settings of how the chooser is drawn, which are updated when
+/// the configuration is reloaded.
+struct Appearance {
+    theme: theme::Theme,
+    nerd_font: bool,
+    row_numbers: bool,
+}
+
+impl Appearance {
+    fn new(config: &config::Config) -> Appearance {
+        Appearance {
+            theme: theme::Theme::new(&config.theme),
+            nerd_font: config.ui.nerd_font,
+            row_numbers: config.ui.row_numbers,
+        }
+    }
+}
+
+/// How often the configuration file is checked for changes while
+/// the chooser is idle.
+const CONFIG_POLL_INTERVAL: time::Duration = time::Duration::from_millis(250);
+
+/// A structure scheduling filtering the completions after the query
+/// is edited, postponed until the user stops typing for a while.
+struct RefilterSchedule {
+    delay: time::Duration,
+    deadline: Option<time::Instant>,
+}
+
+impl RefilterSchedule {
+    fn new(delay_ms: u64) -> RefilterSchedule {
+        RefilterSchedule {
+            delay: time::Duration::from_millis(delay_ms),
+            deadline: None,
+        }
+    }
+
+    fn set_delay(&mut self, delay_ms: u64) {
+        self.delay = time::Duration::from_millis(delay_ms);
+    }
+
+    /// (Re)starts the delay after an edit of the query.
+    fn postpone(&mut self) {
+        self.deadline = Some(time::Instant::now() + self.delay);
+    }
+
+    /// Returns how long to wait for input before filtering is due,
+    /// but not longer than `max_wait`.
+    fn wait_time(&self, max_wait: time::Duration) -> time::Duration {
+        match self.deadline {
+            Some(d) => cmp::min(max_wait, d.saturating_duration_since(time::Instant::now())),
+            None => max_wait,
+        }
+    }
+
+    /// Returns `true` if filtering is due, or if `force` is set and
+    /// filtering is scheduled at all. The schedule is cleared in
+    /// that case.
+    fn take_due(&mut self, force: bool) -> bool {
+        match self.deadline {
+            Some(d) if force || d <= time::Instant::now() => {
+                self.deadline = None;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[test]
+fn test_refilter_schedule() {
+    let max_wait = time::Duration::from_secs(1);
+    let mut schedule = RefilterSchedule::new(0);
+    assert_eq!(schedule.wait_time(max_wait), max_wait);
+    assert!(!schedule.take_due(true));
+    schedule.postpone();
+    assert_eq!(schedule.wait_time(max_wait), time::Duration::ZERO);
+    assert!(schedule.take_due(false));
+    assert!(!schedule.take_due(false));
+
+    schedule.set_delay(60_000);
+    schedule.postpone();
+    assert!(schedule.wait_time(max_wait) <= max_wait);
+    assert!(!schedule.take_due(false));
+    assert!(schedule.take_due(true));
+}
+
+fn print_state(
+    term_canvas: &mut canvas::TermCanvas,
+    model: &model::Model,
+    rows: &Rows,
+    appearance: &Appearance,
+    message: Option<&str>,
+    jump: Option<&labels::Jump>,
+    profiler: &profiler::Profiler,
+) -> io::Result<()> {
+    let height = rows.height;
+    let theme = &appearance.theme;
     let off = model.view_offset();
     let prompt = "  Search: ";
     let count = model.completions_count();
     let status_string = format!(
-        "[{} {}-{}/{}]",
-        model.completer_name(),
+        "[{} {}-{}/{}{} by {}{}]",
+        model.breadcrumb(),
         off + 1,
-        cmp::min(off + CHOOSER_HEIGHT + 1, count),
+        cmp::min(off + height + 1, count),
         count,
+        if model.truncated() { ", truncated" } else { "" },
+        model.sort_order().name(),
+        if model.per_tab_queries() {
+            ", query per tab"
+        } else {
+            ""
+        },
     );
 
     term_canvas.clear()?;
-    write!(term_canvas, "{}{}", prompt, model.query())?;
+    term_canvas.move_to(rows.query(), 0)?;
+    term_canvas.print(&format!("{}{}", prompt, model.query()))?;
     let term_width = term_canvas.width();
-    term_canvas.move_to(0, term_width - status_string.len())?;
-    write!(term_canvas, "{}", status_string)?;
+    term_canvas.move_to(
+        rows.query(),
+        term_width.saturating_sub(text::width(&status_string)),
+    )?;
+    term_canvas.print(&status_string)?;
+
+    term_canvas.move_to(rows.tabs(), 0)?;
+    for (i, name) in model.tab_names().iter().enumerate() {
+        if i == model.tab_index() {
+            term_canvas.print(&theme.select(&format!(" {}:{} ", i + 1, name)))?;
+        } else {
+            term_canvas.print(&format!(" {}:{} ", i + 1, name))?;
+        }
+    }
+    let notice = match message {
+        Some(m) => Some((theme.message, m)),
+        None => model.errors().last().map(|e| (theme.error, e.as_str())),
+    };
+    if let Some((color, text)) = notice {
+        let (text, width) = text::truncate(text, term_width / 2);
+        term_canvas.move_to(rows.tabs(), term_width - width)?;
+        term_canvas.print(&format!("{}{}{}", color.fg(), text, ansi::FG_RESET))?;
+    }
 
-    let end_offset = cmp::min(off + CHOOSER_HEIGHT, count);
-    for i in off..end_offset {
+    let gutter_width = if appearance.row_numbers {
+        GUTTER_WIDTH
+    } else {
+        0
+    };
+    for (row, row_content) in model.rows().into_iter().enumerate() {
+        let i = match row_content {
+            model::Row::Completion(i) => i,
+            model::Row::Header(name) => {
+                term_canvas.move_to(rows.completion(row), 0)?;
+                term_canvas.print(&format!(
+                    "{}{}{}{}{}{}",
+                    " ".repeat(gutter_width),
+                    theme.dimmed.fg(),
+                    ansi::BOLD,
+                    name,
+                    ansi::NORMAL_INTENSITY,
+                    ansi::FG_RESET
+                ))?;
+                continue;
+            }
+        };
         let (comp, score) = model.completion_at(i);
-        let completion_string = comp.display_string();
-        let displayed_length = cmp::min(completion_string.len(), term_canvas.width() - 2);
-        let displayed_completion = &(completion_string)[..displayed_length];
-        term_canvas.move_to(i - off + 1, 0)?;
+        // In jump mode, labels are shown in place of the scores.
+        let score = match jump {
+            Some(j) => format!(
+                "{}{}{:width$}{}{}",
+                theme.message.fg(),
+                ansi::BOLD,
+                j.remaining(i - off).unwrap_or(""),
+                ansi::NORMAL_INTENSITY,
+                ansi::FG_RESET,
+                width = j.width()
+            ),
+            None => score.to_string(),
+        };
+        let mut completion_string = theme.format_completion(comp);
+        // The first column of scrolled completions shows that they are.
+        if model.horizontal_offset() > 0 {
+            completion_string = format!(
+                "{}\u{2026}{}{}",
+                theme.dimmed.fg(),
+                ansi::FG_RESET,
+                text::skip(&completion_string, model.horizontal_offset() + 1)
+            );
+        }
+        let columns = if model.show_metadata() {
+            columns::format_columns(comp.file_metadata())
+        } else {
+            String::new()
+        };
+        let icon = icons::format_icon(comp.kind(), appearance.nerd_font, theme);
+        let gutter = if !appearance.row_numbers {
+            String::new()
+        } else if i - off < NUMBERED_ROWS {
+            format!("{}{}{} ", theme.dimmed.fg(), i - off + 1, ansi::FG_RESET)
+        } else {
+            " ".repeat(GUTTER_WIDTH)
+        };
+        let available_width = (term_canvas.width() - 2)
+            .saturating_sub(text::width(&gutter) + columns.len() + icons::ICON_WIDTH);
+        let displayed_completion = columns::format_text_and_description(
+            &completion_string,
+            comp.description().as_deref(),
+            available_width,
+            theme.dimmed,
+        );
+        term_canvas.move_to(rows.completion(row), 0)?;
+        term_canvas.print(&gutter)?;
         if i == model.selection() {
-            write!(
-                term_canvas,
-                "{}{} {}{}",
-                termion::style::Invert,
-                score,
-                displayed_completion,
-                termion::style::Reset,
-            )?;
+            term_canvas.print(&theme.select(&format!(
+                "{} {}{}{}",
+                score, columns, icon, displayed_completion
+            )))?;
         } else {
-            write!(term_canvas, "{} {}", score, displayed_completion)?;
+            term_canvas.print(&format!(
+                "{} {}{}{}",
+                score, columns, icon, displayed_completion
+            ))?;
         }
     }
 
-    term_canvas.move_to(0, prompt.len() + model.query().len())?;
+    profiler.draw(term_canvas, model, rows.first_completion(), height, theme)?;
 
-    return Result::Ok(());
+    term_canvas.move_to(rows.query(), prompt.len() + model.cursor_column())?;
+    term_canvas.present()
 }
 
 fn key_reader_thread_routine(
+    mut pressed_keys: backend::Keys,
     req_receiver: mpsc::Receiver<()>,
-    key_sender: mpsc::Sender<termion::event::Key>,
+    key_sender: mpsc::Sender<keys::Key>,
 ) {
-    let mut keys = io::stdin().keys();
     while let Result::Ok(()) = req_receiver.recv() {
-        if let Some(Result::Ok(key)) = keys.next() {
+        if let Some(Result::Ok(key)) = pressed_keys.next() {
             let result = key_sender.send(key);
             if result.is_err() {
                 break;
@@ -79,94 +376,516 @@ fn key_reader_thread_routine(
     }
 }
 
+/// Lets the user choose a completion from the given completers.
+///
+/// This is a shorthand for running a `picker::Picker`.
 pub fn get_completion(
     initial_query: &str,
     completers: Vec<Box<dyn core::Completer>>,
-) -> io::Result<String> {
-    let term = termion::get_tty()?;
-    let mut model = model::Model::new(completers);
+    config: &config::Config,
+) -> io::Result<core::CompletionResult> {
+    picker::Picker::new()
+        .query(initial_query)
+        .completers(completers)
+        .config(config.clone())
+        .run()
+}
 
-    model.query_set(&initial_query);
+/// Waits until all completions of the current tab are fetched and
+/// scored, and selects the first one.
+fn fetch_all(model: &mut model::Model) {
+    while !model.fetching_completions_finished() || !model.scoring_finished() {
+        thread::sleep(time::Duration::from_millis(10));
+        model.fetch_completions();
+        model.receive_scores();
+    }
+    // Completions fetched last may be listed below the first ones
+    // until the selection is moved.
+    model.select_first();
+}
 
-    let original_terminal_state = terminal::prepare()?;
+/// Returns the result of the choice if the completions of the first
+/// tab settle it, so that the chooser isn't shown: the only matching
+/// completion if `select_one` is set, or the query if nothing matches
+/// and `exit_zero` is set.
+fn early_result(
+    model: &mut model::Model,
+    ui_config: &config::UiConfig,
+    initial_query: &str,
+) -> Option<core::CompletionResult> {
+    if !ui_config.select_one && !ui_config.exit_zero {
+        return None;
+    }
+    fetch_all(model);
+    match model.completions_count() {
+        0 if ui_config.exit_zero => Some(core::CompletionResult::cancelled(initial_query)),
+        1 if ui_config.select_one => model.get_selected_result(),
+        _ => None,
+    }
+}
 
-    let mut term_canvas = canvas::TermCanvas::new(term, CHOOSER_HEIGHT + 1)?;
+#[test]
+fn test_early_result() {
+    use self::testing::words;
+    use self::testing::WordCompleter;
 
+    let mut config = config::Config::default();
+    let result = |config: &config::Config, query: &str| {
+        let completer = WordCompleter::new("words", words(&["foo", "bar", "baz"]));
+        let mut model = model::Model::new(vec![Box::new(completer)], config, 5);
+        model.query_set(query);
+        model.start_fetching_completions();
+        early_result(&mut model, &config.ui, query)
+    };
+    assert_eq!(result(&config, "foo"), None);
+    config.ui.select_one = true;
+    assert_eq!(result(&config, "foo").unwrap().text, "foo");
+    assert_eq!(result(&config, "ba"), None);
+    assert_eq!(result(&config, "xyz"), None);
+    config.ui.exit_zero = true;
+    assert!(result(&config, "xyz").unwrap().cancelled);
+}
+
+/// Returns the best match for the query among the completions of the
+/// first tab without showing the chooser, for when there is no terminal
+/// to show it in. If nothing matches, the query is returned.
+pub fn best_match(
+    initial_query: &str,
+    completers: Vec<Box<dyn core::Completer>>,
+    config: &config::Config,
+) -> io::Result<core::CompletionResult> {
+    let mut model = model::Model::new(completers, config, config::CHOOSER_HEIGHT);
+    model.query_set(initial_query);
     model.start_fetching_completions();
+    fetch_all(&mut model);
+    Result::Ok(
+        model
+            .get_selected_result()
+            .unwrap_or_else(|| core::CompletionResult::cancelled(initial_query)),
+    )
+}
 
-    let result: String;
+#[test]
+fn test_best_match() {
+    use self::testing::words;
+    use self::testing::WordCompleter;
 
-    let (key_sender, key_receiver) = mpsc::channel::<termion::event::Key>();
+    // Returns one word on every fetch.
+    let slow = |names: &[&str]| {
+        WordCompleter::batches("slow", names.iter().map(|n| words(&[n])).collect())
+    };
+    let config = config::Config::default();
+    let completer = slow(&["xaxb", "axxb", "ab"]);
+    let result = best_match("ab", vec![Box::new(completer)], &config).unwrap();
+    assert_eq!(result.text, "ab");
+    let completer = slow(&["foo"]);
+    let result = best_match("xyz", vec![Box::new(completer)], &config).unwrap();
+    assert_eq!((result.text.as_str(), result.cursor_offset), ("xyz", 3));
+    assert!(result.cancelled);
+}
+
+/// Runs the chooser, displaying `height` completions at once, or as
+/// many as the configuration sets if it is None.
+pub(crate) fn choose(
+    initial_query: &str,
+    completers: Vec<Box<dyn core::Completer>>,
+    config: &config::Config,
+    height: Option<usize>,
+) -> io::Result<core::CompletionResult> {
+    let mut backend = backend::new(config.ui.backend.as_deref())?;
+    let height = match height {
+        Some(h) => h,
+        None => completion_rows(config.ui.height, backend.dimensions()?.1),
+    };
+    let mut trace = config
+        .log
+        .trace_path
+        .as_ref()
+        .map(|_| trace::SessionTrace::new());
+    let mut model = model::Model::new(completers, config, height);
+    model.query_set(initial_query);
+    model.start_fetching_completions();
+    if let Some(r) = early_result(&mut model, &config.ui, initial_query) {
+        return Result::Ok(r);
+    }
+    let query_history_path = match config.ui.query_history {
+        0 => None,
+        _ => history_file::path("query_history"),
+    };
+    if let Some(p) = &query_history_path {
+        model.set_query_history(history_file::read(p));
+    }
+    let mut keymap = keys::Keymap::new(&config.keys, config.ui.row_numbers);
+    let mut config_watcher = config::ConfigWatcher::new();
+    let mut title_progress = osc::TitleProgress::new(config.ui.terminal_title);
+    let mut refilter_schedule = RefilterSchedule::new(config.ui.debounce_ms);
+    let mut appearance = Appearance::new(config);
+    let mut completion_actions = config.actions.clone();
+    let mut clipboard_command = config.ui.clipboard_command.clone();
+
+    // A message shown in the status line until the next key, e.g. a
+    // question confirming the command in `pending_command`.
+    let mut message: Option<String> = None;
+    let mut pending_command: Option<String> = None;
+    // The labels shown in jump mode, until a label is typed.
+    let mut jump: Option<labels::Jump> = None;
+    let mut profiler = profiler::Profiler::new();
+
+    backend.prepare(false)?;
+
+    let mut term_canvas = canvas::TermCanvas::new(&mut *backend, height + HEADER_HEIGHT)?;
+
+    let result: core::CompletionResult;
+
+    let (key_sender, key_receiver) = mpsc::channel::<keys::Key>();
     let (req_sender, req_receiver) = mpsc::channel::<()>();
+    let pressed_keys = backend.keys()?;
     let key_reader_thread =
-        thread::spawn(move || key_reader_thread_routine(req_receiver, key_sender));
+        thread::spawn(move || key_reader_thread_routine(pressed_keys, req_receiver, key_sender));
     let mut req_sender = Some(req_sender);
 
     req_sender.as_ref().unwrap().send(()).unwrap();
+    let mut redraw = true;
     loop {
-        print_state(&mut term_canvas, &model)?;
+        if redraw {
+            let start = time::Instant::now();
+            print_state(
+                &mut term_canvas,
+                &model,
+                &Rows {
+                    layout: config.ui.layout,
+                    height,
+                },
+                &appearance,
+                message.as_deref(),
+                jump.as_ref(),
+                &profiler,
+            )?;
+            profiler.set_render_time(start.elapsed());
+            if let Some(t) = &mut trace {
+                t.render(start.elapsed());
+            }
+        }
+        redraw = false;
 
         let key_or_nothing;
-        if !model.fetching_completions_finished() {
+        if !model.fetching_completions_finished() || !model.scoring_finished() {
             key_or_nothing = key_receiver
-                .recv_timeout(time::Duration::from_millis(10))
+                .recv_timeout(refilter_schedule.wait_time(time::Duration::from_millis(10)))
                 .ok();
-            model.fetch_completions();
+            if !model.fetching_completions_finished() {
+                fetch_completions(&mut model, trace.as_mut());
+                if model.fetching_completions_finished() {
+                    title_progress.finish(&mut term_canvas)?;
+                } else {
+                    title_progress.update(
+                        &mut term_canvas,
+                        &model.completer_name(),
+                        model.completions_count(),
+                    )?;
+                }
+                redraw = true;
+            }
+            if model.receive_scores() {
+                redraw = true;
+            }
         } else {
-            key_or_nothing = key_receiver.recv().ok();
+            key_or_nothing = key_receiver
+                .recv_timeout(refilter_schedule.wait_time(CONFIG_POLL_INTERVAL))
+                .ok();
+        }
+
+        if let Some(new_config) = config_watcher.poll() {
+            match new_config {
+                Result::Ok(c) => {
+                    keymap = keys::Keymap::new(&c.keys, c.ui.row_numbers);
+                    appearance = Appearance::new(&c);
+                    completion_actions = c.actions;
+                    model.set_scoring_settings(c.scoring);
+                    title_progress.set_enabled(&mut term_canvas, c.ui.terminal_title)?;
+                    refilter_schedule.set_delay(c.ui.debounce_ms);
+                    clipboard_command = c.ui.clipboard_command;
+                    redraw = true;
+                }
+                Result::Err(e) => warn!("Failed to reload configuration: {}", e),
+            }
         }
 
         if let Some(key) = key_or_nothing {
-            match key {
-                Up => model.select_previous(),
-                Down => model.select_next(),
-                PageUp => model.previous_page(),
-                PageDown => model.next_page(),
-                Home => model.select_first(),
-                End => model.select_last(),
-
-                Left => model.ascend(),
-                Right => model.descend(),
-
-                Char('\n') => {
+            if let Some(t) = &mut trace {
+                t.key(&key);
+            }
+            message = None;
+            let mut handled = false;
+            let mut command_line = None;
+            if let Some(mut j) = jump.take() {
+                // Any key other than a letter of a label leaves jump
+                // mode.
+                if let Char(c) = key {
+                    match j.press(c) {
+                        labels::Outcome::Pending => jump = Some(j),
+                        labels::Outcome::Chosen(row) => {
+                            if let Some(r) = model.accept_visible(row) {
+                                result = r;
+                                break;
+                            }
+                        }
+                        labels::Outcome::Cancelled => (),
+                    }
+                }
+                handled = true;
+            } else if let Some(c) = pending_command.take() {
+                // Any key other than y cancels the command.
+                if key == Char('y') {
+                    command_line = Some(c);
+                }
+                handled = true;
+            } else if let Some(completion) = model.selected_completion() {
+                if let Some(a) = actions::find(&completion_actions, &key, completion) {
+                    let line = actions::command_line(a, completion);
+                    if a.confirm {
+                        message = Some(format!("Run {}? [y/N]", line));
+                        pending_command = Some(line);
+                    } else {
+                        command_line = Some(line);
+                    }
+                    handled = true;
+                }
+            }
+            if let Some(c) = command_line {
+                let (new_canvas, failure) = run_command(&mut *backend, term_canvas, height, &c)?;
+                term_canvas = new_canvas;
+                message = failure;
+                // The command may have changed what is completed, e.g.
+                // removed a file.
+                model.refresh();
+            }
+            if handled {
+                req_sender.as_ref().unwrap().send(()).unwrap();
+                redraw = true;
+                continue;
+            }
+
+            let action = match (config.ui.layout, keymap.action(&key)) {
+                // The completions are listed upwards, so moving up
+                // selects the following ones.
+                (config::Layout::Reverse, Some(a)) => Some(a.reversed()),
+                (_, a) => a,
+            };
+            let edits_query = match action {
+                Some(Action::DeleteBackward)
+                | Some(Action::DeleteForward)
+                | Some(Action::DeleteWordBackward)
+                | Some(Action::ClearQuery)
+                | Some(Action::RecallPreviousQuery)
+                | Some(Action::RecallNextQuery)
+                | Some(Action::CursorBackward)
+                | Some(Action::CursorForward)
+                | Some(Action::CursorStart)
+                | Some(Action::CursorEnd) => true,
+                None => matches!(key, Char(_)),
+                _ => false,
+            };
+            // Filtering is postponed only while the query is being
+            // edited, including moving the cursor within it; any other
+            // action works on up-to-date results.
+            if refilter_schedule.take_due(!edits_query) {
+                refilter(&mut model, trace.as_mut());
+            }
+            match action {
+                Some(Action::SelectPrevious) => model.select_previous(),
+                Some(Action::SelectNext) => model.select_next(),
+                Some(Action::PreviousPage) => model.previous_page(),
+                Some(Action::NextPage) => model.next_page(),
+                Some(Action::SelectFirst) => model.select_first(),
+                Some(Action::SelectLast) => model.select_last(),
+
+                Some(Action::Ascend) => model.ascend(),
+                Some(Action::Descend) => model.descend(),
+                Some(Action::ToggleLevelSearch) => model.toggle_level_search(),
+
+                Some(Action::Accept) => {
                     if let Some(r) = model.get_selected_result() {
                         result = r;
                         break;
                     }
                 }
-                Ctrl('c') => {
-                    result = initial_query.to_owned();
+                Some(Action::AcceptRow(row)) => {
+                    if let Some(r) = model.accept_visible(row) {
+                        result = r;
+                        break;
+                    }
+                }
+                Some(Action::Cancel) => {
+                    result = core::CompletionResult::cancelled(initial_query);
                     break;
                 }
-                Char('\t') => model.next_tab(),
-                Char(c) => model.query_append(c),
-                Backspace => model.query_backspace(),
+                Some(Action::NextTab) => model.next_tab(),
+                Some(Action::PreviousTab) => model.previous_tab(),
+                Some(Action::SelectTab(i)) => model.select_tab(i),
+                Some(Action::DeleteBackward) => {
+                    model.query_backspace();
+                    refilter_schedule.postpone();
+                }
+                Some(Action::DeleteForward) => {
+                    model.query_delete();
+                    refilter_schedule.postpone();
+                }
+                Some(Action::DeleteWordBackward) => {
+                    model.query_delete_word();
+                    refilter_schedule.postpone();
+                }
+                Some(Action::ClearQuery) => {
+                    model.query_clear();
+                    refilter_schedule.postpone();
+                }
+                Some(Action::RecallPreviousQuery) => {
+                    model.recall_previous_query();
+                    refilter_schedule.postpone();
+                }
+                Some(Action::RecallNextQuery) => {
+                    model.recall_next_query();
+                    refilter_schedule.postpone();
+                }
+                Some(Action::CursorBackward) => model.cursor_backward(),
+                Some(Action::CursorForward) => model.cursor_forward(),
+                Some(Action::CursorStart) => model.cursor_start(),
+                Some(Action::CursorEnd) => model.cursor_end(),
+                Some(Action::ToggleHidden) => model.toggle_hidden(),
+                Some(Action::ToggleMetadata) => model.toggle_metadata(),
+                Some(Action::SortByModified) => {
+                    model.toggle_sort_order(config::SortOrder::Modified)
+                }
+                Some(Action::SortBySize) => model.toggle_sort_order(config::SortOrder::Size),
+                Some(Action::SortByGroup) => model.toggle_sort_order(config::SortOrder::Group),
+                Some(Action::CycleSortOrder) => model.cycle_sort_order(),
+                Some(Action::Refresh) => model.refresh(),
+                Some(Action::ScrollLeft) => model.scroll_left(),
+                Some(Action::ScrollRight) => model.scroll_right(),
+                Some(Action::JumpToLabel) => {
+                    jump = Some(labels::Jump::new(model.visible_count()));
+                }
+                Some(Action::ToggleProfiler) => profiler.toggle(),
+                Some(Action::CopyToClipboard) => {
+                    if let Some(r) = model.get_selected_result() {
+                        message = Some(
+                            match clipboard::copy(
+                                &mut term_canvas,
+                                &r.text,
+                                clipboard_command.as_deref(),
+                            ) {
+                                Result::Ok(()) => format!("Copied {}", r.text),
+                                Result::Err(e) => format!("Failed to copy: {}", e),
+                            },
+                        );
+                    }
+                }
 
-                _ => {}
+                None => {
+                    if let Char(c) = key {
+                        if !model.descend_on_separator(c) {
+                            model.query_insert(c);
+                            refilter_schedule.postpone();
+                        }
+                    }
+                }
             };
             req_sender.as_ref().unwrap().send(()).unwrap();
+            redraw = true;
+        }
+
+        if refilter_schedule.take_due(false) {
+            refilter(&mut model, trace.as_mut());
+            redraw = true;
+        }
+        if let Some(t) = &mut trace {
+            t.observe_scoring(&model.metrics());
         }
     }
 
+    title_progress.finish(&mut term_canvas)?;
+
     req_sender.take();
     key_reader_thread.join().unwrap();
 
-    clear()?;
-    terminal::restore(original_terminal_state)?;
+    clear(&mut term_canvas, height)?;
+    backend.restore()?;
+
+    if let (Some(t), Some(p)) = (&trace, &config.log.trace_path) {
+        if let Result::Err(e) = t.save(std::path::Path::new(p), &model.metrics()) {
+            warn!("Failed to save the session trace: {}", e);
+        }
+    }
+
+    let query = model.query();
+    if let Some(p) = query_history_path.filter(|_| !result.cancelled && !query.trim().is_empty()) {
+        if let Result::Err(e) = history_file::save(&p, &query, config.ui.query_history) {
+            warn!("Failed to save the query history: {}", e);
+        }
+    }
+
+    Result::Ok(result)
+}
+
+/// Filters the completions with the query, tracing how long it took.
+fn refilter(model: &mut model::Model, trace: Option<&mut trace::SessionTrace>) {
+    let start = time::Instant::now();
+    model.refilter();
+    if let Some(t) = trace {
+        t.filter(&model.query(), start.elapsed());
+    }
+}
 
-    return Result::Ok(result);
+/// Fetches the completions of the current tab, tracing how long it
+/// took if any were fetched.
+fn fetch_completions(model: &mut model::Model, trace: Option<&mut trace::SessionTrace>) {
+    let trace = match trace {
+        Some(t) => t,
+        None => return model.fetch_completions(),
+    };
+    let fetched = model.metrics().swap_remove(model.tab_index()).fetched;
+    let start = time::Instant::now();
+    model.fetch_completions();
+    let duration = start.elapsed();
+    let metrics = model.metrics().swap_remove(model.tab_index());
+    if metrics.fetched != fetched || metrics.fetching_finished {
+        trace.fetch(&metrics.name, metrics.fetched, duration);
+    }
+}
+
+/// Runs the command line of a completion action with the terminal in
+/// its normal state, returning the canvas to draw the chooser on
+/// afterwards, and a message if the command failed.
+fn run_command(
+    backend: &mut dyn backend::Backend,
+    mut term_canvas: canvas::TermCanvas,
+    height: usize,
+    command_line: &str,
+) -> io::Result<(canvas::TermCanvas, Option<String>)> {
+    term_canvas.clear()?;
+    term_canvas.present()?;
+    backend.restore()?;
+    let status = actions::run(command_line);
+    backend.prepare(false)?;
+    let term_canvas = canvas::TermCanvas::new(backend, height + HEADER_HEIGHT)?;
+    let failure = match status {
+        Result::Ok(s) if s.success() => None,
+        Result::Ok(s) => Some(format!("{} failed: {}", command_line, s)),
+        Result::Err(e) => Some(format!("Failed to run {}: {}", command_line, e)),
+    };
+    Result::Ok((term_canvas, failure))
 }
 
-pub fn clear() -> io::Result<()> {
-    let mut term = termion::get_tty()?;
-    for _ in 0..(CHOOSER_HEIGHT + 1) {
-        write!(term, "{}{}", clear::CurrentLine, termion::cursor::Down(1))?;
+/// Clears the lines of a chooser displaying `height` completions.
+pub fn clear<W: Write>(term: &mut W, height: usize) -> io::Result<()> {
+    for _ in 0..(height + HEADER_HEIGHT) {
+        write!(term, "{}{}", ansi::CLEAR_LINE, ansi::Down(1))?;
     }
     write!(
         term,
         "{}{}",
-        termion::cursor::Left(100),
-        termion::cursor::Up((CHOOSER_HEIGHT + 1) as u16)
+        ansi::Left(100),
+        ansi::Up((height + HEADER_HEIGHT) as u16)
     )?;
-    return Result::Ok(());
+    term.flush()
 }
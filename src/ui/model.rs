@@ -1,11 +1,21 @@
 use std::cmp;
+use std::time;
 
-use itertools::Itertools;
-
+use crate::completers::levels;
 use crate::config::*;
 use crate::core;
 use crate::scoring;
 
+use super::scorer;
+use super::text;
+
+/// The maximum number of candidates scored directly on the UI thread;
+/// larger collections are scored by the background scorer.
+const BACKGROUND_SCORING_THRESHOLD: usize = 8192;
+
+/// The number of columns by which completions are scrolled at a time.
+const HORIZONTAL_SCROLL_STEP: usize = 8;
+
 #[derive(Clone, Copy)]
 struct CompletionScore {
     /// The index of the completion in the 'all_completions' vector.
@@ -15,6 +25,253 @@ struct CompletionScore {
     score: scoring::Score,
 }
 
+/// Orders completion scores from the highest score; completions with
+/// equal scores are kept in the order in which they were fetched.
+fn by_score(a: &CompletionScore, b: &CompletionScore) -> cmp::Ordering {
+    b.score.cmp(&a.score).then(a.index.cmp(&b.index))
+}
+
+/// A function ordering completion scores, the first ones first.
+type Comparator<'a> = &'a dyn Fn(&CompletionScore, &CompletionScore) -> cmp::Ordering;
+
+/// Orders completion scores in the given sort order, and by score
+/// where that order does not distinguish them.
+fn compare(
+    sort_order: SortOrder,
+    completions: &[core::CompletionBox],
+    a: &CompletionScore,
+    b: &CompletionScore,
+) -> cmp::Ordering {
+    let metadata = |sc: &CompletionScore| completions[sc.index].file_metadata();
+    let ordering = match sort_order {
+        SortOrder::Score => cmp::Ordering::Equal,
+        SortOrder::Alphabetical => {
+            let search_string = |sc: &CompletionScore| completions[sc.index].search_string();
+            search_string(a).cmp(&search_string(b))
+        }
+        // Completions without metadata come last.
+        SortOrder::Modified => {
+            let modified = |sc: &CompletionScore| metadata(sc).and_then(|m| m.modified);
+            modified(b).cmp(&modified(a))
+        }
+        SortOrder::Size => {
+            let size = |sc: &CompletionScore| metadata(sc).map(|m| m.size);
+            size(b).cmp(&size(a))
+        }
+        SortOrder::Native => a.index.cmp(&b.index),
+        // Completions without a group come last.
+        SortOrder::Group => {
+            let group = |sc: &CompletionScore| {
+                let group = completions[sc.index].group();
+                (group.is_none(), group)
+            };
+            group(a).cmp(&group(b))
+        }
+    };
+    ordering.then_with(|| by_score(a, b))
+}
+
+/// A displayed row of the list of completions.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Row {
+    /// The header of the group of the completions below it, which
+    /// can't be selected.
+    Header(String),
+
+    /// The completion at the given position in the sorted completions.
+    Completion(usize),
+}
+
+/// A collection of the completions matching the query, sorted lazily.
+///
+/// Only the completions which are displayed need to be in order, so
+/// instead of sorting all of them, the best ones are selected and
+/// sorted, and the rest is sorted gradually as the user scrolls down.
+///
+/// The first completions may be frozen, so that completions added
+/// later are sorted after them even if they come before them, and
+/// the displayed rows don't move under the user.
+struct ScoredCompletions {
+    /// All matching completions. The first 'sorted_count' of them are
+    /// sorted, and none of the others comes before any of them, except
+    /// for the first 'frozen_count' ones.
+    completions: Vec<CompletionScore>,
+
+    sorted_count: usize,
+
+    /// The number of completions kept in place when new ones are added.
+    frozen_count: usize,
+
+    /// The number of completions displayed at once, which are sorted
+    /// at least at a time.
+    page_size: usize,
+}
+
+impl ScoredCompletions {
+    fn new(page_size: usize) -> ScoredCompletions {
+        ScoredCompletions {
+            completions: Vec::new(),
+            sorted_count: 0,
+            frozen_count: 0,
+            page_size,
+        }
+    }
+
+    /// Adds new completions, keeping the currently sorted part sorted.
+    fn extend(&mut self, completions: Vec<CompletionScore>, compare: Comparator) {
+        let sorted_count = self.sorted_count;
+        self.completions.extend(completions);
+        self.sorted_count = self.frozen_count;
+        self.sort_up_to(cmp::max(sorted_count, self.page_size), compare);
+    }
+
+    /// Forgets the order of the completions, e.g. when the order in
+    /// which they should be sorted changes. This unfreezes them.
+    fn reset_order(&mut self) {
+        self.sorted_count = 0;
+        self.frozen_count = 0;
+    }
+
+    /// Keeps the first `count` completions in place, as far as they
+    /// are sorted, when new ones are added.
+    fn freeze(&mut self, count: usize) {
+        self.frozen_count = cmp::min(count, self.sorted_count);
+    }
+
+    fn is_frozen(&self) -> bool {
+        self.frozen_count > 0
+    }
+
+    /// Returns the position of the completion with the given index in
+    /// 'all_completions', making sure that the completions up to it
+    /// are sorted, or None if it doesn't match the query.
+    fn locate(&mut self, index: usize, compare: Comparator) -> Option<usize> {
+        let found = self.completions.iter().position(|sc| sc.index == index)?;
+        if found < self.sorted_count {
+            return Some(found);
+        }
+        // Completions are ordered totally, so the completion ends up
+        // after exactly those which come before it.
+        let target = self.completions[found];
+        let position = self.sorted_count
+            + self.completions[self.sorted_count..]
+                .iter()
+                .filter(|sc| compare(sc, &target) == cmp::Ordering::Less)
+                .count();
+        self.sort_up_to(position + 1, compare);
+        Some(position)
+    }
+
+    /// Makes sure that the first `count` completions are sorted.
+    fn sort_up_to(&mut self, count: usize, compare: Comparator) {
+        if count <= self.sorted_count {
+            return;
+        }
+        // Sorting at least a page at a time avoids selecting the best
+        // completions out of the rest on every step down.
+        let unsorted = &mut self.completions[self.sorted_count..];
+        let to_sort = cmp::max(count - self.sorted_count, self.page_size);
+        if to_sort < unsorted.len() {
+            unsorted.select_nth_unstable_by(to_sort, compare);
+            unsorted[..to_sort].sort_unstable_by(compare);
+            self.sorted_count += to_sort;
+        } else {
+            unsorted.sort_unstable_by(compare);
+            self.sorted_count = self.completions.len();
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<CompletionScore> {
+        debug_assert!(index < self.sorted_count || index >= self.completions.len());
+        self.completions.get(index).copied()
+    }
+
+    fn len(&self) -> usize {
+        self.completions.len()
+    }
+
+    fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.completions.iter().map(|sc| sc.index)
+    }
+}
+
+#[test]
+fn test_scored_completions() {
+    let scores = |s: &[scoring::Score]| {
+        s.iter()
+            .enumerate()
+            .map(|(index, &score)| CompletionScore { index, score })
+            .collect::<Vec<_>>()
+    };
+    let new = |completions: Vec<CompletionScore>| {
+        let mut sc = ScoredCompletions::new(5);
+        sc.extend(completions, &by_score);
+        sc
+    };
+    let order = |sc: &ScoredCompletions, count: usize| {
+        (0..count)
+            .map(|i| sc.get(i).unwrap().index)
+            .collect::<Vec<_>>()
+    };
+
+    let mut sc = new(scores(&[3, 1, 4, 1, 5]));
+    assert_eq!(order(&sc, 5), vec![4, 2, 0, 1, 3]);
+
+    let many: Vec<scoring::Score> = (0..30).map(|i| i % 7).collect();
+    sc = new(scores(&many));
+    assert_eq!(sc.sorted_count, 5);
+    let mut expected: Vec<CompletionScore> = scores(&many);
+    expected.sort_by(by_score);
+    let expected: Vec<usize> = expected.iter().map(|sc| sc.index).collect();
+    assert_eq!(order(&sc, 5), expected[..5]);
+    // At least a page more is sorted at a time.
+    sc.sort_up_to(6, &by_score);
+    assert_eq!(sc.sorted_count, 10);
+    sc.sort_up_to(many.len(), &by_score);
+    assert_eq!(order(&sc, many.len()), expected);
+
+    sc = new(scores(&[1, 1]));
+    sc.extend(vec![CompletionScore { index: 2, score: 2 }], &by_score);
+    assert_eq!(order(&sc, 3), vec![2, 0, 1]);
+
+    // Frozen completions stay in place.
+    sc.freeze(2);
+    sc.extend(vec![CompletionScore { index: 3, score: 3 }], &by_score);
+    assert_eq!(order(&sc, 4), vec![2, 0, 3, 1]);
+    sc.reset_order();
+    assert!(!sc.is_frozen());
+    assert_eq!(sc.locate(0, &by_score), Some(2));
+    assert_eq!(sc.locate(3, &by_score), Some(0));
+    assert_eq!(sc.locate(7, &by_score), None);
+
+    sc = new(scores(&many));
+    let last = expected[many.len() - 1];
+    assert_eq!(sc.locate(last, &by_score), Some(many.len() - 1));
+    assert_eq!(order(&sc, many.len()), expected);
+}
+
+/// Settings which affect how completions are filtered and scored
+/// against the query.
+#[derive(Clone, Copy, PartialEq)]
+struct FilterSettings {
+    scoring: scoring::ScoringSettings,
+
+    /// Indicates if hidden completions should be shown.
+    show_hidden: bool,
+}
+
+/// Completions scored for a query, kept so that they can be reused
+/// when the query is extended or restored when it is shortened.
+struct ScoredQuery {
+    query: String,
+
+    /// The number of entries in 'all_completions' when the completions
+    /// were scored; completions fetched later are not included.
+    scored_count: usize,
+
+    scored_completions: ScoredCompletions,
+}
+
 struct CompleterView {
     /// The completer which provides the propositions for this view.
     pub completer: Box<dyn core::Completer>,
@@ -26,9 +283,27 @@ struct CompleterView {
     /// The index of the currently selected completion.
     pub selection: usize,
 
+    /// The number of completions displayed at once.
+    height: usize,
+
+    /// The number of columns of the completions scrolled out of view
+    /// on the left.
+    horizontal_offset: usize,
+
+    /// The order of 'scored_completions'.
+    sort_order: SortOrder,
+
+    /// The errors reported by the completer, oldest first.
+    errors: Vec<String>,
+
     /// The current query for this completer.
     pub query: String,
 
+    /// The query typed in the tab and the position of the cursor in
+    /// it, kept while another tab is shown when each tab has its own
+    /// query.
+    typed_query: Option<(String, usize)>,
+
     /// All completions which have been fetched so far.
     ///
     /// This is not affected by the query.
@@ -37,19 +312,77 @@ struct CompleterView {
     /// Completions for the current query.
     ///
     /// This is sorted by score, so that completions with the highest
-    /// score are at the beginning of the vector.
-    scored_completions: Vec<CompletionScore>,
+    /// score are at the beginning, at least as far as the view goes.
+    scored_completions: ScoredCompletions,
+
+    /// The settings used to score 'scored_completions'.
+    scored_with: Option<FilterSettings>,
+
+    /// Scored completions for prefixes of the current query, shortest
+    /// first.
+    ///
+    /// Only completions which match a prefix of the query may match
+    /// the query itself, so extending the query only requires
+    /// re-scoring the completions of the longest cached prefix.
+    query_history: Vec<ScoredQuery>,
+
+    /// The background scorer, started when first needed.
+    scorer: Option<scorer::Scorer>,
+
+    /// The generation of scoring jobs for the current query; results
+    /// of jobs of other generations are discarded.
+    scoring_generation: u64,
+
+    /// The number of jobs of the current generation which are not
+    /// finished yet.
+    pending_scoring_jobs: usize,
+
+    /// The time spent in the completer fetching completions so far.
+    fetch_time: time::Duration,
+
+    /// When scoring the completions for the current query started, if
+    /// it isn't finished yet.
+    scoring_start: Option<time::Instant>,
+
+    /// How long scoring the completions for the query took last.
+    last_scoring_time: Option<time::Duration>,
+
+    /// The number of completions kept at most.
+    max_candidates: usize,
+
+    /// Indicates if fetching stopped because 'max_candidates'
+    /// completions were fetched.
+    truncated: bool,
 }
 
 impl CompleterView {
-    pub fn new(completer: Box<dyn core::Completer>) -> CompleterView {
+    pub fn new(
+        completer: Box<dyn core::Completer>,
+        height: usize,
+        max_candidates: usize,
+    ) -> CompleterView {
         CompleterView {
-            completer: completer,
+            completer,
             view_offset: 0,
             selection: 0,
+            height,
+            horizontal_offset: 0,
+            sort_order: SortOrder::Score,
+            errors: Vec::new(),
             query: "".to_string(),
+            typed_query: None,
             all_completions: Vec::new(),
-            scored_completions: Vec::new(),
+            scored_completions: ScoredCompletions::new(height),
+            scored_with: None,
+            query_history: Vec::new(),
+            scorer: None,
+            scoring_generation: 0,
+            pending_scoring_jobs: 0,
+            fetch_time: time::Duration::ZERO,
+            scoring_start: None,
+            last_scoring_time: None,
+            max_candidates,
+            truncated: false,
         }
     }
 
@@ -59,109 +392,591 @@ impl CompleterView {
             .map(|sc| &*self.all_completions[sc.index] as &dyn core::Completion)
     }
 
+    /// Returns the index in 'all_completions' of the selected completion.
+    fn selected_index(&self) -> Option<usize> {
+        self.scored_completions
+            .get(self.selection)
+            .map(|sc| sc.index)
+    }
+
+    /// Selects the completion with the given index in 'all_completions'
+    /// again after the completions were reordered, scrolling the view to
+    /// it if it moved out of it.
+    fn reselect(&mut self, index: Option<usize>) {
+        let all_completions = &self.all_completions;
+        let sort_order = self.sort_order;
+        let scored_completions = &mut self.scored_completions;
+        let position = index.and_then(|i| {
+            scored_completions.locate(i, &|a, b| compare(sort_order, all_completions, a, b))
+        });
+        if let Some(p) = position {
+            self.selection = p;
+            self.scroll_to_selection();
+        }
+        self.sort_visible_completions();
+    }
+
+    /// Returns the header shown above the completion at the given
+    /// position when sorting by group, if the completion starts a
+    /// group.
+    fn header(&self, position: usize) -> Option<String> {
+        if self.sort_order != SortOrder::Group {
+            return None;
+        }
+        let group = |p: usize| {
+            self.scored_completions
+                .get(p)
+                .and_then(|sc| self.all_completions[sc.index].group())
+        };
+        let current = group(position);
+        let previous = position.checked_sub(1).and_then(group);
+        if current == previous {
+            return None;
+        }
+        Some(current.map_or_else(|| "other".to_owned(), |g| g.name))
+    }
+
+    /// Returns the displayed rows, starting with the completion at
+    /// 'view_offset'. The first completion is displayed even if the
+    /// header of its group doesn't fit.
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = vec![];
+        for position in self.view_offset..self.scored_completions.len() {
+            if let Some(h) = self.header(position) {
+                if rows.len() + 2 <= self.height {
+                    rows.push(Row::Header(h));
+                } else if !rows.is_empty() {
+                    break;
+                }
+            }
+            if rows.len() >= self.height {
+                break;
+            }
+            rows.push(Row::Completion(position));
+        }
+        rows
+    }
+
+    /// Returns the number of displayed completions, which is less than
+    /// the height when headers are displayed among them.
+    fn visible_count(&self) -> usize {
+        self.rows()
+            .iter()
+            .filter(|r| matches!(r, Row::Completion(_)))
+            .count()
+    }
+
+    /// Scrolls the view so that the selected completion is displayed.
+    fn scroll_to_selection(&mut self) {
+        // Each completion takes at least a row, so the view doesn't
+        // start more than a height before the selection.
+        self.view_offset = cmp::max(
+            cmp::min(self.view_offset, self.selection),
+            (self.selection + 1).saturating_sub(self.height),
+        );
+        self.sort_visible_completions();
+        while self.view_offset < self.selection
+            && self.selection >= self.view_offset + self.visible_count()
+        {
+            self.view_offset += 1;
+            self.sort_visible_completions();
+        }
+    }
+
+    /// Lets the completions be reordered again after the user moved the
+    /// selection, keeping the selected completion selected.
+    fn thaw(&mut self) {
+        if !self.scored_completions.is_frozen() {
+            return;
+        }
+        let selected = self.selected_index();
+        self.scored_completions.reset_order();
+        self.reselect(selected);
+    }
+
     pub fn select_previous(&mut self) {
+        self.thaw();
         self.selection = self.selection.saturating_sub(1);
-        if self.selection < self.view_offset {
-            self.view_offset = self.view_offset - 1;
-        }
+        self.scroll_to_selection();
     }
 
     pub fn select_next(&mut self) {
+        self.thaw();
         let completions_count = self.scored_completions.len();
         self.selection = cmp::min(self.selection + 1, completions_count.saturating_sub(1));
-        if self.selection >= self.view_offset + CHOOSER_HEIGHT {
-            self.view_offset = self.view_offset + 1;
-        }
+        self.scroll_to_selection();
     }
 
     pub fn previous_page(&mut self) {
-        self.selection = self.selection.saturating_sub(CHOOSER_HEIGHT);
-        if self.selection < self.view_offset {
-            self.view_offset = self.selection;
-        }
+        self.thaw();
+        self.selection = self.selection.saturating_sub(self.height);
+        self.scroll_to_selection();
     }
 
     pub fn next_page(&mut self) {
+        self.thaw();
         let completions_count = self.scored_completions.len();
-        self.selection = cmp::min(self.selection + CHOOSER_HEIGHT, completions_count - 1);
-        if self.selection >= self.view_offset + CHOOSER_HEIGHT {
-            self.view_offset = self.selection.saturating_sub(CHOOSER_HEIGHT - 1);
-        }
+        self.selection = cmp::min(self.selection + self.height, completions_count - 1);
+        self.scroll_to_selection();
     }
 
     pub fn select_first(&mut self) {
+        self.thaw();
         self.selection = 0;
         self.view_offset = 0;
     }
 
     pub fn select_last(&mut self) {
+        self.thaw();
         let completions_count = self.scored_completions.len();
         self.selection = completions_count - 1;
-        self.view_offset = self.selection.saturating_sub(CHOOSER_HEIGHT - 1);
+        self.scroll_to_selection();
+    }
+
+    /// Selects the displayed completion with the given number, counted
+    /// from the first displayed one without the headers of groups.
+    /// Returns false if there is no such completion.
+    pub fn select_visible(&mut self, row: usize) -> bool {
+        if row >= self.visible_count() {
+            return false;
+        }
+        self.selection = self.view_offset + row;
+        true
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.horizontal_offset = self
+            .horizontal_offset
+            .saturating_sub(HORIZONTAL_SCROLL_STEP);
+    }
+
+    /// Scrolls the completions to the left, unless the longest of the
+    /// displayed ones would be scrolled out of view.
+    pub fn scroll_right(&mut self) {
+        let end = self.view_offset + self.visible_count();
+        let longest = (self.view_offset..end)
+            .filter_map(|i| self.scored_completions.get(i))
+            .map(|sc| {
+                self.all_completions[sc.index]
+                    .display_spans()
+                    .iter()
+                    .map(|s| text::width(&s.text))
+                    .sum()
+            })
+            .max()
+            .unwrap_or(0);
+        if self.horizontal_offset + HORIZONTAL_SCROLL_STEP < longest {
+            self.horizontal_offset += HORIZONTAL_SCROLL_STEP;
+        }
     }
 
-    fn update_query(&mut self, new_query: String) {
+    /// Makes sure that the completions shown in the view are sorted.
+    fn sort_visible_completions(&mut self) {
+        let all_completions = &self.all_completions;
+        let sort_order = self.sort_order;
+        self.scored_completions
+            .sort_up_to(self.view_offset + self.height, &|a, b| {
+                compare(sort_order, all_completions, a, b)
+            });
+    }
+
+    fn set_sort_order(&mut self, sort_order: SortOrder) {
+        if self.sort_order == sort_order {
+            return;
+        }
+        self.sort_order = sort_order;
+        self.scored_completions.reset_order();
+        for h in &mut self.query_history {
+            h.scored_completions.reset_order();
+        }
+        self.sort_visible_completions();
+    }
+
+    fn update_query(&mut self, new_query: String, filter_settings: &FilterSettings) {
+        if self.completer.is_dynamic() && new_query != self.query {
+            self.completer.requery(&new_query);
+            self.query = new_query;
+            self.discard_completions();
+            self.fetch_completions(filter_settings);
+            return;
+        }
         self.selection = 0;
         self.view_offset = 0;
+        // Completions which are still being scored cannot be reused.
+        if self.scored_with == Some(*filter_settings) && self.pending_scoring_jobs == 0 {
+            self.query_history.push(ScoredQuery {
+                query: std::mem::take(&mut self.query),
+                scored_count: self.all_completions.len(),
+                scored_completions: std::mem::replace(
+                    &mut self.scored_completions,
+                    ScoredCompletions::new(self.height),
+                ),
+            });
+        } else {
+            self.query_history.clear();
+        }
+        while self
+            .query_history
+            .last()
+            .is_some_and(|h| !new_query.starts_with(&h.query))
+        {
+            self.query_history.pop();
+        }
         self.query = new_query;
-        self.scored_completions = self.scores(0);
+        self.scored_with = Some(*filter_settings);
+        self.scoring_generation += 1;
+        self.pending_scoring_jobs = 0;
+        self.scoring_start = Some(time::Instant::now());
+
+        let all_count = self.all_completions.len();
+        let candidates: Vec<usize> = match self.query_history.last() {
+            Some(h) if h.query == self.query => {
+                let h = self.query_history.pop().unwrap();
+                self.scored_completions = h.scored_completions;
+                (h.scored_count..all_count).collect()
+            }
+            Some(h) => {
+                self.scored_completions = ScoredCompletions::new(self.height);
+                h.scored_completions
+                    .indices()
+                    .chain(h.scored_count..all_count)
+                    .collect()
+            }
+            None => {
+                self.scored_completions = ScoredCompletions::new(self.height);
+                (0..all_count).collect()
+            }
+        };
+        self.score_candidates(candidates, filter_settings);
     }
 
-    fn fetch_completions(&mut self) {
-        let new_completions = self.completer.fetch_completions();
+    fn fetch_completions(&mut self, filter_settings: &FilterSettings) {
+        if self.truncated {
+            return;
+        }
+        let start = time::Instant::now();
+        let mut new_completions = self.completer.fetch_completions();
+        self.fetch_time += start.elapsed();
+        let room = self
+            .max_candidates
+            .saturating_sub(self.all_completions.len());
+        if new_completions.len() > room {
+            new_completions.truncate(room);
+            self.truncated = true;
+            self.completer.stop();
+        }
+        // The displayed completions stay in place until the user moves
+        // the selection; better ones are listed below them meanwhile.
+        if !new_completions.is_empty() {
+            self.scored_completions
+                .freeze(self.view_offset + self.height);
+        }
+        self.errors.extend(self.completer.take_errors());
         let score_start_index = self.all_completions.len();
-        self.all_completions.extend(new_completions.into_iter());
-        let new_completion_scores = self.scores(score_start_index);
-        let existing_completion_scores = self.scored_completions.drain(..);
-        self.scored_completions = existing_completion_scores
-            .merge_by(new_completion_scores, |a, b| a.score >= b.score)
-            .collect();
+        self.all_completions.extend(new_completions);
+        self.score_candidates(
+            (score_start_index..self.all_completions.len()).collect(),
+            filter_settings,
+        );
+        self.scored_with = Some(*filter_settings);
+    }
+
+    /// Asks the completer to fetch the completions anew, discarding
+    /// those fetched so far if it can.
+    fn refresh(&mut self, filter_settings: &FilterSettings) {
+        if !self.completer.refresh() {
+            return;
+        }
+        self.discard_completions();
+        self.fetch_completions(filter_settings);
+    }
+
+    /// Tells the completer whether hidden completions are shown,
+    /// discarding the completions fetched so far if it is refreshed for
+    /// them. They are fetched anew once the view is shown.
+    fn show_hidden(&mut self, show_hidden: bool) {
+        if self.completer.show_hidden(show_hidden) && self.completer.refresh() {
+            self.discard_completions();
+        }
+    }
+
+    /// Discards the completions fetched so far, e.g. before they are
+    /// fetched anew.
+    fn discard_completions(&mut self) {
+        self.selection = 0;
+        self.view_offset = 0;
+        self.horizontal_offset = 0;
+        self.errors.clear();
+        self.all_completions.clear();
+        self.scored_completions = ScoredCompletions::new(self.height);
+        self.query_history.clear();
+        // Scores of the old completions which are still being
+        // computed are discarded.
+        self.scoring_generation += 1;
+        self.pending_scoring_jobs = 0;
+        self.scoring_start = None;
+        self.fetch_time = time::Duration::ZERO;
+        self.truncated = false;
     }
 
-    fn scores(&self, score_start_index: usize) -> Vec<CompletionScore> {
-        let scoring_settings = scoring::ScoringSettings {
-            letter_match: 1,
-            word_start_bonus: 2,
-            subsequent_bonus: 3,
+    /// Scores the completions with the given indices in 'all_completions',
+    /// adding those matching the query to 'scored_completions'.
+    ///
+    /// Large collections of completions are passed to the background
+    /// scorer; their scores are added in `receive_scores`.
+    fn score_candidates(&mut self, candidates: Vec<usize>, filter_settings: &FilterSettings) {
+        // All completions of a dynamic completer match the query.
+        let query = if self.completer.is_dynamic() {
+            String::new()
+        } else {
+            self.query.clone()
         };
-        let mut completion_scores = self.all_completions[score_start_index..]
-            .iter()
-            .enumerate()
-            .filter(|(_, c)| scoring::subsequence_match(&self.query, &c.search_string()))
-            .map(|(i, c)| CompletionScore {
-                score: scoring::score(&c.search_string(), &self.query, &scoring_settings),
-                index: score_start_index + i,
-            })
-            .collect::<Vec<_>>();
-        completion_scores.sort_by(|a, b| a.score.cmp(&b.score).reverse());
-        completion_scores
+        let (indices, search_strings): (Vec<usize>, Vec<String>) = candidates
+            .into_iter()
+            .map(|i| (i, &self.all_completions[i]))
+            .filter(|(_, c)| filter_settings.show_hidden || !c.is_hidden())
+            .map(|(i, c)| (i, c.search_string()))
+            .unzip();
+        if indices.len() <= BACKGROUND_SCORING_THRESHOLD {
+            let scores = scorer::score(&indices, &search_strings, &query, &filter_settings.scoring);
+            self.add_scores(scores);
+            self.finish_scoring();
+        } else {
+            self.scorer
+                .get_or_insert_with(scorer::Scorer::new)
+                .submit(scorer::Job {
+                    generation: self.scoring_generation,
+                    query,
+                    settings: filter_settings.scoring,
+                    indices,
+                    search_strings,
+                });
+            self.pending_scoring_jobs += 1;
+        }
+    }
+
+    fn add_scores(&mut self, scores: Vec<(usize, scoring::Score)>) {
+        let selected = self.selected_index();
+        let all_completions = &self.all_completions;
+        let sort_order = self.sort_order;
+        self.scored_completions.extend(
+            scores
+                .into_iter()
+                .map(|(index, score)| CompletionScore { index, score })
+                .collect(),
+            &|a, b| compare(sort_order, all_completions, a, b),
+        );
+        self.reselect(selected);
+    }
+
+    /// Adds the scores computed by the background scorer so far.
+    ///
+    /// Returns `true` if any scores were received.
+    fn receive_scores(&mut self) -> bool {
+        let mut received = false;
+        while let Some(chunk) = self.scorer.as_ref().and_then(|s| s.try_recv()) {
+            if chunk.generation != self.scoring_generation {
+                continue;
+            }
+            if chunk.last {
+                self.pending_scoring_jobs -= 1;
+            }
+            self.add_scores(chunk.scores);
+            self.finish_scoring();
+            received = true;
+        }
+        received
+    }
+
+    /// Records how long scoring for the query took, once all the jobs
+    /// are finished.
+    fn finish_scoring(&mut self) {
+        if self.pending_scoring_jobs > 0 {
+            return;
+        }
+        if let Some(start) = self.scoring_start.take() {
+            self.last_scoring_time = Some(start.elapsed());
+        }
+    }
+
+    fn metrics(&self) -> CompleterMetrics {
+        CompleterMetrics {
+            name: self.completer.name(),
+            query: self.query.clone(),
+            fetched: self.all_completions.len(),
+            matched: self.completions_count(),
+            fetching_finished: self.fetching_finished(),
+            fetch_time: self.fetch_time,
+            last_scoring_time: self.last_scoring_time,
+        }
+    }
+
+    fn scoring_finished(&self) -> bool {
+        self.pending_scoring_jobs == 0
+    }
+
+    fn fetching_finished(&self) -> bool {
+        self.truncated || self.completer.fetching_completions_finished()
     }
 
     /// Returns the completion at the specified index in 'scored_completions'
     /// along with its score.
     fn completion_at(&self, index: usize) -> (&dyn core::Completion, scoring::Score) {
-        let sc = self.scored_completions[index];
+        let sc = self.scored_completions.get(index).unwrap();
         (&*self.all_completions[sc.index], sc.score)
     }
 
+    /// Returns the index in 'all_completions' of the directory named by
+    /// the given component of a path: among the least nested ones
+    /// matching it, e.g. `src` rather than `test/src` for `sr`, the one
+    /// named exactly by it or otherwise the only one. Returns None if
+    /// several directories match, as the component is ambiguous.
+    fn find_directory(
+        &self,
+        component: &str,
+        separator: char,
+        filter_settings: &FilterSettings,
+    ) -> Option<usize> {
+        let (indices, names): (Vec<usize>, Vec<String>) = self
+            .all_completions
+            .iter()
+            .enumerate()
+            .filter(|(_, c)| c.kind() == core::CompletionKind::Directory)
+            .filter(|(_, c)| filter_settings.show_hidden || !c.is_hidden())
+            .map(|(i, c)| {
+                let path = c.result_string();
+                let path = path.trim_end_matches(separator);
+                (i, path.rsplit(separator).next().unwrap_or(path).to_owned())
+            })
+            .unzip();
+        let depth = |i: usize| {
+            self.all_completions[i]
+                .result_string()
+                .trim_end_matches(separator)
+                .matches(separator)
+                .count()
+        };
+        let matches: Vec<usize> =
+            scorer::score(&indices, &names, component, &filter_settings.scoring)
+                .into_iter()
+                .map(|(i, _)| i)
+                .collect();
+        let min_depth = matches.iter().map(|i| depth(*i)).min()?;
+        let least_nested: Vec<usize> = matches
+            .into_iter()
+            .filter(|i| depth(*i) == min_depth)
+            .collect();
+        let name = |i: usize| &names[indices.binary_search(&i).unwrap()];
+        match least_nested.iter().find(|i| name(**i) == component) {
+            Some(i) => Some(*i),
+            None if least_nested.len() == 1 => Some(least_nested[0]),
+            None => None,
+        }
+    }
+
     /// Return the number of completions after applying the current query filter.
     fn completions_count(&self) -> usize {
         self.scored_completions.len()
     }
 }
 
+/// Measurements of the work done for the completer of a view, for
+/// diagnosing slowness.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CompleterMetrics {
+    pub name: String,
+    pub query: String,
+
+    /// The number of completions fetched so far.
+    pub fetched: usize,
+
+    /// The number of completions matching the query.
+    pub matched: usize,
+
+    pub fetching_finished: bool,
+
+    /// The time spent in the completer fetching completions so far.
+    pub fetch_time: time::Duration,
+
+    /// How long scoring the completions for the query took last, or
+    /// None if no scoring finished yet.
+    pub last_scoring_time: Option<time::Duration>,
+}
+
+/// The separator of the parts of a breadcrumb trail.
+const BREADCRUMB_SEPARATOR: &str = " \u{25B8} ";
+
+/// Builds the breadcrumb trail from the names and locations of the
+/// completers in a stack, from the bottom one, e.g. `br: main ▸ co`.
+///
+/// A completer with the same name as the one below it, e.g. a
+/// subdirectory in the file system completer, replaces the location
+/// of that completer rather than extending the trail.
+fn breadcrumb(levels: &[(String, Option<String>)]) -> String {
+    let mut parts: Vec<&str> = vec![];
+    for (i, (name, location)) in levels.iter().enumerate() {
+        let replaces_previous = i > 0 && *name == levels[i - 1].0;
+        if replaces_previous && levels[i - 1].1.is_some() {
+            parts.pop();
+        }
+        if let Some(ref l) = *location {
+            parts.push(l);
+        }
+        if i > 0 && !replaces_previous {
+            parts.push(name);
+        }
+    }
+    if parts.is_empty() {
+        levels[0].0.clone()
+    } else {
+        format!("{}: {}", levels[0].0, parts.join(BREADCRUMB_SEPARATOR))
+    }
+}
+
+#[test]
+fn test_breadcrumb() {
+    let level = |name: &str, location: Option<&str>| (name.to_owned(), location.map(String::from));
+    assert_eq!(breadcrumb(&[level("br", None)]), "br");
+    assert_eq!(breadcrumb(&[level("fs", Some("."))]), "fs: .");
+    assert_eq!(
+        breadcrumb(&[level("fs", Some(".")), level("fs", Some("./src/ui"))]),
+        "fs: ./src/ui"
+    );
+    assert_eq!(
+        breadcrumb(&[level("br", None), level("co", Some("origin/main"))]),
+        "br: origin/main \u{25B8} co"
+    );
+}
+
 /// A structure representing a single stack of completers.
 ///
 /// The stack may be expanded by descending into the selected
 /// completer. The completer stack is never empty.
 struct CompleterStack {
     stack: Vec<CompleterView>,
+
+    /// The number of completions displayed at once in each view.
+    height: usize,
+
+    /// The number of completions each view keeps at most.
+    max_candidates: usize,
+
+    /// Indicates if the top view lists the completions of all the
+    /// levels below it.
+    searching_levels: bool,
 }
 
 impl CompleterStack {
-    pub fn new(completer: Box<dyn core::Completer>) -> CompleterStack {
+    pub fn new(
+        completer: Box<dyn core::Completer>,
+        height: usize,
+        max_candidates: usize,
+    ) -> CompleterStack {
         CompleterStack {
-            stack: vec![CompleterView::new(completer)],
+            stack: vec![CompleterView::new(completer, height, max_candidates)],
+            height,
+            max_candidates,
+            searching_levels: false,
         }
     }
 
@@ -173,32 +988,80 @@ impl CompleterStack {
         self.stack.last_mut().unwrap()
     }
 
+    pub fn breadcrumb(&self) -> String {
+        let levels: Vec<(String, Option<String>)> = self
+            .stack
+            .iter()
+            .map(|v| (v.completer.name(), v.completer.location()))
+            .collect();
+        breadcrumb(&levels)
+    }
+
     /// Descends into the selected completion.
     ///
     /// Returns `true` if we descended anywhere, `false` if we stayed in the same view.
-    fn descend(&mut self) -> bool {
-        if let Some(scb) = self.top().selected_completion() {
-            if let Some(descended_completer) = self.top().completer.descend(scb) {
-                let mut new_level = CompleterView::new(descended_completer);
-                new_level.fetch_completions();
-                self.stack.push(new_level);
-                return true;
-            }
+    fn descend(&mut self, filter_settings: &FilterSettings) -> bool {
+        match self.top().selected_index() {
+            Some(index) => self.descend_into(index, filter_settings),
+            None => false,
+        }
+    }
+
+    /// Descends into the completion with the given index in
+    /// 'all_completions' of the top view.
+    fn descend_into(&mut self, index: usize, filter_settings: &FilterSettings) -> bool {
+        let top = self.top();
+        if let Some(descended_completer) = top.completer.descend(&*top.all_completions[index]) {
+            let mut new_level =
+                CompleterView::new(descended_completer, self.height, self.max_candidates);
+            new_level.fetch_completions(filter_settings);
+            self.stack.push(new_level);
+            return true;
         }
         false
     }
 
-    fn ascend(&mut self) {
-        if self.stack.len() == 1 {
+    fn ascend(&mut self, filter_settings: &FilterSettings) {
+        if self.searching_levels {
+            self.searching_levels = false;
+            self.stack.pop();
+        } else if self.stack.len() == 1 {
             if let Some(new_completer) = self.top().completer.ascend() {
-                let mut new_level = CompleterView::new(new_completer);
-                new_level.fetch_completions();
+                let mut new_level =
+                    CompleterView::new(new_completer, self.height, self.max_candidates);
+                new_level.fetch_completions(filter_settings);
                 self.stack[0] = new_level;
             }
         } else {
             self.stack.pop();
         }
     }
+
+    /// Starts listing the completions fetched so far at all the levels
+    /// in a single view, or stops it, returning to the top level.
+    fn toggle_level_search(&mut self, filter_settings: &FilterSettings) {
+        if self.searching_levels {
+            self.searching_levels = false;
+            self.stack.pop();
+            return;
+        }
+        let levels = self
+            .stack
+            .iter()
+            .map(|v| {
+                let name = v.completer.location().unwrap_or_else(|| v.completer.name());
+                (name, &v.all_completions[..])
+            })
+            .collect();
+        let mut view = CompleterView::new(
+            Box::new(levels::LevelsCompleter::new(levels)),
+            self.height,
+            self.max_candidates,
+        );
+        view.fetch_completions(filter_settings);
+        self.stack.push(view);
+        self.searching_levels = true;
+    }
 }
 
 /// A structure representing the entire model of the data necessary to
@@ -218,18 +1081,77 @@ pub struct Model {
 
     /// The current query.
     query: String,
+
+    /// The position of the cursor within the query, as a byte index.
+    cursor: usize,
+
+    /// Indicates if the query was edited since the completions were
+    /// last filtered.
+    query_changed: bool,
+
+    /// The settings used for filtering and scoring completions.
+    filter_settings: FilterSettings,
+
+    /// The order in which completions are listed.
+    sort_order: SortOrder,
+
+    /// Indicates if the metadata of files is shown next to them.
+    show_metadata: bool,
+
+    /// Indicates if each tab has its own query.
+    per_tab_queries: bool,
+
+    /// Indicates if the tabs got the initial query, which happens once
+    /// when each tab has its own query.
+    seeded: bool,
+
+    /// The queries accepted before, from the most recent one.
+    query_history: Vec<String>,
+
+    /// The position in 'query_history' of the recalled query, if one
+    /// was recalled.
+    recalled: Option<usize>,
+
+    /// The query typed before the first query was recalled, restored
+    /// when recalling past the most recent query.
+    typed_query: String,
 }
 
 impl Model {
-    pub fn new(completers: Vec<Box<dyn core::Completer>>) -> Model {
+    /// Creates a model for the given completers, displaying `height`
+    /// completions at once.
+    ///
+    /// Completers which are not applicable get no tabs, unless none of
+    /// them is applicable.
+    pub fn new(completers: Vec<Box<dyn core::Completer>>, config: &Config, height: usize) -> Model {
+        let (applicable, inapplicable): (Vec<_>, Vec<_>) =
+            completers.into_iter().partition(|c| c.is_applicable());
+        let completers = if applicable.is_empty() {
+            inapplicable
+        } else {
+            applicable
+        };
         let mut stacks = vec![];
         for c in completers {
-            stacks.push(CompleterStack::new(c));
+            stacks.push(CompleterStack::new(c, height, config.ui.max_candidates));
         }
         Model {
-            stacks: stacks,
+            stacks,
             selection: 0,
             query: "".to_string(),
+            cursor: 0,
+            query_changed: false,
+            filter_settings: FilterSettings {
+                scoring: config.scoring,
+                show_hidden: config.fs.show_hidden,
+            },
+            sort_order: config.ui.sort_order,
+            show_metadata: config.ui.metadata_columns,
+            per_tab_queries: config.ui.per_tab_queries,
+            seeded: false,
+            query_history: vec![],
+            recalled: None,
+            typed_query: String::new(),
         }
     }
 
@@ -253,18 +1175,34 @@ impl Model {
         self.current_view().completer.name()
     }
 
+    /// Returns the breadcrumb trail of the current tab, showing where
+    /// in the hierarchy of completers the user is.
+    pub fn breadcrumb(&self) -> String {
+        self.current_stack().breadcrumb()
+    }
+
     pub fn completion_at(&self, index: usize) -> (&dyn core::Completion, scoring::Score) {
         self.current_view().completion_at(index)
     }
 
+    /// Returns the errors reported by the completer of the current
+    /// view, oldest first.
+    pub fn errors(&self) -> &[String] {
+        &self.current_view().errors
+    }
+
     pub fn completions_count(&self) -> usize {
         self.current_view().completions_count()
     }
 
-    pub fn get_selected_result(&self) -> Option<String> {
+    pub fn selected_completion(&self) -> Option<&dyn core::Completion> {
+        self.current_view().selected_completion()
+    }
+
+    pub fn get_selected_result(&self) -> Option<core::CompletionResult> {
         self.current_view()
             .selected_completion()
-            .map(|c| c.result_string())
+            .map(|c| core::CompletionResult::from_result_string(&c.result_string()))
     }
 
     pub fn view_offset(&self) -> usize {
@@ -275,6 +1213,20 @@ impl Model {
         self.current_view().selection
     }
 
+    /// Returns the number of columns of the completions scrolled out
+    /// of view on the left.
+    pub fn horizontal_offset(&self) -> usize {
+        self.current_view().horizontal_offset
+    }
+
+    pub fn scroll_left(&mut self) {
+        self.current_view_mut().scroll_left();
+    }
+
+    pub fn scroll_right(&mut self) {
+        self.current_view_mut().scroll_right();
+    }
+
     pub fn select_previous(&mut self) {
         self.current_view_mut().select_previous();
     }
@@ -299,23 +1251,145 @@ impl Model {
         self.current_view_mut().select_last();
     }
 
+    /// Returns the displayed rows of completions and of the headers of
+    /// their groups.
+    pub fn rows(&self) -> Vec<Row> {
+        self.current_view().rows()
+    }
+
+    /// Returns the number of displayed completions.
+    pub fn visible_count(&self) -> usize {
+        self.current_view().visible_count()
+    }
+
+    /// Selects the displayed completion with the given number, counted
+    /// from the first displayed one without the headers of groups.
+    /// Returns false if there is no such completion.
+    pub fn select_visible(&mut self, row: usize) -> bool {
+        self.current_view_mut().select_visible(row)
+    }
+
+    /// Selects the completion displayed in the given row and returns
+    /// its result, or None if there is no such row.
+    pub fn accept_visible(&mut self, row: usize) -> Option<core::CompletionResult> {
+        if self.select_visible(row) {
+            self.get_selected_result()
+        } else {
+            None
+        }
+    }
+
     fn update_query(&mut self) {
+        self.descend_query_path();
         let query: String = self.query.clone();
-        self.current_view_mut().update_query(query);
+        let filter_settings = self.filter_settings;
+        let sort_order = self.sort_order;
+        self.query_changed = false;
+        let view = self.current_view_mut();
+        view.set_sort_order(sort_order);
+        view.update_query(query, &filter_settings);
     }
 
+    /// Removes the character before the cursor.
+    ///
+    /// The completions are not filtered until `refilter` is called.
     pub fn query_backspace(&mut self) {
-        self.query.pop();
-        self.update_query();
+        if let Some(ch) = self.query[..self.cursor].chars().next_back() {
+            self.cursor -= ch.len_utf8();
+            self.query.remove(self.cursor);
+            self.query_changed = true;
+        }
     }
 
-    pub fn query_append(&mut self, ch: char) {
-        self.query.push(ch);
-        self.update_query()
+    /// Removes the character under the cursor.
+    ///
+    /// The completions are not filtered until `refilter` is called.
+    pub fn query_delete(&mut self) {
+        if self.cursor < self.query.len() {
+            self.query.remove(self.cursor);
+            self.query_changed = true;
+        }
+    }
+
+    /// Removes the word before the cursor, along with the whitespace
+    /// following it.
+    ///
+    /// The completions are not filtered until `refilter` is called.
+    pub fn query_delete_word(&mut self) {
+        let before_cursor = self.query[..self.cursor].trim_end();
+        let word_start = before_cursor.rfind(char::is_whitespace).map_or(0, |p| {
+            p + before_cursor[p..].chars().next().unwrap().len_utf8()
+        });
+        if word_start < self.cursor {
+            self.query.replace_range(word_start..self.cursor, "");
+            self.cursor = word_start;
+            self.query_changed = true;
+        }
+    }
+
+    /// Removes the entire query.
+    ///
+    /// The completions are not filtered until `refilter` is called.
+    pub fn query_clear(&mut self) {
+        if !self.query.is_empty() {
+            self.query.clear();
+            self.cursor = 0;
+            self.query_changed = true;
+        }
+    }
+
+    /// Inserts a character at the cursor.
+    ///
+    /// The completions are not filtered until `refilter` is called.
+    pub fn query_insert(&mut self, ch: char) {
+        self.query.insert(self.cursor, ch);
+        self.cursor += ch.len_utf8();
+        self.query_changed = true;
+    }
+
+    pub fn cursor_backward(&mut self) {
+        if let Some(ch) = self.query[..self.cursor].chars().next_back() {
+            self.cursor -= ch.len_utf8();
+        }
+    }
+
+    pub fn cursor_forward(&mut self) {
+        if let Some(ch) = self.query[self.cursor..].chars().next() {
+            self.cursor += ch.len_utf8();
+        }
+    }
+
+    pub fn cursor_start(&mut self) {
+        self.cursor = 0;
+    }
+
+    pub fn cursor_end(&mut self) {
+        self.cursor = self.query.len();
+    }
+
+    /// Returns the position of the cursor within the query, counted
+    /// in characters.
+    pub fn cursor_column(&self) -> usize {
+        self.query[..self.cursor].chars().count()
+    }
+
+    /// Filters the completions with the query, if it was edited since
+    /// they were last filtered.
+    pub fn refilter(&mut self) {
+        if self.query_changed {
+            self.update_query();
+        }
     }
 
     pub fn query_set(&mut self, query: &str) {
         self.query = query.to_string();
+        self.cursor = self.query.len();
+        if self.per_tab_queries && !self.seeded {
+            self.seeded = true;
+            for stack in &mut self.stacks {
+                stack.top_mut().typed_query = Some((query.to_owned(), query.len()));
+            }
+        }
         self.update_query()
     }
 
@@ -323,38 +1397,1076 @@ impl Model {
         self.query.clone()
     }
 
+    /// Sets the queries accepted before, from the most recent one, which
+    /// the user may recall.
+    pub fn set_query_history(&mut self, queries: Vec<String>) {
+        self.query_history = queries;
+        self.recalled = None;
+    }
+
+    /// Replaces the query with a recalled one, placing the cursor at its
+    /// end.
+    ///
+    /// The completions are not filtered until `refilter` is called.
+    fn replace_query(&mut self, query: String) {
+        self.query = query;
+        self.cursor = self.query.len();
+        self.query_changed = true;
+    }
+
+    /// Replaces the query with the previous, i.e. older, query in the
+    /// history which differs from it.
+    pub fn recall_previous_query(&mut self) {
+        let start = self.recalled.map_or(0, |r| r + 1);
+        let found = self.query_history[start.min(self.query_history.len())..]
+            .iter()
+            .position(|q| *q != self.query);
+        if let Some(offset) = found {
+            if self.recalled.is_none() {
+                self.typed_query = self.query.clone();
+            }
+            self.recalled = Some(start + offset);
+            let query = self.query_history[start + offset].clone();
+            self.replace_query(query);
+        }
+    }
+
+    /// Replaces the query with the next, i.e. more recent, query in the
+    /// history, or with the typed one past the most recent query.
+    pub fn recall_next_query(&mut self) {
+        match self.recalled {
+            None => (),
+            Some(0) => {
+                self.recalled = None;
+                let query = std::mem::take(&mut self.typed_query);
+                self.replace_query(query);
+            }
+            Some(r) => {
+                self.recalled = Some(r - 1);
+                let query = self.query_history[r - 1].clone();
+                self.replace_query(query);
+            }
+        }
+    }
+
     pub fn descend(&mut self) {
-        let descended = self.current_stack_mut().descend();
+        let filter_settings = self.filter_settings;
+        let descended = self.current_stack_mut().descend(&filter_settings);
         if descended {
             self.query_set("");
         }
     }
 
+    /// Descends into the directory named by the query when the
+    /// separator of paths of the completer is typed at the end of the
+    /// query, clearing the query. Returns `false`, in which case the
+    /// character is to be added to the query, if the query names no
+    /// directory, or the completions aren't all fetched yet.
+    ///
+    /// A directory is named by the query as by a component of a path
+    /// query, so typing the separator never descends where typing the
+    /// rest of a path wouldn't; see `descend_query_path`.
+    pub fn descend_on_separator(&mut self, ch: char) -> bool {
+        if self.query.is_empty()
+            || self.cursor != self.query.len()
+            || self.current_view().completer.path_separator() != Some(ch)
+        {
+            return false;
+        }
+        let filter_settings = self.filter_settings;
+        let view = self.current_view();
+        if !view.fetching_finished() {
+            return false;
+        }
+        match view.find_directory(&self.query, ch, &filter_settings) {
+            Some(directory) => {
+                self.current_view_mut().reselect(Some(directory));
+                self.descend();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Descends into the directories named by the leading components
+    /// of a query with the separator of paths of the completer, e.g.
+    /// into `src` and then `ui` for `src/ui/mo`, leaving the last
+    /// component as the query. Each component matches the names of
+    /// directories fuzzily, and is descended into only if it names one
+    /// directory unambiguously, as when the separator is typed after
+    /// it; see `find_directory`. Components which name no directory,
+    /// or which are typed before all the completions are fetched, are
+    /// kept in the query, e.g. `s/` for both `src` and `scripts`.
+    ///
+    /// Returns `true` if any directory was descended into.
+    fn descend_query_path(&mut self) -> bool {
+        let filter_settings = self.filter_settings;
+        let mut descended = false;
+        while let Some(separator) = self.current_view().completer.path_separator() {
+            let (component, rest) = match self.query.split_once(separator) {
+                Some(split) => split,
+                None => break,
+            };
+            // Absolute and relative paths aren't components.
+            if component.is_empty() || component == "." || component == ".." {
+                break;
+            }
+            let view = self.current_view();
+            let directory = if view.fetching_finished() {
+                view.find_directory(component, separator, &filter_settings)
+            } else {
+                None
+            };
+            let removed = self.query.len() - rest.len();
+            let rest = rest.to_owned();
+            match directory {
+                Some(d) if self.current_stack_mut().descend_into(d, &filter_settings) => {
+                    self.query = rest;
+                    self.cursor = self.cursor.saturating_sub(removed);
+                    descended = true;
+                }
+                _ => break,
+            }
+        }
+        descended
+    }
+
     pub fn ascend(&mut self) {
-        self.current_stack_mut().ascend()
+        let filter_settings = self.filter_settings;
+        let sort_order = self.sort_order;
+        self.current_stack_mut().ascend(&filter_settings);
+        self.current_view_mut().set_sort_order(sort_order);
     }
 
-    pub fn next_tab(&mut self) {
-        // We preserve the query when switching tabs in order
-        // to retain the initial query when the user switches
-        // between tabs at the beginning.
-        self.selection = (self.selection + 1) % self.stacks.len();
+    /// Starts searching the completions of all the levels of the
+    /// current tab at once, keeping the query, or stops it.
+    pub fn toggle_level_search(&mut self) {
+        let filter_settings = self.filter_settings;
+        self.current_stack_mut()
+            .toggle_level_search(&filter_settings);
         self.update_query();
     }
 
+    /// Switches to the tab with the given index. If each tab has its
+    /// own query, the query typed in that tab is restored; otherwise
+    /// the query is preserved, in order to retain the initial query
+    /// when the user switches between tabs at the beginning.
+    fn switch_tab(&mut self, index: usize) {
+        if self.per_tab_queries {
+            let typed_query = (std::mem::take(&mut self.query), self.cursor);
+            self.current_view_mut().typed_query = Some(typed_query);
+            self.selection = index;
+            let (query, cursor) = self
+                .current_view_mut()
+                .typed_query
+                .take()
+                .unwrap_or_default();
+            self.query = query;
+            self.cursor = cursor;
+        } else {
+            self.selection = index;
+        }
+        self.update_query();
+    }
+
+    pub fn next_tab(&mut self) {
+        self.switch_tab((self.selection + 1) % self.stacks.len());
+    }
+
+    pub fn previous_tab(&mut self) {
+        self.switch_tab((self.selection + self.stacks.len() - 1) % self.stacks.len());
+    }
+
+    /// Switches to the tab with the given index, if there is one.
+    pub fn select_tab(&mut self, index: usize) {
+        if index < self.stacks.len() && index != self.selection {
+            self.switch_tab(index);
+        }
+    }
+
+    /// Indicates if each tab has its own query.
+    pub fn per_tab_queries(&self) -> bool {
+        self.per_tab_queries
+    }
+
+    /// Returns the names of the completers in all tabs.
+    pub fn tab_names(&self) -> Vec<String> {
+        self.stacks
+            .iter()
+            .map(|s| s.top().completer.name())
+            .collect()
+    }
+
+    /// Returns the index of the current tab.
+    pub fn tab_index(&self) -> usize {
+        self.selection
+    }
+
     pub fn start_fetching_completions(&mut self) {
         for stack in &mut self.stacks {
-            stack.top_mut().fetch_completions();
+            stack.top_mut().fetch_completions(&self.filter_settings);
         }
+        self.refilter_query_path();
     }
 
     pub fn fetch_completions(&mut self) {
-        self.current_view_mut().fetch_completions();
+        let filter_settings = self.filter_settings;
+        self.current_view_mut().fetch_completions(&filter_settings);
+        self.refilter_query_path();
+    }
+
+    /// Descends into the directories named in the query which were
+    /// fetched only now, filtering the completions of the directory
+    /// descended into.
+    fn refilter_query_path(&mut self) {
+        if self.descend_query_path() {
+            self.update_query();
+        }
+    }
+
+    /// Fetches the completions of the current view anew.
+    pub fn refresh(&mut self) {
+        let filter_settings = self.filter_settings;
+        self.current_view_mut().refresh(&filter_settings);
+    }
+
+    /// Replaces the scoring settings, re-scoring the completions in the
+    /// current view.
+    pub fn set_scoring_settings(&mut self, scoring_settings: scoring::ScoringSettings) {
+        if self.filter_settings.scoring != scoring_settings {
+            self.filter_settings.scoring = scoring_settings;
+            self.update_query();
+        }
+    }
+
+    pub fn sort_order(&self) -> SortOrder {
+        self.sort_order
+    }
+
+    /// Switches to the next sort order.
+    pub fn cycle_sort_order(&mut self) {
+        self.sort_order = self.sort_order.next();
+        let sort_order = self.sort_order;
+        self.current_view_mut().set_sort_order(sort_order);
+    }
+
+    /// Switches to the given sort order, or back to sorting by score
+    /// if it is already used.
+    pub fn toggle_sort_order(&mut self, sort_order: SortOrder) {
+        self.sort_order = if self.sort_order == sort_order {
+            SortOrder::Score
+        } else {
+            sort_order
+        };
+        let sort_order = self.sort_order;
+        self.current_view_mut().set_sort_order(sort_order);
+    }
+
+    pub fn show_metadata(&self) -> bool {
+        self.show_metadata
+    }
+
+    /// Toggles showing the metadata of files next to them.
+    pub fn toggle_metadata(&mut self) {
+        self.show_metadata = !self.show_metadata;
+    }
+
+    /// Toggles showing hidden completions, re-filtering the current view.
+    /// The views whose completers need to fetch anew for hidden
+    /// completions, e.g. to search hidden directories, are refreshed.
+    pub fn toggle_hidden(&mut self) {
+        self.filter_settings.show_hidden = !self.filter_settings.show_hidden;
+        let show_hidden = self.filter_settings.show_hidden;
+        for stack in &mut self.stacks {
+            for view in &mut stack.stack {
+                view.show_hidden(show_hidden);
+            }
+        }
+        self.update_query();
+    }
+
+    /// Adds the scores computed in the background so far, returning
+    /// `true` if the view changed.
+    pub fn receive_scores(&mut self) -> bool {
+        self.current_view_mut().receive_scores()
+    }
+
+    /// Indicates if all completions fetched so far have been scored.
+    pub fn scoring_finished(&self) -> bool {
+        self.current_view().scoring_finished()
     }
 
     pub fn fetching_completions_finished(&self) -> bool {
-        self.current_view()
-            .completer
-            .fetching_completions_finished()
+        self.current_view().fetching_finished()
+    }
+
+    /// Indicates if fetching the completions of the current view
+    /// stopped because the maximum number of them was fetched.
+    pub fn truncated(&self) -> bool {
+        self.current_view().truncated
+    }
+
+    /// Returns the measurements of the current view of each tab.
+    pub fn metrics(&self) -> Vec<CompleterMetrics> {
+        self.stacks.iter().map(|s| s.top().metrics()).collect()
+    }
+}
+
+#[test]
+fn test_query_editing() {
+    let mut model = Model::new(vec![], &Config::default(), CHOOSER_HEIGHT);
+    for ch in "foo bär".chars() {
+        model.query_insert(ch);
+    }
+    model.cursor_backward();
+    model.cursor_backward();
+    assert_eq!(model.cursor_column(), 5);
+    model.query_insert('x');
+    assert_eq!(model.query(), "foo bxär");
+    model.query_delete();
+    model.query_backspace();
+    assert_eq!(model.query(), "foo br");
+    model.cursor_end();
+    model.cursor_forward();
+    model.query_delete();
+    assert_eq!(model.cursor_column(), 6);
+
+    model.query_insert(' ');
+    model.query_delete_word();
+    assert_eq!(model.query(), "foo ");
+    model.cursor_start();
+    model.query_backspace();
+    model.query_delete_word();
+    assert_eq!(model.query(), "foo ");
+    assert_eq!(model.cursor_column(), 0);
+    model.query_clear();
+    assert_eq!(model.query(), "");
+}
+
+#[test]
+fn test_query_recall() {
+    use crate::completers::git;
+    let mut model = Model::new(
+        vec![Box::new(git::GitBranchCompleter::default())],
+        &Config::default(),
+        CHOOSER_HEIGHT,
+    );
+    model.query_set("doc");
+    // Nothing happens without a history.
+    model.recall_previous_query();
+    assert_eq!(model.query(), "doc");
+
+    model.set_query_history(vec![
+        "doc".to_owned(),
+        "docker-compose".to_owned(),
+        "main".to_owned(),
+    ]);
+    // Queries equal to the current one are skipped.
+    model.recall_previous_query();
+    assert_eq!(model.query(), "docker-compose");
+    model.recall_previous_query();
+    assert_eq!(model.query(), "main");
+    model.recall_previous_query();
+    assert_eq!(model.query(), "main");
+    assert_eq!(model.cursor_column(), 4);
+    model.recall_next_query();
+    assert_eq!(model.query(), "docker-compose");
+    // Past the most recent query, the typed one is restored.
+    model.recall_next_query();
+    assert_eq!(model.query(), "doc");
+    model.recall_next_query();
+    assert_eq!(model.query(), "doc");
+}
+
+#[test]
+fn test_tabs() {
+    use crate::completers::git;
+    let completers: Vec<Box<dyn core::Completer>> = vec![
+        Box::new(git::GitBranchCompleter::default()),
+        Box::new(git::GitBranchCompleter::default()),
+        Box::new(git::GitBranchCompleter::default()),
+    ];
+    let mut model = Model::new(completers, &Config::default(), CHOOSER_HEIGHT);
+    assert_eq!(model.tab_names(), vec!["br", "br", "br"]);
+    model.previous_tab();
+    assert_eq!(model.tab_index(), 2);
+    model.next_tab();
+    assert_eq!(model.tab_index(), 0);
+    model.select_tab(1);
+    assert_eq!(model.tab_index(), 1);
+    model.select_tab(8);
+    assert_eq!(model.tab_index(), 1);
+}
+
+#[test]
+fn test_per_tab_queries() {
+    use crate::completers::git;
+    let completers = || -> Vec<Box<dyn core::Completer>> {
+        vec![
+            Box::new(git::GitBranchCompleter::default()),
+            Box::new(git::GitBranchCompleter::default()),
+            Box::new(git::GitBranchCompleter::default()),
+        ]
+    };
+
+    // The query is shared by default.
+    let mut model = Model::new(completers(), &Config::default(), CHOOSER_HEIGHT);
+    model.query_set("ma");
+    model.next_tab();
+    model.query_insert('i');
+    model.previous_tab();
+    assert_eq!(model.query(), "mai");
+
+    let mut config = Config::default();
+    config.ui.per_tab_queries = true;
+    let mut model = Model::new(completers(), &config, CHOOSER_HEIGHT);
+    assert!(model.per_tab_queries());
+    model.query_set("ma");
+    model.next_tab();
+    // Every tab starts with the initial query.
+    assert_eq!(model.query(), "ma");
+    model.query_insert('i');
+    model.cursor_start();
+    model.previous_tab();
+    assert_eq!(model.query(), "ma");
+    model.select_tab(2);
+    assert_eq!(model.query(), "ma");
+    model.query_clear();
+    model.select_tab(1);
+    assert_eq!(
+        (model.query(), model.cursor_column()),
+        ("mai".to_owned(), 0)
+    );
+    model.next_tab();
+    assert_eq!(model.query(), "");
+}
+
+#[test]
+fn test_sort_order() {
+    use std::any;
+    use std::time;
+
+    struct SizedCompletion(Option<u64>);
+    impl core::Completion for SizedCompletion {
+        fn result_string(&self) -> String {
+            format!("{:?}", self.0)
+        }
+        fn file_metadata(&self) -> Option<core::FileMetadata> {
+            self.0.map(|size| core::FileMetadata {
+                is_dir: false,
+                size,
+                modified: Some(time::UNIX_EPOCH + time::Duration::from_secs(size)),
+                mode: None,
+            })
+        }
+        fn as_any(&self) -> &dyn any::Any {
+            self
+        }
+    }
+
+    let completions: Vec<core::CompletionBox> = vec![
+        Box::new(SizedCompletion(Some(10))),
+        Box::new(SizedCompletion(None)),
+        Box::new(SizedCompletion(Some(20))),
+        Box::new(SizedCompletion(Some(10))),
+    ];
+    let scores = [1, 3, 0, 2];
+    let sorted = |sort_order: SortOrder| {
+        let mut sc: Vec<CompletionScore> = scores
+            .iter()
+            .enumerate()
+            .map(|(index, &score)| CompletionScore { index, score })
+            .collect();
+        sc.sort_by(|a, b| compare(sort_order, &completions, a, b));
+        sc.iter().map(|sc| sc.index).collect::<Vec<usize>>()
+    };
+    assert_eq!(sorted(SortOrder::Score), vec![1, 3, 0, 2]);
+    assert_eq!(sorted(SortOrder::Size), vec![2, 3, 0, 1]);
+    assert_eq!(sorted(SortOrder::Modified), vec![2, 3, 0, 1]);
+    assert_eq!(sorted(SortOrder::Alphabetical), vec![1, 3, 0, 2]);
+    assert_eq!(sorted(SortOrder::Native), vec![0, 1, 2, 3]);
+}
+
+#[test]
+fn test_errors() {
+    struct FailingCompleter;
+    impl core::Completer for FailingCompleter {
+        fn name(&self) -> String {
+            "fail".to_owned()
+        }
+        fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+            vec![]
+        }
+        fn take_errors(&mut self) -> Vec<String> {
+            vec!["permission denied".to_owned()]
+        }
+    }
+
+    let mut model = Model::new(
+        vec![Box::new(FailingCompleter)],
+        &Config::default(),
+        CHOOSER_HEIGHT,
+    );
+    assert!(model.errors().is_empty());
+    model.start_fetching_completions();
+    model.fetch_completions();
+    assert_eq!(model.errors(), ["permission denied", "permission denied"]);
+}
+
+#[test]
+fn test_inapplicable_tabs() {
+    struct NamedCompleter(&'static str, bool);
+    impl core::Completer for NamedCompleter {
+        fn name(&self) -> String {
+            self.0.to_owned()
+        }
+        fn is_applicable(&self) -> bool {
+            self.1
+        }
+        fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+            vec![]
+        }
+    }
+
+    let completers: Vec<Box<dyn core::Completer>> = vec![
+        Box::new(NamedCompleter("fs", true)),
+        Box::new(NamedCompleter("br", false)),
+        Box::new(NamedCompleter("npm", true)),
+    ];
+    let mut model = Model::new(completers, &Config::default(), CHOOSER_HEIGHT);
+    assert_eq!(model.tab_names(), vec!["fs", "npm"]);
+    model.next_tab();
+    assert_eq!(model.completer_name(), "npm");
+    model.next_tab();
+    assert_eq!(model.completer_name(), "fs");
+
+    // Without any applicable completers, all of them are shown.
+    let completers: Vec<Box<dyn core::Completer>> = vec![
+        Box::new(NamedCompleter("br", false)),
+        Box::new(NamedCompleter("pkg", false)),
+    ];
+    let model = Model::new(completers, &Config::default(), CHOOSER_HEIGHT);
+    assert_eq!(model.tab_names(), vec!["br", "pkg"]);
+}
+
+#[test]
+fn test_refresh() {
+    use super::testing::words;
+    use super::testing::WordCompleter;
+
+    // Every refresh lists one more word, named after the refresh.
+    let completer = WordCompleter::new("gen", words(&["0-0"])).refreshing(|refreshes| {
+        let names: Vec<String> = (0..refreshes + 1)
+            .map(|i| format!("{}-{}", refreshes, i))
+            .collect();
+        words(&names)
+    });
+    let mut model = Model::new(
+        vec![Box::new(completer)],
+        &Config::default(),
+        CHOOSER_HEIGHT,
+    );
+    model.start_fetching_completions();
+    assert_eq!(model.completions_count(), 1);
+    model.refresh();
+    assert_eq!(model.completions_count(), 2);
+    assert_eq!(model.completion_at(0).0.result_string(), "1-0");
+    model.query_insert('1');
+    model.refilter();
+    model.refresh();
+    assert_eq!(model.completions_count(), 1);
+    assert_eq!(model.completion_at(0).0.result_string(), "2-1");
+}
+
+#[test]
+fn test_toggle_hidden() {
+    use super::testing::words;
+    use super::testing::WordCompleter;
+
+    let completer = WordCompleter::new("fs", words(&["a"]))
+        .refreshing(|_| words(&["a", "b"]))
+        .hiding();
+    let mut model = Model::new(
+        vec![Box::new(completer)],
+        &Config::default(),
+        CHOOSER_HEIGHT,
+    );
+    model.start_fetching_completions();
+    assert_eq!(model.completions_count(), 1);
+    model.toggle_hidden();
+    assert_eq!(model.completions_count(), 0);
+    model.fetch_completions();
+    assert_eq!(model.completions_count(), 2);
+    // Hiding them again keeps the completions.
+    model.toggle_hidden();
+    model.fetch_completions();
+    assert_eq!(model.completions_count(), 2);
+}
+
+#[test]
+fn test_incremental_refilter() {
+    use super::testing::words;
+    use super::testing::WordCompleter;
+
+    const WORDS: [&str; 12] = [
+        "src",
+        "scripts",
+        "source",
+        "resources",
+        "sr",
+        "s-r-c",
+        "README",
+        "srv/cache",
+        "ssrc",
+        "xsr",
+        "sxr",
+        "tests",
+    ];
+    let new_model = || {
+        let completer = WordCompleter::new("words", words(&WORDS));
+        Model::new(vec![Box::new(completer)], &Config::default(), WORDS.len())
+    };
+    let results = |model: &Model| {
+        (0..model.completions_count())
+            .map(|i| model.completion_at(i).0.result_string())
+            .collect::<Vec<_>>()
+    };
+    // The completions matching a query, scored anew from all of them.
+    let rescored = |query: &str| {
+        let mut model = new_model();
+        model.query_set(query);
+        model.start_fetching_completions();
+        results(&model)
+    };
+
+    let mut model = new_model();
+    model.query_set("");
+    model.start_fetching_completions();
+    let check = |model: &mut Model, query: &str| {
+        model.refilter();
+        assert_eq!(model.query(), query);
+        assert_eq!(results(model), rescored(query), "query {:?}", query);
+    };
+    // Extending the query re-scores the previous matches.
+    for (i, ch) in "src".chars().enumerate() {
+        model.query_insert(ch);
+        check(&mut model, &"src"[..i + 1]);
+    }
+    // Shortening it restores them.
+    model.query_backspace();
+    check(&mut model, "sr");
+    model.query_backspace();
+    check(&mut model, "s");
+    model.query_insert('r');
+    check(&mut model, "sr");
+    // Editing it in the middle makes it no extension of the previous
+    // queries.
+    model.cursor_backward();
+    model.query_insert('x');
+    check(&mut model, "sxr");
+    model.query_backspace();
+    check(&mut model, "sr");
+    model.cursor_start();
+    model.query_insert('x');
+    check(&mut model, "xsr");
+}
+
+#[test]
+fn test_dynamic_completer() {
+    use super::testing::Word;
+    use super::testing::WordCompleter;
+
+    // Searches for the query, finding it along with a word which
+    // doesn't match it.
+    let completer = WordCompleter::new("search", vec![])
+        .dynamic(|query| vec![Word::new(format!("{}-found", query)), Word::new("other")]);
+    let mut model = Model::new(
+        vec![Box::new(completer)],
+        &Config::default(),
+        CHOOSER_HEIGHT,
+    );
+    model.query_set("ab");
+    model.start_fetching_completions();
+    assert_eq!(model.completions_count(), 2);
+    assert_eq!(model.completion_at(0).0.result_string(), "ab-found");
+    model.query_insert('c');
+    model.refilter();
+    assert_eq!(model.completions_count(), 2);
+    assert_eq!(model.completion_at(0).0.result_string(), "abc-found");
+    // Switching back to the same query doesn't search again.
+    model.next_tab();
+    assert_eq!(model.completions_count(), 2);
+}
+
+#[test]
+fn test_horizontal_scrolling() {
+    use super::testing::words;
+    use super::testing::WordCompleter;
+
+    let completer = WordCompleter::new("words", words(&["short", "a-word-of-20-columns"]));
+    let mut model = Model::new(
+        vec![Box::new(completer)],
+        &Config::default(),
+        CHOOSER_HEIGHT,
+    );
+    model.start_fetching_completions();
+    model.scroll_left();
+    assert_eq!(model.horizontal_offset(), 0);
+    model.scroll_right();
+    model.scroll_right();
+    assert_eq!(model.horizontal_offset(), 16);
+    // The longest completion stays in view.
+    model.scroll_right();
+    assert_eq!(model.horizontal_offset(), 16);
+    model.scroll_left();
+    assert_eq!(model.horizontal_offset(), 8);
+}
+
+#[test]
+fn test_sticky_selection() {
+    use super::testing::words;
+    use super::testing::WordCompleter;
+
+    let completer =
+        WordCompleter::batches("batches", vec![words(&["xaxb", "axxb"]), words(&["ab"])]);
+    let mut model = Model::new(
+        vec![Box::new(completer)],
+        &Config::default(),
+        CHOOSER_HEIGHT,
+    );
+    model.query_set("ab");
+    model.start_fetching_completions();
+    let result = |model: &Model, i: usize| model.completion_at(i).0.result_string();
+    let (first, second) = (result(&model, 0), result(&model, 1));
+    model.select_next();
+
+    // The better match arriving later is listed below the displayed
+    // completions, which stay in place.
+    model.fetch_completions();
+    assert_eq!(model.completions_count(), 3);
+    assert_eq!(result(&model, 2), "ab");
+    assert_eq!(model.selection(), 1);
+    assert_eq!(result(&model, 1), second);
+
+    // Moving the selection reorders the completions, starting from the
+    // selected one.
+    model.select_previous();
+    assert_eq!(result(&model, 0), "ab");
+    assert_eq!(model.selected_completion().unwrap().result_string(), first);
+}
+
+#[test]
+fn test_group_headers() {
+    use super::testing::Word;
+    use super::testing::WordCompleter;
+
+    let refs = WordCompleter::new(
+        "refs",
+        vec![
+            Word::new("HEAD~1"),
+            Word::new("v1").with_group(2, "tags"),
+            Word::new("main").with_group(0, "branches"),
+            Word::new("origin/main").with_group(1, "remote branches"),
+            Word::new("dev").with_group(0, "branches"),
+        ],
+    );
+
+    let mut config = Config::default();
+    config.ui.sort_order = SortOrder::Group;
+    let mut model = Model::new(vec![Box::new(refs)], &config, 4);
+    model.query_set("");
+    model.start_fetching_completions();
+    let results = |model: &Model| {
+        (0..model.completions_count())
+            .map(|i| model.completion_at(i).0.result_string())
+            .collect::<Vec<_>>()
+    };
+    let header = |name: &str| Row::Header(name.to_owned());
+    // The header of the next group doesn't fit with a completion.
+    assert_eq!(
+        model.rows(),
+        vec![header("branches"), Row::Completion(0), Row::Completion(1)]
+    );
+
+    // Headers are skipped when moving the selection, and scrolling
+    // keeps the selected completion displayed.
+    model.select_next();
+    model.select_next();
+    assert_eq!(model.selection(), 2);
+    assert_eq!(
+        model.rows(),
+        vec![
+            Row::Completion(1),
+            header("remote branches"),
+            Row::Completion(2)
+        ]
+    );
+    model.select_last();
+    assert_eq!(
+        results(&model),
+        vec!["main", "dev", "origin/main", "v1", "HEAD~1"]
+    );
+    assert_eq!(
+        model.rows(),
+        vec![
+            header("tags"),
+            Row::Completion(3),
+            header("other"),
+            Row::Completion(4)
+        ]
+    );
+    assert_eq!(model.visible_count(), 2);
+    assert!(model.select_visible(0));
+    assert_eq!(model.selection(), 3);
+    assert!(!model.select_visible(2));
+
+    // Without sorting by group, there are no headers.
+    model.cycle_sort_order();
+    model.select_first();
+    assert_eq!(model.rows().len(), 4);
+    assert!(!model.rows().contains(&header("tags")));
+}
+
+#[test]
+fn test_level_search() {
+    use super::testing::words;
+    use super::testing::WordCompleter;
+
+    // Lists branches, and descends into their commits.
+    let refs = WordCompleter::new("refs", words(&["main", "dev"])).descending(|branch| {
+        let commits = [format!("fix {}", branch), "add tests".to_owned()];
+        Some(WordCompleter::new("refs", words(&commits)).at(branch))
+    });
+
+    let mut model = Model::new(vec![Box::new(refs)], &Config::default(), 10);
+    model.query_set("");
+    model.start_fetching_completions();
+    model.descend();
+    model.query_set("main");
+    assert_eq!(model.completions_count(), 1);
+
+    model.toggle_level_search();
+    assert_eq!(model.breadcrumb(), "refs: main \u{25B8} levels");
+    assert_eq!(model.query(), "main");
+    assert_eq!(
+        (0..model.completions_count())
+            .map(|i| model.completion_at(i).0.display_string())
+            .collect::<Vec<_>>(),
+        vec!["refs main", "main fix main"]
+    );
+    assert_eq!(
+        model.get_selected_result().map(|r| r.text),
+        Some("main".to_owned())
+    );
+
+    // Ascending stops the search, returning to the level it started at.
+    model.ascend();
+    assert_eq!(model.breadcrumb(), "refs: main");
+    model.toggle_level_search();
+    model.toggle_level_search();
+    assert_eq!(model.breadcrumb(), "refs: main");
+    assert_eq!(model.completions_count(), 1);
+}
+
+#[test]
+fn test_descend_on_separator() {
+    use super::testing::Word;
+    use super::testing::WordCompleter;
+
+    // Lists a directory with a few entries, and descends into empty
+    // ones.
+    let dir = WordCompleter::new(
+        "fs",
+        vec![
+            Word::new("src").with_kind(core::CompletionKind::Directory),
+            Word::new("scripts").with_kind(core::CompletionKind::Directory),
+            Word::new("src.txt").with_kind(core::CompletionKind::File),
+        ],
+    )
+    .with_path_separator('/')
+    .descending(|d| {
+        Some(
+            WordCompleter::new("fs", vec![])
+                .at(d)
+                .with_path_separator('/'),
+        )
+    });
+
+    let mut model = Model::new(vec![Box::new(dir)], &Config::default(), 10);
+    model.query_set("");
+    model.start_fetching_completions();
+
+    // Several directories match.
+    model.query_insert('s');
+    assert!(!model.descend_on_separator('/'));
+
+    // The query is filtered before descending.
+    model.query_insert('r');
+    model.query_insert('c');
+    assert!(!model.descend_on_separator('x'));
+    assert!(model.descend_on_separator('/'));
+    assert_eq!(model.breadcrumb(), "fs: src");
+    assert_eq!(model.query(), "");
+
+    // Nothing matches an empty query.
+    assert!(!model.descend_on_separator('/'));
+}
+
+#[test]
+fn test_query_path() {
+    use super::testing::Word;
+    use super::testing::WordCompleter;
+
+    /// Lists the entries of a tree of directories under a directory,
+    /// like the file system completer.
+    fn tree(dir: &str) -> WordCompleter {
+        let entries = [
+            ("test", core::CompletionKind::Directory),
+            ("test/src", core::CompletionKind::Directory),
+            ("src", core::CompletionKind::Directory),
+            ("src/ui", core::CompletionKind::Directory),
+            ("src/ui/keys.rs", core::CompletionKind::File),
+            ("src/ui/model.rs", core::CompletionKind::File),
+            ("src/ui/mod.rs", core::CompletionKind::File),
+        ];
+        let prefix = format!("{}/", dir);
+        let listed = entries
+            .iter()
+            .map(|(p, k)| (format!("./{}", p), *k))
+            .filter(|(p, _)| p.starts_with(&prefix))
+            .map(|(p, k)| Word::new(p).with_kind(k))
+            .collect();
+        WordCompleter::new("fs", listed)
+            .at(dir)
+            .with_path_separator('/')
+            .descending(|d| Some(tree(d)))
+    }
+
+    let results = |model: &Model| {
+        (0..model.completions_count())
+            .map(|i| model.completion_at(i).0.result_string())
+            .collect::<Vec<_>>()
+    };
+
+    // The directories are descended into once they are fetched, the
+    // least nested ones first.
+    let mut model = Model::new(vec![Box::new(tree("."))], &Config::default(), 10);
+    model.query_set("sr/u/mo");
+    assert_eq!(model.query(), "sr/u/mo");
+    model.start_fetching_completions();
+    assert_eq!(model.breadcrumb(), "fs: ./src/ui");
+    assert_eq!((model.query(), model.cursor_column()), ("mo".to_owned(), 2));
+    assert_eq!(
+        results(&model),
+        vec!["./src/ui/model.rs", "./src/ui/mod.rs"]
+    );
+
+    // Components are kept in the query while no directory matches.
+    let mut model = Model::new(vec![Box::new(tree("."))], &Config::default(), 10);
+    model.query_set("");
+    model.start_fetching_completions();
+    for ch in "x/".chars() {
+        model.query_insert(ch);
+    }
+    model.refilter();
+    assert_eq!(model.breadcrumb(), "fs: .");
+    assert_eq!(model.query(), "x/");
+    model.query_clear();
+    for ch in "./src/".chars() {
+        model.query_insert(ch);
+    }
+    model.refilter();
+    assert_eq!(model.breadcrumb(), "fs: .");
+    model.query_clear();
+    for ch in "tes/s".chars() {
+        model.query_insert(ch);
     }
+    model.refilter();
+    assert_eq!(model.breadcrumb(), "fs: ./test");
+    assert_eq!(results(&model), vec!["./test/src"]);
+
+    // Typing the separator descends into the same directories as path
+    // components do, and neither descends into ambiguous ones.
+    let mut model = Model::new(vec![Box::new(tree("."))], &Config::default(), 10);
+    model.query_set("");
+    model.start_fetching_completions();
+    let type_keys = |model: &mut Model, keys: &str| {
+        for ch in keys.chars() {
+            if !model.descend_on_separator(ch) {
+                model.query_insert(ch);
+            }
+            model.refilter();
+        }
+    };
+    type_keys(&mut model, "s/");
+    assert_eq!(model.breadcrumb(), "fs: .");
+    assert_eq!(model.query(), "s/");
+    type_keys(&mut model, "u");
+    assert_eq!(model.breadcrumb(), "fs: .");
+    model.query_clear();
+    type_keys(&mut model, "sr/");
+    assert_eq!(model.breadcrumb(), "fs: ./src");
+    assert_eq!(model.query(), "");
+    type_keys(&mut model, "ui/mo");
+    assert_eq!(model.breadcrumb(), "fs: ./src/ui");
+    assert_eq!(model.query(), "mo");
+}
+
+#[test]
+fn test_metrics() {
+    use super::testing::words;
+    use super::testing::WordCompleter;
+
+    let mut model = Model::new(
+        vec![
+            Box::new(WordCompleter::new("a", words(&["foo", "bar", "baz"]))),
+            Box::new(WordCompleter::new("b", words(&["qux"]))),
+        ],
+        &Config::default(),
+        CHOOSER_HEIGHT,
+    );
+    model.start_fetching_completions();
+    let metrics = model.metrics();
+    assert_eq!(
+        metrics
+            .iter()
+            .map(|m| (m.name.as_str(), m.fetched, m.matched))
+            .collect::<Vec<_>>(),
+        vec![("a", 3, 3), ("b", 1, 1)]
+    );
+    assert!(metrics.iter().all(|m| m.fetching_finished));
+    assert_eq!(metrics[0].last_scoring_time, None);
+
+    model.query_set("ba");
+    let metrics = model.metrics();
+    assert_eq!((metrics[0].query.as_str(), metrics[0].matched), ("ba", 2));
+    assert!(metrics[0].last_scoring_time.is_some());
+    assert_eq!(metrics[1].last_scoring_time, None);
+}
+
+#[test]
+fn test_max_candidates() {
+    use super::testing::words;
+    use super::testing::WordCompleter;
+
+    // Returns five words on every fetch, more than are kept.
+    let batches = (0..3)
+        .map(|b| {
+            let names: Vec<String> = (b * 5..b * 5 + 5).map(|i| i.to_string()).collect();
+            words(&names)
+        })
+        .collect();
+    let completer = WordCompleter::batches("endless", batches);
+    let mut config = Config::default();
+    config.ui.max_candidates = 7;
+    let mut model = Model::new(vec![Box::new(completer)], &config, CHOOSER_HEIGHT);
+    model.start_fetching_completions();
+    assert!(!model.truncated());
+    assert!(!model.fetching_completions_finished());
+    model.fetch_completions();
+    assert!(model.truncated());
+    assert!(model.fetching_completions_finished());
+    model.fetch_completions();
+    assert_eq!(model.completions_count(), 7);
+    assert_eq!(model.metrics()[0].fetched, 7);
 }
@@ -0,0 +1,109 @@
+//! Module reporting the progress of fetching completions through the
+//! terminal window title and the progress indicator supported by
+//! terminals such as WezTerm, Windows Terminal or Alacritty.
+//!
+//! The title is set with OSC 0, and the progress indicator with the
+//! OSC 9;4 sequence. The original title is saved and restored with the
+//! XTWINOPS title stack, so that the shell's title comes back once
+//! fetching is finished.
+
+use std::io;
+use std::io::Write;
+
+/// Saves the current window title on the terminal's title stack.
+const PUSH_TITLE: &str = "\x1b[22;0t";
+
+/// Restores the window title saved with `PUSH_TITLE`.
+const POP_TITLE: &str = "\x1b[23;0t";
+
+/// Sets the progress indicator to the "indeterminate" state.
+const PROGRESS_INDETERMINATE: &str = "\x1b]9;4;3;0\x1b\\";
+
+/// Removes the progress indicator.
+const PROGRESS_CLEAR: &str = "\x1b]9;4;0;0\x1b\\";
+
+/// A structure maintaining the window title while completions are
+/// being fetched.
+pub struct TitleProgress {
+    enabled: bool,
+
+    /// Indicates if the title was changed and needs to be restored.
+    active: bool,
+
+    /// The last title written, used to avoid redundant writes.
+    last_title: String,
+}
+
+impl TitleProgress {
+    pub fn new(enabled: bool) -> TitleProgress {
+        TitleProgress {
+            enabled,
+            active: false,
+            last_title: String::new(),
+        }
+    }
+
+    /// Enables or disables reporting, restoring the title if reporting
+    /// is disabled while active.
+    pub fn set_enabled<W: Write>(&mut self, term: &mut W, enabled: bool) -> io::Result<()> {
+        if !enabled {
+            self.finish(term)?;
+        }
+        self.enabled = enabled;
+        Result::Ok(())
+    }
+
+    /// Reports that the completer with the given name has fetched
+    /// `count` completions so far.
+    pub fn update<W: Write>(
+        &mut self,
+        term: &mut W,
+        completer_name: &str,
+        count: usize,
+    ) -> io::Result<()> {
+        if !self.enabled {
+            return Result::Ok(());
+        }
+        if !self.active {
+            write!(term, "{}{}", PUSH_TITLE, PROGRESS_INDETERMINATE)?;
+            self.active = true;
+        }
+        let title = format!("completers: scanning {} ({})", completer_name, count);
+        if title != self.last_title {
+            write!(term, "\x1b]0;{}\x07", title)?;
+            self.last_title = title;
+        }
+        term.flush()
+    }
+
+    /// Restores the original title and removes the progress indicator.
+    pub fn finish<W: Write>(&mut self, term: &mut W) -> io::Result<()> {
+        if !self.active {
+            return Result::Ok(());
+        }
+        write!(term, "{}{}", PROGRESS_CLEAR, POP_TITLE)?;
+        self.active = false;
+        self.last_title.clear();
+        term.flush()
+    }
+}
+
+#[test]
+fn test_title_progress() {
+    let mut out: Vec<u8> = Vec::new();
+    let mut title = TitleProgress::new(false);
+    title.update(&mut out, "fs", 10).unwrap();
+    title.finish(&mut out).unwrap();
+    assert!(out.is_empty());
+
+    title.set_enabled(&mut out, true).unwrap();
+    title.update(&mut out, "fs", 10).unwrap();
+    title.update(&mut out, "fs", 10).unwrap();
+    title.finish(&mut out).unwrap();
+    title.finish(&mut out).unwrap();
+    let expected = format!(
+        "{}{}\x1b]0;completers: scanning fs (10)\x07{}{}",
+        PUSH_TITLE, PROGRESS_INDETERMINATE, PROGRESS_CLEAR, POP_TITLE
+    );
+    assert_eq!(String::from_utf8(out).unwrap(), expected);
+}
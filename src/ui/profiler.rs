@@ -0,0 +1,152 @@
+//! Module implementing the profiling overlay, a panel over the
+//! completions showing live measurements of the work of the chooser,
+//! to help with diagnosing slowness, e.g. in big repositories.
+
+use std::io;
+use std::time;
+
+use crate::ansi;
+
+use super::canvas;
+use super::model;
+use super::text;
+use super::theme;
+
+/// Formats a duration in milliseconds, or a dash if there is none.
+fn format_duration(duration: Option<time::Duration>) -> String {
+    match duration {
+        Some(d) => format!("{:.1} ms", d.as_secs_f64() * 1000.0),
+        None => "-".to_owned(),
+    }
+}
+
+/// The state of the overlay, and the measurements of the chooser which
+/// the model doesn't know about.
+#[derive(Default)]
+pub struct Profiler {
+    shown: bool,
+
+    /// How long drawing the chooser took last.
+    render_time: Option<time::Duration>,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    pub fn toggle(&mut self) {
+        self.shown = !self.shown;
+    }
+
+    pub fn set_render_time(&mut self, render_time: time::Duration) {
+        self.render_time = Some(render_time);
+    }
+
+    /// Returns the lines of the overlay: the measurements of the current
+    /// tab, and the state of fetching of each tab, the current one
+    /// marked.
+    pub fn lines(&self, metrics: &[model::CompleterMetrics], tab_index: usize) -> Vec<String> {
+        let mut lines = vec![];
+        if let Some(current) = metrics.get(tab_index) {
+            lines.push(format!(
+                "fetched {}, matched {}",
+                current.fetched, current.matched
+            ));
+            lines.push(format!(
+                "scoring {}, render {}",
+                format_duration(current.last_scoring_time),
+                format_duration(self.render_time)
+            ));
+        }
+        let name_width = metrics
+            .iter()
+            .map(|m| text::width(&m.name))
+            .max()
+            .unwrap_or(0);
+        for (i, m) in metrics.iter().enumerate() {
+            lines.push(format!(
+                "{}{:width$} {} in {}, {}",
+                if i == tab_index { "* " } else { "  " },
+                m.name,
+                m.fetched,
+                format_duration(Some(m.fetch_time)),
+                if m.fetching_finished {
+                    "done"
+                } else {
+                    "fetching"
+                },
+                width = name_width
+            ));
+        }
+        lines
+    }
+
+    /// Draws the overlay at the right edge of the given rows of the
+    /// canvas, if it is shown. Lines which don't fit are left out.
+    pub fn draw(
+        &self,
+        term_canvas: &mut canvas::TermCanvas,
+        model: &model::Model,
+        first_row: usize,
+        row_count: usize,
+        theme: &theme::Theme,
+    ) -> io::Result<()> {
+        if !self.shown || row_count < 3 {
+            return Result::Ok(());
+        }
+        let mut lines = self.lines(&model.metrics(), model.tab_index());
+        lines.truncate(row_count - 2);
+        let inner_width = lines.iter().map(|l| text::width(l)).max().unwrap_or(0) + 2;
+        let term_width = term_canvas.width();
+        if inner_width + 2 > term_width {
+            return Result::Ok(());
+        }
+        let left = term_width - inner_width - 2;
+        let last_row = first_row + lines.len() + 1;
+        term_canvas.print(&theme.dimmed.fg())?;
+        term_canvas.rectangle(first_row, left, last_row, left + inner_width + 1)?;
+        term_canvas.print(ansi::FG_RESET)?;
+        for (i, line) in lines.iter().enumerate() {
+            term_canvas.move_to(first_row + 1 + i, left + 1)?;
+            term_canvas.print(&format!(
+                " {}{}",
+                line,
+                " ".repeat(inner_width - 1 - text::width(line))
+            ))?;
+        }
+        Result::Ok(())
+    }
+}
+
+#[test]
+fn test_lines() {
+    let metrics = |name: &str, fetched, matched, finished, fetch_ms, scoring_ms: Option<u64>| {
+        model::CompleterMetrics {
+            name: name.to_owned(),
+            query: String::new(),
+            fetched,
+            matched,
+            fetching_finished: finished,
+            fetch_time: time::Duration::from_millis(fetch_ms),
+            last_scoring_time: scoring_ms.map(time::Duration::from_millis),
+        }
+    };
+    let mut profiler = Profiler::new();
+    let all = vec![
+        metrics("fs", 120000, 42, false, 1500, Some(3)),
+        metrics("br", 12, 1, true, 20, None),
+    ];
+    assert_eq!(
+        profiler.lines(&all, 0),
+        vec![
+            "fetched 120000, matched 42",
+            "scoring 3.0 ms, render -",
+            "* fs 120000 in 1500.0 ms, fetching",
+            "  br 12 in 20.0 ms, done",
+        ]
+    );
+    profiler.set_render_time(time::Duration::from_micros(1300));
+    assert_eq!(profiler.lines(&all, 1)[1], "scoring -, render 1.3 ms");
+    assert!(profiler.lines(&[], 0).is_empty());
+}
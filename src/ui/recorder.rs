@@ -0,0 +1,51 @@
+//! Module implementing the key recorder, which shows the canonical
+//! names of keys pressed by the user so that they can be used in the
+//! key bindings in the configuration file.
+
+use std::io;
+use std::io::Write;
+
+use super::backend;
+use super::keys;
+use super::keys::Key;
+
+/// Reads keys from the terminal and prints their names, until ctrl-c
+/// is pressed twice in a row.
+pub fn record_keys(backend: &mut dyn backend::Backend) -> io::Result<()> {
+    backend.prepare(true)?;
+    let result = print_keys(&*backend);
+    backend.restore()?;
+    result
+}
+
+fn print_keys(backend: &dyn backend::Backend) -> io::Result<()> {
+    let mut term = backend.output()?;
+
+    write!(
+        term,
+        "Press the keys to record; press ctrl-c twice to quit.\r\n"
+    )?;
+    term.flush()?;
+
+    let mut previous_key = None;
+    for key in backend.keys()? {
+        let key = key?;
+        match keys::key_name(&key) {
+            Some(name) => {
+                write!(term, "{}", name)?;
+                if let Some(warning) = keys::key_warning(&key) {
+                    write!(term, " (warning: {})", warning)?;
+                }
+                write!(term, "\r\n")?;
+            }
+            None => write!(term, "(this key cannot be bound)\r\n")?,
+        }
+        term.flush()?;
+
+        if key == Key::Ctrl('c') && previous_key == Some(key) {
+            break;
+        }
+        previous_key = Some(key);
+    }
+    Result::Ok(())
+}
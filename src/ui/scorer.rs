@@ -0,0 +1,168 @@
+//! Module scoring completions in a background thread, so that the
+//! chooser stays responsive while large collections are scored.
+//!
+//! Jobs are tagged with a generation number; submitting a job of a
+//! newer generation cancels the jobs of older ones, e.g. when the
+//! query changes before scoring for the previous query is finished.
+//! Results are sent back in chunks as soon as they are ready.
+
+use std::collections::VecDeque;
+use std::sync::mpsc;
+use std::thread;
+
+use crate::scoring;
+
+/// The number of candidates scored before the results are sent and
+/// the thread checks for newer jobs.
+const CHUNK_SIZE: usize = 4096;
+
+/// A request to score a collection of candidates against a query.
+pub struct Job {
+    pub generation: u64,
+    pub query: String,
+    pub settings: scoring::ScoringSettings,
+
+    /// The indices identifying the candidates, and their search
+    /// strings.
+    pub indices: Vec<usize>,
+    pub search_strings: Vec<String>,
+}
+
+/// A part of the results of a job.
+pub struct Chunk {
+    pub generation: u64,
+
+    /// Indices and scores of the matching candidates.
+    pub scores: Vec<(usize, scoring::Score)>,
+
+    /// Indicates if this is the last chunk of the job.
+    pub last: bool,
+}
+
+/// Scores the candidates, returning the indices and scores of those
+/// which match the query.
+pub fn score(
+    indices: &[usize],
+    search_strings: &[String],
+    query: &str,
+    settings: &scoring::ScoringSettings,
+) -> Vec<(usize, scoring::Score)> {
+    scoring::score_many(search_strings, query, settings)
+        .into_iter()
+        .zip(indices)
+        .filter_map(|(score, index)| score.map(|score| (*index, score)))
+        .collect()
+}
+
+/// A handle to the background scoring thread.
+///
+/// The thread finishes when the handle is dropped.
+pub struct Scorer {
+    job_send: mpsc::Sender<Job>,
+    chunk_recv: mpsc::Receiver<Chunk>,
+}
+
+impl Scorer {
+    pub fn new() -> Scorer {
+        let (job_send, job_recv) = mpsc::channel::<Job>();
+        let (chunk_send, chunk_recv) = mpsc::channel::<Chunk>();
+        thread::spawn(move || scoring_thread_routine(job_recv, chunk_send));
+        Scorer {
+            job_send,
+            chunk_recv,
+        }
+    }
+
+    pub fn submit(&self, job: Job) {
+        self.job_send.send(job).unwrap();
+    }
+
+    /// Returns the next chunk of results, if one is ready.
+    pub fn try_recv(&self) -> Option<Chunk> {
+        self.chunk_recv.try_recv().ok()
+    }
+}
+
+impl Default for Scorer {
+    fn default() -> Self {
+        Scorer::new()
+    }
+}
+
+fn scoring_thread_routine(job_recv: mpsc::Receiver<Job>, chunk_send: mpsc::Sender<Chunk>) {
+    let mut pending_jobs: VecDeque<Job> = VecDeque::new();
+    loop {
+        let job = match pending_jobs.pop_front() {
+            Some(j) => j,
+            None => match job_recv.recv() {
+                Result::Ok(j) => j,
+                Result::Err(_) => return,
+            },
+        };
+
+        let chunk_count = job.indices.len().div_ceil(CHUNK_SIZE).max(1);
+        let chunks = job
+            .indices
+            .chunks(CHUNK_SIZE)
+            .zip(job.search_strings.chunks(CHUNK_SIZE));
+        let mut sent_count = 0;
+        for (indices, search_strings) in chunks {
+            pending_jobs.extend(job_recv.try_iter());
+            if pending_jobs.iter().any(|j| j.generation > job.generation) {
+                break;
+            }
+            sent_count += 1;
+            let chunk = Chunk {
+                generation: job.generation,
+                scores: score(indices, search_strings, &job.query, &job.settings),
+                last: sent_count == chunk_count,
+            };
+            if chunk_send.send(chunk).is_err() {
+                return;
+            }
+        }
+        if job.indices.is_empty() {
+            let chunk = Chunk {
+                generation: job.generation,
+                scores: vec![],
+                last: true,
+            };
+            if chunk_send.send(chunk).is_err() {
+                return;
+            }
+        }
+
+        if let Some(newest) = pending_jobs.iter().map(|j| j.generation).max() {
+            pending_jobs.retain(|j| j.generation == newest);
+        }
+    }
+}
+
+#[test]
+fn test_scoring_thread_routine() {
+    let settings = scoring::ScoringSettings::default();
+    let job = |generation: u64, query: &str, count: usize| Job {
+        generation,
+        query: query.to_owned(),
+        settings,
+        indices: (0..count).collect(),
+        search_strings: (0..count).map(|i| format!("file{}", i)).collect(),
+    };
+    let (job_send, job_recv) = mpsc::channel::<Job>();
+    let (chunk_send, chunk_recv) = mpsc::channel::<Chunk>();
+    job_send.send(job(1, "f", 3 * CHUNK_SIZE)).unwrap();
+    job_send.send(job(2, "1", 10)).unwrap();
+    job_send.send(job(3, "1", 2 * CHUNK_SIZE + 1)).unwrap();
+    job_send.send(job(3, "x", 0)).unwrap();
+    drop(job_send);
+    scoring_thread_routine(job_recv, chunk_send);
+
+    let chunks: Vec<Chunk> = chunk_recv.try_iter().collect();
+    assert!(chunks.iter().all(|c| c.generation == 3));
+    assert_eq!(
+        chunks.iter().map(|c| c.last).collect::<Vec<_>>(),
+        vec![false, false, true, true]
+    );
+    assert!(chunks[0].scores.iter().any(|s| s.0 == 1));
+    assert!(chunks[3].scores.is_empty());
+}
@@ -0,0 +1,201 @@
+//! Fixtures of the tests of the chooser and of the completers wrapping
+//! others: completions of words, and a completer listing them which
+//! can be set up to behave like the various kinds of completers, e.g.
+//! streaming, descending or dynamic.
+
+use std::any;
+use std::collections::VecDeque;
+
+use crate::core;
+
+/// A completion of a word, of a given kind and group.
+pub struct Word {
+    text: String,
+    kind: core::CompletionKind,
+    group: Option<core::CompletionGroup>,
+}
+
+impl Word {
+    pub fn new<S: Into<String>>(text: S) -> Word {
+        Word {
+            text: text.into(),
+            kind: core::CompletionKind::Other,
+            group: None,
+        }
+    }
+
+    pub fn with_kind(mut self, kind: core::CompletionKind) -> Word {
+        self.kind = kind;
+        self
+    }
+
+    pub fn with_group(mut self, order: u32, name: &str) -> Word {
+        self.group = Some(core::CompletionGroup::new(order, name));
+        self
+    }
+}
+
+impl core::Completion for Word {
+    fn result_string(&self) -> String {
+        self.text.clone()
+    }
+
+    fn kind(&self) -> core::CompletionKind {
+        self.kind
+    }
+
+    fn group(&self) -> Option<core::CompletionGroup> {
+        self.group.clone()
+    }
+
+    fn as_any(&self) -> &dyn any::Any {
+        self
+    }
+}
+
+/// Returns completions of the given words, of no particular kind.
+pub fn words<S: AsRef<str>>(texts: &[S]) -> Vec<Word> {
+    texts.iter().map(|t| Word::new(t.as_ref())).collect()
+}
+
+/// A completer returning a batch of words on every fetch, until none is
+/// left.
+pub struct WordCompleter {
+    name: String,
+    batches: VecDeque<Vec<Word>>,
+    location: Option<String>,
+    path_separator: Option<char>,
+
+    /// Returns the completer descended into from the completion with
+    /// the given result string.
+    descend: Option<fn(&str) -> Option<WordCompleter>>,
+
+    /// Returns the words listed after the given number of refreshes.
+    refresh: Option<fn(usize) -> Vec<Word>>,
+    refreshes: usize,
+
+    /// Returns the words found for the given query, which makes the
+    /// completer dynamic.
+    requery: Option<fn(&str) -> Vec<Word>>,
+    stopped: bool,
+
+    /// Indicates if the completer needs to be refreshed once hidden
+    /// completions are shown.
+    hiding: bool,
+}
+
+impl WordCompleter {
+    /// Returns a completer listing the given words on its first fetch.
+    pub fn new(name: &str, words: Vec<Word>) -> WordCompleter {
+        WordCompleter::batches(name, vec![words])
+    }
+
+    /// Returns a completer listing a batch of words on every fetch.
+    pub fn batches(name: &str, batches: Vec<Vec<Word>>) -> WordCompleter {
+        WordCompleter {
+            name: name.to_owned(),
+            batches: batches.into_iter().collect(),
+            location: None,
+            path_separator: None,
+            descend: None,
+            refresh: None,
+            refreshes: 0,
+            requery: None,
+            stopped: false,
+            hiding: false,
+        }
+    }
+
+    pub fn at(mut self, location: &str) -> WordCompleter {
+        self.location = Some(location.to_owned());
+        self
+    }
+
+    pub fn with_path_separator(mut self, separator: char) -> WordCompleter {
+        self.path_separator = Some(separator);
+        self
+    }
+
+    pub fn descending(mut self, descend: fn(&str) -> Option<WordCompleter>) -> WordCompleter {
+        self.descend = Some(descend);
+        self
+    }
+
+    pub fn refreshing(mut self, refresh: fn(usize) -> Vec<Word>) -> WordCompleter {
+        self.refresh = Some(refresh);
+        self
+    }
+
+    pub fn dynamic(mut self, requery: fn(&str) -> Vec<Word>) -> WordCompleter {
+        self.requery = Some(requery);
+        self
+    }
+
+    pub fn hiding(mut self) -> WordCompleter {
+        self.hiding = true;
+        self
+    }
+}
+
+impl core::Completer for WordCompleter {
+    fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    fn location(&self) -> Option<String> {
+        self.location.clone()
+    }
+
+    fn path_separator(&self) -> Option<char> {
+        self.path_separator
+    }
+
+    fn fetching_completions_finished(&self) -> bool {
+        self.batches.is_empty()
+    }
+
+    fn fetch_completions(&mut self) -> Vec<core::CompletionBox> {
+        assert!(!self.stopped, "fetched from a stopped completer");
+        self.batches
+            .pop_front()
+            .unwrap_or_default()
+            .into_iter()
+            .map(|w| Box::new(w) as core::CompletionBox)
+            .collect()
+    }
+
+    fn descend(&self, completion: &dyn core::Completion) -> Option<Box<dyn core::Completer>> {
+        let descend = self.descend?;
+        descend(&completion.result_string()).map(|c| Box::new(c) as Box<dyn core::Completer>)
+    }
+
+    fn stop(&mut self) {
+        self.stopped = true;
+        self.batches.clear();
+    }
+
+    fn refresh(&mut self) -> bool {
+        match self.refresh {
+            Some(refresh) => {
+                self.refreshes += 1;
+                self.batches = VecDeque::from(vec![refresh(self.refreshes)]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn show_hidden(&mut self, shown: bool) -> bool {
+        shown && std::mem::take(&mut self.hiding)
+    }
+
+    fn is_dynamic(&self) -> bool {
+        self.requery.is_some()
+    }
+
+    fn requery(&mut self, query: &str) {
+        if let Some(requery) = self.requery {
+            self.batches = VecDeque::from(vec![requery(query)]);
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Module measuring and truncating text shown in the terminal, which
+//! may contain ANSI escape sequences and characters taking two columns,
+//! e.g. CJK ideographs.
+//!
+//! Escape sequences take no columns, and are never cut in half, as the
+//! rest of such a sequence would be shown as text and could leave the
+//! terminal styling the following rows.
+
+use unicode_width::UnicodeWidthChar;
+
+/// The characters starting a control sequence, e.g. one setting colors,
+/// and an operating system command, e.g. a hyperlink.
+const CSI: char = '[';
+const OSC: char = ']';
+
+/// The character ending an operating system command, which may be also
+/// ended with `ESC \`.
+const BEL: char = '\x07';
+
+/// A part of text: an escape sequence, or a single character along
+/// with the number of columns it takes.
+pub enum Part<'a> {
+    Escape(&'a str),
+    Char(char, usize),
+}
+
+/// An iterator over the parts of text.
+pub struct Parts<'a> {
+    text: &'a str,
+}
+
+/// Splits text into escape sequences and characters.
+pub fn parts(text: &str) -> Parts<'_> {
+    Parts { text }
+}
+
+impl<'a> Iterator for Parts<'a> {
+    type Item = Part<'a>;
+
+    fn next(&mut self) -> Option<Part<'a>> {
+        let mut chars = self.text.char_indices();
+        let (_, first) = chars.next()?;
+        if first != '\x1b' {
+            self.text = &self.text[first.len_utf8()..];
+            return Some(Part::Char(first, first.width().unwrap_or(0)));
+        }
+        let end = match chars.next() {
+            Some((_, CSI)) => chars
+                .find(|(_, c)| ('\x40'..='\x7e').contains(c))
+                .map(|(i, c)| i + c.len_utf8()),
+            Some((_, OSC)) => {
+                let mut end = None;
+                let mut previous = OSC;
+                for (i, c) in chars {
+                    if c == BEL || (previous == '\x1b' && c == '\\') {
+                        end = Some(i + c.len_utf8());
+                        break;
+                    }
+                    previous = c;
+                }
+                end
+            }
+            Some((i, c)) => Some(i + c.len_utf8()),
+            None => None,
+        };
+        // An unterminated sequence takes the rest of the text.
+        let end = end.unwrap_or(self.text.len());
+        let (escape, rest) = self.text.split_at(end);
+        self.text = rest;
+        Some(Part::Escape(escape))
+    }
+}
+
+/// Returns the number of columns the text takes in the terminal.
+pub fn width(text: &str) -> usize {
+    parts(text)
+        .map(|p| match p {
+            Part::Escape(_) => 0,
+            Part::Char(_, w) => w,
+        })
+        .sum()
+}
+
+/// Truncates text to `width` columns. Escape sequences are copied whole,
+/// including those after the truncated text, so that styles started in
+/// the text are still reset. Returns the truncated text along with its
+/// width, which is less than `width` if a wide character didn't fit.
+pub fn truncate(text: &str, width: usize) -> (String, usize) {
+    let mut result = String::with_capacity(text.len());
+    let mut result_width = 0;
+    let mut fits = true;
+    for part in parts(text) {
+        match part {
+            Part::Escape(e) => result.push_str(e),
+            Part::Char(c, w) if fits && result_width + w <= width => {
+                result.push(c);
+                result_width += w;
+            }
+            // The following characters are dropped even if they are
+            // narrower, so that no gap is left in the text.
+            Part::Char(..) => fits = false,
+        }
+    }
+    (result, result_width)
+}
+
+/// Removes the first `width` columns of text. Escape sequences are kept,
+/// so that styles started in the removed part still apply. A wide
+/// character cut in half is replaced with a space.
+pub fn skip(text: &str, width: usize) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut skipped = 0;
+    for part in parts(text) {
+        match part {
+            Part::Escape(e) => result.push_str(e),
+            Part::Char(c, _) if skipped >= width => result.push(c),
+            Part::Char(_, w) => {
+                skipped += w;
+                if skipped > width {
+                    result.push(' ');
+                }
+            }
+        }
+    }
+    result
+}
+
+#[test]
+fn test_width() {
+    assert_eq!(width("abc"), 3);
+    assert_eq!(width("żółw"), 4);
+    assert_eq!(width("日本"), 4);
+    assert_eq!(width("\x1b[38;5;1mab\x1b[39m"), 2);
+    assert_eq!(width("\x1b]8;;http://example.com\x07link\x1b]8;;\x1b\\"), 4);
+    assert_eq!(width("a\x1b"), 1);
+}
+
+#[test]
+fn test_truncate() {
+    assert_eq!(truncate("abcdef", 4), ("abcd".to_owned(), 4));
+    assert_eq!(truncate("ab", 4), ("ab".to_owned(), 2));
+    assert_eq!(
+        truncate("\x1b[34mabc\x1b[39mdef", 2),
+        ("\x1b[34mab\x1b[39m".to_owned(), 2)
+    );
+    assert_eq!(truncate("日本語", 5), ("日本".to_owned(), 4));
+    assert_eq!(truncate("日x", 1), ("".to_owned(), 0));
+    assert_eq!(
+        truncate("\x1b]8;;file:///tmp\x07tmp\x1b]8;;\x07", 1),
+        ("\x1b]8;;file:///tmp\x07t\x1b]8;;\x07".to_owned(), 1)
+    );
+}
+
+#[test]
+fn test_skip() {
+    assert_eq!(skip("abcdef", 0), "abcdef");
+    assert_eq!(skip("abcdef", 4), "ef");
+    assert_eq!(skip("abc", 4), "");
+    assert_eq!(skip("\x1b[34mabc\x1b[39mdef", 4), "\x1b[34m\x1b[39mef");
+    assert_eq!(skip("日本語", 3), " 語");
+}
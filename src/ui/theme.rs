@@ -0,0 +1,401 @@
+//! Module with the colors of the user interface: those of a built-in
+//! theme, overridden by the ones set in the configuration.
+//!
+//! Completions don't choose colors themselves; they report their kinds,
+//! which the theme maps to the colors of their icons, and the severities
+//! of their states, which it maps to the colors of their text.
+
+use std::collections::BTreeMap;
+
+use crate::ansi;
+use crate::ansi::Color;
+use crate::config;
+use crate::core;
+use crate::core::CompletionKind;
+use crate::core::Severity;
+use crate::core::SyntaxClass;
+
+/// The accent colors of a built-in theme, which kinds of completions
+/// are shown in.
+struct Accents {
+    blue: Color,
+    cyan: Color,
+    green: Color,
+    yellow: Color,
+    red: Color,
+    violet: Color,
+}
+
+impl Accents {
+    /// Returns the colors of the kinds of completions. Kinds without
+    /// a color, e.g. files, are dimmed.
+    fn kind_colors(&self) -> BTreeMap<CompletionKind, Color> {
+        let mut colors = BTreeMap::new();
+        let groups = [
+            (&[CompletionKind::Directory][..], self.blue),
+            (
+                &[
+                    CompletionKind::Symlink,
+                    CompletionKind::Host,
+                    CompletionKind::Image,
+                    CompletionKind::Application,
+                ][..],
+                self.cyan,
+            ),
+            (
+                &[
+                    CompletionKind::Branch,
+                    CompletionKind::Package,
+                    CompletionKind::PullRequest,
+                ][..],
+                self.green,
+            ),
+            (
+                &[
+                    CompletionKind::Tag,
+                    CompletionKind::Commit,
+                    CompletionKind::Section,
+                ][..],
+                self.yellow,
+            ),
+            (
+                &[
+                    CompletionKind::Process,
+                    CompletionKind::Function,
+                    CompletionKind::Issue,
+                ][..],
+                self.red,
+            ),
+            (
+                &[
+                    CompletionKind::Alias,
+                    CompletionKind::Script,
+                    CompletionKind::Manual,
+                ][..],
+                self.violet,
+            ),
+        ];
+        for (kinds, color) in groups.iter() {
+            for kind in kinds.iter() {
+                colors.insert(*kind, *color);
+            }
+        }
+        colors
+    }
+
+    /// Returns the colors of the classes of tokens of source code.
+    /// Comments are dimmed.
+    fn syntax_colors(&self) -> BTreeMap<SyntaxClass, Color> {
+        let mut colors = BTreeMap::new();
+        colors.insert(SyntaxClass::String, self.green);
+        colors.insert(SyntaxClass::Constant, self.yellow);
+        colors.insert(SyntaxClass::Keyword, self.violet);
+        colors.insert(SyntaxClass::Function, self.blue);
+        colors.insert(SyntaxClass::Type, self.cyan);
+        colors.insert(SyntaxClass::Inserted, self.green);
+        colors.insert(SyntaxClass::Deleted, self.red);
+        colors
+    }
+
+    /// Returns the colors of the severities other than errors, which
+    /// are shown in the color of errors.
+    fn severity_colors(&self, dimmed: Color) -> BTreeMap<Severity, Color> {
+        let mut colors = BTreeMap::new();
+        colors.insert(Severity::Inactive, dimmed);
+        colors.insert(Severity::Warning, self.yellow);
+        colors
+    }
+}
+
+/// The colors of the user interface.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Theme {
+    /// The colors of the text and the background of the selection, or
+    /// None if it is shown in reverse video.
+    pub selection: Option<(Color, Color)>,
+
+    /// The color of secondary text, e.g. descriptions of completions.
+    pub dimmed: Color,
+
+    pub message: Color,
+    pub error: Color,
+
+    kinds: BTreeMap<CompletionKind, Color>,
+    syntax: BTreeMap<SyntaxClass, Color>,
+    severities: BTreeMap<Severity, Color>,
+}
+
+impl Theme {
+    /// Returns a built-in theme.
+    pub fn base(base: config::BaseTheme) -> Theme {
+        let (selection, dimmed, message, error, accents) = match base {
+            config::BaseTheme::Dark => (
+                None,
+                Color::Basic(8),
+                Color::Basic(3),
+                Color::Basic(1),
+                Accents {
+                    blue: Color::Basic(4),
+                    cyan: Color::Basic(6),
+                    green: Color::Basic(2),
+                    yellow: Color::Basic(3),
+                    red: Color::Basic(1),
+                    violet: Color::Basic(4),
+                },
+            ),
+            // Yellow is hard to read on a light background, so magenta
+            // is used in its place.
+            config::BaseTheme::Light => (
+                None,
+                Color::Basic(8),
+                Color::Basic(5),
+                Color::Basic(1),
+                Accents {
+                    blue: Color::Basic(4),
+                    cyan: Color::Basic(6),
+                    green: Color::Basic(2),
+                    yellow: Color::Basic(5),
+                    red: Color::Basic(1),
+                    violet: Color::Basic(4),
+                },
+            ),
+            config::BaseTheme::Solarized => (
+                Some((Color::Rgb(0x93, 0xa1, 0xa1), Color::Rgb(0x07, 0x36, 0x42))),
+                Color::Rgb(0x58, 0x6e, 0x75),
+                Color::Rgb(0xb5, 0x89, 0x00),
+                Color::Rgb(0xdc, 0x32, 0x2f),
+                Accents {
+                    blue: Color::Rgb(0x26, 0x8b, 0xd2),
+                    cyan: Color::Rgb(0x2a, 0xa1, 0x98),
+                    green: Color::Rgb(0x85, 0x99, 0x00),
+                    yellow: Color::Rgb(0xb5, 0x89, 0x00),
+                    red: Color::Rgb(0xcb, 0x4b, 0x16),
+                    violet: Color::Rgb(0x6c, 0x71, 0xc4),
+                },
+            ),
+        };
+        Theme {
+            selection,
+            dimmed,
+            message,
+            error,
+            kinds: accents.kind_colors(),
+            syntax: accents.syntax_colors(),
+            severities: accents.severity_colors(dimmed),
+        }
+    }
+
+    /// Returns the theme set in the configuration.
+    pub fn new(config: &config::ThemeConfig) -> Theme {
+        let mut theme = Theme::base(config.base);
+        if config.selection_fg.is_some() || config.selection_bg.is_some() {
+            let (fg, bg) = theme.selection.unwrap_or((Color::Default, Color::Default));
+            theme.selection = Some((
+                config.selection_fg.unwrap_or(fg),
+                config.selection_bg.unwrap_or(bg),
+            ));
+        }
+        theme.dimmed = config.dimmed.unwrap_or(theme.dimmed);
+        theme.message = config.message.unwrap_or(theme.message);
+        theme.error = config.error.unwrap_or(theme.error);
+        theme
+            .kinds
+            .extend(config.kinds.iter().map(|(k, c)| (*k, *c)));
+        theme
+            .severities
+            .extend(config.severities.iter().map(|(s, c)| (*s, *c)));
+        theme
+    }
+
+    /// Returns the color of the icon of a kind.
+    pub fn kind_color(&self, kind: CompletionKind) -> Color {
+        self.kinds.get(&kind).cloned().unwrap_or(self.dimmed)
+    }
+
+    /// Returns the color of text in a span.
+    pub fn span_color(&self, color: core::SpanColor) -> Color {
+        match color {
+            core::SpanColor::Kind(kind) => self.kind_color(kind),
+            core::SpanColor::Dimmed => self.dimmed,
+            core::SpanColor::Error => self.error,
+            core::SpanColor::Syntax(SyntaxClass::Comment) => self.dimmed,
+            core::SpanColor::Syntax(class) => {
+                self.syntax.get(&class).cloned().unwrap_or(Color::Default)
+            }
+            core::SpanColor::Severity(severity) => self
+                .severities
+                .get(&severity)
+                .cloned()
+                .unwrap_or(self.error),
+        }
+    }
+
+    /// Formats the displayed spans of a completion, the parts without a
+    /// color of their own in the color of its severity, if it has one.
+    pub fn format_completion(&self, completion: &dyn core::Completion) -> String {
+        let mut spans = completion.display_spans();
+        if let Some(severity) = completion.severity() {
+            for span in spans.iter_mut().filter(|s| s.style.color.is_none()) {
+                span.style.color = Some(core::SpanColor::Severity(severity));
+            }
+        }
+        self.format_spans(&spans)
+    }
+
+    /// Formats styled spans of text. The style of each span is reset
+    /// after it, without resetting that of the surrounding text, e.g.
+    /// the background of the selection.
+    pub fn format_spans(&self, spans: &[core::Span]) -> String {
+        let mut result = String::new();
+        for span in spans {
+            let style = span.style;
+            if let Some(c) = style.color {
+                result.push_str(&self.span_color(c).fg());
+            }
+            if style.bold {
+                result.push_str(ansi::BOLD);
+            }
+            if style.underline {
+                result.push_str(ansi::UNDERLINE);
+            }
+            result.push_str(&span.text);
+            if style.underline {
+                result.push_str(ansi::NO_UNDERLINE);
+            }
+            if style.bold {
+                result.push_str(ansi::NORMAL_INTENSITY);
+            }
+            if style.color.is_some() {
+                result.push_str(ansi::FG_RESET);
+            }
+        }
+        result
+    }
+
+    /// Formats selected text, e.g. the row of the selected completion.
+    pub fn select(&self, text: &str) -> String {
+        match self.selection {
+            None => format!("{}{}{}", ansi::INVERT, text, ansi::RESET),
+            // Colored parts of the text reset the color of the text to
+            // the default one, which is replaced with the selection's.
+            Some((fg, bg)) => format!(
+                "{}{}{}{}",
+                fg.fg(),
+                bg.bg(),
+                text.replace(ansi::FG_RESET, &fg.fg()),
+                ansi::RESET
+            ),
+        }
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::new(&config::ThemeConfig::default())
+    }
+}
+
+#[test]
+fn test_theme() {
+    let theme = Theme::default();
+    assert_eq!(theme.kind_color(CompletionKind::Directory), Color::Basic(4));
+    assert_eq!(theme.kind_color(CompletionKind::File), Color::Basic(8));
+    assert_eq!(
+        theme.span_color(core::SpanColor::Syntax(SyntaxClass::String)),
+        Color::Basic(2)
+    );
+    assert_eq!(
+        theme.span_color(core::SpanColor::Syntax(SyntaxClass::Comment)),
+        Color::Basic(8)
+    );
+    assert_eq!(
+        theme.select("a\x1b[31mb\x1b[39m"),
+        "\x1b[7ma\x1b[31mb\x1b[39m\x1b[m"
+    );
+    assert_eq!(
+        theme.format_spans(&[
+            core::Span::styled("src/", core::Style::colored(core::SpanColor::Dimmed)),
+            core::Span::styled(
+                "main.rs",
+                core::Style {
+                    bold: true,
+                    ..core::Style::colored(core::SpanColor::Kind(CompletionKind::Directory))
+                },
+            ),
+            core::Span::plain(" x"),
+        ]),
+        "\x1b[90msrc/\x1b[39m\x1b[34m\x1b[1mmain.rs\x1b[22m\x1b[39m x"
+    );
+
+    let config = config::Config::parse(
+        "[theme]\n\
+         base = \"solarized\"\n\
+         selection_bg = 236\n\
+         error = \"bright-red\"\n\
+         kinds = { directory = \"#0000ff\", file = \"default\" }\n",
+    )
+    .unwrap();
+    let theme = Theme::new(&config.theme);
+    assert_eq!(
+        theme.selection,
+        Some((Color::Rgb(0x93, 0xa1, 0xa1), Color::Indexed(236)))
+    );
+    assert_eq!(theme.error, Color::Basic(9));
+    assert_eq!(theme.message, Color::Rgb(0xb5, 0x89, 0x00));
+    assert_eq!(
+        theme.kind_color(CompletionKind::Directory),
+        Color::Rgb(0, 0, 255)
+    );
+    assert_eq!(theme.kind_color(CompletionKind::File), Color::Default);
+    assert_eq!(
+        theme.select("a\x1b[31mb\x1b[39m"),
+        "\x1b[38;2;147;161;161m\x1b[48;5;236ma\x1b[31mb\x1b[38;2;147;161;161m\x1b[m"
+    );
+
+    assert!(config::Config::parse("[theme]\nmessage = \"purple\"\n").is_err());
+}
+
+#[test]
+fn test_severity_colors() {
+    use std::any;
+
+    struct Unit(Option<Severity>);
+    impl core::Completion for Unit {
+        fn result_string(&self) -> String {
+            "cron.service".to_owned()
+        }
+        fn display_spans(&self) -> Vec<core::Span> {
+            vec![
+                core::Span::plain("cron"),
+                core::Span::styled(".service", core::Style::colored(core::SpanColor::Dimmed)),
+            ]
+        }
+        fn severity(&self) -> Option<Severity> {
+            self.0
+        }
+        fn as_any(&self) -> &dyn any::Any {
+            self
+        }
+    }
+
+    let theme = Theme::default();
+    assert_eq!(
+        theme.format_completion(&Unit(None)),
+        "cron\x1b[90m.service\x1b[39m"
+    );
+    assert_eq!(
+        theme.format_completion(&Unit(Some(Severity::Error))),
+        "\x1b[31mcron\x1b[39m\x1b[90m.service\x1b[39m"
+    );
+    assert_eq!(
+        theme.span_color(core::SpanColor::Severity(Severity::Inactive)),
+        Color::Basic(8)
+    );
+
+    let config = config::Config::parse("[theme.severities]\nwarning = \"magenta\"\n").unwrap();
+    let theme = Theme::new(&config.theme);
+    assert_eq!(
+        theme.span_color(core::SpanColor::Severity(Severity::Warning)),
+        Color::Basic(5)
+    );
+}
@@ -0,0 +1,199 @@
+//! Module tracing sessions of the chooser, to help with diagnosing
+//! slowness: the keys pressed, and how long filtering, fetching,
+//! scoring and drawing the completions took.
+//!
+//! The trace is written as JSON when the chooser closes, to the file
+//! set in the configuration.
+
+use std::fs;
+use std::io;
+use std::path;
+use std::time;
+
+use super::keys;
+use super::model;
+
+/// Returns a duration in milliseconds, with a fractional part.
+fn millis(duration: time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The events of a session, timed from its start.
+pub struct SessionTrace {
+    start: time::Instant,
+    events: Vec<serde_json::Value>,
+
+    /// The last scoring time of each tab which was recorded, so that
+    /// each scoring is recorded once.
+    scoring_times: Vec<Option<time::Duration>>,
+}
+
+impl SessionTrace {
+    pub fn new() -> SessionTrace {
+        SessionTrace {
+            start: time::Instant::now(),
+            events: vec![],
+            scoring_times: vec![],
+        }
+    }
+
+    fn record(&mut self, event: &str, details: serde_json::Value) {
+        let mut record = serde_json::json!({
+            "time_ms": millis(self.start.elapsed()),
+            "event": event,
+        });
+        if let (Some(record), serde_json::Value::Object(details)) =
+            (record.as_object_mut(), details)
+        {
+            record.extend(details);
+        }
+        self.events.push(record);
+    }
+
+    pub fn key(&mut self, key: &keys::Key) {
+        let name = keys::key_name(key).unwrap_or_else(|| format!("{:?}", key));
+        self.record("key", serde_json::json!({ "key": name }));
+    }
+
+    /// Records filtering the completions with the query, which took the
+    /// given time on the UI thread; large collections are scored in the
+    /// background afterwards.
+    pub fn filter(&mut self, query: &str, duration: time::Duration) {
+        self.record(
+            "filter",
+            serde_json::json!({ "query": query, "duration_ms": millis(duration) }),
+        );
+    }
+
+    /// Records fetching completions from a completer, which has given
+    /// `fetched` completions so far.
+    pub fn fetch(&mut self, completer: &str, fetched: usize, duration: time::Duration) {
+        self.record(
+            "fetch",
+            serde_json::json!({
+                "completer": completer,
+                "fetched": fetched,
+                "duration_ms": millis(duration),
+            }),
+        );
+    }
+
+    /// Records drawing the chooser.
+    pub fn render(&mut self, duration: time::Duration) {
+        self.record(
+            "render",
+            serde_json::json!({ "duration_ms": millis(duration) }),
+        );
+    }
+
+    /// Records the scorings of the tabs which finished since the last
+    /// call.
+    pub fn observe_scoring(&mut self, metrics: &[model::CompleterMetrics]) {
+        self.scoring_times.resize(metrics.len(), None);
+        for (i, m) in metrics.iter().enumerate() {
+            if m.last_scoring_time.is_none() || m.last_scoring_time == self.scoring_times[i] {
+                continue;
+            }
+            self.scoring_times[i] = m.last_scoring_time;
+            self.record(
+                "score",
+                serde_json::json!({
+                    "completer": m.name,
+                    "query": m.query,
+                    "matched": m.matched,
+                    "duration_ms": m.last_scoring_time.map(millis),
+                }),
+            );
+        }
+    }
+
+    /// Returns the trace as a JSON document, with the final metrics of
+    /// the tabs.
+    pub fn to_json(&self, metrics: &[model::CompleterMetrics]) -> serde_json::Value {
+        let completers: Vec<serde_json::Value> = metrics
+            .iter()
+            .map(|m| {
+                serde_json::json!({
+                    "name": m.name,
+                    "query": m.query,
+                    "fetched": m.fetched,
+                    "matched": m.matched,
+                    "fetching_finished": m.fetching_finished,
+                    "fetch_ms": millis(m.fetch_time),
+                    "last_scoring_ms": m.last_scoring_time.map(millis),
+                })
+            })
+            .collect();
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "duration_ms": millis(self.start.elapsed()),
+            "events": self.events,
+            "completers": completers,
+        })
+    }
+
+    /// Writes the trace to the given file, readable only by the user, as
+    /// the keys and queries may be secrets.
+    pub fn save(
+        &self,
+        trace_path: &path::Path,
+        metrics: &[model::CompleterMetrics],
+    ) -> io::Result<()> {
+        if let Some(dir) = trace_path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            options.mode(0o600);
+        }
+        let file = options.open(trace_path)?;
+        serde_json::to_writer_pretty(file, &self.to_json(metrics)).map_err(io::Error::from)
+    }
+}
+
+impl Default for SessionTrace {
+    fn default() -> Self {
+        SessionTrace::new()
+    }
+}
+
+#[test]
+fn test_session_trace() {
+    let mut trace = SessionTrace::new();
+    trace.key(&keys::Key::Char('a'));
+    trace.filter("a", time::Duration::from_millis(2));
+    trace.fetch("fs", 10, time::Duration::from_micros(1500));
+    let mut metrics = vec![model::CompleterMetrics {
+        name: "fs".to_owned(),
+        query: "a".to_owned(),
+        fetched: 10,
+        matched: 4,
+        fetching_finished: true,
+        fetch_time: time::Duration::from_millis(3),
+        last_scoring_time: Some(time::Duration::from_millis(1)),
+    }];
+    trace.observe_scoring(&metrics);
+    trace.observe_scoring(&metrics);
+    metrics[0].last_scoring_time = None;
+    trace.observe_scoring(&metrics);
+    trace.render(time::Duration::from_millis(4));
+
+    let document = trace.to_json(&metrics);
+    let events = document["events"].as_array().unwrap();
+    assert_eq!(
+        events
+            .iter()
+            .map(|e| e["event"].as_str().unwrap())
+            .collect::<Vec<_>>(),
+        vec!["key", "filter", "fetch", "score", "render"]
+    );
+    assert_eq!(events[0]["key"], "a");
+    assert_eq!(events[1]["duration_ms"], 2.0);
+    assert_eq!(events[2]["duration_ms"], 1.5);
+    assert_eq!(events[3]["matched"], 4);
+    assert_eq!(document["completers"][0]["fetch_ms"], 3.0);
+    assert!(document["completers"][0]["last_scoring_ms"].is_null());
+}
@@ -0,0 +1,108 @@
+//! Compares version strings, such as tags or package versions, so that
+//! their numbers are compared as numbers: v1.10.0 comes after v1.9.0.
+
+use std::cmp;
+
+/// Splits a version into runs of digits and runs of other characters.
+fn chunks(version: &str) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut start = 0;
+    let mut previous_is_digit = None;
+    for (i, c) in version.char_indices() {
+        let is_digit = c.is_ascii_digit();
+        if previous_is_digit.is_some_and(|d| d != is_digit) {
+            chunks.push(&version[start..i]);
+            start = i;
+        }
+        previous_is_digit = Some(is_digit);
+    }
+    if start < version.len() {
+        chunks.push(&version[start..]);
+    }
+    chunks
+}
+
+/// Compares two runs of digits by their value, without overflowing on
+/// long ones, and by their number of leading zeros if they are equal.
+fn compare_numbers(a: &str, b: &str) -> cmp::Ordering {
+    let a_value = a.trim_start_matches('0');
+    let b_value = b.trim_start_matches('0');
+    a_value
+        .len()
+        .cmp(&b_value.len())
+        .then_with(|| a_value.cmp(b_value))
+        .then_with(|| b.len().cmp(&a.len()))
+}
+
+/// Indicates if the rest of a version marks a pre-release of the
+/// version before it, e.g. "-rc1" in "v2.0.0-rc1".
+fn is_pre_release(rest: &[&str]) -> bool {
+    rest.first()
+        .is_some_and(|c| c.starts_with('-') || c.starts_with('~'))
+}
+
+/// Compares two versions, comparing runs of digits by their value and
+/// other characters in lexicographic order. A pre-release comes before
+/// the release, e.g. v2.0.0-rc1 before v2.0.0.
+pub fn compare(a: &str, b: &str) -> cmp::Ordering {
+    let a_chunks = chunks(a);
+    let b_chunks = chunks(b);
+    for (a_chunk, b_chunk) in a_chunks.iter().zip(&b_chunks) {
+        let a_is_number = a_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let b_is_number = b_chunk.starts_with(|c: char| c.is_ascii_digit());
+        let ordering = if a_is_number && b_is_number {
+            compare_numbers(a_chunk, b_chunk)
+        } else {
+            a_chunk.cmp(b_chunk)
+        };
+        if ordering != cmp::Ordering::Equal {
+            return ordering;
+        }
+    }
+    let common = cmp::min(a_chunks.len(), b_chunks.len());
+    match (
+        is_pre_release(&a_chunks[common..]),
+        is_pre_release(&b_chunks[common..]),
+    ) {
+        (true, false) => cmp::Ordering::Less,
+        (false, true) => cmp::Ordering::Greater,
+        _ => a_chunks.len().cmp(&b_chunks.len()),
+    }
+}
+
+#[test]
+fn test_compare() {
+    let mut versions = vec![
+        "v1.10.0",
+        "v2.0.0",
+        "v1.9.0",
+        "v2.0.0-rc1",
+        "v1.9.0-beta.2",
+        "v1.9.0-beta.10",
+        "v1.9.1",
+        "v1.9",
+        "release-3",
+        "v10.0.0",
+    ];
+    versions.sort_by(|a, b| compare(a, b));
+    assert_eq!(
+        versions,
+        vec![
+            "release-3",
+            "v1.9",
+            "v1.9.0-beta.2",
+            "v1.9.0-beta.10",
+            "v1.9.0",
+            "v1.9.1",
+            "v1.10.0",
+            "v2.0.0-rc1",
+            "v2.0.0",
+            "v10.0.0",
+        ]
+    );
+    assert_eq!(compare("1.02", "1.2"), cmp::Ordering::Less);
+    assert_eq!(
+        compare("99999999999999999999999", "100000000000000000000000"),
+        cmp::Ordering::Less
+    );
+}